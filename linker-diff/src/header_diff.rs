@@ -23,6 +23,11 @@ use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+// The object crate doesn't yet define the dynamic tags for packed relative relocations.
+const DT_RELRSZ: u32 = 35;
+const DT_RELR: u32 = 36;
+const DT_RELRENT: u32 = 37;
+
 pub(crate) enum Converter {
     None,
     SectionAddress,
@@ -433,6 +438,12 @@ fn read_dynamic_fields(obj: &Object) -> Result<FieldValues> {
             DT_VERDEF => (Cow::Borrowed("DT_VERDEF"), Converter::None),
             DT_VERDEFNUM => (Cow::Borrowed("DT_VERDEFNUM"), Converter::None),
             DT_AUXILIARY => (Cow::Borrowed("DT_AUXILIARY"), Converter::None),
+            DT_RELR => (Cow::Borrowed("DT_RELR"), Converter::SectionAddress),
+            DT_RELRSZ => {
+                // Ignore sizes for now.
+                continue;
+            }
+            DT_RELRENT => (Cow::Borrowed("DT_RELRENT"), Converter::None),
             other => (
                 Cow::Owned(format!("Unknown (0x{other:x})")),
                 Converter::None,
@@ -112,6 +112,8 @@ impl Config {
                 // doesn't define such sections.
                 "section.shdr",
                 "section.phdr",
+                // Only wild records its command line.
+                "section.note.wild.cmdline",
                 // We don't yet support these sections.
                 "section.data.rel.ro",
                 "section.debug*",
@@ -62,6 +62,86 @@ pub fn rel_type_to_string(r_type: u32) -> Cow<'static, str> {
     }
 }
 
+pub fn segment_type_to_string(p_type: u32) -> Cow<'static, str> {
+    if let Some(name) = const_name_by_value![
+        p_type,
+        PT_NULL,
+        PT_LOAD,
+        PT_DYNAMIC,
+        PT_INTERP,
+        PT_NOTE,
+        PT_SHLIB,
+        PT_PHDR,
+        PT_TLS,
+        PT_GNU_EH_FRAME,
+        PT_GNU_STACK,
+        PT_GNU_RELRO,
+        PT_GNU_PROPERTY
+    ] {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(format!("Unknown segment type 0x{p_type:x}"))
+    }
+}
+
+pub fn dynamic_tag_to_string(d_tag: u64) -> Cow<'static, str> {
+    let Ok(d_tag) = u32::try_from(d_tag) else {
+        return Cow::Owned(format!("Unknown dynamic tag 0x{d_tag:x}"));
+    };
+    if let Some(name) = const_name_by_value![
+        d_tag,
+        DT_NULL,
+        DT_NEEDED,
+        DT_PLTRELSZ,
+        DT_PLTGOT,
+        DT_HASH,
+        DT_STRTAB,
+        DT_SYMTAB,
+        DT_RELA,
+        DT_RELASZ,
+        DT_RELAENT,
+        DT_STRSZ,
+        DT_SYMENT,
+        DT_INIT,
+        DT_FINI,
+        DT_SONAME,
+        DT_RPATH,
+        DT_SYMBOLIC,
+        DT_REL,
+        DT_RELSZ,
+        DT_RELENT,
+        DT_PLTREL,
+        DT_DEBUG,
+        DT_TEXTREL,
+        DT_JMPREL,
+        DT_BIND_NOW,
+        DT_INIT_ARRAY,
+        DT_FINI_ARRAY,
+        DT_INIT_ARRAYSZ,
+        DT_FINI_ARRAYSZ,
+        DT_RUNPATH,
+        DT_FLAGS,
+        DT_PREINIT_ARRAY,
+        DT_PREINIT_ARRAYSZ,
+        DT_SYMTAB_SHNDX,
+        DT_GNU_HASH,
+        DT_VERSYM,
+        DT_RELACOUNT,
+        DT_RELCOUNT,
+        DT_FLAGS_1,
+        DT_VERDEF,
+        DT_VERDEFNUM,
+        DT_VERNEED,
+        DT_VERNEEDNUM,
+        DT_AUXILIARY,
+        DT_FILTER
+    ] {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(format!("Unknown dynamic tag 0x{d_tag:x}"))
+    }
+}
+
 /// Section flag bit values.
 pub mod shf {
     use super::SectionFlags;
@@ -79,6 +159,11 @@ pub mod shf {
     pub const TLS: SectionFlags = SectionFlags::from_u32(object::elf::SHF_TLS);
     pub const COMPRESSED: SectionFlags = SectionFlags::from_u32(object::elf::SHF_COMPRESSED);
     pub const GNU_RETAIN: SectionFlags = SectionFlags::from_u32(object::elf::SHF_GNU_RETAIN);
+    pub const EXCLUDE: SectionFlags = SectionFlags::from_u32(object::elf::SHF_EXCLUDE);
+
+    /// x86-64 only. The section may be larger than 2 GiB, so must be placed where it doesn't get in
+    /// the way of code that refers to other sections with 32 bit offsets.
+    pub const X86_64_LARGE: SectionFlags = SectionFlags::from_u32(0x1000_0000);
 }
 
 pub mod sht {
@@ -89,6 +174,7 @@ pub mod sht {
     pub const SYMTAB: SectionType = SectionType(object::elf::SHT_SYMTAB);
     pub const STRTAB: SectionType = SectionType(object::elf::SHT_STRTAB);
     pub const RELA: SectionType = SectionType(object::elf::SHT_RELA);
+    pub const RELR: SectionType = SectionType(19);
     pub const HASH: SectionType = SectionType(object::elf::SHT_HASH);
     pub const DYNAMIC: SectionType = SectionType(object::elf::SHT_DYNAMIC);
     pub const NOTE: SectionType = SectionType(object::elf::SHT_NOTE);
@@ -118,6 +204,8 @@ pub mod sht {
     pub const HIPROC: SectionType = SectionType(object::elf::SHT_HIPROC);
     pub const LOUSER: SectionType = SectionType(object::elf::SHT_LOUSER);
     pub const HIUSER: SectionType = SectionType(object::elf::SHT_HIUSER);
+    pub const LLVM_ADDRSIG: SectionType = SectionType(0x6fff4c03);
+    pub const LLVM_CALL_GRAPH_PROFILE: SectionType = SectionType(0x6fff4c09);
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
@@ -202,6 +290,9 @@ impl std::fmt::Display for SectionFlags {
         if self.contains(shf::COMPRESSED) {
             f.write_str("C")?;
         }
+        if self.contains(shf::X86_64_LARGE) {
+            f.write_str("l")?;
+        }
         Ok(())
     }
 }
@@ -1,4 +1,13 @@
-fn main() -> wild_lib::error::Result {
-    let linker = wild_lib::Linker::from_env()?;
-    linker.run()
+fn main() {
+    let linker = match wild_lib::Linker::from_env() {
+        Ok(linker) => linker,
+        Err(error) => {
+            wild_lib::error::report(&error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = linker.run() {
+        linker.report_error(&error);
+        std::process::exit(1);
+    }
 }
@@ -9,6 +9,9 @@
 //! argument. If no ExpectComment directives are given then .comment isn't checked. The argument may
 //! end with '*' which matches anything.
 //!
+//! ExpectError: Checks that linking fails with an error that contains the supplied argument. The
+//! resulting binary, if any, isn't diffed or run.
+//!
 //! TODO: Document the rest of the directives.
 
 use anyhow::anyhow;
@@ -171,6 +174,7 @@ struct Config {
     compiler: String,
     should_diff: bool,
     should_run: bool,
+    expected_error: Option<String>,
 }
 impl Config {
     fn is_linker_enabled(&self, linker: &Linker) -> bool {
@@ -273,6 +277,7 @@ impl Default for Config {
             section_equiv: Default::default(),
             is_abstract: false,
             deps: Default::default(),
+            expected_error: None,
             compiler: "gcc".to_owned(),
             should_diff: true,
             should_run: true,
@@ -393,6 +398,7 @@ fn parse_configs(src_filename: &Path) -> Result<Vec<Config>> {
                     input_type: InputType::SharedObject,
                 }),
                 "Compiler" => config.compiler = arg.trim().to_owned(),
+                "ExpectError" => config.expected_error = Some(arg.trim().to_owned()),
                 other => bail!("{}: Unknown directive '{other}'", src_filename.display()),
             }
         }
@@ -766,7 +772,9 @@ impl Linker {
                 .extend(config.wild_extra_linker_args.args.iter().cloned());
         }
         let mut command = LinkCommand::new(self, inputs, &output_path, &linker_args, config);
-        if !command.can_skip {
+        if let Some(expected_error) = &config.expected_error {
+            command.run_expecting_error(expected_error)?;
+        } else if !command.can_skip {
             command.run()?;
             write_cmd_file(&output_path, &command.to_string())?;
         }
@@ -914,6 +922,27 @@ impl LinkCommand {
         }
         Ok(())
     }
+
+    fn run_expecting_error(&mut self, expected_error: &str) -> Result {
+        let output = self
+            .command
+            .output()
+            .with_context(|| format!("Failed to run command: {:?}", self.command))?;
+        if output.status.success() {
+            bail!(
+                "Linker succeeded, but we expected it to fail with `{expected_error}`. \
+                 Relink with:\n{self}"
+            );
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.contains(expected_error) {
+            bail!(
+                "Expected linker to fail with `{expected_error}`, but got:\n{stderr}\n\
+                 Relink with:\n{self}"
+            );
+        }
+        Ok(())
+    }
 }
 
 fn get_script(inputs: &[LinkerInput]) -> Option<(PathBuf, &[LinkerInput])> {
@@ -1290,6 +1319,7 @@ fn integration_test(
         "tls.c",
         "old_init.c",
         "custom_section.c",
+        "start_stop_export.c",
         "stack_alignment.s",
         "got_ref_to_local.c",
         "local_symbol_refs.s",
@@ -1301,6 +1331,18 @@ fn integration_test(
         "trivial_asm.s",
         "non-alloc.s",
         "libc-integration.c",
+        "static-pie.c",
+        "lazy-binding.c",
+        "icf.c",
+        "copy-relocations.c",
+        "ifunc-dynamic.c",
+        "comdat.c",
+        "magic.c",
+        "init-priority.c",
+        "link_order.c",
+        "lto.c",
+        "eh_frame_cies.c",
+        "defsym_wrap_export.c",
         "rust-integration.rs",
         "rust-integration-dynamic.rs",
         "cpp-integration.cc",
@@ -1376,6 +1418,9 @@ fn integration_test(
                 result
             })
             .collect::<Result<Vec<_>>>()?;
+        if config.expected_error.is_some() {
+            continue;
+        }
 
         let start = Instant::now();
         diff_shared_objects(&config, &programs)?;
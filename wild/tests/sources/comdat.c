@@ -0,0 +1,40 @@
+// Checks that when two objects define the same COMDAT group, we keep the first and that references
+// to members of the other group from outside of it are redirected to the copy that we kept.
+
+//#AbstractConfig:default
+//#Object:exit.c
+//#Object:comdat1.s
+
+// GNU ld only redirects references from non-alloc sections such as debug info. It reports an error
+// for references from code, so we only link with wild.
+//#Config:redirect:default
+//#Object:comdat2.s
+//#SkipLinker:ld
+//#DiffEnabled:false
+
+// If the discarded member doesn't have a counterpart of the same size, references to it are an
+// error.
+//#Config:size-mismatch:default
+//#Object:comdat3.s
+//#SkipLinker:ld
+//#ExpectError:which has no section with the same name and size
+
+#include "exit.h"
+
+int get_value(void);
+int call_get_value1(void);
+int call_get_value2(void);
+
+void _start(void) {
+    if (get_value() != 10) {
+        exit_syscall(101);
+    }
+    if (call_get_value1() != 10) {
+        exit_syscall(102);
+    }
+    // comdat2.s calls its own copy, which returns 20, but that copy was discarded.
+    if (call_get_value2() != 10) {
+        exit_syscall(103);
+    }
+    exit_syscall(42);
+}
@@ -0,0 +1,17 @@
+.section .text.get_value,"axG",@progbits,get_value,comdat
+.globl get_value
+.type get_value, @function
+get_value:
+// A temporary label, so references to it from outside of the group use the section symbol.
+.Lget_value_impl:
+    mov $20, %eax
+    ret
+
+.section .text,"ax",@progbits
+.globl call_get_value2
+.type call_get_value2, @function
+call_get_value2:
+    call .Lget_value_impl
+    ret
+
+.section .note.GNU-stack,"",@progbits
@@ -0,0 +1,14 @@
+int copy_var = 10;
+// Like `environ` and `__environ` in glibc, the alias is weak. GNU ld only shares copies between a
+// weak symbol and the strong symbol at the same address.
+extern int copy_var_alias __attribute__((weak, alias("copy_var")));
+
+__attribute__((visibility("protected"))) int protected_var = 30;
+
+int *copy_var_address(void) {
+    return &copy_var;
+}
+
+int *copy_var_alias_address(void) {
+    return &copy_var_alias;
+}
@@ -0,0 +1,57 @@
+// Checks that when a non-PIC executable references variables defined by a shared object, copy
+// relocations are used and that aliases of a variable share a single copy.
+
+//#AbstractConfig:default
+//#CompArgs:-fno-pic -no-pie
+//#CompSoArgs:-fPIC
+//#LinkArgs:--cc=gcc -dynamic -no-pie -Wl,-z,now
+//#Shared:copy-relocations-1.c
+//#DiffIgnore:.dynamic.DT_NEEDED
+//#DiffIgnore:section.relro_padding
+//#DiffIgnore:section.rodata
+//#DiffIgnore:section.data
+
+//#Config:aliases:default
+
+// A shared object binds its own references to a protected variable directly, so it'd keep using
+// the original rather than our copy.
+//#Config:protected:default
+//#CompArgs:-fno-pic -no-pie -DPROTECTED
+//#SkipLinker:ld
+//#ExpectError:has protected visibility
+
+extern int copy_var;
+extern int copy_var_alias;
+
+int *copy_var_address(void);
+int *copy_var_alias_address(void);
+
+#ifdef PROTECTED
+extern int protected_var;
+#else
+// Accessing the addresses through volatile variables stops the compiler from assuming that
+// different variables have different addresses.
+int *volatile copy_var_ptr = &copy_var;
+int *volatile copy_var_alias_ptr = &copy_var_alias;
+#endif
+
+int main() {
+#ifdef PROTECTED
+    return protected_var;
+#else
+    if (copy_var_ptr != copy_var_alias_ptr) {
+        return 101;
+    }
+    if (copy_var_address() != copy_var_ptr || copy_var_alias_address() != copy_var_ptr) {
+        return 102;
+    }
+    if (copy_var != 10) {
+        return 103;
+    }
+    copy_var_alias = 20;
+    if (copy_var != 20 || *copy_var_address() != 20) {
+        return 104;
+    }
+    return 42;
+#endif
+}
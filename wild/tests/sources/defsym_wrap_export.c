@@ -0,0 +1,69 @@
+// Checks that symbols from --defsym and --wrap resolve correctly and that whether a shared object
+// exports them follows the version script in the same way as for other symbols.
+
+//#AbstractConfig:default
+//#DiffIgnore:.got.plt
+//#DiffIgnore:.dynamic.DT_PLTGOT
+//#DiffIgnore:.dynamic.DT_JMPREL
+//#DiffIgnore:.dynamic.DT_NEEDED
+//#DiffIgnore:.dynamic.DT_PLTREL
+//#DiffIgnore:section.plt.entsize
+//#DiffIgnore:section.rela.plt.link
+//#DiffIgnore:section.relro_padding
+//#DiffIgnore:section.rodata.alignment
+
+//#Config:gcc-dynamic-pie:default
+//#CompArgs:-g -fpie
+//#CompSoArgs:-g -fPIC
+//#LinkArgs:--cc=gcc -dynamic -Wl,-z,now -ldl -Wl,--wrap=lib_fn
+//#LinkSoArgs:-z now -Wl,--wrap=exe_hook -Wl,--defsym=lib_alias=lib_value_fn -Wl,--defsym=lib_abs=0x2a
+//#Shared:defsym_wrap_export1.c
+
+//#Config:version-script:gcc-dynamic-pie
+//#CompArgs:-g -fpie -DLOCAL_SYMBOLS
+//#LinkSoArgs:-z now -Wl,--wrap=exe_hook -Wl,--defsym=lib_alias=lib_value_fn -Wl,--defsym=lib_abs=0x2a -Wl,--version-script=./tests/sources/defsym_wrap_export.map
+
+#define _GNU_SOURCE
+#include <dlfcn.h>
+
+int lib_fn(void);
+int __real_lib_fn(void);
+int lib_call_hook(void);
+int lib_call_alias(void);
+
+// The shared object wraps its references to this function.
+int exe_hook(void) { return 5; }
+
+// We wrap our references to `lib_fn`.
+int __wrap_lib_fn(void) { return __real_lib_fn() + 100; }
+
+int main() {
+    if (lib_fn() != 101) {
+        return 10;
+    }
+    if (lib_call_hook() != 15) {
+        return 11;
+    }
+    if (lib_call_alias() != 7) {
+        return 12;
+    }
+    int (*alias)(void) = dlsym(RTLD_DEFAULT, "lib_alias");
+    void *abs = dlsym(RTLD_DEFAULT, "lib_abs");
+    void *wrap = dlsym(RTLD_DEFAULT, "__wrap_exe_hook");
+#ifdef LOCAL_SYMBOLS
+    if (alias != 0 || abs != 0 || wrap != 0) {
+        return 13;
+    }
+#else
+    if (alias == 0 || alias() != 7) {
+        return 14;
+    }
+    if (abs != (void *)0x2a) {
+        return 15;
+    }
+    if (wrap == 0) {
+        return 16;
+    }
+#endif
+    return 42;
+}
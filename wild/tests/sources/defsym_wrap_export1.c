@@ -0,0 +1,13 @@
+int exe_hook(void);
+int __real_exe_hook(void);
+int lib_alias(void);
+
+int lib_fn(void) { return 1; }
+
+int lib_value_fn(void) { return 7; }
+
+int __wrap_exe_hook(void) { return __real_exe_hook() + 10; }
+
+int lib_call_hook(void) { return exe_hook(); }
+
+int lib_call_alias(void) { return lib_alias(); }
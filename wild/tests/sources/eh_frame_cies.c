@@ -0,0 +1,133 @@
+// Checks that identical CIEs from different objects are merged, that each FDE points at a CIE that
+// precedes it and that the unwinder can still find the FDEs of functions in each object using
+// .eh_frame_hdr.
+
+//#Object:eh_frame_cies1.c
+//#Object:eh_frame_cies2.c
+//#LinkArgs:--cc=gcc -Wl,--eh-frame-hdr -Wl,-z,now
+//#DiffIgnore:section.rodata
+
+#define _GNU_SOURCE
+#include <link.h>
+#include <stdint.h>
+#include <string.h>
+
+#define DW_EH_PE_pcrel_sdata4 0x1b
+#define MAX_CIES 64
+
+struct dwarf_eh_bases {
+    void *tbase;
+    void *dbase;
+    void *func;
+};
+
+const void *_Unwind_Find_FDE(void *pc, struct dwarf_eh_bases *bases);
+
+int fn1(int x);
+int fn2(int x);
+
+static const unsigned char *eh_frame_hdr;
+static const unsigned char *cies[MAX_CIES];
+static int num_cies;
+
+static int find_eh_frame_hdr(struct dl_phdr_info *info, size_t size, void *data) {
+    (void)size;
+    (void)data;
+    for (int i = 0; i < info->dlpi_phnum; i++) {
+        if (info->dlpi_phdr[i].p_type == PT_GNU_EH_FRAME) {
+            eh_frame_hdr = (const unsigned char *)(info->dlpi_addr + info->dlpi_phdr[i].p_vaddr);
+        }
+    }
+    // The first object is our executable.
+    return 1;
+}
+
+static int is_cie(const unsigned char *entry) {
+    for (int i = 0; i < num_cies; i++) {
+        if (cies[i] == entry) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+// Returns the CIE that the FDE at `fde` points to.
+static const unsigned char *fde_cie(const unsigned char *fde) {
+    uint32_t cie_ptr;
+    memcpy(&cie_ptr, fde + 4, sizeof(cie_ptr));
+    return fde + 4 - cie_ptr;
+}
+
+// Walks .eh_frame, recording each CIE. Returns non-zero if anything is wrong.
+static int check_eh_frame(const unsigned char *entry) {
+    while (1) {
+        uint32_t length;
+        uint32_t id;
+        memcpy(&length, entry, sizeof(length));
+        if (length == 0) {
+            return 0;
+        }
+        if (length == 0xffffffff) {
+            // We don't expect 64 bit entries.
+            return 1;
+        }
+        memcpy(&id, entry + 4, sizeof(id));
+        if (id == 0) {
+            for (int i = 0; i < num_cies; i++) {
+                uint32_t other_length;
+                memcpy(&other_length, cies[i], sizeof(other_length));
+                if (other_length == length && memcmp(cies[i], entry, length + 4) == 0) {
+                    return 2;
+                }
+            }
+            if (num_cies == MAX_CIES) {
+                return 3;
+            }
+            cies[num_cies++] = entry;
+        } else if (!is_cie(fde_cie(entry))) {
+            // An FDE's CIE must come before it.
+            return 4;
+        }
+        entry += length + 4;
+    }
+}
+
+// Looks up the FDE for `fn` using .eh_frame_hdr. Returns its CIE, or null if there's a problem.
+static const unsigned char *lookup_cie(void *fn) {
+    struct dwarf_eh_bases bases;
+    const unsigned char *fde = _Unwind_Find_FDE((char *)fn + 1, &bases);
+    if (fde == NULL || bases.func != fn) {
+        return NULL;
+    }
+    return fde_cie(fde);
+}
+
+int main(void) {
+    dl_iterate_phdr(find_eh_frame_hdr, NULL);
+    if (eh_frame_hdr == NULL) {
+        return 10;
+    }
+    if (eh_frame_hdr[0] != 1 || eh_frame_hdr[1] != DW_EH_PE_pcrel_sdata4) {
+        return 11;
+    }
+    int32_t eh_frame_offset;
+    memcpy(&eh_frame_offset, eh_frame_hdr + 4, sizeof(eh_frame_offset));
+    int result = check_eh_frame(eh_frame_hdr + 4 + eh_frame_offset);
+    if (result != 0) {
+        return 20 + result;
+    }
+
+    // Our functions were all compiled the same way, so should all share a CIE.
+    const unsigned char *main_cie = lookup_cie((void *)main);
+    if (main_cie == NULL || !is_cie(main_cie)) {
+        return 30;
+    }
+    if (lookup_cie((void *)fn1) != main_cie) {
+        return 31;
+    }
+    if (lookup_cie((void *)fn2) != main_cie) {
+        return 32;
+    }
+
+    return fn1(20) + fn2(20);
+}
@@ -0,0 +1,3 @@
+int fn1(int x) {
+    return x + 1;
+}
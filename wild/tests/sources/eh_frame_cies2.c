@@ -0,0 +1,3 @@
+int fn2(int x) {
+    return x + 1;
+}
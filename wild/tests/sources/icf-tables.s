@@ -0,0 +1,15 @@
+// Two read-only tables with identical contents. In position-independent output, each needs a
+// dynamic relocation, so neither may be folded into the other.
+.section .rodata.icf_table_a,"a",@progbits
+.globl icf_table_a
+.p2align 3
+icf_table_a:
+    .quad times3_plus2
+
+.section .rodata.icf_table_b,"a",@progbits
+.globl icf_table_b
+.p2align 3
+icf_table_b:
+    .quad times3_plus2
+
+.section .note.GNU-stack,"",@progbits
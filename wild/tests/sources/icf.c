@@ -0,0 +1,95 @@
+// Checks that identical code folding merges functions with the same code, including ones that are
+// only the same because they call functions that get folded. GNU ld doesn't support `--icf`, so we
+// only link with wild.
+
+//#AbstractConfig:default
+//#SkipLinker:ld
+//#DiffEnabled:false
+
+//#Config:all:default
+//#CompArgs:-ffunction-sections -O0 -DEXPECT_FOLDED
+//#LinkArgs:--cc=gcc -Wl,--icf=all -Wl,--print-icf-sections
+
+// Absolute references in read-only sections need dynamic relocations in a PIE, which we allocate
+// before folding, so sections with them aren't folded.
+//#Config:pie:default
+//#CompArgs:-ffunction-sections -O0 -fPIE -DEXPECT_FOLDED -DTABLES
+//#LinkArgs:--cc=gcc -pie -Wl,-z,notext -Wl,--icf=all
+//#Object:icf-tables.s
+
+// GCC doesn't emit `.llvm_addrsig`, so with `--icf=safe` every symbol is treated as
+// address-significant and nothing gets folded.
+//#Config:safe:default
+//#CompArgs:-ffunction-sections -O0
+//#LinkArgs:--cc=gcc -Wl,--icf=safe
+
+typedef int (*fn_ptr)(int);
+
+#ifdef TABLES
+extern const fn_ptr icf_table_a[];
+extern const fn_ptr icf_table_b[];
+#endif
+
+int times3_plus1_a(int x) {
+    return x * 3 + 1;
+}
+
+int times3_plus1_b(int x) {
+    return x * 3 + 1;
+}
+
+int times3_plus2(int x) {
+    return x * 3 + 2;
+}
+
+int call_a(int x) {
+    return times3_plus1_a(x);
+}
+
+int call_b(int x) {
+    return times3_plus1_b(x);
+}
+
+// Accessing the pointers through volatile variables stops the compiler from assuming that
+// different functions have different addresses.
+volatile fn_ptr a = times3_plus1_a;
+volatile fn_ptr b = times3_plus1_b;
+volatile fn_ptr c = times3_plus2;
+volatile fn_ptr call_a_ptr = call_a;
+volatile fn_ptr call_b_ptr = call_b;
+
+int main() {
+    if (a(2) != 7 || b(2) != 7 || c(2) != 8) {
+        return 101;
+    }
+    if (call_a_ptr(3) != 10 || call_b_ptr(3) != 10) {
+        return 102;
+    }
+    if (a == c) {
+        return 103;
+    }
+#ifdef EXPECT_FOLDED
+    if (a != b) {
+        return 104;
+    }
+    if (call_a_ptr != call_b_ptr) {
+        return 105;
+    }
+#else
+    if (a == b) {
+        return 106;
+    }
+    if (call_a_ptr == call_b_ptr) {
+        return 107;
+    }
+#endif
+#ifdef TABLES
+    if (icf_table_a[0](2) != 8 || icf_table_b[0](2) != 8) {
+        return 108;
+    }
+    if (icf_table_a == icf_table_b) {
+        return 109;
+    }
+#endif
+    return 42;
+}
@@ -0,0 +1,19 @@
+typedef int (*fn_ptr)(void);
+
+static int return10(void) {
+    return 10;
+}
+
+static void *resolve_compute_value10(void) {
+    return return10;
+}
+
+int compute_value10(void) __attribute__((ifunc("resolve_compute_value10")));
+
+fn_ptr so_value10_ptr(void) {
+    return compute_value10;
+}
+
+int so_call_value10(void) {
+    return compute_value10();
+}
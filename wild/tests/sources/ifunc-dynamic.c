@@ -0,0 +1,51 @@
+// Checks ifuncs in dynamically linked executables, both ones that we define and ones defined by a
+// shared object. The dynamic loader calls the resolvers via IRELATIVE and symbolic relocations.
+
+//#AbstractConfig:default
+//#CompSoArgs:-fPIC
+//#Shared:ifunc-dynamic-1.c
+//#DiffIgnore:.dynamic.DT_NEEDED
+//#DiffIgnore:section.rodata
+
+//#Config:pie:default
+//#CompArgs:-fpie
+//#LinkArgs:--cc=gcc -dynamic -Wl,-z,now
+
+//#Config:no-pie:default
+//#CompArgs:-fno-pie -no-pie
+//#LinkArgs:--cc=gcc -dynamic -no-pie -Wl,-z,now
+
+typedef int (*fn_ptr)(void);
+
+// Defined as ifuncs in the shared object.
+extern int compute_value10(void);
+fn_ptr so_value10_ptr(void);
+int so_call_value10(void);
+
+static int return32(void) {
+    return 32;
+}
+
+static void *resolve_compute_value32(void) {
+    return return32;
+}
+
+int compute_value32(void) __attribute__((ifunc("resolve_compute_value32")));
+
+volatile fn_ptr value10_ptr = compute_value10;
+volatile fn_ptr value32_ptr = compute_value32;
+
+int main() {
+    if (compute_value10() != 10 || so_call_value10() != 10 || value10_ptr() != 10) {
+        return 101;
+    }
+    if (compute_value32() != 32 || value32_ptr() != 32) {
+        return 102;
+    }
+    // Whichever module takes the address of an ifunc, it must get the same address.
+    fn_ptr from_so = so_value10_ptr();
+    if (from_so != value10_ptr || from_so() != 10) {
+        return 103;
+    }
+    return 42;
+}
@@ -0,0 +1,21 @@
+// Init functions for init-priority.c. The sections have different alignments, so that they'd end
+// up in different parts of the output section if it weren't for their priorities.
+
+.section .init_array,"aw",@init_array
+.p2align 3
+.quad init_default
+
+// .ctors priorities are inverted, so this has priority 100.
+.section .ctors.65435,"aw",@progbits
+.p2align 0
+.quad init_100
+
+.section .init_array.00200,"aw",@init_array
+.p2align 2
+.quad init_200
+
+.section .init_array.00150,"aw",@init_array
+.p2align 3
+.quad init_150
+
+.section .note.GNU-stack,"",@progbits
@@ -0,0 +1,44 @@
+// Checks that init functions with priorities run ahead of those without, in order of priority, even
+// when the sections that hold them have lower alignment than those without priorities.
+
+//#Object:init-priority-arrays.s
+//#Object:init.c
+//#Object:exit.c
+
+#include "exit.h"
+#include "init.h"
+
+static int order[4];
+static int count = 0;
+
+static void record(int priority) {
+    if (count < 4) {
+        order[count] = priority;
+    }
+    count++;
+}
+
+void init_100(void) { record(100); }
+void init_150(void) { record(150); }
+void init_200(void) { record(200); }
+void init_default(void) { record(0); }
+
+void _start(void) {
+    call_init_functions();
+    if (count != 4) {
+        exit_syscall(count + 10);
+    }
+    if (order[0] != 100) {
+        exit_syscall(1);
+    }
+    if (order[1] != 150) {
+        exit_syscall(2);
+    }
+    if (order[2] != 200) {
+        exit_syscall(3);
+    }
+    if (order[3] != 0) {
+        exit_syscall(4);
+    }
+    exit_syscall(42);
+}
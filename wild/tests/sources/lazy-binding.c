@@ -0,0 +1,35 @@
+// Checks that calls to functions in shared objects work when they're bound lazily via .plt and
+// .got.plt. The first call to each function goes via the dynamic loader's resolver, which then
+// updates the .got.plt entry so that later calls go directly to the function.
+
+//#AbstractConfig:default
+//#DiffIgnore:.dynamic.DT_NEEDED
+//#DiffIgnore:section.relro_padding
+//#DiffIgnore:section.rodata.alignment
+//#DiffIgnore:section.data
+
+//#Config:pie:default
+//#CompArgs:-fpie
+//#LinkArgs:--cc=gcc -pie -Wl,-z,lazy
+
+//#Config:no-pie:default
+//#CompArgs:-fno-pie
+//#LinkArgs:--cc=gcc -no-pie -Wl,-z,lazy
+
+#include <stdlib.h>
+#include <string.h>
+
+int main() {
+    char *data = malloc(16);
+    for (int i = 0; i < 2; i++) {
+        strcpy(data, "lazy");
+        if (strlen(data) != 4) {
+            return 101;
+        }
+        if (strcmp(data, "lazy") != 0) {
+            return 102;
+        }
+    }
+    free(data);
+    return 42;
+}
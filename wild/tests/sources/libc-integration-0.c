@@ -43,6 +43,10 @@ int get_weak_var(void) {
     return weak_var;
 }
 
+// When we're a shared object, this needs a dynamic relocation against `weak_var`, since the
+// definition in the main program takes precedence over ours.
+int *weak_var_ptr = &weak_var;
+
 int get_weak_tvar(void) {
     return weak_tvar;
 }
@@ -65,6 +69,8 @@ static void *resolve_compute_value10(void) {
     return return10;
 }
 
+int (*compute_value10_ptr)(void) = compute_value10;
+
 int sometimes_weak_fn(void) {
     return 42;
 }
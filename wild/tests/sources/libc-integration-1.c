@@ -5,3 +5,5 @@
 int get_42(void) {
     return 42;
 }
+
+int hidden_ref_var = 55;
@@ -64,6 +64,15 @@
 //#Shared:libc-integration-0.c
 //#Shared:libc-integration-1.c
 
+// Relative relocations, including those for GOT entries, go in .relr.dyn, both in the executable
+// and in the shared objects.
+//#Config:gcc-dynamic-pie-relr:default
+//#CompArgs:-g -fpie -DDYNAMIC_DEP
+//#CompSoArgs:-g -fPIC -ftls-model=global-dynamic
+//#LinkArgs:--cc=gcc -dynamic -Wl,--strip-debug -Wl,--gc-sections -Wl,-z,now -Wl,-z,pack-relative-relocs
+//#Shared:libc-integration-0.c
+//#Shared:libc-integration-1.c
+
 //#Config:gcc-dynamic-pie-large:default
 //#CompArgs:-g -fpie -DDYNAMIC_DEP -mcmodel=large
 //#CompSoArgs:-g -fPIC -ftls-model=global-dynamic
@@ -105,6 +114,15 @@ int get_weak_tvar2(void);
 int compute_value10(void);
 int black_box(int v);
 int get_42(void);
+extern int *weak_var_ptr;
+extern int (*compute_value10_ptr)(void);
+
+#ifndef DYNAMIC_DEP
+// A hidden reference to a symbol that's defined with default visibility in another object. The
+// reference makes the symbol local to our output, so the pointer can use a relative relocation.
+extern int hidden_ref_var __attribute__ ((visibility("hidden")));
+int *hidden_ref_var_ptr = &hidden_ref_var;
+#endif
 
 typedef int(*get_int_fn_t)(void);
 
@@ -232,6 +250,17 @@ int main() {
     if (get_42() != 42) {
         return 121;
     }
+    if (*weak_var_ptr != 30) {
+        return 122;
+    }
+    if (compute_value10_ptr() != 10) {
+        return 123;
+    }
+#ifndef DYNAMIC_DEP
+    if (*hidden_ref_var_ptr != 55) {
+        return 124;
+    }
+#endif
 
     return 42;
 }
@@ -0,0 +1,53 @@
+// Checks that the entries that -fpatchable-function-entry records in __patchable_function_entries
+// are in the same order as the functions that they're for, even when those functions come from
+// different objects and aren't placed in input order. Entries for functions that --gc-sections
+// discards should be discarded with them.
+
+//#AbstractConfig:default
+//#Object:link_order1.c
+//#Object:exit.c
+//#CompArgs:-fpatchable-function-entry=1 -ffunction-sections
+//#LinkArgs:-z now --gc-sections -z keep-text-section-prefix
+//#DiffIgnore:section.text.hot
+//#DiffIgnore:section.__patchable_function_entries.link
+
+//#Config:reordered:default
+
+//#Config:gc:default
+//#Object:link_order2.c
+// GNU ld keeps all sections that a __start_ symbol refers to, even SHF_LINK_ORDER sections for
+// functions that it discarded, so it'd keep the entry for link_order2.c.
+//#SkipLinker:ld
+//#DiffEnabled:false
+
+#include "exit.h"
+
+int hot_fn(void);
+
+extern void *__start___patchable_function_entries[];
+extern void *__stop___patchable_function_entries[];
+
+// GCC links all the entries in an object to the section of the first function, so this needs to be
+// one that we keep.
+int main_fn(void) {
+    return 30;
+}
+
+void _start(void) {
+    void **entries = __start___patchable_function_entries;
+    long count = __stop___patchable_function_entries - entries;
+    // main_fn, _start, hot_fn and exit_syscall.
+    if (count != 4) {
+        exit_syscall(count + 10);
+    }
+    for (long i = 1; i < count; i++) {
+        if (entries[i - 1] >= entries[i]) {
+            exit_syscall(i);
+        }
+    }
+    // hot_fn goes in .text.hot, which comes before .text, so its entry comes first.
+    if (entries[0] != (void *)hot_fn) {
+        exit_syscall(6);
+    }
+    exit_syscall(main_fn() + hot_fn());
+}
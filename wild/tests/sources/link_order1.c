@@ -0,0 +1,3 @@
+__attribute__((section(".text.hot.hot_fn"))) int hot_fn(void) {
+    return 12;
+}
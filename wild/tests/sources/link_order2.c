@@ -0,0 +1 @@
+void unused_fn2(void) {}
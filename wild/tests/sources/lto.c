@@ -0,0 +1,18 @@
+// Checks that we can link -flto objects through GCC's linker plugin. Our entry point has a custom
+// name, so it only survives LTO if we tell the plugin that it's referenced from outside the IR.
+
+//#Object:lto1.c
+//#Object:exit.c
+//#CompArgs:-flto -O2
+//#LinkArgs:--cc=gcc -nostdlib -static -Wl,-e,lto_entry -Wl,-z,now -Wl,--gc-sections
+// Our inputs include the objects that LTO produced, which GCC deletes once the link is done, so
+// there's nothing to diff against.
+//#DiffEnabled:false
+
+#include "exit.h"
+
+int lto_value(void);
+
+void lto_entry(void) {
+    exit_syscall(lto_value());
+}
@@ -0,0 +1,9 @@
+static int value = 40;
+
+int lto_value(void) {
+    return value + 2;
+}
+
+int lto_unused(void) {
+    return value;
+}
@@ -0,0 +1,40 @@
+// Checks that output without page-aligned segments runs. With `-n` and `-N`, segments share pages,
+// so everything needs to go in a single LOAD segment that has all the permissions that any part
+// of it needs.
+
+//#AbstractConfig:default
+//#Object:exit.c
+
+//#Config:nmagic:default
+//#LinkArgs:-n -z now
+
+// GNU ld also marks `.text` as writable with `-N`. We only make the segment writable.
+//#Config:omagic:default
+//#LinkArgs:-N -z now
+//#DiffIgnore:section.text.flags
+
+// `--no-rosegment` comes from lld. GNU ld doesn't have it.
+//#Config:no-rosegment:default
+//#LinkArgs:--no-rosegment -z now
+//#SkipLinker:ld
+//#DiffEnabled:false
+
+#include "exit.h"
+
+static const char message[] = "magic";
+static int counter = 40;
+static int zeroed[16];
+
+void _start(void) {
+    if (message[0] != 'm' || message[4] != 'c') {
+        exit_syscall(101);
+    }
+    for (int i = 0; i < 16; i++) {
+        if (zeroed[i] != 0) {
+            exit_syscall(102);
+        }
+        zeroed[i] = i;
+    }
+    counter += zeroed[2];
+    exit_syscall(counter);
+}
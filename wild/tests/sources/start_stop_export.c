@@ -0,0 +1,55 @@
+// Checks that start/stop symbols for custom sections in a shared object get exported, unless the
+// version script makes them local.
+
+//#AbstractConfig:default
+//#DiffIgnore:.got.plt
+//#DiffIgnore:.dynamic.DT_PLTGOT
+//#DiffIgnore:.dynamic.DT_JMPREL
+//#DiffIgnore:.dynamic.DT_NEEDED
+//#DiffIgnore:.dynamic.DT_PLTREL
+//#DiffIgnore:section.plt.entsize
+//#DiffIgnore:section.rela.plt.link
+//#DiffIgnore:section.relro_padding
+//#DiffIgnore:section.rodata.alignment
+
+//#Config:gcc-dynamic-pie:default
+//#CompArgs:-g -fpie
+//#CompSoArgs:-g -fPIC
+//#LinkArgs:--cc=gcc -dynamic -Wl,-z,now -ldl
+//#Shared:start_stop_export1.c
+
+//#Config:version-script:gcc-dynamic-pie
+//#CompArgs:-g -fpie -DLOCAL_START_STOP
+//#LinkSoArgs:-z now -Wl,--version-script=./tests/sources/start_stop_export.map
+
+#define _GNU_SOURCE
+#include <dlfcn.h>
+
+int *lib_section_start(void);
+int *lib_section_stop(void);
+
+int main() {
+    int *start = dlsym(RTLD_DEFAULT, "__start_lib_section");
+    int *stop = dlsym(RTLD_DEFAULT, "__stop_lib_section");
+#ifdef LOCAL_START_STOP
+    if (start != 0 || stop != 0) {
+        return 14;
+    }
+    start = lib_section_start();
+    stop = lib_section_stop();
+#else
+    if (start == 0 || stop == 0) {
+        return 10;
+    }
+#endif
+    if (start != lib_section_start()) {
+        return 11;
+    }
+    if (stop != lib_section_stop()) {
+        return 12;
+    }
+    if (stop - start != 2 || start[0] + start[1] != 42) {
+        return 13;
+    }
+    return 42;
+}
@@ -0,0 +1,13 @@
+static int a __attribute__ ((used, retain, section ("lib_section"))) = 40;
+static int b __attribute__ ((used, retain, section ("lib_section"))) = 2;
+
+extern int __start_lib_section[];
+extern int __stop_lib_section[];
+
+int *lib_section_start(void) {
+    return __start_lib_section;
+}
+
+int *lib_section_stop(void) {
+    return __stop_lib_section;
+}
@@ -0,0 +1,63 @@
+// Checks that a static-PIE can relocate itself. There's no dynamic loader, so libc applies the
+// relative and IRELATIVE relocations from `.rela.dyn` and `.rela.plt` at startup, then calls
+// `apply_irel`, which uses absolute addresses and so needs `__rela_iplt_start` and
+// `__rela_iplt_end` to describe an empty range.
+
+//#AbstractConfig:default
+//#CompArgs:-fPIE
+//#DiffIgnore:section.rela.plt.link
+//#DiffIgnore:section.relro_padding
+
+//#Config:gcc:default
+//#LinkArgs:--cc=gcc -static-pie -Wl,--strip-debug -Wl,--gc-sections -Wl,-z,now
+
+#include <stdlib.h>
+#include <string.h>
+
+extern const char __rela_iplt_start[] __attribute__ ((weak));
+extern const char __rela_iplt_end[] __attribute__ ((weak));
+
+static int return10(void) {
+    return 10;
+}
+
+static void *resolve_compute_value10(void) {
+    return return10;
+}
+
+int compute_value10(void) __attribute__ ((ifunc ("resolve_compute_value10")));
+
+int value5 = 5;
+int *value5_ptr = &value5;
+int (*compute_value10_ptr)(void) = compute_value10;
+const char *message = "static-pie";
+
+__thread int tvar = 70;
+
+int main() {
+    if (__rela_iplt_end != __rela_iplt_start) {
+        return 101;
+    }
+    if (*value5_ptr != 5) {
+        return 102;
+    }
+    if (compute_value10() != 10) {
+        return 103;
+    }
+    if (compute_value10_ptr() != 10) {
+        return 104;
+    }
+    if (strcmp(message, "static-pie") != 0) {
+        return 105;
+    }
+    if (tvar != 70) {
+        return 106;
+    }
+    char *data = malloc(16);
+    strcpy(data, message);
+    if (strlen(data) != 10) {
+        return 107;
+    }
+    free(data);
+    return 42;
+}
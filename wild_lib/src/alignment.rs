@@ -32,7 +32,7 @@ pub(crate) const GNU_HASH: Alignment = Alignment { exponent: 3 };
 /// The minimum alignment of a phdr entry.
 pub(crate) const PROGRAM_HEADER_ENTRY: Alignment = Alignment { exponent: 3 };
 
-/// The minimum alignment of loadable program segments.
+/// The default alignment of loadable program segments.
 pub(crate) const PAGE: Alignment = Alignment { exponent: 12 };
 
 /// The minimum alignment of a PLT entry.
@@ -45,6 +45,8 @@ pub(crate) const USIZE: Alignment = Alignment { exponent: 3 };
 
 pub(crate) const EH_FRAME_HDR: Alignment = Alignment { exponent: 2 };
 
+pub(crate) const NOTE: Alignment = Alignment { exponent: 2 };
+
 impl Alignment {
     pub(crate) fn new(raw: u64) -> Result<Self> {
         let exponent = raw.trailing_zeros();
@@ -59,6 +61,17 @@ impl Alignment {
         })
     }
 
+    /// Creates an alignment for a page size. Pages can be larger than the largest section alignment
+    /// that we support, so this doesn't apply the same limit as `new`.
+    pub(crate) fn page_size(raw: u64) -> Result<Self> {
+        if !raw.is_power_of_two() {
+            bail!("Page size 0x{raw:x} is not a power of two");
+        }
+        Ok(Alignment {
+            exponent: raw.trailing_zeros() as u8,
+        })
+    }
+
     pub(crate) fn value(self) -> u64 {
         1 << self.exponent
     }
@@ -73,16 +86,6 @@ impl Alignment {
         }
     }
 
-    pub(crate) fn align_up_usize(&self, value: usize) -> usize {
-        let base = value & (usize::MAX << self.exponent);
-        if value == base {
-            // Already aligned
-            value
-        } else {
-            base + (1 << self.exponent)
-        }
-    }
-
     /// Returns `mem_offset`, possibly adjusted up so that it is >= `align_up(mem_offset)` and has
     /// the same modulo as `file_offset`
     pub(crate) fn align_modulo(&self, file_offset: u64, mut mem_offset: u64) -> u64 {
@@ -11,8 +11,6 @@ use std::ops::Range;
 
 pub(crate) enum ArchiveEntry<'data> {
     Regular(ArchiveContent<'data>),
-    // TODO: Consider if we want to keep this.
-    #[allow(dead_code)]
     Symbols(SymbolTable<'data>),
     Filenames(ExtendedFilenames<'data>),
 }
@@ -45,10 +43,14 @@ pub(crate) struct ArchiveContent<'data> {
     pub(crate) data_offset: usize,
 }
 
-// TODO: Consider if we want to keep this.
-#[allow(dead_code)]
+/// The archive's index of which member defines each symbol. We don't use this when linking, but it
+/// lets us answer queries about what's defined without looking at the members.
 pub(crate) struct SymbolTable<'data> {
     pub(crate) data: &'data [u8],
+
+    /// Whether the count and offsets are 8 bytes rather than 4. Archives use a `/SYM64/` table
+    /// when they're too large for 32 bit offsets.
+    pub(crate) is_64: bool,
 }
 
 pub(crate) struct ArchiveIterator<'data> {
@@ -111,7 +113,14 @@ impl<'data> ArchiveIterator<'data> {
         let ident = ident.trim();
         let entry_data = &self.data[..size];
         let entry = match ident {
-            "/" => ArchiveEntry::Symbols(SymbolTable { data: entry_data }),
+            "/" => ArchiveEntry::Symbols(SymbolTable {
+                data: entry_data,
+                is_64: false,
+            }),
+            "/SYM64/" => ArchiveEntry::Symbols(SymbolTable {
+                data: entry_data,
+                is_64: true,
+            }),
             "//" => ArchiveEntry::Filenames(ExtendedFilenames { data: entry_data }),
             _ => ArchiveEntry::Regular(ArchiveContent {
                 ident,
@@ -168,6 +177,49 @@ impl<'data> ArchiveContent<'data> {
     pub(crate) fn data_range(&self) -> Range<usize> {
         self.data_offset..self.data_offset + self.entry_data.len()
     }
+
+    /// Returns the offset in the archive of this entry's header. This is how the symbol table
+    /// refers to entries.
+    pub(crate) fn header_offset(&self) -> usize {
+        self.data_offset - HEADER_SIZE
+    }
+}
+
+impl<'data> SymbolTable<'data> {
+    /// Returns each symbol name in the table together with the header offset of the entry that
+    /// defines it. The table is a big-endian count, then that many big-endian entry offsets, then
+    /// that many null-terminated names. The count and offsets are 4 bytes each, or 8 bytes in a
+    /// `/SYM64/` table.
+    pub(crate) fn entries(&self) -> Result<Vec<(&'data [u8], usize)>> {
+        let word_size = if self.is_64 { 8 } else { 4 };
+        let read_word = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .fold(0_u64, |value, &byte| (value << 8) | u64::from(byte))
+        };
+        let count = read_word(
+            self.data
+                .get(..word_size)
+                .context("Archive symbol table is missing its count")?,
+        );
+        let names_start = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_add(1))
+            .and_then(|words| words.checked_mul(word_size))
+            .filter(|&end| end <= self.data.len())
+            .context("Archive symbol table is truncated")?;
+        let mut names = &self.data[names_start..];
+        let mut entries = Vec::with_capacity(names_start / word_size - 1);
+        for offset in self.data[word_size..names_start].chunks_exact(word_size) {
+            let end =
+                memchr::memchr(0, names).context("Unterminated name in archive symbol table")?;
+            let offset = usize::try_from(read_word(offset))
+                .context("Archive symbol table offset is out of range")?;
+            entries.push((&names[..end], offset));
+            names = &names[end + 1..];
+        }
+        Ok(entries)
+    }
 }
 
 impl<'data> Identifier<'data> {
@@ -242,6 +294,7 @@ mod tests {
                 let ar_summary = ar_read_entries(path)?;
                 let data = std::fs::read(path)?;
                 let mut our_entries = Vec::new();
+                let mut our_symbols = Vec::new();
                 let mut filenames = None;
                 for entry in ArchiveIterator::from_archive_bytes(&data)? {
                     let entry = entry?;
@@ -249,7 +302,10 @@ mod tests {
                         ArchiveEntry::Regular(content) => {
                             our_entries.push(content);
                         }
-                        ArchiveEntry::Symbols(_symbol_table) => {}
+                        ArchiveEntry::Symbols(symbol_table) => {
+                            our_symbols
+                                .extend(symbol_table.entries()?.into_iter().map(|(name, _)| name));
+                        }
                         ArchiveEntry::Filenames(table) => filenames = Some(table),
                     }
                 }
@@ -275,6 +331,13 @@ mod tests {
                         bail!("Different data");
                     }
                 }
+                if ar_summary.symbols != our_symbols {
+                    bail!(
+                        "ar read {} symbols, but we read {}",
+                        ar_summary.symbols.len(),
+                        our_symbols.len()
+                    );
+                }
                 for (a, b) in ar_summary.identifiers.iter().zip(our_entries.iter()) {
                     let b = b.identifier(filenames).as_slice();
                     if a != b {
@@ -298,6 +361,29 @@ mod tests {
         assert_eq!(limit, 0);
     }
 
+    #[test]
+    fn test_symbol_table_64() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&2_u64.to_be_bytes());
+        data.extend_from_slice(&8_u64.to_be_bytes());
+        data.extend_from_slice(&0x1234_u64.to_be_bytes());
+        data.extend_from_slice(b"foo\0bar\0");
+        let table = SymbolTable {
+            data: &data,
+            is_64: true,
+        };
+        assert_eq!(
+            table.entries().unwrap(),
+            vec![(&b"foo"[..], 8), (&b"bar"[..], 0x1234)]
+        );
+
+        let truncated = SymbolTable {
+            data: &data[..20],
+            is_64: true,
+        };
+        assert!(truncated.entries().is_err());
+    }
+
     #[test]
     fn test_parse_decimal_int() {
         assert_eq!(parse_decimal_int(b"123   "), 123);
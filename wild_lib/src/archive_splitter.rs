@@ -1,12 +1,22 @@
 use crate::archive::ArchiveEntry;
 use crate::archive::ArchiveIterator;
 use crate::archive::EntryMeta;
+use crate::args::Args;
+use crate::args::DuplicateMemberPolicy;
 use crate::args::Modifiers;
+use crate::diagnostics::should_warn;
+use crate::diagnostics::WarningCategory;
 use crate::error::Result;
 use crate::file_kind::FileKind;
 use crate::input_data::InputData;
 use crate::input_data::InputRef;
 use crate::threading::prelude::*;
+use crate::warning;
+use anyhow::bail;
+use object::read::elf::FileHeader as _;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+use std::collections::HashMap;
 use std::fmt::Display;
 
 pub(crate) struct InputBytes<'data> {
@@ -66,7 +76,109 @@ pub fn split_archives<'data>(input_data: &'data InputData) -> Result<Vec<InputBy
             }
         })
         .collect::<Result<Vec<Vec<InputBytes>>>>()?;
-    Ok(split_output.into_iter().flatten().collect())
+    let mut inputs: Vec<InputBytes> = split_output.into_iter().flatten().collect();
+    if let Some(policy) = input_data.config.duplicate_archive_members {
+        apply_duplicate_member_policy(&mut inputs, policy, input_data.config)?;
+    }
+    Ok(inputs)
+}
+
+/// How we identify an archive member when looking for copies of the same object in different
+/// archives.
+#[derive(PartialEq, Eq, Hash)]
+enum MemberIdentity<'data> {
+    BuildId(&'data [u8]),
+    Content { hash: u64, len: usize },
+}
+
+/// Finds archive members that are copies of a member of an earlier archive and applies `policy` to
+/// them. Copies within a single archive are left alone.
+#[tracing::instrument(skip_all, name = "Find duplicate archive members")]
+fn apply_duplicate_member_policy(
+    inputs: &mut Vec<InputBytes>,
+    policy: DuplicateMemberPolicy,
+    args: &Args,
+) -> Result {
+    let identities = inputs
+        .par_iter()
+        .map(|input| {
+            (input.kind == FileKind::Archive).then(|| MemberIdentity::for_member(input.data))
+        })
+        .collect::<Vec<_>>();
+
+    let mut first_seen: HashMap<&MemberIdentity, usize> = HashMap::new();
+    let mut keep = Vec::with_capacity(inputs.len());
+    for (index, identity) in identities.iter().enumerate() {
+        let Some(identity) = identity else {
+            keep.push(true);
+            continue;
+        };
+        let first_index = *first_seen.entry(identity).or_insert(index);
+        let first = &inputs[first_index];
+        let is_copy =
+            first_index != index && !std::ptr::eq(first.input.file, inputs[index].input.file);
+        if is_copy {
+            match policy {
+                DuplicateMemberPolicy::Deduplicate => {}
+                DuplicateMemberPolicy::Warn => {
+                    if should_warn(
+                        args,
+                        WarningCategory::DuplicateArchiveMember,
+                        &inputs[index].input.file.filename,
+                    ) {
+                        warning!(
+                            args.diagnostics,
+                            "Ignoring `{}`, which is a duplicate of `{first}`",
+                            inputs[index]
+                        );
+                    }
+                }
+                DuplicateMemberPolicy::Error => {
+                    bail!(
+                        "Archive member `{}` is a duplicate of `{first}`",
+                        inputs[index]
+                    );
+                }
+            }
+        }
+        keep.push(!is_copy);
+    }
+
+    let mut keep = keep.into_iter();
+    inputs.retain(|_| keep.next().unwrap());
+    Ok(())
+}
+
+impl<'data> MemberIdentity<'data> {
+    fn for_member(data: &'data [u8]) -> Self {
+        if let Some(build_id) = build_id(data) {
+            return MemberIdentity::BuildId(build_id);
+        }
+        MemberIdentity::Content {
+            hash: crate::hash::hash_bytes(data),
+            len: data.len(),
+        }
+    }
+}
+
+/// Returns the contents of the GNU build-id note, if `data` is an ELF object that has one.
+fn build_id(data: &[u8]) -> Option<&[u8]> {
+    let e = LittleEndian;
+    let header = crate::elf::FileHeader::parse(data).ok()?;
+    let sections = header.sections(e, data).ok()?;
+    for section in sections.iter() {
+        let Ok(Some(mut notes)) = section.notes(e, data) else {
+            continue;
+        };
+        while let Ok(Some(note)) = notes.next() {
+            if note.name() == object::elf::ELF_NOTE_GNU
+                && note.n_type(e) == object::elf::NT_GNU_BUILD_ID
+            {
+                return Some(note.desc());
+            }
+        }
+    }
+    None
 }
 
 impl<'data> Display for InputBytes<'data> {
@@ -4,13 +4,18 @@
 //! order is important for some arguments and it's not clear how easy it would be to get that
 //! correct with something like clap.
 
+use crate::alignment::Alignment;
+use crate::diagnostics::WarningSuppression;
+use crate::error::Diagnostics;
 use crate::error::Result;
 use crate::input_data::FileId;
+use crate::query::QueryDefines;
 use crate::save_dir::SaveDir;
 use crate::warning;
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context as _;
+use std::collections::HashSet;
 use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
@@ -23,6 +28,10 @@ pub(crate) struct Args {
     pub(crate) output: Arc<Path>,
     pub(crate) dynamic_linker: Option<Box<Path>>,
     pub(crate) output_kind: OutputKind,
+
+    /// The target that we're linking for. Selected with `-m`, or if that isn't given, inferred from
+    /// the first input object.
+    pub(crate) emulation: &'static Emulation,
     pub(crate) num_threads: NonZeroUsize,
     pub(crate) strip_all: bool,
     pub(crate) strip_debug: bool,
@@ -38,7 +47,67 @@ pub(crate) struct Args {
     pub(crate) should_write_eh_frame_hdr: bool,
     pub(crate) write_trace: bool,
     pub(crate) rpaths: Vec<String>,
+
+    /// The value of our DT_RUNPATH or DT_RPATH entry. This is all our `-rpath` options, joined with
+    /// colons and with duplicates removed.
+    pub(crate) rpath: Option<String>,
+
+    /// Whether to write DT_RUNPATH rather than the older DT_RPATH.
+    pub(crate) enable_new_dtags: bool,
+
+    /// Directories to search for the dependencies of shared objects that we link against.
+    pub(crate) rpath_link: Vec<Box<Path>>,
     pub(crate) soname: Option<String>,
+
+    /// The library named by `-F`, whose symbol table ours filters. Written as DT_FILTER.
+    pub(crate) filter: Option<String>,
+
+    /// Libraries named by `-f`, which provide definitions that take precedence over ours if they
+    /// exist at runtime. Each is written as DT_AUXILIARY.
+    pub(crate) auxiliary: Vec<String>,
+
+    /// A linker plugin given with `-plugin`, which we load if any of our inputs contain compiler
+    /// IR, e.g. GCC's `liblto_plugin.so` or `LLVMgold.so`.
+    pub(crate) plugin: Option<PathBuf>,
+
+    /// Options from `-plugin-opt`, which we pass to the plugin in the order they were given.
+    pub(crate) plugin_opts: Vec<String>,
+
+    /// Whether to compile the LLVM bitcode embedded in fat LTO objects, rather than linking their
+    /// regular code.
+    pub(crate) fat_lto_objects: bool,
+
+    /// Set by `--thinlto-index-only`. Rather than linking, we write a ThinLTO index and a list of
+    /// imports next to each bitcode input, so that the backends can be run by a distributed build.
+    /// If a path is given, we also write the bitcode files that need compiling to it.
+    pub(crate) thinlto_index_only: Option<Option<PathBuf>>,
+
+    /// Where ThinLTO caches the objects that it compiles, so that unchanged modules don't need to
+    /// be recompiled.
+    pub(crate) thinlto_cache_dir: Option<PathBuf>,
+
+    /// How many ThinLTO backends to run at once. Defaults to the number of threads that we use.
+    pub(crate) thinlto_jobs: Option<NonZeroUsize>,
+
+    /// The optimisation level for LTO that we do ourselves, from `--lto-O` or `-plugin-opt=O`.
+    pub(crate) lto_opt_level: Option<u8>,
+
+    /// The CPU to generate code for when we do LTO ourselves, from `-plugin-opt=mcpu=`.
+    pub(crate) lto_cpu: Option<String>,
+
+    /// The symbol whose address we use as our entry point. Set by `-e` or `--entry`.
+    pub(crate) entry: String,
+
+    /// Functions named by `-init` and `-fini`, whose addresses we write as DT_INIT and DT_FINI
+    /// instead of the start of `.init` and `.fini`.
+    pub(crate) init: Option<String>,
+    pub(crate) fini: Option<String>,
+
+    /// The address at which to place our file headers and first segment. See `--image-base`.
+    pub(crate) image_base: Option<u64>,
+
+    /// Addresses requested for particular output sections by `--section-start` or `-Ttext` etc.
+    pub(crate) section_starts: Vec<(String, u64)>,
     pub(crate) files_per_group: Option<u32>,
 
     /// If set, GC stats will be written to the specified filename.
@@ -51,7 +120,208 @@ pub(crate) struct Args {
     pub(crate) verbose_gc_stats: bool,
 
     pub(crate) print_allocations: Option<FileId>,
-    pub(crate) execstack: bool,
+
+    /// Whether the stack should be executable, if set by `-z execstack` or `-z noexecstack`. If not
+    /// set, we decide based on the `.note.GNU-stack` sections of our input objects.
+    pub(crate) execstack: Option<bool>,
+
+    /// Whether to warn if the output ends up with an executable stack.
+    pub(crate) warn_execstack: bool,
+
+    /// Whether to fail the link if the output would end up with an executable stack.
+    pub(crate) error_execstack: bool,
+
+    /// GNU_PROPERTY_X86_FEATURE_1_* bits that `-z ibt` and `-z shstk` ask us to set in the output's
+    /// `.note.gnu.property`, even if our inputs don't all support them.
+    pub(crate) force_x86_features: u32,
+
+    /// How to report input objects that lack IBT or SHSTK support. See `-z cet-report`.
+    pub(crate) cet_report: CetReport,
+
+    /// Whether to warn about input sections that need to be writable or executable, but end up in
+    /// a segment that isn't.
+    pub(crate) warn_rwx_segments: bool,
+
+    /// Whether to emit a PT_GNU_RELRO segment covering data that only needs to be writable while
+    /// the dynamic loader applies relocations.
+    pub(crate) relro: bool,
+
+    /// Whether the dynamic loader should resolve all symbols at startup (`-z now`), rather than
+    /// binding calls to functions in shared objects the first time that they're made (`-z lazy`).
+    pub(crate) bind_now: bool,
+
+    /// The largest page size that the output should work with. Loadable segments are aligned so
+    /// that they can be mapped with pages of this size.
+    pub(crate) max_page_size: Alignment,
+
+    /// The page size that the output is optimised for. We pad the end of the RELRO segment to a
+    /// multiple of this.
+    pub(crate) common_page_size: Alignment,
+
+    /// Whether executable code should get pages to itself, rather than sharing its first and last
+    /// pages with read-only data and the file header. Sharing pages makes the output smaller, but
+    /// means that some non-code bytes end up mapped executable.
+    pub(crate) separate_code: bool,
+
+    /// Whether read-only data gets a LOAD segment of its own, rather than sharing the executable
+    /// one. See `--no-rosegment`.
+    pub(crate) rosegment: bool,
+    pub(crate) magic: Magic,
+
+    /// Whether to fail the link if the dynamic loader would need to write to a read-only section
+    /// (`-z text`), rather than marking the output with DT_TEXTREL (`-z notext`).
+    pub(crate) text: bool,
+
+    /// DF_1_* flags requested by options like `-z nodelete`, to be added to those that we set
+    /// ourselves in DT_FLAGS_1.
+    pub(crate) dt_flags_1: u32,
+
+    /// Whether we may use copy relocations for direct references to data defined by shared
+    /// objects. If not, such references are an error.
+    pub(crate) copy_relocations: bool,
+
+    /// Whether to pack relative relocations into `.relr.dyn` rather than emitting them as RELA
+    /// relocations. See `-z pack-relative-relocs`.
+    pub(crate) pack_relative_relocs: bool,
+
+    /// Which classes of relaxation we may perform.
+    pub(crate) relax: Relax,
+
+    /// Which definitions in a shared object that references from within the shared object bind
+    /// to directly, rather than allowing them to be interposed. See `-Bsymbolic`.
+    pub(crate) symbolic: Symbolic,
+
+    /// Whether, when building a shared object, symbols that we'd export keep the sections that
+    /// define them alive. If not, we only export symbols from sections that are otherwise
+    /// reachable.
+    pub(crate) gc_keep_exported: bool,
+
+    /// Whether to write the variables section of our merged CTF type information. It's usually
+    /// redundant with the data objects section, so is omitted unless `--ctf-variables` is given.
+    pub(crate) ctf_variables: bool,
+
+    /// Overrides for the values written by relocations in debug sections that refer to discarded
+    /// sections, from `-z dead-reloc-in-nonalloc`. Later rules take precedence.
+    pub(crate) dead_reloc_in_nonalloc: Vec<DeadRelocRule>,
+
+    /// The number of buckets to use in .gnu.hash, if set by `--hash-size`. Otherwise we choose
+    /// based on the number of dynamic symbols.
+    pub(crate) hash_size: Option<u32>,
+
+    /// The number of 64 bit words in the .gnu.hash bloom filter, if set by `--hash-bloom-words`.
+    pub(crate) hash_bloom_words: Option<u32>,
+
+    /// How to compute the contents of `.note.gnu.build-id`, or None if we shouldn't write one.
+    pub(crate) build_id: Option<BuildId>,
+
+    /// Where to write debug info, if it should be split out of the output and replaced with a
+    /// `.gnu.debuglink` section.
+    pub(crate) separate_debug_file: Option<PathBuf>,
+
+    /// Whether to print which I/O paths were chosen after probing for optional facilities.
+    pub(crate) explain_io: bool,
+
+    pub(crate) section_order: SectionOrder,
+
+    /// Which sections, if any, identical code folding may merge.
+    pub(crate) icf: Icf,
+
+    /// Whether to report each section that identical code folding removes.
+    pub(crate) print_icf_sections: bool,
+
+    /// Symbols whose sections must not be folded, even if identical to other sections.
+    pub(crate) keep_unique: Vec<String>,
+
+    /// Whether to use call-graph profiles from `.llvm.call-graph-profile` sections to place hot
+    /// functions near each other.
+    pub(crate) call_graph_profile_sort: bool,
+
+    /// Whether `.text.hot`, `.text.unlikely`, `.text.startup` and `.text.exit` sections go in
+    /// output sections of their own rather than in `.text`.
+    pub(crate) keep_text_section_prefix: bool,
+
+    /// Where to write a map file describing where input sections and symbols ended up.
+    pub(crate) map_file: Option<PathBuf>,
+
+    /// The format in which to write the map file.
+    pub(crate) map_format: MapFormat,
+
+    /// Where to write a Make-style list of the files that the output depends on.
+    pub(crate) dependency_file: Option<PathBuf>,
+
+    /// Where to write a tar archive of our inputs and a response file that repeats the link.
+    pub(crate) reproduce: Option<PathBuf>,
+
+    /// Whether to report statistics about the link and if so, the file to write them to. If no
+    /// file was given, they're printed to stdout.
+    pub(crate) stats: Option<Option<PathBuf>>,
+
+    /// Where to write which reference caused each archive member to be loaded.
+    pub(crate) why_extract: Option<PathBuf>,
+
+    /// Symbols for which we should report each definition and reference. Only files that were
+    /// loaded are reported.
+    pub(crate) trace_symbols: Vec<String>,
+
+    /// Symbols defined by `--defsym`, in the order that they were given.
+    pub(crate) defsyms: Vec<Defsym>,
+
+    /// Symbols named by `--wrap`. Undefined references to each from our input objects go to the
+    /// symbol's `__wrap_` variant, while references to its `__real_` variant go to the symbol.
+    pub(crate) wrapped_symbols: Vec<WrappedSymbol>,
+
+    /// Whether to print each input file that we load, as GNU ld does with `-t`. We also print each
+    /// path that we probe when searching for libraries.
+    pub(crate) trace: bool,
+
+    /// Whether undefined symbols in executables are reported as warnings rather than errors.
+    pub(crate) warn_unresolved_symbols: bool,
+
+    /// Whether to use the first definition of symbols that are defined more than once, rather than
+    /// reporting an error.
+    pub(crate) allow_multiple_definition: bool,
+
+    /// The maximum number of errors to report. Zero means no limit.
+    pub(crate) error_limit: usize,
+
+    /// Whether to demangle symbol names in diagnostics and reports.
+    pub(crate) demangle: bool,
+
+    /// How to present warnings and errors, together with a count of the warnings that we've issued.
+    pub(crate) diagnostics: Arc<Diagnostics>,
+
+    /// What to do when an archive member is a copy of a member of an earlier archive. If not set,
+    /// we don't look for copies.
+    pub(crate) duplicate_archive_members: Option<DuplicateMemberPolicy>,
+
+    /// Whether to fail the link if we issued any warnings.
+    pub(crate) fatal_warnings: bool,
+
+    /// Which warnings to suppress for which input files.
+    pub(crate) suppress_warnings_from: Vec<WarningSuppression>,
+
+    /// A directory that paths starting with `=` or `$SYSROOT` are relative to.
+    pub(crate) sysroot: Option<Box<Path>>,
+
+    /// Whether to print a summary of segments and dynamic tags once the output is written.
+    pub(crate) print_output_summary: bool,
+
+    /// The arguments that we were invoked with.
+    pub(crate) command_line: Vec<String>,
+
+    /// Whether to record our arguments in `.note.wild.cmdline`. Off unless `--record-command-line`
+    /// is given.
+    pub(crate) record_command_line: bool,
+
+    /// If set, we leave the output alone if nothing has changed since the previous link, using the
+    /// specified method to tell whether an input has changed.
+    pub(crate) skip_if_unchanged: Option<ChangeDetection>,
+
+    /// Whether to write a listing of our global symbols instead of an ELF file.
+    pub(crate) emit_symbols_only: bool,
+
+    /// Whether to print why each exported symbol is or isn't preemptible.
+    pub(crate) print_preemptibility: bool,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -61,6 +331,9 @@ pub(crate) enum Action {
 
     /// Print the linker version.
     Version,
+
+    /// Report which inputs define the specified symbols without linking.
+    QueryDefines(QueryDefines),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -70,6 +343,224 @@ pub(crate) enum OutputKind {
     SharedObject,
 }
 
+/// A rule from `-z dead-reloc-in-nonalloc=<pattern>=<value>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DeadRelocRule {
+    /// A section name, optionally ending with `*` to match any section with that prefix.
+    pub(crate) section_pattern: String,
+
+    /// The value to write in place of the address of a discarded section.
+    pub(crate) value: u64,
+}
+
+/// A symbol defined by `--defsym <name>=<value>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Defsym {
+    pub(crate) name: String,
+    pub(crate) value: DefsymValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum DefsymValue {
+    /// An absolute value, e.g. `--defsym foo=0x1000`.
+    Absolute(u64),
+
+    /// Another symbol, whose value we copy, e.g. `--defsym foo=bar`.
+    Symbol(String),
+}
+
+/// A symbol named by `--wrap`, together with the names that references are redirected between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WrappedSymbol {
+    pub(crate) name: String,
+    pub(crate) wrap_name: String,
+    pub(crate) real_name: String,
+}
+
+/// The formats for the map file, selected by `--map-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MapFormat {
+    /// The text format used by GNU ld.
+    Text,
+
+    /// Structured layout data for consumption by tools.
+    Json,
+}
+
+/// How `--skip-if-unchanged` tells whether an input has changed since the previous link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChangeDetection {
+    /// Compare file metadata such as size and modification time. This is cheap, but misses changes
+    /// made by tools that preserve both the size and the timestamps of a file, and treats a file
+    /// that was rewritten with identical contents as changed.
+    Metadata,
+
+    /// Compare a hash of each file's contents. This reads every input in full.
+    Contents,
+}
+
+/// When to use colour in diagnostics, selected by `--color-diagnostics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColorChoice {
+    /// Use colour if we're writing to a terminal and `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// How errors and warnings are written, selected by `--diagnostics-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiagnosticsFormat {
+    /// Text for people to read.
+    Text,
+
+    /// One JSON object per line, for consumption by tools.
+    Json,
+}
+
+/// The identical code folding modes selected by `--icf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Icf {
+    /// Don't fold anything.
+    None,
+
+    /// Only fold sections that don't define address-significant symbols, as listed in each
+    /// object's `.llvm_addrsig` section. This keeps function-pointer comparisons working.
+    Safe,
+
+    /// Fold any identical read-only or executable sections, even if that means that distinct
+    /// functions end up with the same address.
+    All,
+}
+
+/// The classic output formats, named after their a.out magic numbers, that `-n` and `-N` select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Magic {
+    /// The default. Segments are page aligned, so that they can be mapped from the file on demand.
+    Zmagic,
+
+    /// Segments aren't page aligned. Segments with different permissions would then share pages,
+    /// so as with GNU ld, everything goes in a single writable, executable segment. See `-n`.
+    Nmagic,
+
+    /// Segments aren't page aligned and everything goes in a single writable, executable segment.
+    /// See `-N`.
+    Omagic,
+}
+
+/// How input sections are ordered within each output section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SectionOrder {
+    /// Sections are placed in the order in which they appear in the input files.
+    Input,
+
+    /// Within each input file, sections are sorted by name then by a hash of their contents. This
+    /// keeps the output more similar between successive builds, making binary diffs smaller.
+    Content,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicateMemberPolicy {
+    /// Silently drop the later copy.
+    Deduplicate,
+
+    /// Drop the later copy, but issue a warning.
+    Warn,
+
+    /// Fail the link.
+    Error,
+}
+
+/// The classes of relaxation that we may perform. `--relax` and `--no-relax` turn them all on or
+/// off, while `--relax=` and `--no-relax=` take a comma-separated list of classes. Static
+/// executables are always relaxed, since libc depends on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Relax {
+    /// Computing addresses and values directly, rather than loading them from the GOT.
+    pub(crate) got: bool,
+
+    /// Turning calls via the PLT into direct calls.
+    pub(crate) plt: bool,
+
+    /// Changing TLS accesses to use a cheaper access model.
+    pub(crate) tls: bool,
+}
+
+impl Relax {
+    pub(crate) const ALL: Relax = Relax {
+        got: true,
+        plt: true,
+        tls: true,
+    };
+
+    pub(crate) const NONE: Relax = Relax {
+        got: false,
+        plt: false,
+        tls: false,
+    };
+
+    /// Enables or disables each of the comma-separated classes in `classes`.
+    fn set(&mut self, classes: &str, enabled: bool) -> Result {
+        for class in classes.split(',') {
+            match class {
+                "got" => self.got = enabled,
+                "plt" => self.plt = enabled,
+                "tls" => self.tls = enabled,
+                other => bail!("Unknown relaxation class `{other}`. Expected got, plt or tls"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which definitions `-Bsymbolic` and `-Bsymbolic-functions` make non-interposable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Symbolic {
+    None,
+    Functions,
+    All,
+}
+
+impl Symbolic {
+    /// Returns whether a definition of type `st_type` in a shared object is bound to directly.
+    pub(crate) fn applies_to(self, st_type: u8) -> bool {
+        match self {
+            Symbolic::None => false,
+            Symbolic::Functions => {
+                matches!(st_type, object::elf::STT_FUNC | object::elf::STT_GNU_IFUNC)
+            }
+            Symbolic::All => true,
+        }
+    }
+}
+
+/// How `-z cet-report` asks us to report inputs that lack CET features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CetReport {
+    None,
+    Warning,
+    Error,
+}
+
+/// The ways that we can compute a build ID. See `--build-id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BuildId {
+    /// A fast, non-cryptographic hash of the output.
+    Fast,
+
+    /// An MD5 hash of the output.
+    Md5,
+
+    /// A SHA-1 hash of the output.
+    Sha1,
+
+    /// A random UUID. Unlike the other styles, this changes even if the output doesn't.
+    Uuid,
+
+    /// Bytes that were supplied on the command line.
+    Hex(Vec<u8>),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum RelocationModel {
     NonRelocatable,
@@ -83,6 +574,9 @@ pub(crate) struct Modifiers {
 
     /// Whether we're currently allowed to link against shared libraries.
     pub(crate) allow_shared: bool,
+
+    /// Whether the DT_NEEDED entries of shared objects should be added to the link.
+    pub(crate) copy_dt_needed: bool,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -98,6 +592,9 @@ pub(crate) struct Input {
 pub(crate) enum InputSpec {
     File(Box<Path>),
     Lib(Box<str>),
+
+    /// A file containing the paths of input files, one per line. `-` means stdin.
+    FileList(Box<Path>),
 }
 
 pub const VALIDATE_ENV: &str = "WILD_VALIDATE_OUTPUT";
@@ -106,13 +603,14 @@ pub const WRITE_TRACE_ENV: &str = "WILD_WRITE_TRACE";
 pub const REFERENCE_LINKER_ENV: &str = "WILD_REFERENCE_LINKER";
 pub(crate) const FILES_PER_GROUP_ENV: &str = "WILD_FILES_PER_GROUP";
 
+/// How many errors we report if `--error-limit` isn't given. This is the same as lld.
+const DEFAULT_ERROR_LIMIT: usize = 20;
+
 // These flags don't currently affect our behaviour. TODO: Assess whether we should error or warn if
 // these are given. This is tricky though. On the one hand we want to be a drop-in replacement for
 // other linkers. On the other, we should perhaps somehow let the user know that we don't support a
 // feature.
 const IGNORED_FLAGS: &[&str] = &[
-    // TODO: Support build-ids
-    "build-id",
     // TODO: We currently always GC sections. Support _not_ GCing them.
     "gc-sections",
     // TODO: Think about if anything is needed here. We don't need groups in order resolve cycles,
@@ -125,14 +623,12 @@ const IGNORED_FLAGS: &[&str] = &[
     // TODO
     "no-undefined-version",
     "export-dynamic",
-    "fatal-warnings",
     "color-diagnostics",
     "undefined-version",
     "no-call-graph-profile-sort",
     "gdb-index",
-    "disable-new-dtags",
-    "relax",
-    "no-relax",
+    // Library search probes are printed with `--trace` instead.
+    "verbose",
 ];
 
 pub(crate) fn from_env() -> Result<Action> {
@@ -142,12 +638,15 @@ pub(crate) fn from_env() -> Result<Action> {
 
 // Parse the supplied input arguments, which should not include the program name.
 #[allow(clippy::if_same_then_else)]
-pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Result<Action> {
+pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(input: I) -> Result<Action> {
     let mut lib_search_path = Vec::new();
     let mut inputs = Vec::new();
     let mut output = None;
     let mut is_dynamic_executable = false;
     let mut dynamic_linker = None;
+    let mut dynamic_linker_given = false;
+    let mut is_static = false;
+    let mut emulation = None;
     let mut output_kind = None;
     let mut time_phases = false;
     let mut num_threads = None;
@@ -172,8 +671,83 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
     let mut action = None;
     let mut unrecognised = Vec::new();
     let mut rpaths = Vec::new();
+    let mut enable_new_dtags = true;
+    let mut rpath_link = Vec::new();
     let mut soname = None;
-    let mut execstack = false;
+    let mut filter = None;
+    let mut auxiliary = Vec::new();
+    let mut plugin = None;
+    let mut plugin_opts = Vec::new();
+    let mut fat_lto_objects = false;
+    let mut thinlto_index_only = None;
+    let mut thinlto_cache_dir = None;
+    let mut thinlto_jobs = None;
+    let mut lto_opt_level = None;
+    let mut entry = None;
+    let mut init = None;
+    let mut fini = None;
+    let mut image_base = None;
+    let mut section_starts = Vec::new();
+    let mut execstack = None;
+    let mut warn_execstack = false;
+    let mut error_execstack = false;
+    let mut force_x86_features = 0;
+    let mut cet_report = CetReport::None;
+    let mut symbolic = Symbolic::None;
+    let mut relax = Relax::ALL;
+    let mut warn_rwx_segments = true;
+    let mut relro = true;
+    let mut max_page_size = None;
+    let mut common_page_size = None;
+    let mut separate_code = true;
+    let mut rosegment = true;
+    let mut magic = Magic::Zmagic;
+    let mut text = false;
+    let mut dt_flags_1 = 0;
+    let mut copy_relocations = true;
+    let mut pack_relative_relocs = false;
+    let mut gc_keep_exported = true;
+    let mut ctf_variables = false;
+    let mut dead_reloc_in_nonalloc = Vec::new();
+    let mut hash_size = None;
+    let mut hash_bloom_words = None;
+    let mut build_id = None;
+    let mut separate_debug_file: Option<Option<PathBuf>> = None;
+    let mut bind_now = true;
+    let mut explain_io = false;
+    let mut section_order = SectionOrder::Input;
+    let mut icf = Icf::None;
+    let mut print_icf_sections = false;
+    let mut keep_unique = Vec::new();
+    let mut call_graph_profile_sort = true;
+    let mut keep_text_section_prefix = false;
+    let mut map_file = None;
+    let mut map_format = MapFormat::Text;
+    let mut dependency_file = None;
+    let mut reproduce = None;
+    let mut stats = None;
+    let mut why_extract = None;
+    let mut trace_symbols = Vec::new();
+    let mut defsyms = Vec::new();
+    let mut wrapped_symbols = Vec::new();
+    let mut trace = false;
+    let mut warn_unresolved_symbols = false;
+    let mut allow_multiple_definition = false;
+    let mut error_limit = DEFAULT_ERROR_LIMIT;
+    let mut demangle = true;
+    let mut color_diagnostics = ColorChoice::Auto;
+    let mut diagnostics_format = DiagnosticsFormat::Text;
+    let mut duplicate_archive_members = None;
+    let mut fatal_warnings = false;
+    let mut suppress_warnings_from = Vec::new();
+    let mut sysroot = None;
+    let mut print_output_summary = false;
+    let mut record_command_line = false;
+    let mut skip_if_unchanged = None;
+    let mut emit_symbols_only = false;
+    let mut print_preemptibility = false;
+    let mut command_line = Vec::new();
+    let mut input = input.inspect(|arg| command_line.push(arg.as_ref().to_owned()));
     let max_files_per_group = std::env::var(FILES_PER_GROUP_ENV)
         .ok()
         .map(|s| s.parse())
@@ -202,18 +776,67 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             assert!(option.ends_with('='));
             strip_option(arg).and_then(|stripped_arg| stripped_arg.strip_prefix(option))
         };
-        let mut handle_z_option = |arg: &str| {
+        let mut handle_z_option = |arg: &str| -> Result {
+            if let Some(size) = arg.strip_prefix("max-page-size=") {
+                max_page_size = Some(parse_page_size(size).context("Invalid -z max-page-size")?);
+                return Ok(());
+            }
+            if let Some(rule) = arg.strip_prefix("dead-reloc-in-nonalloc=") {
+                dead_reloc_in_nonalloc.push(
+                    DeadRelocRule::parse(rule)
+                        .with_context(|| format!("Invalid -z dead-reloc-in-nonalloc={rule}"))?,
+                );
+                return Ok(());
+            }
+            if let Some(report) = arg.strip_prefix("cet-report=") {
+                cet_report = match report {
+                    "none" => CetReport::None,
+                    "warning" => CetReport::Warning,
+                    "error" => CetReport::Error,
+                    other => bail!("Invalid -z cet-report `{other}`"),
+                };
+                return Ok(());
+            }
+            if matches!(arg, "bti" | "force-bti" | "pac-plt") || arg.starts_with("bti-report=") {
+                // These only mean something for AArch64 output. Ignoring them like other unknown -z
+                // options would produce output that lacks the protection that was asked for.
+                bail!("-z {arg} is only supported for AArch64 output, which we can't produce");
+            }
+            if let Some(size) = arg.strip_prefix("common-page-size=") {
+                common_page_size =
+                    Some(parse_page_size(size).context("Invalid -z common-page-size")?);
+                return Ok(());
+            }
             match arg {
-                "now" => {}
-                "lazy" => {
-                    warning!("wild doesn't support -z lazy");
-                }
-                "execstack" => execstack = true,
-                "noexecstack" => execstack = false,
+                "now" => bind_now = true,
+                "lazy" => bind_now = false,
+                "relro" => relro = true,
+                "norelro" => relro = false,
+                "execstack" => execstack = Some(true),
+                "noexecstack" => execstack = Some(false),
+                "separate-code" => separate_code = true,
+                "noseparate-code" => separate_code = false,
+                "text" => text = true,
+                "notext" | "textoff" => text = false,
+                "nodelete" => dt_flags_1 |= object::elf::DF_1_NODELETE,
+                "nodlopen" => dt_flags_1 |= object::elf::DF_1_NOOPEN,
+                "initfirst" => dt_flags_1 |= object::elf::DF_1_INITFIRST,
+                "interpose" => dt_flags_1 |= object::elf::DF_1_INTERPOSE,
+                "global" => dt_flags_1 |= object::elf::DF_1_GLOBAL,
+                "copyreloc" => copy_relocations = true,
+                "nocopyreloc" => copy_relocations = false,
+                "pack-relative-relocs" => pack_relative_relocs = true,
+                "nopack-relative-relocs" => pack_relative_relocs = false,
+                "keep-text-section-prefix" => keep_text_section_prefix = true,
+                "nokeep-text-section-prefix" => keep_text_section_prefix = false,
+                "muldefs" => allow_multiple_definition = true,
+                "ibt" => force_x86_features |= object::elf::GNU_PROPERTY_X86_FEATURE_1_IBT,
+                "shstk" => force_x86_features |= object::elf::GNU_PROPERTY_X86_FEATURE_1_SHSTK,
                 _ => {
                     // TODO: Handle these
                 }
             }
+            Ok(())
         };
 
         if let Some(rest) = arg.strip_prefix("-L") {
@@ -224,22 +847,83 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             } else {
                 lib_search_path.push(Box::from(Path::new(rest)));
             }
+        } else if let Some(rest) = arg.strip_prefix("-y") {
+            if rest.is_empty() {
+                trace_symbols.push(
+                    input
+                        .next()
+                        .context("Missing argument to -y")?
+                        .as_ref()
+                        .to_owned(),
+                );
+            } else {
+                trace_symbols.push(rest.to_owned());
+            }
         } else if let Some(rest) = arg.strip_prefix("-l") {
             inputs.push(Input {
                 spec: InputSpec::Lib(Box::from(rest)),
                 search_first: None,
                 modifiers: *modifier_stack.last().unwrap(),
             });
-        } else if long_arg_eq("static") || long_arg_eq("Bstatic") {
+        } else if ["static", "Bstatic", "dn", "non_shared"]
+            .iter()
+            .any(|option| long_arg_eq(option))
+        {
+            // Subsequent -l options only consider archives.
             modifier_stack.last_mut().unwrap().allow_shared = false;
-        } else if long_arg_eq("Bdynamic") {
+            is_static |= long_arg_eq("static");
+        } else if ["Bdynamic", "dy", "call_shared"]
+            .iter()
+            .any(|option| long_arg_eq(option))
+        {
+            // Subsequent -l options prefer shared objects, falling back to archives.
             modifier_stack.last_mut().unwrap().allow_shared = true;
+        } else if long_arg_eq("nmagic") || arg == "-n" || long_arg_eq("omagic") || arg == "-N" {
+            magic = if long_arg_eq("omagic") || arg == "-N" {
+                Magic::Omagic
+            } else {
+                Magic::Nmagic
+            };
+            // Like GNU ld, we don't link against shared objects in these modes.
+            modifier_stack.last_mut().unwrap().allow_shared = false;
+        } else if long_arg_eq("no-omagic") {
+            magic = Magic::Zmagic;
+        } else if long_arg_eq("rosegment") {
+            rosegment = true;
+        } else if long_arg_eq("no-rosegment") {
+            rosegment = false;
+        } else if long_arg_eq("relax") {
+            relax = Relax::ALL;
+        } else if long_arg_eq("no-relax") {
+            relax = Relax::NONE;
+        } else if let Some(classes) = long_arg_split_prefix("relax=") {
+            relax.set(classes, true)?;
+        } else if let Some(classes) = long_arg_split_prefix("no-relax=") {
+            relax.set(classes, false)?;
+        } else if long_arg_eq("Bsymbolic") {
+            symbolic = Symbolic::All;
+        } else if long_arg_eq("Bsymbolic-functions") {
+            symbolic = Symbolic::Functions;
+        } else if long_arg_eq("Bno-symbolic") {
+            symbolic = Symbolic::None;
         } else if arg == "-o" {
             output = input.next().map(|a| Arc::from(Path::new(a.as_ref())));
-        } else if long_arg_eq("dynamic-linker") {
+        } else if long_arg_eq("dynamic-linker") || arg == "-I" {
+            is_dynamic_executable = true;
+            dynamic_linker_given = true;
+            dynamic_linker = parse_dynamic_linker(
+                input
+                    .next()
+                    .context("Missing argument to --dynamic-linker")?
+                    .as_ref(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("dynamic-linker=") {
             is_dynamic_executable = true;
-            dynamic_linker = input.next().map(|a| Box::from(Path::new(a.as_ref())));
+            dynamic_linker_given = true;
+            dynamic_linker = parse_dynamic_linker(rest);
         } else if long_arg_eq("no-dynamic-linker") {
+            // Used for static-PIE, where the executable relocates itself and there's no loader.
+            dynamic_linker_given = true;
             dynamic_linker = None;
         } else if let Some(style) = long_arg_split_prefix("hash-style=") {
             // We don't technically support both hash styles, but if requested to do both, we just
@@ -248,7 +932,37 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
                 bail!("Unsupported hash-style `{style}`");
             }
             // Since we currently only support GNU hash, there's no state to update.
-        } else if long_arg_split_prefix("build-id=").is_some() {
+        } else if let Some(rest) = long_arg_split_prefix("hash-size=") {
+            let size = u32::try_from(parse_number(rest)?).context("Invalid --hash-size")?;
+            ensure!(size > 0, "--hash-size must be non-zero");
+            hash_size = Some(size);
+        } else if let Some(rest) = long_arg_split_prefix("hash-bloom-words=") {
+            let words = u32::try_from(parse_number(rest)?).context("Invalid --hash-bloom-words")?;
+            ensure!(
+                words.is_power_of_two(),
+                "--hash-bloom-words must be a power of two, got {words}"
+            );
+            hash_bloom_words = Some(words);
+        } else if long_arg_eq("build-id") {
+            build_id = Some(BuildId::Sha1);
+        } else if let Some(rest) = long_arg_split_prefix("build-id=") {
+            build_id = parse_build_id(rest)?;
+        } else if long_arg_eq("fix-cortex-a53-843419") || long_arg_eq("fix-cortex-a53-835769") {
+            // AArch64 toolchains pass these by default. Patching the errata needs an AArch64
+            // backend, so report that rather than "unrecognised argument".
+            bail!("{arg} is only supported for AArch64 output, which we can't produce");
+        } else if long_arg_eq("gc-keep-exported") {
+            gc_keep_exported = true;
+        } else if long_arg_eq("no-gc-keep-exported") {
+            gc_keep_exported = false;
+        } else if long_arg_eq("ctf-variables") {
+            ctf_variables = true;
+        } else if long_arg_eq("no-ctf-variables") {
+            ctf_variables = false;
+        } else if long_arg_eq("separate-debug-file") {
+            separate_debug_file = Some(None);
+        } else if let Some(rest) = long_arg_split_prefix("separate-debug-file=") {
+            separate_debug_file = Some(Some(PathBuf::from(rest)));
         } else if long_arg_eq("time") {
             time_phases = true;
         } else if let Some(rest) = long_arg_split_prefix("threads=") {
@@ -266,12 +980,13 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
         } else if long_arg_eq("strip-debug") {
             strip_debug = true;
         } else if arg == "-m" {
-            // TODO: Handle these flags
-            input.next();
+            emulation = Some(find_emulation(
+                input.next().context("Missing argument to -m")?.as_ref(),
+            )?);
         } else if arg == "-z" {
-            handle_z_option(input.next().context("Missing argument to -z")?.as_ref());
+            handle_z_option(input.next().context("Missing argument to -z")?.as_ref())?;
         } else if let Some(arg) = arg.strip_prefix("-z") {
-            handle_z_option(arg);
+            handle_z_option(arg)?;
         } else if let Some(_rest) = arg.strip_prefix("-O") {
             // We don't use opt-level for now.
         } else if long_arg_eq("prepopulate-maps") {
@@ -282,6 +997,10 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             modifier_stack.last_mut().unwrap().as_needed = true;
         } else if long_arg_eq("no-as-needed") {
             modifier_stack.last_mut().unwrap().as_needed = false;
+        } else if long_arg_eq("copy-dt-needed-entries") || long_arg_eq("add-needed") {
+            modifier_stack.last_mut().unwrap().copy_dt_needed = true;
+        } else if long_arg_eq("no-copy-dt-needed-entries") || long_arg_eq("no-add-needed") {
+            modifier_stack.last_mut().unwrap().copy_dt_needed = false;
         } else if long_arg_eq("push-state") {
             modifier_stack.push(*modifier_stack.last().unwrap());
         } else if long_arg_eq("pop-state") {
@@ -312,6 +1031,10 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             );
         } else if let Some(rest) = long_arg_split_prefix("rpath=") {
             rpaths.push(rest.to_owned());
+        } else if long_arg_eq("enable-new-dtags") {
+            enable_new_dtags = true;
+        } else if long_arg_eq("disable-new-dtags") {
+            enable_new_dtags = false;
         } else if long_arg_eq("no-string-merge") {
             merge_strings = false;
         } else if long_arg_eq("pie") {
@@ -320,9 +1043,19 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             eh_frame_hdr = true;
         } else if long_arg_eq("shared") {
             output_kind = Some(OutputKind::SharedObject);
+        } else if let Some(rest) = long_arg_split_prefix("entry=") {
+            entry = Some(rest.to_owned());
+        } else if long_arg_eq("entry") || arg == "-e" {
+            entry = Some(
+                input
+                    .next()
+                    .context("Missing argument to --entry")?
+                    .as_ref()
+                    .to_owned(),
+            );
         } else if let Some(rest) = long_arg_split_prefix("soname=") {
             soname = Some(rest.to_owned());
-        } else if long_arg_eq("soname") {
+        } else if long_arg_eq("soname") || arg == "-h" {
             soname = Some(
                 input
                     .next()
@@ -330,13 +1063,134 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
                     .as_ref()
                     .to_owned(),
             );
-        } else if long_arg_split_prefix("plugin-opt=").is_some() {
-            // TODO: Implement support for linker plugins.
+        } else if let Some(rest) = long_arg_split_prefix("image-base=") {
+            image_base = Some(parse_address(rest)?);
+        } else if long_arg_eq("image-base") {
+            image_base = Some(parse_address(
+                input
+                    .next()
+                    .context("Missing argument to --image-base")?
+                    .as_ref(),
+            )?);
+        } else if let Some(rest) = long_arg_split_prefix("Ttext-segment=") {
+            image_base = Some(parse_address(rest)?);
+        } else if let Some(rest) = long_arg_split_prefix("section-start=") {
+            section_starts.push(parse_section_start(rest)?);
+        } else if long_arg_eq("section-start") {
+            section_starts.push(parse_section_start(
+                input
+                    .next()
+                    .context("Missing argument to --section-start")?
+                    .as_ref(),
+            )?);
+        } else if let Some(section) = ["text", "data", "bss"]
+            .into_iter()
+            .find(|section| strip_option(arg).and_then(|a| a.strip_prefix('T')) == Some(section))
+        {
+            let address = input
+                .next()
+                .with_context(|| format!("Missing argument to -T{section}"))?;
+            section_starts.push((format!(".{section}"), parse_address(address.as_ref())?));
+        } else if let Some((section, address)) = ["text=", "data=", "bss="]
+            .into_iter()
+            .find_map(|section| Some((section, long_arg_split_prefix(&format!("T{section}"))?)))
+        {
+            let name = format!(".{}", section.trim_end_matches('='));
+            section_starts.push((name, parse_address(address)?));
+        } else if let Some(rest) = long_arg_split_prefix("init=") {
+            init = Some(rest.to_owned());
+        } else if long_arg_eq("init") {
+            init = Some(
+                input
+                    .next()
+                    .context("Missing argument to -init")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("fini=") {
+            fini = Some(rest.to_owned());
+        } else if long_arg_eq("fini") {
+            fini = Some(
+                input
+                    .next()
+                    .context("Missing argument to -fini")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("filter=") {
+            filter = Some(rest.to_owned());
+        } else if long_arg_eq("filter") || arg == "-F" {
+            filter = Some(
+                input
+                    .next()
+                    .context("Missing argument to -F")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("auxiliary=") {
+            auxiliary.push(rest.to_owned());
+        } else if long_arg_eq("auxiliary") || arg == "-f" {
+            auxiliary.push(
+                input
+                    .next()
+                    .context("Missing argument to -f")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("plugin-opt=") {
+            plugin_opts.push(rest.to_owned());
+        } else if long_arg_eq("plugin-opt") {
+            plugin_opts.push(
+                input
+                    .next()
+                    .context("Missing argument to -plugin-opt")?
+                    .as_ref()
+                    .to_owned(),
+            );
         } else if long_arg_eq("plugin") {
-            input.next();
+            let path = input.next().context("Missing argument to -plugin")?;
+            let path = PathBuf::from(path.as_ref());
+            if plugin.as_ref().is_some_and(|existing| *existing != path) {
+                bail!("Only one linker plugin is supported");
+            }
+            plugin = Some(path);
+        } else if long_arg_eq("fat-lto-objects") {
+            fat_lto_objects = true;
+        } else if long_arg_eq("no-fat-lto-objects") {
+            fat_lto_objects = false;
+        } else if long_arg_eq("thinlto-index-only") {
+            thinlto_index_only = Some(None);
+        } else if let Some(rest) = long_arg_split_prefix("thinlto-index-only=") {
+            thinlto_index_only = Some(Some(PathBuf::from(rest)));
+        } else if let Some(rest) = long_arg_split_prefix("thinlto-cache-dir=") {
+            thinlto_cache_dir = Some(PathBuf::from(rest));
+        } else if let Some(rest) = long_arg_split_prefix("thinlto-jobs=") {
+            thinlto_jobs = if rest == "all" {
+                Some(crate::threading::available_parallelism())
+            } else {
+                Some(
+                    NonZeroUsize::try_from(rest.parse::<usize>()?)
+                        .context("--thinlto-jobs must be at least 1")?,
+                )
+            };
+        } else if let Some(rest) = strip_option(arg).and_then(|arg| arg.strip_prefix("lto-O")) {
+            lto_opt_level = Some(parse_lto_opt_level(rest)?);
+        } else if long_arg_eq("flto")
+            || long_arg_eq("fno-lto")
+            || long_arg_split_prefix("flto=").is_some()
+            || long_arg_split_prefix("lto-partitions=").is_some()
+        {
+            // Some build systems pass compiler flags like these to the linker too. Whether we do
+            // LTO only depends on whether our inputs contain IR.
         } else if long_arg_eq("rpath-link") {
-            // TODO
-            input.next();
+            let dirs = input.next().context("Missing argument to -rpath-link")?;
+            rpath_link.extend(
+                dirs.as_ref()
+                    .split(':')
+                    .map(|dir| Box::from(Path::new(dir))),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("rpath-link=") {
+            rpath_link.extend(rest.split(':').map(|dir| Box::from(Path::new(dir))));
         } else if long_arg_eq("validate-output") {
             validate_output = true;
         } else if long_arg_eq("write-layout") {
@@ -349,6 +1203,193 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             gc_stats_ignore.push(rest.to_owned());
         } else if long_arg_eq("version") || arg == "-v" {
             action = Some(Action::Version);
+        } else if let Some(rest) = long_arg_split_prefix("section-order=") {
+            section_order = match rest {
+                "input" => SectionOrder::Input,
+                "content" => SectionOrder::Content,
+                other => bail!("Unsupported --section-order `{other}`"),
+            };
+        } else if let Some(rest) = long_arg_split_prefix("icf=") {
+            icf = match rest {
+                "none" => Icf::None,
+                "safe" => Icf::Safe,
+                "all" => Icf::All,
+                other => bail!("Unsupported --icf `{other}`"),
+            };
+        } else if long_arg_eq("print-icf-sections") {
+            print_icf_sections = true;
+        } else if long_arg_eq("no-print-icf-sections") {
+            print_icf_sections = false;
+        } else if long_arg_eq("keep-unique") {
+            keep_unique.push(
+                input
+                    .next()
+                    .context("Missing argument to --keep-unique")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("keep-unique=") {
+            keep_unique.push(rest.to_owned());
+        } else if long_arg_eq("call-graph-profile-sort") {
+            call_graph_profile_sort = true;
+        } else if long_arg_eq("no-call-graph-profile-sort") {
+            call_graph_profile_sort = false;
+        } else if long_arg_eq("Map") {
+            map_file = Some(PathBuf::from(
+                input.next().context("Missing argument to -Map")?.as_ref(),
+            ));
+        } else if let Some(rest) = long_arg_split_prefix("Map=") {
+            map_file = Some(PathBuf::from(rest));
+        } else if let Some(rest) = long_arg_split_prefix("map-format=") {
+            map_format = match rest {
+                "text" => MapFormat::Text,
+                "json" => MapFormat::Json,
+                other => bail!("Unsupported --map-format `{other}`"),
+            };
+        } else if long_arg_eq("dependency-file") {
+            dependency_file = Some(PathBuf::from(
+                input
+                    .next()
+                    .context("Missing argument to --dependency-file")?
+                    .as_ref(),
+            ));
+        } else if let Some(rest) = long_arg_split_prefix("dependency-file=") {
+            dependency_file = Some(PathBuf::from(rest));
+        } else if long_arg_eq("reproduce") {
+            reproduce = Some(PathBuf::from(
+                input
+                    .next()
+                    .context("Missing argument to --reproduce")?
+                    .as_ref(),
+            ));
+        } else if let Some(rest) = long_arg_split_prefix("reproduce=") {
+            reproduce = Some(PathBuf::from(rest));
+        } else if long_arg_eq("stats") {
+            stats = Some(None);
+        } else if let Some(rest) = long_arg_split_prefix("stats=") {
+            stats = Some(Some(PathBuf::from(rest)));
+        } else if let Some(rest) = long_arg_split_prefix("why-extract=") {
+            why_extract = Some(PathBuf::from(rest));
+        } else if long_arg_eq("trace-symbol") {
+            trace_symbols.push(
+                input
+                    .next()
+                    .context("Missing argument to --trace-symbol")?
+                    .as_ref()
+                    .to_owned(),
+            );
+        } else if let Some(rest) = long_arg_split_prefix("trace-symbol=") {
+            trace_symbols.push(rest.to_owned());
+        } else if long_arg_eq("defsym") {
+            defsyms.push(parse_defsym(
+                input
+                    .next()
+                    .context("Missing argument to --defsym")?
+                    .as_ref(),
+            )?);
+        } else if let Some(rest) = long_arg_split_prefix("defsym=") {
+            defsyms.push(parse_defsym(rest)?);
+        } else if long_arg_eq("wrap") {
+            wrapped_symbols.push(WrappedSymbol::new(
+                input.next().context("Missing argument to --wrap")?.as_ref(),
+            ));
+        } else if let Some(rest) = long_arg_split_prefix("wrap=") {
+            wrapped_symbols.push(WrappedSymbol::new(rest));
+        } else if long_arg_eq("trace") || arg == "-t" {
+            trace = true;
+        } else if long_arg_eq("warn-unresolved-symbols") {
+            warn_unresolved_symbols = true;
+        } else if long_arg_eq("error-unresolved-symbols") {
+            warn_unresolved_symbols = false;
+        } else if long_arg_eq("allow-multiple-definition") {
+            allow_multiple_definition = true;
+        } else if long_arg_eq("error-limit") {
+            let limit = input.next().context("Missing argument to --error-limit")?;
+            error_limit = parse_error_limit(limit.as_ref())?;
+        } else if let Some(rest) = long_arg_split_prefix("error-limit=") {
+            error_limit = parse_error_limit(rest)?;
+        } else if long_arg_eq("demangle") || long_arg_split_prefix("demangle=").is_some() {
+            // We work out the style from the name, so ignore any style that was given.
+            demangle = true;
+        } else if long_arg_eq("no-demangle") {
+            demangle = false;
+        } else if long_arg_eq("color-diagnostics") {
+            color_diagnostics = ColorChoice::Always;
+        } else if let Some(rest) = long_arg_split_prefix("color-diagnostics=") {
+            color_diagnostics = match rest {
+                "auto" => ColorChoice::Auto,
+                "always" => ColorChoice::Always,
+                "never" => ColorChoice::Never,
+                other => bail!("Unsupported --color-diagnostics `{other}`"),
+            };
+        } else if long_arg_eq("no-color-diagnostics") {
+            color_diagnostics = ColorChoice::Never;
+        } else if let Some(rest) = long_arg_split_prefix("diagnostics-format=") {
+            diagnostics_format = match rest {
+                "text" => DiagnosticsFormat::Text,
+                "json" => DiagnosticsFormat::Json,
+                other => bail!("Unsupported --diagnostics-format `{other}`"),
+            };
+        } else if let Some(rest) = long_arg_split_prefix("duplicate-archive-members=") {
+            duplicate_archive_members = Some(match rest {
+                "dedup" => DuplicateMemberPolicy::Deduplicate,
+                "warn" => DuplicateMemberPolicy::Warn,
+                "error" => DuplicateMemberPolicy::Error,
+                other => bail!("Unsupported --duplicate-archive-members `{other}`"),
+            });
+        } else if let Some(rest) = long_arg_split_prefix("files-from=") {
+            inputs.push(Input {
+                spec: InputSpec::FileList(Box::from(Path::new(rest))),
+                search_first: None,
+                modifiers: *modifier_stack.last().unwrap(),
+            });
+        } else if let Some(rest) = long_arg_split_prefix("sysroot=") {
+            sysroot = Some(Box::from(Path::new(rest)));
+        } else if long_arg_eq("sysroot") {
+            sysroot = Some(Box::from(Path::new(
+                input
+                    .next()
+                    .context("Missing argument to --sysroot")?
+                    .as_ref(),
+            )));
+        } else if long_arg_eq("print-output-summary") {
+            print_output_summary = true;
+        } else if long_arg_eq("print-preemptibility") {
+            print_preemptibility = true;
+        } else if long_arg_eq("record-command-line") {
+            record_command_line = true;
+        } else if long_arg_eq("no-record-command-line") {
+            record_command_line = false;
+        } else if long_arg_eq("skip-if-unchanged") {
+            skip_if_unchanged = Some(ChangeDetection::Metadata);
+        } else if let Some(rest) = long_arg_split_prefix("skip-if-unchanged=") {
+            skip_if_unchanged = Some(match rest {
+                "metadata" => ChangeDetection::Metadata,
+                "contents" => ChangeDetection::Contents,
+                other => bail!("Unsupported --skip-if-unchanged `{other}`"),
+            });
+        } else if long_arg_eq("emit-symbols-only") {
+            emit_symbols_only = true;
+        } else if long_arg_eq("warn-execstack") {
+            warn_execstack = true;
+        } else if long_arg_eq("no-warn-execstack") {
+            warn_execstack = false;
+        } else if long_arg_eq("error-execstack") {
+            error_execstack = true;
+        } else if long_arg_eq("no-error-execstack") {
+            error_execstack = false;
+        } else if long_arg_eq("warn-rwx-segments") {
+            warn_rwx_segments = true;
+        } else if long_arg_eq("no-warn-rwx-segments") {
+            warn_rwx_segments = false;
+        } else if long_arg_eq("fatal-warnings") {
+            fatal_warnings = true;
+        } else if long_arg_eq("no-fatal-warnings") {
+            fatal_warnings = false;
+        } else if let Some(rest) = long_arg_split_prefix("suppress-warnings-from=") {
+            suppress_warnings_from.push(WarningSuppression::parse(rest)?);
+        } else if long_arg_eq("explain-io") {
+            explain_io = true;
         } else if long_arg_eq("verbose-gc-stats") {
             verbose_gc_stats = true;
         } else if let Some(rest) = long_arg_split_prefix("debug-address=") {
@@ -363,11 +1404,23 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
                 bail!("Mixing of @{{filename}} and regular arguments isn't supported");
             }
             return parse_from_argument_file(Path::new(path));
+        } else if long_arg_eq("query-defines") {
+            return Ok(Action::QueryDefines(QueryDefines::from_args(input)?));
         } else if long_arg_eq("help") {
             bail!("Sorry, help isn't implemented yet");
         } else if strip_option(arg)
             .is_some_and(|stripped_arg| IGNORED_FLAGS.contains(&stripped_arg))
         {
+        } else if let Some(rest) = arg.strip_prefix("-h") {
+            // The joined form of `-h`. Checked late so that it doesn't shadow long options that
+            // start with `h`.
+            soname = Some(rest.to_owned());
+        } else if let Some(rest) = arg.strip_prefix("-e") {
+            // The joined form of `-e`, which like `-h`, is checked late.
+            entry = Some(rest.to_owned());
+        } else if let Some(rest) = arg.strip_prefix("-o") {
+            // The joined form of `-o`, which is also checked late so as not to shadow `-omagic`.
+            output = Some(Arc::from(Path::new(rest)));
         } else if arg.starts_with('-') {
             unrecognised.push(format!("`{arg}`"));
         } else {
@@ -383,6 +1436,32 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
         bail!("Unrecognised argument(s): {}", unrecognised.join(" "));
     }
     let num_threads = num_threads.unwrap_or_else(crate::threading::available_parallelism);
+    // Compiler drivers pass LLVM's LTO options as plugin options even when they don't pass a
+    // plugin, as they do for lld. We use them if we do LTO ourselves. Explicit flags take
+    // precedence.
+    let mut lto_cpu = None;
+    for option in &plugin_opts {
+        if let Some(level) = option.strip_prefix('O') {
+            lto_opt_level = lto_opt_level.or(Some(parse_lto_opt_level(level)?));
+        } else if let Some(cpu) = option.strip_prefix("mcpu=") {
+            lto_cpu = Some(cpu.to_owned());
+        } else if let Some(jobs) = option.strip_prefix("jobs=") {
+            thinlto_jobs = thinlto_jobs.or(Some(
+                NonZeroUsize::try_from(jobs.parse::<usize>()?)
+                    .context("-plugin-opt=jobs must be at least 1")?,
+            ));
+        } else if let Some(dir) = option.strip_prefix("cache-dir=") {
+            thinlto_cache_dir = thinlto_cache_dir.or(Some(PathBuf::from(dir)));
+        } else if option == "thinlto-index-only" {
+            thinlto_index_only = thinlto_index_only.or(Some(None));
+        } else if let Some(list) = option.strip_prefix("thinlto-index-only=") {
+            thinlto_index_only = thinlto_index_only.or(Some(Some(PathBuf::from(list))));
+        }
+    }
+    // Like GNU ld, a PIE is dynamically linked unless we're told that there's no dynamic linker.
+    if relocation_model == RelocationModel::Relocatable && !is_static && !dynamic_linker_given {
+        is_dynamic_executable = true;
+    }
     let output_kind = output_kind.unwrap_or({
         if is_dynamic_executable {
             OutputKind::DynamicExecutable(relocation_model)
@@ -390,16 +1469,69 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
             OutputKind::StaticExecutable(relocation_model)
         }
     });
+    let emulation = emulation
+        .or_else(|| infer_emulation(&inputs))
+        .unwrap_or_else(default_emulation);
+    let diagnostics = Diagnostics::new(color_diagnostics, diagnostics_format);
+    if let Some(dynamic_linker) = dynamic_linker.as_deref() {
+        check_dynamic_linker(dynamic_linker, &diagnostics);
+    } else if !dynamic_linker_given && matches!(output_kind, OutputKind::DynamicExecutable(_)) {
+        dynamic_linker = Some(default_dynamic_linker(emulation, sysroot.as_deref()));
+    }
+    // -L options can come before --sysroot, so we apply the sysroot once we've seen everything.
+    let lib_search_path = lib_search_path
+        .into_iter()
+        .map(|dir: Box<Path>| {
+            dir.to_str()
+                .and_then(|dir| sysroot_prefixed(sysroot.as_deref(), dir))
+                .map_or(dir, Box::from)
+        })
+        .collect();
+    let mut max_page_size = max_page_size.unwrap_or(emulation.max_page_size);
+    let mut common_page_size = common_page_size.unwrap_or(crate::alignment::PAGE);
+    if magic != Magic::Zmagic {
+        // Nothing gets page aligned, so there are no pages for code or RELRO to have to themselves.
+        max_page_size = crate::alignment::MIN;
+        common_page_size = crate::alignment::MIN;
+        separate_code = false;
+        relro = false;
+    }
+    if common_page_size > max_page_size {
+        warning!(
+            diagnostics,
+            "-z common-page-size=0x{:x} is larger than -z max-page-size=0x{:x}",
+            common_page_size.value(),
+            max_page_size.value()
+        );
+        common_page_size = max_page_size;
+    }
+    if let Some(image_base) = image_base {
+        if image_base % max_page_size.value() != 0 {
+            bail!(
+                "--image-base 0x{image_base:x} isn't a multiple of the maximum page size 0x{:x}",
+                max_page_size.value()
+            );
+        }
+    }
     save_dir.finish()?;
     if let Some(a) = action {
         return Ok(a);
     }
+    let output: Arc<Path> = output.unwrap_or_else(|| Arc::from(Path::new("a.out")));
+    let separate_debug_file = separate_debug_file.map(|path| {
+        path.unwrap_or_else(|| {
+            let mut default = output.as_os_str().to_owned();
+            default.push(".dbg");
+            PathBuf::from(default)
+        })
+    });
     Ok(Action::Link(Args {
         lib_search_path,
         inputs,
-        output: output.unwrap_or_else(|| Arc::from(Path::new("a.out"))),
+        output,
         dynamic_linker,
         output_kind,
+        emulation,
         time_phases,
         num_threads,
         strip_all,
@@ -417,33 +1549,280 @@ pub(crate) fn parse<S: AsRef<str>, I: Iterator<Item = S>>(mut input: I) -> Resul
         write_gc_stats,
         gc_stats_ignore,
         verbose_gc_stats,
+        rpath: join_rpaths(&rpaths),
         rpaths,
+        enable_new_dtags,
+        rpath_link,
         soname,
+        filter,
+        auxiliary,
+        plugin,
+        plugin_opts,
+        fat_lto_objects,
+        thinlto_index_only,
+        thinlto_cache_dir,
+        thinlto_jobs,
+        lto_opt_level,
+        lto_cpu,
+        entry: entry.unwrap_or_else(|| "_start".to_owned()),
+        init,
+        fini,
+        image_base,
+        section_starts,
         print_allocations: std::env::var("WILD_PRINT_ALLOCATIONS")
             .ok()
             .and_then(|s| s.parse().ok())
             .map(FileId::from_encoded),
         files_per_group: max_files_per_group,
         execstack,
+        warn_execstack,
+        error_execstack,
+        force_x86_features,
+        cet_report,
+        warn_rwx_segments,
+        relro,
+        bind_now,
+        max_page_size,
+        common_page_size,
+        separate_code,
+        rosegment,
+        magic,
+        text,
+        dt_flags_1,
+        copy_relocations,
+        pack_relative_relocs,
+        relax,
+        symbolic,
+        gc_keep_exported,
+        ctf_variables,
+        dead_reloc_in_nonalloc,
+        hash_size,
+        hash_bloom_words,
+        build_id,
+        separate_debug_file,
+        explain_io,
+        section_order,
+        icf,
+        print_icf_sections,
+        keep_unique,
+        call_graph_profile_sort,
+        keep_text_section_prefix,
+        map_file,
+        map_format,
+        dependency_file,
+        reproduce,
+        stats,
+        why_extract,
+        trace_symbols,
+        defsyms,
+        wrapped_symbols,
+        trace,
+        warn_unresolved_symbols,
+        allow_multiple_definition,
+        error_limit,
+        demangle,
+        diagnostics: Arc::new(diagnostics),
+        duplicate_archive_members,
+        fatal_warnings,
+        suppress_warnings_from,
+        sysroot,
+        print_output_summary,
+        command_line,
+        record_command_line,
+        skip_if_unchanged,
+        emit_symbols_only,
+        print_preemptibility,
     }))
 }
 
-fn parse_from_argument_file(path: &Path) -> Result<Action> {
-    let contents = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read arguments from file `{}`", path.display()))?;
-    parse(arguments_from_string(&contents)?.into_iter())
+/// A target that can be selected with `-m`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Emulation {
+    pub(crate) name: &'static str,
+
+    /// The `e_machine` of objects for this target.
+    pub(crate) machine: u16,
+
+    /// The default for `-z max-page-size`.
+    max_page_size: Alignment,
+
+    /// The dynamic loaders to use when we're not told which one to use. Where there's more than
+    /// one, e.g. glibc and musl, we use the first one that exists, or the first one if none exist.
+    dynamic_linkers: &'static [&'static str],
 }
 
-impl Args {
-    pub(crate) fn setup_thread_pool(&self) -> Result {
-        crate::threading::ThreadPoolBuilder::new()
+const EMULATIONS: &[Emulation] = &[
+    Emulation {
+        name: "elf_x86_64",
+        machine: object::elf::EM_X86_64,
+        max_page_size: crate::alignment::PAGE,
+        dynamic_linkers: &["/lib64/ld-linux-x86-64.so.2", "/lib/ld-musl-x86_64.so.1"],
+    },
+    Emulation {
+        name: "aarch64linux",
+        machine: object::elf::EM_AARCH64,
+        max_page_size: Alignment { exponent: 16 },
+        dynamic_linkers: &["/lib/ld-linux-aarch64.so.1", "/lib/ld-musl-aarch64.so.1"],
+    },
+    Emulation {
+        name: "elf64lriscv",
+        machine: object::elf::EM_RISCV,
+        max_page_size: crate::alignment::PAGE,
+        dynamic_linkers: &[
+            "/lib/ld-linux-riscv64-lp64d.so.1",
+            "/lib/ld-musl-riscv64.so.1",
+        ],
+    },
+    Emulation {
+        name: "elf_i386",
+        machine: object::elf::EM_386,
+        max_page_size: crate::alignment::PAGE,
+        dynamic_linkers: &["/lib/ld-linux.so.2", "/lib/ld-musl-i386.so.1"],
+    },
+];
+
+fn find_emulation(name: &str) -> Result<&'static Emulation> {
+    let Some(emulation) = EMULATIONS.iter().find(|emulation| emulation.name == name) else {
+        let names: Vec<&str> = EMULATIONS.iter().map(|emulation| emulation.name).collect();
+        bail!(
+            "Unknown emulation `{name}`. Supported emulations: {}",
+            names.join(" ")
+        );
+    };
+    Ok(emulation)
+}
+
+/// The emulation that we assume if `-m` isn't given and we can't infer one from our inputs.
+fn default_emulation() -> &'static Emulation {
+    let name = if cfg!(target_arch = "aarch64") {
+        "aarch64linux"
+    } else if cfg!(target_arch = "riscv64") {
+        "elf64lriscv"
+    } else {
+        "elf_x86_64"
+    };
+    find_emulation(name).unwrap()
+}
+
+/// Returns the emulation for the machine of the first input that's an ELF file, like GNU ld does
+/// when `-m` isn't given. Inputs that we can't read, or that are archives or linker scripts, are
+/// skipped. Reading them properly is left to when we load our inputs.
+fn infer_emulation(inputs: &[Input]) -> Option<&'static Emulation> {
+    use std::io::Read as _;
+
+    inputs.iter().find_map(|input| {
+        let InputSpec::File(path) = &input.spec else {
+            return None;
+        };
+        let mut header = [0; 20];
+        std::fs::File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .ok()?;
+        header
+            .starts_with(&object::elf::ELFMAG)
+            .then(|| emulation_for_header(&header))
+    })?
+}
+
+/// Returns the emulation for the ELF file whose first 20 bytes are `header`.
+fn emulation_for_header(header: &[u8; 20]) -> Option<&'static Emulation> {
+    let machine = match header[5] {
+        object::elf::ELFDATA2MSB => u16::from_be_bytes([header[18], header[19]]),
+        _ => u16::from_le_bytes([header[18], header[19]]),
+    };
+    EMULATIONS
+        .iter()
+        .find(|emulation| emulation.machine == machine)
+}
+
+/// Selects the PT_INTERP path for a dynamically linked executable when `--dynamic-linker` wasn't
+/// given.
+fn default_dynamic_linker(emulation: &Emulation, sysroot: Option<&Path>) -> Box<Path> {
+    let candidates = emulation.dynamic_linkers;
+    let path = candidates
+        .iter()
+        .find(|path| in_sysroot(sysroot, path).exists())
+        .unwrap_or(&candidates[0]);
+    Box::from(Path::new(path))
+}
+
+/// Like GNU ld, we combine all `-rpath` options into a single entry. Paths are written verbatim, so
+/// `$ORIGIN` and the like are left for the dynamic loader to expand.
+fn join_rpaths(rpaths: &[String]) -> Option<String> {
+    let mut seen = HashSet::new();
+    let joined = rpaths
+        .iter()
+        .flat_map(|r| r.split(':'))
+        .filter(|dir| !dir.is_empty() && seen.insert(*dir))
+        .collect::<Vec<_>>()
+        .join(":");
+    (!joined.is_empty()).then_some(joined)
+}
+
+/// Parses the value of `--dynamic-linker`. The special value "none" requests a dynamically
+/// relocated output without PT_INTERP, e.g. for use with a custom loader.
+fn parse_dynamic_linker(value: &str) -> Option<Box<Path>> {
+    (value != "none").then(|| Box::from(Path::new(value)))
+}
+
+/// Warns about interpreter paths that are unlikely to work. We don't require the path to exist,
+/// since the output may well be intended to run on a different system.
+fn check_dynamic_linker(path: &Path, diagnostics: &Diagnostics) {
+    if path.as_os_str().is_empty() {
+        warning!(
+            diagnostics,
+            "Empty --dynamic-linker path. Use `--dynamic-linker=none` to omit PT_INTERP"
+        );
+    } else if path.is_relative() {
+        warning!(
+            diagnostics,
+            "--dynamic-linker `{}` is relative, so the kernel will resolve it against the working \
+            directory of whoever runs the program",
+            path.display()
+        );
+    }
+}
+
+fn parse_lto_opt_level(level: &str) -> Result<u8> {
+    match level.parse() {
+        Ok(level @ 0..=3) => Ok(level),
+        _ => bail!("Invalid LTO optimisation level `{level}`, expected 0 to 3"),
+    }
+}
+
+fn parse_from_argument_file(path: &Path) -> Result<Action> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read arguments from file `{}`", path.display()))?;
+    parse(arguments_from_string(&contents)?.into_iter())
+}
+
+impl Args {
+    /// Sets up the global thread pool. It can only be set up once per process, so if an earlier
+    /// link or the program that embeds us already did so, we use that pool as it is.
+    pub(crate) fn setup_thread_pool(&self) {
+        let _ = crate::threading::ThreadPoolBuilder::new()
             .num_threads(self.num_threads.get())
-            .build_global()?;
-        Ok(())
+            .build_global();
+    }
+
+    /// Returns the name that an undefined reference to `name` from one of our input objects should
+    /// resolve to, taking `--wrap` into account.
+    pub(crate) fn wrapped_name<'a>(&'a self, name: &'a [u8]) -> &'a [u8] {
+        for wrapped in &self.wrapped_symbols {
+            if name == wrapped.name.as_bytes() {
+                return wrapped.wrap_name.as_bytes();
+            }
+            if name == wrapped.real_name.as_bytes() {
+                return wrapped.name.as_bytes();
+            }
+        }
+        name
     }
 
     pub(crate) fn base_address(&self) -> u64 {
-        if self.is_relocatable() {
+        if let Some(image_base) = self.image_base {
+            image_base
+        } else if self.is_relocatable() {
             0
         } else {
             crate::elf::NON_PIE_START_MEM_ADDRESS
@@ -498,6 +1877,50 @@ impl Args {
         self.output_kind.needs_dynamic()
     }
 
+    /// Returns whether calls to functions in shared objects should go via PLT entries that the
+    /// dynamic loader binds on first use. Our lazy PLT entries don't start with `endbr64`, so `-z
+    /// ibt` implies `-z now`.
+    pub(crate) fn lazy_binding(&self) -> bool {
+        !self.bind_now
+            && !self.output_kind.is_static_executable()
+            && self.force_x86_features & object::elf::GNU_PROPERTY_X86_FEATURE_1_IBT == 0
+    }
+
+    /// Returns the name to put in DT_SONAME. Like GNU ld, we only write DT_SONAME for shared
+    /// objects, even if `-soname` was passed when linking an executable. Without a DT_SONAME,
+    /// anything linking against our output will refer to it by the name that it was given on the
+    /// command line.
+    pub(crate) fn dt_soname(&self) -> Option<&str> {
+        if self.output_kind != OutputKind::SharedObject {
+            return None;
+        }
+        self.soname.as_deref()
+    }
+
+    /// Returns the DT_FILTER and DT_AUXILIARY entries to write. As with DT_SONAME, these only mean
+    /// something in shared objects, so we ignore `-F` and `-f` for other kinds of output.
+    pub(crate) fn dt_filters(&self) -> impl Iterator<Item = (u32, &str)> {
+        let is_shared = self.output_kind == OutputKind::SharedObject;
+        let filter = self
+            .filter
+            .iter()
+            .map(|name| (object::elf::DT_FILTER, name.as_str()));
+        let auxiliary = self
+            .auxiliary
+            .iter()
+            .map(|name| (object::elf::DT_AUXILIARY, name.as_str()));
+        filter.chain(auxiliary).filter(move |_| is_shared)
+    }
+
+    /// Returns the dynamic tag to use for our rpath.
+    pub(crate) fn rpath_tag(&self) -> u32 {
+        if self.enable_new_dtags {
+            object::elf::DT_RUNPATH
+        } else {
+            object::elf::DT_RPATH
+        }
+    }
+
     #[allow(dead_code)]
     pub(crate) fn should_debug_address(&self, address: u64) -> bool {
         self.debug_address
@@ -511,6 +1934,25 @@ impl Args {
         )
     }
 
+    /// Returns where `path` should be looked for if the sysroot affects it. That's the case if it
+    /// starts with `=` or `$SYSROOT`, or if it's absolute and came from a linker script that's
+    /// within the sysroot, in which case `script_dir` is the directory containing that script.
+    pub(crate) fn path_in_sysroot(
+        &self,
+        path: &Path,
+        script_dir: Option<&Path>,
+    ) -> Option<PathBuf> {
+        let path = path.to_str()?;
+        if let Some(prefixed) = sysroot_prefixed(self.sysroot.as_deref(), path) {
+            return Some(prefixed);
+        }
+        let sysroot = self.sysroot.as_deref()?;
+        if Path::new(path).is_absolute() && script_dir.is_some_and(|dir| dir.starts_with(sysroot)) {
+            return Some(in_sysroot(Some(sysroot), path));
+        }
+        None
+    }
+
     pub(crate) fn trace_span_for_file(
         &self,
         file_id: FileId,
@@ -520,6 +1962,112 @@ impl Args {
     }
 }
 
+/// If `path` starts with `=` or `$SYSROOT`, returns it with that prefix replaced by the sysroot.
+fn sysroot_prefixed(sysroot: Option<&Path>, path: &str) -> Option<PathBuf> {
+    let rest = path
+        .strip_prefix('=')
+        .or_else(|| path.strip_prefix("$SYSROOT"))?;
+    Some(in_sysroot(sysroot, rest))
+}
+
+fn in_sysroot(sysroot: Option<&Path>, path: &str) -> PathBuf {
+    match sysroot {
+        // Joining an absolute path would replace the sysroot, so strip any leading slashes.
+        Some(sysroot) => sysroot.join(path.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+fn parse_page_size(s: &str) -> Result<Alignment> {
+    Alignment::page_size(parse_number(s)?)
+}
+
+fn parse_error_limit(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .with_context(|| format!("Invalid --error-limit `{value}`"))
+}
+
+fn parse_build_id(style: &str) -> Result<Option<BuildId>> {
+    Ok(Some(match style {
+        "none" => return Ok(None),
+        "fast" => BuildId::Fast,
+        "md5" => BuildId::Md5,
+        "sha1" | "tree" => BuildId::Sha1,
+        "uuid" => BuildId::Uuid,
+        _ => {
+            let Some(hex) = style.strip_prefix("0x").or(style.strip_prefix("0X")) else {
+                bail!("Unsupported --build-id style `{style}`");
+            };
+            ensure!(
+                !hex.is_empty() && hex.len() % 2 == 0 && hex.is_ascii(),
+                "--build-id=0x... requires a non-empty, even number of hex digits"
+            );
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .with_context(|| format!("Invalid hex in --build-id={style}"))?;
+            BuildId::Hex(bytes)
+        }
+    }))
+}
+
+/// Parses an address given to an option like `-Ttext`. Like GNU ld, we treat it as hexadecimal,
+/// whether or not it has a `0x` prefix.
+fn parse_address(s: &str) -> Result<u64> {
+    let digits = s.strip_prefix("0x").or(s.strip_prefix("0X")).unwrap_or(s);
+    u64::from_str_radix(digits, 16).with_context(|| format!("Invalid address `{s}`"))
+}
+
+/// Parses the argument to `--section-start`, which has the form `<section>=<address>`.
+fn parse_section_start(s: &str) -> Result<(String, u64)> {
+    let (name, address) = s
+        .split_once('=')
+        .with_context(|| format!("Expected <section>=<address>, but got `{s}`"))?;
+    Ok((name.to_owned(), parse_address(address)?))
+}
+
+/// Parses the argument to `--defsym`. Unlike GNU ld, we don't support arbitrary expressions, just
+/// a number or the name of another symbol.
+fn parse_defsym(arg: &str) -> Result<Defsym> {
+    let Some((name, value)) = arg.split_once('=') else {
+        bail!("Invalid --defsym `{arg}`, expected <symbol>=<value>");
+    };
+    let name = name.trim();
+    let value = value.trim();
+    ensure!(
+        !name.is_empty(),
+        "Invalid --defsym `{arg}`, missing symbol name"
+    );
+    let value = if let Ok(number) = parse_number(value) {
+        DefsymValue::Absolute(number)
+    } else if !value.is_empty()
+        && !value.starts_with(|c: char| c.is_ascii_digit())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+    {
+        DefsymValue::Symbol(value.to_owned())
+    } else {
+        bail!("Unsupported --defsym expression `{value}`, only numbers and symbol names are supported");
+    };
+    Ok(Defsym {
+        name: name.to_owned(),
+        value,
+    })
+}
+
+impl WrappedSymbol {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            wrap_name: format!("__wrap_{name}"),
+            real_name: format!("__real_{name}"),
+        }
+    }
+}
+
 fn parse_number(s: &str) -> Result<u64> {
     if let Some(s) = s.strip_prefix("0x") {
         Ok(u64::from_str_radix(s, 16)?)
@@ -528,11 +2076,46 @@ fn parse_number(s: &str) -> Result<u64> {
     }
 }
 
+impl DeadRelocRule {
+    fn parse(rule: &str) -> Result<DeadRelocRule> {
+        let Some((pattern, value)) = rule.rsplit_once('=') else {
+            bail!("Expected <section pattern>=<value>");
+        };
+        if pattern.trim_end_matches('*').contains('*') {
+            bail!("Only a trailing `*` is supported in section patterns");
+        }
+        Ok(DeadRelocRule {
+            section_pattern: pattern.to_owned(),
+            value: parse_number(value)?,
+        })
+    }
+
+    pub(crate) fn matches(&self, section_name: &[u8]) -> bool {
+        match self.section_pattern.strip_suffix('*') {
+            Some(prefix) => section_name.starts_with(prefix.as_bytes()),
+            None => section_name == self.section_pattern.as_bytes(),
+        }
+    }
+}
+
+impl BuildId {
+    /// Returns the size in bytes of the build ID.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            BuildId::Fast => 8,
+            BuildId::Md5 | BuildId::Uuid => 16,
+            BuildId::Sha1 => 20,
+            BuildId::Hex(bytes) => bytes.len(),
+        }
+    }
+}
+
 impl Default for Modifiers {
     fn default() -> Self {
         Self {
             as_needed: false,
             allow_shared: true,
+            copy_dt_needed: false,
         }
     }
 }
@@ -640,7 +2223,21 @@ fn arguments_from_string(input: &str) -> Result<Vec<String>> {
 mod tests {
     use super::IGNORED_FLAGS;
     use crate::args::Action;
+    use crate::args::BuildId;
+    use crate::args::CetReport;
+    use crate::args::ChangeDetection;
+    use crate::args::ColorChoice;
+    use crate::args::Defsym;
+    use crate::args::DefsymValue;
+    use crate::args::DiagnosticsFormat;
+    use crate::args::Icf;
     use crate::args::InputSpec;
+    use crate::args::Magic;
+    use crate::args::MapFormat;
+    use crate::args::OutputKind;
+    use crate::args::Relax;
+    use crate::args::RelocationModel;
+    use crate::args::Symbolic;
     use itertools::Itertools;
     use std::num::NonZeroUsize;
     use std::path::Path;
@@ -752,7 +2349,7 @@ mod tests {
             args.inputs
                 .iter()
                 .filter_map(|i| match &i.spec {
-                    InputSpec::File(_) => None,
+                    InputSpec::File(_) | InputSpec::FileList(_) => None,
                     InputSpec::Lib(lib_name) => Some(lib_name.as_ref()),
                 })
                 .collect_vec(),
@@ -772,6 +2369,1207 @@ mod tests {
         assert_eq!(args.num_threads, NonZeroUsize::new(1).unwrap());
     }
 
+    #[test]
+    fn test_copy_dt_needed_entries() {
+        let Action::Link(args) = super::parse(
+            [
+                "-la",
+                "--copy-dt-needed-entries",
+                "-lb",
+                "--no-copy-dt-needed-entries",
+                "-lc",
+                "-rpath-link=/x:/y",
+            ]
+            .iter(),
+        )
+        .unwrap() else {
+            panic!("Unexpected action");
+        };
+        assert_eq!(
+            args.inputs
+                .iter()
+                .map(|i| i.modifiers.copy_dt_needed)
+                .collect_vec(),
+            &[false, true, false]
+        );
+        assert_contains(&args.rpath_link, "/x");
+        assert_contains(&args.rpath_link, "/y");
+    }
+
+    #[test]
+    fn test_soname() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["-shared", "-h", "libfoo.so.1", "a.o"]);
+        assert_eq!(args.dt_soname(), Some("libfoo.so.1"));
+        let args = parse(&["-shared", "-hlibfoo.so.2", "-hash-style=gnu", "a.o"]);
+        assert_eq!(args.dt_soname(), Some("libfoo.so.2"));
+        let args = parse(&["-shared", "a.o"]);
+        assert_eq!(args.dt_soname(), None);
+        let args = parse(&["-soname=libfoo.so.1", "a.o"]);
+        assert_eq!(args.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(args.dt_soname(), None);
+    }
+
+    #[test]
+    fn test_init_fini() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["-shared", "-init", "my_init", "--fini=my_fini", "a.o"]);
+        assert_eq!(args.init.as_deref(), Some("my_init"));
+        assert_eq!(args.fini.as_deref(), Some("my_fini"));
+        let args = parse(&["-shared", "a.o"]);
+        assert_eq!(args.init, None);
+        assert_eq!(args.fini, None);
+    }
+
+    #[test]
+    fn test_entry() {
+        let entry = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.entry,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(entry(&["a.o"]), "_start");
+        assert_eq!(entry(&["-e", "foo", "a.o"]), "foo");
+        assert_eq!(entry(&["-efoo", "a.o"]), "foo");
+        assert_eq!(entry(&["--entry", "foo", "a.o"]), "foo");
+        assert_eq!(entry(&["--entry=foo", "a.o"]), "foo");
+        assert_eq!(
+            entry(&["--eh-frame-hdr", "-export-dynamic", "a.o"]),
+            "_start"
+        );
+    }
+
+    #[test]
+    fn test_joined_output() {
+        let output = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.output,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(&*output(&["-o", "foo", "a.o"]), Path::new("foo"));
+        assert_eq!(&*output(&["-ofoo", "a.o"]), Path::new("foo"));
+        assert_eq!(&*output(&["-omagic", "-o", "foo", "a.o"]), Path::new("foo"));
+    }
+
+    #[test]
+    fn test_plugin() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&[
+            "-plugin",
+            "/usr/lib/liblto_plugin.so",
+            "-plugin-opt=-fresolution=a.res",
+            "-plugin-opt",
+            "-pass-through=-lc",
+            "a.o",
+        ]);
+        assert_eq!(
+            args.plugin.as_deref(),
+            Some(Path::new("/usr/lib/liblto_plugin.so"))
+        );
+        assert_eq!(
+            args.plugin_opts,
+            ["-fresolution=a.res", "-pass-through=-lc"]
+        );
+        assert_eq!(parse(&["a.o"]).plugin, None);
+        assert!(parse(&["--fat-lto-objects", "a.o"]).fat_lto_objects);
+        assert!(!parse(&["--fat-lto-objects", "--no-fat-lto-objects", "a.o"]).fat_lto_objects);
+    }
+
+    #[test]
+    fn test_thinlto_options() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&[
+            "--thinlto-index-only=files.txt",
+            "--thinlto-cache-dir=cache",
+            "--thinlto-jobs=3",
+            "a.o",
+        ]);
+        assert_eq!(
+            args.thinlto_index_only,
+            Some(Some(PathBuf::from("files.txt")))
+        );
+        assert_eq!(args.thinlto_cache_dir.as_deref(), Some(Path::new("cache")));
+        assert_eq!(args.thinlto_jobs, NonZeroUsize::new(3));
+        let args = parse(&["--thinlto-index-only", "a.o"]);
+        assert_eq!(args.thinlto_index_only, Some(None));
+        assert_eq!(args.thinlto_jobs, None);
+        assert!(parse(&["--thinlto-jobs=all", "a.o"]).thinlto_jobs.is_some());
+        assert!(super::parse(["--thinlto-jobs=0", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_lto_compatibility_flags() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        // What clang passes when it expects to be talking to lld.
+        let args = parse(&[
+            "-plugin-opt=mcpu=x86-64",
+            "-plugin-opt=O3",
+            "-plugin-opt=thinlto",
+            "-plugin-opt=jobs=4",
+            "-flto=thin",
+            "a.o",
+        ]);
+        assert_eq!(args.plugin, None);
+        assert_eq!(args.lto_cpu.as_deref(), Some("x86-64"));
+        assert_eq!(args.lto_opt_level, Some(3));
+        assert_eq!(args.thinlto_jobs, NonZeroUsize::new(4));
+        let args = parse(&["--lto-O1", "-plugin-opt=O3", "-flto", "-fno-lto", "a.o"]);
+        assert_eq!(args.lto_opt_level, Some(1));
+        assert!(super::parse(["--lto-O4", "a.o"].iter()).is_err());
+        // What GCC passes, which only means something to its plugin.
+        let args = parse(&[
+            "-plugin",
+            "/usr/lib/gcc/x86_64-linux-gnu/12/liblto_plugin.so",
+            "-plugin-opt=/usr/lib/gcc/x86_64-linux-gnu/12/lto-wrapper",
+            "-plugin-opt=-fresolution=/tmp/cc.res",
+            "-plugin-opt=-pass-through=-lc",
+            "a.o",
+        ]);
+        assert_eq!(args.lto_opt_level, None);
+        assert_eq!(args.thinlto_index_only, None);
+    }
+
+    #[test]
+    fn test_section_starts() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&[
+            "-Ttext",
+            "0x500000",
+            "-Tdata=600000",
+            "--section-start=.foo=0x700000",
+            "--section-start",
+            ".bar=800000",
+            "--image-base=0x200000",
+            "a.o",
+        ]);
+        assert_eq!(
+            args.section_starts,
+            [
+                (".text".to_owned(), 0x500000),
+                (".data".to_owned(), 0x600000),
+                (".foo".to_owned(), 0x700000),
+                (".bar".to_owned(), 0x800000),
+            ]
+        );
+        assert_eq!(args.base_address(), 0x200000);
+        assert_eq!(
+            parse(&["-pie", "-Ttext-segment=0x10000", "a.o"]).base_address(),
+            0x10000
+        );
+        assert!(super::parse(["--image-base=0x1234", "a.o"].iter()).is_err());
+        assert!(super::parse(["-Tbss=xyz", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_filters() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&[
+            "-shared",
+            "-F",
+            "libc.so.6",
+            "-f",
+            "liba.so",
+            "--auxiliary=libb.so",
+            "a.o",
+        ]);
+        assert_eq!(
+            args.dt_filters().collect::<Vec<_>>(),
+            [
+                (object::elf::DT_FILTER, "libc.so.6"),
+                (object::elf::DT_AUXILIARY, "liba.so"),
+                (object::elf::DT_AUXILIARY, "libb.so"),
+            ]
+        );
+        let args = parse(&["--filter=libc.so.6", "a.o"]);
+        assert_eq!(args.dt_filters().count(), 0);
+    }
+
+    #[test]
+    fn test_rpath() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&[
+            "-rpath",
+            "$ORIGIN/../lib",
+            "-rpath=/a:/b",
+            "-rpath",
+            "/a",
+            "a.o",
+        ]);
+        assert_eq!(args.rpath.as_deref(), Some("$ORIGIN/../lib:/a:/b"));
+        assert_eq!(args.rpath_tag(), object::elf::DT_RUNPATH);
+        let args = parse(&["--disable-new-dtags", "-rpath", "/a", "a.o"]);
+        assert_eq!(args.rpath_tag(), object::elf::DT_RPATH);
+        let args = parse(&["a.o"]);
+        assert_eq!(args.rpath, None);
+    }
+
+    #[test]
+    fn test_lazy_binding() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        assert!(!parse(&["-pie", "a.o"]).lazy_binding());
+        assert!(parse(&["-pie", "-z", "lazy", "a.o"]).lazy_binding());
+        assert!(parse(&["-shared", "-zlazy", "a.o"]).lazy_binding());
+        assert!(!parse(&["-pie", "-z", "lazy", "-z", "now", "a.o"]).lazy_binding());
+        assert!(!parse(&["-static", "-z", "lazy", "a.o"]).lazy_binding());
+        assert!(!parse(&["-pie", "-z", "lazy", "-z", "ibt", "a.o"]).lazy_binding());
+    }
+
+    #[test]
+    fn test_relax() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        assert_eq!(parse(&["a.o"]).relax, Relax::ALL);
+        assert_eq!(parse(&["--no-relax", "a.o"]).relax, Relax::NONE);
+        assert_eq!(parse(&["--no-relax", "--relax", "a.o"]).relax, Relax::ALL);
+        assert_eq!(
+            parse(&["--no-relax=got,tls", "a.o"]).relax,
+            Relax {
+                got: false,
+                plt: true,
+                tls: false,
+            }
+        );
+        assert_eq!(
+            parse(&["--no-relax", "--relax=plt", "a.o"]).relax,
+            Relax {
+                got: false,
+                plt: true,
+                tls: false,
+            }
+        );
+        assert!(super::parse(["--no-relax=calls", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_symbolic() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        assert_eq!(parse(&["-shared", "a.o"]).symbolic, Symbolic::None);
+        assert_eq!(
+            parse(&["-shared", "-Bsymbolic", "a.o"]).symbolic,
+            Symbolic::All
+        );
+        assert_eq!(
+            parse(&["-shared", "-Bsymbolic", "--Bsymbolic-functions", "a.o"]).symbolic,
+            Symbolic::Functions
+        );
+        assert_eq!(
+            parse(&["-shared", "-Bsymbolic", "-Bno-symbolic", "a.o"]).symbolic,
+            Symbolic::None
+        );
+    }
+
+    #[test]
+    fn test_cet() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["a.o"]);
+        assert_eq!(args.force_x86_features, 0);
+        assert_eq!(args.cet_report, CetReport::None);
+        let args = parse(&["-z", "ibt", "-zshstk", "-z", "cet-report=warning", "a.o"]);
+        assert_eq!(
+            args.force_x86_features,
+            object::elf::GNU_PROPERTY_X86_FEATURE_1_IBT
+                | object::elf::GNU_PROPERTY_X86_FEATURE_1_SHSTK
+        );
+        assert_eq!(args.cet_report, CetReport::Warning);
+        assert_eq!(
+            parse(&["-z", "cet-report=error", "a.o"]).cet_report,
+            CetReport::Error
+        );
+        assert!(super::parse(["-z", "cet-report=loud", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_aarch64_errata() {
+        for option in ["--fix-cortex-a53-843419", "--fix-cortex-a53-835769"] {
+            assert!(super::parse([option, "a.o"].iter())
+                .is_err_and(|error| error.to_string().contains("AArch64")));
+        }
+    }
+
+    #[test]
+    fn test_aarch64_z_options() {
+        for option in ["bti", "force-bti", "pac-plt", "bti-report=error"] {
+            assert!(super::parse(["-z", option, "a.o"].iter())
+                .is_err_and(|error| error.to_string().contains("AArch64")));
+        }
+        assert!(super::parse(["-zpac-plt", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_execstack() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["a.o"]);
+        assert_eq!(args.execstack, None);
+        assert!(!args.warn_execstack && !args.error_execstack);
+        let args = parse(&["-z", "execstack", "--warn-execstack", "a.o"]);
+        assert_eq!(args.execstack, Some(true));
+        assert!(args.warn_execstack);
+        let args = parse(&[
+            "-znoexecstack",
+            "--error-execstack",
+            "--no-error-execstack",
+            "a.o",
+        ]);
+        assert_eq!(args.execstack, Some(false));
+        assert!(!args.error_execstack);
+    }
+
+    #[test]
+    fn test_warn_rwx_segments() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        assert!(parse(&["a.o"]).warn_rwx_segments);
+        assert!(!parse(&["--no-warn-rwx-segments", "a.o"]).warn_rwx_segments);
+        assert!(parse(&["--no-warn-rwx-segments", "--warn-rwx-segments", "a.o"]).warn_rwx_segments);
+    }
+
+    #[test]
+    fn test_page_sizes() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => Ok(args),
+            Ok(_) => panic!("Unexpected action"),
+            Err(error) => Err(error),
+        };
+        let args = parse(&["a.o"]).unwrap();
+        assert_eq!(args.max_page_size.value(), 0x1000);
+        assert_eq!(args.common_page_size.value(), 0x1000);
+        let args = parse(&[
+            "-z",
+            "max-page-size=0x10000",
+            "-zcommon-page-size=8192",
+            "a.o",
+        ])
+        .unwrap();
+        assert_eq!(args.max_page_size.value(), 0x10000);
+        assert_eq!(args.common_page_size.value(), 0x2000);
+        let args = parse(&["-z", "max-page-size=0x200000", "a.o"]).unwrap();
+        assert_eq!(args.max_page_size.value(), 0x200000);
+        assert!(parse(&["-z", "max-page-size=0x3000", "a.o"]).is_err());
+    }
+
+    #[test]
+    fn test_magic() {
+        let parse = |input: &[&str]| {
+            let Action::Link(args) = super::parse(input.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["a.o"]);
+        assert_eq!(args.magic, Magic::Zmagic);
+        assert!(args.rosegment);
+        let args = parse(&["-n", "a.o"]);
+        assert_eq!(args.magic, Magic::Nmagic);
+        assert_eq!(args.max_page_size, crate::alignment::MIN);
+        assert!(!args.separate_code);
+        assert!(!args.relro);
+        assert_eq!(parse(&["--omagic", "a.o"]).magic, Magic::Omagic);
+        assert_eq!(parse(&["-N", "--no-omagic", "a.o"]).magic, Magic::Zmagic);
+        assert!(!parse(&["--no-rosegment", "a.o"]).rosegment);
+    }
+
+    #[test]
+    fn test_separate_code() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(parse(&["a.o"]).separate_code);
+        assert!(!parse(&["-z", "noseparate-code", "a.o"]).separate_code);
+        assert!(parse(&["-znoseparate-code", "-zseparate-code", "a.o"]).separate_code);
+        assert!(!parse(&["a.o"]).text);
+        assert!(parse(&["-z", "text", "a.o"]).text);
+        assert!(!parse(&["-ztext", "-z", "notext", "a.o"]).text);
+    }
+
+    #[test]
+    fn test_dt_flags_1() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert_eq!(parse(&["a.o"]).dt_flags_1, 0);
+        assert_eq!(
+            parse(&["-shared", "-z", "nodelete", "-zglobal", "a.o"]).dt_flags_1,
+            object::elf::DF_1_NODELETE | object::elf::DF_1_GLOBAL
+        );
+        assert_eq!(
+            parse(&[
+                "-z",
+                "nodlopen",
+                "-z",
+                "initfirst",
+                "-z",
+                "interpose",
+                "a.o"
+            ])
+            .dt_flags_1,
+            object::elf::DF_1_NOOPEN | object::elf::DF_1_INITFIRST | object::elf::DF_1_INTERPOSE
+        );
+    }
+
+    #[test]
+    fn test_copy_relocations() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(parse(&["a.o"]).copy_relocations);
+        assert!(!parse(&["-z", "nocopyreloc", "a.o"]).copy_relocations);
+        assert!(parse(&["-znocopyreloc", "-zcopyreloc", "a.o"]).copy_relocations);
+    }
+
+    #[test]
+    fn test_pack_relative_relocs() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(!parse(&["a.o"]).pack_relative_relocs);
+        assert!(parse(&["-z", "pack-relative-relocs", "a.o"]).pack_relative_relocs);
+        assert!(
+            !parse(&["-zpack-relative-relocs", "-znopack-relative-relocs", "a.o"])
+                .pack_relative_relocs
+        );
+    }
+
+    #[test]
+    fn test_keep_text_section_prefix() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args.keep_text_section_prefix,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(!parse(&["a.o"]));
+        assert!(parse(&["-z", "keep-text-section-prefix", "a.o"]));
+        assert!(!parse(&[
+            "-z",
+            "keep-text-section-prefix",
+            "-z",
+            "nokeep-text-section-prefix",
+            "a.o"
+        ]));
+    }
+
+    #[test]
+    fn test_dead_reloc_in_nonalloc() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => Ok(args.dead_reloc_in_nonalloc),
+            Ok(_) => panic!("Unexpected action"),
+            Err(error) => Err(error),
+        };
+        assert!(parse(&["a.o"]).unwrap().is_empty());
+        let rules = parse(&[
+            "-z",
+            "dead-reloc-in-nonalloc=.debug_*=0",
+            "-zdead-reloc-in-nonalloc=.debug_ranges=0x1",
+            "a.o",
+        ])
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].value, 1);
+        assert!(rules[0].matches(b".debug_info"));
+        assert!(!rules[0].matches(b".gcc_except_table"));
+        assert!(rules[1].matches(b".debug_ranges"));
+        assert!(!rules[1].matches(b".debug_rnglists"));
+        assert!(parse(&["-z", "dead-reloc-in-nonalloc=.debug_info", "a.o"]).is_err());
+        assert!(parse(&["-z", "dead-reloc-in-nonalloc=*info=0", "a.o"]).is_err());
+        assert!(parse(&["-z", "dead-reloc-in-nonalloc=.debug_info=x", "a.o"]).is_err());
+    }
+
+    #[test]
+    fn test_gc_keep_exported() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(parse(&["-shared", "a.o"]).gc_keep_exported);
+        assert!(!parse(&["-shared", "--no-gc-keep-exported", "a.o"]).gc_keep_exported);
+        assert!(parse(&["--no-gc-keep-exported", "--gc-keep-exported", "a.o"]).gc_keep_exported);
+    }
+
+    #[test]
+    fn test_ctf_variables() {
+        let parse = |input: &[&str]| match super::parse(input.iter()).unwrap() {
+            Action::Link(args) => args,
+            _ => panic!("Unexpected action"),
+        };
+        assert!(!parse(&["a.o"]).ctf_variables);
+        assert!(parse(&["--ctf-variables", "a.o"]).ctf_variables);
+        assert!(!parse(&["--ctf-variables", "--no-ctf-variables", "a.o"]).ctf_variables);
+    }
+
+    #[test]
+    fn test_icf() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => Ok(args.icf),
+            Ok(_) => panic!("Unexpected action"),
+            Err(error) => Err(error),
+        };
+        assert_eq!(parse(&["a.o"]).unwrap(), Icf::None);
+        assert_eq!(parse(&["--icf=all", "a.o"]).unwrap(), Icf::All);
+        assert_eq!(parse(&["--icf=safe", "a.o"]).unwrap(), Icf::Safe);
+        assert_eq!(
+            parse(&["--icf=all", "--icf=none", "a.o"]).unwrap(),
+            Icf::None
+        );
+        assert!(parse(&["--icf=some", "a.o"]).is_err());
+    }
+
+    #[test]
+    fn test_keep_unique() {
+        let args = match super::parse(
+            [
+                "--keep-unique=foo",
+                "--keep-unique",
+                "bar",
+                "--print-icf-sections",
+                "a.o",
+            ]
+            .iter(),
+        ) {
+            Ok(Action::Link(args)) => args,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(args.keep_unique, ["foo", "bar"]);
+        assert!(args.print_icf_sections);
+        assert!(super::parse(["a.o", "--keep-unique"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_map_file() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.map_file,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["-Map", "a.map", "a.o"]),
+            Some(PathBuf::from("a.map"))
+        );
+        assert_eq!(parse(&["-Map=a.map", "a.o"]), Some(PathBuf::from("a.map")));
+        assert_eq!(parse(&["--Map=b.map", "a.o"]), Some(PathBuf::from("b.map")));
+        assert!(super::parse(["a.o", "-Map"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_dependency_file() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.dependency_file,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["--dependency-file", "out.d", "a.o"]),
+            Some(PathBuf::from("out.d"))
+        );
+        assert_eq!(
+            parse(&["--dependency-file=out.d", "a.o"]),
+            Some(PathBuf::from("out.d"))
+        );
+        assert!(super::parse(["a.o", "--dependency-file"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_reproduce() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.reproduce,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["--reproduce", "r.tar", "a.o"]),
+            Some(PathBuf::from("r.tar"))
+        );
+        assert_eq!(
+            parse(&["--reproduce=r.tar", "a.o"]),
+            Some(PathBuf::from("r.tar"))
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.stats,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(parse(&["--stats", "a.o"]), Some(None));
+        assert_eq!(
+            parse(&["--stats=stats.txt", "a.o"]),
+            Some(Some(PathBuf::from("stats.txt")))
+        );
+    }
+
+    #[test]
+    fn test_why_extract() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.why_extract,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["--why-extract=why.txt", "a.o"]),
+            Some(PathBuf::from("why.txt"))
+        );
+    }
+
+    #[test]
+    fn test_defsym_and_wrap() {
+        let Action::Link(args) = super::parse(
+            [
+                "--defsym=foo=0x10",
+                "--defsym",
+                "bar = baz",
+                "--wrap=malloc",
+                "-wrap",
+                "free",
+                "a.o",
+            ]
+            .iter(),
+        )
+        .unwrap() else {
+            panic!("Expected link action");
+        };
+        assert_eq!(
+            args.defsyms,
+            [
+                Defsym {
+                    name: "foo".to_owned(),
+                    value: DefsymValue::Absolute(0x10)
+                },
+                Defsym {
+                    name: "bar".to_owned(),
+                    value: DefsymValue::Symbol("baz".to_owned())
+                },
+            ]
+        );
+        assert_eq!(args.wrapped_name(b"malloc"), b"__wrap_malloc");
+        assert_eq!(args.wrapped_name(b"__real_malloc"), b"malloc");
+        assert_eq!(args.wrapped_name(b"__wrap_malloc"), b"__wrap_malloc");
+        assert_eq!(args.wrapped_name(b"free"), b"__wrap_free");
+        assert_eq!(args.wrapped_name(b"calloc"), b"calloc");
+        assert!(super::parse(["--defsym=foo", "a.o"].iter()).is_err());
+        assert!(super::parse(["--defsym=foo=bar+4", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_trace_symbols() {
+        let args = match super::parse(
+            [
+                "-y",
+                "foo",
+                "-ybar",
+                "--trace-symbol",
+                "baz",
+                "--trace-symbol=qux",
+                "a.o",
+            ]
+            .iter(),
+        ) {
+            Ok(Action::Link(args)) => args,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(args.trace_symbols, ["foo", "bar", "baz", "qux"]);
+        assert!(super::parse(["a.o", "-y"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let trace = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.trace,
+            _ => panic!("Expected link action"),
+        };
+        assert!(!trace(&["a.o"]));
+        assert!(trace(&["-t", "a.o"]));
+        assert!(trace(&["--trace", "a.o"]));
+    }
+
+    #[test]
+    fn test_record_command_line() {
+        let record = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.record_command_line,
+            _ => panic!("Expected link action"),
+        };
+        assert!(!record(&["a.o"]));
+        assert!(record(&["--record-command-line", "a.o"]));
+        assert!(!record(&[
+            "--record-command-line",
+            "--no-record-command-line",
+            "a.o"
+        ]));
+    }
+
+    #[test]
+    fn test_color_diagnostics() {
+        let color = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.diagnostics.color_choice,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(color(&["a.o"]), ColorChoice::Auto);
+        assert_eq!(color(&["--color-diagnostics", "a.o"]), ColorChoice::Always);
+        assert_eq!(
+            color(&["--color-diagnostics=never", "a.o"]),
+            ColorChoice::Never
+        );
+        assert_eq!(
+            color(&["--no-color-diagnostics", "a.o"]),
+            ColorChoice::Never
+        );
+        assert!(super::parse(["--color-diagnostics=red", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_diagnostics_format() {
+        let format = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.diagnostics.format,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(format(&["a.o"]), DiagnosticsFormat::Text);
+        assert_eq!(
+            format(&["--diagnostics-format=json", "a.o"]),
+            DiagnosticsFormat::Json
+        );
+        assert!(super::parse(["--diagnostics-format=xml", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_demangle() {
+        let demangle = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.demangle,
+            _ => panic!("Expected link action"),
+        };
+        assert!(demangle(&["a.o"]));
+        assert!(!demangle(&["--no-demangle", "a.o"]));
+        assert!(demangle(&["--no-demangle", "--demangle=auto", "a.o"]));
+    }
+
+    #[test]
+    fn test_error_limit() {
+        let limit = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.error_limit,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(limit(&["a.o"]), 20);
+        assert_eq!(limit(&["--error-limit=0", "a.o"]), 0);
+        assert_eq!(limit(&["--error-limit", "5", "a.o"]), 5);
+        assert!(super::parse(["--error-limit=x", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_allow_multiple_definition() {
+        let allow = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.allow_multiple_definition,
+            _ => panic!("Expected link action"),
+        };
+        assert!(!allow(&["a.o"]));
+        assert!(allow(&["--allow-multiple-definition", "a.o"]));
+        assert!(allow(&["-z", "muldefs", "a.o"]));
+    }
+
+    #[test]
+    fn test_warn_unresolved_symbols() {
+        let warn = |args: &[&str]| match super::parse(args.iter()) {
+            Ok(Action::Link(args)) => args.warn_unresolved_symbols,
+            _ => panic!("Expected link action"),
+        };
+        assert!(!warn(&["a.o"]));
+        assert!(warn(&["--warn-unresolved-symbols", "a.o"]));
+        assert!(!warn(&[
+            "--warn-unresolved-symbols",
+            "--error-unresolved-symbols",
+            "a.o"
+        ]));
+    }
+
+    #[test]
+    fn test_skip_if_unchanged() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.skip_if_unchanged,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["--skip-if-unchanged", "a.o"]),
+            Some(ChangeDetection::Metadata)
+        );
+        assert_eq!(
+            parse(&["--skip-if-unchanged=contents", "a.o"]),
+            Some(ChangeDetection::Contents)
+        );
+        assert!(super::parse(["--skip-if-unchanged=never", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_map_format() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.map_format,
+            _ => panic!("Expected link action"),
+        };
+        assert_eq!(parse(&["a.o"]), MapFormat::Text);
+        assert_eq!(parse(&["--map-format=json", "a.o"]), MapFormat::Json);
+        assert_eq!(
+            parse(&["--map-format=json", "--map-format=text", "a.o"]),
+            MapFormat::Text
+        );
+        assert!(super::parse(["--map-format=yaml", "a.o"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_call_graph_profile_sort() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.call_graph_profile_sort,
+            _ => panic!("Expected link action"),
+        };
+        assert!(parse(&["a.o"]));
+        assert!(!parse(&["--no-call-graph-profile-sort", "a.o"]));
+        assert!(parse(&[
+            "--no-call-graph-profile-sort",
+            "--call-graph-profile-sort",
+            "a.o"
+        ]));
+    }
+
+    #[test]
+    fn test_hash_tuning() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => Ok(args),
+            Ok(_) => panic!("Unexpected action"),
+            Err(error) => Err(error),
+        };
+        let args = parse(&["-shared", "a.o"]).unwrap();
+        assert_eq!(args.hash_size, None);
+        assert_eq!(args.hash_bloom_words, None);
+        let args = parse(&[
+            "-shared",
+            "--hash-size=1021",
+            "--hash-bloom-words=0x40",
+            "a.o",
+        ])
+        .unwrap();
+        assert_eq!(args.hash_size, Some(1021));
+        assert_eq!(args.hash_bloom_words, Some(64));
+        assert!(parse(&["--hash-size=0", "a.o"]).is_err());
+        assert!(parse(&["--hash-bloom-words=3", "a.o"]).is_err());
+    }
+
+    #[test]
+    fn test_build_id() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => Ok(args.build_id),
+            Ok(_) => panic!("Unexpected action"),
+            Err(error) => Err(error),
+        };
+        assert_eq!(parse(&["a.o"]).unwrap(), None);
+        assert_eq!(parse(&["--build-id", "a.o"]).unwrap(), Some(BuildId::Sha1));
+        assert_eq!(
+            parse(&["--build-id=md5", "a.o"]).unwrap(),
+            Some(BuildId::Md5)
+        );
+        assert_eq!(
+            parse(&["--build-id=fast", "a.o"]).unwrap(),
+            Some(BuildId::Fast)
+        );
+        assert_eq!(
+            parse(&["--build-id=uuid", "a.o"]).unwrap(),
+            Some(BuildId::Uuid)
+        );
+        assert_eq!(
+            parse(&["--build-id=0xdeadBEEF01", "a.o"]).unwrap(),
+            Some(BuildId::Hex(vec![0xde, 0xad, 0xbe, 0xef, 0x01]))
+        );
+        assert_eq!(
+            parse(&["--build-id", "--build-id=none", "a.o"]).unwrap(),
+            None
+        );
+        assert!(parse(&["--build-id=0xabc", "a.o"]).is_err());
+        assert!(parse(&["--build-id=0xzz", "a.o"]).is_err());
+        // Two bytes, but a single character, which mustn't be split.
+        assert!(parse(&["--build-id=0xé", "a.o"]).is_err());
+        assert!(parse(&["--build-id=sha256", "a.o"]).is_err());
+    }
+
+    #[test]
+    fn test_separate_debug_file() {
+        let parse = |input: &[&str]| match super::parse(input.iter()) {
+            Ok(Action::Link(args)) => args.separate_debug_file,
+            _ => panic!("Unexpected action"),
+        };
+        assert_eq!(parse(&["a.o"]), None);
+        assert_eq!(
+            parse(&["--separate-debug-file", "a.o", "-o", "out/prog"]),
+            Some(PathBuf::from("out/prog.dbg"))
+        );
+        assert_eq!(
+            parse(&["--separate-debug-file=prog.debug", "a.o"]),
+            Some(PathBuf::from("prog.debug"))
+        );
+    }
+
+    #[test]
+    fn test_files_from() {
+        let Action::Link(args) =
+            super::parse(["a.o", "--files-from=list.txt", "-Bstatic", "--files-from=-"].iter())
+                .unwrap()
+        else {
+            panic!("Unexpected action");
+        };
+        assert_eq!(
+            args.inputs
+                .iter()
+                .map(|i| (&i.spec, i.modifiers.allow_shared))
+                .collect_vec(),
+            &[
+                (&InputSpec::File(Box::from(Path::new("a.o"))), true),
+                (&InputSpec::FileList(Box::from(Path::new("list.txt"))), true),
+                (&InputSpec::FileList(Box::from(Path::new("-"))), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_linker() {
+        let parse = |args: &[&str]| {
+            let Action::Link(args) = super::parse(args.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["--dynamic-linker=/opt/ld.so"]);
+        assert!(args.output_kind.needs_dynsym());
+        assert_eq!(
+            args.dynamic_linker.as_deref(),
+            Some(Path::new("/opt/ld.so"))
+        );
+        let args = parse(&["-I", "/opt/ld.so"]);
+        assert_eq!(
+            args.dynamic_linker.as_deref(),
+            Some(Path::new("/opt/ld.so"))
+        );
+        let args = parse(&["-dynamic-linker", "none"]);
+        assert!(args.output_kind.needs_dynsym());
+        assert_eq!(args.dynamic_linker, None);
+        let args = parse(&["--dynamic-linker=none", "-pie"]);
+        assert_eq!(
+            args.output_kind,
+            OutputKind::DynamicExecutable(RelocationModel::Relocatable)
+        );
+        assert_eq!(args.dynamic_linker, None);
+    }
+
+    #[test]
+    fn test_emulation() {
+        let parse = |args: &[&str]| {
+            let Action::Link(args) = super::parse(args.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["-m", "elf_x86_64", "a.o"]);
+        assert_eq!(args.emulation.machine, object::elf::EM_X86_64);
+        assert_eq!(args.max_page_size.value(), 0x1000);
+        let args = parse(&["-m", "aarch64linux", "a.o"]);
+        assert_eq!(args.emulation.machine, object::elf::EM_AARCH64);
+        assert_eq!(args.max_page_size.value(), 0x10000);
+        assert!(super::parse(["-m", "elf_unknown", "a.o"].iter()).is_err());
+
+        let mut header = [0; 20];
+        header[..4].copy_from_slice(&object::elf::ELFMAG);
+        header[5] = object::elf::ELFDATA2LSB;
+        header[18..].copy_from_slice(&object::elf::EM_RISCV.to_le_bytes());
+        assert_eq!(
+            super::emulation_for_header(&header).map(|emulation| emulation.name),
+            Some("elf64lriscv")
+        );
+        header[18..].copy_from_slice(&object::elf::EM_PPC64.to_le_bytes());
+        assert_eq!(super::emulation_for_header(&header), None);
+    }
+
+    #[test]
+    fn test_default_dynamic_linker() {
+        let parse = |args: &[&str]| {
+            let Action::Link(args) = super::parse(args.iter()).unwrap() else {
+                panic!("Unexpected action");
+            };
+            args
+        };
+        let args = parse(&["-m", "elf_x86_64", "--sysroot=/does/not/exist", "-pie"]);
+        assert_eq!(
+            args.output_kind,
+            OutputKind::DynamicExecutable(RelocationModel::Relocatable)
+        );
+        assert_eq!(
+            args.dynamic_linker.as_deref(),
+            Some(Path::new("/lib64/ld-linux-x86-64.so.2"))
+        );
+        let args = parse(&["-m", "aarch64linux", "--sysroot=/does/not/exist", "-pie"]);
+        assert_eq!(
+            args.dynamic_linker.as_deref(),
+            Some(Path::new("/lib/ld-linux-aarch64.so.1"))
+        );
+        let args = parse(&["-static", "-pie", "--no-dynamic-linker"]);
+        assert_eq!(
+            args.output_kind,
+            OutputKind::StaticExecutable(RelocationModel::Relocatable)
+        );
+        assert_eq!(args.dynamic_linker, None);
+        let args = parse(&["-pie", "--no-dynamic-linker"]);
+        assert_eq!(
+            args.output_kind,
+            OutputKind::StaticExecutable(RelocationModel::Relocatable)
+        );
+        let args = parse(&["-static"]);
+        assert_eq!(
+            args.output_kind,
+            OutputKind::StaticExecutable(RelocationModel::NonRelocatable)
+        );
+        assert!(super::parse(["-m", "elf_unknown", "-pie"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_static_dynamic_toggles() {
+        let Action::Link(args) = super::parse(
+            [
+                "-la",
+                "-Bstatic",
+                "-lb",
+                "--push-state",
+                "-Bdynamic",
+                "-lc",
+                "--pop-state",
+                "-ld",
+                "-dy",
+                "-le",
+                "-static",
+                "-lf",
+                "-call_shared",
+                "-lg",
+                "-dn",
+                "-lh",
+                "-non_shared",
+                "-li",
+            ]
+            .iter(),
+        )
+        .unwrap() else {
+            panic!("Unexpected action");
+        };
+        assert_eq!(
+            args.inputs
+                .iter()
+                .map(|i| i.modifiers.allow_shared)
+                .collect_vec(),
+            &[true, false, true, false, true, false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_sysroot() {
+        let Action::Link(args) = super::parse(
+            [
+                "-L=/usr/lib",
+                "-L$SYSROOT/lib",
+                "-L/opt/lib",
+                "--sysroot=/target",
+            ]
+            .iter(),
+        )
+        .unwrap() else {
+            panic!("Unexpected action");
+        };
+        assert_eq!(
+            args.lib_search_path
+                .iter()
+                .map(|p| p.as_ref())
+                .collect_vec(),
+            &[
+                Path::new("/target/usr/lib"),
+                Path::new("/target/lib"),
+                Path::new("/opt/lib")
+            ]
+        );
+        assert_eq!(
+            args.path_in_sysroot(
+                Path::new("/lib/libc.so.6"),
+                Some(Path::new("/target/usr/lib"))
+            ),
+            Some(PathBuf::from("/target/lib/libc.so.6"))
+        );
+        assert_eq!(
+            args.path_in_sysroot(Path::new("/lib/libc.so.6"), Some(Path::new("/usr/lib"))),
+            None
+        );
+        assert_eq!(
+            args.path_in_sysroot(Path::new("=/lib/crt1.o"), None),
+            Some(PathBuf::from("/target/lib/crt1.o"))
+        );
+    }
+
     #[test]
     fn test_arguments_from_string() {
         use super::arguments_from_string;
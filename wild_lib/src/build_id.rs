@@ -0,0 +1,95 @@
+//! Computes the contents of `.note.gnu.build-id`. Hashing styles hash the output file once it has
+//! been fully written. So that this doesn't serialise the end of the link, the file is split into
+//! chunks which are hashed in parallel, then the chunk hashes are themselves hashed to produce the
+//! ID. This means that our IDs differ from those that other linkers would compute for the same
+//! bytes, but they're still deterministic and still change whenever the output changes.
+
+use crate::args::BuildId;
+use crate::threading::prelude::*;
+use md5::Md5;
+use sha1::Digest as _;
+use sha1::Sha1;
+
+/// The size of the note header plus the note name, "GNU\0".
+pub(crate) const NOTE_HEADER_SIZE: usize = 16;
+
+/// The amount of the output that we hash as a single unit.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Returns the size of the whole note for the supplied build-ID style.
+pub(crate) fn note_size(build_id: &BuildId) -> u64 {
+    (NOTE_HEADER_SIZE + build_id.len().next_multiple_of(4)) as u64
+}
+
+/// Writes the note header for `build_id` into `out`. The descriptor is left zeroed, ready to be
+/// filled in by `compute` once the rest of the file has been written.
+pub(crate) fn write_note_header(build_id: &BuildId, out: &mut [u8]) {
+    out[..4].copy_from_slice(&4_u32.to_le_bytes());
+    out[4..8].copy_from_slice(&(build_id.len() as u32).to_le_bytes());
+    out[8..12].copy_from_slice(&object::elf::NT_GNU_BUILD_ID.to_le_bytes());
+    out[12..16].copy_from_slice(b"GNU\0");
+}
+
+/// Computes the build ID for `file`, in which the descriptor must still be zeroed.
+pub(crate) fn compute(build_id: &BuildId, file: &[u8]) -> Vec<u8> {
+    match build_id {
+        BuildId::Fast => tree_hash(file, |bytes| fxhash::hash64(bytes).to_le_bytes().to_vec()),
+        BuildId::Md5 => tree_hash(file, |bytes| md5(bytes).to_vec()),
+        BuildId::Sha1 => tree_hash(file, |bytes| sha1(bytes).to_vec()),
+        BuildId::Uuid => uuid::Uuid::new_v4().into_bytes().to_vec(),
+        BuildId::Hex(bytes) => bytes.clone(),
+    }
+}
+
+fn tree_hash(file: &[u8], hash: impl Fn(&[u8]) -> Vec<u8> + Sync) -> Vec<u8> {
+    let chunk_hashes: Vec<Vec<u8>> = file
+        .chunks(CHUNK_SIZE)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(&hash)
+        .collect();
+    hash(&chunk_hashes.concat())
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    Sha1::digest(data).into()
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    Md5::digest(data).into()
+}
+
+#[cfg(test)]
+mod tests {
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(
+            hex(&super::sha1(b"")),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert_eq!(
+            hex(&super::sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&super::sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(hex(&super::md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&super::md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(&super::md5(&[b'a'; 100])),
+            "36a92cc94a9e0fa21f625f8bfb007adf"
+        );
+    }
+}
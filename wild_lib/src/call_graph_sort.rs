@@ -0,0 +1,360 @@
+//! Ordering of hot functions using the call-graph profiles that clang and rustc write to
+//! `.llvm.call-graph-profile` sections when compiling with profile data. We cluster sections using
+//! the C3 heuristic from "Optimizing Function Placement for Large-Scale Data-Center Applications"
+//! (Ottoni and Maher, CGO 2017), as LLD does. Each section is appended to the cluster of its most
+//! frequent caller, provided that doesn't make the cluster too big or too much colder. The clusters
+//! are then placed in order of decreasing density, i.e. call weight per byte.
+
+use crate::elf::File;
+use crate::error::Result;
+use crate::input_data::FileId;
+use crate::part_id::PartId;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolIdRange;
+use anyhow::bail;
+use fxhash::FxHashMap;
+use linker_utils::elf::sht;
+use linker_utils::elf::SectionType;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+
+/// Clusters larger than this aren't grown any further, since their members would no longer share
+/// pages or cache lines anyway.
+const MAX_CLUSTER_SIZE: u64 = 1024 * 1024;
+
+/// We don't merge a cluster into its caller's cluster if that would reduce the caller's density by
+/// more than this factor.
+const MAX_DENSITY_DEGRADATION: f64 = 8.0;
+
+/// An input object whose sections we might reorder.
+pub(crate) struct ProfileFile<'data> {
+    pub(crate) file_id: FileId,
+    pub(crate) object: &'data File<'data>,
+    pub(crate) symbol_id_range: SymbolIdRange,
+
+    /// Live sections that can be reordered, with the output section part that each is in and how
+    /// much space it occupies.
+    pub(crate) sections: Vec<(object::SectionIndex, PartId, u64)>,
+}
+
+struct Node {
+    file_id: FileId,
+    section_index: object::SectionIndex,
+    part_id: PartId,
+    size: u64,
+
+    /// The caller that calls this section the most, together with how many times it does so.
+    best_caller: Option<(usize, u64)>,
+}
+
+struct Cluster {
+    members: Vec<usize>,
+    size: u64,
+
+    /// The total weight of calls into members of this cluster.
+    weight: u64,
+}
+
+impl Cluster {
+    fn density(&self) -> f64 {
+        self.weight as f64 / self.size.max(1) as f64
+    }
+}
+
+/// Returns the sections that appear in call-graph profiles in the order in which they should be
+/// placed. Sections that aren't mentioned by any profile aren't included.
+#[tracing::instrument(skip_all, name = "Sort sections by call graph")]
+pub(crate) fn compute_order(
+    files: &[ProfileFile],
+    symbol_db: &SymbolDb,
+) -> Result<Vec<(FileId, object::SectionIndex)>> {
+    let files_by_id: FxHashMap<FileId, &ProfileFile> =
+        files.iter().map(|file| (file.file_id, file)).collect();
+    let mut node_indexes: FxHashMap<(FileId, object::SectionIndex), usize> = FxHashMap::default();
+    let mut nodes = Vec::new();
+    let mut node_for_symbol = |file: &ProfileFile, symbol_index| -> Result<Option<usize>> {
+        let symbol_id = symbol_db.definition(file.symbol_id_range.input_to_id(symbol_index));
+        let Some(definition_file) = files_by_id.get(&symbol_db.file_id_for_symbol(symbol_id))
+        else {
+            return Ok(None);
+        };
+        let definition_index = definition_file.symbol_id_range.id_to_input(symbol_id);
+        let symbol = definition_file.object.symbol(definition_index)?;
+        let Some(section_index) = definition_file
+            .object
+            .symbol_section(symbol, definition_index)?
+        else {
+            return Ok(None);
+        };
+        let key = (definition_file.file_id, section_index);
+        if let Some(&index) = node_indexes.get(&key) {
+            return Ok(Some(index));
+        }
+        let Some(&(_, part_id, size)) = definition_file
+            .sections
+            .iter()
+            .find(|(index, ..)| *index == section_index)
+        else {
+            return Ok(None);
+        };
+        node_indexes.insert(key, nodes.len());
+        nodes.push(Node {
+            file_id: definition_file.file_id,
+            section_index,
+            part_id,
+            size,
+            best_caller: None,
+        });
+        Ok(Some(nodes.len() - 1))
+    };
+
+    let mut edges: FxHashMap<(usize, usize), u64> = FxHashMap::default();
+    for file in files {
+        for (from, to, weight) in read_profile(file.object)? {
+            let (Some(from), Some(to)) = (node_for_symbol(file, from)?, node_for_symbol(file, to)?)
+            else {
+                continue;
+            };
+            *edges.entry((from, to)).or_default() += weight;
+        }
+    }
+
+    Ok(cluster(&mut nodes, edges)
+        .into_iter()
+        .map(|member| (nodes[member].file_id, nodes[member].section_index))
+        .collect())
+}
+
+/// Groups `nodes` into clusters, given the total weight of calls along each (caller, callee) edge.
+/// Returns the indexes of all nodes in the order in which they should be placed.
+fn cluster(nodes: &mut [Node], edges: FxHashMap<(usize, usize), u64>) -> Vec<usize> {
+    let mut clusters: Vec<Cluster> = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| Cluster {
+            members: vec![index],
+            size: node.size,
+            weight: 0,
+        })
+        .collect();
+    let mut edges = edges.into_iter().collect::<Vec<_>>();
+    edges.sort_unstable();
+    for ((from, to), weight) in edges {
+        // Calls within a section don't affect its placement, and sections in different output
+        // sections can't be placed next to each other.
+        if from == to
+            || nodes[from].part_id.output_section_id() != nodes[to].part_id.output_section_id()
+        {
+            continue;
+        }
+        clusters[to].weight += weight;
+        if nodes[to]
+            .best_caller
+            .is_none_or(|(_, best_weight)| weight > best_weight)
+        {
+            nodes[to].best_caller = Some((from, weight));
+        }
+    }
+
+    let mut by_density: Vec<usize> = (0..nodes.len()).collect();
+    by_density.sort_by(|&a, &b| clusters[b].density().total_cmp(&clusters[a].density()));
+
+    let mut cluster_of: Vec<usize> = (0..nodes.len()).collect();
+    for &index in &by_density {
+        let Some((caller, _)) = nodes[index].best_caller else {
+            continue;
+        };
+        let into = cluster_of[caller];
+        if into == index
+            || clusters[into].size + clusters[index].size > MAX_CLUSTER_SIZE
+            || is_new_density_bad(&clusters[into], &clusters[index])
+        {
+            continue;
+        }
+        let merged = core::mem::take(&mut clusters[index].members);
+        for &member in &merged {
+            cluster_of[member] = into;
+        }
+        let (size, weight) = (clusters[index].size, clusters[index].weight);
+        let target = &mut clusters[into];
+        target.members.extend(merged);
+        target.size += size;
+        target.weight += weight;
+    }
+
+    let mut leaders: Vec<usize> = (0..nodes.len())
+        .filter(|&index| cluster_of[index] == index)
+        .collect();
+    leaders.sort_by(|&a, &b| clusters[b].density().total_cmp(&clusters[a].density()));
+    leaders
+        .into_iter()
+        .flat_map(|leader| core::mem::take(&mut clusters[leader].members))
+        .collect()
+}
+
+fn is_new_density_bad(into: &Cluster, cluster: &Cluster) -> bool {
+    let new_density =
+        (into.weight + cluster.weight) as f64 / (into.size + cluster.size).max(1) as f64;
+    new_density < into.density() / MAX_DENSITY_DEGRADATION
+}
+
+/// Returns whether `object` contains a call-graph profile.
+pub(crate) fn has_profile(object: &File) -> bool {
+    object
+        .sections
+        .iter()
+        .any(|section| SectionType::from_header(section) == sht::LLVM_CALL_GRAPH_PROFILE)
+}
+
+/// Returns the (caller, callee, weight) entries from the call-graph profile of `object`, if it has
+/// one. Current compilers store just the weights in the section and identify the caller and callee
+/// of each entry with a pair of relocations. Older compilers stored symbol indexes directly.
+fn read_profile(object: &File) -> Result<Vec<(object::SymbolIndex, object::SymbolIndex, u64)>> {
+    let e = LittleEndian;
+    let Some((profile_index, profile)) = object
+        .sections
+        .enumerate()
+        .find(|(_, section)| SectionType::from_header(section) == sht::LLVM_CALL_GRAPH_PROFILE)
+    else {
+        return Ok(Vec::new());
+    };
+    let data = object.raw_section_data(profile)?;
+    let relocations = object.sections.iter().find(|section| {
+        SectionType::from_header(section) == sht::REL
+            && section.sh_info(e) as usize == profile_index.0
+    });
+    let mut entries = Vec::new();
+    if let Some(relocations) = relocations {
+        let weights: &[object::U64<LittleEndian>] = object::slice_from_all_bytes(data)
+            .map_err(|()| anyhow::anyhow!("Invalid .llvm.call-graph-profile size"))?;
+        let rels: &[object::elf::Rel64<LittleEndian>] =
+            object::slice_from_all_bytes(object.raw_section_data(relocations)?)
+                .map_err(|()| anyhow::anyhow!("Invalid .rel.llvm.call-graph-profile size"))?;
+        if rels.len() != weights.len() * 2 {
+            bail!(".rel.llvm.call-graph-profile doesn't have two relocations per entry");
+        }
+        for (weight, pair) in weights.iter().zip(rels.chunks_exact(2)) {
+            entries.push((
+                object::SymbolIndex(pair[0].r_sym(e) as usize),
+                object::SymbolIndex(pair[1].r_sym(e) as usize),
+                weight.get(e),
+            ));
+        }
+    } else {
+        // Each entry is a pair of 32 bit symbol indexes followed by a 64 bit weight.
+        if data.len() % 16 != 0 {
+            bail!("Invalid .llvm.call-graph-profile size");
+        }
+        for entry in data.chunks_exact(16) {
+            let from = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let to = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            let weight = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+            entries.push((
+                object::SymbolIndex(from as usize),
+                object::SymbolIndex(to as usize),
+                weight,
+            ));
+        }
+    }
+    let num_symbols = object.symbols.len();
+    if let Some(index) = entries
+        .iter()
+        .flat_map(|(from, to, _)| [from.0, to.0])
+        .find(|index| *index >= num_symbols)
+    {
+        bail!(
+            ".llvm.call-graph-profile refers to symbol {index}, but there are only {num_symbols} \
+             symbols"
+        );
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment;
+    use crate::output_section_id;
+
+    fn nodes(sizes: &[u64]) -> Vec<Node> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| Node {
+                file_id: FileId::new(1, 0),
+                section_index: object::SectionIndex(index + 1),
+                part_id: output_section_id::TEXT.part_id_with_alignment(alignment::MIN),
+                size,
+                best_caller: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_callees_follow_callers() {
+        // 0 calls 1 and 2, with 2 being hotter. 3 calls 1 more often than 0 does, so 1 goes with 3.
+        let mut nodes = nodes(&[100, 100, 100, 100]);
+        let edges = [((0, 1), 10), ((0, 2), 50), ((3, 1), 20)]
+            .into_iter()
+            .collect();
+        assert_eq!(cluster(&mut nodes, edges), [0, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_different_output_sections_are_not_clustered() {
+        let mut nodes = nodes(&[100, 100, 100]);
+        nodes[1].part_id = output_section_id::RODATA.part_id_with_alignment(alignment::MIN);
+        let edges = [((0, 1), 100), ((0, 2), 10)].into_iter().collect();
+        assert_eq!(cluster(&mut nodes, edges), [0, 2, 1]);
+    }
+
+    #[test]
+    fn test_cluster_size_limit() {
+        let edges = || [((0, 1), 100)].into_iter().collect();
+        assert_eq!(cluster(&mut nodes(&[100, 100]), edges()), [0, 1]);
+
+        // Appending 1 to the cluster of 0 would make it too big, so 1 stays on its own. It has all
+        // of the weight, so it's denser and goes first.
+        assert_eq!(
+            cluster(&mut nodes(&[MAX_CLUSTER_SIZE, 100]), edges()),
+            [1, 0]
+        );
+    }
+
+    #[test]
+    fn test_density_degradation() {
+        // 0 is small and very hot. Appending the large, cold section 1 would make the cluster
+        // far less dense, so 1 is left on its own.
+        let mut nodes = nodes(&[10, 100_000, 10]);
+        let edges = [((2, 0), 1000), ((0, 1), 1)].into_iter().collect();
+        assert_eq!(cluster(&mut nodes, edges), [2, 0, 1]);
+    }
+
+    #[test]
+    fn test_invalid_symbol_index() {
+        use object::write;
+
+        let mut builder = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let profile = builder.add_section(
+            Vec::new(),
+            b".llvm.call-graph-profile".to_vec(),
+            object::SectionKind::Elf(sht::LLVM_CALL_GRAPH_PROFILE.raw()),
+        );
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&0_u32.to_le_bytes());
+        entry.extend_from_slice(&99_u32.to_le_bytes());
+        entry.extend_from_slice(&10_u64.to_le_bytes());
+        builder.append_section_data(profile, &entry, 8);
+        let data = builder.write().unwrap();
+        let object = File::parse(&data, false).unwrap();
+        let error = read_profile(&object).unwrap_err();
+        assert!(
+            error.to_string().contains("refers to symbol 99"),
+            "Unexpected error: {error}"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Deduplication of COMDAT section groups. Compilers put code and data that more than one
+//! compilation unit might emit, e.g. inline functions and template instantiations, into a group
+//! that's identified by a signature. We keep the first group with each signature, in input order,
+//! and discard the members of the others.
+//!
+//! References to a discarded member from outside of its group, e.g. from code built by a different
+//! compiler or from debug info, are redirected to the member of the kept group with the same name,
+//! provided that it's the same size. GNU ld does the same for references from debug info, but
+//! reports references from code as errors. If there's no such member, then referencing the
+//! discarded one is an error.
+
+use crate::elf::File;
+use crate::elf::SectionHeader;
+use crate::error::Result;
+use crate::input_data::FileId;
+use crate::resolution::DuplicateSection;
+use crate::resolution::ResolvedFile;
+use crate::resolution::ResolvedGroup;
+use crate::resolution::SectionSlot;
+use anyhow::Context as _;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+use object::SectionIndex;
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// A COMDAT group in one of our input objects.
+struct ComdatGroup<'data> {
+    signature: &'data [u8],
+    members: Vec<SectionIndex>,
+}
+
+/// The COMDAT groups of an input object.
+struct ObjectGroups<'data> {
+    file_id: FileId,
+    object: &'data File<'data>,
+    groups: Vec<ComdatGroup<'data>>,
+}
+
+/// Replaces the members of each COMDAT group that has the same signature as a group in an earlier
+/// object with `SectionSlot::Duplicate`, so that they won't be loaded. Must be called before we
+/// select between alternative symbol definitions, so that definitions in discarded sections aren't
+/// selected.
+#[tracing::instrument(skip_all, name = "Discard duplicate COMDAT groups")]
+pub(crate) fn discard_duplicate_groups(resolved: &mut [ResolvedGroup]) -> Result {
+    let objects = resolved
+        .par_iter()
+        .flat_map_iter(|group| &group.files)
+        .filter_map(|file| match file {
+            ResolvedFile::Object(obj) if obj.non_dynamic.is_some() => Some(obj),
+            _ => None,
+        })
+        .map(|obj| {
+            Ok(ObjectGroups {
+                file_id: obj.file_id,
+                object: obj.object,
+                groups: read_groups(obj.object)
+                    .with_context(|| format!("Failed to read section groups of {}", obj.input))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The object and group that we kept for each signature.
+    let mut kept: HashMap<&[u8], (usize, usize)> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (object_index, object) in objects.iter().enumerate() {
+        for (group_index, group) in object.groups.iter().enumerate() {
+            match kept.entry(group.signature) {
+                Entry::Occupied(entry) => {
+                    duplicates.push(((object_index, group_index), *entry.get()));
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((object_index, group_index));
+                }
+            }
+        }
+    }
+
+    for ((object_index, group_index), (kept_object_index, kept_group_index)) in duplicates {
+        let object = &objects[object_index];
+        let kept_object = &objects[kept_object_index];
+        let group = &object.groups[group_index];
+        let kept_group = &kept_object.groups[kept_group_index];
+        let ResolvedFile::Object(obj) =
+            &mut resolved[object.file_id.group()].files[object.file_id.file()]
+        else {
+            unreachable!();
+        };
+        let sections = &mut obj.non_dynamic.as_mut().unwrap().sections;
+        for &member in &group.members {
+            let part_id = match sections.get(member.0) {
+                Some(SectionSlot::Unloaded(unloaded) | SectionSlot::MustLoad(unloaded)) => {
+                    unloaded.part_id
+                }
+                Some(SectionSlot::UnloadedDebugInfo(part_id)) => *part_id,
+                // Merged strings are shared regardless of where they came from, so keeping both
+                // copies costs nothing. Other sections, e.g. .eh_frame, aren't really members.
+                _ => continue,
+            };
+            sections[member.0] = SectionSlot::Duplicate(DuplicateSection {
+                part_id,
+                signature: group.signature,
+                kept_file: kept_object.file_id,
+                kept_section: matching_member(
+                    object.object,
+                    member,
+                    kept_object.object,
+                    kept_group,
+                )?,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the COMDAT groups of `object`. Groups without the GRP_COMDAT flag aren't deduplicated, so
+/// are ignored.
+fn read_groups<'data>(object: &'data File<'data>) -> Result<Vec<ComdatGroup<'data>>> {
+    let e = LittleEndian;
+    let mut groups = Vec::new();
+    for section in object.sections.iter() {
+        let Some((flags, members)) = section.group(e, object.data)? else {
+            continue;
+        };
+        if flags & object::elf::GRP_COMDAT == 0 {
+            continue;
+        }
+        groups.push(ComdatGroup {
+            signature: signature(object, section)?,
+            members: members
+                .iter()
+                .map(|member| SectionIndex(member.get(e) as usize))
+                .collect(),
+        });
+    }
+    Ok(groups)
+}
+
+/// Returns the signature of the group `section`. This is the name of the symbol that `sh_info`
+/// refers to, or if that's a section symbol, the name of the section.
+fn signature<'data>(object: &File<'data>, section: &SectionHeader) -> Result<&'data [u8]> {
+    let symbol_index = object::SymbolIndex(section.sh_info(LittleEndian) as usize);
+    let symbol = object.symbol(symbol_index)?;
+    if symbol.st_type() == object::elf::STT_SECTION {
+        if let Some(section_index) = object.symbol_section(symbol, symbol_index)? {
+            return object.section_name(object.section(section_index)?);
+        }
+    }
+    object.symbol_name(symbol)
+}
+
+/// Returns the member of `kept_group` that has the same name and size as `member`.
+fn matching_member(
+    object: &File,
+    member: SectionIndex,
+    kept_object: &File,
+    kept_group: &ComdatGroup,
+) -> Result<Option<SectionIndex>> {
+    let section = object.section(member)?;
+    let name = object.section_name(section)?;
+    let size = object.section_size(section)?;
+    for &kept_member in &kept_group.members {
+        let kept_section = kept_object.section(kept_member)?;
+        if kept_object.section_name(kept_section)? == name
+            && kept_object.section_size(kept_section)? == size
+        {
+            return Ok(Some(kept_member));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns a description of why `duplicate` was discarded, for use in error messages.
+pub(crate) fn describe(duplicate: &DuplicateSection, kept_file: impl std::fmt::Display) -> String {
+    format!(
+        "it's a member of COMDAT group `{}`, which was discarded in favour of the copy in {}",
+        String::from_utf8_lossy(duplicate.signature),
+        kept_file,
+    )
+}
@@ -0,0 +1,635 @@
+//! Merging of Compact C Type Format (CTF) type information, which GCC emits with `-gctf`. Each input
+//! object has a `.ctf` section containing a dictionary of the types, functions and variables of its
+//! compilation unit. We merge these into a single dictionary in which each distinct type appears
+//! once, as GNU ld does via libctf.
+//!
+//! Two types are the same if they have the same kind, name and layout and the types that they refer
+//! to are also the same. Since types can refer to themselves, e.g. via a pointer member, we find the
+//! distinct types by partition refinement: we start by grouping types by everything other than the
+//! types that they refer to, then repeatedly split groups whose members refer to types in different
+//! groups until nothing changes. Forward declarations are replaced by the definition with the same
+//! name, if there's exactly one.
+//!
+//! Where compilation units define different types with the same name, GNU ld moves the conflicting
+//! types into per-unit child dictionaries. We instead keep them all in the one dictionary, with only
+//! the first visible to lookups by name.
+
+use crate::error::Result;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context as _;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+const MAGIC: u16 = 0xdff2;
+const ARCHIVE_MAGIC: u64 = 0x8b47_f2a4_d762_3eeb;
+const VERSION_3: u8 = 4;
+const HEADER_SIZE: usize = 52;
+
+const F_COMPRESS: u8 = 0x1;
+const F_NEWFUNCINFO: u8 = 0x2;
+const F_IDXSORTED: u8 = 0x4;
+
+/// A `ctt_size` value indicating that the actual size follows as two 32 bit halves.
+const LSIZE_SENT: u32 = u32::MAX;
+
+/// Structs and unions at least this big have members with 64 bit offsets.
+const LSTRUCT_THRESH: u64 = 1 << 29;
+
+/// Set in references to strings that are in the ELF string table rather than our own.
+const NAME_EXTERNAL: u32 = 1 << 31;
+
+mod kind {
+    pub(super) const INTEGER: u8 = 1;
+    pub(super) const FLOAT: u8 = 2;
+    pub(super) const POINTER: u8 = 3;
+    pub(super) const ARRAY: u8 = 4;
+    pub(super) const FUNCTION: u8 = 5;
+    pub(super) const STRUCT: u8 = 6;
+    pub(super) const UNION: u8 = 7;
+    pub(super) const ENUM: u8 = 8;
+    pub(super) const FORWARD: u8 = 9;
+    pub(super) const TYPEDEF: u8 = 10;
+    pub(super) const VOLATILE: u8 = 11;
+    pub(super) const CONST: u8 = 12;
+    pub(super) const RESTRICT: u8 = 13;
+    pub(super) const SLICE: u8 = 14;
+}
+
+/// Everything about a type other than whether it's visible by name and the types that it refers to.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Shape<'data> {
+    kind: u8,
+    name: &'data [u8],
+    vlen: u32,
+
+    /// The size of the type, or for forward declarations, the kind of type being declared. Zero for
+    /// kinds where `ctt_type` refers to another type.
+    size: u64,
+
+    /// Kind-specific values, e.g. integer encodings, member offsets and enumerator values.
+    values: Vec<u64>,
+
+    /// The names of struct and union members and of enumerators.
+    member_names: Vec<&'data [u8]>,
+}
+
+#[derive(Debug)]
+struct Type<'data> {
+    shape: Shape<'data>,
+    is_root: bool,
+
+    /// The IDs of the types that this type refers to, in the order that they're written. Zero means
+    /// no type, e.g. the varargs marker at the end of a function's arguments.
+    refs: Vec<u32>,
+}
+
+/// Names of data objects, functions or variables and the IDs of their types.
+type Symbols<'data> = Vec<(&'data [u8], u32)>;
+
+/// An input CTF dictionary.
+pub(crate) struct Dict<'data> {
+    /// Types in order of ID, starting from ID 1.
+    types: Vec<Type<'data>>,
+    objects: Symbols<'data>,
+    functions: Symbols<'data>,
+    variables: Symbols<'data>,
+}
+
+/// Parses the CTF dictionary in an input `.ctf` section.
+pub(crate) fn parse(data: &[u8]) -> Result<Dict<'_>> {
+    if data.len() >= 8 && u64::from_le_bytes(data[..8].try_into().unwrap()) == ARCHIVE_MAGIC {
+        bail!("CTF archives are not supported");
+    }
+    ensure!(data.len() >= HEADER_SIZE, "CTF header is truncated");
+    ensure!(
+        u16::from_le_bytes([data[0], data[1]]) == MAGIC,
+        "Bad CTF magic"
+    );
+    ensure!(data[2] == VERSION_3, "Unsupported CTF version {}", data[2]);
+    let flags = data[3];
+    ensure!(flags & F_COMPRESS == 0, "Compressed CTF is not supported");
+    let header = |index: usize| read_u32(data, 4 + index * 4).map(|value| value as usize);
+    ensure!(
+        header(1)? == 0,
+        "CTF dictionaries with parents are not supported"
+    );
+    let body = &data[HEADER_SIZE..];
+    let section = |start: usize, end: usize| {
+        body.get(header(start)?..header(end)?)
+            .context("CTF section is out of bounds")
+    };
+    let objects = section(4, 5)?;
+    let functions = section(5, 6)?;
+    let object_index = section(6, 7)?;
+    let function_index = section(7, 8)?;
+    let variables = section(8, 9)?;
+    let types = section(9, 10)?;
+    let strings = body
+        .get(header(10)?..header(10)? + header(11)?)
+        .context("CTF string table is out of bounds")?;
+    ensure!(
+        functions.is_empty() || flags & F_NEWFUNCINFO != 0,
+        "Old-style CTF function info is not supported"
+    );
+
+    let types = parse_types(types, strings)?;
+    let num_types = types.len() as u32;
+    let check_type = |type_id: u32| {
+        ensure!(type_id <= num_types, "CTF type {type_id} is out of range");
+        Ok(type_id)
+    };
+    for ty in &types {
+        for &type_id in &ty.refs {
+            check_type(type_id)?;
+        }
+    }
+    let symbols = |types: &[u8], index: &[u8]| -> Result<Symbols> {
+        // Without an index, entries correspond to symbols in the ELF symbol table, which we don't
+        // preserve, so we drop them.
+        if index.len() != types.len() {
+            return Ok(Vec::new());
+        }
+        u32s(index)
+            .zip(u32s(types))
+            .map(|(name, type_id)| Ok((string(strings, name)?, check_type(type_id)?)))
+            .collect()
+    };
+    Ok(Dict {
+        objects: symbols(objects, object_index)?,
+        functions: symbols(functions, function_index)?,
+        variables: u32s(variables)
+            .collect::<Vec<_>>()
+            .chunks_exact(2)
+            .map(|entry| Ok((string(strings, entry[0])?, check_type(entry[1])?)))
+            .collect::<Result<_>>()?,
+        types,
+    })
+}
+
+fn parse_types<'data>(mut data: &'data [u8], strings: &'data [u8]) -> Result<Vec<Type<'data>>> {
+    let mut types = Vec::new();
+    while !data.is_empty() {
+        let mut words = Words(&mut data);
+        let name = string(strings, words.next()?)?;
+        let info = words.next()?;
+        let mut size = u64::from(words.next()?);
+        if size == u64::from(LSIZE_SENT) {
+            size = u64::from(words.next()?) << 32 | u64::from(words.next()?);
+        }
+        let kind = (info >> 26) as u8;
+        let vlen = info & 0xff_ffff;
+        let mut refs = Vec::new();
+        let mut values = Vec::new();
+        let mut member_names = Vec::new();
+        match kind {
+            kind::INTEGER | kind::FLOAT => values.push(u64::from(words.next()?)),
+            kind::POINTER | kind::TYPEDEF | kind::VOLATILE | kind::CONST | kind::RESTRICT => {
+                refs.push(size as u32);
+                size = 0;
+            }
+            kind::ARRAY => {
+                refs.push(words.next()?);
+                refs.push(words.next()?);
+                values.push(u64::from(words.next()?));
+            }
+            kind::FUNCTION => {
+                refs.push(size as u32);
+                size = 0;
+                for _ in 0..vlen {
+                    refs.push(words.next()?);
+                }
+                if vlen % 2 == 1 {
+                    words.next()?;
+                }
+            }
+            kind::STRUCT | kind::UNION => {
+                for _ in 0..vlen {
+                    member_names.push(string(strings, words.next()?)?);
+                    if size >= LSTRUCT_THRESH {
+                        let high = words.next()?;
+                        refs.push(words.next()?);
+                        values.push(u64::from(high) << 32 | u64::from(words.next()?));
+                    } else {
+                        values.push(u64::from(words.next()?));
+                        refs.push(words.next()?);
+                    }
+                }
+            }
+            kind::ENUM => {
+                for _ in 0..vlen {
+                    member_names.push(string(strings, words.next()?)?);
+                    values.push(u64::from(words.next()?));
+                }
+            }
+            kind::SLICE => {
+                refs.push(words.next()?);
+                values.push(u64::from(words.next()?));
+            }
+            kind::FORWARD => {}
+            0 => {}
+            other => bail!("Unsupported CTF type kind {other}"),
+        }
+        types.push(Type {
+            shape: Shape {
+                kind,
+                name,
+                vlen,
+                size,
+                values,
+                member_names,
+            },
+            is_root: info & (1 << 25) != 0,
+            refs,
+        });
+    }
+    Ok(types)
+}
+
+/// Merges the supplied dictionaries into a single dictionary and returns its serialised form. The
+/// variables section is only written if `include_variables` is set, since it's normally redundant
+/// with the data objects section.
+pub(crate) fn merge<'data>(dicts: &[Dict<'data>], include_variables: bool) -> Vec<u8> {
+    // Our nodes are all the types of all the dictionaries. `bases` maps from a dictionary to the
+    // index of the node for its type 1.
+    let mut bases = Vec::with_capacity(dicts.len());
+    let mut nodes = Vec::new();
+    for dict in dicts {
+        bases.push(nodes.len());
+        nodes.extend(dict.types.iter());
+    }
+    let node_for = |dict_index: usize, type_id: u32| {
+        (type_id != 0).then(|| bases[dict_index] + type_id as usize - 1)
+    };
+    let mut refs: Vec<Vec<Option<usize>>> = Vec::with_capacity(nodes.len());
+    for (dict_index, dict) in dicts.iter().enumerate() {
+        for ty in &dict.types {
+            refs.push(
+                ty.refs
+                    .iter()
+                    .map(|&type_id| node_for(dict_index, type_id))
+                    .collect(),
+            );
+        }
+    }
+
+    // Replace references to forward declarations with references to the definition, where that's
+    // unambiguous, then partition again so that types that referred to them can be merged.
+    let classes = partition(&nodes, &refs);
+    let mut definitions: HashMap<(u8, &[u8]), Option<usize>> = HashMap::new();
+    for (node, ty) in nodes.iter().enumerate() {
+        let shape = &ty.shape;
+        if matches!(shape.kind, kind::STRUCT | kind::UNION | kind::ENUM) && !shape.name.is_empty() {
+            match definitions.entry((shape.kind, shape.name)) {
+                Entry::Occupied(mut entry) => {
+                    if let Some(existing) = *entry.get() {
+                        if classes[existing] != classes[node] {
+                            entry.insert(None);
+                        }
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(Some(node));
+                }
+            }
+        }
+    }
+    let resolved: Vec<Option<usize>> = nodes
+        .iter()
+        .map(|ty| {
+            let shape = &ty.shape;
+            if shape.kind != kind::FORWARD {
+                return None;
+            }
+            definitions
+                .get(&(shape.size as u8, shape.name))
+                .copied()
+                .flatten()
+        })
+        .collect();
+    for node_refs in &mut refs {
+        for node in node_refs.iter_mut().flatten() {
+            *node = resolved[*node].unwrap_or(*node);
+        }
+    }
+    let classes = partition(&nodes, &refs);
+
+    // Assign output type IDs to each class in order of first appearance, skipping forward
+    // declarations that we resolved, since nothing refers to them now.
+    let mut type_ids = vec![0; classes.iter().max().map_or(0, |max| max + 1)];
+    let mut representatives = Vec::new();
+    let mut is_root = Vec::new();
+    for (node, &class) in classes.iter().enumerate() {
+        if resolved[node].is_some() {
+            continue;
+        }
+        if type_ids[class] == 0 {
+            representatives.push(node);
+            is_root.push(false);
+            type_ids[class] = representatives.len() as u32;
+        }
+        let index = type_ids[class] as usize - 1;
+        is_root[index] |= nodes[node].is_root;
+    }
+    let type_id = |node: Option<usize>| node.map_or(0, |node| type_ids[classes[node]]);
+
+    // Only the first of several types with the same name stays visible to lookups by name.
+    let mut visible_names = std::collections::HashSet::new();
+    for (index, &node) in representatives.iter().enumerate() {
+        let shape = &nodes[node].shape;
+        if !is_root[index] || shape.name.is_empty() {
+            continue;
+        }
+        let namespace = match shape.kind {
+            kind::STRUCT | kind::UNION | kind::ENUM => shape.kind,
+            kind::FORWARD => shape.size as u8,
+            _ => 0,
+        };
+        if !visible_names.insert((namespace, shape.name)) {
+            is_root[index] = false;
+        }
+    }
+
+    let mut strings = StringTable::default();
+    let mut types = Vec::new();
+    for (index, &node) in representatives.iter().enumerate() {
+        let ty = nodes[node];
+        let shape = &ty.shape;
+        let mut node_refs = refs[node].iter().map(|&node| type_id(node));
+        let info = u32::from(shape.kind) << 26 | u32::from(is_root[index]) << 25 | shape.vlen;
+        push_u32(&mut types, strings.add(shape.name));
+        push_u32(&mut types, info);
+        match shape.kind {
+            kind::POINTER
+            | kind::TYPEDEF
+            | kind::VOLATILE
+            | kind::CONST
+            | kind::RESTRICT
+            | kind::FUNCTION => push_u32(&mut types, node_refs.next().unwrap()),
+            _ => {
+                if shape.size >= u64::from(LSIZE_SENT) {
+                    push_u32(&mut types, LSIZE_SENT);
+                    push_u32(&mut types, (shape.size >> 32) as u32);
+                }
+                push_u32(&mut types, shape.size as u32);
+            }
+        }
+        match shape.kind {
+            kind::INTEGER | kind::FLOAT => push_u32(&mut types, shape.values[0] as u32),
+            kind::ARRAY => {
+                push_u32(&mut types, node_refs.next().unwrap());
+                push_u32(&mut types, node_refs.next().unwrap());
+                push_u32(&mut types, shape.values[0] as u32);
+            }
+            kind::FUNCTION => {
+                for arg in node_refs.by_ref() {
+                    push_u32(&mut types, arg);
+                }
+                if shape.vlen % 2 == 1 {
+                    push_u32(&mut types, 0);
+                }
+            }
+            kind::STRUCT | kind::UNION => {
+                for (name, &offset) in shape.member_names.iter().zip(&shape.values) {
+                    push_u32(&mut types, strings.add(name));
+                    if shape.size >= LSTRUCT_THRESH {
+                        push_u32(&mut types, (offset >> 32) as u32);
+                        push_u32(&mut types, node_refs.next().unwrap());
+                        push_u32(&mut types, offset as u32);
+                    } else {
+                        push_u32(&mut types, offset as u32);
+                        push_u32(&mut types, node_refs.next().unwrap());
+                    }
+                }
+            }
+            kind::ENUM => {
+                for (name, &value) in shape.member_names.iter().zip(&shape.values) {
+                    push_u32(&mut types, strings.add(name));
+                    push_u32(&mut types, value as u32);
+                }
+            }
+            kind::SLICE => {
+                push_u32(&mut types, node_refs.next().unwrap());
+                push_u32(&mut types, shape.values[0] as u32);
+            }
+            _ => {}
+        }
+    }
+
+    // Symbols are looked up by name, so if more than one compilation unit has a symbol with the
+    // same name, e.g. a static function, we can only keep the first.
+    let merge_symbols = |get: for<'a> fn(&'a Dict<'data>) -> &'a Symbols<'data>| {
+        let mut merged: HashMap<&[u8], u32> = HashMap::new();
+        for (dict_index, dict) in dicts.iter().enumerate() {
+            for &(name, id) in get(dict) {
+                merged
+                    .entry(name)
+                    .or_insert_with(|| type_id(node_for(dict_index, id)));
+            }
+        }
+        let mut merged = merged.into_iter().collect::<Vec<_>>();
+        merged.sort_unstable();
+        merged
+    };
+    let objects = merge_symbols(|dict| &dict.objects);
+    let functions = merge_symbols(|dict| &dict.functions);
+    let variables = if include_variables {
+        merge_symbols(|dict| &dict.variables)
+    } else {
+        Vec::new()
+    };
+
+    let mut sections: [Vec<u8>; 6] = Default::default();
+    for (name, id) in &objects {
+        push_u32(&mut sections[0], *id);
+        push_u32(&mut sections[2], strings.add(name));
+    }
+    for (name, id) in &functions {
+        push_u32(&mut sections[1], *id);
+        push_u32(&mut sections[3], strings.add(name));
+    }
+    for (name, id) in &variables {
+        push_u32(&mut sections[4], strings.add(name));
+        push_u32(&mut sections[4], *id);
+    }
+    sections[5] = types;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC.to_le_bytes());
+    out.push(VERSION_3);
+    out.push(F_NEWFUNCINFO | F_IDXSORTED);
+    // Parent label, parent name, compilation unit name and label section offset.
+    for _ in 0..4 {
+        push_u32(&mut out, 0);
+    }
+    let mut offset = 0;
+    for section in &sections {
+        push_u32(&mut out, offset);
+        offset += section.len() as u32;
+    }
+    push_u32(&mut out, offset);
+    push_u32(&mut out, strings.data.len() as u32);
+    for section in &sections {
+        out.extend_from_slice(section);
+    }
+    out.extend_from_slice(&strings.data);
+    out
+}
+
+/// Returns the class of each node, where nodes in the same class have the same shape and refer to
+/// nodes in the same classes. Classes are numbered in order of first appearance.
+fn partition(nodes: &[&Type], refs: &[Vec<Option<usize>>]) -> Vec<usize> {
+    let mut shape_classes = HashMap::new();
+    let mut classes: Vec<usize> = nodes
+        .iter()
+        .map(|ty| {
+            let next = shape_classes.len();
+            *shape_classes.entry(&ty.shape).or_insert(next)
+        })
+        .collect();
+    let mut num_classes = shape_classes.len();
+    loop {
+        let mut ids = HashMap::new();
+        let refined: Vec<usize> = refs
+            .iter()
+            .enumerate()
+            .map(|(node, node_refs)| {
+                let key = (
+                    classes[node],
+                    node_refs
+                        .iter()
+                        .map(|r| r.map(|r| classes[r]))
+                        .collect::<Vec<_>>(),
+                );
+                let next = ids.len();
+                *ids.entry(key).or_insert(next)
+            })
+            .collect();
+        classes = refined;
+        if ids.len() == num_classes {
+            return classes;
+        }
+        num_classes = ids.len();
+    }
+}
+
+#[derive(Default)]
+struct StringTable<'data> {
+    data: Vec<u8>,
+    offsets: HashMap<&'data [u8], u32>,
+}
+
+impl<'data> StringTable<'data> {
+    fn add(&mut self, string: &'data [u8]) -> u32 {
+        if self.data.is_empty() {
+            self.data.push(0);
+        }
+        if string.is_empty() {
+            return 0;
+        }
+        *self.offsets.entry(string).or_insert_with(|| {
+            let offset = self.data.len() as u32;
+            self.data.extend_from_slice(string);
+            self.data.push(0);
+            offset
+        })
+    }
+}
+
+/// Reads consecutive 32 bit values from the front of a slice.
+struct Words<'a, 'data>(&'a mut &'data [u8]);
+
+impl Words<'_, '_> {
+    fn next(&mut self) -> Result<u32> {
+        let value = read_u32(self.0, 0)?;
+        *self.0 = &self.0[4..];
+        Ok(value)
+    }
+}
+
+fn string(strings: &[u8], offset: u32) -> Result<&[u8]> {
+    ensure!(
+        offset & NAME_EXTERNAL == 0,
+        "CTF references to the ELF string table are not supported"
+    );
+    let rest = strings
+        .get(offset as usize..)
+        .context("CTF string is out of bounds")?;
+    let end = memchr::memchr(0, rest).context("CTF string is unterminated")?;
+    Ok(&rest[..end])
+}
+
+fn u32s(data: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    data.chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("CTF data is truncated")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn test_merge_recursive_types() {
+    // Two dictionaries, each with `struct node { struct node *next; }` and an `int`, in different
+    // orders.
+    let node = |next: u32| Type {
+        shape: Shape {
+            kind: kind::STRUCT,
+            name: b"node",
+            vlen: 1,
+            size: 8,
+            values: vec![0],
+            member_names: vec![b"next"],
+        },
+        is_root: true,
+        refs: vec![next],
+    };
+    let pointer = |target: u32| Type {
+        shape: Shape {
+            kind: kind::POINTER,
+            name: b"",
+            vlen: 0,
+            size: 0,
+            values: vec![],
+            member_names: vec![],
+        },
+        is_root: true,
+        refs: vec![target],
+    };
+    let int = || Type {
+        shape: Shape {
+            kind: kind::INTEGER,
+            name: b"int",
+            vlen: 0,
+            size: 4,
+            values: vec![0x0100_0020],
+            member_names: vec![],
+        },
+        is_root: true,
+        refs: vec![],
+    };
+    let dict = |types| Dict {
+        types,
+        objects: vec![],
+        functions: vec![],
+        variables: vec![(b"head".as_slice(), 1)],
+    };
+    let dicts = [
+        dict(vec![node(2), pointer(1), int()]),
+        dict(vec![pointer(3), int(), node(1)]),
+    ];
+    let merged = merge(&dicts, true);
+    let parsed = parse(&merged).unwrap();
+    assert_eq!(parsed.types.len(), 3);
+    assert_eq!(parsed.variables, vec![(b"head".as_slice(), 1)]);
+    assert_eq!(parsed.types[0].refs, vec![2]);
+    assert_eq!(parsed.types[1].refs, vec![1]);
+    assert_eq!(parsed.types[2].shape.name, b"int");
+}
@@ -0,0 +1,165 @@
+//! Support for `.ctors` and `.dtors`, which older toolchains use instead of `.init_array` and
+//! `.fini_array`. The entries of these sections are run from last to first, whereas those of the
+//! arrays are run from first to last, so we reverse them as we move them into the arrays, as gold
+//! does with `--ctors-in-init-array`.
+//!
+//! crtbegin.o and crtend.o from such toolchains mark the ends of the list with -1 and 0 entries
+//! that `__do_global_ctors_aux` looks for. Sections that contain only these sentinels are left in
+//! `.ctors` and `.dtors`, where they form an empty list, since everything else was moved. Sentinels
+//! in other sections are dropped, since the arrays would otherwise try to call them.
+
+use crate::elf::File;
+use crate::elf::SectionHeader;
+use crate::error::Result;
+use crate::layout::Section;
+use crate::output_section_id;
+use anyhow::bail;
+use object::read::elf::Rela as _;
+use object::LittleEndian;
+
+const ENTRY_SIZE: u64 = size_of::<u64>() as u64;
+
+/// Where each entry of a `.ctors` or `.dtors` section goes once it's been moved into `.init_array`
+/// or `.fini_array`.
+pub(crate) struct LegacyArray {
+    /// The output offset of each input entry, or `None` for sentinels, which we drop.
+    output_offsets: Vec<Option<u64>>,
+
+    /// The size of the section once sentinels have been dropped.
+    pub(crate) size: u64,
+}
+
+/// Returns whether `name` is that of a `.ctors` or `.dtors` section, possibly with a priority.
+fn is_legacy_name(name: &[u8]) -> bool {
+    [b".ctors".as_slice(), b".dtors".as_slice()]
+        .iter()
+        .any(|prefix| {
+            name.strip_prefix(*prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"."))
+        })
+}
+
+/// Returns whether `section`, which is at `section_index` in `object`, is a `.ctors` or `.dtors`
+/// section that should be moved into `.init_array` or `.fini_array`.
+pub(crate) fn should_convert(
+    object: &File,
+    section_index: object::SectionIndex,
+    section: &SectionHeader,
+) -> Result<bool> {
+    if !is_legacy_name(object.section_name(section)?) {
+        return Ok(false);
+    }
+    let size = object.section_size(section)?;
+    if size % ENTRY_SIZE != 0 {
+        return Ok(false);
+    }
+    let sentinels = find_sentinels(object, section_index, section)?;
+    Ok(sentinels.iter().any(|is_sentinel| !is_sentinel))
+}
+
+/// Returns for each entry of the section whether it's a sentinel, i.e. a -1 or 0 entry that isn't
+/// relocated.
+fn find_sentinels(
+    object: &File,
+    section_index: object::SectionIndex,
+    section: &SectionHeader,
+) -> Result<Vec<bool>> {
+    let data = object.raw_section_data(section)?;
+    let mut sentinels = data
+        .chunks_exact(ENTRY_SIZE as usize)
+        .map(|entry| {
+            let value = u64::from_le_bytes(entry.try_into().unwrap());
+            value == 0 || value == u64::MAX
+        })
+        .collect::<Vec<_>>();
+    for rel in object.relocations(section_index)? {
+        let entry_index = rel.r_offset(LittleEndian) / ENTRY_SIZE;
+        if let Some(is_sentinel) = sentinels.get_mut(entry_index as usize) {
+            *is_sentinel = false;
+        }
+    }
+    Ok(sentinels)
+}
+
+impl LegacyArray {
+    /// Returns the layout of the entries of `section` if it's a `.ctors` or `.dtors` section that
+    /// we're moving into `.init_array` or `.fini_array`.
+    pub(crate) fn for_section(object: &File, section: &Section) -> Result<Option<Self>> {
+        let section_id = section.output_section_id();
+        if section_id != output_section_id::INIT_ARRAY
+            && section_id != output_section_id::FINI_ARRAY
+        {
+            return Ok(None);
+        }
+        let header = object.section(section.index)?;
+        if !is_legacy_name(object.section_name(header)?) {
+            return Ok(None);
+        }
+        let sentinels = find_sentinels(object, section.index, header)?;
+        let mut output_offsets = vec![None; sentinels.len()];
+        let mut size = 0;
+        for (input_index, is_sentinel) in sentinels.iter().enumerate().rev() {
+            if !is_sentinel {
+                output_offsets[input_index] = Some(size);
+                size += ENTRY_SIZE;
+            }
+        }
+        Ok(Some(Self {
+            output_offsets,
+            size,
+        }))
+    }
+
+    /// Returns where the byte at `input_offset` in the input section goes in the output, or `None`
+    /// if it's part of a sentinel.
+    pub(crate) fn output_offset(&self, input_offset: u64) -> Option<u64> {
+        let output_offset = (*self
+            .output_offsets
+            .get((input_offset / ENTRY_SIZE) as usize)?)?;
+        Some(output_offset + input_offset % ENTRY_SIZE)
+    }
+
+    /// Copies the entries from `input` to their places in `out`.
+    pub(crate) fn copy(&self, input: &[u8], out: &mut [u8]) -> Result {
+        if out.len() as u64 != self.size {
+            bail!(
+                "Expected {} bytes for reversed .ctors/.dtors, got {}",
+                self.size,
+                out.len()
+            );
+        }
+        for (entry, output_offset) in input
+            .chunks_exact(ENTRY_SIZE as usize)
+            .zip(&self.output_offsets)
+        {
+            if let Some(output_offset) = *output_offset {
+                let start = output_offset as usize;
+                out[start..start + ENTRY_SIZE as usize].copy_from_slice(entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_is_legacy_name() {
+    assert!(is_legacy_name(b".ctors"));
+    assert!(is_legacy_name(b".dtors.65434"));
+    assert!(!is_legacy_name(b".ctorsx"));
+    assert!(!is_legacy_name(b".init_array"));
+}
+
+#[test]
+fn test_reversal() {
+    let array = LegacyArray {
+        output_offsets: vec![None, Some(8), Some(0)],
+        size: 16,
+    };
+    assert_eq!(array.output_offset(8), Some(8));
+    assert_eq!(array.output_offset(20), Some(4));
+    assert_eq!(array.output_offset(3), None);
+    let input = [[0xff; 8], [1; 8], [2; 8]].concat();
+    let mut out = [0; 16];
+    array.copy(&input, &mut out).unwrap();
+    assert_eq!(out, [[2; 8], [1; 8]].concat().as_slice());
+}
@@ -20,7 +20,7 @@ pub(crate) fn init() {
         false
     });
 
-    tracing_subscriber::registry()
+    let _ = tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer().with_filter(filter))
-        .init();
+        .try_init();
 }
@@ -0,0 +1,50 @@
+//! Demangling of symbol names for diagnostics and reports. We handle Rust (legacy and v0) and
+//! Itanium C++ names. Names that aren't mangled, or that fail to demangle, are shown as is.
+//! Demangling can be turned off with `--no-demangle`.
+
+use crate::args::Args;
+
+/// Returns `name` for display to the user, demangled if that's enabled.
+pub(crate) fn display_name(name: &[u8], args: &Args) -> String {
+    if args.demangle {
+        demangle(name)
+    } else {
+        String::from_utf8_lossy(name).into_owned()
+    }
+}
+
+fn demangle(name: &[u8]) -> String {
+    // Keep any symbol version, e.g. `@@GLIBC_2.2.5`, but don't try to demangle it.
+    let (base, version) = match memchr::memchr(b'@', name) {
+        Some(at) => name.split_at(at),
+        None => (name, &[][..]),
+    };
+    let base_str = String::from_utf8_lossy(base);
+    let demangled = if let Ok(symbol) = rustc_demangle::try_demangle(&base_str) {
+        // The alternate format omits the hash from legacy names.
+        Some(format!("{symbol:#}"))
+    } else if base.starts_with(b"_Z") {
+        cpp_demangle::Symbol::new(base)
+            .ok()
+            .and_then(|symbol| symbol.demangle(&Default::default()).ok())
+    } else {
+        None
+    };
+    let mut out = demangled.unwrap_or_else(|| base_str.into_owned());
+    out.push_str(&String::from_utf8_lossy(version));
+    out
+}
+
+#[test]
+fn test_demangle() {
+    assert_eq!(demangle(b"main"), "main");
+    assert_eq!(demangle(b"_ZN3foo3barEv"), "foo::bar()");
+    assert_eq!(demangle(b"_Z3addii"), "add(int, int)");
+    assert_eq!(
+        demangle(b"_ZN4core3fmt5write17h0123456789abcdefE"),
+        "core::fmt::write"
+    );
+    assert_eq!(demangle(b"_RNvCs1234_7mycrate3foo"), "mycrate::foo");
+    assert_eq!(demangle(b"_Z3addii@@VER_1"), "add(int, int)@@VER_1");
+    assert_eq!(demangle(b"_Znot-valid"), "_Znot-valid");
+}
@@ -0,0 +1,53 @@
+//! Support for `--dependency-file`. Once the link has succeeded, we write a depfile in the syntax
+//! understood by Make and Ninja, listing every file that we read as a prerequisite of the output.
+//! This includes files that we found by searching library paths and files named by linker scripts,
+//! so build systems re-link when any of them change. As with `-MP` in compilers, each input also
+//! gets an empty rule so that deleting an input doesn't break the build.
+
+use crate::args::Args;
+use crate::error::Result;
+use crate::input_data::InputData;
+use anyhow::Context;
+use std::fmt::Write as _;
+use std::path::Path;
+
+pub(crate) fn write(path: &Path, args: &Args, input_data: &InputData) -> Result {
+    std::fs::write(path, contents(args, input_data)?)
+        .with_context(|| format!("Failed to write dependency file `{}`", path.display()))
+}
+
+fn contents(args: &Args, input_data: &InputData) -> Result<String> {
+    let paths = input_data.dependency_paths();
+    let mut out = String::new();
+    write!(&mut out, "{}:", escape(&args.output))?;
+    for path in &paths {
+        write!(&mut out, " \\\n  {}", escape(path))?;
+    }
+    writeln!(&mut out)?;
+    for path in &paths {
+        write!(&mut out, "\n{}:\n", escape(path))?;
+    }
+    Ok(out)
+}
+
+/// Escapes characters that have special meaning in Make rules.
+fn escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape(Path::new("a/b.o")), "a/b.o");
+    assert_eq!(escape(Path::new("my dir/$x#1.o")), "my\\ dir/$$x\\#1.o");
+}
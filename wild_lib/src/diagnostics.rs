@@ -0,0 +1,126 @@
+//! Filtering of warnings that relate to particular input files. `--suppress-warnings-from` lets
+//! users silence some or all categories of warning for inputs whose paths match a glob, e.g. so
+//! that `--fatal-warnings` can stay on while linking known-noisy third-party objects.
+
+use crate::args::Args;
+use crate::error::Result;
+use anyhow::bail;
+use anyhow::Context;
+use std::path::Path;
+
+/// The kinds of warnings that we issue about particular input files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WarningCategory {
+    /// An archive member that's a copy of a member of an earlier archive was ignored.
+    DuplicateArchiveMember,
+
+    /// A DT_NEEDED entry of a shared object couldn't be found.
+    MissingDependency,
+
+    /// An undefined symbol is only defined by a library that wasn't given on the command line.
+    IndirectUndefined,
+
+    /// An input section needs to be writable or executable, but its segment isn't.
+    SectionFlags,
+}
+
+impl WarningCategory {
+    const ALL: &'static [WarningCategory] = &[
+        WarningCategory::DuplicateArchiveMember,
+        WarningCategory::MissingDependency,
+        WarningCategory::IndirectUndefined,
+        WarningCategory::SectionFlags,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            WarningCategory::DuplicateArchiveMember => "duplicate-archive-member",
+            WarningCategory::MissingDependency => "missing-dependency",
+            WarningCategory::IndirectUndefined => "indirect-undefined",
+            WarningCategory::SectionFlags => "section-flags",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.name() == name)
+    }
+}
+
+/// A single `--suppress-warnings-from` argument.
+#[derive(Debug)]
+pub(crate) struct WarningSuppression {
+    /// The categories to suppress. Empty means all categories.
+    categories: Vec<WarningCategory>,
+
+    patterns: Vec<glob::Pattern>,
+}
+
+impl WarningSuppression {
+    /// Parses `[CATEGORY,...:]GLOB`. A relative glob matches anywhere in an input's path, so
+    /// `third_party/**` matches both `third_party/a.o` and `/src/third_party/a.o`.
+    pub(crate) fn parse(value: &str) -> Result<Self> {
+        let (categories, glob) = match value.split_once(':') {
+            Some((categories, glob)) if !categories.contains('/') => {
+                let categories = categories
+                    .split(',')
+                    .map(|name| {
+                        WarningCategory::from_name(name).with_context(|| {
+                            format!(
+                                "Unknown warning category `{name}`. Valid categories are: {}",
+                                WarningCategory::ALL
+                                    .iter()
+                                    .map(|c| c.name())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (categories, glob)
+            }
+            _ => (Vec::new(), value),
+        };
+        if glob.is_empty() {
+            bail!("Missing glob in --suppress-warnings-from={value}");
+        }
+        let mut patterns = vec![glob::Pattern::new(glob)?];
+        if !glob.starts_with('/') && !glob.starts_with("**") {
+            patterns.push(glob::Pattern::new(&format!("**/{glob}"))?);
+        }
+        Ok(Self {
+            categories,
+            patterns,
+        })
+    }
+
+    fn suppresses(&self, category: WarningCategory, input: &Path) -> bool {
+        (self.categories.is_empty() || self.categories.contains(&category))
+            && self.patterns.iter().any(|p| p.matches_path(input))
+    }
+}
+
+/// Returns whether a warning of `category` about `input` should be issued.
+pub(crate) fn should_warn(args: &Args, category: WarningCategory, input: &Path) -> bool {
+    !args
+        .suppress_warnings_from
+        .iter()
+        .any(|s| s.suppresses(category, input))
+}
+
+#[test]
+fn test_warning_suppression() {
+    let all = WarningSuppression::parse("third_party/**").unwrap();
+    let dup = WarningCategory::DuplicateArchiveMember;
+    let missing = WarningCategory::MissingDependency;
+    assert!(all.suppresses(dup, Path::new("third_party/a.o")));
+    assert!(all.suppresses(missing, Path::new("/src/third_party/x/libfoo.a")));
+    assert!(!all.suppresses(dup, Path::new("src/a.o")));
+
+    let some = WarningSuppression::parse("duplicate-archive-member:/vendor/*.a").unwrap();
+    assert!(some.suppresses(dup, Path::new("/vendor/libz.a")));
+    assert!(!some.suppresses(missing, Path::new("/vendor/libz.a")));
+    assert!(!some.suppresses(dup, Path::new("/src/vendor/libz.a")));
+
+    assert!(WarningSuppression::parse("no-such-category:*.o").is_err());
+    assert!(WarningSuppression::parse("missing-dependency:").is_err());
+}
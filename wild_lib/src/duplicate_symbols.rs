@@ -0,0 +1,98 @@
+//! Reporting of symbols that have more than one strong definition. As for undefined symbols, each
+//! definition is listed in the same format as lld and mold, with the source file and line if the
+//! defining object has debug info, then the object and the section containing the definition.
+//!
+//! Definitions in section groups (COMDAT) are expected to have copies in other objects, so they're
+//! never reported. `-z muldefs` and `--allow-multiple-definition` turn off reporting entirely, in
+//! which case the first definition is used.
+
+use crate::error::Diagnostic;
+use crate::error::DiagnosticLocation;
+use crate::error::Error;
+use crate::error::Result;
+use crate::resolution::ResolvedFile;
+use crate::resolution::ResolvedGroup;
+use crate::source_location::ObjectLocation;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolId;
+use linker_utils::elf::shf;
+use linker_utils::elf::SectionFlags;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+
+/// Returns an error for each of `duplicates` that really is defined more than once. Each entry of
+/// `duplicates` contains the strong, non-dynamic definitions of one symbol.
+pub(crate) fn check(
+    symbol_db: &SymbolDb,
+    resolved: &[ResolvedGroup],
+    duplicates: &[Vec<SymbolId>],
+) -> Result<Vec<Error>> {
+    if duplicates.is_empty() || symbol_db.args.allow_multiple_definition {
+        return Ok(Vec::new());
+    }
+    let mut errors = Vec::new();
+    for definitions in duplicates {
+        let locations = definitions
+            .iter()
+            .filter_map(|&symbol_id| {
+                definition_location(symbol_db, resolved, symbol_id).transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if locations.len() < 2 {
+            continue;
+        }
+        let name = symbol_db.symbol_name(definitions[0])?;
+        let symbol = crate::demangle::display_name(name.bytes(), symbol_db.args);
+        errors.push(Error::new(Diagnostic {
+            kind: "duplicate-symbol",
+            summary: "duplicate symbol",
+            symbol,
+            locations,
+            location_label: "defined at",
+            prefer_function: false,
+            note: None,
+        }));
+    }
+    Ok(errors)
+}
+
+/// Returns where `symbol_id` is defined, or `None` if it's a definition that is allowed to be
+/// duplicated.
+fn definition_location(
+    symbol_db: &SymbolDb,
+    resolved: &[ResolvedGroup],
+    symbol_id: SymbolId,
+) -> Result<Option<DiagnosticLocation>> {
+    let file_id = symbol_db.file_id_for_symbol(symbol_id);
+    let ResolvedFile::Object(obj) = &resolved[file_id.group()].files[file_id.file()] else {
+        return Ok(None);
+    };
+    let symbol_index = symbol_id.to_input(obj.symbol_id_range);
+    let symbol = obj.object.symbol(symbol_index)?;
+    if symbol.st_bind() == object::elf::STB_GNU_UNIQUE {
+        return Ok(None);
+    }
+    let input = crate::map_file::display_input(&obj.input);
+    let Some(section_index) = obj.object.symbol_section(symbol, symbol_index)? else {
+        return Ok(Some(DiagnosticLocation {
+            file: input,
+            section: None,
+            section_offset: symbol.st_value(LittleEndian),
+            file_offset: None,
+            function: None,
+            source: None,
+        }));
+    };
+    let section = obj.object.section(section_index)?;
+    if SectionFlags::from_header(section).contains(shf::GROUP) {
+        return Ok(None);
+    }
+    ObjectLocation {
+        input,
+        object: obj.object,
+        section: section_index,
+        offset: symbol.st_value(LittleEndian),
+    }
+    .describe(symbol_db.args)
+    .map(Some)
+}
@@ -9,6 +9,7 @@ use linker_utils::elf::rel_type_to_string;
 use linker_utils::elf::sht;
 use linker_utils::elf::SectionType;
 use object::read::elf::CompressionHeader;
+use object::read::elf::Dyn as _;
 use object::read::elf::FileHeader as _;
 use object::read::elf::ProgramHeader as _;
 use object::read::elf::RelocationSections;
@@ -17,6 +18,7 @@ use object::LittleEndian;
 use std::borrow::Cow;
 use std::io::Read as _;
 use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 
 /// Our starting address in memory when linking non-relocatable executables. We can start memory
 /// addresses wherever we like, even from 0. We pick 400k because it's the same as what ld does and
@@ -55,6 +57,8 @@ pub(crate) struct File<'data> {
 
 impl<'data> File<'data> {
     pub(crate) fn parse(data: &'data [u8], is_dynamic: bool) -> Result<Self> {
+        // Archive members don't go through `FileKind::identify_bytes`, so check them here.
+        crate::file_kind::check_target(data)?;
         let header = FileHeader::parse(data)?;
         let endian = header.endian()?;
         let sections = header.sections(endian, data)?;
@@ -115,8 +119,11 @@ impl<'data> File<'data> {
         self.sections.section_by_name(LittleEndian, name.as_bytes())
     }
 
+    /// Returns the name of `section`. Legacy compressed debug sections (`.zdebug_*`) are reported
+    /// under the name of the `.debug_*` section that they decompress to.
     pub(crate) fn section_name(&self, section: &SectionHeader) -> Result<&'data [u8]> {
-        Ok(self.sections.section_name(LittleEndian, section)?)
+        let name = self.sections.section_name(LittleEndian, section)?;
+        Ok(decompressed_debug_name(name).unwrap_or(name))
     }
 
     pub(crate) fn section_display_name(&self, index: object::SectionIndex) -> Cow<'data, str> {
@@ -142,13 +149,12 @@ impl<'data> File<'data> {
             .loaded_bytes
             .fetch_add(data.len(), Ordering::Relaxed);
 
-        if let Some((compression, _, _)) = section.compression(LittleEndian, self.data)? {
+        if let Some(compression) = self.compression(section)? {
             loaded_metrics
                 .loaded_compressed_bytes
                 .fetch_add(data.len(), Ordering::Relaxed);
-            let len = self.section_size(section)?;
-            let decompressed = member.alloc_slice_fill_default(len as usize);
-            decompress_into(compression, &data[COMPRESSION_HEADER_SIZE..], decompressed)?;
+            let decompressed = member.alloc_slice_fill_default(compression.size as usize);
+            compression.decompress_into(data, decompressed)?;
             loaded_metrics
                 .decompressed_bytes
                 .fetch_add(decompressed.len(), Ordering::Relaxed);
@@ -163,8 +169,8 @@ impl<'data> File<'data> {
     pub(crate) fn copy_section_data(&self, section: &SectionHeader, out: &mut [u8]) -> Result {
         let data = section.data(LittleEndian, self.data)?;
 
-        if let Some((compression, _, _)) = section.compression(LittleEndian, self.data)? {
-            decompress_into(compression, &data[COMPRESSION_HEADER_SIZE..], out)?;
+        if let Some(compression) = self.compression(section)? {
+            compression.decompress_into(data, out)?;
         } else {
             out.copy_from_slice(data);
         }
@@ -172,17 +178,47 @@ impl<'data> File<'data> {
     }
 
     pub(crate) fn section_size(&self, section: &SectionHeader) -> Result<u64> {
-        Ok(section.compression(LittleEndian, self.data)?.map_or_else(
-            || section.sh_size.get(LittleEndian),
-            |compression| compression.0.ch_size(LittleEndian),
-        ))
+        Ok(self
+            .compression(section)?
+            .map_or_else(|| section.sh_size.get(LittleEndian), |c| c.size))
     }
 
     pub(crate) fn section_alignment(&self, section: &SectionHeader) -> Result<u64> {
-        Ok(section.compression(LittleEndian, self.data)?.map_or_else(
-            || section.sh_addralign(LittleEndian),
-            |compression| compression.0.ch_addralign(LittleEndian),
-        ))
+        Ok(self
+            .compression(section)?
+            .map_or_else(|| section.sh_addralign(LittleEndian), |c| c.alignment))
+    }
+
+    /// Returns how `section` is compressed, or None if it isn't. Sections can be compressed either
+    /// with SHF_COMPRESSED, or in the legacy GNU format used by `.zdebug_*` sections.
+    fn compression(&self, section: &SectionHeader) -> Result<Option<SectionCompression>> {
+        if let Some((header, _, _)) = section.compression(LittleEndian, self.data)? {
+            return Ok(Some(SectionCompression {
+                format: header.ch_type(LittleEndian),
+                size: header.ch_size(LittleEndian),
+                alignment: header.ch_addralign(LittleEndian),
+                header_size: COMPRESSION_HEADER_SIZE,
+            }));
+        }
+        let name = self.sections.section_name(LittleEndian, section)?;
+        if !name.starts_with(b".zdebug") {
+            return Ok(None);
+        }
+        // The data starts with "ZLIB", then the decompressed size as a big-endian 64 bit integer.
+        let data = section.data(LittleEndian, self.data)?;
+        let Some(size) = data
+            .strip_prefix(b"ZLIB")
+            .and_then(|rest| rest.get(..8))
+            .map(|size| u64::from_be_bytes(size.try_into().unwrap()))
+        else {
+            return Ok(None);
+        };
+        Ok(Some(SectionCompression {
+            format: object::elf::ELFCOMPRESS_ZLIB,
+            size,
+            alignment: section.sh_addralign(LittleEndian),
+            header_size: 12,
+        }))
     }
 
     pub(crate) fn relocations(&self, index: object::SectionIndex) -> Result<&'data [Rela]> {
@@ -228,12 +264,116 @@ impl<'data> File<'data> {
     }
 }
 
-fn decompress_into(
-    compression: &object::elf::CompressionHeader64<LittleEndian>,
-    input: &[u8],
-    out: &mut [u8],
-) -> Result {
-    match compression.ch_type.get(LittleEndian) {
+#[derive(Default)]
+pub(crate) struct DynamicTagValues<'data> {
+    pub(crate) verdefnum: u64,
+    pub(crate) soname: Option<&'data [u8]>,
+    /// The names of the shared objects listed in DT_NEEDED.
+    pub(crate) needed: Vec<&'data [u8]>,
+}
+
+impl<'data> DynamicTagValues<'data> {
+    pub(crate) fn read(file: &File<'data>) -> Result<Self> {
+        let mut values = DynamicTagValues::default();
+        let Ok(dynamic_tags) = file.dynamic_tags() else {
+            return Ok(values);
+        };
+        let e = LittleEndian;
+        for entry in dynamic_tags {
+            let value = entry.d_val(e);
+            match entry.d_tag(e) as u32 {
+                object::elf::DT_VERDEFNUM => {
+                    values.verdefnum = value;
+                }
+                object::elf::DT_SONAME => {
+                    values.soname = Some(
+                        file.symbols
+                            .strings()
+                            .get(value as u32)
+                            .map_err(|()| anyhow!("Invalid DT_SONAME 0x{value:x}"))?,
+                    );
+                }
+                object::elf::DT_NEEDED => {
+                    values.needed.push(
+                        file.symbols
+                            .strings()
+                            .get(value as u32)
+                            .map_err(|()| anyhow!("Invalid DT_NEEDED 0x{value:x}"))?,
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(values)
+    }
+}
+
+struct SectionCompression {
+    /// One of the ELFCOMPRESS_* constants.
+    format: u32,
+
+    /// The size of the section once decompressed.
+    size: u64,
+
+    /// The alignment of the section once decompressed.
+    alignment: u64,
+
+    /// The number of bytes at the start of the section data before the compressed stream.
+    header_size: usize,
+}
+
+impl SectionCompression {
+    fn decompress_into(&self, data: &[u8], out: &mut [u8]) -> Result {
+        let input = data
+            .get(self.header_size..)
+            .context("Compressed section is smaller than its header")?;
+        decompress_into(self.format, input, out)
+    }
+}
+
+/// Returns the name of the `.debug*` section that the legacy compressed section `name` holds, if
+/// `name` is a `.zdebug*` section. That's the same name without the `z`, but since that isn't in the
+/// input, names other than the standard ones are copied and kept for the rest of the link.
+fn decompressed_debug_name(name: &[u8]) -> Option<&'static [u8]> {
+    const DEBUG_SECTION_NAMES: &[&[u8]] = &[
+        b".debug_abbrev",
+        b".debug_addr",
+        b".debug_aranges",
+        b".debug_frame",
+        b".debug_info",
+        b".debug_line",
+        b".debug_line_str",
+        b".debug_loc",
+        b".debug_loclists",
+        b".debug_macinfo",
+        b".debug_macro",
+        b".debug_names",
+        b".debug_pubnames",
+        b".debug_pubtypes",
+        b".debug_ranges",
+        b".debug_rnglists",
+        b".debug_str",
+        b".debug_str_offsets",
+        b".debug_types",
+    ];
+    static OTHER_NAMES: Mutex<Vec<&'static [u8]>> = Mutex::new(Vec::new());
+
+    let suffix = name.strip_prefix(b".zdebug")?;
+    let matches = |debug_name: &&[u8]| debug_name.strip_prefix(b".debug") == Some(suffix);
+    if let Some(debug_name) = DEBUG_SECTION_NAMES.iter().copied().find(matches) {
+        return Some(debug_name);
+    }
+    let mut other_names = OTHER_NAMES.lock().unwrap();
+    if let Some(debug_name) = other_names.iter().copied().find(matches) {
+        return Some(debug_name);
+    }
+    let debug_name: &'static [u8] = [b".debug", suffix].concat().leak();
+    other_names.push(debug_name);
+    Some(debug_name)
+}
+
+fn decompress_into(format: u32, input: &[u8], out: &mut [u8]) -> Result {
+    match format {
         object::elf::ELFCOMPRESS_ZLIB => {
             flate2::Decompress::new(true).decompress(
                 input,
@@ -364,10 +504,31 @@ pub(crate) const PLT_ENTRY_TEMPLATE: &[u8] = &[
     0x0f, 0x1f, 0x44, 0x0, 0x0, // nopl   0x0(%rax,%rax,1)
 ];
 
+/// The first entry of .plt when binding lazily. Pushes the identifier that the dynamic loader
+/// stored in the second .got.plt entry, then jumps to the resolver stored in the third.
+pub(crate) const PLT0_TEMPLATE: &[u8] = &[
+    0xff, 0x35, 0x0, 0x0, 0x0, 0x0, // push {relative .got.plt+8}(%rip)
+    0xff, 0x25, 0x0, 0x0, 0x0, 0x0, // jmp *{relative .got.plt+16}(%rip)
+    0x0f, 0x1f, 0x40, 0x0, // nopl   0x0(%rax)
+];
+
+/// A lazily bound PLT entry. Until the function has been bound, its .got.plt entry points at the
+/// push, so the first call passes the index of its relocation to the resolver via PLT0.
+pub(crate) const LAZY_PLT_ENTRY_TEMPLATE: &[u8] = &[
+    0xff, 0x25, 0x0, 0x0, 0x0, 0x0, // jmp *{relative .got.plt entry}(%rip)
+    0x68, 0x0, 0x0, 0x0, 0x0, // push {relocation index}
+    0xe9, 0x0, 0x0, 0x0, 0x0, // jmp {relative PLT0}
+];
+
+/// The number of entries at the start of .got.plt that are reserved for the dynamic loader.
+pub(crate) const NUM_RESERVED_GOT_PLT_ENTRIES: u64 = 3;
+
 const _ASSERTS: () = {
     assert!(FILE_HEADER_SIZE as usize == std::mem::size_of::<FileHeader>());
     assert!(PROGRAM_HEADER_SIZE as usize == std::mem::size_of::<ProgramHeader>());
     assert!(SECTION_HEADER_SIZE as usize == std::mem::size_of::<SectionHeader>());
+    assert!(PLT0_TEMPLATE.len() == PLT_ENTRY_SIZE as usize);
+    assert!(LAZY_PLT_ENTRY_TEMPLATE.len() == PLT_ENTRY_SIZE as usize);
 };
 
 /// For additional information on ELF relocation types, see "ELF-64 Object File Format" -
@@ -434,11 +595,14 @@ impl RelocationKindInfo {
             object::elf::R_X86_64_PC32 => (RelocationKind::Relative, 4),
             object::elf::R_X86_64_PC64 => (RelocationKind::Relative, 8),
             object::elf::R_X86_64_GOT32 => (RelocationKind::GotRelGotBase, 4),
-            object::elf::R_X86_64_GOT64 => (RelocationKind::GotRelGotBase, 8),
+            object::elf::R_X86_64_GOT64 | object::elf::R_X86_64_GOTPLT64 => {
+                (RelocationKind::GotRelGotBase, 8)
+            }
             object::elf::R_X86_64_GOTOFF64 => (RelocationKind::SymRelGotBase, 8),
             object::elf::R_X86_64_PLT32 => (RelocationKind::PltRelative, 4),
             object::elf::R_X86_64_PLTOFF64 => (RelocationKind::PltRelGotBase, 8),
             object::elf::R_X86_64_GOTPCREL => (RelocationKind::GotRelative, 4),
+            object::elf::R_X86_64_GOTPCREL64 => (RelocationKind::GotRelative, 8),
 
             // For now, we rely on GOTPC64 and GOTPC32 always referencing the symbol
             // _GLOBAL_OFFSET_TABLE_, which means that we can just treat these a normal relative
@@ -475,3 +639,58 @@ pub(crate) fn slice_from_all_bytes_mut<T: object::Pod>(data: &mut [u8]) -> &mut
         .unwrap()
         .0
 }
+
+/// Legacy compressed debug sections are renamed by dropping the `z`, even if they're not one of the
+/// standard debug sections.
+#[test]
+fn test_zdebug_sections() {
+    use object::write;
+    use std::io::Write as _;
+
+    let contents = b"some debug info that's been compressed";
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(contents).unwrap();
+    let mut compressed = b"ZLIB".to_vec();
+    compressed.extend_from_slice(&(contents.len() as u64).to_be_bytes());
+    compressed.extend_from_slice(&encoder.finish().unwrap());
+
+    let mut builder = write::Object::new(
+        object::BinaryFormat::Elf,
+        object::Architecture::X86_64,
+        object::Endianness::Little,
+    );
+    for name in [
+        &b".zdebug_info"[..],
+        b".zdebug_wild_custom",
+        b".zdebug_wild_custom",
+    ] {
+        let section = builder.add_section(Vec::new(), name.to_vec(), object::SectionKind::Debug);
+        builder.append_section_data(section, &compressed, 1);
+    }
+    let bytes = builder.write().unwrap();
+    let file = File::parse(&bytes, false).unwrap();
+
+    let names = file
+        .sections
+        .iter()
+        .skip(1)
+        .map(|section| file.section_name(section).unwrap())
+        .filter(|name| name.starts_with(b".debug") || name.starts_with(b".zdebug"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            &b".debug_info"[..],
+            b".debug_wild_custom",
+            b".debug_wild_custom"
+        ]
+    );
+    // Both custom sections should share the copy of their name.
+    assert!(std::ptr::eq(names[1], names[2]));
+
+    let (_, section) = file.section_by_name(".zdebug_wild_custom").unwrap();
+    assert_eq!(file.section_size(section).unwrap(), contents.len() as u64);
+    let mut out = vec![0; contents.len()];
+    file.copy_section_data(section, &mut out).unwrap();
+    assert_eq!(out, contents);
+}
@@ -1,6 +1,10 @@
 use crate::alignment;
 use crate::args::Args;
+use crate::args::BuildId;
+use crate::args::Defsym;
+use crate::args::DefsymValue;
 use crate::args::OutputKind;
+use crate::ctors::LegacyArray;
 use crate::debug_assert_bail;
 use crate::elf;
 use crate::elf::slice_from_all_bytes_mut;
@@ -18,8 +22,14 @@ use crate::elf::Vernaux;
 use crate::elf::Verneed;
 use crate::elf::Versym;
 use crate::error::Result;
+use crate::input_data::FileId;
+use crate::io_probe;
+use crate::io_probe::IoReport;
 use crate::layout::compute_allocations;
+use crate::layout::compute_file_sizes;
 use crate::layout::get_merged_string_output_address;
+use crate::layout::placement_indexes;
+use crate::layout::CiePlacement;
 use crate::layout::DynamicLayout;
 use crate::layout::EpilogueLayout;
 use crate::layout::FileLayout;
@@ -34,16 +44,22 @@ use crate::layout::ResolutionFlags;
 use crate::layout::Section;
 use crate::layout::StringOffsetCache;
 use crate::layout::SymbolCopyInfo;
+use crate::layout::SymtabShard;
+use crate::layout::SYMTAB_SHARD_SIZE;
 use crate::output_section_id;
 use crate::output_section_id::OrderEvent;
 use crate::output_section_id::OutputSectionId;
 use crate::output_section_id::OutputSections;
 use crate::output_section_map::OutputSectionMap;
 use crate::output_section_part_map::OutputSectionPartMap;
+use crate::parsing::InternalSymDefInfo;
 use crate::part_id;
+use crate::post_layout::PostLayoutHook;
 use crate::program_segments::STACK;
 use crate::relaxation::Relaxation;
 use crate::relaxation::RelocationModifier;
+use crate::relr::RelrWriter;
+use crate::resolution::DuplicateSection;
 use crate::resolution::SectionSlot;
 use crate::resolution::ValueFlags;
 use crate::sharding::ShardKey;
@@ -55,6 +71,7 @@ use ahash::AHashMap;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
+use fxhash::FxHashMap;
 use linker_utils::elf::rel_type_to_string;
 use linker_utils::elf::shf;
 use linker_utils::elf::sht;
@@ -70,6 +87,7 @@ use std::ops::Deref;
 use std::ops::DerefMut;
 use std::ops::Range;
 use std::ops::Sub;
+use std::os::unix::fs::FileExt as _;
 use std::path::Path;
 use std::sync::atomic::Ordering::Relaxed;
 use std::sync::mpsc::Receiver;
@@ -97,6 +115,7 @@ pub(crate) struct SizedOutput {
     file: std::fs::File,
     out: OutputBuffer,
     path: Arc<Path>,
+    io_report: IoReport,
 }
 
 enum OutputBuffer {
@@ -105,15 +124,26 @@ enum OutputBuffer {
 }
 
 impl OutputBuffer {
-    fn new(file: &std::fs::File, file_size: u64) -> Self {
-        Self::new_mmapped(file, file_size)
-            .unwrap_or_else(|| Self::InMemory(vec![0; file_size as usize]))
+    fn new(file: &std::fs::File, file_size: u64, io_report: &mut IoReport) -> Result<Self> {
+        match Self::new_mmapped(file, file_size, io_report) {
+            Ok(buffer) => Ok(buffer),
+            Err(error) if io_probe::is_mmap_unsupported(&error) => {
+                io_report.record(
+                    "output buffer",
+                    format!("in memory, written on completion ({error:#})"),
+                );
+                Ok(Self::InMemory(vec![0; file_size as usize]))
+            }
+            Err(error) => Err(error),
+        }
     }
 
-    fn new_mmapped(file: &std::fs::File, file_size: u64) -> Option<Self> {
-        file.set_len(file_size).ok()?;
-        let mmap = unsafe { MmapOptions::new().map_mut(file) }.ok()?;
-        Some(Self::Mmap(mmap))
+    fn new_mmapped(file: &std::fs::File, file_size: u64, io_report: &mut IoReport) -> Result<Self> {
+        io_probe::reserve_space(file, file_size, io_report)?;
+        let mmap = unsafe { MmapOptions::new().map_mut(file) }.context("mmap failed")?;
+        io_report.record("output buffer", "mmap");
+        io_probe::advise_huge_pages(&mmap, io_report);
+        Ok(Self::Mmap(mmap))
     }
 }
 
@@ -146,7 +176,9 @@ struct SectionAllocation {
 
 impl Output {
     pub(crate) fn new(args: &Args) -> Output {
-        if args.num_threads.get() > 1 {
+        // When we're only emitting symbols, we never size the output, so mustn't create it in the
+        // background.
+        if args.num_threads.get() > 1 && !args.emit_symbols_only {
             // Deletion of the old output file can take a while, so we start that in the background.
             // When we get to the stage where we're going to create the new output file, we'll wait
             // for deletion to complete if it hasn't already.
@@ -197,8 +229,29 @@ impl Output {
         }
     }
 
+    /// Gives up on the output without writing it. If we'd already started creating the file in the
+    /// background, then we wait for that to finish and delete it, so that we don't leave behind a
+    /// file full of zeros.
+    pub(crate) fn discard(self) {
+        if let FileCreator::Background {
+            sized_output_sender: None,
+            sized_output_recv,
+            deletion_complete_recv: _,
+        } = &self.creator
+        {
+            if let Ok(Ok(sized_output)) = sized_output_recv.recv() {
+                drop(sized_output);
+                let _ = std::fs::remove_file(&self.path);
+            }
+        }
+    }
+
     #[tracing::instrument(skip_all, name = "Write output file")]
-    pub fn write(&mut self, layout: &Layout) -> Result<SizedOutput> {
+    pub fn write(
+        &mut self,
+        layout: &Layout,
+        post_layout_hooks: &[Box<dyn PostLayoutHook>],
+    ) -> Result<SizedOutput> {
         if layout.args().write_layout {
             write_layout(layout)?;
         }
@@ -216,8 +269,17 @@ impl Output {
                 self.create_file_non_lazily(file_size)?
             }
         };
-        sized_output.write(layout)?;
+        sized_output.write(layout, post_layout_hooks)?;
         sized_output.flush()?;
+        if let Some(debug_path) = layout.args().separate_debug_file.as_ref() {
+            sized_output.split_debug_info(debug_path)?;
+        }
+        if layout.args().explain_io {
+            core::mem::take(&mut sized_output.io_report).explain(&self.path);
+        }
+        if layout.args().print_output_summary {
+            crate::output_summary::print(&self.path, &sized_output.out)?;
+        }
         // This triggers writing our .trace file if any. See output_trace module.
         tracing::trace!(output_write_complete = true);
         Ok(sized_output)
@@ -229,6 +291,18 @@ impl Output {
     }
 }
 
+#[tracing::instrument(skip_all, name = "Compute build ID")]
+fn write_build_id(build_id: &BuildId, layout: &Layout, out: &mut [u8]) -> Result {
+    let id = crate::build_id::compute(build_id, out);
+    let offset = layout
+        .section_layouts
+        .get(output_section_id::NOTE_GNU_BUILD_ID)
+        .file_offset
+        + crate::build_id::NOTE_HEADER_SIZE;
+    out[offset..offset + id.len()].copy_from_slice(&id);
+    Ok(())
+}
+
 /// Delete the old output file. Note, this is only used when running from a single thread.
 #[tracing::instrument(skip_all, name = "Delete old output")]
 fn delete_old_output(args: &Args) {
@@ -249,11 +323,22 @@ impl SizedOutput {
             .truncate(true)
             .open(&path)
             .with_context(|| format!("Failed to open `{}`", path.display()))?;
-        let out = OutputBuffer::new(&file, file_size);
-        Ok(SizedOutput { file, out, path })
+        let mut io_report = IoReport::default();
+        let out = OutputBuffer::new(&file, file_size, &mut io_report)
+            .with_context(|| format!("Failed to allocate space for `{}`", path.display()))?;
+        Ok(SizedOutput {
+            file,
+            out,
+            path,
+            io_report,
+        })
     }
 
-    pub(crate) fn write(&mut self, layout: &Layout) -> Result {
+    pub(crate) fn write(
+        &mut self,
+        layout: &Layout,
+        post_layout_hooks: &[Box<dyn PostLayoutHook>],
+    ) -> Result {
         self.write_file_contents(layout)?;
         if layout.args().validate_output {
             crate::validation::validate_bytes(layout, &self.out)?;
@@ -263,6 +348,15 @@ impl SizedOutput {
             let mut section_buffers = split_output_into_sections(layout, &mut self.out);
             sort_eh_frame_hdr_entries(section_buffers.get_mut(output_section_id::EH_FRAME_HDR));
         }
+
+        if !post_layout_hooks.is_empty() {
+            crate::post_layout::apply(post_layout_hooks, layout, &mut self.out)?;
+        }
+
+        // This needs to come last, since it hashes everything else that we've written.
+        if let Some(build_id) = layout.args().build_id.as_ref() {
+            write_build_id(build_id, layout, &mut self.out)?;
+        }
         Ok(())
     }
 
@@ -282,15 +376,32 @@ impl SizedOutput {
         Ok(())
     }
 
+    /// Moves debug info out of the output and into `debug_path`. See the `separate_debug` module.
+    fn split_debug_info(&mut self, debug_path: &Path) -> Result {
+        let split = crate::separate_debug::split(&self.out, debug_path)?;
+        std::fs::write(debug_path, &split.debug)
+            .with_context(|| format!("Failed to write `{}`", debug_path.display()))?;
+        // Drop any mapping of the file before we shrink it.
+        self.out = OutputBuffer::InMemory(Vec::new());
+        self.file
+            .set_len(0)
+            .and_then(|()| self.file.write_all_at(&split.main, 0))
+            .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        self.out = OutputBuffer::InMemory(split.main);
+        Ok(())
+    }
+
     #[tracing::instrument(skip_all, name = "Write data to file")]
     pub(crate) fn write_file_contents(&mut self, layout: &Layout) -> Result {
         let mut section_buffers = split_output_into_sections(layout, &mut self.out);
 
         let mut writable_buckets = split_buffers_by_alignment(&mut section_buffers, layout);
+        let hot_buffers = split_hot_section_buffers(layout, &mut writable_buckets);
         let groups_and_buffers = split_output_by_group(layout, &mut writable_buckets);
         groups_and_buffers
             .into_par_iter()
-            .try_for_each(|(group, mut buffers)| -> Result {
+            .zip(hot_buffers)
+            .try_for_each(|((group, mut buffers), mut hot_buffers)| -> Result {
                 let mut table_writer = TableWriter::from_layout(
                     layout,
                     group.dynstr_start_offset,
@@ -300,9 +411,10 @@ impl SizedOutput {
                 );
 
                 for file in &group.files {
-                    file.write(&mut buffers, &mut table_writer, layout)
+                    file.write(&mut buffers, &mut hot_buffers, &mut table_writer, layout)
                         .with_context(|| format!("Failed copying from {file} to output file"))?
                 }
+                table_writer.finish_relr()?;
                 table_writer
                     .validate_empty(&group.mem_sizes)
                     .with_context(|| format!("validate_empty failed for {group}"))?;
@@ -322,6 +434,42 @@ impl SizedOutput {
     }
 }
 
+/// The space for each hot section that a group needs to write, keyed by file and section index.
+type HotBuffers<'out> = FxHashMap<(FileId, object::SectionIndex), &'out mut [u8]>;
+
+/// Takes the space for the hot sections, which come before those of all groups, from
+/// `writable_buckets`. The result has an entry for each group, holding the buffers for the hot
+/// sections that it owns.
+fn split_hot_section_buffers<'out>(
+    layout: &Layout,
+    writable_buckets: &mut OutputSectionPartMap<&'out mut [u8]>,
+) -> Vec<HotBuffers<'out>> {
+    let mut per_group: Vec<HotBuffers> = layout
+        .group_layouts
+        .iter()
+        .map(|_| HotBuffers::default())
+        .collect();
+    if layout.hot_sections.sections.is_empty() {
+        return per_group;
+    }
+    let file_sizes = compute_file_sizes(&layout.hot_sections.mem_sizes, &layout.output_sections);
+    let mut hot_region = writable_buckets.take_mut(&file_sizes);
+    for section in &layout.hot_sections.sections {
+        if !layout
+            .output_sections
+            .has_data_in_file(section.part_id.output_section_id())
+        {
+            continue;
+        }
+        let buffer = slice_take_prefix_mut(
+            hot_region.get_mut(section.part_id),
+            section.capacity as usize,
+        );
+        per_group[section.file_id.group()].insert((section.file_id, section.section_index), buffer);
+    }
+    per_group
+}
+
 #[tracing::instrument(skip_all, name = "Split output buffers by group")]
 fn split_output_by_group<'data, 'out>(
     layout: &'data Layout<'data>,
@@ -400,14 +548,13 @@ fn write_program_headers(program_headers_out: &mut ProgramHeaderWriter, layout:
         let segment_header = program_headers_out.take_header()?;
         let mut alignment = segment_sizes.alignment;
         if segment_id.segment_type() == object::elf::PT_LOAD {
-            alignment = alignment.max(crate::alignment::PAGE);
+            alignment = alignment.max(layout.args().max_page_size);
         }
         let e = LittleEndian;
         segment_header.p_type.set(e, segment_id.segment_type());
 
-        // Support executable stack (Wild defaults to non-executable stack)
         let mut segment_flags = segment_id.segment_flags();
-        if segment_id == STACK && layout.args().execstack {
+        if segment_id == STACK && layout.exec_stack {
             segment_flags |= object::elf::PF_X;
         }
         segment_header.p_flags.set(e, segment_flags);
@@ -445,7 +592,7 @@ fn populate_file_header(
     header.e_ident.abi_version = 0;
     header.e_ident.padding = Default::default();
     header.e_type.set(e, ty);
-    header.e_machine.set(e, object::elf::EM_X86_64);
+    header.e_machine.set(e, layout.args().emulation.machine);
     header.e_version.set(e, object::elf::EV_CURRENT as u32);
     header.e_entry.set(e, layout.entry_symbol_address()?);
     header.e_phoff.set(e, elf::PHEADER_OFFSET);
@@ -477,11 +624,12 @@ impl<'data> FileLayout<'data> {
     fn write(
         &self,
         buffers: &mut OutputSectionPartMap<&mut [u8]>,
+        hot_buffers: &mut HotBuffers,
         table_writer: &mut TableWriter,
         layout: &Layout,
     ) -> Result {
         match self {
-            FileLayout::Object(s) => s.write_file(buffers, table_writer, layout)?,
+            FileLayout::Object(s) => s.write_file(buffers, hot_buffers, table_writer, layout)?,
             FileLayout::Prelude(s) => s.write_file(buffers, table_writer, layout)?,
             FileLayout::Epilogue(s) => s.write_file(buffers, table_writer, layout)?,
             FileLayout::NotLoaded => {}
@@ -549,12 +697,19 @@ impl<'out> VersionWriter<'out> {
 
 struct TableWriter<'data, 'out> {
     output_kind: OutputKind,
+    lazy_binding: LazyBinding,
     got: &'out mut [u64],
     plt_got: &'out mut [u8],
+    plt: &'out mut [u8],
+    got_plt: &'out mut [u64],
     rela_plt: &'out mut [elf::Rela],
+    rela_irelative: &'out mut [elf::Rela],
     tls: Range<u64>,
     rela_dyn_relative: &'out mut [crate::elf::Rela],
     rela_dyn_general: &'out mut [crate::elf::Rela],
+    pack_relative_relocs: bool,
+    relr: RelrWriter<'out>,
+    relr_got: RelrWriter<'out>,
     dynsym_writer: SymbolTableWriter<'data, 'out>,
     debug_symbol_writer: SymbolTableWriter<'data, 'out>,
     eh_frame_start_address: u64,
@@ -568,6 +723,37 @@ struct TableWriter<'data, 'out> {
     version_writer: VersionWriter<'out>,
 }
 
+/// Returns the offset of `target` relative to `next_instruction`, for use as a RIP-relative operand.
+fn pc_relative(target: u64, next_instruction: u64) -> Result<[u8; 4]> {
+    let offset: i32 = (target.wrapping_sub(next_instruction) as i64)
+        .try_into()
+        .map_err(|_| anyhow!("PLT is more than 2GB away from .got.plt"))?;
+    Ok(offset.to_le_bytes())
+}
+
+/// What we need to know in order to write lazily bound PLT entries. Each entry refers to PLT0 and
+/// to its own .got.plt entry, which we locate based on the entry's position in .plt.
+#[derive(Debug, Clone, Copy, Default)]
+struct LazyBinding {
+    plt_start: u64,
+    got_plt_start: u64,
+}
+
+impl LazyBinding {
+    fn from_layout(layout: &Layout) -> Self {
+        Self {
+            plt_start: layout
+                .section_layouts
+                .get(output_section_id::PLT)
+                .mem_offset,
+            got_plt_start: layout
+                .section_layouts
+                .get(output_section_id::GOT_PLT)
+                .mem_offset,
+        }
+    }
+}
+
 impl<'data, 'out> TableWriter<'data, 'out> {
     fn from_layout(
         layout: &'data Layout<'data>,
@@ -583,7 +769,9 @@ impl<'data, 'out> TableWriter<'data, 'out> {
 
         Self::new(
             layout.args().output_kind,
+            LazyBinding::from_layout(layout),
             layout.tls_start_address()..layout.tls_end_address(),
+            layout.pack_relative_relocs,
             buffers,
             dynsym_writer,
             debug_symbol_writer,
@@ -593,7 +781,9 @@ impl<'data, 'out> TableWriter<'data, 'out> {
 
     fn new(
         output_kind: OutputKind,
+        lazy_binding: LazyBinding,
         tls: Range<u64>,
+        pack_relative_relocs: bool,
         buffers: &mut OutputSectionPartMap<&'out mut [u8]>,
         dynsym_writer: SymbolTableWriter<'data, 'out>,
         debug_symbol_writer: SymbolTableWriter<'data, 'out>,
@@ -609,12 +799,21 @@ impl<'data, 'out> TableWriter<'data, 'out> {
 
         TableWriter {
             output_kind,
+            lazy_binding,
             got: bytemuck::cast_slice_mut(buffers.take(part_id::GOT)),
             plt_got: buffers.take(part_id::PLT_GOT),
-            rela_plt: slice_from_all_bytes_mut(buffers.take(part_id::RELA_PLT)),
+            plt: buffers.take(part_id::PLT),
+            got_plt: bytemuck::cast_slice_mut(buffers.take(part_id::GOT_PLT)),
+            rela_plt: slice_from_all_bytes_mut(buffers.take(part_id::RELA_PLT_GENERAL)),
+            rela_irelative: slice_from_all_bytes_mut(buffers.take(part_id::RELA_PLT_IRELATIVE)),
             tls,
             rela_dyn_relative: slice_from_all_bytes_mut(buffers.take(part_id::RELA_DYN_RELATIVE)),
             rela_dyn_general: slice_from_all_bytes_mut(buffers.take(part_id::RELA_DYN_GENERAL)),
+            pack_relative_relocs,
+            relr: RelrWriter::new(bytemuck::cast_slice_mut(buffers.take(part_id::RELR_DYN))),
+            relr_got: RelrWriter::new(bytemuck::cast_slice_mut(
+                buffers.take(part_id::RELR_DYN_GOT),
+            )),
             dynsym_writer,
             debug_symbol_writer,
             eh_frame_start_address,
@@ -629,13 +828,8 @@ impl<'data, 'out> TableWriter<'data, 'out> {
         let is_copy_relocation = res
             .resolution_flags
             .contains(ResolutionFlags::COPY_RELOCATION);
-        if is_copy_relocation {
-            self.write_rela_dyn_general(
-                res.raw_value,
-                res.dynamic_symbol_index()?,
-                object::elf::R_X86_64_COPY,
-                0,
-            )?;
+        if res.resolution_flags.contains(ResolutionFlags::LAZY_PLT) {
+            self.write_lazy_plt_entry(res)?;
         }
         let Some(got_address) = res.got_address else {
             return Ok(());
@@ -660,14 +854,25 @@ impl<'data, 'out> TableWriter<'data, 'out> {
         if (res.value_flags.contains(ValueFlags::DYNAMIC) && !is_copy_relocation)
             || (resolution_flags.contains(ResolutionFlags::EXPORT_DYNAMIC)
                 && !res.value_flags.contains(ValueFlags::CAN_BYPASS_GOT))
-                && !res.value_flags.contains(ValueFlags::IFUNC)
         {
             debug_assert_bail!(
                 *compute_allocations(res, self.output_kind).get(part_id::RELA_DYN_GENERAL) > 0,
                 "Tried to write glob-dat with no allocation. {}",
                 ResFlagsDisplay(res)
             );
-            self.write_dynamic_symbol_relocation(got_address, 0, res.dynamic_symbol_index()?)?;
+            if resolution_flags.contains(ResolutionFlags::CANONICAL_PLT) {
+                // Our dynamic symbol has the address of the PLT entry, so a GLOB_DAT would resolve
+                // to that and the PLT entry would jump to itself. The dynamic loader doesn't
+                // consider undefined symbols in the executable when resolving JUMP_SLOT.
+                self.write_rela_dyn_general(
+                    got_address,
+                    res.dynamic_symbol_index()?,
+                    object::elf::R_X86_64_JUMP_SLOT,
+                    0,
+                )?;
+            } else {
+                self.write_dynamic_symbol_relocation(got_address, 0, res.dynamic_symbol_index()?)?;
+            }
         } else if res.value_flags.contains(ValueFlags::IFUNC) {
             self.write_ifunc_relocation(res)?;
         } else {
@@ -675,11 +880,15 @@ impl<'data, 'out> TableWriter<'data, 'out> {
             if (res.value_flags.contains(ValueFlags::ADDRESS) || is_copy_relocation)
                 && self.output_kind.is_relocatable()
             {
-                self.write_address_relocation(got_address, res.raw_value as i64)?;
+                if self.pack_relative_relocs {
+                    self.relr_got.add(got_address);
+                } else {
+                    self.write_address_relocation(got_address, res.raw_value as i64)?;
+                }
             }
         }
-        if let Some(plt_address) = res.plt_address {
-            self.write_plt_entry(got_address, plt_address.get())?;
+        if resolution_flags.contains(ResolutionFlags::PLT) {
+            self.write_plt_entry(got_address, res.plt_address()?)?;
         }
         Ok(())
     }
@@ -718,7 +927,7 @@ impl<'data, 'out> TableWriter<'data, 'out> {
 
     fn process_got_tls_mod(&mut self, res: &Resolution, got_address: u64) -> Result {
         let got_entry = self.take_next_got_entry()?;
-        if self.output_kind.is_executable() {
+        if self.output_kind.is_executable() && !res.value_flags.contains(ValueFlags::DYNAMIC) {
             *got_entry = elf::CURRENT_EXE_TLS_MOD;
         } else {
             let dynamic_symbol_index = res.dynamic_symbol_index.map(|i| i.get()).unwrap_or(0);
@@ -756,6 +965,76 @@ impl<'data, 'out> TableWriter<'data, 'out> {
         Ok(())
     }
 
+    /// Writes a PLT entry that jumps via its .got.plt entry, which initially points back into the
+    /// PLT entry so that the first call goes to the resolver via PLT0.
+    fn write_lazy_plt_entry(&mut self, res: &Resolution) -> Result {
+        let plt_address = res.plt_address()?;
+        let index = (plt_address.wrapping_sub(self.lazy_binding.plt_start) / elf::PLT_ENTRY_SIZE)
+            .checked_sub(1)
+            .context("Lazy PLT entry overlaps PLT0")?;
+        let got_plt_address = self.lazy_binding.got_plt_start
+            + (elf::NUM_RESERVED_GOT_PLT_ENTRIES + index) * elf::GOT_ENTRY_SIZE;
+
+        if self.plt.len() < elf::PLT_ENTRY_SIZE as usize {
+            bail!("Didn't allocate enough space in .plt");
+        }
+        let plt_entry = slice_take_prefix_mut(&mut self.plt, elf::PLT_ENTRY_SIZE as usize);
+        plt_entry.copy_from_slice(elf::LAZY_PLT_ENTRY_TEMPLATE);
+        plt_entry[2..6].copy_from_slice(&pc_relative(got_plt_address, plt_address + 6)?);
+        let index_bytes = u32::try_from(index)
+            .context("Too many PLT entries")?
+            .to_le_bytes();
+        plt_entry[7..11].copy_from_slice(&index_bytes);
+        plt_entry[12..16].copy_from_slice(&pc_relative(
+            self.lazy_binding.plt_start,
+            plt_address + elf::PLT_ENTRY_SIZE,
+        )?);
+
+        *crate::slice::take_first_mut(&mut self.got_plt)
+            .context("Insufficient .got.plt allocation")? = plt_address + 6;
+
+        let rela = crate::slice::take_first_mut(&mut self.rela_plt)
+            .context("Insufficient .rela.plt allocation")?;
+        rela.r_offset.set(LittleEndian, got_plt_address);
+        rela.r_addend.set(LittleEndian, 0);
+        rela.set_r_info(
+            LittleEndian,
+            false,
+            res.dynamic_symbol_index()?,
+            object::elf::R_X86_64_JUMP_SLOT,
+        );
+        Ok(())
+    }
+
+    /// Writes PLT0 and the reserved entries at the start of .got.plt.
+    fn write_lazy_plt_header(&mut self, dynamic_address: u64) -> Result {
+        let plt_start = self.lazy_binding.plt_start;
+        let got_plt_start = self.lazy_binding.got_plt_start;
+        if self.plt.len() < elf::PLT_ENTRY_SIZE as usize
+            || self.got_plt.len() < elf::NUM_RESERVED_GOT_PLT_ENTRIES as usize
+        {
+            bail!("Didn't allocate space for PLT0");
+        }
+        let plt0 = slice_take_prefix_mut(&mut self.plt, elf::PLT_ENTRY_SIZE as usize);
+        plt0.copy_from_slice(elf::PLT0_TEMPLATE);
+        plt0[2..6].copy_from_slice(&pc_relative(
+            got_plt_start + elf::GOT_ENTRY_SIZE,
+            plt_start + 6,
+        )?);
+        plt0[8..12].copy_from_slice(&pc_relative(
+            got_plt_start + elf::GOT_ENTRY_SIZE * 2,
+            plt_start + 12,
+        )?);
+
+        // The dynamic loader fills in the second and third entries.
+        let reserved = slice_take_prefix_mut(
+            &mut self.got_plt,
+            elf::NUM_RESERVED_GOT_PLT_ENTRIES as usize,
+        );
+        reserved.copy_from_slice(&[dynamic_address, 0, 0]);
+        Ok(())
+    }
+
     fn take_plt_got_entry(&mut self) -> Result<&'out mut [u8]> {
         if self.plt_got.len() < elf::PLT_ENTRY_SIZE as usize {
             bail!("Didn't allocate enough space in .plt.got");
@@ -772,6 +1051,16 @@ impl<'data, 'out> TableWriter<'data, 'out> {
 
     /// Checks that we used all of the entries that we requested during layout.
     fn validate_empty(&self, mem_sizes: &OutputSectionPartMap<u64>) -> Result {
+        if !self.plt.is_empty() || !self.got_plt.is_empty() {
+            bail!(
+                "Allocated too much space for lazy binding. {} of {} .plt bytes and {} of {} \
+                 .got.plt entries remain unused.",
+                self.plt.len(),
+                mem_sizes.get(part_id::PLT),
+                self.got_plt.len(),
+                mem_sizes.get(part_id::GOT_PLT) / elf::GOT_ENTRY_SIZE,
+            );
+        }
         if !self.rela_dyn_relative.is_empty() {
             bail!(
                 "Allocated too much relative space in .rela.dyn. {} of {} entries remain unused.",
@@ -786,6 +1075,13 @@ impl<'data, 'out> TableWriter<'data, 'out> {
                 mem_sizes.get(part_id::RELA_DYN_GENERAL) / elf::RELA_ENTRY_SIZE,
             );
         }
+        if !self.rela_irelative.is_empty() {
+            bail!(
+                "Allocated too much IRELATIVE space in .rela.plt. {} of {} entries remain unused.",
+                self.rela_irelative.len(),
+                mem_sizes.get(part_id::RELA_PLT_IRELATIVE) / elf::RELA_ENTRY_SIZE,
+            );
+        }
         self.dynsym_writer.check_exhausted()?;
         self.debug_symbol_writer.check_exhausted()?;
         self.version_writer.check_exhausted(mem_sizes)?;
@@ -807,15 +1103,22 @@ impl<'data, 'out> TableWriter<'data, 'out> {
     }
 
     fn write_ifunc_relocation(&mut self, res: &Resolution) -> Result {
-        let out = slice_take_prefix_mut(&mut self.rela_plt, 1);
-        let out = &mut out[0];
-        let e = LittleEndian;
-        out.r_addend.set(e, res.raw_value as i64);
         let got_address = res
             .got_address
             .context("Missing GOT entry for ifunc")?
             .get();
-        out.r_offset.set(e, got_address);
+        self.write_irelative_relocation(got_address, res.raw_value)
+    }
+
+    /// Writes a relocation that sets `place` to the result of calling the ifunc resolver at
+    /// `resolver`. These go at the end of .rela.plt, after the entries that need to line up with
+    /// .plt when binding lazily.
+    fn write_irelative_relocation(&mut self, place: u64, resolver: u64) -> Result {
+        let out = crate::slice::take_first_mut(&mut self.rela_irelative)
+            .context("Insufficient .rela.plt allocation for IRELATIVE relocation")?;
+        let e = LittleEndian;
+        out.r_addend.set(e, resolver as i64);
+        out.r_offset.set(e, place);
         out.r_info.set(e, object::elf::R_X86_64_IRELATIVE as u64);
         Ok(())
     }
@@ -866,6 +1169,39 @@ impl<'data, 'out> TableWriter<'data, 'out> {
         Ok(())
     }
 
+    /// Writes a relocation that adds the load address to `relative_address` at `place`. Returns the
+    /// value that should be written to `place`, which for packed relocations is the addend.
+    fn write_relative_relocation(
+        &mut self,
+        place: u64,
+        relative_address: u64,
+        section_info: &SectionInfo,
+    ) -> Result<u64> {
+        if !self.pack_relative_relocs {
+            self.write_address_relocation(place, relative_address as i64)?;
+            return Ok(0);
+        }
+        if section_info.packs_relative_relocs && place.is_multiple_of(2) {
+            self.relr.add(place);
+            return Ok(relative_address);
+        }
+        // Layout allocated this in the general part, since the relative part is only for GOT
+        // entries when we're packing.
+        let rela = self.take_rela_dyn()?;
+        rela.r_offset.set(LittleEndian, place);
+        rela.r_addend.set(LittleEndian, relative_address as i64);
+        rela.r_info
+            .set(LittleEndian, object::elf::R_X86_64_RELATIVE.into());
+        Ok(0)
+    }
+
+    /// Encodes any packed relative relocations that we haven't yet written and fills the rest of
+    /// our part of `.relr.dyn`.
+    fn finish_relr(&mut self) -> Result {
+        self.relr.finish()?;
+        self.relr_got.finish()
+    }
+
     fn write_dynamic_symbol_relocation(
         &mut self,
         place: u64,
@@ -1082,6 +1418,31 @@ impl<'data, 'out> SymbolTableWriter<'data, 'out> {
         Ok(entry)
     }
 
+    /// Takes the entries and string space for `shard` from the front of our buffers and returns a
+    /// writer for them.
+    fn split_off_shard(&mut self, shard: &SymtabShard) -> Result<SymbolTableWriter<'data, 'out>> {
+        let local_entries =
+            crate::slice::try_slice_take_prefix_mut(&mut self.local_entries, shard.num_locals)
+                .context("Insufficient .symtab local entries allocated for shard")?;
+        let global_entries =
+            crate::slice::try_slice_take_prefix_mut(&mut self.global_entries, shard.num_globals)
+                .context("Insufficient .symtab global entries allocated for shard")?;
+        let out = crate::slice::try_slice_take_prefix_mut(
+            &mut self.strtab_writer.out,
+            shard.strings_size,
+        )
+        .context("Insufficient .strtab space allocated for shard")?;
+        let next_offset = self.strtab_writer.next_offset;
+        self.strtab_writer.next_offset += shard.strings_size as u32;
+        Ok(SymbolTableWriter {
+            local_entries,
+            global_entries,
+            output_sections: self.output_sections,
+            strtab_writer: StrTabWriter { next_offset, out },
+            is_dynamic: self.is_dynamic,
+        })
+    }
+
     /// Verifies that we've used up all the space allocated to this writer. i.e. checks that we
     /// didn't allocate too much or missed writing something that we were supposed to write.
     fn check_exhausted(&self) -> Result {
@@ -1109,17 +1470,25 @@ impl<'out> ObjectLayout<'out> {
     fn write_file(
         &self,
         buffers: &mut OutputSectionPartMap<&mut [u8]>,
+        hot_buffers: &mut HotBuffers,
         table_writer: &mut TableWriter,
         layout: &Layout,
     ) -> Result {
         let _span = debug_span!("write_file", filename = ?self.input.file.filename).entered();
         let _file_span = layout.args().trace_span_for_file(self.file_id);
         let mut string_offset_cache = StringOffsetCache::new(&layout.output_sections);
-        for sec in &self.sections {
-            match sec {
-                SectionSlot::Loaded(sec) => {
-                    self.write_section(layout, sec, buffers, table_writer)?
-                }
+        // Sections need to be written in the same order in which they were laid out, since each
+        // one takes the next part of the buffer for its output section part.
+        for index in placement_indexes(self.section_order.as_deref(), self.sections.len()) {
+            match &self.sections[index] {
+                SectionSlot::Loaded(sec) => self.write_section(
+                    layout,
+                    sec,
+                    buffers,
+                    hot_buffers,
+                    table_writer,
+                    &mut string_offset_cache,
+                )?,
                 SectionSlot::LoadedDebugInfo(sec) => {
                     self.write_debug_section(layout, sec, buffers, &mut string_offset_cache)?;
                 }
@@ -1146,10 +1515,10 @@ impl<'out> ObjectLayout<'out> {
                     let symbol = self
                         .object
                         .symbol(self.symbol_id_range.id_to_input(symbol_id))?;
-                    let name = self.object.symbol_name(symbol)?;
+                    let name = layout.symbol_db.symbol_name(symbol_id)?;
                     table_writer
                         .dynsym_writer
-                        .copy_symbol_shndx(symbol, name, 0, 0)?;
+                        .copy_symbol_shndx(symbol, name.bytes(), 0, 0)?;
                     if layout.gnu_version_enabled() {
                         table_writer
                             .version_writer
@@ -1170,17 +1539,30 @@ impl<'out> ObjectLayout<'out> {
         layout: &Layout,
         sec: &Section,
         buffers: &mut OutputSectionPartMap<&mut [u8]>,
+        hot_buffers: &mut HotBuffers,
         table_writer: &mut TableWriter,
+        string_offset_cache: &mut StringOffsetCache,
     ) -> Result {
-        let out = self.write_section_raw(layout, sec, buffers)?;
-        self.apply_relocations(out, sec, layout, table_writer)
-            .with_context(|| {
-                format!(
-                    "Failed to apply relocations in section `{}` of {}",
-                    self.object.section_display_name(sec.index),
-                    self.input
-                )
-            })?;
+        let legacy_array = LegacyArray::for_section(self.object, sec)?;
+        let out = match hot_buffers.remove(&(self.file_id, sec.index)) {
+            Some(out) => self.copy_section_data(sec, out, legacy_array.as_ref())?,
+            None => self.write_section_raw(layout, sec, buffers, legacy_array.as_ref())?,
+        };
+        let is_alloc =
+            SectionFlags::from_header(self.object.section(sec.index)?).contains(shf::ALLOC);
+        if is_alloc {
+            self.apply_relocations(out, sec, layout, table_writer, legacy_array.as_ref())
+        } else {
+            // Like debug info, this isn't loaded at runtime, so gets link-time addresses.
+            self.apply_debug_relocations(out, sec, layout, string_offset_cache)
+        }
+        .with_context(|| {
+            format!(
+                "Failed to apply relocations in section `{}` of {}",
+                self.object.section_display_name(sec.index),
+                self.input
+            )
+        })?;
         if sec.resolution_kind.contains(ResolutionFlags::GOT)
             || sec.resolution_kind.contains(ResolutionFlags::PLT)
         {
@@ -1196,7 +1578,7 @@ impl<'out> ObjectLayout<'out> {
         buffers: &mut OutputSectionPartMap<&mut [u8]>,
         string_offset_cache: &mut StringOffsetCache,
     ) -> Result {
-        let out = self.write_section_raw(layout, sec, buffers)?;
+        let out = self.write_section_raw(layout, sec, buffers, None)?;
         self.apply_debug_relocations(out, sec, layout, string_offset_cache)
             .with_context(|| {
                 format!(
@@ -1213,6 +1595,7 @@ impl<'out> ObjectLayout<'out> {
         layout: &Layout,
         sec: &Section,
         buffers: &'out mut OutputSectionPartMap<&mut [u8]>,
+        legacy_array: Option<&LegacyArray>,
     ) -> Result<&'out mut [u8]> {
         if layout
             .output_sections
@@ -1228,25 +1611,72 @@ impl<'out> ObjectLayout<'out> {
                 );
             }
             let out = slice_take_prefix_mut(section_buffer, allocation_size);
-            // Cut off any padding so that our output buffer is the size of our input buffer.
-            let object_section = self.object.section(sec.index)?;
-            let section_size = self.object.section_size(object_section)?;
-            let out: &'out mut [u8] = &mut out[..section_size as usize];
-            self.object.copy_section_data(object_section, out)?;
-            Ok(out)
+            self.copy_section_data(sec, out, legacy_array)
         } else {
             Ok(&mut [])
         }
     }
 
+    /// Copies the data for `sec` into `out`, which is the space allocated for it, returning the
+    /// part of `out` that holds the data. `legacy_array` is set if `sec` is a `.ctors` or `.dtors`
+    /// section, whose entries need to be reversed.
+    fn copy_section_data<'buf>(
+        &self,
+        sec: &Section,
+        out: &'buf mut [u8],
+        legacy_array: Option<&LegacyArray>,
+    ) -> Result<&'buf mut [u8]> {
+        let object_section = self.object.section(sec.index)?;
+        if let Some(array) = legacy_array {
+            let out = &mut out[..array.size as usize];
+            array.copy(self.object.raw_section_data(object_section)?, out)?;
+            return Ok(out);
+        }
+        // Cut off any padding so that our output buffer is the size of our input buffer.
+        let section_size = self.object.section_size(object_section)?;
+        let out = &mut out[..section_size as usize];
+        self.object.copy_section_data(object_section, out)?;
+        Ok(out)
+    }
+
     /// Writes debug symbols.
     fn write_symbols(&self, symbol_writer: &mut SymbolTableWriter, layout: &Layout) -> Result {
-        for ((sym_index, sym), sym_state) in self
-            .object
-            .symbols
+        let num_symbols = self.object.symbols.len();
+        if self.symtab_shards.is_empty() {
+            return self.write_symbol_range(symbol_writer, layout, 0..num_symbols);
+        }
+        // We have lots of symbols, so split our part of .symtab and .strtab up using the sizes
+        // computed during layout and write each shard on a separate thread.
+        let shard_writers = self
+            .symtab_shards
+            .iter()
+            .map(|shard| symbol_writer.split_off_shard(shard))
+            .collect::<Result<Vec<_>>>()?;
+        shard_writers
+            .into_par_iter()
+            .enumerate()
+            .try_for_each(|(i, mut shard_writer)| {
+                let start = i * SYMTAB_SHARD_SIZE;
+                let end = (start + SYMTAB_SHARD_SIZE).min(num_symbols);
+                self.write_symbol_range(&mut shard_writer, layout, start..end)?;
+                shard_writer.check_exhausted()
+            })
+    }
+
+    fn write_symbol_range(
+        &self,
+        symbol_writer: &mut SymbolTableWriter,
+        layout: &Layout,
+        input_range: Range<usize>,
+    ) -> Result {
+        let resolution_flags = &layout.symbol_resolution_flags[self.symbol_id_range.as_usize()];
+        let symbols = self.object.symbols.symbols();
+        for (index, (sym, sym_state)) in symbols[input_range.clone()]
+            .iter()
+            .zip(&resolution_flags[input_range.clone()])
             .enumerate()
-            .zip(&layout.symbol_resolution_flags[self.symbol_id_range.as_usize()])
         {
+            let sym_index = object::SymbolIndex(input_range.start + index);
             let symbol_id = self.symbol_id_range.input_to_id(sym_index);
             if let Some(info) = SymbolCopyInfo::new(
                 self.object,
@@ -1263,6 +1693,7 @@ impl<'out> ObjectLayout<'out> {
                 {
                     match &self.sections[section_index.0] {
                         SectionSlot::Loaded(section) => section.output_section_id(),
+                        SectionSlot::Folded(folded) => folded.section.output_section_id(),
                         SectionSlot::MergeStrings(section) => section.part_id.output_section_id(),
                         SectionSlot::EhFrameData(..) => output_section_id::EH_FRAME,
                         _ => bail!("Tried to copy a symbol in a section we didn't load"),
@@ -1308,6 +1739,7 @@ impl<'out> ObjectLayout<'out> {
         section: &Section,
         layout: &Layout,
         table_writer: &mut TableWriter,
+        legacy_array: Option<&LegacyArray>,
     ) -> Result {
         let section_address = self.section_resolutions[section.index.0]
             .address()
@@ -1315,6 +1747,8 @@ impl<'out> ObjectLayout<'out> {
 
         let object_section = self.object.section(section.index)?;
         let section_flags = SectionFlags::from_header(object_section);
+        let packs_relative_relocs = table_writer.pack_relative_relocs
+            && crate::layout::can_pack_relative_relocs(self.object, object_section)?;
         let mut modifier = RelocationModifier::Normal;
         let relocations = self.object.relocations(section.index)?;
         layout
@@ -1326,7 +1760,12 @@ impl<'out> ObjectLayout<'out> {
                 modifier = RelocationModifier::Normal;
                 continue;
             }
-            let offset_in_section = rel.r_offset.get(LittleEndian);
+            let mut offset_in_section = rel.r_offset.get(LittleEndian);
+            if let Some(array) = legacy_array {
+                offset_in_section = array
+                    .output_offset(offset_in_section)
+                    .context("Relocation applies to a .ctors/.dtors sentinel")?;
+            }
             modifier = apply_relocation(
                 self,
                 offset_in_section,
@@ -1335,6 +1774,7 @@ impl<'out> ObjectLayout<'out> {
                     section_address,
                     is_writable: section.is_writable,
                     section_flags,
+                    packs_relative_relocs,
                 },
                 layout,
                 out,
@@ -1348,7 +1788,7 @@ impl<'out> ObjectLayout<'out> {
                 )
             })?;
         }
-        Ok(())
+        table_writer.relr.flush()
     }
 
     fn apply_debug_relocations(
@@ -1360,18 +1800,7 @@ impl<'out> ObjectLayout<'out> {
     ) -> Result {
         let object_section = self.object.section(section.index)?;
         let section_name = self.object.section_name(object_section)?;
-        let tombstone_value: u64 =
-            // TODO: Starting with DWARF 6, the tombstone value will be defined as -1 and -2.
-            // However, the change is premature as consumers of the DWARF format don't fully support
-            // the new tombstone values.
-            //
-            // Link: https://dwarfstd.org/issues/200609.1.html
-            if section_name == b".debug_loc" || section_name == b".debug_ranges" {
-                // These sections use zero as a list terminator.
-                1
-            } else {
-                0
-            };
+        let tombstone_value = debug_tombstone_value(section_name, layout.args());
 
         let relocations = self.object.relocations(section.index)?;
         layout
@@ -1410,138 +1839,178 @@ impl<'out> ObjectLayout<'out> {
         const PREFIX_LEN: usize = core::mem::size_of::<elf::EhFrameEntryPrefix>();
         let e = LittleEndian;
         let section_flags = SectionFlags::from_header(eh_frame_section);
-        let mut relocations = self
-            .object
-            .relocations(eh_frame_section_index)?
-            .iter()
-            .peekable();
+        let relocations = self.object.relocations(eh_frame_section_index)?;
         let mut input_pos = 0;
         let mut output_pos = 0;
         let frame_info_ptr_base = table_writer.eh_frame_start_address;
         let eh_frame_hdr_address = layout.mem_address_of_built_in(output_section_id::EH_FRAME_HDR);
 
-        // Map from input offset to output offset of each CIE.
-        let mut cies_offset_conversion: AHashMap<u32, u32> = AHashMap::new();
-
-        while input_pos + PREFIX_LEN <= data.len() {
-            let prefix: elf::EhFrameEntryPrefix =
-                bytemuck::pod_read_unaligned(&data[input_pos..input_pos + PREFIX_LEN]);
-            let size = core::mem::size_of_val(&prefix.length) + prefix.length as usize;
-            let next_input_pos = input_pos + size;
-            let next_output_pos = output_pos + size;
-            if next_input_pos > data.len() {
-                bail!("Invalid .eh_frame data");
-            }
-            let mut should_keep = false;
-            let mut output_cie_offset = None;
-            if prefix.cie_id == 0 {
-                // This is a CIE
-                cies_offset_conversion.insert(input_pos as u32, output_pos as u32);
-                should_keep = true;
-            } else {
-                // This is an FDE
-                if let Some(rel) = relocations.peek() {
-                    let rel_offset = rel.r_offset.get(e);
-                    if rel_offset < next_input_pos as u64 {
-                        let is_pc_begin =
-                            (rel_offset as usize - input_pos) == elf::FDE_PC_BEGIN_OFFSET;
-
-                        if is_pc_begin {
-                            let Some(index) = rel.symbol(e, false) else {
-                                bail!("Unexpected absolute relocation in .eh_frame pc-begin");
-                            };
-                            let elf_symbol = &self.object.symbol(index)?;
-                            let Some(section_index) =
-                                self.object.symbol_section(elf_symbol, index)?
-                            else {
-                                bail!(".eh_frame pc-begin refers to symbol that's not defined in file");
-                            };
-                            let offset_in_section =
-                                (elf_symbol.st_value(e) as i64 + rel.r_addend.get(e)) as u64;
-                            if let Some(section_address) =
-                                self.section_resolutions[section_index.0].address()
-                            {
-                                should_keep = true;
-                                let cie_pointer_pos = input_pos as u32 + 4;
-                                let input_cie_pos = cie_pointer_pos
-                                    .checked_sub(prefix.cie_id)
-                                    .with_context(|| {
-                                        format!(
-                                            "CIE pointer is {}, but we're at offset {}",
-                                            prefix.cie_id, cie_pointer_pos
+        // Map from input offset to output address of each CIE and whether we write it.
+        let mut cie_addresses: AHashMap<u32, (u64, bool)> = AHashMap::new();
+        for cie in &self.cies {
+            let is_ours = matches!(cie.placement, CiePlacement::Address(_));
+            cie_addresses.insert(
+                cie.input_offset,
+                (cie_address(cie.placement, layout)?, is_ours),
+            );
+        }
+
+        // We write all of our CIEs before any of our FDEs, so that the layout could decide where
+        // each CIE goes without knowing which FDEs we'd keep.
+        for writing_cies in [true, false] {
+            let mut relocations = relocations.iter().peekable();
+            input_pos = 0;
+            while input_pos + PREFIX_LEN <= data.len() {
+                let prefix: elf::EhFrameEntryPrefix =
+                    bytemuck::pod_read_unaligned(&data[input_pos..input_pos + PREFIX_LEN]);
+                let size = core::mem::size_of_val(&prefix.length) + prefix.length as usize;
+                let next_input_pos = input_pos + size;
+                let next_output_pos = output_pos + size;
+                if next_input_pos > data.len() {
+                    bail!("Invalid .eh_frame data");
+                }
+                let mut should_keep = false;
+                let mut output_cie_offset = None;
+                if prefix.cie_id == 0 {
+                    // This is a CIE
+                    if writing_cies {
+                        let (address, is_ours) = *cie_addresses
+                            .get(&(input_pos as u32))
+                            .context("CIE missing from layout")?;
+                        debug_assert!(
+                            !is_ours || address == frame_info_ptr_base + output_pos as u64
+                        );
+                        should_keep = is_ours;
+                    }
+                } else if !writing_cies {
+                    // This is an FDE
+                    if let Some(rel) = relocations.peek() {
+                        let rel_offset = rel.r_offset.get(e);
+                        if rel_offset < next_input_pos as u64 {
+                            let is_pc_begin =
+                                (rel_offset as usize - input_pos) == elf::FDE_PC_BEGIN_OFFSET;
+
+                            if is_pc_begin {
+                                let Some(index) = rel.symbol(e, false) else {
+                                    bail!("Unexpected absolute relocation in .eh_frame pc-begin");
+                                };
+                                let elf_symbol = &self.object.symbol(index)?;
+                                let Some(section_index) =
+                                    self.object.symbol_section(elf_symbol, index)?
+                                else {
+                                    bail!(".eh_frame pc-begin refers to symbol that's not defined in file");
+                                };
+                                let offset_in_section =
+                                    (elf_symbol.st_value(e) as i64 + rel.r_addend.get(e)) as u64;
+                                // A folded section has the address of the section it was folded into,
+                                // which already has its own FDE.
+                                let is_folded = matches!(
+                                    self.sections[section_index.0],
+                                    SectionSlot::Folded(..)
+                                );
+                                if let Some(section_address) = self.section_resolutions
+                                    [section_index.0]
+                                    .address()
+                                    .filter(|_| !is_folded)
+                                {
+                                    should_keep = true;
+                                    let cie_pointer_pos = input_pos as u32 + 4;
+                                    let input_cie_pos = cie_pointer_pos
+                                        .checked_sub(prefix.cie_id)
+                                        .with_context(|| {
+                                            format!(
+                                                "CIE pointer is {}, but we're at offset {}",
+                                                prefix.cie_id, cie_pointer_pos
+                                            )
+                                        })?;
+                                    if let Some(hdr_out) = table_writer.take_eh_frame_hdr_entry() {
+                                        let frame_ptr = (section_address + offset_in_section)
+                                            as i64
+                                            - eh_frame_hdr_address as i64;
+                                        let frame_info_ptr =
+                                            (frame_info_ptr_base + output_pos as u64) as i64
+                                                - eh_frame_hdr_address as i64;
+                                        *hdr_out = EhFrameHdrEntry {
+                                            frame_ptr: i32::try_from(frame_ptr)
+                                                .context("32 bit overflow in frame_ptr")?,
+                                            frame_info_ptr: i32::try_from(frame_info_ptr).context(
+                                                "32 bit overflow when computing frame_info_ptr",
+                                            )?,
+                                        };
+                                    }
+                                    let (cie_address, _) =
+                                        cie_addresses.get(&input_cie_pos).with_context(|| {
+                                            format!(
+                                                "FDE referenced CIE at {input_cie_pos}, but no \
+                                                 CIE at that position"
+                                            )
+                                        })?;
+                                    let cie_pointer_address =
+                                        frame_info_ptr_base + output_pos as u64 + 4;
+                                    output_cie_offset = Some(
+                                        u32::try_from(
+                                            cie_pointer_address
+                                                .checked_sub(*cie_address)
+                                                .context("FDE precedes its CIE")?,
                                         )
-                                    })?;
-                                if let Some(hdr_out) = table_writer.take_eh_frame_hdr_entry() {
-                                    let frame_ptr = (section_address + offset_in_section) as i64
-                                        - eh_frame_hdr_address as i64;
-                                    let frame_info_ptr = (frame_info_ptr_base + output_pos as u64)
-                                        as i64
-                                        - eh_frame_hdr_address as i64;
-                                    *hdr_out = EhFrameHdrEntry {
-                                        frame_ptr: i32::try_from(frame_ptr)
-                                            .context("32 bit overflow in frame_ptr")?,
-                                        frame_info_ptr: i32::try_from(frame_info_ptr).context(
-                                            "32 bit overflow when computing frame_info_ptr",
-                                        )?,
-                                    };
+                                        .context("32 bit overflow in CIE pointer")?,
+                                    );
                                 }
-                                // TODO: Experiment with skipping this lookup if the `input_cie_pos`
-                                // is the same as the previous entry.
-                                let output_cie_pos = cies_offset_conversion.get(&input_cie_pos).with_context(|| format!("FDE referenced CIE at {input_cie_pos}, but no CIE at that position"))?;
-                                output_cie_offset = Some(output_pos as u32 + 4 - *output_cie_pos);
                             }
                         }
                     }
                 }
-            }
-            if should_keep {
-                let entry_out = table_writer.take_eh_frame_data(next_output_pos - output_pos)?;
-                entry_out.copy_from_slice(&data[input_pos..next_input_pos]);
-                if let Some(output_cie_offset) = output_cie_offset {
-                    entry_out[4..8].copy_from_slice(&output_cie_offset.to_le_bytes());
-                }
-                while let Some(rel) = relocations.peek() {
-                    let rel_offset = rel.r_offset.get(e);
-                    if rel_offset >= next_input_pos as u64 {
-                        // This relocation belongs to the next entry.
-                        break;
+                if should_keep {
+                    let entry_out =
+                        table_writer.take_eh_frame_data(next_output_pos - output_pos)?;
+                    entry_out.copy_from_slice(&data[input_pos..next_input_pos]);
+                    if let Some(output_cie_offset) = output_cie_offset {
+                        entry_out[4..8].copy_from_slice(&output_cie_offset.to_le_bytes());
                     }
-                    apply_relocation(
-                        self,
-                        rel_offset - input_pos as u64,
-                        rel,
-                        SectionInfo {
-                            section_address: output_pos as u64
-                                + table_writer.eh_frame_start_address,
-                            is_writable: false,
-                            section_flags,
-                        },
-                        layout,
-                        entry_out,
-                        table_writer,
-                        &mut StringOffsetCache::no_caching(),
-                    )
-                    .with_context(|| {
-                        format!(
-                            "Failed to apply eh_frame {}",
-                            self.display_relocation(rel, layout)
+                    while let Some(rel) = relocations.peek() {
+                        let rel_offset = rel.r_offset.get(e);
+                        if rel_offset >= next_input_pos as u64 {
+                            // This relocation belongs to the next entry.
+                            break;
+                        }
+                        apply_relocation(
+                            self,
+                            rel_offset - input_pos as u64,
+                            rel,
+                            SectionInfo {
+                                section_address: output_pos as u64
+                                    + table_writer.eh_frame_start_address,
+                                is_writable: false,
+                                section_flags,
+                                packs_relative_relocs: false,
+                            },
+                            layout,
+                            entry_out,
+                            table_writer,
+                            &mut StringOffsetCache::no_caching(),
                         )
-                    })?;
-                    relocations.next();
-                }
-                output_pos = next_output_pos;
-            } else {
-                // We're ignoring this entry, skip any relocations for it.
-                while let Some(rel) = relocations.peek() {
-                    let rel_offset = rel.r_offset.get(e);
-                    if rel_offset < next_input_pos as u64 {
+                        .with_context(|| {
+                            format!(
+                                "Failed to apply eh_frame {}",
+                                self.display_relocation(rel, layout)
+                            )
+                        })?;
                         relocations.next();
-                    } else {
-                        break;
+                    }
+                    output_pos = next_output_pos;
+                } else {
+                    // We're ignoring this entry, skip any relocations for it.
+                    while let Some(rel) = relocations.peek() {
+                        let rel_offset = rel.r_offset.get(e);
+                        if rel_offset < next_input_pos as u64 {
+                            relocations.next();
+                        } else {
+                            break;
+                        }
                     }
                 }
+                input_pos = next_input_pos;
             }
-            input_pos = next_input_pos;
         }
 
         // Copy any remaining bytes in .eh_frame that aren't large enough to constitute an actual
@@ -1601,6 +2070,9 @@ struct SectionInfo {
     section_address: u64,
     is_writable: bool,
     section_flags: SectionFlags,
+
+    /// Whether relative relocations at even places in this section go in `.relr.dyn`.
+    packs_relative_relocs: bool,
 }
 
 /// Applies the relocation `rel` at `offset_in_section`, where the section bytes are `out`. See "ELF
@@ -1648,6 +2120,7 @@ fn apply_relocation(
         value_flags,
         output_kind,
         section_info.section_flags,
+        layout.args().relax,
     ) {
         tracing::trace!(?relaxation.kind, %value_flags, %resolution_flags);
         rel_info = relaxation.rel_info;
@@ -1721,6 +2194,15 @@ fn apply_relocation(
             .wrapping_add(addend),
         RelocationKind::None => 0,
     };
+    if matches!(
+        r_type,
+        object::elf::R_X86_64_PC32 | object::elf::R_X86_64_PLT32
+    ) && matches!(
+        rel_info.kind,
+        RelocationKind::Relative | RelocationKind::PltRelative
+    ) {
+        check_branch_range(value)?;
+    }
     let value_bytes = value.to_le_bytes();
     let end = offset_in_section as usize + rel_info.byte_size;
     if out.len() < end {
@@ -1730,6 +2212,41 @@ fn apply_relocation(
     Ok(next_modifier)
 }
 
+/// Checks that the displacement of a call, jump or PC-relative reference fits in the signed 32 bits
+/// that x86-64 gives it. Other architectures insert range-extension thunks when a branch target is
+/// out of reach, but x86-64 has no such mechanism, so rather than truncating the displacement, we
+/// report it.
+fn check_branch_range(value: u64) -> Result {
+    if i32::try_from(value as i64).is_err() {
+        bail!(
+            "Branch target is out of range: a displacement of {} bytes doesn't fit in 32 bits",
+            value as i64
+        );
+    }
+    Ok(())
+}
+
+/// Returns the value that relocations in the debug section `section_name` should resolve to when
+/// they refer to a discarded section. We use the DWARF 6 tombstones: -1 in general, but -2 in
+/// `.debug_loc` and `.debug_ranges`, where a start address of -1 selects a new base address.
+///
+/// Link: https://dwarfstd.org/issues/200609.1.html
+fn debug_tombstone_value(section_name: &[u8], args: &Args) -> u64 {
+    if let Some(rule) = args
+        .dead_reloc_in_nonalloc
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(section_name))
+    {
+        return rule.value;
+    }
+    if section_name == b".debug_loc" || section_name == b".debug_ranges" {
+        -2_i64 as u64
+    } else {
+        u64::MAX
+    }
+}
+
 fn apply_debug_relocation(
     object_layout: &ObjectLayout,
     offset_in_section: u64,
@@ -1750,15 +2267,41 @@ fn apply_debug_relocation(
     let r_type = rel.r_type(e, false);
     let rel_info = RelocationKindInfo::from_raw(r_type)?;
 
-    let resolution = layout
-        .merged_symbol_resolution(object_layout.symbol_id_range.input_to_id(symbol_index))
-        .or_else(|| {
-            section_index.and_then(|section_index| {
+    // If the symbol is defined by a section of this object that we discarded, then the debug info
+    // describes code that isn't in the output. This can happen even if the symbol itself resolved,
+    // e.g. to a copy of a COMDAT group in another object. Using that copy's address would make
+    // debuggers attribute its addresses to the wrong compilation unit. The same applies to sections
+    // that were folded into an identical section.
+    let is_in_discarded_section = section_index.is_some_and(|section_index| {
+        matches!(
+            object_layout.sections[section_index.0],
+            SectionSlot::Discard
+                | SectionSlot::Unloaded(..)
+                | SectionSlot::Folded(..)
+                | SectionSlot::Duplicate(..)
+        )
+    });
+
+    let resolution = if is_in_discarded_section {
+        None
+    } else {
+        layout.merged_symbol_resolution(object_layout.symbol_id_range.input_to_id(symbol_index))
+    }
+    .or_else(|| {
+        section_index
+            .filter(|_| !is_in_discarded_section)
+            .and_then(|section_index| {
                 object_layout.section_resolutions[section_index.0].full_resolution()
             })
-        });
+    });
 
-    let value = if let Some(resolution) = resolution {
+    let value = if let Some(address) = section_index
+        .map(|section_index| kept_debug_section_address(object_layout, section_index, layout))
+        .transpose()?
+        .flatten()
+    {
+        address.wrapping_add(sym.st_value(e)).wrapping_add(addend)
+    } else if let Some(resolution) = resolution {
         match rel_info.kind {
             RelocationKind::Absolute => resolution.value_with_addend(
                 addend,
@@ -1787,7 +2330,10 @@ fn apply_debug_relocation(
                 string_offset_cache,
             )?
             .context("Cannot get merged string offset for a debug info section")?,
-            SectionSlot::Discard | SectionSlot::Unloaded(..) => section_tombstone_value,
+            SectionSlot::Discard
+            | SectionSlot::Unloaded(..)
+            | SectionSlot::Folded(..)
+            | SectionSlot::Duplicate(..) => section_tombstone_value,
             _ => bail!("Could not find a relocation resolution for a debug info section"),
         }
     } else {
@@ -1803,6 +2349,48 @@ fn apply_debug_relocation(
     Ok(())
 }
 
+/// Returns the address of the CIE with the specified placement.
+fn cie_address(placement: CiePlacement, layout: &Layout) -> Result<u64> {
+    match placement {
+        CiePlacement::Address(address) => Ok(address),
+        CiePlacement::SameAs(file_id, index) => {
+            let FileLayout::Object(object) = layout.file_layout(file_id) else {
+                bail!("CIE is a duplicate of one in a non-object file");
+            };
+            match object.cies[index].placement {
+                CiePlacement::Address(address) => Ok(address),
+                CiePlacement::SameAs(..) => bail!("CIE is a duplicate of another duplicate"),
+            }
+        }
+    }
+}
+
+/// Returns the address of the copy of `section_index` that we kept if it's a debug section, e.g.
+/// `.debug_macro`, in a COMDAT group that we discarded. Unlike for code, the kept copy describes the
+/// same thing, so references to ours can use it.
+fn kept_debug_section_address(
+    object_layout: &ObjectLayout,
+    section_index: object::SectionIndex,
+    layout: &Layout,
+) -> Result<Option<u64>> {
+    let SectionSlot::Duplicate(DuplicateSection {
+        kept_file,
+        kept_section: Some(kept_section),
+        ..
+    }) = object_layout.sections[section_index.0]
+    else {
+        return Ok(None);
+    };
+    let section = object_layout.object.section(section_index)?;
+    if SectionFlags::from_header(section).contains(shf::ALLOC) {
+        return Ok(None);
+    }
+    let FileLayout::Object(kept_object) = layout.file_layout(kept_file) else {
+        bail!("COMDAT group was kept in a file that isn't an object");
+    };
+    Ok(kept_object.section_resolutions[kept_section.0].address())
+}
+
 fn write_absolute_relocation(
     table_writer: &mut TableWriter,
     resolution: Resolution,
@@ -1813,19 +2401,50 @@ fn write_absolute_relocation(
     object_layout: &ObjectLayout,
     layout: &Layout,
 ) -> Result<u64> {
-    if resolution.value_flags.contains(ValueFlags::DYNAMIC) && section_info.is_writable {
+    // If our output is relocatable, then we emit a dynamic relocation even if the section is
+    // read-only. Layout will have checked that text relocations are permitted.
+    if resolution.value_flags.contains(ValueFlags::DYNAMIC)
+        && (section_info.is_writable || table_writer.output_kind.is_relocatable())
+    {
         table_writer.write_dynamic_symbol_relocation(
             place,
             addend,
             resolution.dynamic_symbol_index()?,
         )?;
         Ok(0)
-    } else if table_writer.output_kind.is_relocatable() && !resolution.is_absolute() {
-        table_writer
-            .write_address_relocation(place, resolution.raw_value.wrapping_add(addend) as i64)?;
+    } else if section_info.is_writable
+        && layout
+            .symbol_db
+            .symbol_value_flags(object_layout.symbol_id_range.input_to_id(symbol_index))
+            .is_interposable()
+    {
+        // Another module might define this symbol, so we need the runtime loader to fill in the
+        // address of whichever definition it selects. This needs to use the same flags as layout
+        // did when it allocated the relocation.
+        table_writer.write_rela_dyn_general(
+            place,
+            resolution.dynamic_symbol_index()?,
+            object::elf::R_X86_64_64,
+            addend as i64,
+        )?;
         Ok(0)
     } else if resolution.value_flags.contains(ValueFlags::IFUNC) {
+        if table_writer.output_kind.is_relocatable() {
+            if addend == 0 {
+                // Use the address that the resolver returns, so that it matches the GOT entry.
+                table_writer.write_irelative_relocation(place, resolution.raw_value)?;
+                return Ok(0);
+            }
+            let address = resolution.plt_address()?.wrapping_add(addend);
+            return table_writer.write_relative_relocation(place, address, &section_info);
+        }
         Ok(resolution.plt_address()?.wrapping_add(addend))
+    } else if table_writer.output_kind.is_relocatable() && !resolution.is_absolute() {
+        table_writer.write_relative_relocation(
+            place,
+            resolution.raw_value.wrapping_add(addend),
+            &section_info,
+        )
     } else {
         resolution.value_with_addend(
             addend,
@@ -1870,6 +2489,18 @@ impl PreludeLayout {
 
         self.write_merged_strings(buffers, layout);
 
+        if let Some(sframe) = self.sframe.as_ref() {
+            sframe
+                .write(
+                    buffers.get_mut(
+                        output_section_id::SFRAME.part_id_with_alignment(alignment::USIZE),
+                    ),
+                    layout.mem_address_of_built_in(output_section_id::SFRAME),
+                    layout,
+                )
+                .context("Failed to write .sframe")?;
+        }
+
         self.write_interp(buffers);
 
         // If we're emitting symbol versions, we should have only one - symbol 0 - the undefined
@@ -1917,9 +2548,35 @@ impl PreludeLayout {
             buffers.get_mut(output_section_id::COMMENT.part_id_with_alignment(alignment::MIN));
         crate::slice::slice_take_prefix_mut(comment_buffer, self.identity.len())
             .copy_from_slice(self.identity.as_bytes());
+
+        let note_buffer = buffers
+            .get_mut(output_section_id::NOTE_WILD_CMDLINE.part_id_with_alignment(alignment::NOTE));
+        crate::slice::slice_take_prefix_mut(note_buffer, self.command_line_note.len())
+            .copy_from_slice(&self.command_line_note);
+
+        let property_buffer = buffers
+            .get_mut(output_section_id::NOTE_GNU_PROPERTY.part_id_with_alignment(alignment::USIZE));
+        crate::slice::slice_take_prefix_mut(property_buffer, self.gnu_property_note.len())
+            .copy_from_slice(&self.gnu_property_note);
+
+        let ctf_buffer =
+            buffers.get_mut(output_section_id::CTF.part_id_with_alignment(alignment::MIN));
+        crate::slice::slice_take_prefix_mut(ctf_buffer, self.ctf.len()).copy_from_slice(&self.ctf);
+
+        if let Some(build_id) = layout.args().build_id.as_ref() {
+            let note_buffer = buffers.get_mut(
+                output_section_id::NOTE_GNU_BUILD_ID.part_id_with_alignment(alignment::NOTE),
+            );
+            crate::build_id::write_note_header(build_id, note_buffer);
+        }
     }
 
     fn write_plt_got_entries(&self, layout: &Layout, table_writer: &mut TableWriter) -> Result {
+        if self.has_lazy_plt {
+            table_writer
+                .write_lazy_plt_header(layout.vma_of_section(output_section_id::DYNAMIC))?;
+        }
+
         // Write a pair of GOT entries for use by any TLSLD or TLSGD relocations.
         if let Some(got_address) = self.tlsld_got_entry {
             if layout.args().output_kind.is_executable() {
@@ -1965,16 +2622,16 @@ impl PreludeLayout {
 }
 
 fn write_epilogue_dynamic_entries(layout: &Layout, table_writer: &mut TableWriter) -> Result {
-    for rpath in &layout.args().rpaths {
+    if let Some(rpath) = &layout.args().rpath {
         let offset = table_writer
             .dynsym_writer
             .strtab_writer
             .write_str(rpath.as_bytes());
         table_writer
             .dynamic
-            .write(object::elf::DT_RUNPATH, offset.into())?;
+            .write(layout.args().rpath_tag(), offset.into())?;
     }
-    if let Some(soname) = layout.args().soname.as_ref() {
+    if let Some(soname) = layout.args().dt_soname() {
         let offset = table_writer
             .dynsym_writer
             .strtab_writer
@@ -1983,6 +2640,13 @@ fn write_epilogue_dynamic_entries(layout: &Layout, table_writer: &mut TableWrite
             .dynamic
             .write(object::elf::DT_SONAME, offset.into())?;
     }
+    for (tag, name) in layout.args().dt_filters() {
+        let offset = table_writer
+            .dynsym_writer
+            .strtab_writer
+            .write_str(name.as_bytes());
+        table_writer.dynamic.write(tag, offset.into())?;
+    }
     for writer in EPILOGUE_DYNAMIC_ENTRY_WRITERS {
         writer.write(&mut table_writer.dynamic, layout)?;
     }
@@ -2099,16 +2763,10 @@ fn write_dynamic_symbol_definitions(
                     &mut table_writer.dynsym_writer,
                 )?;
 
-                // We don't yet support setting symbol versions for symbols that we export, so right
-                // now we just set them all to the global version.
-                if let Some(version_out) =
-                    crate::slice::take_first_mut(&mut table_writer.version_writer.versym)
-                {
-                    version_out.0.set(LittleEndian, object::elf::VER_NDX_GLOBAL);
-                }
+                write_defined_symbol_version(&mut table_writer.version_writer.versym);
             }
             FileLayout::Dynamic(object) => {
-                write_copy_relocation_dynamic_symbol_definition(
+                write_shared_object_symbol_definition(
                     sym_def,
                     object,
                     layout,
@@ -2122,6 +2780,26 @@ fn write_dynamic_symbol_definitions(
                     &mut table_writer.version_writer.versym,
                 )?;
             }
+            FileLayout::Epilogue(epilogue) => {
+                write_internal_dynamic_symbol_definition(
+                    sym_def,
+                    &epilogue.internal_symbols,
+                    layout,
+                    &mut table_writer.dynsym_writer,
+                )?;
+
+                write_defined_symbol_version(&mut table_writer.version_writer.versym);
+            }
+            FileLayout::Prelude(prelude) => {
+                write_internal_dynamic_symbol_definition(
+                    sym_def,
+                    &prelude.internal_symbols,
+                    layout,
+                    &mut table_writer.dynsym_writer,
+                )?;
+
+                write_defined_symbol_version(&mut table_writer.version_writer.versym);
+            }
             _ => bail!(
                 "Internal error: Unexpected dynamic symbol definition from {:?}. {}",
                 file_layout,
@@ -2133,30 +2811,51 @@ fn write_dynamic_symbol_definitions(
     Ok(())
 }
 
-fn write_copy_relocation_dynamic_symbol_definition(
+/// Writes a dynamic symbol for a symbol from a shared object that our output defines, either because
+/// we copied it or because its PLT entry is its address.
+fn write_shared_object_symbol_definition(
     sym_def: &crate::layout::DynamicSymbolDefinition,
     object: &DynamicLayout,
     layout: &Layout,
     dynamic_symbol_writer: &mut SymbolTableWriter,
 ) -> Result {
-    debug_assert_bail!(
-        layout
-            .resolution_flags_for_symbol(sym_def.symbol_id)
-            .contains(ResolutionFlags::COPY_RELOCATION),
-        "Tried to write copy relocation for symbol without COPY_RELOCATION flag"
-    );
     let sym_index = sym_def.symbol_id.to_input(object.symbol_id_range);
     let sym = object.object.symbol(sym_index)?;
     let name = sym_def.name;
-    let shndx = layout
-        .output_sections
-        .output_index_of_section(output_section_id::BSS)
-        .context("Copy relocation with no BSS section")?;
     let res = layout
         .local_symbol_resolution(sym_def.symbol_id)
-        .context("Copy relocation for unresolved symbol")?;
+        .context("Dynamic symbol definition for unresolved symbol")?;
+    let (shndx, value) = if res
+        .resolution_flags
+        .contains(ResolutionFlags::CANONICAL_PLT)
+    {
+        // The symbol stays undefined, but its value tells the dynamic loader to use our PLT entry
+        // as the function's address. Our PLT entry is an ordinary function, even if it leads to an
+        // ifunc.
+        let entry = dynamic_symbol_writer.define_symbol(
+            false,
+            object::elf::SHN_UNDEF,
+            res.plt_address()?,
+            sym.st_size(LittleEndian),
+            name,
+        )?;
+        entry.st_info = (sym.st_bind() << 4) | object::elf::STT_FUNC;
+        entry.st_other = sym.st_other();
+        return Ok(());
+    } else {
+        debug_assert_bail!(
+            res.resolution_flags
+                .contains(ResolutionFlags::COPY_RELOCATION),
+            "Tried to write copy relocation for symbol without COPY_RELOCATION flag"
+        );
+        let shndx = layout
+            .output_sections
+            .output_index_of_section(output_section_id::BSS)
+            .context("Copy relocation with no BSS section")?;
+        (shndx, res.raw_value)
+    };
     dynamic_symbol_writer
-        .copy_symbol_shndx(sym, name, shndx, res.raw_value)
+        .copy_symbol_shndx(sym, name, shndx, value)
         .with_context(|| {
             format!(
                 "Failed to copy dynamic {}",
@@ -2166,6 +2865,46 @@ fn write_copy_relocation_dynamic_symbol_definition(
     Ok(())
 }
 
+/// Writes a dynamic symbol for a symbol that we defined ourselves, such as a start/stop symbol or a
+/// symbol from `--defsym`. These are given protected visibility, since references from within our
+/// output will have already been resolved directly to them.
+fn write_internal_dynamic_symbol_definition(
+    sym_def: &crate::layout::DynamicSymbolDefinition,
+    internal_symbols: &InternalSymbols,
+    layout: &Layout,
+    dynamic_symbol_writer: &mut SymbolTableWriter,
+) -> Result {
+    let symbol_id = sym_def.symbol_id;
+    let def_info = internal_symbols
+        .symbol_definitions
+        .get(symbol_id.offset_from(internal_symbols.start_symbol_id))
+        .context("Internal error: Dynamic symbol isn't an internal symbol")?;
+    let (shndx, st_type) = internal_symbol_placement(*def_info, layout).with_context(|| {
+        format!(
+            "Failed to find section for exported {}",
+            layout.symbol_debug(symbol_id)
+        )
+    })?;
+    let resolution = layout.local_symbol_resolution(symbol_id).with_context(|| {
+        format!(
+            "Tried to write dynamic symbol definition without a resolution: {}",
+            layout.symbol_debug(symbol_id)
+        )
+    })?;
+    let entry = dynamic_symbol_writer
+        .define_symbol(
+            false,
+            shndx,
+            resolution.value_for_symbol_table(),
+            0,
+            sym_def.name,
+        )
+        .with_context(|| format!("Failed to write dynamic {}", layout.symbol_debug(symbol_id)))?;
+    entry.st_info = (object::elf::STB_GLOBAL << 4) | st_type;
+    entry.st_other = object::elf::STV_PROTECTED;
+    Ok(())
+}
+
 fn write_regular_object_dynamic_symbol_definition(
     sym_def: &crate::layout::DynamicSymbolDefinition,
     object: &ObjectLayout,
@@ -2176,10 +2915,11 @@ fn write_regular_object_dynamic_symbol_definition(
     let sym = object.object.symbol(sym_index)?;
     let name = sym_def.name;
     if let Some(section_index) = object.object.symbol_section(sym, sym_index)? {
-        let SectionSlot::Loaded(section) = &object.sections[section_index.0] else {
-            bail!("Internal error: Defined symbols should always be for a loaded section");
+        let output_section_id = match &object.sections[section_index.0] {
+            SectionSlot::Loaded(section) => section.output_section_id(),
+            SectionSlot::Folded(folded) => folded.section.output_section_id(),
+            _ => bail!("Internal error: Defined symbols should always be for a loaded section"),
         };
-        let output_section_id = section.output_section_id();
         let symbol_id = sym_def.symbol_id;
         let resolution = layout.local_symbol_resolution(symbol_id).with_context(|| {
             format!(
@@ -2226,23 +2966,15 @@ fn write_internal_symbols(
         let Some(resolution) = layout.local_symbol_resolution(symbol_id) else {
             continue;
         };
-        let Some(section_id) = def_info.section_id() else {
+        if matches!(def_info, InternalSymDefInfo::Undefined) {
             // The null symbol is currently handled elsewhere. TODO: See if the code would be
             // simpler if we just handled it here.
             continue;
-        };
+        }
 
         let symbol_name = layout.symbol_db.symbol_name(symbol_id)?;
-        let mut shndx = layout
-            .output_sections
-            .output_index_of_section(section_id)
-            .with_context(|| {
-                format!(
-                    "symbol `{}` in section `{}` that we're not going to output {resolution:?}",
-                    symbol_name,
-                    layout.output_sections.display_name(section_id)
-                )
-            })?;
+        let (mut shndx, st_type) = internal_symbol_placement(*def_info, layout)
+            .with_context(|| format!("symbol `{symbol_name}` {resolution:?}"))?;
 
         // Move symbols that are in our header (section 0) into the first section, otherwise they'll
         // show up as undefined.
@@ -2250,15 +2982,83 @@ fn write_internal_symbols(
             shndx = 1;
         }
 
-        let address = resolution.address()?;
         let entry = symbol_writer
-            .define_symbol(false, shndx, address, 0, symbol_name.bytes())
+            .define_symbol(
+                false,
+                shndx,
+                resolution.value_for_symbol_table(),
+                0,
+                symbol_name.bytes(),
+            )
             .with_context(|| format!("Failed to write {}", layout.symbol_debug(symbol_id)))?;
-        entry.st_info = object::elf::STB_GLOBAL << 4;
+        entry.st_info = (object::elf::STB_GLOBAL << 4) | st_type;
     }
     Ok(())
 }
 
+/// Returns the index of the output section that a symbol that we defined ourselves is in, together
+/// with the symbol's type.
+fn internal_symbol_placement(def_info: InternalSymDefInfo, layout: &Layout) -> Result<(u16, u8)> {
+    if let InternalSymDefInfo::Defsym(index) = def_info {
+        return defsym_placement(&layout.args().defsyms[index], layout);
+    }
+    let section_id = def_info
+        .section_id()
+        .context("Internal error: Internal symbol without a section")?;
+    let shndx = layout
+        .output_sections
+        .output_index_of_section(section_id)
+        .with_context(|| {
+            format!(
+                "Symbol is in section `{}` that we're not going to output",
+                layout.output_sections.display_name(section_id)
+            )
+        })?;
+    Ok((shndx, object::elf::STT_NOTYPE))
+}
+
+/// Returns the section index and type for a symbol defined by `--defsym`. Absolute values go in
+/// SHN_ABS, while aliases of other symbols take that symbol's section and type, as with GNU ld.
+fn defsym_placement(defsym: &Defsym, layout: &Layout) -> Result<(u16, u8)> {
+    let DefsymValue::Symbol(target) = &defsym.value else {
+        return Ok((object::elf::SHN_ABS, object::elf::STT_NOTYPE));
+    };
+    let target_id = layout
+        .symbol_db
+        .defsym_target(target)
+        .with_context(|| format!("Internal error: --defsym target `{target}` is undefined"))?;
+    match layout.file_layout(layout.symbol_db.file_id_for_symbol(target_id)) {
+        FileLayout::Object(object) => {
+            let sym_index = target_id.to_input(object.symbol_id_range);
+            let sym = object.object.symbol(sym_index)?;
+            let Some(section_index) = object.object.symbol_section(sym, sym_index)? else {
+                return Ok((object::elf::SHN_ABS, sym.st_type()));
+            };
+            let output_section_id = match &object.sections[section_index.0] {
+                SectionSlot::Loaded(section) => section.output_section_id(),
+                SectionSlot::Folded(folded) => folded.section.output_section_id(),
+                _ => bail!("Internal error: Defined symbols should always be for a loaded section"),
+            };
+            let shndx = layout
+                .output_sections
+                .output_index_of_section(output_section_id)
+                .context("--defsym target is in a section that we're not going to output")?;
+            Ok((shndx, sym.st_type()))
+        }
+        FileLayout::Prelude(PreludeLayout {
+            internal_symbols, ..
+        })
+        | FileLayout::Epilogue(EpilogueLayout {
+            internal_symbols, ..
+        }) => internal_symbol_placement(
+            internal_symbols.symbol_definitions
+                [target_id.offset_from(internal_symbols.start_symbol_id)],
+            layout,
+        ),
+        other => bail!("Internal error: Unexpected --defsym target in {other:?}"),
+    }
+}
+
 fn write_eh_frame_hdr(table_writer: &mut TableWriter, layout: &Layout<'_>) -> Result {
     let header = table_writer.take_eh_frame_hdr();
     header.version = 1;
@@ -2304,13 +3104,13 @@ pub(crate) const NUM_EPILOGUE_DYNAMIC_ENTRIES: usize = EPILOGUE_DYNAMIC_ENTRY_WR
 const EPILOGUE_DYNAMIC_ENTRY_WRITERS: &[DynamicEntryWriter] = &[
     DynamicEntryWriter::optional(
         object::elf::DT_INIT,
-        |layout| layout.has_data_in_section(output_section_id::INIT),
-        |layout| layout.vma_of_section(output_section_id::INIT),
+        |layout| init_address(layout).is_some(),
+        |layout| init_address(layout).unwrap_or(0),
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_FINI,
-        |layout| layout.has_data_in_section(output_section_id::FINI),
-        |layout| layout.vma_of_section(output_section_id::FINI),
+        |layout| fini_address(layout).is_some(),
+        |layout| fini_address(layout).unwrap_or(0),
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_INIT_ARRAY,
@@ -2388,23 +3188,29 @@ const EPILOGUE_DYNAMIC_ENTRY_WRITERS: &[DynamicEntryWriter] = &[
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_JMPREL,
-        |layout| layout.section_part_layouts.get(part_id::RELA_PLT).mem_size > 0,
+        |layout| layout.size_of_section(output_section_id::RELA_PLT) > 0,
         |layout| layout.vma_of_section(output_section_id::RELA_PLT),
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_PLTGOT,
         |layout| layout.args().needs_dynamic(),
-        |layout| layout.vma_of_section(output_section_id::GOT),
+        |layout| {
+            if layout.args().lazy_binding() {
+                layout.vma_of_section(output_section_id::GOT_PLT)
+            } else {
+                layout.vma_of_section(output_section_id::GOT)
+            }
+        },
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_PLTREL,
-        |layout| layout.section_part_layouts.get(part_id::RELA_PLT).mem_size > 0,
+        |layout| layout.size_of_section(output_section_id::RELA_PLT) > 0,
         |_| object::elf::DT_RELA.into(),
     ),
     DynamicEntryWriter::optional(
         object::elf::DT_PLTRELSZ,
-        |layout| layout.section_part_layouts.get(part_id::RELA_PLT).mem_size > 0,
-        |layout| layout.section_part_layouts.get(part_id::RELA_PLT).mem_size,
+        |layout| layout.size_of_section(output_section_id::RELA_PLT) > 0,
+        |layout| layout.size_of_section(output_section_id::RELA_PLT),
     ),
     DynamicEntryWriter::new(object::elf::DT_RELA, |layout| {
         layout.vma_of_section(output_section_id::RELA_DYN)
@@ -2422,9 +3228,29 @@ const EPILOGUE_DYNAMIC_ENTRY_WRITERS: &[DynamicEntryWriter] = &[
             .mem_size
             / core::mem::size_of::<elf::Rela>() as u64
     }),
+    DynamicEntryWriter::optional(
+        crate::relr::DT_RELR,
+        |layout| layout.size_of_section(output_section_id::RELR_DYN) > 0,
+        |layout| layout.vma_of_section(output_section_id::RELR_DYN),
+    ),
+    DynamicEntryWriter::optional(
+        crate::relr::DT_RELRSZ,
+        |layout| layout.size_of_section(output_section_id::RELR_DYN) > 0,
+        |layout| layout.size_of_section(output_section_id::RELR_DYN),
+    ),
+    DynamicEntryWriter::optional(
+        crate::relr::DT_RELRENT,
+        |layout| layout.size_of_section(output_section_id::RELR_DYN) > 0,
+        |_layout| crate::relr::ENTRY_SIZE,
+    ),
     DynamicEntryWriter::new(object::elf::DT_GNU_HASH, |layout| {
         layout.vma_of_section(output_section_id::GNU_HASH)
     }),
+    DynamicEntryWriter::optional(
+        object::elf::DT_TEXTREL,
+        |layout| layout.has_text_relocations,
+        |_layout| 0,
+    ),
     DynamicEntryWriter::optional(
         object::elf::DT_FLAGS,
         |layout| layout.dt_flags() != 0,
@@ -2438,6 +3264,22 @@ const EPILOGUE_DYNAMIC_ENTRY_WRITERS: &[DynamicEntryWriter] = &[
     DynamicEntryWriter::new(object::elf::DT_NULL, |_layout| 0),
 ];
 
+fn init_address(layout: &Layout) -> Option<u64> {
+    layout.init_function_address(
+        layout.args().init.as_deref(),
+        layout.prelude().init_symbol_id,
+        output_section_id::INIT,
+    )
+}
+
+fn fini_address(layout: &Layout) -> Option<u64> {
+    layout.init_function_address(
+        layout.args().fini.as_deref(),
+        layout.prelude().fini_symbol_id,
+        output_section_id::FINI,
+    )
+}
+
 struct DynamicEntryWriter {
     tag: u32,
     is_present_cb: fn(&Layout) -> bool,
@@ -2527,10 +3369,11 @@ fn write_section_headers(out: &mut [u8], layout: &Layout) {
             size = section_layout.mem_size;
             alignment = section_layout.alignment.value();
         };
-        let link = layout
-            .output_sections
-            .link_ids(section_id)
+        let link = output_sections
+            .output_info(section_id)
+            .link_order_link
             .iter()
+            .chain(layout.output_sections.link_ids(section_id))
             .find_map(|link_id| output_sections.output_index_of_section(*link_id))
             .unwrap_or(0);
         let entry = entries.next().unwrap();
@@ -2538,11 +3381,13 @@ fn write_section_headers(out: &mut [u8], layout: &Layout) {
         entry.sh_name.set(e, name_offset);
         entry.sh_type.set(e, section_type.raw());
         // TODO: Section are always uncompressed and the output compression is not supported yet.
+        // We don't write section groups, so none of our sections are members of one.
         entry.sh_flags.set(
             e,
             output_sections
                 .section_flags(section_id)
                 .without(shf::COMPRESSED)
+                .without(shf::GROUP)
                 .raw(),
         );
         entry.sh_addr.set(e, section_layout.mem_offset);
@@ -2623,7 +3468,28 @@ impl<'data> DynamicLayout<'data> {
                     .contains(ResolutionFlags::COPY_RELOCATION)
                 {
                     // Symbol needs a copy relocation, which means that the symbol will be written
-                    // by the epilogue not by us.
+                    // by the epilogue not by us. Aliases share a single copy, so only one of them
+                    // gets the relocation.
+                    if crate::layout::copy_relocation_owner(
+                        self.object,
+                        self.symbol_id_range,
+                        layout.symbol_db,
+                        symbol_id,
+                    )? == symbol_id
+                    {
+                        table_writer.write_rela_dyn_general(
+                            res.raw_value,
+                            res.dynamic_symbol_index()?,
+                            object::elf::R_X86_64_COPY,
+                            0,
+                        )?;
+                    }
+                } else if res
+                    .resolution_flags
+                    .contains(ResolutionFlags::CANONICAL_PLT)
+                {
+                    // Likewise, the epilogue writes the symbol, since it needs to be in the hash
+                    // table.
                 } else {
                     let name = self.object.symbol_name(symbol)?;
                     table_writer
@@ -2733,6 +3599,16 @@ impl<'data> DynamicLayout<'data> {
     }
 }
 
+/// Writes the version of a dynamic symbol that we define, either in an object or as a
+/// linker-defined symbol such as `__start_foo`. Symbols that a version script makes local never get
+/// here, since they're not exported. We don't yet emit version definitions, so everything that is
+/// exported gets the global version.
+fn write_defined_symbol_version(versym_out: &mut &mut [Versym]) {
+    if let Some(version_out) = crate::slice::take_first_mut(versym_out) {
+        version_out.0.set(LittleEndian, object::elf::VER_NDX_GLOBAL);
+    }
+}
+
 fn write_symbol_version(
     versym_in: &[Versym],
     local_symbol_index: usize,
@@ -2826,7 +3702,9 @@ pub(crate) fn verify_resolution_allocation(
     let debug_symbol_writer = SymbolTableWriter::new(0, &mut buffers, output_sections);
     let mut table_writer = TableWriter::new(
         output_kind,
+        LazyBinding::default(),
         0..100,
+        false,
         &mut buffers,
         dynsym_writer,
         debug_symbol_writer,
@@ -2835,3 +3713,12 @@ pub(crate) fn verify_resolution_allocation(
     table_writer.process_resolution(resolution)?;
     table_writer.validate_empty(&mem_sizes)
 }
+
+#[test]
+fn test_check_branch_range() {
+    assert!(check_branch_range(0).is_ok());
+    assert!(check_branch_range(i32::MAX as u64).is_ok());
+    assert!(check_branch_range(i32::MIN as i64 as u64).is_ok());
+    assert!(check_branch_range(i32::MAX as u64 + 1).is_err());
+    assert!(check_branch_range((i32::MIN as i64 - 1) as u64).is_err());
+}
@@ -1,7 +1,354 @@
+use crate::args::ColorChoice;
+use crate::args::DiagnosticsFormat;
+use crate::json::Json;
+use crate::source_location::SourceLocation;
 pub(crate) use anyhow::Error;
+use std::fmt::Display;
+use std::io::IsTerminal as _;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 pub type Result<T = (), E = Error> = core::result::Result<T, E>;
 
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_MAGENTA: &str = "\x1b[1;35m";
+const CYAN: &str = "\x1b[36m";
+
+/// An error or warning about a symbol, together with the locations that it relates to. The
+/// `Display` impl gives the text form, while `--diagnostics-format=json` writes the fields.
+#[derive(Debug)]
+pub(crate) struct Diagnostic {
+    /// What went wrong, e.g. `undefined-symbol`.
+    pub(crate) kind: &'static str,
+
+    /// Precedes the symbol in the text form, e.g. "undefined symbol".
+    pub(crate) summary: &'static str,
+
+    pub(crate) symbol: String,
+    pub(crate) locations: Vec<DiagnosticLocation>,
+
+    /// How each location relates to the symbol, e.g. "referenced by".
+    pub(crate) location_label: &'static str,
+
+    /// Whether to describe locations by the function containing them, if known, rather than by
+    /// section and offset.
+    pub(crate) prefer_function: bool,
+
+    /// A line to write after the locations, e.g. to say how many we left out.
+    pub(crate) note: Option<String>,
+}
+
+/// A location in an input object that a diagnostic relates to.
+#[derive(Debug)]
+pub(crate) struct DiagnosticLocation {
+    /// The input, formatted like `libfoo.a(foo.o)`.
+    pub(crate) file: String,
+
+    /// The name of the section. `None` for absolute symbols.
+    pub(crate) section: Option<String>,
+
+    pub(crate) section_offset: u64,
+
+    /// The offset within the input object, if the section's data is stored uncompressed.
+    pub(crate) file_offset: Option<u64>,
+
+    /// The function or object containing the location.
+    pub(crate) function: Option<String>,
+
+    pub(crate) source: Option<SourceLocation>,
+}
+
+/// Several errors that are reported together. See `combine`.
+#[derive(Debug)]
+struct Errors {
+    /// The errors to show. There may have been more, up to `total`.
+    errors: Vec<Error>,
+    total: usize,
+}
+
+/// How we present diagnostics, as chosen by `--color-diagnostics` and `--diagnostics-format`,
+/// together with the number of warnings that we've issued. Each set of arguments has its own, so
+/// links that run in the same process don't affect each other.
+#[derive(Debug)]
+pub(crate) struct Diagnostics {
+    pub(crate) color_choice: ColorChoice,
+
+    pub(crate) format: DiagnosticsFormat,
+
+    /// The number of warnings that we've issued. Used to implement `--fatal-warnings`.
+    num_warnings: AtomicUsize,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(color_choice: ColorChoice, format: DiagnosticsFormat) -> Self {
+        Self {
+            color_choice,
+            format,
+            num_warnings: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_json(&self) -> bool {
+        self.format == DiagnosticsFormat::Json
+    }
+
+    /// Returns whether to use colour in text diagnostics.
+    fn use_color(&self) -> bool {
+        // Escape codes would just get in the way of tools reading our JSON.
+        !self.is_json() && should_use_color(self.color_choice)
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        paint(text, style, self.use_color())
+    }
+
+    /// Formats the name of an input file so that it stands out in a diagnostic.
+    pub(crate) fn styled_file(&self, name: &str) -> String {
+        self.paint(name, CYAN)
+    }
+
+    /// Prints `error` to stderr. Used for errors that end the link.
+    pub(crate) fn report(&self, error: &Error) {
+        let errors = error.downcast_ref::<Errors>();
+        if !self.is_json() {
+            let label = self.paint("error:", BOLD_RED);
+            if let Some(errors) = errors {
+                eprintln!("{label} {}", errors.to_text(self.use_color()));
+            } else if let Some(diagnostic) = error.downcast_ref::<Diagnostic>() {
+                eprintln!("{label} {}", diagnostic.to_text(self.use_color()));
+            } else {
+                eprintln!("{label} {error:?}");
+            }
+            return;
+        }
+        match errors {
+            Some(errors) => {
+                for error in &errors.errors {
+                    print_json("error", error.downcast_ref(), &format!("{error:#}"));
+                }
+            }
+            None => print_json("error", error.downcast_ref(), &format!("{error:#}")),
+        }
+    }
+
+    /// Issues a warning. Use the `warning!` macro rather than calling this directly.
+    pub(crate) fn warning(&self, message: &str) {
+        self.num_warnings.fetch_add(1, Ordering::Relaxed);
+        if self.is_json() {
+            print_json("warning", None, message);
+        } else {
+            println!("{} {message}", self.paint("warning:", BOLD_MAGENTA));
+        }
+    }
+
+    /// Issues `diagnostic` as a warning.
+    pub(crate) fn warn(&self, diagnostic: &Diagnostic) {
+        if self.is_json() {
+            self.num_warnings.fetch_add(1, Ordering::Relaxed);
+            print_json("warning", Some(diagnostic), &diagnostic.to_string());
+        } else {
+            self.warning(&diagnostic.to_text(self.use_color()));
+        }
+    }
+
+    /// Forgets about any warnings from a previous link that used the same arguments.
+    pub(crate) fn reset_warnings(&self) {
+        self.num_warnings.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn num_warnings(&self) -> usize {
+        self.num_warnings.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Diagnostics {
+    /// What we use before we've parsed our arguments.
+    fn default() -> Self {
+        Self::new(ColorChoice::Auto, DiagnosticsFormat::Text)
+    }
+}
+
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if color {
+        format!("{style}{text}{RESET}")
+    } else {
+        text.to_owned()
+    }
+}
+
+fn should_use_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+                && std::io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Prints `error` to stderr for when we don't have any arguments that say how to present it, e.g.
+/// because we failed to parse them. See also `Linker::report_error`.
+pub fn report(error: &Error) {
+    Diagnostics::default().report(error);
+}
+
+/// Writes a diagnostic as a line of JSON to stderr.
+fn print_json(severity: &str, diagnostic: Option<&Diagnostic>, message: &str) {
+    let mut out = Vec::new();
+    // Writing to a Vec can't fail.
+    let _ = diagnostic_json(severity, diagnostic, message).write_compact(&mut out);
+    eprintln!("{}", String::from_utf8_lossy(&out));
+}
+
+fn diagnostic_json(severity: &str, diagnostic: Option<&Diagnostic>, message: &str) -> Json {
+    let mut fields = vec![
+        ("severity", Json::String(severity.to_owned())),
+        (
+            "kind",
+            Json::String(diagnostic.map_or("other", |d| d.kind).to_owned()),
+        ),
+        ("message", Json::String(message.to_owned())),
+    ];
+    if let Some(diagnostic) = diagnostic {
+        fields.push(("symbol", Json::String(diagnostic.symbol.clone())));
+        fields.push((
+            "locations",
+            Json::Array(
+                diagnostic
+                    .locations
+                    .iter()
+                    .map(DiagnosticLocation::to_json)
+                    .collect(),
+            ),
+        ));
+    }
+    Json::Object(fields)
+}
+
+impl DiagnosticLocation {
+    /// Writes the location in the style of lld: the source file and line if known, then the input
+    /// and either the containing function or the section and offset. `what` describes the
+    /// location, e.g. "referenced by".
+    fn write(
+        &self,
+        out: &mut String,
+        what: &str,
+        prefer_function: bool,
+        color: bool,
+    ) -> std::fmt::Result {
+        use std::fmt::Write as _;
+
+        let place = match (&self.section, &self.function) {
+            (_, Some(function)) if prefer_function => format!("({function})"),
+            (Some(section), _) => format!("({section}+0x{:x})", self.section_offset),
+            (None, _) => "(*ABS*)".to_owned(),
+        };
+        let file = paint(&self.file, CYAN, color);
+        match &self.source {
+            Some(source) => {
+                writeln!(out, ">>> {what} {source}")?;
+                writeln!(out, ">>> {:width$} {file}:{place}", "", width = what.len())?;
+            }
+            None => writeln!(out, ">>> {what} {file}:{place}")?,
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Json {
+        let mut fields = vec![("file", Json::String(self.file.clone()))];
+        if let Some(section) = &self.section {
+            fields.push(("section", Json::String(section.clone())));
+        }
+        fields.push(("section_offset", Json::Number(self.section_offset)));
+        if let Some(file_offset) = self.file_offset {
+            fields.push(("file_offset", Json::Number(file_offset)));
+        }
+        if let Some(function) = &self.function {
+            fields.push(("function", Json::String(function.clone())));
+        }
+        if let Some(source) = &self.source {
+            fields.push(("source_file", Json::String(source.file.clone())));
+            fields.push(("line", Json::Number(source.line)));
+        }
+        Json::Object(fields)
+    }
+}
+
+impl Diagnostic {
+    /// Returns the text form, styled for the terminal if `color` is set.
+    fn to_text(&self, color: bool) -> String {
+        let mut text = format!("{}: {}\n", self.summary, paint(&self.symbol, BOLD, color));
+        for location in &self.locations {
+            // Writing to a String can't fail.
+            let _ = location.write(&mut text, self.location_label, self.prefer_function, color);
+        }
+        if let Some(note) = &self.note {
+            text.push_str(note);
+        }
+        text.truncate(text.trim_end().len());
+        text
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_text(false), f)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Errors {
+    /// Returns the text form, styled for the terminal if `color` is set.
+    fn to_text(&self, color: bool) -> String {
+        use std::fmt::Write as _;
+
+        let label = paint("error:", BOLD_RED, color);
+        let mut text = format!("{} errors:", self.total);
+        for error in &self.errors {
+            match error.downcast_ref::<Diagnostic>() {
+                Some(diagnostic) => {
+                    let _ = write!(text, "\n\n{label} {}", diagnostic.to_text(color));
+                }
+                None => {
+                    let _ = write!(text, "\n\n{label} {error:#}");
+                }
+            }
+        }
+        if self.errors.len() < self.total {
+            text.push_str(
+                "\n\ntoo many errors emitted, stopping now (use --error-limit=0 to see all errors)",
+            );
+        }
+        text
+    }
+}
+
+impl Display for Errors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_text(false), f)
+    }
+}
+
+impl std::error::Error for Errors {}
+
+/// Combines `errors` into a single error so that they can all be reported together. At most `limit`
+/// errors are included, or all of them if `limit` is zero. Returns `Ok` if there are no errors.
+pub(crate) fn combine(mut errors: Vec<Error>, limit: usize) -> Result {
+    if errors.len() <= 1 {
+        return errors.pop().map_or(Ok(()), Err);
+    }
+    let total = errors.len();
+    if limit != 0 {
+        errors.truncate(limit);
+    }
+    Err(Error::new(Errors { errors, total }))
+}
+
 /// Like debug_assert, but bails instead of panicking.
 ///
 /// Returning an error often allows us to give
@@ -16,11 +363,86 @@ macro_rules! debug_assert_bail {
     };
 }
 
-/// Prints a warning. By using our own macro for this, it'll be easier to find places that issue
-/// warnings if we want to say have a flag to suppress them.
+/// Prints a warning via `diagnostics`, which is generally `args.diagnostics`. By using our own macro
+/// for this, it'll be easier to find places that issue warnings if we want to say have a flag to
+/// suppress them.
 #[macro_export]
 macro_rules! warning {
-    ($($args:tt)*) => {
-        println!($($args)*);
+    ($diagnostics:expr, $($args:tt)*) => {
+        $diagnostics.warning(&format!($($args)*))
     };
 }
+
+#[test]
+fn test_combine() {
+    assert!(combine(Vec::new(), 20).is_ok());
+    let errors = || {
+        vec![
+            anyhow::anyhow!("a"),
+            anyhow::anyhow!("b"),
+            anyhow::anyhow!("c"),
+        ]
+    };
+    assert_eq!(
+        combine(vec![anyhow::anyhow!("a")], 20)
+            .unwrap_err()
+            .to_string(),
+        "a"
+    );
+    assert_eq!(
+        combine(errors(), 0).unwrap_err().to_string(),
+        "3 errors:\n\nerror: a\n\nerror: b\n\nerror: c"
+    );
+    assert_eq!(
+        combine(errors(), 2).unwrap_err().to_string(),
+        "3 errors:\n\nerror: a\n\nerror: b\n\n\
+         too many errors emitted, stopping now (use --error-limit=0 to see all errors)"
+    );
+}
+
+#[test]
+fn test_styling_is_only_applied_when_printing() {
+    let diagnostic = || Diagnostic {
+        kind: "undefined-symbol",
+        summary: "undefined symbol",
+        symbol: "foo".to_owned(),
+        locations: vec![DiagnosticLocation {
+            file: "a.o".to_owned(),
+            section: Some(".text".to_owned()),
+            section_offset: 0x10,
+            file_offset: None,
+            function: Some("main".to_owned()),
+            source: None,
+        }],
+        location_label: "referenced by",
+        prefer_function: true,
+        note: None,
+    };
+    let plain = "undefined symbol: foo\n>>> referenced by a.o:(main)";
+    assert_eq!(diagnostic().to_string(), plain);
+    assert_eq!(diagnostic().to_text(false), plain);
+    assert_eq!(
+        diagnostic().to_text(true),
+        "undefined symbol: \x1b[1mfoo\x1b[0m\n>>> referenced by \x1b[36ma.o\x1b[0m:(main)"
+    );
+
+    let Err(error) = combine(vec![Error::new(diagnostic()), anyhow::anyhow!("b")], 0) else {
+        panic!("Expected an error");
+    };
+    let errors = error.downcast_ref::<Errors>().unwrap();
+    assert_eq!(
+        error.to_string(),
+        format!("2 errors:\n\nerror: {plain}\n\nerror: b")
+    );
+    assert!(errors.to_text(true).contains("\x1b[1;31merror:\x1b[0m b"));
+}
+
+#[test]
+fn test_warnings_are_counted_per_diagnostics() {
+    let first = Diagnostics::new(ColorChoice::Never, DiagnosticsFormat::Text);
+    let second = Diagnostics::new(ColorChoice::Never, DiagnosticsFormat::Text);
+    crate::warning!(first, "a {}", 1);
+    crate::warning!(first, "b");
+    assert_eq!(first.num_warnings(), 2);
+    assert_eq!(second.num_warnings(), 0);
+}
@@ -1,8 +1,10 @@
 //! Code for identifying what sort of file we're dealing with based on the bytes of the file.
 
+use crate::args::Emulation;
 use crate::elf;
 use crate::error::Result;
 use anyhow::bail;
+use object::elf::SHT_SYMTAB;
 use object::read::elf::FileHeader;
 use object::LittleEndian;
 
@@ -13,9 +15,27 @@ pub(crate) enum FileKind {
     ElfDynamic,
     Archive,
     Text,
+    LlvmIr,
     Epilogue,
 }
 
+/// The kind of compiler IR that an input contains when it was compiled for link-time optimisation.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum IrKind {
+    /// LLVM bitcode, either bare or in a bitcode wrapper.
+    LlvmBitcode,
+
+    /// An ELF object from GCC that contains only IR.
+    GccSlim,
+
+    /// An ELF object from GCC that contains both IR and regular code, so can be linked without a
+    /// plugin.
+    GccFat,
+
+    /// An ELF object from LLVM that has bitcode in a `.llvm.lto` section as well as regular code.
+    LlvmFat,
+}
+
 impl FileKind {
     pub(crate) fn identify_bytes(bytes: &[u8]) -> Result<FileKind> {
         if bytes.starts_with(b"!<arch>") {
@@ -25,35 +45,204 @@ impl FileKind {
             if bytes.len() < HEADER_LEN {
                 bail!("Invalid ELF file");
             }
+            check_target(bytes)?;
             let header: &elf::FileHeader = object::from_bytes(&bytes[..HEADER_LEN]).unwrap().0;
-            if header.e_ident.class != object::elf::ELFCLASS64 {
-                bail!("Only 64 bit ELF is currently supported");
-            }
-            if header.e_ident.data != object::elf::ELFDATA2LSB {
-                bail!("Only little endian is currently supported");
-            }
-
-            let sections = header.sections(LittleEndian, bytes)?;
-            if sections.iter().any(|sec| {
-                sections
-                    .section_name(LittleEndian, sec)
-                    .map(|section_name| section_name.starts_with(b".gnu.lto_.symtab"))
-                    .unwrap_or(false)
-            }) {
-                bail!("GCC IR (LTO mode) is not supported yet");
-            }
-
             match header.e_type.get(LittleEndian) {
                 object::elf::ET_REL => Ok(FileKind::ElfObject),
                 object::elf::ET_DYN => Ok(FileKind::ElfDynamic),
                 t => bail!("Unsupported ELF kind {t}"),
             }
+        } else if is_llvm_bitcode(bytes) {
+            Ok(FileKind::LlvmIr)
         } else if bytes.is_ascii() {
             Ok(FileKind::Text)
-        } else if bytes.starts_with(b"BC") {
-            bail!("LLVM IR (LTO mode) is not supported yet");
         } else {
             bail!("Couldn't identify file type");
         }
     }
 }
+
+impl IrKind {
+    /// Returns what kind of IR `bytes` contains, if any.
+    pub(crate) fn identify(bytes: &[u8]) -> Option<IrKind> {
+        if is_llvm_bitcode(bytes) {
+            return Some(IrKind::LlvmBitcode);
+        }
+        if !bytes.starts_with(&object::elf::ELFMAG) || check_target(bytes).is_err() {
+            return None;
+        }
+        let header = elf::FileHeader::parse(bytes).ok()?;
+        let sections = header.sections(LittleEndian, bytes).ok()?;
+        let has_section = |predicate: fn(&[u8]) -> bool| {
+            sections.iter().any(|section| {
+                sections
+                    .section_name(LittleEndian, section)
+                    .is_ok_and(predicate)
+            })
+        };
+        if has_section(|name| name == b".llvm.lto") {
+            return Some(IrKind::LlvmFat);
+        }
+        if !has_section(|name| name.starts_with(b".gnu.lto_")) {
+            return None;
+        }
+        // GCC marks objects that contain only IR with a special symbol.
+        let symbols = sections.symbols(LittleEndian, bytes, SHT_SYMTAB).ok()?;
+        let is_slim = symbols.iter().any(|symbol| {
+            symbols
+                .symbol_name(LittleEndian, symbol)
+                .is_ok_and(|name| name == b"__gnu_lto_slim")
+        });
+        Some(if is_slim {
+            IrKind::GccSlim
+        } else {
+            IrKind::GccFat
+        })
+    }
+
+    /// Like `identify`, but only recognises bare or wrapped bitcode, which is much cheaper to check
+    /// for.
+    pub(crate) fn identify_bitcode(bytes: &[u8]) -> Option<IrKind> {
+        is_llvm_bitcode(bytes).then_some(IrKind::LlvmBitcode)
+    }
+
+    pub(crate) fn needs_plugin(self) -> bool {
+        matches!(self, IrKind::LlvmBitcode | IrKind::GccSlim)
+    }
+}
+
+impl std::fmt::Display for IrKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IrKind::LlvmBitcode | IrKind::LlvmFat => f.write_str("LLVM IR"),
+            IrKind::GccSlim | IrKind::GccFat => f.write_str("GCC IR"),
+        }
+    }
+}
+
+/// Returns whether `bytes` is LLVM bitcode, either bare or inside the wrapper that Apple's tools
+/// use.
+fn is_llvm_bitcode(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"BC\xC0\xDE") || bytes.starts_with(&0x0B17_C0DE_u32.to_le_bytes())
+}
+
+/// Offsets within `e_ident`.
+const EI_CLASS: usize = 4;
+const EI_DATA: usize = 5;
+
+/// The machine that we produce output for.
+const TARGET_MACHINE: u16 = object::elf::EM_X86_64;
+
+/// Checks that we can produce output for `emulation`, which may have been inferred from our first
+/// input rather than given with `-m`.
+pub(crate) fn check_emulation(emulation: &Emulation) -> Result {
+    if emulation.machine != TARGET_MACHINE {
+        bail!(
+            "Linking for `{}` isn't supported yet, only for {}",
+            emulation.name,
+            describe_target(
+                TARGET_MACHINE,
+                object::elf::ELFCLASS64,
+                object::elf::ELFDATA2LSB
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the ELF file in `bytes` is for the same architecture, class and byte order as our
+/// output. Mixing in a file built for something else would otherwise fail later with an error that
+/// doesn't point at the real problem, e.g. an unsupported relocation type.
+pub(crate) fn check_target(bytes: &[u8]) -> Result {
+    // The identification bytes, `e_type` and `e_machine` are at the same offsets for both ELF
+    // classes, so we can read them before we know which class the file is.
+    const E_MACHINE_OFFSET: usize = 18;
+    let Some(machine) = bytes.get(E_MACHINE_OFFSET..E_MACHINE_OFFSET + 2) else {
+        bail!("Invalid ELF file");
+    };
+    let class = bytes[EI_CLASS];
+    let data = bytes[EI_DATA];
+    let machine = match data {
+        object::elf::ELFDATA2MSB => u16::from_be_bytes([machine[0], machine[1]]),
+        _ => u16::from_le_bytes([machine[0], machine[1]]),
+    };
+    if machine != TARGET_MACHINE
+        || class != object::elf::ELFCLASS64
+        || data != object::elf::ELFDATA2LSB
+    {
+        bail!(
+            "Incompatible input: file is {}, but we're linking {}",
+            describe_target(machine, class, data),
+            describe_target(
+                TARGET_MACHINE,
+                object::elf::ELFCLASS64,
+                object::elf::ELFDATA2LSB
+            )
+        );
+    }
+    Ok(())
+}
+
+fn describe_target(machine: u16, class: u8, data: u8) -> String {
+    let machine = match machine {
+        object::elf::EM_386 => "i386".to_owned(),
+        object::elf::EM_X86_64 => "x86-64".to_owned(),
+        object::elf::EM_ARM => "ARM".to_owned(),
+        object::elf::EM_AARCH64 => "AArch64".to_owned(),
+        object::elf::EM_RISCV => "RISC-V".to_owned(),
+        object::elf::EM_PPC => "PowerPC".to_owned(),
+        object::elf::EM_PPC64 => "PowerPC64".to_owned(),
+        object::elf::EM_S390 => "s390".to_owned(),
+        object::elf::EM_MIPS => "MIPS".to_owned(),
+        object::elf::EM_SPARCV9 => "SPARC V9".to_owned(),
+        object::elf::EM_LOONGARCH => "LoongArch".to_owned(),
+        other => format!("machine {other}"),
+    };
+    let class = match class {
+        object::elf::ELFCLASS32 => "ELF32",
+        object::elf::ELFCLASS64 => "ELF64",
+        _ => "ELF of unknown class",
+    };
+    let data = match data {
+        object::elf::ELFDATA2LSB => "little-endian",
+        object::elf::ELFDATA2MSB => "big-endian",
+        _ => "unknown byte order",
+    };
+    format!("{machine} ({class}, {data})")
+}
+
+#[test]
+fn test_check_target() {
+    fn header(class: u8, data: u8, machine: u16) -> Vec<u8> {
+        let mut bytes = vec![0; 64];
+        bytes[..4].copy_from_slice(&object::elf::ELFMAG);
+        bytes[EI_CLASS] = class;
+        bytes[EI_DATA] = data;
+        let machine = if data == object::elf::ELFDATA2MSB {
+            machine.to_be_bytes()
+        } else {
+            machine.to_le_bytes()
+        };
+        bytes[18..20].copy_from_slice(&machine);
+        bytes
+    }
+    use object::elf::*;
+
+    check_target(&header(ELFCLASS64, ELFDATA2LSB, EM_X86_64)).unwrap();
+    assert_eq!(
+        check_target(&header(ELFCLASS32, ELFDATA2LSB, EM_386))
+            .unwrap_err()
+            .to_string(),
+        "Incompatible input: file is i386 (ELF32, little-endian), \
+         but we're linking x86-64 (ELF64, little-endian)"
+    );
+    assert!(check_target(&header(ELFCLASS64, ELFDATA2LSB, EM_AARCH64))
+        .unwrap_err()
+        .to_string()
+        .contains("AArch64 (ELF64, little-endian)"));
+    assert!(check_target(&header(ELFCLASS64, ELFDATA2MSB, EM_PPC64))
+        .unwrap_err()
+        .to_string()
+        .contains("PowerPC64 (ELF64, big-endian)"));
+    assert!(check_target(&[0; 10]).is_err());
+}
@@ -0,0 +1,128 @@
+//! Merging of the `.note.gnu.property` sections of our input objects. Each object records the x86
+//! control-flow enforcement (CET) features that its code is compatible with in a
+//! GNU_PROPERTY_X86_FEATURE_1_AND property. The output is only compatible with a feature if every
+//! input is, so we AND the bits from all objects, treating an object without the note as
+//! supporting nothing. `-z ibt` and `-z shstk` set bits regardless of our inputs and `-z
+//! cet-report` lists the objects that are holding the output back.
+
+use crate::args::Args;
+use crate::args::CetReport;
+use crate::elf::File;
+use crate::error::Result;
+use crate::input_data::InputRef;
+use crate::warning;
+use anyhow::bail;
+use object::elf::GNU_PROPERTY_X86_FEATURE_1_AND;
+use object::elf::GNU_PROPERTY_X86_FEATURE_1_IBT;
+use object::elf::GNU_PROPERTY_X86_FEATURE_1_SHSTK;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+
+/// The size of the note that we write. The note header and name, "GNU\0", are followed by a single
+/// property whose 4 byte value is padded to 8 bytes.
+pub(crate) const NOTE_SIZE: usize = 32;
+
+/// Returns the GNU_PROPERTY_X86_FEATURE_1_AND bits that the output should have, given the objects
+/// that we're linking. Objects that lack IBT or SHSTK are reported as requested by `-z cet-report`.
+pub(crate) fn merge_x86_features<'a>(
+    objects: impl Iterator<Item = (&'a File<'a>, &'a InputRef<'a>)>,
+    args: &Args,
+) -> Result<u32> {
+    let mut merged = None;
+    let mut missing = Vec::new();
+    for (object, input) in objects {
+        let features = x86_features(object)?;
+        merged = Some(merged.unwrap_or(u32::MAX) & features);
+        if args.cet_report != CetReport::None {
+            let features = features | args.force_x86_features;
+            let names: Vec<&str> = [
+                (GNU_PROPERTY_X86_FEATURE_1_IBT, "IBT"),
+                (GNU_PROPERTY_X86_FEATURE_1_SHSTK, "SHSTK"),
+            ]
+            .into_iter()
+            .filter(|(bit, _)| features & bit == 0)
+            .map(|(_, name)| name)
+            .collect();
+            if !names.is_empty() {
+                missing.push(format!("{input}: missing {}", names.join(" and ")));
+            }
+        }
+    }
+    match args.cet_report {
+        CetReport::Error if !missing.is_empty() => {
+            bail!("Inputs lack CET support:\n{}", missing.join("\n"));
+        }
+        CetReport::Warning => {
+            for message in missing {
+                warning!(args.diagnostics, "{message}");
+            }
+        }
+        _ => {}
+    }
+    Ok(merged.unwrap_or(0) | args.force_x86_features)
+}
+
+/// Returns the GNU_PROPERTY_X86_FEATURE_1_AND bits from `object`'s `.note.gnu.property` section, or
+/// 0 if it doesn't have one.
+fn x86_features(object: &File) -> Result<u32> {
+    let e = LittleEndian;
+    let Some((_, section)) = object.section_by_name(".note.gnu.property") else {
+        return Ok(0);
+    };
+    let Some(mut notes) = section.notes(e, object.data)? else {
+        return Ok(0);
+    };
+    while let Some(note) = notes.next()? {
+        let Some(mut properties) = note.gnu_properties(e) else {
+            continue;
+        };
+        while let Some(property) = properties.next()? {
+            if property.pr_type() == GNU_PROPERTY_X86_FEATURE_1_AND {
+                return Ok(property.data_u32(e)?);
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Builds the contents of our `.note.gnu.property` section, which records `features`.
+pub(crate) fn note(features: u32) -> Vec<u8> {
+    let mut note = Vec::with_capacity(NOTE_SIZE);
+    note.extend_from_slice(&4_u32.to_le_bytes());
+    note.extend_from_slice(&16_u32.to_le_bytes());
+    note.extend_from_slice(&object::elf::NT_GNU_PROPERTY_TYPE_0.to_le_bytes());
+    note.extend_from_slice(b"GNU\0");
+    note.extend_from_slice(&GNU_PROPERTY_X86_FEATURE_1_AND.to_le_bytes());
+    note.extend_from_slice(&4_u32.to_le_bytes());
+    note.extend_from_slice(&features.to_le_bytes());
+    note.extend_from_slice(&0_u32.to_le_bytes());
+    note
+}
+
+#[test]
+fn test_note() {
+    let note = note(GNU_PROPERTY_X86_FEATURE_1_IBT | GNU_PROPERTY_X86_FEATURE_1_SHSTK);
+    assert_eq!(note.len(), NOTE_SIZE);
+    let section = object::elf::SectionHeader64::<LittleEndian> {
+        sh_name: Default::default(),
+        sh_type: object::U32::new(LittleEndian, object::elf::SHT_NOTE),
+        sh_flags: Default::default(),
+        sh_addr: Default::default(),
+        sh_offset: Default::default(),
+        sh_size: object::U64::new(LittleEndian, note.len() as u64),
+        sh_link: Default::default(),
+        sh_info: Default::default(),
+        sh_addralign: object::U64::new(LittleEndian, 8),
+        sh_entsize: Default::default(),
+    };
+    let mut notes = section
+        .notes(LittleEndian, note.as_slice())
+        .unwrap()
+        .unwrap();
+    let parsed = notes.next().unwrap().unwrap();
+    let mut properties = parsed.gnu_properties(LittleEndian).unwrap();
+    let property = properties.next().unwrap().unwrap();
+    assert_eq!(property.pr_type(), GNU_PROPERTY_X86_FEATURE_1_AND);
+    assert_eq!(property.data_u32(LittleEndian).unwrap(), 3);
+    assert!(properties.next().unwrap().is_none());
+}
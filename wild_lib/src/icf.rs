@@ -0,0 +1,559 @@
+//! Identical code folding (`--icf`). Once GC has decided which sections are live, we look for
+//! read-only sections that have the same contents and whose relocations refer to the same things.
+//! All but one section from each such set are then dropped, with references redirected to the one
+//! that remains.
+//!
+//! Whether two sections are equivalent can depend on whether the sections that they refer to are
+//! equivalent, e.g. two copies of a function that call two copies of another function. So we start
+//! by partitioning sections by their contents and the shape of their relocations, then repeatedly
+//! split classes whose members refer to sections in different classes until nothing changes.
+//!
+//! With `--icf=safe`, sections that define address-significant symbols are left alone. Compilers
+//! that support this (clang and rustc) list such symbols in an `.llvm_addrsig` section. All
+//! symbols referenced by objects without that section are treated as address-significant, as are
+//! symbols that might be referenced from outside the output.
+
+use crate::args::Args;
+use crate::args::Icf;
+use crate::args::OutputKind;
+use crate::elf::File;
+use crate::elf::Rela;
+use crate::elf::RelocationKind;
+use crate::elf::RelocationKindInfo;
+use crate::error::Result;
+use crate::input_data::FileId;
+use crate::part_id::PartId;
+use crate::symbol::SymbolName;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolId;
+use crate::symbol_db::SymbolIdRange;
+use crate::threading::prelude::*;
+use crate::warning;
+use fxhash::FxHashMap;
+use fxhash::FxHashSet;
+use linker_utils::elf::shf;
+use linker_utils::elf::sht;
+use linker_utils::elf::SectionFlags;
+use linker_utils::elf::SectionType;
+use object::read::elf::Rela as _;
+use object::read::elf::SectionHeader as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+/// Names of sections that must never be folded, since their contents are concatenated with those
+/// of other sections of the same name to form a single piece of code or table.
+const UNFOLDABLE_PREFIXES: &[&[u8]] =
+    &[b".init", b".fini", b".ctors", b".dtors", b".preinit_array"];
+
+/// An input object that has sections that we might fold.
+pub(crate) struct IcfFile<'data> {
+    pub(crate) file_id: FileId,
+    pub(crate) object: &'data File<'data>,
+    pub(crate) symbol_id_range: SymbolIdRange,
+
+    /// Live sections that are eligible for folding, together with the output section part that
+    /// each is in.
+    pub(crate) sections: Vec<(object::SectionIndex, PartId)>,
+}
+
+/// A section that should be replaced by an identical section.
+pub(crate) struct Fold {
+    pub(crate) file_id: FileId,
+    pub(crate) section_index: object::SectionIndex,
+    pub(crate) into_file: FileId,
+    pub(crate) into_section: object::SectionIndex,
+}
+
+struct Candidate<'data> {
+    file_id: FileId,
+    section_index: object::SectionIndex,
+    part_id: PartId,
+    alignment: u64,
+    data: &'data [u8],
+    relocations: &'data [Rela],
+
+    /// What each of `relocations` refers to.
+    targets: Vec<Target>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Target {
+    /// The relocation doesn't reference a symbol.
+    None,
+
+    /// An offset within another candidate for folding. Whether two such targets are the same
+    /// depends on whether the candidates end up in the same class.
+    Candidate { index: usize, offset: u64 },
+
+    /// A symbol that isn't defined in a candidate for folding.
+    Symbol(SymbolId),
+}
+
+/// Returns whether the section at `section_index` in `object` may be folded into an identical
+/// section.
+pub(crate) fn is_eligible(
+    object: &File,
+    section_index: object::SectionIndex,
+    args: &Args,
+) -> Result<bool> {
+    let section = object.section(section_index)?;
+    let flags = SectionFlags::from_header(section);
+    if !flags.contains(shf::ALLOC)
+        || flags.contains(shf::WRITE)
+        || flags.contains(shf::TLS)
+        || flags.contains(shf::LINK_ORDER)
+        || SectionType::from_header(section) != sht::PROGBITS
+        || object.section_size(section)? == 0
+    {
+        return Ok(false);
+    }
+    let name = object.section_name(section)?;
+    if UNFOLDABLE_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        return Ok(false);
+    }
+    // Sections with names that are C identifiers can be referenced via __start_ and __stop_
+    // symbols, which requires that each copy occupies its own space.
+    let is_c_identifier = name.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_');
+    if is_c_identifier {
+        return Ok(false);
+    }
+    // In position-independent output, absolute relocations need dynamic relocations. We've already
+    // allocated those by the time we fold, so we leave such sections alone.
+    if args.is_relocatable() {
+        let needs_dynamic_relocations = object.relocations(section_index)?.iter().any(|rel| {
+            RelocationKindInfo::from_raw(rel.r_type(LittleEndian, false))
+                .map_or(true, |info| info.kind == RelocationKind::Absolute)
+        });
+        if needs_dynamic_relocations {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Determines which sections in `files` can be folded into which other sections. `is_exported`
+/// reports whether a symbol is visible outside of the output file. Folds are sorted by the section
+/// that is kept, then by the section that is removed.
+#[tracing::instrument(skip_all, name = "Find identical sections")]
+pub(crate) fn find_folds(
+    files: &[IcfFile],
+    symbol_db: &SymbolDb,
+    is_exported: &(dyn Fn(SymbolId) -> bool + Sync),
+) -> Result<Vec<Fold>> {
+    let files_by_id: FxHashMap<FileId, &IcfFile> =
+        files.iter().map(|file| (file.file_id, file)).collect();
+    let mut keep_unique = if symbol_db.args.icf == Icf::Safe {
+        address_significant_sections(files, &files_by_id, symbol_db, is_exported)?
+    } else {
+        FxHashSet::default()
+    };
+    for name in &symbol_db.args.keep_unique {
+        let Some(&symbol_id) = symbol_db
+            .global_names
+            .get(&SymbolName::prehashed(name.as_bytes()))
+        else {
+            warning!(
+                symbol_db.args.diagnostics,
+                "Could not find symbol `{name}` to keep unique"
+            );
+            continue;
+        };
+        if let Some((location, _)) = symbol_location(symbol_id, &files_by_id, symbol_db)? {
+            keep_unique.insert(location);
+        }
+    }
+    let mut candidate_indexes = FxHashMap::default();
+    let mut candidates = Vec::new();
+    let mut candidate_files = Vec::new();
+    for file in files {
+        for &(section_index, part_id) in &file.sections {
+            if keep_unique.contains(&(file.file_id, section_index)) {
+                continue;
+            }
+            candidate_indexes.insert((file.file_id, section_index), candidates.len());
+            let section = file.object.section(section_index)?;
+            candidates.push(Candidate {
+                file_id: file.file_id,
+                section_index,
+                part_id,
+                alignment: section.sh_addralign(LittleEndian),
+                data: file.object.raw_section_data(section)?,
+                relocations: file.object.relocations(section_index)?,
+                targets: Vec::new(),
+            });
+            candidate_files.push(file);
+        }
+    }
+
+    candidates
+        .par_iter_mut()
+        .zip(candidate_files.par_iter())
+        .try_for_each(|(candidate, file)| -> Result {
+            candidate.targets = candidate
+                .relocations
+                .iter()
+                .map(|rel| {
+                    relocation_target(rel, file, &files_by_id, &candidate_indexes, symbol_db)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(())
+        })?;
+
+    let classes = compute_classes(&candidates);
+
+    let mut members_by_class: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+    for (index, class) in classes.iter().enumerate() {
+        members_by_class.entry(*class).or_default().push(index);
+    }
+    let mut folds = Vec::new();
+    for members in members_by_class.values() {
+        let Some((&leader, rest)) = members.split_first() else {
+            continue;
+        };
+        for &member in rest {
+            // Classes are formed from hashes, so we check that members really are identical before
+            // folding them.
+            if is_equivalent(&candidates[leader], &candidates[member], &classes) {
+                folds.push(Fold {
+                    file_id: candidates[member].file_id,
+                    section_index: candidates[member].section_index,
+                    into_file: candidates[leader].file_id,
+                    into_section: candidates[leader].section_index,
+                });
+            }
+        }
+    }
+    folds.sort_unstable_by_key(|fold| {
+        (
+            fold.into_file,
+            fold.into_section.0,
+            fold.file_id,
+            fold.section_index.0,
+        )
+    });
+    Ok(folds)
+}
+
+fn relocation_target(
+    rel: &Rela,
+    file: &IcfFile,
+    files_by_id: &FxHashMap<FileId, &IcfFile>,
+    candidate_indexes: &FxHashMap<(FileId, object::SectionIndex), usize>,
+    symbol_db: &SymbolDb,
+) -> Result<Target> {
+    let e = LittleEndian;
+    let Some(symbol_index) = rel.symbol(e, false) else {
+        return Ok(Target::None);
+    };
+    let symbol_id = symbol_db.definition(file.symbol_id_range.input_to_id(symbol_index));
+    if let Some((location, offset)) = symbol_location(symbol_id, files_by_id, symbol_db)? {
+        if let Some(&index) = candidate_indexes.get(&location) {
+            return Ok(Target::Candidate { index, offset });
+        }
+    }
+    Ok(Target::Symbol(symbol_id))
+}
+
+/// Returns the file and section that define `symbol_id` together with the symbol's offset within
+/// that section, if it's defined by a section of one of our files.
+fn symbol_location(
+    symbol_id: SymbolId,
+    files_by_id: &FxHashMap<FileId, &IcfFile>,
+    symbol_db: &SymbolDb,
+) -> Result<Option<((FileId, object::SectionIndex), u64)>> {
+    let Some(file) = files_by_id.get(&symbol_db.file_id_for_symbol(symbol_id)) else {
+        return Ok(None);
+    };
+    let symbol_index = file.symbol_id_range.id_to_input(symbol_id);
+    let symbol = file.object.symbol(symbol_index)?;
+    Ok(file
+        .object
+        .symbol_section(symbol, symbol_index)?
+        .map(|section_index| ((file.file_id, section_index), symbol.st_value(LittleEndian))))
+}
+
+/// Returns the sections that define symbols whose addresses might be compared, and which therefore
+/// must keep an address of their own.
+fn address_significant_sections(
+    files: &[IcfFile],
+    files_by_id: &FxHashMap<FileId, &IcfFile>,
+    symbol_db: &SymbolDb,
+    is_exported: &(dyn Fn(SymbolId) -> bool + Sync),
+) -> Result<FxHashSet<(FileId, object::SectionIndex)>> {
+    let is_shared_object = symbol_db.args.output_kind == OutputKind::SharedObject;
+    let per_file = files
+        .par_iter()
+        .map(|file| -> Result<Vec<(FileId, object::SectionIndex)>> {
+            let mut significant = Vec::new();
+            let mut add = |symbol_index| -> Result {
+                let symbol_id =
+                    symbol_db.definition(file.symbol_id_range.input_to_id(symbol_index));
+                if let Some((location, _)) = symbol_location(symbol_id, files_by_id, symbol_db)? {
+                    significant.push(location);
+                }
+                Ok(())
+            };
+            let table = address_significance_table(file.object)?;
+            for (symbol_index, symbol) in file.object.symbols.enumerate() {
+                let symbol_id = file.symbol_id_range.input_to_id(symbol_index);
+                if table.is_none()
+                    || is_exported(symbol_id)
+                    || (is_shared_object && crate::layout::can_export_symbol(symbol))
+                {
+                    add(symbol_index)?;
+                }
+            }
+            for symbol_index in table.into_iter().flatten() {
+                add(symbol_index)?;
+            }
+            Ok(significant)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(per_file.into_iter().flatten().collect())
+}
+
+/// Returns the symbols listed in the address-significance table of `object`, or None if it doesn't
+/// have a usable one. Tools that don't understand the table, e.g. `ld -r`, may keep it without
+/// updating it, but they clear its link to the symbol table, so we ignore it in that case.
+fn address_significance_table(object: &File) -> Result<Option<Vec<object::SymbolIndex>>> {
+    let e = LittleEndian;
+    let Some(section) = object
+        .sections
+        .iter()
+        .find(|section| SectionType::from_header(section) == sht::LLVM_ADDRSIG)
+    else {
+        return Ok(None);
+    };
+    if section.sh_link(e) as usize != object.symbols.section().0 {
+        return Ok(None);
+    }
+    let mut data = object::Bytes(object.raw_section_data(section)?);
+    let mut symbols = Vec::new();
+    while !data.is_empty() {
+        let index = data
+            .read_uleb128()
+            .map_err(|()| anyhow::anyhow!("Invalid .llvm_addrsig section"))?;
+        symbols.push(object::SymbolIndex(index as usize));
+    }
+    Ok(Some(symbols))
+}
+
+/// Returns a class for each candidate, such that candidates that can be folded together have the
+/// same class.
+fn compute_classes(candidates: &[Candidate]) -> Vec<u64> {
+    let mut classes: Vec<u64> = candidates.par_iter().map(initial_class).collect();
+    let mut num_classes = count_distinct(&classes);
+    loop {
+        let refined: Vec<u64> = candidates
+            .par_iter()
+            .zip(classes.par_iter())
+            .map(|(candidate, class)| {
+                let mut hasher = fxhash::FxHasher64::default();
+                class.hash(&mut hasher);
+                for target in &candidate.targets {
+                    if let Target::Candidate { index, .. } = target {
+                        classes[*index].hash(&mut hasher);
+                    }
+                }
+                hasher.finish()
+            })
+            .collect();
+        // Each refined class incorporates the previous class, so classes can only ever be split.
+        // Once the number of classes stops changing, we're done.
+        let num_refined = count_distinct(&refined);
+        classes = refined;
+        if num_refined == num_classes {
+            return classes;
+        }
+        num_classes = num_refined;
+    }
+}
+
+/// Hashes everything about `candidate` except for the classes of the candidates that it refers
+/// to.
+fn initial_class(candidate: &Candidate) -> u64 {
+    let e = LittleEndian;
+    let mut hasher = fxhash::FxHasher64::default();
+    candidate.part_id.hash(&mut hasher);
+    candidate.alignment.hash(&mut hasher);
+    candidate.data.hash(&mut hasher);
+    for (rel, target) in candidate.relocations.iter().zip(&candidate.targets) {
+        rel.r_offset.get(e).hash(&mut hasher);
+        rel.r_type(e, false).hash(&mut hasher);
+        rel.r_addend.get(e).hash(&mut hasher);
+        match target {
+            Target::None => {}
+            Target::Candidate { offset, .. } => offset.hash(&mut hasher),
+            Target::Symbol(symbol_id) => symbol_id.hash(&mut hasher),
+        }
+    }
+    hasher.finish()
+}
+
+fn count_distinct(classes: &[u64]) -> usize {
+    classes.iter().collect::<FxHashSet<_>>().len()
+}
+
+fn is_equivalent(a: &Candidate, b: &Candidate, classes: &[u64]) -> bool {
+    let e = LittleEndian;
+    a.part_id == b.part_id
+        && a.alignment == b.alignment
+        && a.data == b.data
+        && a.relocations.len() == b.relocations.len()
+        && a.relocations.iter().zip(b.relocations).all(|(ra, rb)| {
+            ra.r_offset.get(e) == rb.r_offset.get(e)
+                && ra.r_type(e, false) == rb.r_type(e, false)
+                && ra.r_addend.get(e) == rb.r_addend.get(e)
+        })
+        && a.targets.iter().zip(&b.targets).all(|pair| match pair {
+            (
+                Target::Candidate {
+                    index: ia,
+                    offset: oa,
+                },
+                Target::Candidate {
+                    index: ib,
+                    offset: ob,
+                },
+            ) => oa == ob && classes[*ia] == classes[*ib],
+            (ta, tb) => ta == tb,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment;
+    use crate::output_section_id;
+
+    const CALL: &[u8] = &[0xe8, 0, 0, 0, 0, 0xc3];
+
+    /// A relocation at offset 1, which for `CALL` is the operand of the call.
+    fn call_relocation() -> Rela {
+        let e = LittleEndian;
+        Rela {
+            r_offset: object::U64::new(e, 1),
+            r_info: object::U64::new(e, (1 << 32) | u64::from(object::elf::R_X86_64_PLT32)),
+            r_addend: object::I64::new(e, -4),
+        }
+    }
+
+    fn candidate<'data>(
+        index: u32,
+        data: &'data [u8],
+        relocations: &'data [Rela],
+        targets: Vec<Target>,
+    ) -> Candidate<'data> {
+        Candidate {
+            file_id: FileId::new(1, index),
+            section_index: object::SectionIndex(1),
+            part_id: output_section_id::TEXT.part_id_with_alignment(alignment::MIN),
+            alignment: 1,
+            data,
+            relocations,
+            targets,
+        }
+    }
+
+    fn calls(index: usize) -> Vec<Target> {
+        vec![Target::Candidate { index, offset: 0 }]
+    }
+
+    /// Returns pairs of candidates that `find_folds` would fold together.
+    fn equivalent_pairs(candidates: &[Candidate]) -> Vec<(usize, usize)> {
+        let classes = compute_classes(candidates);
+        let mut pairs = Vec::new();
+        for a in 0..candidates.len() {
+            for b in a + 1..candidates.len() {
+                if classes[a] == classes[b] {
+                    assert!(is_equivalent(&candidates[a], &candidates[b], &classes));
+                    pairs.push((a, b));
+                } else {
+                    assert!(!is_equivalent(&candidates[a], &candidates[b], &classes));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_identical_contents() {
+        let mut other_part = candidate(2, CALL, &[], vec![]);
+        other_part.part_id = output_section_id::RODATA.part_id_with_alignment(alignment::MIN);
+        // Sections whose alignment is small enough to share a part can still differ in alignment.
+        let mut other_alignment = candidate(4, CALL, &[], vec![]);
+        other_alignment.alignment = 2;
+        let candidates = [
+            candidate(0, CALL, &[], vec![]),
+            candidate(1, CALL, &[], vec![]),
+            other_part,
+            candidate(3, &[0xc3], &[], vec![]),
+            other_alignment,
+        ];
+        assert_eq!(equivalent_pairs(&candidates), [(0, 1)]);
+    }
+
+    #[test]
+    fn test_relocations_to_symbols() {
+        let relocations = [call_relocation()];
+        let mut other_addend = call_relocation();
+        other_addend.r_addend.set(LittleEndian, 0);
+        let other_addend = [other_addend];
+        let foo = Target::Symbol(SymbolId::from_usize(10));
+        let bar = Target::Symbol(SymbolId::from_usize(11));
+        let candidates = [
+            candidate(0, CALL, &relocations, vec![foo]),
+            candidate(1, CALL, &relocations, vec![foo]),
+            candidate(2, CALL, &relocations, vec![bar]),
+            candidate(3, CALL, &other_addend, vec![foo]),
+            candidate(4, CALL, &[], vec![]),
+        ];
+        assert_eq!(equivalent_pairs(&candidates), [(0, 1)]);
+    }
+
+    #[test]
+    fn test_relocations_to_candidates() {
+        let relocations = [call_relocation()];
+        let candidates = [
+            // 0 and 1 call identical leaf functions, 2 and 3.
+            candidate(0, CALL, &relocations, calls(2)),
+            candidate(1, CALL, &relocations, calls(3)),
+            candidate(2, &[0xc3], &[], vec![]),
+            candidate(3, &[0xc3], &[], vec![]),
+            // 4 calls a different leaf function, so it must be split from the class of 0 and 1,
+            // even though its own contents are the same.
+            candidate(4, CALL, &relocations, calls(5)),
+            candidate(5, &[0x90, 0xc3], &[], vec![]),
+        ];
+        assert_eq!(equivalent_pairs(&candidates), [(0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        let relocations = [call_relocation()];
+        let candidates = [
+            // 0 and 1 call each other, as do 2 and 3. All four are equivalent, which we can only
+            // see if we start by assuming that they are.
+            candidate(0, CALL, &relocations, calls(1)),
+            candidate(1, CALL, &relocations, calls(0)),
+            candidate(2, CALL, &relocations, calls(3)),
+            candidate(3, CALL, &relocations, calls(2)),
+            // 4 calls something that isn't a candidate.
+            candidate(
+                4,
+                CALL,
+                &relocations,
+                vec![Target::Symbol(SymbolId::from_usize(10))],
+            ),
+        ];
+        assert_eq!(
+            equivalent_pairs(&candidates),
+            [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]
+        );
+    }
+}
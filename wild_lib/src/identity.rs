@@ -1,5 +1,104 @@
+use std::path::Path;
+
 /// Returns a null-terminated string that identifies this linker. This is written into the .comment
 /// section which usually also contains the versions of compilers that were used.
 pub(crate) fn linker_identity() -> String {
     format!("Linker: Wild version {}\0", env!("CARGO_PKG_VERSION"))
 }
+
+/// The owner name of the note that records how an output was linked.
+const NOTE_NAME: &[u8] = b"wild\0";
+
+/// The note type of our command line note.
+const NT_WILD_COMMAND_LINE: u32 = 1;
+
+/// Builds the contents of `.note.wild.cmdline`: an ELF note owned by "wild" whose descriptor is
+/// our version followed by the command line, both null-terminated. This lets a binary be traced
+/// back to how it was linked.
+pub(crate) fn command_line_note(args: &[String]) -> Vec<u8> {
+    let cwd = std::env::current_dir().ok();
+    let command_line = args
+        .iter()
+        .map(|arg| sanitise_arg(arg, cwd.as_deref()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut desc = format!("Wild version {}\0", env!("CARGO_PKG_VERSION")).into_bytes();
+    desc.extend_from_slice(command_line.as_bytes());
+    desc.push(0);
+
+    let mut note = Vec::new();
+    note.extend_from_slice(&(NOTE_NAME.len() as u32).to_le_bytes());
+    note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    note.extend_from_slice(&NT_WILD_COMMAND_LINE.to_le_bytes());
+    note.extend_from_slice(NOTE_NAME);
+    pad_to_4(&mut note);
+    note.extend_from_slice(&desc);
+    pad_to_4(&mut note);
+    note
+}
+
+/// Makes an argument suitable for recording. Paths inside the directory we were run from are made
+/// relative, so that the note doesn't reveal or depend on where the build happened. Arguments that
+/// a shell would split or interpret are quoted so that the command line can be copied and rerun.
+fn sanitise_arg(arg: &str, cwd: Option<&Path>) -> String {
+    // Options can be given as `--option=value`, in which case it's the value that might be a path.
+    let (prefix, value) = match arg.split_once('=') {
+        Some((option, value)) if arg.starts_with('-') => (&arg[..=option.len()], value),
+        _ => ("", arg),
+    };
+    if let Some(relative) = cwd.and_then(|cwd| Path::new(value).strip_prefix(cwd).ok()) {
+        let relative = if relative.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            relative
+        };
+        return quote_for_shell(&format!("{prefix}{}", relative.display()));
+    }
+    quote_for_shell(arg)
+}
+
+fn quote_for_shell(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_=+,./:@%".contains(&b));
+    if is_plain {
+        return arg.to_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn pad_to_4(bytes: &mut Vec<u8>) {
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+}
+
+#[test]
+fn test_sanitise_arg() {
+    let cwd = Some(Path::new("/home/user/project"));
+    assert_eq!(sanitise_arg("-o", cwd), "-o");
+    assert_eq!(sanitise_arg("/home/user/project/a.o", cwd), "a.o");
+    assert_eq!(
+        sanitise_arg("--version-script=/home/user/project/x.ver", cwd),
+        "--version-script=x.ver"
+    );
+    assert_eq!(
+        sanitise_arg("/home/user/projects/a.o", cwd),
+        "/home/user/projects/a.o"
+    );
+    assert_eq!(sanitise_arg("-rpath=$ORIGIN", cwd), "'-rpath=$ORIGIN'");
+    assert_eq!(sanitise_arg("it's", None), "'it'\\''s'");
+    assert_eq!(sanitise_arg("", None), "''");
+}
+
+#[test]
+fn test_command_line_note() {
+    let note = command_line_note(&["-o".to_owned(), "out".to_owned()]);
+    assert_eq!(note.len() % 4, 0);
+    let u32_at = |offset: usize| u32::from_le_bytes(note[offset..offset + 4].try_into().unwrap());
+    assert_eq!(u32_at(0), NOTE_NAME.len() as u32);
+    assert_eq!(u32_at(8), NT_WILD_COMMAND_LINE);
+    assert_eq!(&note[12..17], NOTE_NAME);
+    let desc = &note[20..20 + u32_at(4) as usize];
+    assert!(desc.starts_with(b"Wild version "));
+    assert!(desc.ends_with(b"\0-o out\0"));
+}
@@ -5,15 +5,26 @@ use crate::args::Args;
 use crate::args::Input;
 use crate::args::InputSpec;
 use crate::args::Modifiers;
+use crate::diagnostics::should_warn;
+use crate::diagnostics::WarningCategory;
+use crate::elf::DynamicTagValues;
 use crate::error::Result;
 use crate::file_kind::FileKind;
+use crate::threading::prelude::*;
+use crate::warning;
 use anyhow::bail;
 use anyhow::Context;
 use memmap2::Mmap;
 use std::collections::HashSet;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
 
+/// How many entries of a `--files-from` list we read before opening them. The entries in a batch
+/// are opened in parallel.
+const FILE_LIST_BATCH_SIZE: usize = 1024;
+
 pub(crate) struct InputData<'config> {
     pub config: &'config Args,
     pub filenames: HashSet<PathBuf>,
@@ -26,7 +37,7 @@ pub(crate) struct VersionScriptData {
 }
 
 /// Identifies an input file. IDs start from 0 which is reserved for our prelude file.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct FileId(u32);
 
 pub(crate) const PRELUDE_FILE_ID: FileId = FileId::new(0, 0);
@@ -101,6 +112,7 @@ impl<'config> InputData<'config> {
         for input in &config.inputs {
             input_data.register_input(input)?;
         }
+        input_data.register_copied_dt_needed_entries()?;
 
         // Our last "file", similar to the prelude is responsible for internal stuff, but this time
         // at the end.
@@ -114,47 +126,203 @@ impl<'config> InputData<'config> {
         Ok(input_data)
     }
 
+    /// Returns every file that we read in order to determine our inputs, sorted and without
+    /// duplicates. This includes linker scripts, file lists and the version script.
+    pub(crate) fn dependency_paths(&self) -> Vec<&Path> {
+        let mut paths: Vec<&Path> = self.filenames.iter().map(|p| p.as_path()).collect();
+        paths.extend(self.config.version_script_path.as_deref());
+        paths.extend(
+            self.config
+                .inputs
+                .iter()
+                .filter_map(|input| match &input.spec {
+                    InputSpec::FileList(list) if list.as_ref() != Path::new("-") => {
+                        Some(list.as_ref())
+                    }
+                    _ => None,
+                }),
+        );
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Adds the dependencies of shared objects that were given while `--copy-dt-needed-entries` was
+    /// in effect. The dependencies are added as-needed, so they only end up in our DT_NEEDED if we
+    /// reference them. Since we keep going until we reach the end of our files, the dependencies of
+    /// those dependencies get added too.
+    fn register_copied_dt_needed_entries(&mut self) -> Result {
+        if !self.files.iter().any(|file| file.modifiers.copy_dt_needed) {
+            return Ok(());
+        }
+        let mut known_names = HashSet::new();
+        let mut index = 0;
+        while index < self.files.len() {
+            let file = &self.files[index];
+            index += 1;
+            if file.kind != FileKind::ElfDynamic {
+                continue;
+            }
+            let object = crate::elf::File::parse(file.data(), true)
+                .with_context(|| format!("Failed to parse `{}`", file.filename.display()))?;
+            let dt_info = DynamicTagValues::read(&object)?;
+            known_names.insert(
+                crate::needed_libs::name_for_needed(
+                    &dt_info,
+                    file.original_filename.as_os_str().as_encoded_bytes(),
+                )
+                .to_owned(),
+            );
+            if !file.modifiers.copy_dt_needed {
+                continue;
+            }
+            let needed_by = file.filename.clone();
+            let modifiers = Modifiers {
+                as_needed: true,
+                ..file.modifiers
+            };
+            let needed = dt_info
+                .needed
+                .iter()
+                .map(|name| name.to_vec())
+                .collect::<Vec<_>>();
+            for name in needed {
+                if known_names.contains(&name) {
+                    continue;
+                }
+                let Some(path) = crate::needed_libs::find_needed(self.config, &name, &needed_by)
+                else {
+                    if should_warn(self.config, WarningCategory::MissingDependency, &needed_by) {
+                        warning!(
+                            self.config.diagnostics,
+                            "{}, needed by {}, not found (try using -rpath-link)",
+                            String::from_utf8_lossy(&name),
+                            needed_by.display()
+                        );
+                    }
+                    continue;
+                };
+                let paths = InputPath {
+                    absolute: path,
+                    original: PathBuf::from(String::from_utf8_lossy(&name).as_ref()),
+                };
+                if self.filenames.contains(&paths.absolute) {
+                    continue;
+                }
+                let opened = open_input(self.config, modifiers, paths)?;
+                self.add_opened_input(opened)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds inputs that a linker plugin asked for, generally the objects that it produced. They go
+    /// before our epilogue.
+    pub(crate) fn add_plugin_inputs(&mut self, inputs: &[Input]) -> Result {
+        let epilogue = self.files.pop();
+        for input in inputs {
+            self.register_input(input)?;
+        }
+        self.files.extend(epilogue);
+        Ok(())
+    }
+
+    /// Adds an object that we produced in memory, such as the output of LTO codegen. `name` is used
+    /// only in diagnostics. Like plugin inputs, it goes before our epilogue. It isn't added to
+    /// `filenames`, since there's no file for things like dependency files to refer to.
+    pub(crate) fn add_in_memory_object(&mut self, name: &Path, bytes: &[u8]) -> Result {
+        let mut mmap = memmap2::MmapMut::map_anon(bytes.len())?;
+        mmap.copy_from_slice(bytes);
+        let epilogue = self.files.pop();
+        self.files.push(InputFile {
+            filename: name.to_owned(),
+            original_filename: name.to_owned(),
+            kind: FileKind::identify_bytes(bytes)?,
+            modifiers: Default::default(),
+            bytes: Some(mmap.make_read_only()?),
+        });
+        self.files.extend(epilogue);
+        Ok(())
+    }
+
     fn register_input(&mut self, input: &Input) -> Result {
+        if let InputSpec::FileList(list_path) = &input.spec {
+            return self.register_file_list(list_path, input.modifiers);
+        }
         let paths = input.path(self.config)?;
-        let absolute_path = &paths.absolute;
-        if !self.filenames.insert(absolute_path.clone()) {
+        if self.filenames.contains(&paths.absolute) {
             // File has already been added.
             return Ok(());
         }
-        let file = std::fs::File::open(absolute_path)
-            .with_context(|| format!("Failed to open input file `{}`", absolute_path.display()))?;
-
-        // Safety: Unfortunately, this is a bit of a compromise. Basically this is only safe if our
-        // users manage to avoid editing the input files while we've got them mapped. It'd be great
-        // if there were a way to protect against unsoundness when the input files were modified
-        // externally, but there isn't - at least on Linux. Not only could the bytes change without
-        // notice, but the mapped file could be truncated causing any access to result in a SIGBUS.
-        //
-        // For our use case, mmap just has too many advantages. There are likely large parts of our
-        // input files that we don't need to read, so reading all our input files up front isn't
-        // really an option. Reading just the parts we need might be an option, but would add
-        // substantial complexity. Also, using mmap means that if the system needs to reclaim
-        // memory, it can just release some of our pages.
-
-        let mut mmap_options = memmap2::MmapOptions::new();
-
-        // Prepopulating maps generally slows things down, so is off by default, however it's useful
-        // when profiling, since it means that you don't see false positive slowness in the parts of
-        // the code that first read a bit of memory.
-        if self.config.prepopulate_maps {
-            mmap_options.populate();
+        let opened = open_input(self.config, input.modifiers, paths)?;
+        self.add_opened_input(opened)
+    }
+
+    /// Registers the files listed in `list_path`, or stdin if it's `-`. The list is read a batch at
+    /// a time and the files in each batch are opened in parallel, so that we don't need to hold the
+    /// whole list in memory and don't pay for tens of thousands of sequential opens.
+    fn register_file_list(&mut self, list_path: &Path, modifiers: Modifiers) -> Result {
+        let reader: Box<dyn BufRead> = if list_path == Path::new("-") {
+            Box::new(std::io::stdin().lock())
+        } else {
+            let file = std::fs::File::open(list_path)
+                .with_context(|| format!("Failed to open file list `{}`", list_path.display()))?;
+            Box::new(BufReader::new(file))
+        };
+        let mut batch = Vec::with_capacity(FILE_LIST_BATCH_SIZE);
+        for line in reader.lines() {
+            let line = line
+                .with_context(|| format!("Failed to read file list `{}`", list_path.display()))?;
+            let entry = line.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            batch.push(Input {
+                spec: InputSpec::File(Box::from(Path::new(entry))),
+                search_first: None,
+                modifiers,
+            });
+            if batch.len() == FILE_LIST_BATCH_SIZE {
+                self.register_batch(&batch)?;
+                batch.clear();
+            }
         }
+        self.register_batch(&batch)
+    }
+
+    fn register_batch(&mut self, inputs: &[Input]) -> Result {
+        let config = self.config;
+        let opened: Vec<Result<OpenedInput>> = inputs
+            .par_iter()
+            .map(|input| open_input(config, input.modifiers, input.path(config)?))
+            .collect();
+        // Entries are added in list order, regardless of the order in which they were opened.
+        for opened in opened {
+            self.add_opened_input(opened?)?;
+        }
+        Ok(())
+    }
 
-        let bytes = unsafe { mmap_options.map(&file) }
-            .with_context(|| format!("Failed to mmap input file `{}`", absolute_path.display()))?;
+    fn add_opened_input(&mut self, opened: OpenedInput) -> Result {
+        let OpenedInput {
+            paths,
+            modifiers,
+            kind,
+            bytes,
+        } = opened;
+        let absolute_path = &paths.absolute;
+        if !self.filenames.insert(absolute_path.clone()) {
+            // File has already been added.
+            return Ok(());
+        }
 
-        let kind = FileKind::identify_bytes(&bytes)?;
         if matches!(kind, FileKind::Text) {
-            for input in crate::linker_script::linker_script_to_inputs(
-                &bytes,
-                absolute_path,
-                input.modifiers,
-            )? {
+            if self.config.trace {
+                println!("{}", absolute_path.display());
+            }
+            for input in
+                crate::linker_script::linker_script_to_inputs(&bytes, absolute_path, modifiers)?
+            {
                 self.register_input(&input)?;
             }
             return Ok(());
@@ -164,7 +332,7 @@ impl<'config> InputData<'config> {
             filename: absolute_path.to_owned(),
             original_filename: paths.original,
             kind,
-            modifiers: input.modifiers,
+            modifiers,
             bytes: Some(bytes),
         };
         self.files.push(file_info);
@@ -172,6 +340,53 @@ impl<'config> InputData<'config> {
     }
 }
 
+/// An input file that has been mapped into memory, but not yet added to our list of inputs.
+struct OpenedInput {
+    paths: InputPath,
+    modifiers: Modifiers,
+    kind: FileKind,
+    bytes: Mmap,
+}
+
+fn open_input(config: &Args, modifiers: Modifiers, paths: InputPath) -> Result<OpenedInput> {
+    let absolute_path = &paths.absolute;
+    let file = std::fs::File::open(absolute_path)
+        .with_context(|| format!("Failed to open input file `{}`", absolute_path.display()))?;
+
+    // Safety: Unfortunately, this is a bit of a compromise. Basically this is only safe if our
+    // users manage to avoid editing the input files while we've got them mapped. It'd be great if
+    // there were a way to protect against unsoundness when the input files were modified
+    // externally, but there isn't - at least on Linux. Not only could the bytes change without
+    // notice, but the mapped file could be truncated causing any access to result in a SIGBUS.
+    //
+    // For our use case, mmap just has too many advantages. There are likely large parts of our
+    // input files that we don't need to read, so reading all our input files up front isn't really
+    // an option. Reading just the parts we need might be an option, but would add substantial
+    // complexity. Also, using mmap means that if the system needs to reclaim memory, it can just
+    // release some of our pages.
+
+    let mut mmap_options = memmap2::MmapOptions::new();
+
+    // Prepopulating maps generally slows things down, so is off by default, however it's useful
+    // when profiling, since it means that you don't see false positive slowness in the parts of the
+    // code that first read a bit of memory.
+    if config.prepopulate_maps {
+        mmap_options.populate();
+    }
+
+    let bytes = unsafe { mmap_options.map(&file) }
+        .with_context(|| format!("Failed to mmap input file `{}`", absolute_path.display()))?;
+
+    let kind = FileKind::identify_bytes(&bytes)
+        .with_context(|| format!("Failed to read input file `{}`", absolute_path.display()))?;
+    Ok(OpenedInput {
+        paths,
+        modifiers,
+        kind,
+        bytes,
+    })
+}
+
 fn read_version_script(path: &Path) -> Result<VersionScriptData> {
     let data = std::fs::read_to_string(path)
         .with_context(|| format!("Failed to read version script `{}`", path.display()))?;
@@ -182,40 +397,30 @@ impl Input {
     fn path(&self, args: &Args) -> Result<InputPath> {
         match &self.spec {
             InputSpec::File(p) => {
-                if p.components().count() == 1 {
-                    if let Some(absolute) = search_for_file(
-                        &args.lib_search_path,
-                        self.search_first.as_ref(),
-                        p.as_ref(),
-                    ) {
+                let in_sysroot = args.path_in_sysroot(p, self.search_first.as_deref());
+                let p: &Path = in_sysroot.as_deref().unwrap_or(p);
+                // Files named on the command line are opened directly if they exist. Only if they
+                // don't, or they came from a linker script, do we search for them.
+                let direct = self.search_first.is_none() && p.exists();
+                if !direct && p.components().count() == 1 {
+                    if let Some((absolute, _)) =
+                        search_for_file(args, self.search_first.as_ref(), &[p])
+                    {
                         return Ok(InputPath {
                             absolute,
-                            original: p.as_ref().to_owned(),
+                            original: p.to_owned(),
                         });
                     }
                 }
                 Ok(InputPath {
-                    absolute: p.as_ref().to_owned(),
-                    original: p.as_ref().to_owned(),
+                    absolute: p.to_owned(),
+                    original: p.to_owned(),
                 })
             }
             InputSpec::Lib(lib_name) => {
-                if self.modifiers.allow_shared {
-                    let filename = format!("lib{lib_name}.so");
-                    if let Some(absolute) = search_for_file(
-                        &args.lib_search_path,
-                        self.search_first.as_ref(),
-                        &filename,
-                    ) {
-                        return Ok(InputPath {
-                            absolute,
-                            original: PathBuf::from(filename),
-                        });
-                    }
-                }
-                let filename = format!("lib{lib_name}.a");
-                if let Some(absolute) =
-                    search_for_file(&args.lib_search_path, self.search_first.as_ref(), &filename)
+                let filenames = self.lib_filenames(lib_name);
+                if let Some((absolute, filename)) =
+                    search_for_file(args, self.search_first.as_ref(), &filenames)
                 {
                     return Ok(InputPath {
                         absolute,
@@ -224,26 +429,56 @@ impl Input {
                 }
                 bail!("Couldn't find library `{lib_name}` on library search path");
             }
+            InputSpec::FileList(list_path) => {
+                bail!(
+                    "Internal error: File list `{}` should have been expanded",
+                    list_path.display()
+                );
+            }
         }
     }
-}
 
-fn search_for_file(
-    lib_search_path: &[Box<Path>],
-    search_first: Option<&PathBuf>,
-    filename: impl AsRef<Path>,
-) -> Option<PathBuf> {
-    let filename = filename.as_ref();
-    if let Some(search_first) = search_first {
-        let path = search_first.join(filename);
-        if path.exists() {
-            return Some(path);
+    /// Returns the filenames that we should look for in each directory for `-l{lib_name}`, in order
+    /// of preference. `-l:name` looks for exactly `name`. Otherwise, we prefer a shared object over
+    /// an archive if we're allowed to link against shared objects.
+    fn lib_filenames(&self, lib_name: &str) -> Vec<PathBuf> {
+        if let Some(exact) = lib_name.strip_prefix(':') {
+            return vec![PathBuf::from(exact)];
         }
+        let mut filenames = Vec::with_capacity(2);
+        if self.modifiers.allow_shared {
+            filenames.push(PathBuf::from(format!("lib{lib_name}.so")));
+        }
+        filenames.push(PathBuf::from(format!("lib{lib_name}.a")));
+        filenames
     }
-    for dir in lib_search_path {
-        let path = dir.join(filename);
-        if path.exists() {
-            return Some(path);
+}
+
+/// Searches for the first of `filenames` that exists, returning its path and which filename
+/// matched. Like GNU ld, the directory is the outer loop, so an archive in an earlier directory is
+/// preferred over a shared object in a later directory. If `--trace` was passed, then each
+/// candidate path that we probe is printed.
+fn search_for_file<'a, P: AsRef<Path>>(
+    args: &Args,
+    search_first: Option<&PathBuf>,
+    filenames: &'a [P],
+) -> Option<(PathBuf, &'a Path)> {
+    let dirs = search_first
+        .map(|dir| dir.as_path())
+        .into_iter()
+        .chain(args.lib_search_path.iter().map(|dir| dir.as_ref()));
+    for dir in dirs {
+        for filename in filenames {
+            let filename = filename.as_ref();
+            let path = dir.join(filename);
+            let exists = path.exists();
+            if args.trace {
+                let outcome = if exists { "succeeded" } else { "failed" };
+                println!("attempt to open {} {outcome}", path.display());
+            }
+            if exists {
+                return Some((path, filename));
+            }
         }
     }
     None
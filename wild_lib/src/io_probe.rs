@@ -0,0 +1,259 @@
+//! Runtime probing of optional I/O facilities. Containers and sandboxes frequently block syscalls
+//! that are normally present (e.g. via seccomp), or run on filesystems that don't support them.
+//! Rather than assuming that something is available and failing hard when it isn't, we try the
+//! optional thing first and fall back to something more basic. When `--explain-io` is passed, we
+//! record what was found and which paths were chosen, then print it once the output is written.
+
+use crate::error::Result;
+use std::fs::File;
+use std::os::fd::AsRawFd as _;
+use std::path::Path;
+
+const THP_ENABLED_PATH: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// Records the I/O paths that were chosen while writing the output file.
+#[derive(Default)]
+pub(crate) struct IoReport {
+    entries: Vec<(&'static str, String)>,
+}
+
+/// What we found out about an optional facility.
+pub(crate) enum Capability {
+    Available,
+    Unavailable(std::io::Error),
+}
+
+/// The modes that the kernel reports for transparent hugepages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HugePageMode {
+    Always,
+    Madvise,
+    Never,
+}
+
+impl IoReport {
+    pub(crate) fn record(&mut self, facility: &'static str, outcome: impl Into<String>) {
+        self.entries.push((facility, outcome.into()));
+    }
+
+    /// Probes facilities that we don't currently use, but which are useful to know about when
+    /// diagnosing I/O problems, then prints everything that we've recorded.
+    pub(crate) fn explain(mut self, output_path: &Path) {
+        let dir = output_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        self.record(
+            "copy_file_range",
+            format!(
+                "{} (only used indirectly when copying inputs to WILD_SAVE_DIR)",
+                probe_copy_file_range()
+            ),
+        );
+        self.record(
+            "O_TMPFILE",
+            format!(
+                "{} in `{}` (not used, output is created in place)",
+                probe_o_tmpfile(dir),
+                dir.display()
+            ),
+        );
+        self.record(
+            "io_uring",
+            format!(
+                "{} (not used, writes go via mmap or write)",
+                probe_io_uring()
+            ),
+        );
+
+        println!("I/O paths:");
+        let width = self.entries.iter().map(|(f, _)| f.len()).max().unwrap_or(0);
+        for (facility, outcome) in &self.entries {
+            println!("  {facility:width$}  {outcome}");
+        }
+    }
+}
+
+/// Sets the length of `file` to `size`. We try to reserve the space with `fallocate` first, so
+/// that running out of disk space is reported as an error now rather than as a SIGBUS when we later
+/// write via a memory map. If `fallocate` isn't supported, we fall back to `set_len`, which creates
+/// a sparse file.
+pub(crate) fn reserve_space(file: &File, size: u64, report: &mut IoReport) -> Result {
+    if size > 0 {
+        match fallocate(file, size) {
+            Ok(()) => {
+                report.record("fallocate", "used to reserve output file space");
+                return Ok(());
+            }
+            Err(error) if error.raw_os_error() == Some(libc::ENOSPC) => {
+                return Err(
+                    anyhow::Error::new(error).context("Failed to reserve output file space")
+                );
+            }
+            Err(error) => {
+                report.record(
+                    "fallocate",
+                    format!("unavailable ({error}), falling back to set_len"),
+                );
+            }
+        }
+    }
+    file.set_len(size)?;
+    Ok(())
+}
+
+/// Returns whether `error`, from sizing and mapping the output file, means that the file can't be
+/// written via a memory map, e.g. because it's a pipe or on a filesystem that doesn't support
+/// mmap. In that case, we can still write the output from memory. Other errors, in particular
+/// running out of disk space, need to be reported.
+pub(crate) fn is_mmap_unsupported(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| {
+            matches!(
+                e.raw_os_error(),
+                Some(
+                    libc::ENODEV
+                        | libc::EINVAL
+                        | libc::ESPIPE
+                        | libc::EACCES
+                        | libc::EPERM
+                        | libc::ENOSYS
+                        | libc::EOPNOTSUPP
+                )
+            )
+        })
+}
+
+/// Asks for transparent hugepages to back `mmap` if the kernel is configured to only use them when
+/// requested. This is best-effort only.
+pub(crate) fn advise_huge_pages(mmap: &memmap2::MmapMut, report: &mut IoReport) {
+    let outcome = match huge_page_mode() {
+        Some(HugePageMode::Madvise) => match mmap.advise(memmap2::Advice::HugePage) {
+            Ok(()) => "requested via madvise".to_owned(),
+            Err(error) => format!("madvise failed ({error}), using regular pages"),
+        },
+        Some(HugePageMode::Always) => "enabled system-wide, nothing to request".to_owned(),
+        Some(HugePageMode::Never) => "disabled by the system, using regular pages".to_owned(),
+        None => format!("`{THP_ENABLED_PATH}` not readable, using regular pages"),
+    };
+    report.record("transparent hugepages", outcome);
+}
+
+fn huge_page_mode() -> Option<HugePageMode> {
+    parse_huge_page_mode(&std::fs::read_to_string(THP_ENABLED_PATH).ok()?)
+}
+
+/// Parses the contents of the transparent hugepage `enabled` file. The selected mode is the one in
+/// square brackets, e.g. "always [madvise] never".
+fn parse_huge_page_mode(contents: &str) -> Option<HugePageMode> {
+    let selected = contents
+        .split_whitespace()
+        .find_map(|word| word.strip_prefix('[')?.strip_suffix(']'))?;
+    match selected {
+        "always" => Some(HugePageMode::Always),
+        "madvise" => Some(HugePageMode::Madvise),
+        "never" => Some(HugePageMode::Never),
+        _ => None,
+    }
+}
+
+fn fallocate(file: &File, size: u64) -> std::io::Result<()> {
+    let len = libc::off_t::try_from(size).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+    let result = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Returns whether a syscall failure means that the syscall isn't available to us, as opposed to
+/// it having rejected the (deliberately invalid) arguments that we passed.
+fn is_unavailable(error: &std::io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ENOSYS | libc::EPERM | libc::EOPNOTSUPP)
+    )
+}
+
+fn capability_from_result(result: libc::c_long) -> Capability {
+    if result >= 0 {
+        return Capability::Available;
+    }
+    let error = std::io::Error::last_os_error();
+    if is_unavailable(&error) {
+        Capability::Unavailable(error)
+    } else {
+        Capability::Available
+    }
+}
+
+fn probe_copy_file_range() -> Capability {
+    // Invalid file descriptors give EBADF if the syscall exists.
+    let result =
+        unsafe { libc::copy_file_range(-1, std::ptr::null_mut(), -1, std::ptr::null_mut(), 0, 0) };
+    capability_from_result(result as libc::c_long)
+}
+
+fn probe_io_uring() -> Capability {
+    // Zero entries gives EINVAL if the syscall exists and is permitted.
+    let result = unsafe { libc::syscall(libc::SYS_io_uring_setup, 0, std::ptr::null_mut::<u8>()) };
+    capability_from_result(result)
+}
+
+fn probe_o_tmpfile(dir: &Path) -> Capability {
+    use std::os::unix::fs::OpenOptionsExt as _;
+
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .mode(0o600)
+        .custom_flags(libc::O_TMPFILE)
+        .open(dir)
+    {
+        // The file is anonymous, so dropping it is all that's needed to clean up.
+        Ok(_) => Capability::Available,
+        Err(error) => Capability::Unavailable(error),
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Available => write!(f, "available"),
+            Capability::Unavailable(error) => write!(f, "unavailable ({error})"),
+        }
+    }
+}
+
+#[test]
+fn test_parse_huge_page_mode() {
+    assert_eq!(
+        parse_huge_page_mode("always [madvise] never\n"),
+        Some(HugePageMode::Madvise)
+    );
+    assert_eq!(
+        parse_huge_page_mode("[always] madvise never"),
+        Some(HugePageMode::Always)
+    );
+    assert_eq!(
+        parse_huge_page_mode("always madvise [never]"),
+        Some(HugePageMode::Never)
+    );
+    assert_eq!(parse_huge_page_mode("always madvise never"), None);
+}
+
+#[test]
+fn test_is_mmap_unsupported() {
+    let io_error = |code| anyhow::Error::new(std::io::Error::from_raw_os_error(code));
+    assert!(is_mmap_unsupported(
+        &io_error(libc::ENODEV).context("mmap failed")
+    ));
+    assert!(is_mmap_unsupported(&io_error(libc::EINVAL)));
+    assert!(!is_mmap_unsupported(
+        &io_error(libc::ENOSPC).context("Failed to reserve output file space")
+    ));
+    assert!(!is_mmap_unsupported(&io_error(libc::EDQUOT)));
+    assert!(!is_mmap_unsupported(&anyhow::anyhow!("something else")));
+}
@@ -0,0 +1,118 @@
+//! Just enough JSON to write machine-readable reports, such as `--map-format=json` and
+//! `--diagnostics-format=json`, without depending on a serialisation library. Objects keep their keys
+//! in the order given.
+
+use crate::error::Result;
+
+pub(crate) enum Json {
+    Object(Vec<(&'static str, Json)>),
+    Array(Vec<Json>),
+    String(String),
+    Number(u64),
+}
+
+impl Json {
+    /// Writes the value, indented for reading by people. `indent` is the current indentation.
+    pub(crate) fn write(&self, out: &mut impl std::io::Write, indent: usize) -> Result {
+        let inner = indent + 2;
+        match self {
+            Json::Object(entries) if entries.is_empty() => write!(out, "{{}}")?,
+            Json::Object(entries) => {
+                writeln!(out, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    write!(out, "{:inner$}", "")?;
+                    write_json_string(out, key)?;
+                    write!(out, ": ")?;
+                    value.write(out, inner)?;
+                    writeln!(out, "{}", if i + 1 < entries.len() { "," } else { "" })?;
+                }
+                write!(out, "{:indent$}}}", "")?;
+            }
+            Json::Array(values) if values.is_empty() => write!(out, "[]")?,
+            Json::Array(values) => {
+                writeln!(out, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(out, "{:inner$}", "")?;
+                    value.write(out, inner)?;
+                    writeln!(out, "{}", if i + 1 < values.len() { "," } else { "" })?;
+                }
+                write!(out, "{:indent$}]", "")?;
+            }
+            Json::String(s) => write_json_string(out, s)?,
+            Json::Number(n) => write!(out, "{n}")?,
+        }
+        Ok(())
+    }
+
+    /// Writes the value on a single line.
+    pub(crate) fn write_compact(&self, out: &mut impl std::io::Write) -> Result {
+        match self {
+            Json::Object(entries) => {
+                write!(out, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, ",")?;
+                    }
+                    write_json_string(out, key)?;
+                    write!(out, ":")?;
+                    value.write_compact(out)?;
+                }
+                write!(out, "}}")?;
+            }
+            Json::Array(values) => {
+                write!(out, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, ",")?;
+                    }
+                    value.write_compact(out)?;
+                }
+                write!(out, "]")?;
+            }
+            Json::String(s) => write_json_string(out, s)?,
+            Json::Number(n) => write!(out, "{n}")?,
+        }
+        Ok(())
+    }
+}
+
+fn write_json_string(out: &mut impl std::io::Write, s: &str) -> Result {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")?;
+    Ok(())
+}
+
+#[test]
+fn test_json() {
+    let value = Json::Object(vec![
+        ("name", Json::String("a\"b\\c\n\u{1}".to_owned())),
+        (
+            "values",
+            Json::Array(vec![Json::Number(1), Json::Object(Vec::new())]),
+        ),
+        ("empty", Json::Array(Vec::new())),
+    ]);
+    let mut out = Vec::new();
+    value.write(&mut out, 0).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\n  \"name\": \"a\\\"b\\\\c\\n\\u0001\",\n  \"values\": [\n    1,\n    {}\n  ],\n  \"empty\": []\n}"
+    );
+    let mut out = Vec::new();
+    value.write_compact(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"name\":\"a\\\"b\\\\c\\n\\u0001\",\"values\":[1,{}],\"empty\":[]}"
+    );
+}
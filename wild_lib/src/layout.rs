@@ -5,7 +5,11 @@
 use crate::alignment;
 use crate::alignment::Alignment;
 use crate::args::Args;
+use crate::args::DefsymValue;
+use crate::args::Icf;
 use crate::args::OutputKind;
+use crate::args::SectionOrder;
+use crate::args::Symbolic;
 use crate::debug_assert_bail;
 use crate::elf;
 use crate::elf::EhFrameHdrEntry;
@@ -17,6 +21,7 @@ use crate::elf::Versym;
 use crate::elf_writer;
 use crate::error::Error;
 use crate::error::Result;
+use crate::icf::Fold;
 use crate::input_data::FileId;
 use crate::input_data::InputRef;
 use crate::input_data::PRELUDE_FILE_ID;
@@ -27,6 +32,7 @@ use crate::output_section_id::FILE_HEADER;
 use crate::output_section_map::OutputSectionMap;
 use crate::output_section_part_map::OutputSectionPartMap;
 use crate::parsing::InternalSymDefInfo;
+use crate::parsing::ParsedInput;
 use crate::part_id;
 use crate::part_id::PartId;
 use crate::part_id::NUM_GENERATED_PARTS;
@@ -35,8 +41,12 @@ use crate::program_segments::ProgramSegmentId;
 use crate::program_segments::MAX_SEGMENTS;
 use crate::program_segments::STACK;
 use crate::relaxation::Relaxation;
+use crate::relaxation::RelocationModifier;
 use crate::resolution;
+use crate::resolution::FoldedSection;
 use crate::resolution::FrameIndex;
+use crate::resolution::LinkOrder;
+use crate::resolution::LoadReasons;
 use crate::resolution::MergeStringsSection;
 use crate::resolution::NotLoaded;
 use crate::resolution::ResolutionOutputs;
@@ -45,6 +55,7 @@ use crate::resolution::SectionSlot;
 use crate::resolution::StringToMerge;
 use crate::resolution::UnloadedSection;
 use crate::resolution::ValueFlags;
+use crate::sframe::MergedSFrame;
 use crate::sharding::ShardKey;
 use crate::symbol::SymbolName;
 use crate::symbol_db::SymbolDb;
@@ -52,6 +63,7 @@ use crate::symbol_db::SymbolDebug;
 use crate::symbol_db::SymbolId;
 use crate::symbol_db::SymbolIdRange;
 use crate::threading::prelude::*;
+use crate::warning;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::ensure;
@@ -60,11 +72,11 @@ use bitflags::bitflags;
 use crossbeam_queue::ArrayQueue;
 use fxhash::FxHashMap;
 use itertools::Itertools;
+use linker_utils::elf::rel_type_to_string;
 use linker_utils::elf::shf;
 use linker_utils::elf::SectionFlags;
 use object::elf::gnu_hash;
 use object::elf::Rela64;
-use object::read::elf::Dyn as _;
 use object::read::elf::Rela as _;
 use object::read::elf::Sym as _;
 use object::read::elf::VerdefIterator;
@@ -78,8 +90,8 @@ use std::num::NonZeroU32;
 use std::num::NonZeroU64;
 use std::sync::atomic;
 use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU16;
 use std::sync::atomic::AtomicU64;
-use std::sync::atomic::AtomicU8;
 use std::sync::Mutex;
 
 #[tracing::instrument(skip_all, name = "Layout")]
@@ -93,12 +105,22 @@ pub fn compute<'data>(
         mut output_sections,
         merged_strings,
         custom_start_stop_defs,
+        load_reasons,
+        undefined_symbols,
+        mut errors,
     } = resolved;
 
     if let Some(sym_info) = symbol_db.args.sym_info.as_deref() {
         print_symbol_info(symbol_db, sym_info);
     }
+    if symbol_db.args.trace {
+        print_loaded_files(&groups);
+    }
+    if !symbol_db.args.trace_symbols.is_empty() {
+        print_traced_symbols(&groups, &symbol_db.args.trace_symbols)?;
+    }
     let symbol_resolution_flags = vec![AtomicResolutionFlags::empty(); symbol_db.num_symbols()];
+    let pack_relative_relocs = should_pack_relative_relocs(&groups, symbol_db.args);
     let gc_outputs = find_required_sections(
         groups,
         symbol_db,
@@ -106,28 +128,49 @@ pub fn compute<'data>(
         &symbol_resolution_flags,
         &merged_strings,
         custom_start_stop_defs,
+        pack_relative_relocs,
     )?;
     let mut group_states = gc_outputs.group_states;
 
+    if symbol_db.args.icf != Icf::None {
+        fold_identical_sections(&mut group_states, symbol_db, &symbol_resolution_flags)?;
+    }
+    set_link_order_links(&group_states, &mut output_sections);
+    if symbol_db.args.output_kind == OutputKind::SharedObject && !symbol_db.args.gc_keep_exported {
+        export_live_symbols(&mut group_states, symbol_db, &symbol_resolution_flags)?;
+    }
+    deduplicate_cies(&mut group_states);
     merge_dynamic_symbol_definitions(&mut group_states)?;
+    merge_gnu_properties(&mut group_states, symbol_db.args)?;
+    merge_sframe_sections(&mut group_states, symbol_db.args)?;
+    merge_ctf(&mut group_states, symbol_db.args)?;
     finalise_all_sizes(
         symbol_db,
         &output_sections,
         &mut group_states,
         &symbol_resolution_flags,
+        pack_relative_relocs,
     )?;
+    let mut hot_sections = order_hot_sections(&mut group_states, symbol_db, &output_sections)?;
+    order_init_priority_sections(&mut group_states, &mut hot_sections)?;
+    take_link_order_sections(&mut group_states, &mut hot_sections);
     let symbol_resolution_flags: Vec<ResolutionFlags> = symbol_resolution_flags
         .into_iter()
         .map(|f| f.into_non_atomic())
         .collect();
+    if symbol_db.args.print_preemptibility {
+        crate::preemptibility::print(symbol_db, &symbol_resolution_flags)?;
+    }
     let non_addressable_counts = apply_non_addressable_indexes(&mut group_states, symbol_db.args)?;
     let section_part_sizes = compute_total_section_part_sizes(
         &mut group_states,
+        &hot_sections,
         &mut output_sections,
         &symbol_resolution_flags,
         gc_outputs.sections_with_content,
     );
-    let section_part_layouts = layout_section_parts(&section_part_sizes, &output_sections);
+    check_string_table_sizes(&section_part_sizes)?;
+    let section_part_layouts = layout_section_parts(&section_part_sizes, &output_sections)?;
     let section_layouts = layout_sections(&section_part_layouts);
     output.set_size(compute_total_file_size(&section_layouts));
 
@@ -137,9 +180,15 @@ pub fn compute<'data>(
         unreachable!();
     };
     let header_info = internal.header_info.as_ref().unwrap();
-    let segment_layouts = compute_segment_layout(&section_layouts, &output_sections, header_info)?;
+    let segment_layouts = compute_segment_layout(
+        &section_layouts,
+        &output_sections,
+        header_info,
+        symbol_db.args.common_page_size,
+    )?;
 
-    let mem_offsets: OutputSectionPartMap<u64> = starting_memory_offsets(&section_part_layouts);
+    let mut mem_offsets: OutputSectionPartMap<u64> = starting_memory_offsets(&section_part_layouts);
+    hot_sections.assign_addresses(&mut mem_offsets);
     let starting_mem_offsets_by_group = compute_start_offsets_by_group(&group_states, mem_offsets);
     let merged_string_start_addresses =
         MergedStringStartAddresses::compute(&output_sections, &starting_mem_offsets_by_group);
@@ -161,6 +210,7 @@ pub fn compute<'data>(
         section_layouts: &section_layouts,
         merged_string_start_addresses: &merged_string_start_addresses,
         merged_strings: &merged_strings,
+        hot_sections: &hot_sections,
     };
     let group_layouts = compute_symbols_and_layouts(
         group_states,
@@ -171,8 +221,30 @@ pub fn compute<'data>(
     for shard in per_group_res_writers {
         res_writer.try_return_shard(shard)?;
     }
+    errors.extend(crate::undefined_symbols::check(
+        symbol_db,
+        &group_layouts,
+        &undefined_symbols,
+    )?);
+    crate::error::combine(errors, symbol_db.args.error_limit)?;
     update_dynamic_symbol_resolutions(&group_layouts, &mut symbol_resolutions.resolutions);
+    let mut group_layouts = group_layouts;
+    // Sections are folded by ICF and when we redirect references to discarded COMDAT group
+    // members.
+    update_folded_resolutions(&mut group_layouts, &mut symbol_resolutions.resolutions)?;
+    order_link_order_sections(
+        &mut group_layouts,
+        &mut hot_sections,
+        &mut symbol_resolutions.resolutions,
+    )?;
+    update_defsym_resolutions(
+        &group_layouts,
+        symbol_db,
+        &mut symbol_resolutions.resolutions,
+    )?;
     crate::gc_stats::maybe_write_gc_stats(&group_layouts, symbol_db.args)?;
+    let exec_stack = determine_exec_stack(&group_layouts, symbol_db.args)?;
+    crate::section_permissions::check(&group_layouts, &output_sections, symbol_db.args);
 
     let relocation_statistics = OutputSectionMap::with_size(section_layouts.len());
 
@@ -189,10 +261,170 @@ pub fn compute<'data>(
         merged_strings,
         merged_string_start_addresses,
         has_static_tls: gc_outputs.has_static_tls,
+        has_text_relocations: gc_outputs.has_text_relocations,
+        pack_relative_relocs,
+        exec_stack,
         relocation_statistics,
+        hot_sections,
+        load_reasons,
     })
 }
 
+/// Decides whether the stack should be executable. Unless overridden by `-z execstack` or `-z
+/// noexecstack`, an object requests an executable stack with an executable `.note.GNU-stack`
+/// section. Objects without that section are assumed to need one, since they predate the note.
+fn determine_exec_stack(group_layouts: &[GroupLayout], args: &Args) -> Result<bool> {
+    let reason = match args.execstack {
+        Some(false) => return Ok(false),
+        Some(true) => "because of -z execstack".to_owned(),
+        None => {
+            let Some(reason) = group_layouts
+                .iter()
+                .flat_map(|group| &group.files)
+                .find_map(|file| match file {
+                    FileLayout::Object(object) => exec_stack_request(object.object)
+                        .map(|r| format!("{r} in {}", object.input)),
+                    _ => None,
+                })
+            else {
+                return Ok(false);
+            };
+            reason
+        }
+    };
+    if args.error_execstack {
+        bail!("Refusing to make the stack executable {reason}");
+    }
+    if args.warn_execstack {
+        warning!(args.diagnostics, "Making the stack executable {reason}");
+    }
+    Ok(true)
+}
+
+/// Returns a description of why `object` needs an executable stack, if it does.
+fn exec_stack_request(object: &File) -> Option<&'static str> {
+    match object.section_by_name(".note.GNU-stack") {
+        None => Some("because of a missing .note.GNU-stack section"),
+        Some((_, section)) if SectionFlags::from_header(section).contains(shf::EXECINSTR) => {
+            Some("because of an executable .note.GNU-stack section")
+        }
+        Some(_) => None,
+    }
+}
+
+/// Merges the `.note.gnu.property` sections of the objects that we're linking. The result is stored
+/// on the prelude, which writes our own note.
+fn merge_gnu_properties(group_states: &mut [GroupState], args: &Args) -> Result {
+    let x86_features = crate::gnu_property::merge_x86_features(
+        group_states
+            .iter()
+            .flat_map(|group| &group.files)
+            .filter_map(|file| match file {
+                FileLayoutState::Object(object) => Some((object.object, &object.input)),
+                _ => None,
+            }),
+        args,
+    )?;
+    let Some(FileLayoutState::Prelude(prelude)) =
+        group_states.first_mut().and_then(|g| g.files.first_mut())
+    else {
+        unreachable!();
+    };
+    prelude.x86_features = x86_features;
+    Ok(())
+}
+
+/// Returns whether relative relocations should go in `.relr.dyn`.
+fn should_pack_relative_relocs(groups: &[resolution::ResolvedGroup], args: &Args) -> bool {
+    args.pack_relative_relocs
+        && args.is_relocatable()
+        && crate::relr::can_pack(
+            groups
+                .iter()
+                .flat_map(|group| &group.files)
+                .filter_map(|file| match file {
+                    resolution::ResolvedFile::Object(object) if object.non_dynamic.is_none() => {
+                        Some(object.object)
+                    }
+                    _ => None,
+                }),
+        )
+}
+
+/// Merges the `.sframe` sections of the objects that we're linking, keeping only the entries for
+/// functions that survived garbage collection. The result is stored on the prelude, which writes our
+/// `.sframe` section.
+#[tracing::instrument(skip_all, name = "Merge SFrame sections")]
+fn merge_sframe_sections(group_states: &mut [GroupState], args: &Args) -> Result {
+    let inputs = group_states
+        .par_iter()
+        .flat_map_iter(|group| &group.files)
+        .filter_map(|file| match file {
+            FileLayoutState::Object(object) => Some(object),
+            _ => None,
+        })
+        .map(|object| {
+            let sframe =
+                crate::sframe::read_input(object.file_id, object.object, &object.state.sections)
+                    .with_context(|| format!("Failed to read .sframe of {}", object.input))?;
+            Ok(sframe.map(|sframe| (sframe, &object.input)))
+        })
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>>>()?;
+    let sframe = crate::sframe::merge(inputs, &args.diagnostics);
+    let Some(FileLayoutState::Prelude(prelude)) =
+        group_states.first_mut().and_then(|g| g.files.first_mut())
+    else {
+        unreachable!();
+    };
+    prelude.sframe = sframe;
+    Ok(())
+}
+
+/// Merges the CTF type information in the `.ctf` sections of the objects that we're linking. Objects
+/// with CTF that we can't read are reported and left out, as GNU ld does. The result is stored on the
+/// prelude, which writes our `.ctf` section.
+#[tracing::instrument(skip_all, name = "Merge CTF")]
+fn merge_ctf(group_states: &mut [GroupState], args: &Args) -> Result {
+    let dicts = group_states
+        .par_iter()
+        .flat_map_iter(|group| &group.files)
+        .filter_map(|file| match file {
+            FileLayoutState::Object(object) => Some(object),
+            _ => None,
+        })
+        .map(|object| {
+            let Some((_, section)) = object.object.section_by_name(".ctf") else {
+                return Ok(None);
+            };
+            let data = object.object.raw_section_data(section)?;
+            match crate::ctf::parse(data) {
+                Ok(dict) => Ok(Some(dict)),
+                Err(error) => {
+                    warning!(
+                        args.diagnostics,
+                        "Discarding CTF type information of {}: {error}",
+                        object.input
+                    );
+                    Ok(None)
+                }
+            }
+        })
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<_>>>()?;
+    if dicts.is_empty() {
+        return Ok(());
+    }
+    let ctf = crate::ctf::merge(&dicts, args.ctf_variables);
+    let Some(FileLayoutState::Prelude(prelude)) =
+        group_states.first_mut().and_then(|g| g.files.first_mut())
+    else {
+        unreachable!();
+    };
+    prelude.ctf = ctf;
+    Ok(())
+}
+
 /// Update resolutions for all dynamic symbols that our output file defines.
 #[tracing::instrument(skip_all, name = "Update dynamic symbol resolutions")]
 fn update_dynamic_symbol_resolutions(
@@ -211,15 +443,579 @@ fn update_dynamic_symbol_resolutions(
     }
 }
 
+/// Copies the values of symbols that `--defsym` aliases to other symbols. We do this once all other
+/// symbols have their final addresses.
+fn update_defsym_resolutions(
+    group_layouts: &[GroupLayout],
+    symbol_db: &SymbolDb,
+    resolutions: &mut [Option<Resolution>],
+) -> Result {
+    let Some(FileLayout::Prelude(prelude)) = group_layouts.first().and_then(|g| g.files.first())
+    else {
+        panic!("Prelude should be the first file");
+    };
+    let internal_symbols = &prelude.internal_symbols;
+    for (index, def_info) in internal_symbols.symbol_definitions.iter().enumerate() {
+        let InternalSymDefInfo::Defsym(defsym_index) = def_info else {
+            continue;
+        };
+        let DefsymValue::Symbol(target) = &symbol_db.args.defsyms[*defsym_index].value else {
+            continue;
+        };
+        let symbol_id = internal_symbols.start_symbol_id.add_usize(index);
+        let target_value = symbol_db
+            .defsym_target(target)
+            .and_then(|target_id| resolutions[target_id.as_usize()].as_ref())
+            .map(|res| res.raw_value)
+            .with_context(|| format!("Internal error: --defsym target `{target}` has no value"))?;
+        if let Some(res) = &mut resolutions[symbol_id.as_usize()] {
+            res.raw_value = target_value;
+        }
+    }
+    Ok(())
+}
+
 #[tracing::instrument(skip_all, name = "Finalise per-object sizes")]
 fn finalise_all_sizes(
     symbol_db: &SymbolDb,
     output_sections: &OutputSections,
     group_states: &mut [GroupState],
     symbol_resolution_flags: &[AtomicResolutionFlags],
+    pack_relative_relocs: bool,
 ) -> Result {
     group_states.par_iter_mut().try_for_each(|state| {
-        state.finalise_sizes(symbol_db, output_sections, symbol_resolution_flags)
+        state.finalise_sizes(
+            symbol_db,
+            output_sections,
+            symbol_resolution_flags,
+            pack_relative_relocs,
+        )
+    })
+}
+
+/// Moves the sections named by call-graph profiles out of their groups and into the hot sections,
+/// ordered so that functions that call each other a lot are close together.
+fn order_hot_sections(
+    group_states: &mut [GroupState],
+    symbol_db: &SymbolDb,
+    output_sections: &OutputSections,
+) -> Result<HotSections> {
+    let mut hot_sections = HotSections::new(output_sections);
+    let objects = || {
+        group_states
+            .iter()
+            .flat_map(|group| &group.files)
+            .filter_map(|file| match file {
+                FileLayoutState::Object(object) => Some(object),
+                _ => None,
+            })
+    };
+    if !symbol_db.args.call_graph_profile_sort
+        || !objects().any(|object| crate::call_graph_sort::has_profile(object.object))
+    {
+        return Ok(hot_sections);
+    }
+    let mut files = Vec::new();
+    for object in objects() {
+        let mut sections = Vec::new();
+        for (index, slot) in object.state.sections.iter().enumerate() {
+            let SectionSlot::Loaded(section) = slot else {
+                continue;
+            };
+            let header = object.object.section(section.index)?;
+            if SectionFlags::from_header(header).contains(shf::EXECINSTR) {
+                sections.push((
+                    object::SectionIndex(index),
+                    section.part_id,
+                    section.capacity(),
+                ));
+            }
+        }
+        files.push(crate::call_graph_sort::ProfileFile {
+            file_id: object.file_id,
+            object: object.object,
+            symbol_id_range: object.symbol_id_range,
+            sections,
+        });
+    }
+    for (file_id, section_index) in crate::call_graph_sort::compute_order(&files, symbol_db)? {
+        hot_sections.take(group_states, file_id, section_index);
+    }
+    Ok(hot_sections)
+}
+
+/// Moves the `.init_array` and `.fini_array` sections that have priorities, e.g. `.init_array.101`,
+/// out of their groups, so that they're placed ahead of those without, in order of priority, as with
+/// GNU ld's `SORT_BY_INIT_PRIORITY`. Constructors with lower priority values then run first. They all
+/// go in the first part of their output section, whatever their own alignment, since otherwise
+/// sections without priorities, but with higher alignment, would be placed ahead of them.
+fn order_init_priority_sections(
+    group_states: &mut [GroupState],
+    hot_sections: &mut HotSections,
+) -> Result {
+    let mut prioritised = group_states
+        .par_iter()
+        .flat_map_iter(|group| &group.files)
+        .filter_map(|file| match file {
+            FileLayoutState::Object(object) => Some(object),
+            _ => None,
+        })
+        .map(|object| {
+            let mut sections = Vec::new();
+            for slot in &object.state.sections {
+                let SectionSlot::Loaded(section) = slot else {
+                    continue;
+                };
+                let section_id = section.output_section_id();
+                if section_id != output_section_id::INIT_ARRAY
+                    && section_id != output_section_id::FINI_ARRAY
+                {
+                    continue;
+                }
+                let name = object
+                    .object
+                    .section_name(object.object.section(section.index)?)?;
+                if let Some(priority) = init_priority(name) {
+                    sections.push((priority, object.file_id, section.index));
+                }
+            }
+            Ok(sections)
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect_vec();
+    prioritised
+        .sort_by_key(|&(priority, file_id, section_index)| (priority, file_id, section_index.0));
+    let first_parts = [output_section_id::INIT_ARRAY, output_section_id::FINI_ARRAY]
+        .map(|section_id| first_used_part(group_states, section_id));
+    for (_, file_id, section_index) in prioritised {
+        let FileLayoutState::Object(object) = &group_states[file_id.group()].files[file_id.file()]
+        else {
+            unreachable!();
+        };
+        let SectionSlot::Loaded(section) = &object.state.sections[section_index.0] else {
+            unreachable!();
+        };
+        let part_id = first_parts
+            .into_iter()
+            .flatten()
+            .find(|part_id| part_id.output_section_id() == section.output_section_id())
+            .unwrap_or(section.part_id);
+        hot_sections.take_into(group_states, file_id, section_index, part_id);
+    }
+    Ok(())
+}
+
+/// Moves the SHF_LINK_ORDER sections out of their groups and into the hot sections. They need to be
+/// in the same order as the sections that they're linked to, which can come from any file, so they
+/// can't be placed until everything else has been. See `order_link_order_sections`.
+fn take_link_order_sections(group_states: &mut [GroupState], hot_sections: &mut HotSections) {
+    let link_order_sections = group_states
+        .par_iter()
+        .flat_map_iter(|group| &group.files)
+        .filter_map(|file| match file {
+            FileLayoutState::Object(object) => Some(object),
+            _ => None,
+        })
+        .flat_map_iter(|object| {
+            object
+                .state
+                .sections
+                .iter()
+                .enumerate()
+                .filter_map(move |(index, slot)| match slot {
+                    SectionSlot::Loaded(section) if section.link_order.is_some() => {
+                        Some((object.file_id, object::SectionIndex(index)))
+                    }
+                    _ => None,
+                })
+        })
+        .collect::<Vec<_>>();
+    hot_sections.link_order_start = hot_sections.sections.len();
+    for (file_id, section_index) in link_order_sections {
+        hot_sections.take(group_states, file_id, section_index);
+    }
+}
+
+/// Returns the part of `section_id` with the highest alignment that any group has sections in, or
+/// `None` if no group has any.
+fn first_used_part(group_states: &[GroupState], section_id: OutputSectionId) -> Option<PartId> {
+    let base_part_id = section_id.base_part_id();
+    (0..section_id.num_parts())
+        .map(|offset| base_part_id.offset(offset))
+        .find(|&part_id| {
+            group_states
+                .iter()
+                .any(|group| *group.common.mem_sizes.get(part_id) > 0)
+        })
+}
+
+/// Returns the priority of an `.init_array` or `.fini_array` section with the specified name, or
+/// `None` if it doesn't have one. `.ctors` and `.dtors` sections are run in reverse, so their
+/// priorities are inverted, as GNU ld does.
+fn init_priority(name: &[u8]) -> Option<u32> {
+    let parse = |digits: &[u8]| -> Option<u32> {
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    };
+    if let Some(digits) = name
+        .strip_prefix(b".init_array.")
+        .or_else(|| name.strip_prefix(b".fini_array."))
+    {
+        return parse(digits);
+    }
+    let digits = name
+        .strip_prefix(b".ctors.")
+        .or_else(|| name.strip_prefix(b".dtors."))?;
+    Some(65535u32.saturating_sub(parse(digits)?))
+}
+
+/// Replaces live sections that are identical to other live sections with references to those
+/// sections. See the `icf` module.
+fn fold_identical_sections<'data>(
+    group_states: &mut [GroupState<'data>],
+    symbol_db: &SymbolDb<'data>,
+    symbol_resolution_flags: &[AtomicResolutionFlags],
+) -> Result {
+    let mut icf_files = Vec::new();
+    let mut locations = FxHashMap::default();
+    for (group_index, group) in group_states.iter().enumerate() {
+        for (file_index, file) in group.files.iter().enumerate() {
+            let FileLayoutState::Object(object) = file else {
+                continue;
+            };
+            locations.insert(object.file_id, (group_index, file_index));
+            let mut sections = Vec::new();
+            for (index, slot) in object.state.sections.iter().enumerate() {
+                let section_index = object::SectionIndex(index);
+                if let SectionSlot::Loaded(section) = slot {
+                    if crate::icf::is_eligible(object.object, section_index, symbol_db.args)? {
+                        sections.push((section_index, section.part_id));
+                    }
+                }
+            }
+            // Objects without eligible sections are still needed, since their references can make
+            // symbols address-significant.
+            icf_files.push(crate::icf::IcfFile {
+                file_id: object.file_id,
+                object: object.object,
+                symbol_id_range: object.symbol_id_range,
+                sections,
+            });
+        }
+    }
+    let is_exported = |symbol_id: SymbolId| {
+        symbol_resolution_flags[symbol_id.as_usize()]
+            .get()
+            .contains(ResolutionFlags::EXPORT_DYNAMIC)
+    };
+    let folds = crate::icf::find_folds(&icf_files, symbol_db, &is_exported)?;
+    if symbol_db.args.print_icf_sections {
+        print_folds(&folds, group_states, &locations)?;
+    }
+    for fold in folds {
+        let (group_index, file_index) = locations[&fold.file_id];
+        let group = &mut group_states[group_index];
+        let FileLayoutState::Object(object) = &mut group.files[file_index] else {
+            unreachable!();
+        };
+        object.fold_section(&mut group.common, &fold, symbol_db.args)?;
+    }
+    Ok(())
+}
+
+/// Marks each CIE that's identical to a CIE in an earlier object, so that it isn't written and FDEs
+/// use the earlier one instead. Since objects are written in order, an FDE's CIE then always
+/// precedes it, as required.
+#[tracing::instrument(skip_all, name = "Deduplicate CIEs")]
+fn deduplicate_cies(group_states: &mut [GroupState]) {
+    let mut first_by_cie = FxHashMap::default();
+    for group in group_states.iter_mut() {
+        for file in &mut group.files {
+            let FileLayoutState::Object(object) = file else {
+                continue;
+            };
+            for (index, cie) in object.state.cies.iter_mut().enumerate() {
+                if !cie.cie.eligible_for_deduplication {
+                    continue;
+                }
+                match first_by_cie.entry(&cie.cie) {
+                    std::collections::hash_map::Entry::Occupied(entry) => {
+                        cie.duplicate_of = Some(*entry.get());
+                    }
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        entry.insert((object.file_id, index));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Links each output section that contains SHF_LINK_ORDER sections to the output section containing
+/// the section that the first of them is linked to. Tools like objcopy expect SHF_LINK_ORDER
+/// sections in executables to still have a link, as they do with GNU ld.
+fn set_link_order_links(group_states: &[GroupState], output_sections: &mut OutputSections) {
+    let links = group_states
+        .par_iter()
+        .map(|group| {
+            let mut links = Vec::new();
+            for file in &group.files {
+                let FileLayoutState::Object(object) = file else {
+                    continue;
+                };
+                for slot in &object.state.sections {
+                    let SectionSlot::Loaded(section) = slot else {
+                        continue;
+                    };
+                    let Some(link_order) = section.link_order else {
+                        continue;
+                    };
+                    if let SectionSlot::Loaded(target) = &object.state.sections[link_order.target.0]
+                    {
+                        links.push((
+                            section.part_id.output_section_id(),
+                            target.part_id.output_section_id(),
+                        ));
+                    }
+                }
+            }
+            links
+        })
+        .collect::<Vec<_>>();
+    for (section_id, target_id) in links.into_iter().flatten() {
+        output_sections.section_infos[section_id.as_usize()]
+            .link_order_link
+            .get_or_insert(target_id);
+    }
+}
+
+/// Reorders the SHF_LINK_ORDER sections within the space that they were given at the end of the hot
+/// sections, so that they're in the order of the final addresses of the sections that they're linked
+/// to, whichever files those came from. All the sections in a part have the same alignment, so
+/// reordering them doesn't change how much space they take up.
+fn order_link_order_sections(
+    group_layouts: &mut [GroupLayout],
+    hot_sections: &mut HotSections,
+    resolutions: &mut [Option<Resolution>],
+) -> Result {
+    let link_order_start = hot_sections.link_order_start;
+    if link_order_start == hot_sections.sections.len() {
+        return Ok(());
+    }
+    let mut part_starts = FxHashMap::default();
+    let mut link_order_sections = Vec::new();
+    for section in &hot_sections.sections[link_order_start..] {
+        let FileLayout::Object(object) =
+            &group_layouts[section.file_id.group()].files[section.file_id.file()]
+        else {
+            unreachable!();
+        };
+        let SectionSlot::Loaded(loaded) = &object.sections[section.section_index.0] else {
+            unreachable!();
+        };
+        let link_order = loaded
+            .link_order
+            .context("Internal error: hot SHF_LINK_ORDER section has no link")?;
+        let address = hot_sections.addresses[&(section.file_id, section.section_index)];
+        part_starts
+            .entry(section.part_id)
+            .and_modify(|start: &mut u64| *start = (*start).min(address))
+            .or_insert(address);
+        let target_address = object.section_resolutions[link_order.target.0].address();
+        link_order_sections.push((target_address, *section));
+    }
+    link_order_sections.sort_by_key(|(target_address, section)| {
+        (
+            section.part_id,
+            *target_address,
+            section.file_id,
+            section.section_index.0,
+        )
+    });
+
+    let mut moved: FxHashMap<FileId, Vec<(object::SectionIndex, u64, u64)>> = FxHashMap::default();
+    hot_sections.sections.truncate(link_order_start);
+    for (_, section) in link_order_sections {
+        let next = part_starts.get_mut(&section.part_id).unwrap();
+        let new_address = *next;
+        *next += section.capacity;
+        let old_address = hot_sections
+            .addresses
+            .insert((section.file_id, section.section_index), new_address)
+            .unwrap();
+        hot_sections.sections.push(section);
+        if new_address != old_address {
+            moved.entry(section.file_id).or_default().push((
+                section.section_index,
+                old_address,
+                new_address,
+            ));
+        }
+    }
+
+    for (file_id, sections) in moved {
+        let FileLayout::Object(object) = &mut group_layouts[file_id.group()].files[file_id.file()]
+        else {
+            unreachable!();
+        };
+        let mut deltas = vec![None; object.sections.len()];
+        for (section_index, old_address, new_address) in sections {
+            object.section_resolutions[section_index.0] = SectionResolution {
+                address: new_address,
+            };
+            deltas[section_index.0] = Some((old_address, new_address));
+        }
+        for (sym_index, sym) in object.object.symbols.enumerate() {
+            let Some(section_index) = object.object.symbol_section(sym, sym_index)? else {
+                continue;
+            };
+            let Some((old_address, new_address)) = deltas[section_index.0] else {
+                continue;
+            };
+            let symbol_id = object.symbol_id_range.input_to_id(sym_index);
+            if let Some(resolution) = &mut resolutions[symbol_id.as_usize()] {
+                resolution.raw_value = resolution.raw_value - old_address + new_address;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints each section that is kept, followed by the sections that were folded into it. Like other
+/// linkers, we print this to stderr.
+fn print_folds(
+    folds: &[Fold],
+    group_states: &[GroupState],
+    locations: &FxHashMap<FileId, (usize, usize)>,
+) -> Result {
+    let describe = |file_id: FileId, section_index: object::SectionIndex| -> Result<String> {
+        let (group_index, file_index) = locations[&file_id];
+        let FileLayoutState::Object(object) = &group_states[group_index].files[file_index] else {
+            bail!("Internal error: folded a section of {file_id}, which isn't an object");
+        };
+        Ok(format!(
+            "{}:({})",
+            object.input,
+            object.object.section_display_name(section_index)
+        ))
+    };
+    let mut previous = None;
+    for fold in folds {
+        let into = (fold.into_file, fold.into_section);
+        if previous != Some(into) {
+            eprintln!("selected section {}", describe(into.0, into.1)?);
+            previous = Some(into);
+        }
+        eprintln!(
+            "  removing identical section {}",
+            describe(fold.file_id, fold.section_index)?
+        );
+    }
+    Ok(())
+}
+
+/// Points sections that we folded, and the symbols that they define, at the sections that they
+/// were folded into, which now have addresses.
+#[tracing::instrument(skip_all, name = "Update resolutions of folded sections")]
+fn update_folded_resolutions(
+    group_layouts: &mut [GroupLayout],
+    resolutions: &mut [Option<Resolution>],
+) -> Result {
+    let mut folds = FxHashMap::default();
+    for file in group_layouts.iter().flat_map(|group| &group.files) {
+        if let FileLayout::Object(object) = file {
+            for (index, slot) in object.sections.iter().enumerate() {
+                if let SectionSlot::Folded(folded) = slot {
+                    folds.insert(
+                        (object.file_id, object::SectionIndex(index)),
+                        (folded.into_file, folded.into_section),
+                    );
+                }
+            }
+        }
+    }
+    // A section that we redirected to the kept copy of a COMDAT group might have been redirected to
+    // a section that was then itself folded, so follow each fold to the section that has an
+    // address.
+    let final_target = |mut target| {
+        while let Some(&next) = folds.get(&target) {
+            target = next;
+        }
+        target
+    };
+    let mut section_addresses = FxHashMap::default();
+    for &target in folds.values() {
+        section_addresses.insert(final_target(target), None);
+    }
+    for file in group_layouts.iter().flat_map(|group| &group.files) {
+        if let FileLayout::Object(object) = file {
+            for (index, resolution) in object.section_resolutions.iter().enumerate() {
+                if let Some(address) =
+                    section_addresses.get_mut(&(object.file_id, object::SectionIndex(index)))
+                {
+                    *address = resolution.address();
+                }
+            }
+        }
+    }
+    let e = LittleEndian;
+    for file in group_layouts.iter_mut().flat_map(|group| &mut group.files) {
+        let FileLayout::Object(object) = file else {
+            continue;
+        };
+        let mut has_folded_sections = false;
+        for (slot, resolution) in object.sections.iter().zip(&mut object.section_resolutions) {
+            if let SectionSlot::Folded(folded) = slot {
+                let address = section_addresses
+                    [&final_target((folded.into_file, folded.into_section))]
+                    .context("Section was folded into a section without an address")?;
+                *resolution = SectionResolution { address };
+                has_folded_sections = true;
+            }
+        }
+        if !has_folded_sections {
+            continue;
+        }
+        for (sym_index, sym) in object.object.symbols.enumerate() {
+            let Some(section_index) = object.object.symbol_section(sym, sym_index)? else {
+                continue;
+            };
+            if !matches!(object.sections[section_index.0], SectionSlot::Folded(_)) {
+                continue;
+            }
+            let symbol_id = object.symbol_id_range.input_to_id(sym_index);
+            if let Some(resolution) = &mut resolutions[symbol_id.as_usize()] {
+                resolution.raw_value =
+                    object.section_resolutions[section_index.0].address + sym.st_value(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// With `--no-gc-keep-exported`, exports don't keep sections alive, so once GC is done, we export
+/// those symbols that ended up being defined by a live section.
+#[tracing::instrument(skip_all, name = "Export live symbols")]
+fn export_live_symbols<'data>(
+    group_states: &mut [GroupState<'data>],
+    symbol_db: &SymbolDb<'data>,
+    symbol_resolution_flags: &[AtomicResolutionFlags],
+) -> Result {
+    group_states.par_iter_mut().try_for_each(|group| {
+        for file in &group.files {
+            if let FileLayoutState::Object(object) = file {
+                object.export_live_symbols(
+                    &mut group.common,
+                    symbol_db,
+                    symbol_resolution_flags,
+                )?;
+            }
+        }
+        Ok(())
     })
 }
 
@@ -238,6 +1034,18 @@ fn merge_dynamic_symbol_definitions(group_states: &mut [GroupState]) -> Result {
     Ok(())
 }
 
+/// Symbol names are referenced via 32 bit offsets, so string tables can't exceed 4GiB even when the
+/// rest of the file does.
+fn check_string_table_sizes(section_part_sizes: &OutputSectionPartMap<u64>) -> Result {
+    for (part_id, name) in [(part_id::STRTAB, ".strtab"), (part_id::DYNSTR, ".dynstr")] {
+        let size = *section_part_sizes.get(part_id);
+        if size > u64::from(u32::MAX) {
+            bail!("{name} would be {size} bytes, but string table offsets are limited to 32 bits");
+        }
+    }
+    Ok(())
+}
+
 fn compute_total_file_size(section_layouts: &OutputSectionMap<OutputRecordLayout>) -> u64 {
     let mut file_size = 0;
     section_layouts.for_each(|_, s| file_size = file_size.max(s.file_offset + s.file_size));
@@ -260,6 +1068,127 @@ pub struct Layout<'data> {
     pub(crate) merged_string_start_addresses: MergedStringStartAddresses,
     pub(crate) relocation_statistics: OutputSectionMap<AtomicU64>,
     pub(crate) has_static_tls: bool,
+
+    /// Whether the dynamic loader needs to apply relocations to read-only sections.
+    pub(crate) has_text_relocations: bool,
+
+    /// Whether relative relocations go in `.relr.dyn` rather than `.rela.dyn`.
+    pub(crate) pack_relative_relocs: bool,
+
+    /// Whether PT_GNU_STACK should allow execution.
+    pub(crate) exec_stack: bool,
+
+    pub(crate) hot_sections: HotSections,
+
+    /// Why each archive member that we loaded was loaded.
+    pub(crate) load_reasons: LoadReasons,
+}
+
+/// Sections that were ordered using call-graph profiles, together with `.init_array` and
+/// `.fini_array` sections that have priorities and SHF_LINK_ORDER sections. Since these can come from
+/// any file, they're placed at the start of their output section parts, ahead of the sections of the
+/// first group. Sections with priorities all go in the first part of their output section.
+pub(crate) struct HotSections {
+    /// The sections in the order in which they're placed.
+    pub(crate) sections: Vec<HotSection>,
+
+    /// The index in `sections` of the first SHF_LINK_ORDER section. These come after all other hot
+    /// sections.
+    link_order_start: usize,
+
+    /// The total size of the hot sections in each part.
+    pub(crate) mem_sizes: OutputSectionPartMap<u64>,
+
+    addresses: FxHashMap<(FileId, object::SectionIndex), u64>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct HotSection {
+    pub(crate) file_id: FileId,
+    pub(crate) section_index: object::SectionIndex,
+    pub(crate) part_id: PartId,
+    pub(crate) capacity: u64,
+}
+
+impl HotSections {
+    fn new(output_sections: &OutputSections) -> Self {
+        Self {
+            sections: Vec::new(),
+            link_order_start: 0,
+            mem_sizes: output_sections.new_part_map(),
+            addresses: FxHashMap::default(),
+        }
+    }
+
+    /// Moves a loaded section out of its group and appends it to the hot sections.
+    fn take(
+        &mut self,
+        group_states: &mut [GroupState],
+        file_id: FileId,
+        section_index: object::SectionIndex,
+    ) {
+        let group = &group_states[file_id.group()];
+        let FileLayoutState::Object(object) = &group.files[file_id.file()] else {
+            unreachable!();
+        };
+        let SectionSlot::Loaded(section) = &object.state.sections[section_index.0] else {
+            unreachable!();
+        };
+        let part_id = section.part_id;
+        self.take_into(group_states, file_id, section_index, part_id);
+    }
+
+    /// Like `take`, but places the section in `part_id`, which must be a part of the same output
+    /// section with at least the section's alignment. The section's space is padded to the
+    /// alignment of `part_id`, so that the sections after it stay aligned.
+    fn take_into(
+        &mut self,
+        group_states: &mut [GroupState],
+        file_id: FileId,
+        section_index: object::SectionIndex,
+        part_id: PartId,
+    ) {
+        let group = &mut group_states[file_id.group()];
+        let FileLayoutState::Object(object) = &group.files[file_id.file()] else {
+            unreachable!();
+        };
+        let SectionSlot::Loaded(section) = &object.state.sections[section_index.0] else {
+            unreachable!();
+        };
+        debug_assert_eq!(part_id.output_section_id(), section.output_section_id());
+        let capacity = section.capacity();
+        *group.common.mem_sizes.get_mut(section.part_id) -= capacity;
+        let capacity = if part_id == section.part_id {
+            capacity
+        } else {
+            part_id.alignment().align_up(capacity)
+        };
+        *self.mem_sizes.get_mut(part_id) += capacity;
+        self.sections.push(HotSection {
+            file_id,
+            section_index,
+            part_id,
+            capacity,
+        });
+    }
+
+    /// Assigns addresses to the hot sections, starting from `mem_offsets`, which are then advanced
+    /// past them.
+    fn assign_addresses(&mut self, mem_offsets: &mut OutputSectionPartMap<u64>) {
+        for section in &self.sections {
+            let offset = mem_offsets.get_mut(section.part_id);
+            self.addresses
+                .insert((section.file_id, section.section_index), *offset);
+            *offset += section.capacity;
+        }
+    }
+
+    fn address(&self, file_id: FileId, section_index: object::SectionIndex) -> Option<u64> {
+        if self.addresses.is_empty() {
+            return None;
+        }
+        self.addresses.get(&(file_id, section_index)).copied()
+    }
 }
 
 pub(crate) struct SegmentLayouts {
@@ -369,11 +1298,21 @@ struct PreludeLayoutState {
     symbol_id_range: SymbolIdRange,
     internal_symbols: InternalSymbols,
     entry_symbol_id: Option<SymbolId>,
+    init_symbol_id: Option<SymbolId>,
+    fini_symbol_id: Option<SymbolId>,
     needs_tlsld_got_entry: bool,
     identity: String,
+    command_line_note: Vec<u8>,
+
+    /// The GNU_PROPERTY_X86_FEATURE_1_AND bits that our output is compatible with.
+    x86_features: u32,
+    gnu_property_note: Vec<u8>,
+    sframe: Option<MergedSFrame>,
+    ctf: Vec<u8>,
     header_info: Option<HeaderInfo>,
     dynamic_linker: Option<CString>,
     shstrtab_size: u64,
+    has_lazy_plt: bool,
 }
 
 pub(crate) struct EpilogueLayoutState<'data> {
@@ -406,13 +1345,41 @@ pub(crate) struct ObjectLayout<'data> {
     pub(crate) object: &'data File<'data>,
     pub(crate) sections: Vec<SectionSlot<'data>>,
     pub(crate) section_resolutions: Vec<SectionResolution>,
+    /// The order in which our sections were placed in the output. `None` means input order.
+    pub(crate) section_order: Option<Vec<usize>>,
     pub(crate) symbol_id_range: SymbolIdRange,
+    /// How our debug symbols are split into shards that can be written in parallel. Empty if we
+    /// have few enough symbols that we write them all on the one thread.
+    pub(crate) symtab_shards: Vec<SymtabShard>,
+    pub(crate) cies: Vec<CieLayout>,
+}
+
+/// The number of input symbols in each shard of an object's symbol table. Objects with at most this
+/// many symbols are written as a single shard.
+pub(crate) const SYMTAB_SHARD_SIZE: usize = 1 << 16;
+
+/// The space used in .symtab and .strtab by a contiguous range of an object's symbols.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SymtabShard {
+    pub(crate) num_locals: usize,
+    pub(crate) num_globals: usize,
+    pub(crate) strings_size: usize,
 }
 
 pub(crate) struct PreludeLayout {
     pub(crate) entry_symbol_id: Option<SymbolId>,
+
+    /// The functions named by `-init` and `-fini`, if we defined them.
+    pub(crate) init_symbol_id: Option<SymbolId>,
+    pub(crate) fini_symbol_id: Option<SymbolId>,
     pub(crate) tlsld_got_entry: Option<NonZeroU64>,
+    /// Whether we're writing PLT0 and the reserved .got.plt entries needed for lazy binding.
+    pub(crate) has_lazy_plt: bool,
     pub(crate) identity: String,
+    pub(crate) command_line_note: Vec<u8>,
+    pub(crate) gnu_property_note: Vec<u8>,
+    pub(crate) sframe: Option<MergedSFrame>,
+    pub(crate) ctf: Vec<u8>,
     pub(crate) header_info: HeaderInfo,
     pub(crate) internal_symbols: InternalSymbols,
     pub(crate) dynamic_linker: Option<CString>,
@@ -479,7 +1446,9 @@ trait SymbolRequestHandler<'data>: std::fmt::Display {
                                 symbol_db.symbol_name_for_display(symbol_id)
                             )
                         })?;
-                } else {
+                } else if !current_res_flags.contains(ResolutionFlags::CANONICAL_PLT) {
+                    // Symbols with a canonical PLT entry are exported as definitions, so the
+                    // epilogue allocates their dynamic symbols.
                     let name = symbol_db.symbol_name(symbol_id)?;
                     common.allocate(part_id::DYNSTR, name.len() as u64 + 1);
                     common.allocate(part_id::DYNSYM, crate::elf::SYMTAB_ENTRY_SIZE);
@@ -568,6 +1537,14 @@ fn allocate_symbol_resolution(
     if value_flags.contains(ValueFlags::IFUNC) {
         resolution_flags.fetch_or(ResolutionFlags::GOT | ResolutionFlags::PLT);
     }
+    // If the function needs a regular PLT entry to serve as its address, then calls can go via that
+    // too, so there's no point also giving it a lazily bound entry.
+    if resolution_flags
+        .get()
+        .contains(ResolutionFlags::PLT | ResolutionFlags::LAZY_PLT)
+    {
+        resolution_flags.remove(ResolutionFlags::LAZY_PLT);
+    }
     let resolution_flags = resolution_flags.get();
 
     allocate_resolution(value_flags, resolution_flags, mem_sizes, output_kind);
@@ -597,17 +1574,16 @@ fn allocate_resolution(
 ) {
     let has_dynamic_symbol = value_flags.contains(ValueFlags::DYNAMIC)
         || resolution_flags.contains(ResolutionFlags::EXPORT_DYNAMIC);
-    if resolution_flags.contains(ResolutionFlags::COPY_RELOCATION) {
-        // Allocate space required for a copy relocation.
-        mem_sizes.increment(part_id::RELA_DYN_GENERAL, crate::elf::RELA_ENTRY_SIZE);
-    }
     if resolution_flags.contains(ResolutionFlags::GOT) {
         mem_sizes.increment(part_id::GOT, elf::GOT_ENTRY_SIZE);
         if resolution_flags.contains(ResolutionFlags::PLT) {
             mem_sizes.increment(part_id::PLT_GOT, elf::PLT_ENTRY_SIZE);
         }
-        if value_flags.contains(ValueFlags::IFUNC) {
-            mem_sizes.increment(part_id::RELA_PLT, elf::RELA_ENTRY_SIZE);
+        if value_flags.contains(ValueFlags::IFUNC)
+            && (value_flags.contains(ValueFlags::CAN_BYPASS_GOT) || !has_dynamic_symbol)
+        {
+            // An ifunc that might be interposed instead gets a symbolic relocation below.
+            mem_sizes.increment(part_id::RELA_PLT_IRELATIVE, elf::RELA_ENTRY_SIZE);
         } else if resolution_flags.contains(ResolutionFlags::COPY_RELOCATION) {
             // Copy relocation means that we know the relative address.
             if output_kind.is_relocatable() {
@@ -619,11 +1595,16 @@ fn allocate_resolution(
             mem_sizes.increment(part_id::RELA_DYN_RELATIVE, elf::RELA_ENTRY_SIZE);
         }
     }
+    if resolution_flags.contains(ResolutionFlags::LAZY_PLT) {
+        mem_sizes.increment(part_id::PLT, elf::PLT_ENTRY_SIZE);
+        mem_sizes.increment(part_id::GOT_PLT, elf::GOT_ENTRY_SIZE);
+        mem_sizes.increment(part_id::RELA_PLT_GENERAL, elf::RELA_ENTRY_SIZE);
+    }
     if resolution_flags.contains(ResolutionFlags::GOT_TLS_MODULE) {
         mem_sizes.increment(part_id::GOT, elf::GOT_ENTRY_SIZE * 2);
-        // For executables, the TLS module ID is known at link time. For shared objects, we
-        // need a runtime relocation to fill it in.
-        if !output_kind.is_executable() {
+        // For variables that an executable defines, the TLS module ID is known at link time.
+        // Otherwise, we need a runtime relocation to fill it in.
+        if !output_kind.is_executable() || value_flags.contains(ValueFlags::DYNAMIC) {
             mem_sizes.increment(part_id::RELA_DYN_GENERAL, elf::RELA_ENTRY_SIZE);
         }
         if !value_flags.contains(ValueFlags::CAN_BYPASS_GOT) && has_dynamic_symbol {
@@ -722,6 +1703,12 @@ impl<'data> SymbolRequestHandler<'data> for DynamicLayoutState<'data> {
         if symbol_db.args.output_kind == OutputKind::SharedObject {
             bail!("Cannot directly access dynamic symbol when building a shared object",);
         }
+        if copy_relocation_owner(self.object, self.symbol_id_range, symbol_db, symbol_id)?
+            != symbol_id
+        {
+            // We share the copy of an alias.
+            return Ok(());
+        }
         let symbol = self
             .object
             .symbol(self.symbol_id_range().id_to_input(symbol_id))?;
@@ -740,6 +1727,7 @@ impl<'data> SymbolRequestHandler<'data> for DynamicLayoutState<'data> {
             output_section_id::BSS.part_id_with_alignment(alignment),
             st_size,
         );
+        common.allocate(part_id::RELA_DYN_GENERAL, crate::elf::RELA_ENTRY_SIZE);
 
         Ok(())
     }
@@ -820,6 +1808,20 @@ impl CommonGroupState<'_> {
         Ok(())
     }
 
+    /// Moves the relative relocations for our GOT entries from `.rela.dyn` to `.relr.dyn`. We only
+    /// find out which of our GOT entries they're for when we write them, so we allocate enough
+    /// space for the worst case.
+    fn pack_got_relative_relocs(&mut self) {
+        let num_relocations = core::mem::take(self.mem_sizes.get_mut(part_id::RELA_DYN_RELATIVE))
+            / elf::RELA_ENTRY_SIZE;
+        let num_got_entries = self.mem_sizes.get(part_id::GOT) / elf::GOT_ENTRY_SIZE;
+        self.allocate(
+            part_id::RELR_DYN_GOT,
+            crate::relr::got_entries_bound(num_relocations, num_got_entries)
+                * crate::relr::ENTRY_SIZE,
+        );
+    }
+
     fn finalise_layout(
         &self,
         memory_offsets: &mut OutputSectionPartMap<u64>,
@@ -871,6 +1873,8 @@ struct ObjectLayoutState<'data> {
 
     eh_frame_section: Option<&'data object::elf::SectionHeader64<LittleEndian>>,
     eh_frame_size: u64,
+
+    symtab_shards: Vec<SymtabShard>,
 }
 
 /// The parts of `ObjectLayoutState` that we mutate during layout. Separate so that we can pass
@@ -911,7 +1915,7 @@ struct LocalWorkQueue {
 bitflags! {
     /// What kind of resolution we want for a symbol or section.
     #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-    pub(crate) struct ResolutionFlags: u8 {
+    pub(crate) struct ResolutionFlags: u16 {
         /// The direct value is needed. e.g. via a relative or absolute relocation that doesn't use the
         /// PLT or GOT.
         const DIRECT = 1 << 0;
@@ -937,11 +1941,21 @@ bitflags! {
         /// We encountered a direct reference to a symbol from a non-writable section and so we're
         /// going to need to do a copy relocation.
         const COPY_RELOCATION = 1 << 6;
+
+        /// A PLT entry that the dynamic loader binds on first use is needed. Only set for calls to
+        /// functions defined by shared objects when we're binding lazily.
+        const LAZY_PLT = 1 << 7;
+
+        /// The address of a function defined by a shared object is used directly from an
+        /// executable, so the function's PLT entry serves as its address. We export that address
+        /// so that the shared objects use it too, otherwise pointers to the function wouldn't
+        /// compare equal.
+        const CANONICAL_PLT = 1 << 8;
     }
 }
 
 struct AtomicResolutionFlags {
-    value: AtomicU8,
+    value: AtomicU16,
 }
 
 impl AtomicResolutionFlags {
@@ -951,7 +1965,7 @@ impl AtomicResolutionFlags {
 
     fn new(flags: ResolutionFlags) -> Self {
         Self {
-            value: AtomicU8::new(flags.bits()),
+            value: AtomicU16::new(flags.bits()),
         }
     }
 
@@ -974,12 +1988,17 @@ impl AtomicResolutionFlags {
     fn get(&self) -> ResolutionFlags {
         ResolutionFlags::from_bits_retain(self.value.load(atomic::Ordering::Relaxed))
     }
+
+    fn remove(&self, flags: ResolutionFlags) {
+        self.value
+            .fetch_and(!flags.bits(), atomic::Ordering::Relaxed);
+    }
 }
 
 impl Clone for AtomicResolutionFlags {
     fn clone(&self) -> Self {
         Self {
-            value: AtomicU8::new(self.value.load(atomic::Ordering::Relaxed)),
+            value: AtomicU16::new(self.value.load(atomic::Ordering::Relaxed)),
         }
     }
 }
@@ -1027,6 +2046,16 @@ pub(crate) struct Section {
     pub(crate) size: u64,
     pub(crate) resolution_kind: ResolutionFlags,
     pub(crate) is_writable: bool,
+
+    /// The last of the exception frames for this section. Earlier frames are linked from it.
+    pub(crate) last_frame_index: Option<FrameIndex>,
+
+    /// The last of the SHF_LINK_ORDER sections that are linked to this section. Earlier ones are
+    /// linked from it.
+    pub(crate) last_dependent: Option<SectionIndex>,
+
+    /// Set if this is an SHF_LINK_ORDER section.
+    pub(crate) link_order: Option<LinkOrder>,
 }
 
 pub(crate) struct GroupLayout<'data> {
@@ -1055,7 +2084,8 @@ struct GroupState<'data> {
 /// offsets and sizes, since we mmap our output file, so we're frequently working with in-memory
 /// slices. This means that if we were linking on a 32 bit system that we'd be limited to file
 /// offsets that were 32 bits. This isn't a loss though, since we couldn't mmap an output file where
-/// that would be a problem on a 32 bit system.
+/// that would be a problem on a 32 bit system. `layout_section_parts` reports an error in that
+/// case. Anything that goes into the output file is converted to u64.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct OutputRecordLayout {
     pub(crate) file_size: usize,
@@ -1094,6 +2124,11 @@ struct GraphResources<'data, 'scope> {
     merged_strings: &'scope OutputSectionMap<MergeStringsSection<'data>>,
 
     has_static_tls: AtomicBool,
+
+    has_text_relocations: AtomicBool,
+
+    /// Whether relative relocations go in `.relr.dyn` rather than `.rela.dyn`.
+    pack_relative_relocs: bool,
 }
 
 struct FinaliseLayoutResources<'scope, 'data> {
@@ -1103,6 +2138,7 @@ struct FinaliseLayoutResources<'scope, 'data> {
     section_layouts: &'scope OutputSectionMap<OutputRecordLayout>,
     merged_string_start_addresses: &'scope MergedStringStartAddresses,
     merged_strings: &'scope OutputSectionMap<MergeStringsSection<'data>>,
+    hot_sections: &'scope HotSections,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -1112,20 +2148,22 @@ enum WorkItem {
     /// symbol and process any relocations for that section.
     LoadGlobalSymbol(SymbolId),
 
-    /// A direct reference to a dynamic symbol has been encountered. The symbol should be defined in
-    /// BSS with a copy relocation.
-    ExportCopyRelocation(SymbolId),
+    /// A direct reference to a dynamic symbol has been encountered. The symbol should be defined by
+    /// our output, either in BSS with a copy relocation or, for functions, as its PLT entry.
+    ExportDynamicDefinition(SymbolId),
+
+    /// A section of another object was redirected to this section, which should be loaded. See the
+    /// `comdat` module.
+    LoadSection(FileId, object::SectionIndex),
 }
 
 impl WorkItem {
     fn file_id(&self, symbol_db: &SymbolDb) -> FileId {
-        symbol_db.file_id_for_symbol(self.symbol_id())
-    }
-
-    fn symbol_id(&self) -> SymbolId {
         match self {
-            WorkItem::LoadGlobalSymbol(s) => *s,
-            WorkItem::ExportCopyRelocation(s) => *s,
+            WorkItem::LoadGlobalSymbol(s) | WorkItem::ExportDynamicDefinition(s) => {
+                symbol_db.file_id_for_symbol(*s)
+            }
+            WorkItem::LoadSection(file_id, _) => *file_id,
         }
     }
 }
@@ -1195,6 +2233,25 @@ impl<'data> Layout<'data> {
         Ok(resolution.value())
     }
 
+    /// Returns the value for DT_INIT or DT_FINI. If `-init` or `-fini` named a function, then that's
+    /// its address, provided we defined it. Otherwise, it's the start of `section_id`, if non-empty.
+    pub(crate) fn init_function_address(
+        &self,
+        name: Option<&str>,
+        symbol_id: Option<SymbolId>,
+        section_id: OutputSectionId,
+    ) -> Option<u64> {
+        if name.is_none() {
+            return self
+                .has_data_in_section(section_id)
+                .then(|| self.vma_of_section(section_id));
+        }
+        let resolution = self.local_symbol_resolution(symbol_id?)?;
+        let flags = resolution.value_flags();
+        (flags.contains(ValueFlags::ADDRESS) && !flags.contains(ValueFlags::DYNAMIC))
+            .then(|| resolution.value())
+    }
+
     pub(crate) fn tls_start_address(&self) -> u64 {
         let tdata = &self.section_layouts.get(output_section_id::TDATA);
         tdata.mem_offset
@@ -1262,10 +2319,6 @@ impl<'data> Layout<'data> {
         linker_layout::Layout { files }
     }
 
-    pub(crate) fn resolution_flags_for_symbol(&self, symbol_id: SymbolId) -> ResolutionFlags {
-        self.symbol_resolution_flags[symbol_id.as_usize()]
-    }
-
     pub(crate) fn file_layout(&self, file_id: FileId) -> &FileLayout {
         let group_layout = &self.group_layouts[file_id.group()];
         &group_layout.files[file_id.file()]
@@ -1281,16 +2334,26 @@ impl<'data> Layout<'data> {
     pub(crate) fn dt_flags(&self) -> u64 {
         let mut flags = 0;
         let args = self.args();
-        flags |= object::elf::DF_BIND_NOW;
+        if !args.lazy_binding() {
+            flags |= object::elf::DF_BIND_NOW;
+        }
         if !args.output_kind.is_executable() && self.has_static_tls {
             flags |= object::elf::DF_STATIC_TLS;
         }
+        if self.has_text_relocations {
+            flags |= object::elf::DF_TEXTREL;
+        }
+        if args.symbolic == Symbolic::All && args.output_kind == OutputKind::SharedObject {
+            flags |= object::elf::DF_SYMBOLIC;
+        }
         flags as u64
     }
 
     pub(crate) fn dt_flags_1(&self) -> u64 {
-        let mut flags = 0;
-        flags |= object::elf::DF_1_NOW;
+        let mut flags = self.args().dt_flags_1;
+        if !self.args().lazy_binding() {
+            flags |= object::elf::DF_1_NOW;
+        }
         if self.args().output_kind.is_executable() && self.args().is_relocatable() {
             flags |= object::elf::DF_1_PIE;
         }
@@ -1391,6 +2454,7 @@ fn compute_segment_layout(
     section_layouts: &OutputSectionMap<OutputRecordLayout>,
     output_sections: &OutputSections,
     header_info: &HeaderInfo,
+    common_page_size: Alignment,
 ) -> Result<SegmentLayouts> {
     struct Record {
         segment_id: ProgramSegmentId,
@@ -1505,11 +2569,20 @@ fn compute_segment_layout(
             if id == program_segments::TLS {
                 tls_start_address = Some(r.mem_start);
             }
+            let mut mem_end = r.mem_end;
+            if id == program_segments::RELRO || id == program_segments::LOAD_RELRO {
+                // The dynamic loader rounds the end of PT_GNU_RELRO down to a page boundary, so we
+                // round it up to cover the last page. The following LOAD segment starts on a new
+                // page, so nothing that needs to stay writable shares that page. The pages that
+                // we protect must be mapped, so the LOAD segment containing PT_GNU_RELRO extends
+                // to the same place.
+                mem_end = common_page_size.align_up(mem_end);
+            }
             SegmentLayout {
                 id,
                 sizes: OutputRecordLayout {
                     file_size: r.file_end - r.file_start,
-                    mem_size: r.mem_end - r.mem_start,
+                    mem_size: mem_end - r.mem_start,
                     alignment: r.alignment,
                     file_offset: r.file_start,
                     mem_offset: r.mem_start,
@@ -1526,6 +2599,7 @@ fn compute_segment_layout(
 #[tracing::instrument(skip_all, name = "Compute total section sizes")]
 fn compute_total_section_part_sizes(
     group_states: &mut [GroupState],
+    hot_sections: &HotSections,
     output_sections: &mut OutputSections,
     symbol_resolution_flags: &[ResolutionFlags],
     sections_with_content: OutputSectionMap<bool>,
@@ -1534,6 +2608,7 @@ fn compute_total_section_part_sizes(
     for group_state in group_states.iter() {
         total_sizes.merge(&group_state.common.mem_sizes);
     }
+    total_sizes.merge(&hot_sections.mem_sizes);
     let first_group = group_states.first_mut().unwrap();
     let Some(FileLayoutState::Prelude(internal_layout)) = first_group.files.first_mut() else {
         unreachable!();
@@ -1611,6 +2686,7 @@ struct GcOutputs<'data> {
     group_states: Vec<GroupState<'data>>,
     sections_with_content: OutputSectionMap<bool>,
     has_static_tls: bool,
+    has_text_relocations: bool,
 }
 
 #[tracing::instrument(skip_all, name = "Find required sections")]
@@ -1621,6 +2697,7 @@ fn find_required_sections<'data>(
     symbol_resolution_flags: &[AtomicResolutionFlags],
     merged_strings: &OutputSectionMap<MergeStringsSection<'data>>,
     custom_start_stop_defs: Vec<InternalSymDefInfo>,
+    pack_relative_relocs: bool,
 ) -> Result<GcOutputs<'data>> {
     let num_workers = groups_in.len();
     let (worker_slots, groups) = create_worker_slots(
@@ -1646,6 +2723,8 @@ fn find_required_sections<'data>(
         sections_with_content: output_sections.new_section_map(),
         merged_strings,
         has_static_tls: AtomicBool::new(false),
+        has_text_relocations: AtomicBool::new(false),
+        pack_relative_relocs,
     };
     let resources_ref = &resources;
 
@@ -1707,16 +2786,17 @@ fn find_required_sections<'data>(
         }
     });
     let mut errors: Vec<Error> = core::mem::take(resources.errors.lock().unwrap().as_mut());
-    // TODO: Figure out good way to report more than one error.
-    if let Some(error) = errors.pop() {
-        return Err(error);
-    }
+    errors.sort_by_cached_key(|e| format!("{e:#}"));
+    crate::error::combine(errors, symbol_db.args.error_limit)?;
     let group_states = unwrap_worker_states(&resources.worker_slots)?;
     let sections_with_content = resources.sections_with_content.into_map(|v| v.into_inner());
     Ok(GcOutputs {
         group_states,
         sections_with_content,
         has_static_tls: resources.has_static_tls.load(atomic::Ordering::Relaxed),
+        has_text_relocations: resources
+            .has_text_relocations
+            .load(atomic::Ordering::Relaxed),
     })
 }
 
@@ -1792,6 +2872,7 @@ impl<'data> GroupState<'data> {
         symbol_db: &SymbolDb,
         output_sections: &OutputSections,
         symbol_resolution_flags: &[AtomicResolutionFlags],
+        pack_relative_relocs: bool,
     ) -> Result {
         for file_state in &mut self.files {
             file_state.finalise_sizes(
@@ -1801,6 +2882,9 @@ impl<'data> GroupState<'data> {
                 symbol_resolution_flags,
             )?
         }
+        if pack_relative_relocs {
+            self.common.pack_got_relative_relocs();
+        }
         self.common.validate_sizes()?;
         Ok(())
     }
@@ -1879,7 +2963,7 @@ fn activate<'data>(
         FileLayoutState::Prelude(s) => s.activate(common, resources, queue),
         FileLayoutState::Dynamic(s) => s.activate(common, resources, queue),
         FileLayoutState::NotLoaded(_) => Ok(()),
-        FileLayoutState::Epilogue(_) => Ok(()),
+        FileLayoutState::Epilogue(s) => s.activate(common, resources),
     }
 }
 
@@ -1909,13 +2993,13 @@ impl LocalWorkQueue {
         );
     }
 
-    fn send_copy_relocation_request(&mut self, symbol_id: SymbolId, resources: &GraphResources) {
+    fn send_export_definition_request(&mut self, symbol_id: SymbolId, resources: &GraphResources) {
         debug_assert!(resources.symbol_db.is_canonical(symbol_id));
         let symbol_file_id = resources.symbol_db.file_id_for_symbol(symbol_id);
         self.send_work(
             resources,
             symbol_file_id,
-            WorkItem::ExportCopyRelocation(symbol_id),
+            WorkItem::ExportDynamicDefinition(symbol_id),
         );
     }
 }
@@ -2007,17 +3091,27 @@ impl<'data> FileLayoutState<'data> {
                         resources.symbol_db.symbol_debug(symbol_id),
                     )
                 }),
-            WorkItem::ExportCopyRelocation(symbol_id) => match self {
+            WorkItem::ExportDynamicDefinition(symbol_id) => match self {
                 FileLayoutState::Dynamic(state) => {
-                    state.export_copy_relocation(common, symbol_id, resources)
+                    state.export_definition(common, symbol_id, resources, queue)
                 }
                 _ => {
                     bail!(
-                        "Internal error: ExportCopyRelocation sent to non-dynamic object for: {}",
+                        "Internal error: ExportDynamicDefinition sent to non-dynamic object for: {}",
                         resources.symbol_db.symbol_debug(symbol_id)
                     )
                 }
             },
+            WorkItem::LoadSection(_, section_index) => match self {
+                FileLayoutState::Object(state) => {
+                    state
+                        .state
+                        .sections_required
+                        .push(SectionRequest::new(section_index));
+                    state.load_sections(common, resources, queue)
+                }
+                _ => bail!("Internal error: LoadSection sent to {self}"),
+            },
         }
     }
 
@@ -2088,7 +3182,7 @@ impl<'data> FileLayoutState<'data> {
     }
 }
 
-fn compute_file_sizes(
+pub(crate) fn compute_file_sizes(
     mem_sizes: &OutputSectionPartMap<u64>,
     output_sections: &OutputSections<'_>,
 ) -> OutputSectionPartMap<usize> {
@@ -2223,13 +3317,20 @@ impl Section {
     ) -> Result<Section> {
         let object_section = object_state.object.section(section_index)?;
         let size = object_state.object.section_size(object_section)?;
-        let section = Section {
+        let mut section = Section {
             index: section_index,
             part_id,
             size,
             resolution_kind: ResolutionFlags::empty(),
             is_writable: SectionFlags::from_header(object_section).contains(shf::WRITE),
+            last_frame_index: None,
+            last_dependent: None,
+            link_order: None,
         };
+        if let Some(array) = crate::ctors::LegacyArray::for_section(object_state.object, &section)?
+        {
+            section.size = array.size;
+        }
         Ok(section)
     }
 
@@ -2264,8 +3365,10 @@ fn process_relocation(
     section: &object::elf::SectionHeader64<LittleEndian>,
     resources: &GraphResources,
     queue: &mut LocalWorkQueue,
-) -> Result {
+    relr_places: Option<&mut Vec<u64>>,
+) -> Result<RelocationModifier> {
     let args = resources.symbol_db.args;
+    let mut modifier = RelocationModifier::Normal;
     if let Some(local_sym_index) = rel.symbol(LittleEndian, false) {
         let symbol_db = resources.symbol_db;
         let symbol_id = symbol_db.definition(object.symbol_id_range.input_to_id(local_sym_index));
@@ -2274,6 +3377,17 @@ fn process_relocation(
         let rel_offset = rel.r_offset.get(LittleEndian);
         let r_type = rel.r_type(LittleEndian, false);
 
+        // Sections that aren't loaded at runtime, e.g. `.stack_sizes`, can't have dynamic
+        // relocations. We write link-time addresses into them, as we do for debug info.
+        if !SectionFlags::from_header(section).contains(shf::ALLOC) {
+            let previous_flags = resources.symbol_resolution_flags[symbol_id.as_usize()]
+                .fetch_or(ResolutionFlags::DIRECT);
+            if previous_flags.is_empty() {
+                queue.send_symbol_request(symbol_id, resources);
+            }
+            return Ok(modifier);
+        }
+
         let rel_info = if let Some(relaxation) = Relaxation::new(
             r_type,
             object.object.raw_section_data(section)?,
@@ -2281,7 +3395,11 @@ fn process_relocation(
             symbol_value_flags,
             args.output_kind,
             SectionFlags::from_header(section),
+            args.relax,
         ) {
+            if relaxation.skips_next_relocation() {
+                modifier = RelocationModifier::SkipNextRelocation;
+            }
             relaxation.rel_info
         } else {
             RelocationKindInfo::from_raw(r_type)?
@@ -2294,15 +3412,48 @@ fn process_relocation(
 
         let section_is_writable = SectionFlags::from_header(section).contains(shf::WRITE);
         let mut resolution_kind = resolution_flags(rel_info.kind);
+        if args.lazy_binding()
+            && resolution_kind.contains(ResolutionFlags::PLT)
+            && symbol_value_flags.contains(ValueFlags::DYNAMIC)
+        {
+            resolution_kind = ResolutionFlags::LAZY_PLT;
+        }
         if resolution_kind.contains(ResolutionFlags::DIRECT)
             && symbol_value_flags.contains(ValueFlags::DYNAMIC)
         {
             if section_is_writable {
                 common.allocate(part_id::RELA_DYN_GENERAL, elf::RELA_ENTRY_SIZE);
+            } else if args.is_relocatable() && rel_info.kind == RelocationKind::Absolute {
+                // Our output might be loaded at any address, so neither a copy relocation nor a
+                // canonical PLT entry would let us write the address at link time. The dynamic
+                // loader will need to write it into our read-only section.
+                record_text_relocation(object, section, r_type, symbol_id, resources)?;
+                common.allocate(part_id::RELA_DYN_GENERAL, elf::RELA_ENTRY_SIZE);
             } else if canonical_symbol_value_flags.contains(ValueFlags::FUNCTION) {
                 resolution_kind.remove(ResolutionFlags::DIRECT);
                 resolution_kind |= ResolutionFlags::PLT | ResolutionFlags::GOT;
+                if args.output_kind.is_executable() {
+                    resolution_kind |= ResolutionFlags::CANONICAL_PLT;
+                }
             } else if !symbol_value_flags.contains(ValueFlags::ABSOLUTE) {
+                if !args.copy_relocations {
+                    bail!(
+                        "Relocation {} against `{}` requires a copy relocation, but copy \
+                         relocations were disabled with -z nocopyreloc. Recompile with -fPIC",
+                        rel_type_to_string(r_type),
+                        symbol_db.symbol_name_for_display(symbol_id),
+                    );
+                }
+                // The shared object binds its own references to a protected symbol directly, so
+                // they'd keep using the original while we used the copy.
+                if let Some(lib_name) = protected_dynamic_definer(symbol_db, symbol_id)? {
+                    bail!(
+                        "Relocation {} against `{}` requires a copy relocation, but the symbol \
+                         has protected visibility in {lib_name}. Recompile with -fPIC",
+                        rel_type_to_string(r_type),
+                        symbol_db.symbol_name_for_display(symbol_id),
+                    );
+                }
                 resolution_kind |= ResolutionFlags::COPY_RELOCATION;
             }
         }
@@ -2310,23 +3461,134 @@ fn process_relocation(
         let previous_flags =
             resources.symbol_resolution_flags[symbol_id.as_usize()].fetch_or(resolution_kind);
 
+        // An absolute reference to an address needs a relative relocation. For an IFUNC, the
+        // dynamic loader instead calls the resolver, so that the address is the same as the one
+        // that we put in the GOT.
         if args.is_relocatable()
             && rel_info.kind == RelocationKind::Absolute
-            && symbol_value_flags.contains(ValueFlags::ADDRESS)
+            && symbol_value_flags.intersects(ValueFlags::ADDRESS | ValueFlags::IFUNC)
         {
-            common.allocate(part_id::RELA_DYN_RELATIVE, elf::RELA_ENTRY_SIZE);
+            let referencing_flags =
+                symbol_db.symbol_value_flags(object.symbol_id_range.input_to_id(local_sym_index));
+            if section_is_writable && referencing_flags.is_interposable() {
+                common.allocate(part_id::RELA_DYN_GENERAL, elf::RELA_ENTRY_SIZE);
+            } else {
+                if !section_is_writable {
+                    record_text_relocation(object, section, r_type, symbol_id, resources)?;
+                }
+                if symbol_value_flags.contains(ValueFlags::IFUNC)
+                    && rel.r_addend.get(LittleEndian) == 0
+                {
+                    common.allocate(part_id::RELA_PLT_IRELATIVE, elf::RELA_ENTRY_SIZE);
+                } else if let Some(places) = relr_places.filter(|_| rel_offset.is_multiple_of(2)) {
+                    places.push(rel_offset);
+                } else if resources.pack_relative_relocs {
+                    // The relative part of `.rela.dyn` is left for GOT entries, which we might
+                    // later move to `.relr.dyn`.
+                    common.allocate(part_id::RELA_DYN_GENERAL, elf::RELA_ENTRY_SIZE);
+                } else {
+                    common.allocate(part_id::RELA_DYN_RELATIVE, elf::RELA_ENTRY_SIZE);
+                }
+            }
         }
 
         if previous_flags.is_empty() {
             queue.send_symbol_request(symbol_id, resources);
         }
 
-        if resolution_kind.contains(ResolutionFlags::COPY_RELOCATION)
-            && !previous_flags.contains(ResolutionFlags::COPY_RELOCATION)
-        {
-            queue.send_copy_relocation_request(symbol_id, resources);
+        let export_flags = ResolutionFlags::COPY_RELOCATION | ResolutionFlags::CANONICAL_PLT;
+        if resolution_kind.intersects(export_flags) && !previous_flags.intersects(export_flags) {
+            queue.send_export_definition_request(symbol_id, resources);
         }
     }
+    Ok(modifier)
+}
+
+/// Returns whether relative relocations in `section` can go in `.relr.dyn`. The places need to be
+/// even and the dynamic loader needs to be able to write them without being told to make them
+/// writable first.
+pub(crate) fn can_pack_relative_relocs(
+    object: &crate::elf::File,
+    section: &object::elf::SectionHeader64<LittleEndian>,
+) -> Result<bool> {
+    Ok(SectionFlags::from_header(section).contains(shf::WRITE)
+        && object.section_alignment(section)? >= 2)
+}
+
+/// If `symbol_id` is defined with protected visibility by a shared object, returns the name of that
+/// shared object.
+fn protected_dynamic_definer(symbol_db: &SymbolDb, symbol_id: SymbolId) -> Result<Option<String>> {
+    let ParsedInput::Object(definer) = symbol_db.file(symbol_db.file_id_for_symbol(symbol_id))
+    else {
+        return Ok(None);
+    };
+    let symbol = definer
+        .object
+        .symbol(symbol_id.to_input(definer.symbol_id_range))?;
+    Ok(
+        (definer.is_dynamic() && symbol.st_visibility() == object::elf::STV_PROTECTED)
+            .then(|| definer.input.to_string()),
+    )
+}
+
+/// Returns the symbols of `object` that are defined at the same place as `symbol_index`, including
+/// `symbol_index` itself. Code in the shared object might access a variable through any of its names
+/// (e.g. `environ` and `__environ`), so if we copy it, all of those names need to refer to the copy.
+fn copy_relocation_aliases<'data>(
+    object: &'data File<'data>,
+    symbol_index: object::SymbolIndex,
+) -> Result<impl Iterator<Item = object::SymbolIndex> + 'data> {
+    let symbol = object.symbol(symbol_index)?;
+    let section_index = symbol.st_shndx(LittleEndian);
+    let value = symbol.st_value(LittleEndian);
+    Ok(object
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(move |(_, alias)| {
+            alias.st_shndx(LittleEndian) == section_index && alias.st_value(LittleEndian) == value
+        })
+        .map(|(index, _)| object::SymbolIndex(index)))
+}
+
+/// Returns the alias of `symbol_id` that owns the copy of the variable. The other aliases share its
+/// address and don't get their own copy relocation.
+pub(crate) fn copy_relocation_owner(
+    object: &File,
+    symbol_id_range: SymbolIdRange,
+    symbol_db: &SymbolDb,
+    symbol_id: SymbolId,
+) -> Result<SymbolId> {
+    Ok(
+        copy_relocation_aliases(object, symbol_id.to_input(symbol_id_range))?
+            .map(|index| symbol_id_range.input_to_id(index))
+            .find(|&alias| symbol_db.is_canonical(alias))
+            .unwrap_or(symbol_id),
+    )
+}
+
+/// Records that the dynamic loader will need to apply a relocation to a read-only section, or fails
+/// if we were asked not to produce text relocations.
+fn record_text_relocation(
+    object: &ObjectLayoutState,
+    section: &object::elf::SectionHeader64<LittleEndian>,
+    r_type: u32,
+    symbol_id: SymbolId,
+    resources: &GraphResources,
+) -> Result {
+    let symbol_db = resources.symbol_db;
+    if symbol_db.args.text {
+        bail!(
+            "Relocation {} against `{}` in read-only section `{}` requires a text relocation. \
+             Recompile with -fPIC or link with -z notext",
+            rel_type_to_string(r_type),
+            symbol_db.symbol_name_for_display(symbol_id),
+            String::from_utf8_lossy(object.object.section_name(section)?),
+        );
+    }
+    resources
+        .has_text_relocations
+        .store(true, atomic::Ordering::Relaxed);
     Ok(())
 }
 
@@ -2365,18 +3627,26 @@ impl<'data> PreludeLayoutState {
                 start_symbol_id: SymbolId::zero(),
             },
             entry_symbol_id: None,
+            init_symbol_id: None,
+            fini_symbol_id: None,
             needs_tlsld_got_entry: false,
             identity: crate::identity::linker_identity(),
+            command_line_note: Vec::new(),
+            x86_features: 0,
+            gnu_property_note: Vec::new(),
+            sframe: None,
+            ctf: Vec::new(),
             header_info: None,
             dynamic_linker: None,
             shstrtab_size: 0,
+            has_lazy_plt: false,
         }
     }
 
     fn activate(
         &mut self,
-        common: &mut CommonGroupState,
-        resources: &GraphResources,
+        common: &mut CommonGroupState<'data>,
+        resources: &GraphResources<'data, '_>,
         queue: &mut LocalWorkQueue,
     ) -> Result {
         resources.merged_strings.for_each(|section_id, merged| {
@@ -2394,6 +3664,22 @@ impl<'data> PreludeLayoutState {
             self.identity.len() as u64,
         );
 
+        let args = resources.symbol_db.args;
+        if args.record_command_line {
+            self.command_line_note = crate::identity::command_line_note(&args.command_line);
+            common.allocate(
+                output_section_id::NOTE_WILD_CMDLINE.part_id_with_alignment(alignment::NOTE),
+                self.command_line_note.len() as u64,
+            );
+        }
+
+        if let Some(build_id) = args.build_id.as_ref() {
+            common.allocate(
+                output_section_id::NOTE_GNU_BUILD_ID.part_id_with_alignment(alignment::NOTE),
+                crate::build_id::note_size(build_id),
+            );
+        }
+
         // The first entry in the symbol table must be null. Similarly, the first string in the
         // strings table must be empty.
         if !resources.symbol_db.args.strip_all {
@@ -2404,6 +3690,10 @@ impl<'data> PreludeLayoutState {
         if resources.symbol_db.args.output_kind.is_executable() {
             self.load_entry_point(resources, queue)?;
         }
+        self.load_defsyms(common, resources, queue)?;
+        let args = resources.symbol_db.args;
+        self.init_symbol_id = Self::load_init_function(args.init.as_deref(), resources, queue);
+        self.fini_symbol_id = Self::load_init_function(args.fini.as_deref(), resources, queue);
         if resources.symbol_db.args.tls_mode() == TlsMode::Preserve {
             // Allocate space for a TLS module number and offset for use with TLSLD relocations.
             common.allocate(part_id::GOT, elf::GOT_ENTRY_SIZE * 2);
@@ -2438,16 +3728,79 @@ impl<'data> PreludeLayoutState {
         Ok(())
     }
 
+    /// Loads the function named by `-init` or `-fini`. Like GNU ld, if nothing defines it, we just
+    /// don't write the corresponding dynamic entry.
+    fn load_init_function(
+        name: Option<&str>,
+        resources: &GraphResources,
+        queue: &mut LocalWorkQueue,
+    ) -> Option<SymbolId> {
+        let symbol_id = *resources
+            .symbol_db
+            .global_names
+            .get(&SymbolName::prehashed(name?.as_bytes()))?;
+        let file_id = resources.symbol_db.file_id_for_symbol(symbol_id);
+        let old_flags = resources.symbol_resolution_flags[symbol_id.as_usize()]
+            .fetch_or(ResolutionFlags::DIRECT);
+        if old_flags.is_empty() {
+            queue.send_work(resources, file_id, WorkItem::LoadGlobalSymbol(symbol_id));
+        }
+        Some(symbol_id)
+    }
+
+    /// Loads the symbols defined by `--defsym`. Like GNU ld, we define these even if nothing
+    /// references them. If we're writing a shared object, we also export them unless the version
+    /// script says that they should be local.
+    fn load_defsyms(
+        &self,
+        common: &mut CommonGroupState<'data>,
+        resources: &GraphResources<'data, '_>,
+        queue: &mut LocalWorkQueue,
+    ) -> Result {
+        let symbol_db = resources.symbol_db;
+        for (index, def_info) in self.internal_symbols.symbol_definitions.iter().enumerate() {
+            let InternalSymDefInfo::Defsym(defsym_index) = def_info else {
+                continue;
+            };
+            let symbol_id = self.internal_symbols.start_symbol_id.add_usize(index);
+            let mut flags = ResolutionFlags::DIRECT;
+            if symbol_db.args.output_kind == OutputKind::SharedObject
+                && !symbol_db
+                    .local_symbol_value_flags(symbol_id)
+                    .contains(ValueFlags::DOWNGRADE_TO_LOCAL)
+            {
+                flags |= ResolutionFlags::EXPORT_DYNAMIC;
+                export_dynamic(common, symbol_id, resources)?;
+            }
+            resources.symbol_resolution_flags[symbol_id.as_usize()].fetch_or(flags);
+
+            if let DefsymValue::Symbol(target) = &symbol_db.args.defsyms[*defsym_index].value {
+                // Resolution already checked that the target is defined.
+                let target_id = symbol_db
+                    .defsym_target(target)
+                    .context("Internal error: --defsym target is undefined")?;
+                let old_flags = resources.symbol_resolution_flags[target_id.as_usize()]
+                    .fetch_or(ResolutionFlags::DIRECT);
+                if old_flags.is_empty() {
+                    let file_id = symbol_db.file_id_for_symbol(target_id);
+                    queue.send_work(resources, file_id, WorkItem::LoadGlobalSymbol(target_id));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn load_entry_point(
         &mut self,
         resources: &GraphResources,
         queue: &mut LocalWorkQueue,
     ) -> Result {
+        let entry = &resources.symbol_db.args.entry;
         let symbol_id = *resources
             .symbol_db
             .global_names
-            .get(&SymbolName::prehashed(b"_start"))
-            .context("Missing _start symbol")?;
+            .get(&SymbolName::prehashed(entry.as_bytes()))
+            .with_context(|| format!("Missing entry symbol `{entry}`"))?;
         self.entry_symbol_id = Some(symbol_id);
         let file_id = resources.symbol_db.file_id_for_symbol(symbol_id);
         let old_flags = resources.symbol_resolution_flags[symbol_id.as_usize()]
@@ -2479,6 +3832,18 @@ impl<'data> PreludeLayoutState {
             );
         }
 
+        if let Some(sframe) = self.sframe.as_ref() {
+            common.allocate(
+                output_section_id::SFRAME.part_id_with_alignment(alignment::USIZE),
+                sframe.size(),
+            );
+        }
+
+        common.allocate(
+            output_section_id::CTF.part_id_with_alignment(alignment::MIN),
+            self.ctf.len() as u64,
+        );
+
         Ok(())
     }
 
@@ -2495,6 +3860,19 @@ impl<'data> PreludeLayoutState {
     ) {
         use output_section_id::OrderEvent;
 
+        // Lazily bound PLT entries don't start with `endbr64`, so if we have any, our output isn't
+        // compatible with IBT.
+        if *total_sizes.get(part_id::PLT) > 0 {
+            self.x86_features &= !object::elf::GNU_PROPERTY_X86_FEATURE_1_IBT;
+        }
+        if self.x86_features != 0 {
+            self.gnu_property_note = crate::gnu_property::note(self.x86_features);
+            let part_id =
+                output_section_id::NOTE_GNU_PROPERTY.part_id_with_alignment(alignment::USIZE);
+            common.allocate(part_id, self.gnu_property_note.len() as u64);
+            total_sizes.increment(part_id, self.gnu_property_note.len() as u64);
+        }
+
         // Determine which sections to keep. To start with, we keep all sections into which we've
         // loaded an input section. Note, this includes where the input section and even the output
         // section is empty. We still need the output section as it may contain symbols.
@@ -2584,6 +3962,17 @@ impl<'data> PreludeLayoutState {
             .sum::<u64>();
         extra_sizes.increment(part_id::SHSTRTAB, self.shstrtab_size);
 
+        // If anything is bound lazily, then we need PLT0, which calls the resolver, and the
+        // .got.plt entries where the dynamic loader stores the resolver and its handle for us.
+        if *total_sizes.get(part_id::PLT) > 0 {
+            self.has_lazy_plt = true;
+            extra_sizes.increment(part_id::PLT, elf::PLT_ENTRY_SIZE);
+            extra_sizes.increment(
+                part_id::GOT_PLT,
+                elf::GOT_ENTRY_SIZE * elf::NUM_RESERVED_GOT_PLT_ENTRIES,
+            );
+        }
+
         // We need to allocate both our own size record and the file totals, since they've already
         // been computed.
         common.mem_sizes.merge(&extra_sizes);
@@ -2610,6 +3999,14 @@ impl<'data> PreludeLayoutState {
             address
         });
 
+        if self.has_lazy_plt {
+            memory_offsets.increment(part_id::PLT, elf::PLT_ENTRY_SIZE);
+            memory_offsets.increment(
+                part_id::GOT_PLT,
+                elf::GOT_ENTRY_SIZE * elf::NUM_RESERVED_GOT_PLT_ENTRIES,
+            );
+        }
+
         // Take the null symbol's index.
         if resources.symbol_db.args.needs_dynsym() {
             take_dynsym_index(memory_offsets, resources.section_layouts)?;
@@ -2622,6 +4019,30 @@ impl<'data> PreludeLayoutState {
             output_section_id::COMMENT.part_id_with_alignment(alignment::MIN),
             self.identity.len() as u64,
         );
+        memory_offsets.increment(
+            output_section_id::NOTE_WILD_CMDLINE.part_id_with_alignment(alignment::NOTE),
+            self.command_line_note.len() as u64,
+        );
+        memory_offsets.increment(
+            output_section_id::NOTE_GNU_PROPERTY.part_id_with_alignment(alignment::USIZE),
+            self.gnu_property_note.len() as u64,
+        );
+        if let Some(sframe) = self.sframe.as_ref() {
+            memory_offsets.increment(
+                output_section_id::SFRAME.part_id_with_alignment(alignment::USIZE),
+                sframe.size(),
+            );
+        }
+        memory_offsets.increment(
+            output_section_id::CTF.part_id_with_alignment(alignment::MIN),
+            self.ctf.len() as u64,
+        );
+        if let Some(build_id) = resources.symbol_db.args.build_id.as_ref() {
+            memory_offsets.increment(
+                output_section_id::NOTE_GNU_BUILD_ID.part_id_with_alignment(alignment::NOTE),
+                crate::build_id::note_size(build_id),
+            );
+        }
         resources.merged_strings.for_each(|section_id, merged| {
             if merged.len() > 0 {
                 memory_offsets.increment(
@@ -2634,8 +4055,15 @@ impl<'data> PreludeLayoutState {
         Ok(PreludeLayout {
             internal_symbols: self.internal_symbols,
             entry_symbol_id: self.entry_symbol_id,
+            init_symbol_id: self.init_symbol_id,
+            fini_symbol_id: self.fini_symbol_id,
             tlsld_got_entry,
+            has_lazy_plt: self.has_lazy_plt,
             identity: self.identity,
+            command_line_note: self.command_line_note,
+            gnu_property_note: self.gnu_property_note,
+            sframe: self.sframe,
+            ctf: self.ctf,
             dynamic_linker: self.dynamic_linker,
             header_info: self
                 .header_info
@@ -2702,6 +4130,16 @@ impl InternalSymbols {
                     let sec = resources.section_layouts.get(*section_id);
                     (sec.mem_offset + sec.mem_size, ValueFlags::ADDRESS)
                 }
+                InternalSymDefInfo::Defsym(index) => {
+                    match &resources.symbol_db.args.defsyms[*index].value {
+                        DefsymValue::Absolute(value) => (*value, ValueFlags::ABSOLUTE),
+                        // The other symbol might not have an address yet, so we fill in the value
+                        // later in `update_defsym_resolutions`.
+                        DefsymValue::Symbol(_) => {
+                            (0, resources.symbol_db.local_symbol_value_flags(symbol_id))
+                        }
+                    }
+                }
             };
             emitter.emit_resolution(
                 symbol_id,
@@ -2717,6 +4155,42 @@ impl InternalSymbols {
 }
 
 impl<'data> EpilogueLayoutState<'data> {
+    fn activate(
+        &mut self,
+        common: &mut CommonGroupState<'data>,
+        resources: &GraphResources<'data, '_>,
+    ) -> Result {
+        if resources.symbol_db.args.output_kind == OutputKind::SharedObject {
+            self.export_start_stop_symbols(common, resources)?;
+        }
+        Ok(())
+    }
+
+    /// Exports the start/stop symbols that we defined for custom sections, unless the version
+    /// script says that they should be local. This matches what we do for global symbols from
+    /// regular objects.
+    fn export_start_stop_symbols(
+        &mut self,
+        common: &mut CommonGroupState<'data>,
+        resources: &GraphResources<'data, '_>,
+    ) -> Result {
+        for symbol_id in self.symbol_id_range {
+            if !resources.symbol_db.is_canonical(symbol_id) {
+                continue;
+            }
+            let value_flags = resources.symbol_db.local_symbol_value_flags(symbol_id);
+            if value_flags.contains(ValueFlags::DOWNGRADE_TO_LOCAL) {
+                continue;
+            }
+            let old_flags = resources.symbol_resolution_flags[symbol_id.as_usize()]
+                .fetch_or(ResolutionFlags::EXPORT_DYNAMIC);
+            if !old_flags.contains(ResolutionFlags::EXPORT_DYNAMIC) {
+                export_dynamic(common, symbol_id, resources)?;
+            }
+        }
+        Ok(())
+    }
+
     fn new(
         input_state: ResolvedEpilogue,
         custom_start_stop_defs: Vec<InternalSymDefInfo>,
@@ -2756,16 +4230,20 @@ impl<'data> EpilogueLayoutState<'data> {
                 part_id::DYNAMIC,
                 (elf_writer::NUM_EPILOGUE_DYNAMIC_ENTRIES * dynamic_entry_size) as u64,
             );
-            for rpath in &symbol_db.args.rpaths {
+            if let Some(rpath) = &symbol_db.args.rpath {
                 common.allocate(part_id::DYNAMIC, dynamic_entry_size as u64);
                 common.allocate(part_id::DYNSTR, rpath.len() as u64 + 1);
             }
-            if let Some(soname) = symbol_db.args.soname.as_ref() {
+            if let Some(soname) = symbol_db.args.dt_soname() {
                 common.allocate(part_id::DYNSTR, soname.len() as u64 + 1);
                 common.allocate(part_id::DYNAMIC, dynamic_entry_size as u64);
             }
+            for (_, name) in symbol_db.args.dt_filters() {
+                common.allocate(part_id::DYNSTR, name.len() as u64 + 1);
+                common.allocate(part_id::DYNAMIC, dynamic_entry_size as u64);
+            }
 
-            self.allocate_gnu_hash(common);
+            self.allocate_gnu_hash(common, symbol_db.args);
 
             common.allocate(
                 part_id::DYNSTR,
@@ -2785,27 +4263,18 @@ impl<'data> EpilogueLayoutState<'data> {
 
     /// Allocates space required for .gnu.hash. Also sorts dynamic symbol definitions by their hash
     /// bucket as required by .gnu.hash.
-    fn allocate_gnu_hash(&mut self, common: &mut CommonGroupState) {
-        // Our number of buckets is computed somewhat arbitrarily so that we have on average 2
-        // symbols per bucket, but then we round up to a power of two.
+    fn allocate_gnu_hash(&mut self, common: &mut CommonGroupState, args: &Args) {
         let num_defs = self.dynamic_symbol_definitions.len();
-        let gnu_hash_layout = GnuHashLayout {
-            bucket_count: (num_defs / 2).next_power_of_two() as u32,
-            bloom_shift: 6,
-            bloom_count: 1,
-            // `symbol_base` is set later in `finalise_layout`.
-            symbol_base: 0,
-        };
+        let gnu_hash_layout = GnuHashLayout::new(num_defs, args);
         // Sort by bucket. Tie-break by name for determinism. We can use an unstable sort
         // because name should be unique. We use a parallel sort because we're processing
         // symbols from potentially many input objects, so there can be a lot.
         self.dynamic_symbol_definitions
             .par_sort_unstable_by_key(|d| (gnu_hash_layout.bucket_for_hash(d.hash), d.name));
-        let num_blume = 1;
         common.allocate(
             part_id::GNU_HASH,
             (core::mem::size_of::<elf::GnuHashHeader>()
-                + core::mem::size_of::<u64>() * num_blume
+                + core::mem::size_of::<u64>() * gnu_hash_layout.bloom_count as usize
                 + core::mem::size_of::<u32>() * gnu_hash_layout.bucket_count as usize
                 + core::mem::size_of::<u32>() * num_defs) as u64,
         );
@@ -2879,6 +4348,7 @@ fn new_object_layout_state(input_state: resolution::ResolvedObject) -> FileLayou
             exception_frames: Default::default(),
             eh_frame_section: None,
             eh_frame_size: 0,
+            symtab_shards: Vec::new(),
             state: ObjectLayoutMutableState {
                 sections: non_dynamic.sections,
                 sections_required: Default::default(),
@@ -2937,7 +4407,9 @@ impl<'data> ObjectLayoutState<'data> {
             let eh_frame_section = self.object.section(eh_frame_section_index)?;
             self.eh_frame_section = Some(eh_frame_section);
         }
-        if resources.symbol_db.args.output_kind == OutputKind::SharedObject {
+        if resources.symbol_db.args.output_kind == OutputKind::SharedObject
+            && resources.symbol_db.args.gc_keep_exported
+        {
             self.load_non_hidden_symbols(common, resources, queue)?;
         }
         self.load_sections(common, resources, queue)
@@ -2969,7 +4441,33 @@ impl<'data> ObjectLayoutState<'data> {
                         self.object.section_display_name(section_id),
                     );
                 }
+                &SectionSlot::Duplicate(duplicate) => {
+                    let Some(kept_section) = duplicate.kept_section else {
+                        bail!(
+                            "{self}: `{}` is referenced, but {}, which has no section with the \
+                             same name and size",
+                            self.object.section_display_name(section_id),
+                            crate::comdat::describe(
+                                &duplicate,
+                                resources.symbol_db.file(duplicate.kept_file)
+                            ),
+                        );
+                    };
+                    // References go to the kept copy. The kept copy has its own exception frames,
+                    // so we don't process ours.
+                    self.state.sections[section_id.0] = SectionSlot::Folded(FoldedSection {
+                        section: Section::create(self, section_id, duplicate.part_id)?,
+                        into_file: duplicate.kept_file,
+                        into_section: kept_section,
+                    });
+                    queue.send_work(
+                        resources,
+                        duplicate.kept_file,
+                        WorkItem::LoadSection(duplicate.kept_file, kept_section),
+                    );
+                }
                 SectionSlot::Loaded(_)
+                | SectionSlot::Folded(_)
                 | SectionSlot::EhFrameData(..)
                 | SectionSlot::LoadedDebugInfo(..) => {}
                 SectionSlot::MergeStrings(_) => {
@@ -2990,17 +4488,44 @@ impl<'data> ObjectLayoutState<'data> {
         resources: &GraphResources<'data, 'scope>,
     ) -> Result {
         let part_id = unloaded.part_id;
-        let section = Section::create(self, section_id, part_id)?;
+        let mut section = Section::create(self, section_id, part_id)?;
+        section.last_frame_index = unloaded.last_frame_index;
+        section.last_dependent = unloaded.last_dependent;
+        section.link_order = unloaded.link_order;
+        let header = self.object.section(section.index)?;
+        let mut relr_places = Vec::new();
+        let packs_relative_relocs =
+            resources.pack_relative_relocs && can_pack_relative_relocs(self.object, header)?;
+        let mut modifier = RelocationModifier::Normal;
         for rel in self.object.relocations(section.index)? {
-            process_relocation(
+            // The instruction that this relocation applied to was replaced by a relaxation of the
+            // previous relocation, so it doesn't reference anything.
+            if modifier == RelocationModifier::SkipNextRelocation {
+                modifier = RelocationModifier::Normal;
+                continue;
+            }
+            modifier = process_relocation(
                 self,
                 common,
                 rel,
-                self.object.section(section.index)?,
+                header,
                 resources,
                 queue,
+                packs_relative_relocs.then_some(&mut relr_places),
             )?;
         }
+        if !relr_places.is_empty() {
+            if let Some(array) = crate::ctors::LegacyArray::for_section(self.object, &section)? {
+                relr_places = relr_places
+                    .into_iter()
+                    .filter_map(|offset| array.output_offset(offset))
+                    .collect();
+            }
+            common.allocate(
+                part_id::RELR_DYN,
+                crate::relr::num_entries(&mut relr_places) * crate::relr::ENTRY_SIZE,
+            );
+        }
         tracing::debug!(loaded_section = %self.object.section_display_name(section_id),);
         common.allocate(part_id, section.capacity());
 
@@ -3013,6 +4538,18 @@ impl<'data> ObjectLayoutState<'data> {
 
         self.state.sections[section_id.0] = SectionSlot::Loaded(section);
 
+        // SHF_LINK_ORDER sections that are linked to us have metadata about us, so they're needed
+        // whenever we are.
+        let mut next_dependent = unloaded.last_dependent;
+        while let Some(dependent) = next_dependent {
+            next_dependent = self.state.sections[dependent.0]
+                .link_order()
+                .and_then(|link_order| link_order.previous_dependent);
+            self.state
+                .sections_required
+                .push(SectionRequest::new(dependent));
+        }
+
         Ok(())
     }
 
@@ -3040,7 +4577,15 @@ impl<'data> ObjectLayoutState<'data> {
             // section.
             if let Some(eh_frame_section) = self.eh_frame_section {
                 for rel in frame_data_relocations {
-                    process_relocation(self, common, rel, eh_frame_section, resources, queue)?;
+                    process_relocation(
+                        self,
+                        common,
+                        rel,
+                        eh_frame_section,
+                        resources,
+                        queue,
+                        None,
+                    )?;
                 }
             }
         }
@@ -3055,6 +4600,58 @@ impl<'data> ObjectLayoutState<'data> {
         Ok(())
     }
 
+    /// Replaces the loaded section at `fold.section_index` with a reference to an identical section,
+    /// releasing the space that we allocated for it and for its exception frames.
+    fn fold_section(
+        &mut self,
+        common: &mut CommonGroupState<'data>,
+        fold: &Fold,
+        args: &Args,
+    ) -> Result {
+        let SectionSlot::Loaded(section) = self.state.sections[fold.section_index.0] else {
+            bail!(
+                "Internal error: tried to fold {}, which isn't loaded",
+                self.object.section_display_name(fold.section_index)
+            );
+        };
+        *common.mem_sizes.get_mut(section.part_id) -= section.capacity();
+
+        let mut num_frames = 0;
+        let mut next_frame_index = section.last_frame_index;
+        while let Some(frame_index) = next_frame_index {
+            let frame_data = &self.exception_frames[frame_index.as_usize()];
+            self.eh_frame_size -= u64::from(frame_data.frame_size);
+            next_frame_index = frame_data.previous_frame_for_section;
+            num_frames += 1;
+        }
+        if args.should_write_eh_frame_hdr {
+            *common.mem_sizes.get_mut(part_id::EH_FRAME_HDR) -=
+                core::mem::size_of::<EhFrameHdrEntry>() as u64 * num_frames;
+        }
+
+        self.state.sections[fold.section_index.0] = SectionSlot::Folded(FoldedSection {
+            section,
+            into_file: fold.into_file,
+            into_section: fold.into_section,
+        });
+
+        // The metadata in SHF_LINK_ORDER sections linked to the folded section would duplicate that
+        // for the section that we folded into, so we discard them.
+        let mut next_dependent = section.last_dependent;
+        while let Some(dependent) = next_dependent {
+            let slot = &mut self.state.sections[dependent.0];
+            next_dependent = slot
+                .link_order()
+                .and_then(|link_order| link_order.previous_dependent);
+            if let SectionSlot::Loaded(dependent_section) = slot {
+                *common.mem_sizes.get_mut(dependent_section.part_id) -=
+                    dependent_section.capacity();
+                *slot = SectionSlot::Discard;
+            }
+        }
+        Ok(())
+    }
+
     fn load_debug_section(
         &mut self,
         common: &mut CommonGroupState<'data>,
@@ -3091,10 +4688,10 @@ impl<'data> ObjectLayoutState<'data> {
                 );
             }
         }
-        // TODO: Deduplicate CIEs from different objects, then only allocate space for those CIEs
-        // that we "won".
         for cie in &self.state.cies {
-            self.eh_frame_size += cie.cie.bytes.len() as u64;
+            if cie.duplicate_of.is_none() {
+                self.eh_frame_size += cie.cie.bytes.len() as u64;
+            }
         }
         common.allocate(part_id::EH_FRAME, self.eh_frame_size);
         Ok(())
@@ -3108,15 +4705,17 @@ impl<'data> ObjectLayoutState<'data> {
     ) -> Result {
         let _file_span = symbol_db.args.trace_span_for_file(self.file_id());
 
-        let mut num_locals = 0;
-        let mut num_globals = 0;
-        let mut strings_size = 0;
+        let mut shards = Vec::new();
+        let mut shard = SymtabShard::default();
         for ((sym_index, sym), sym_state) in self
             .object
             .symbols
             .enumerate()
             .zip(&symbol_resolution_flags[self.symbol_id_range().as_usize()])
         {
+            if sym_index.0 != 0 && sym_index.0 % SYMTAB_SHARD_SIZE == 0 {
+                shards.push(core::mem::take(&mut shard));
+            }
             let symbol_id = self.symbol_id_range.input_to_id(sym_index);
             if let Some(info) = SymbolCopyInfo::new(
                 self.object,
@@ -3131,17 +4730,32 @@ impl<'data> ObjectLayoutState<'data> {
                 // in a section we're emitting), then make sure we have a resolution for it.
                 sym_state.fetch_or(ResolutionFlags::DIRECT);
                 if sym.is_local() {
-                    num_locals += 1;
+                    shard.num_locals += 1;
                 } else {
-                    num_globals += 1;
+                    shard.num_globals += 1;
                 }
-                strings_size += info.name.len() + 1;
+                shard.strings_size += info.name.len() + 1;
             }
         }
+        let mut total = shard;
+        for s in &shards {
+            total.num_locals += s.num_locals;
+            total.num_globals += s.num_globals;
+            total.strings_size += s.strings_size;
+        }
+        // Only keep the per-shard sizes if there's more than one shard, so that the writer can
+        // split our part of .symtab and .strtab without needing to look at the symbols first.
+        if !shards.is_empty() {
+            shards.push(shard);
+            self.symtab_shards = shards;
+        }
         let entry_size = size_of::<elf::SymtabEntry>() as u64;
-        common.allocate(part_id::SYMTAB_LOCAL, num_locals * entry_size);
-        common.allocate(part_id::SYMTAB_GLOBAL, num_globals * entry_size);
-        common.allocate(part_id::STRTAB, strings_size as u64);
+        common.allocate(part_id::SYMTAB_LOCAL, total.num_locals as u64 * entry_size);
+        common.allocate(
+            part_id::SYMTAB_GLOBAL,
+            total.num_globals as u64 * entry_size,
+        );
+        common.allocate(part_id::STRTAB, total.strings_size as u64);
         Ok(())
     }
 
@@ -3156,10 +4770,17 @@ impl<'data> ObjectLayoutState<'data> {
 
         let mut emitter = create_global_address_emitter(resources.symbol_resolution_flags);
 
-        let mut section_resolutions = Vec::with_capacity(self.state.sections.len());
-        for slot in self.state.sections.iter_mut() {
-            let resolution = match slot {
+        let section_order =
+            section_placement_order(self.object, &self.state.sections, resources.symbol_db.args)?;
+        let mut section_resolutions = vec![SectionResolution::none(); self.state.sections.len()];
+        for index in placement_indexes(section_order.as_deref(), self.state.sections.len()) {
+            let resolution = match &mut self.state.sections[index] {
                 SectionSlot::Loaded(sec) => {
+                    // Hot sections were assigned addresses before any group was laid out.
+                    if let Some(address) = resources.hot_sections.address(self.file_id, sec.index) {
+                        section_resolutions[index] = SectionResolution { address };
+                        continue;
+                    }
                     let part_id = sec.part_id;
                     let address = *memory_offsets.get(part_id);
                     // TODO: We probably need to be able to handle sections that are ifuncs and sections
@@ -3181,7 +4802,7 @@ impl<'data> ObjectLayoutState<'data> {
                 }
                 _ => SectionResolution::none(),
             };
-            section_resolutions.push(resolution);
+            section_resolutions[index] = resolution;
         }
 
         for ((local_symbol_index, local_symbol), &resolution_flags) in self
@@ -3202,6 +4823,26 @@ impl<'data> ObjectLayoutState<'data> {
             )?;
         }
 
+        let mut cie_address = *memory_offsets.get(part_id::EH_FRAME);
+        let cies = self
+            .state
+            .cies
+            .iter()
+            .map(|cie| {
+                let placement = if let Some((file_id, index)) = cie.duplicate_of {
+                    CiePlacement::SameAs(file_id, index)
+                } else {
+                    let address = cie_address;
+                    cie_address += cie.cie.bytes.len() as u64;
+                    CiePlacement::Address(address)
+                };
+                CieLayout {
+                    input_offset: cie.offset,
+                    placement,
+                }
+            })
+            .collect();
+
         memory_offsets.increment(part_id::EH_FRAME, self.eh_frame_size);
 
         Ok(ObjectLayout {
@@ -3210,7 +4851,10 @@ impl<'data> ObjectLayoutState<'data> {
             object: self.object,
             sections: self.state.sections,
             section_resolutions,
+            section_order,
             symbol_id_range,
+            symtab_shards: self.symtab_shards,
+            cies,
         })
     }
 
@@ -3243,6 +4887,10 @@ impl<'data> ObjectLayoutState<'data> {
         {
             if let Some(section_address) = section_resolutions[section_index.0].address() {
                 local_symbol.st_value(e) + section_address
+            } else if let SectionSlot::Folded(_) = self.state.sections[section_index.0] {
+                // The section that we were folded into might not have an address yet, so this is
+                // updated by `update_folded_resolutions`.
+                local_symbol.st_value(e)
             } else {
                 get_merged_string_output_address(
                     local_symbol_index,
@@ -3293,6 +4941,46 @@ impl<'data> ObjectLayoutState<'data> {
         Ok(())
     }
 
+    fn export_live_symbols(
+        &self,
+        common: &mut CommonGroupState<'data>,
+        symbol_db: &SymbolDb<'data>,
+        symbol_resolution_flags: &[AtomicResolutionFlags],
+    ) -> Result {
+        for (sym_index, sym) in self.object.symbols.enumerate() {
+            if !can_export_symbol(sym) {
+                continue;
+            }
+            let symbol_id = self.symbol_id_range().input_to_id(sym_index);
+            if !symbol_db.is_canonical(symbol_id)
+                || symbol_db
+                    .local_symbol_value_flags(symbol_id)
+                    .contains(ValueFlags::DOWNGRADE_TO_LOCAL)
+            {
+                continue;
+            }
+            let flags = &symbol_resolution_flags[symbol_id.as_usize()];
+            let is_live = match self.object.symbol_section(sym, sym_index)? {
+                Some(section_index) => matches!(
+                    self.state.sections[section_index.0],
+                    SectionSlot::Loaded(_) | SectionSlot::Folded(_)
+                ),
+                None => sym.is_absolute(LittleEndian) || !flags.get().is_empty(),
+            };
+            if is_live
+                && !flags
+                    .fetch_or(ResolutionFlags::EXPORT_DYNAMIC)
+                    .contains(ResolutionFlags::EXPORT_DYNAMIC)
+            {
+                let name = symbol_db.symbol_name(symbol_id)?;
+                common
+                    .dynamic_symbol_definitions
+                    .push(DynamicSymbolDefinition::new(symbol_id, name.bytes()));
+            }
+        }
+        Ok(())
+    }
+
     fn load_non_hidden_symbols<'scope>(
         &mut self,
         common: &mut CommonGroupState<'data>,
@@ -3429,7 +5117,15 @@ fn process_eh_frame_data(
                 }
                 // We currently always load all CIEs, so any relocations found in CIEs always need
                 // to be processed.
-                process_relocation(object, common, rel, eh_frame_section, resources, queue)?;
+                process_relocation(
+                    object,
+                    common,
+                    rel,
+                    eh_frame_section,
+                    resources,
+                    queue,
+                    None,
+                )?;
                 if let Some(local_sym_index) = rel.symbol(e, false) {
                     let local_symbol_id = file_symbol_id_range.input_to_id(local_sym_index);
                     let definition = resources.symbol_db.definition(local_symbol_id);
@@ -3446,6 +5142,7 @@ fn process_eh_frame_data(
                     eligible_for_deduplication,
                     referenced_symbols,
                 },
+                duplicate_of: None,
             });
         } else {
             // This is an FDE
@@ -3506,11 +5203,30 @@ struct Cie<'data> {
 }
 
 struct CieAtOffset<'data> {
-    // TODO: Use or remove. I think we need this when we implement deduplication of CIEs.
     /// Offset within .eh_frame
-    #[allow(dead_code)]
     offset: u32,
     cie: Cie<'data>,
+
+    /// Set if the CIE is identical to one in an earlier object, which our FDEs will use instead.
+    duplicate_of: Option<(FileId, usize)>,
+}
+
+/// Where FDEs find a CIE from an object's `.eh_frame`.
+#[derive(Clone, Copy)]
+pub(crate) struct CieLayout {
+    /// The offset of the CIE within the input `.eh_frame`.
+    pub(crate) input_offset: u32,
+    pub(crate) placement: CiePlacement,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum CiePlacement {
+    /// We write the CIE at this address. An object's CIEs are written before its FDEs.
+    Address(u64),
+
+    /// The CIE is identical to the CIE at this index in the CIEs of an earlier file, so we don't
+    /// write it.
+    SameAs(FileId, usize),
 }
 
 #[derive(Clone, Copy)]
@@ -3583,37 +5299,52 @@ fn create_resolution(
         resolution_flags: res_kind,
         value_flags,
     };
+    if res_kind.contains(ResolutionFlags::LAZY_PLT) {
+        // An address of zero is valid input, e.g. with `--image-base=0`, but we use zero to mean
+        // that there's no PLT entry.
+        let plt_address = NonZeroU64::new(*memory_offsets.get(part_id::PLT))
+            .context("Cannot place a lazy PLT entry at address 0")?;
+        memory_offsets.increment(part_id::PLT, elf::PLT_ENTRY_SIZE);
+        memory_offsets.increment(part_id::GOT_PLT, elf::GOT_ENTRY_SIZE);
+        resolution.plt_address = Some(plt_address);
+        resolution.raw_value = plt_address.get();
+    }
     if res_kind.contains(ResolutionFlags::PLT) {
-        let plt_address = allocate_plt(memory_offsets);
+        let plt_address = allocate_plt(memory_offsets)?;
         resolution.plt_address = Some(plt_address);
         if value_flags.contains(ValueFlags::DYNAMIC) {
             resolution.raw_value = plt_address.get();
         }
-        resolution.got_address = Some(allocate_got(1, memory_offsets));
+        resolution.got_address = Some(allocate_got(1, memory_offsets)?);
     } else if res_kind.contains(ResolutionFlags::GOT) {
-        resolution.got_address = Some(allocate_got(1, memory_offsets));
+        resolution.got_address = Some(allocate_got(1, memory_offsets)?);
     } else if res_kind.contains(ResolutionFlags::GOT_TLS_OFFSET) {
         if res_kind.contains(ResolutionFlags::GOT_TLS_MODULE) {
-            resolution.got_address = Some(allocate_got(3, memory_offsets));
+            resolution.got_address = Some(allocate_got(3, memory_offsets)?);
         } else {
-            resolution.got_address = Some(allocate_got(1, memory_offsets));
+            resolution.got_address = Some(allocate_got(1, memory_offsets)?);
         }
     } else if res_kind.contains(ResolutionFlags::GOT_TLS_MODULE) {
-        resolution.got_address = Some(allocate_got(2, memory_offsets));
+        resolution.got_address = Some(allocate_got(2, memory_offsets)?);
     }
     Ok(resolution)
 }
 
-fn allocate_got(num_entries: u64, memory_offsets: &mut OutputSectionPartMap<u64>) -> NonZeroU64 {
-    let got_address = NonZeroU64::new(*memory_offsets.get(part_id::GOT)).unwrap();
+fn allocate_got(
+    num_entries: u64,
+    memory_offsets: &mut OutputSectionPartMap<u64>,
+) -> Result<NonZeroU64> {
+    let got_address = NonZeroU64::new(*memory_offsets.get(part_id::GOT))
+        .context("Cannot place a GOT entry at address 0")?;
     memory_offsets.increment(part_id::GOT, elf::GOT_ENTRY_SIZE * num_entries);
-    got_address
+    Ok(got_address)
 }
 
-fn allocate_plt(memory_offsets: &mut OutputSectionPartMap<u64>) -> NonZeroU64 {
-    let plt_address = NonZeroU64::new(*memory_offsets.get(part_id::PLT_GOT)).unwrap();
+fn allocate_plt(memory_offsets: &mut OutputSectionPartMap<u64>) -> Result<NonZeroU64> {
+    let plt_address = NonZeroU64::new(*memory_offsets.get(part_id::PLT_GOT))
+        .context("Cannot place a PLT entry at address 0")?;
     memory_offsets.increment(part_id::PLT_GOT, elf::PLT_ENTRY_SIZE);
-    plt_address
+    Ok(plt_address)
 }
 
 impl<'data> resolution::ResolvedFile<'data> {
@@ -3745,18 +5476,6 @@ pub(crate) fn get_merged_string_output_address(
     let data = merge_slot.section_data;
     let mut input_offset = symbol.st_value(LittleEndian);
 
-    let cache_entry = if let Some(section_cache) = string_offset_cache
-        .offsets_by_section
-        .get_mut(merge_slot.part_id.output_section_id().as_usize())
-    {
-        match section_cache.entry(input_offset) {
-            std::collections::hash_map::Entry::Occupied(entry) => return Ok(Some(*entry.get())),
-            std::collections::hash_map::Entry::Vacant(entry) => Some(entry),
-        }
-    } else {
-        None
-    };
-
     // When we reference data in a string-merge section via a named symbol, we determine which
     // string we're referencing without taking the addend into account, then apply the addend
     // afterward. However when the reference is to a section (a symbol without a name), we take the
@@ -3772,6 +5491,23 @@ pub(crate) fn get_merged_string_output_address(
         }
         input_offset = input_offset.wrapping_add(addend);
     }
+    let named_symbol_addend = if symbol_has_name { addend } else { 0 };
+
+    // Debug info references `.debug_str` almost entirely via the section symbol, so the key must
+    // include the addend for unnamed symbols. What we cache excludes the addend for named symbols.
+    let cache_entry = if let Some(section_cache) = string_offset_cache
+        .offsets_by_section
+        .get_mut(merge_slot.part_id.output_section_id().as_usize())
+    {
+        match section_cache.entry((section_index.0, input_offset)) {
+            std::collections::hash_map::Entry::Occupied(entry) => {
+                return Ok(Some(entry.get().wrapping_add(named_symbol_addend)));
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => Some(entry),
+        }
+    } else {
+        None
+    };
 
     if input_offset > data.len() as u64 {
         bail!(
@@ -3787,75 +5523,194 @@ pub(crate) fn get_merged_string_output_address(
         .get(&string)
         .with_context(|| format!("Failed to find merge-string `{}`", *string))?;
     let section_base = merged_string_start_addresses.addresses.get(section_id);
-    let mut address = section_base + output_offset;
-    if symbol_has_name {
-        address = address.wrapping_add(addend);
-    }
+    let address = section_base + output_offset;
     if let Some(cache_entry) = cache_entry {
         cache_entry.insert(address);
     }
-    Ok(Some(address))
+    Ok(Some(address.wrapping_add(named_symbol_addend)))
+}
+
+/// Returns the order in which the sections of `object` should be placed in the output, or `None` if
+/// they should be placed in input order. Only the relative order of sections that go into the same
+/// output section part has any effect. Ordering by name, then by content means that adding,
+/// removing or reordering functions within a source file causes fewer unrelated sections to move
+/// between successive builds, which helps binary-diff based patching.
+fn section_placement_order(
+    object: &File,
+    sections: &[SectionSlot],
+    args: &Args,
+) -> Result<Option<Vec<usize>>> {
+    if args.section_order == SectionOrder::Input {
+        return Ok(None);
+    }
+    let mut keys = Vec::with_capacity(sections.len());
+    for (index, slot) in sections.iter().enumerate() {
+        let key = match slot {
+            SectionSlot::Loaded(sec) | SectionSlot::LoadedDebugInfo(sec) => {
+                let section = object.section(sec.index)?;
+                let name = object.section_name(section)?;
+                let content_hash = crate::hash::hash_bytes(object.raw_section_data(section)?);
+                Some((name, content_hash))
+            }
+            _ => None,
+        };
+        keys.push((key, index));
+    }
+    // Sections that we're not placing are all keyed as `None`, so they stay in input order at the
+    // start, where they don't affect anything.
+    keys.sort();
+    Ok(Some(keys.into_iter().map(|(_, index)| index).collect()))
 }
 
+/// Returns indexes into the sections of an object in the order in which they should be placed.
+pub(crate) fn placement_indexes(
+    section_order: Option<&[usize]>,
+    num_sections: usize,
+) -> impl Iterator<Item = usize> + '_ {
+    match section_order {
+        Some(order) => itertools::Either::Left(order.iter().copied()),
+        None => itertools::Either::Right(0..num_sections),
+    }
+}
+
+/// Assigns file and memory offsets to each part of each section. Offsets are computed as u64 and
+/// we then check that the whole file fits in our address space, since on a 32 bit host an output
+/// file larger than 4GiB can't be mapped and our usize file offsets would have wrapped.
+///
+/// Sections that the user asked to start at a particular address are moved forward to that address.
+/// If that's part way through a segment, we pad the file by the same amount, since the whole
+/// segment gets mapped with a single offset.
 fn layout_section_parts(
     sizes: &OutputSectionPartMap<u64>,
     output_sections: &OutputSections,
-) -> OutputSectionPartMap<OutputRecordLayout> {
-    let mut file_offset = 0;
+) -> Result<OutputSectionPartMap<OutputRecordLayout>> {
+    let mut file_offset: u64 = 0;
     let mut mem_offset = output_sections.base_address;
     let mut current_seg_id = None;
+    let mut segment_mem_start = mem_offset;
+    let mut start_address_error = None;
     let mut nonalloc_mem_offsets: OutputSectionMap<u64> =
         OutputSectionMap::with_size(output_sections.num_sections());
 
-    sizes.output_order_map(output_sections, |part_id, section_alignment, part_size| {
-        let section_id = part_id.output_section_id();
-        let section_flags = output_sections.section_flags(section_id);
-        let mem_size = *part_size;
-        // Note, we align up even if our size is zero, otherwise our section will start at an
-        // unaligned address.
-        file_offset = section_alignment.align_up_usize(file_offset);
-
-        if section_flags.contains(shf::ALLOC) {
-            mem_offset = section_alignment.align_up(mem_offset);
-            let seg_id = output_sections.loadable_segment_id_for(section_id);
-            if current_seg_id != seg_id {
-                current_seg_id = seg_id;
-                let segment_alignment = seg_id.map(|s| s.alignment()).unwrap_or(alignment::MIN);
-                mem_offset = segment_alignment.align_modulo(file_offset as u64, mem_offset);
-            }
-            let file_size = if output_sections.has_data_in_file(section_id) {
-                mem_size as usize
-            } else {
-                0
-            };
-
-            let section_layout = OutputRecordLayout {
-                alignment: section_alignment,
-                file_offset,
-                mem_offset,
-                file_size,
-                mem_size,
-            };
-            file_offset += file_size;
-            mem_offset += mem_size;
-            section_layout
-        } else {
+    let layouts =
+        sizes.output_order_map(output_sections, |part_id, section_alignment, part_size| {
             let section_id = part_id.output_section_id();
-            let mem_offset = section_alignment.align_up(*nonalloc_mem_offsets.get(section_id));
-
-            *nonalloc_mem_offsets.get_mut(section_id) += mem_size;
-
-            let section_layout = OutputRecordLayout {
-                alignment: section_alignment,
-                file_offset,
-                mem_offset,
-                file_size: mem_size as usize,
-                mem_size,
-            };
-            file_offset += mem_size as usize;
-            section_layout
-        }
-    })
+            let section_flags = output_sections.section_flags(section_id);
+            let mem_size = *part_size;
+            // Note, we align up even if our size is zero, otherwise our section will start at an
+            // unaligned address.
+            file_offset = section_alignment.align_up(file_offset);
+
+            if section_flags.contains(shf::ALLOC) {
+                mem_offset = section_alignment.align_up(mem_offset);
+                let seg_id = output_sections.loadable_segment_id_for(section_id);
+                if current_seg_id != seg_id {
+                    // With `-z separate-code`, we start a new page in both the file and in memory
+                    // whenever we enter or leave the executable segment, so that nothing else gets
+                    // mapped executable. Otherwise, neighbouring segments can share a page.
+                    //
+                    // The segment for large data follows one that ends with NOBITS sections, so it
+                    // always needs to start on a new page, otherwise the file data that we map for
+                    // it would overwrite the end of the preceding NOBITS sections.
+                    if output_sections.separate_code
+                        && (current_seg_id == Some(crate::program_segments::LOAD_EXEC)
+                            || seg_id == Some(crate::program_segments::LOAD_EXEC))
+                        || seg_id == Some(crate::program_segments::LOAD_RW_LARGE)
+                    {
+                        file_offset = output_sections.page_size.align_up(file_offset);
+                        mem_offset = output_sections.page_size.align_up(mem_offset);
+                    }
+                    current_seg_id = seg_id;
+                    let segment_alignment = seg_id
+                        .map(|s| s.alignment(output_sections.page_size))
+                        .unwrap_or(alignment::MIN);
+                    mem_offset = segment_alignment.align_modulo(file_offset, mem_offset);
+                    segment_mem_start = mem_offset;
+                }
+                if let Some(address) = output_sections
+                    .start_address(section_id)
+                    .filter(|_| part_id == section_id.base_part_id())
+                {
+                    if address % section_alignment.value() != 0 {
+                        start_address_error.get_or_insert_with(|| {
+                            anyhow!(
+                                "Section `{}` can't start at 0x{address:x}, since it needs \
+                                 alignment 0x{:x}",
+                                output_sections.display_name(section_id),
+                                section_alignment.value()
+                            )
+                        });
+                    } else if address < mem_offset {
+                        start_address_error.get_or_insert_with(|| {
+                            anyhow!(
+                                "Section `{}` can't start at 0x{address:x}, since it would \
+                                 overlap earlier sections, which extend to 0x{mem_offset:x}",
+                                output_sections.display_name(section_id),
+                            )
+                        });
+                    } else if mem_offset == segment_mem_start {
+                        // Nothing's in this segment yet, so we can just start it at the requested
+                        // address.
+                        let segment_alignment = current_seg_id
+                            .map(|s| s.alignment(output_sections.page_size))
+                            .unwrap_or(alignment::MIN);
+                        file_offset = segment_alignment.align_modulo(address, file_offset);
+                        mem_offset = address;
+                        segment_mem_start = address;
+                    } else {
+                        // NOBITS sections come after everything in their segment that's in the
+                        // file, so the gap before them gets zero-filled without needing padding.
+                        if output_sections.has_data_in_file(section_id) {
+                            file_offset += address - mem_offset;
+                        }
+                        mem_offset = address;
+                    }
+                }
+                let file_size = if output_sections.has_data_in_file(section_id) {
+                    mem_size
+                } else {
+                    0
+                };
+
+                let section_layout = OutputRecordLayout {
+                    alignment: section_alignment,
+                    file_offset: file_offset as usize,
+                    mem_offset,
+                    file_size: file_size as usize,
+                    mem_size,
+                };
+                file_offset += file_size;
+                mem_offset += mem_size;
+                section_layout
+            } else {
+                let section_id = part_id.output_section_id();
+                let mem_offset = section_alignment.align_up(*nonalloc_mem_offsets.get(section_id));
+
+                *nonalloc_mem_offsets.get_mut(section_id) += mem_size;
+
+                let section_layout = OutputRecordLayout {
+                    alignment: section_alignment,
+                    file_offset: file_offset as usize,
+                    mem_offset,
+                    file_size: mem_size as usize,
+                    mem_size,
+                };
+                file_offset += mem_size;
+                section_layout
+            }
+        });
+    if let Some(error) = start_address_error {
+        return Err(error);
+    }
+    // Offsets only increase, so if the end of the file fits, then so did every offset and size
+    // that we truncated above.
+    if usize::try_from(file_offset).is_err() {
+        bail!(
+            "Output file would be {file_offset} bytes, which is too large to map into memory on \
+             this host"
+        );
+    }
+    Ok(layouts)
 }
 
 impl<'data> DynamicLayoutState<'data> {
@@ -3865,11 +5720,22 @@ impl<'data> DynamicLayoutState<'data> {
         resources: &GraphResources<'data, '_>,
         queue: &mut LocalWorkQueue,
     ) -> Result {
-        let dt_info = DynamicTagValues::read(self.object)?;
+        let dt_info = crate::elf::DynamicTagValues::read(self.object)?;
         self.symbol_versions_needed = vec![false; dt_info.verdefnum as usize];
         if let Some(soname) = dt_info.soname {
             self.lib_name = soname;
         }
+        if resources.pack_relative_relocs {
+            // Reference glibc's DT_RELR version, so that versions that don't support DT_RELR
+            // refuse to load us rather than silently not applying our relative relocations.
+            if let Some(version) = crate::relr::glibc_abi_version(self.object)? {
+                let needed = usize::from(version)
+                    .checked_sub(1)
+                    .and_then(|index| self.symbol_versions_needed.get_mut(index))
+                    .context("Invalid version index")?;
+                *needed = true;
+            }
+        }
         common.allocate(
             part_id::DYNAMIC,
             core::mem::size_of::<crate::elf::DynamicEntry>() as u64,
@@ -3878,13 +5744,40 @@ impl<'data> DynamicLayoutState<'data> {
         self.request_all_undefined_symbols(common, resources, queue)
     }
 
-    fn export_copy_relocation(
+    fn export_definition<'scope>(
         &mut self,
         common: &mut CommonGroupState<'data>,
         symbol_id: SymbolId,
-        graph_resources: &GraphResources<'data, '_>,
+        resources: &GraphResources<'data, 'scope>,
+        queue: &mut LocalWorkQueue,
     ) -> Result {
-        export_dynamic(common, symbol_id, graph_resources)
+        export_dynamic(common, symbol_id, resources)?;
+        if !resources.symbol_resolution_flags[symbol_id.as_usize()]
+            .get()
+            .contains(ResolutionFlags::COPY_RELOCATION)
+        {
+            return Ok(());
+        }
+        // Aliases need to refer to the same copy. Whoever first sets the flag on an alias is
+        // responsible for exporting it, so if that was a reference from some other object, it'll
+        // have sent us a separate request.
+        for alias_index in
+            copy_relocation_aliases(self.object, symbol_id.to_input(self.symbol_id_range))?
+        {
+            let alias = self.symbol_id_range.input_to_id(alias_index);
+            if alias == symbol_id || !resources.symbol_db.is_canonical(alias) {
+                continue;
+            }
+            let old_flags = resources.symbol_resolution_flags[alias.as_usize()]
+                .fetch_or(ResolutionFlags::DIRECT | ResolutionFlags::COPY_RELOCATION);
+            if old_flags.is_empty() {
+                self.load_symbol(common, alias, resources, queue)?;
+            }
+            if !old_flags.contains(ResolutionFlags::COPY_RELOCATION) {
+                export_dynamic(common, alias, resources)?;
+            }
+        }
+        Ok(())
     }
 
     fn request_all_undefined_symbols(
@@ -4000,11 +5893,12 @@ impl<'data> DynamicLayoutState<'data> {
         resources: &FinaliseLayoutResources,
     ) -> Result<DynamicLayout<'data>> {
         let version_mapping = self.compute_version_mapping();
+        let mut copy_addresses = FxHashMap::default();
 
-        for (local_symbol, &resolution_flags) in self
-            .object
-            .symbols
-            .iter()
+        for ((symbol_id, local_symbol), &resolution_flags) in self
+            .symbol_id_range()
+            .into_iter()
+            .zip(self.object.symbols.iter())
             .zip(&resources.symbol_resolution_flags[self.symbol_id_range().as_usize()])
         {
             if resolution_flags.is_empty() {
@@ -4016,11 +5910,31 @@ impl<'data> DynamicLayoutState<'data> {
             let address;
             let dynamic_symbol_index;
             if needs_copy_relocation {
-                address =
-                    assign_copy_relocation_address(self.object, local_symbol, memory_offsets)?;
+                let owner = copy_relocation_owner(
+                    self.object,
+                    self.symbol_id_range,
+                    resources.symbol_db,
+                    symbol_id,
+                )?;
+                // The owner is the first alias, so has already been assigned an address if it's
+                // not this symbol.
+                address = if owner == symbol_id {
+                    let address =
+                        assign_copy_relocation_address(self.object, local_symbol, memory_offsets)?;
+                    copy_addresses.insert(symbol_id, address);
+                    address
+                } else {
+                    *copy_addresses
+                        .get(&owner)
+                        .context("Internal error: Copy relocation alias before its owner")?
+                };
                 // Since this is a definition, the dynamic symbol index will be determined by the
                 // epilogue and set by `update_dynamic_symbol_resolutions`.
                 dynamic_symbol_index = None;
+            } else if resolution_flags.contains(ResolutionFlags::CANONICAL_PLT) {
+                // As for copy relocations, we define the symbol, so the epilogue owns its index.
+                address = 0;
+                dynamic_symbol_index = None;
             } else {
                 address = 0;
                 let symbol_index = take_dynsym_index(memory_offsets, resources.section_layouts)?;
@@ -4092,40 +6006,6 @@ fn assign_copy_relocation_address(
     Ok(a)
 }
 
-#[derive(Default)]
-struct DynamicTagValues<'data> {
-    verdefnum: u64,
-    soname: Option<&'data [u8]>,
-}
-
-impl<'data> DynamicTagValues<'data> {
-    fn read(file: &File<'data>) -> Result<Self> {
-        let mut values = DynamicTagValues::default();
-        let Ok(dynamic_tags) = file.dynamic_tags() else {
-            return Ok(values);
-        };
-        let e = LittleEndian;
-        for entry in dynamic_tags {
-            let value = entry.d_val(e);
-            match entry.d_tag(e) as u32 {
-                object::elf::DT_VERDEFNUM => {
-                    values.verdefnum = value;
-                }
-                object::elf::DT_SONAME => {
-                    values.soname = Some(
-                        file.symbols
-                            .strings()
-                            .get(value as u32)
-                            .map_err(|()| anyhow!("Invalid DT_SONAME 0x{value:x}"))?,
-                    );
-                }
-                _ => {}
-            }
-        }
-        Ok(values)
-    }
-}
-
 fn take_dynsym_index(
     memory_offsets: &mut OutputSectionPartMap<u64>,
     section_layouts: &OutputSectionMap<OutputRecordLayout>,
@@ -4158,6 +6038,49 @@ impl<'data> std::fmt::Debug for FileLayoutState<'data> {
     }
 }
 
+/// Prints each input file that we loaded, for `-t`. Linker scripts are printed as we read them,
+/// since they don't make it this far. Archive members that weren't needed aren't printed.
+fn print_loaded_files(groups: &[resolution::ResolvedGroup]) {
+    for file in groups.iter().flat_map(|group| &group.files) {
+        if let resolution::ResolvedFile::Object(obj) = file {
+            println!("{}", crate::map_file::display_input(&obj.input));
+        }
+    }
+}
+
+/// Prints each definition of and reference to the symbols named by `-y`, in the files that we
+/// loaded, in the same format as GNU ld. Unlike GNU ld, which reports symbols as it reads each
+/// file, we scan once resolution has finished. This means that we only report the files that ended
+/// up in the link, so archive members that were never loaded aren't mentioned, and that we report
+/// files in input order rather than the order in which they were loaded.
+fn print_traced_symbols(groups: &[resolution::ResolvedGroup], names: &[String]) -> Result {
+    let names: std::collections::HashSet<&[u8]> =
+        names.iter().map(|name| name.as_bytes()).collect();
+    for file in groups.iter().flat_map(|group| &group.files) {
+        let resolution::ResolvedFile::Object(obj) = file else {
+            continue;
+        };
+        for symbol in obj.object.symbols.iter().skip(1) {
+            if symbol.is_local() {
+                continue;
+            }
+            let name = obj.object.symbol_name(symbol)?;
+            if !names.contains(name) {
+                continue;
+            }
+            let kind = if symbol.is_undefined(LittleEndian) {
+                "reference to"
+            } else if symbol.is_weak() {
+                "weak definition of"
+            } else {
+                "definition of"
+            };
+            println!("{}: {kind} {}", obj.input, String::from_utf8_lossy(name));
+        }
+    }
+    Ok(())
+}
+
 fn print_symbol_info(symbol_db: &SymbolDb, name: &str) {
     if let Some(symbol_id) = symbol_db
         .global_names
@@ -4223,6 +6146,30 @@ impl Display for SectionDebug {
 }
 
 impl GnuHashLayout {
+    fn new(num_defs: usize, args: &Args) -> Self {
+        // By default, we aim for an average of 2 symbols per bucket. The runtime loader finds the
+        // bucket with a modulo, so the count doesn't need to be a power of two, and an odd count
+        // spreads the hashes better.
+        let bucket_count = args.hash_size.unwrap_or((num_defs / 2) as u32 | 1);
+        // The bloom filter lets the loader reject most symbols that we don't define without
+        // looking at the buckets. We set 2 bits per symbol, so allowing ~12 bits per symbol keeps
+        // the false-positive rate low. The number of words must be a power of two.
+        let bloom_count = args.hash_bloom_words.unwrap_or_else(|| {
+            (num_defs * 12 / u64::BITS as usize)
+                .max(1)
+                .next_power_of_two() as u32
+        });
+        Self {
+            bucket_count,
+            // Using a shift that's larger than log2 of the word size means that the second bit
+            // that we set is independent of the first.
+            bloom_shift: 26,
+            bloom_count,
+            // `symbol_base` is set later in `finalise_layout`.
+            symbol_base: 0,
+        }
+    }
+
     pub(crate) fn bucket_for_hash(&self, hash: u32) -> u32 {
         hash % self.bucket_count
     }
@@ -4249,7 +6196,7 @@ fn test_no_disallowed_overlaps() {
             .build()
             .unwrap();
     let section_part_sizes = output_sections.new_part_map::<u64>().map(|_, _| 7);
-    let section_part_layouts = layout_section_parts(&section_part_sizes, &output_sections);
+    let section_part_layouts = layout_section_parts(&section_part_sizes, &output_sections).unwrap();
     let section_layouts = layout_sections(&section_part_layouts);
 
     // Make sure no alloc sections overlap
@@ -4305,8 +6252,13 @@ fn test_no_disallowed_overlaps() {
         }
     }
 
-    let segment_layouts =
-        compute_segment_layout(&section_layouts, &output_sections, &header_info).unwrap();
+    let segment_layouts = compute_segment_layout(
+        &section_layouts,
+        &output_sections,
+        &header_info,
+        alignment::PAGE,
+    )
+    .unwrap();
 
     // Make sure loadable segments don't overlap in memory or in the file.
     let mut last_file = 0;
@@ -4340,9 +6292,9 @@ impl Display for ResolutionFlags {
 }
 
 pub(crate) struct StringOffsetCache {
-    /// For each output section, a map from input offset to output offset. Empty if caching is
-    /// disabled.
-    offsets_by_section: Vec<FxHashMap<u64, u64>>,
+    /// For each output section, a map from input section index and offset within that section to
+    /// output address. Empty if caching is disabled.
+    offsets_by_section: Vec<FxHashMap<(usize, u64), u64>>,
 }
 
 impl StringOffsetCache {
@@ -4408,6 +6360,7 @@ fn test_resolution_allocation_consistency() -> Result {
                 // non-zero values.
                 *memory_offsets.get_mut(part_id::GOT) = 0x10;
                 *memory_offsets.get_mut(part_id::PLT_GOT) = 0x10;
+                *memory_offsets.get_mut(part_id::PLT) = 0x10;
 
                 let has_dynamic_symbol = value_flags.contains(ValueFlags::DYNAMIC)
                     || (resolution_flags.contains(ResolutionFlags::EXPORT_DYNAMIC)
@@ -4519,7 +6472,19 @@ fn are_flags_valid(
         && (!resolution_flags.contains(ResolutionFlags::DIRECT)
             || !value_flags.contains(ValueFlags::DYNAMIC)
             || value_flags.contains(ValueFlags::FUNCTION)
-            || resolution_flags.contains(ResolutionFlags::PLT))
+            || resolution_flags.intersects(ResolutionFlags::PLT | ResolutionFlags::LAZY_PLT))
+    {
+        return false;
+    }
+    if resolution_flags.contains(ResolutionFlags::LAZY_PLT)
+        && !value_flags.contains(ValueFlags::DYNAMIC)
+    {
+        return false;
+    }
+    if resolution_flags.contains(ResolutionFlags::CANONICAL_PLT)
+        && (!resolution_flags.contains(ResolutionFlags::PLT)
+            || !value_flags.contains(ValueFlags::DYNAMIC)
+            || !output_kind.is_executable())
     {
         return false;
     }
@@ -4536,3 +6501,187 @@ fn are_flags_valid(
     }
     true
 }
+
+/// Makes sure that file offsets past 4GiB are computed correctly, as happens with very large debug
+/// info. On a 32 bit host, such a layout should be rejected rather than wrapping.
+#[test]
+fn test_large_file_offsets() {
+    let output_sections =
+        crate::output_section_id::OutputSectionsBuilder::with_base_address(0x1000)
+            .build()
+            .unwrap();
+    let part_size = 1_u64 << 31;
+    let section_part_sizes = output_sections.new_part_map::<u64>().map(|_, _| part_size);
+    let result = layout_section_parts(&section_part_sizes, &output_sections);
+    if usize::BITS < 64 {
+        assert!(result.is_err());
+        return;
+    }
+    let section_layouts = layout_sections(&result.unwrap());
+    let file_size = compute_total_file_size(&section_layouts);
+    assert!(file_size > u64::from(u32::MAX));
+    let strtab = section_layouts.get(output_section_id::STRTAB);
+    assert!(strtab.file_offset as u64 > u64::from(u32::MAX));
+    assert_eq!(strtab.file_size as u64, part_size);
+
+    assert!(check_string_table_sizes(&section_part_sizes).is_ok());
+    let too_big = section_part_sizes.map(|_, _| 1_u64 << 32);
+    assert!(check_string_table_sizes(&too_big).is_err());
+}
+
+/// Sections can be placed at address 0, e.g. with `--section-start`. Since a zero address means
+/// that there's no PLT or GOT entry, we report an error rather than panicking.
+#[test]
+fn test_resolution_at_address_zero() {
+    let output_sections =
+        crate::output_section_id::OutputSectionsBuilder::with_base_address(0x1000)
+            .build()
+            .unwrap();
+    let mut memory_offsets = output_sections.new_part_map::<u64>();
+    for flags in [
+        ResolutionFlags::LAZY_PLT,
+        ResolutionFlags::PLT,
+        ResolutionFlags::GOT,
+    ] {
+        assert!(create_resolution(
+            flags,
+            0,
+            None,
+            ValueFlags::DYNAMIC,
+            &mut memory_offsets.clone()
+        )
+        .is_err());
+    }
+    *memory_offsets.get_mut(part_id::PLT) = 0x2000;
+    let resolution = create_resolution(
+        ResolutionFlags::LAZY_PLT,
+        0,
+        None,
+        ValueFlags::DYNAMIC,
+        &mut memory_offsets,
+    )
+    .unwrap();
+    assert_eq!(resolution.raw_value, 0x2000);
+}
+
+#[test]
+fn test_init_priority() {
+    assert_eq!(init_priority(b".init_array.101"), Some(101));
+    assert_eq!(init_priority(b".fini_array.00200"), Some(200));
+    assert_eq!(init_priority(b".ctors.65434"), Some(101));
+    assert_eq!(init_priority(b".dtors.65535"), Some(0));
+    assert_eq!(init_priority(b".init_array"), None);
+    assert_eq!(init_priority(b".init_array."), None);
+    assert_eq!(init_priority(b".init_array.foo"), None);
+    assert_eq!(init_priority(b".ctors"), None);
+}
+
+/// Two string-merge sections from the same object that go into the same output section can each
+/// have a string at the same offset. Lookups via their section symbols must not share a cache
+/// entry.
+#[test]
+fn test_merged_strings_at_same_offset_in_different_sections() {
+    use object::read::elf::SectionHeader as _;
+    use object::write;
+
+    let mut builder = write::Object::new(
+        object::BinaryFormat::Elf,
+        object::Architecture::X86_64,
+        object::Endianness::Little,
+    );
+    let mut add_strings = |contents: &[u8]| {
+        let section = builder.add_section(
+            Vec::new(),
+            b".rodata.str1.1".to_vec(),
+            object::SectionKind::ReadOnlyString,
+        );
+        builder.append_section_data(section, contents, 1);
+        builder.section_symbol(section);
+        section
+    };
+    add_strings(b"first\0");
+    let second_section = add_strings(b"second\0");
+    builder.add_symbol(write::Symbol {
+        name: b"second_str".to_vec(),
+        value: 0,
+        size: 7,
+        kind: object::SymbolKind::Data,
+        scope: object::SymbolScope::Compilation,
+        weak: false,
+        section: write::SymbolSection::Section(second_section),
+        flags: object::SymbolFlags::None,
+    });
+    let data = builder.write().unwrap();
+    let object = File::parse(&data, false).unwrap();
+
+    let part_id = output_section_id::RODATA.part_id_with_alignment(alignment::MIN);
+    let sections = object
+        .sections
+        .iter()
+        .map(|section| {
+            if section.sh_type(LittleEndian) == object::elf::SHT_PROGBITS {
+                SectionSlot::MergeStrings(resolution::MergeStringsFileSection {
+                    part_id,
+                    section_data: section.data(LittleEndian, &*data).unwrap(),
+                })
+            } else {
+                SectionSlot::Discard
+            }
+        })
+        .collect_vec();
+
+    let output_sections =
+        crate::output_section_id::OutputSectionsBuilder::with_base_address(0x1000)
+            .build()
+            .unwrap();
+    let mut merged_strings = output_sections.new_section_map::<resolution::MergeStringsSection>();
+    let merged = merged_strings.get_mut(output_section_id::RODATA);
+    let mut bucket = resolution::MergeStringsSectionBucket::default();
+    for (string, offset) in [(b"first\0".as_slice(), 0), (b"second\0", 6)] {
+        let string = resolution::StringToMerge::take_hashed(&mut &*string).unwrap();
+        bucket.string_offsets.insert(string, offset);
+    }
+    merged.buckets.push(bucket);
+    merged.bucket_offsets.push(0);
+    let mut start_addresses = MergedStringStartAddresses {
+        addresses: output_sections.new_section_map(),
+    };
+    *start_addresses.addresses.get_mut(output_section_id::RODATA) = 0x2000;
+
+    let section_symbols = object
+        .symbols
+        .enumerate()
+        .filter(|(_, symbol)| symbol.st_type() == object::elf::STT_SECTION)
+        .map(|(index, _)| index)
+        .collect_vec();
+    let [first, second] = section_symbols[..] else {
+        panic!("Expected two section symbols, got {section_symbols:?}");
+    };
+    let (named_symbol, _) = object
+        .symbols
+        .enumerate()
+        .find(|(_, symbol)| symbol.st_name(LittleEndian) != 0)
+        .unwrap();
+
+    let mut cache = StringOffsetCache::new(&output_sections);
+    let mut lookup = |symbol_index, addend| {
+        get_merged_string_output_address(
+            symbol_index,
+            addend,
+            &object,
+            &sections,
+            &merged_strings,
+            &start_addresses,
+            false,
+            &mut cache,
+        )
+        .unwrap()
+    };
+    assert_eq!(lookup(first, 0), Some(0x2000));
+    assert_eq!(lookup(second, 0), Some(0x2006));
+    assert_eq!(lookup(named_symbol, 2), Some(0x2008));
+    // Repeat the lookups, now that they're cached.
+    assert_eq!(lookup(first, 0), Some(0x2000));
+    assert_eq!(lookup(named_symbol, 3), Some(0x2009));
+    assert_eq!(lookup(second, 0), Some(0x2006));
+}
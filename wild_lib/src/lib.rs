@@ -12,6 +12,7 @@
 // I might add additional fields later, so why make me remove ..Default::default()?
 #![allow(clippy::needless_update)]
 
+use anyhow::Context as _;
 use args::Args;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
@@ -22,35 +23,66 @@ pub(crate) mod alignment;
 pub(crate) mod archive;
 pub(crate) mod archive_splitter;
 pub mod args;
+pub(crate) mod build_id;
+pub(crate) mod call_graph_sort;
+pub(crate) mod comdat;
+pub(crate) mod ctf;
+pub(crate) mod ctors;
 pub(crate) mod debug_trace;
+pub(crate) mod demangle;
+pub(crate) mod dependency_file;
+pub(crate) mod diagnostics;
 pub(crate) mod diff;
+pub(crate) mod duplicate_symbols;
 pub(crate) mod elf;
 pub(crate) mod elf_writer;
 pub mod error;
 pub(crate) mod file_kind;
 pub(crate) mod fs;
 pub(crate) mod gc_stats;
+pub(crate) mod gnu_property;
 pub(crate) mod grouping;
 pub(crate) mod hash;
+pub(crate) mod icf;
 pub(crate) mod identity;
 pub(crate) mod input_data;
+pub(crate) mod io_probe;
+pub(crate) mod json;
 pub(crate) mod layout;
+pub(crate) mod linker_plugin;
 pub(crate) mod linker_script;
+pub(crate) mod llvm_lto;
+pub(crate) mod map_file;
+pub(crate) mod needed_libs;
 pub(crate) mod output_section_id;
 pub(crate) mod output_section_map;
 pub(crate) mod output_section_part_map;
+pub(crate) mod output_summary;
 pub(crate) mod output_trace;
 pub(crate) mod parsing;
 pub(crate) mod part_id;
+pub mod post_layout;
+pub(crate) mod preemptibility;
 pub(crate) mod program_segments;
+pub(crate) mod query;
 pub(crate) mod relaxation;
+pub(crate) mod relr;
+pub(crate) mod reproduce;
 pub(crate) mod resolution;
 pub(crate) mod save_dir;
+pub(crate) mod section_permissions;
+pub mod section_transform;
+pub(crate) mod separate_debug;
+pub(crate) mod sframe;
 pub(crate) mod sharding;
 pub(crate) mod shutdown;
 pub(crate) mod slice;
+pub(crate) mod source_location;
+pub(crate) mod stamp;
+pub(crate) mod stats;
 pub(crate) mod symbol;
 pub(crate) mod symbol_db;
+pub(crate) mod symbols_only;
 #[cfg(not(feature = "single-threaded"))]
 #[path = "threading_rayon.rs"]
 pub(crate) mod threading;
@@ -58,20 +90,50 @@ pub(crate) mod threading;
 #[path = "threading_none.rs"]
 pub(crate) mod threading;
 pub(crate) mod timing;
+pub(crate) mod undefined_symbols;
 pub(crate) mod validation;
 pub(crate) mod verification;
+pub(crate) mod why_extract;
 
 pub struct Linker {
     action: crate::args::Action,
+    section_transforms: Vec<Box<dyn section_transform::SectionTransform>>,
+    post_layout_hooks: Vec<Box<dyn post_layout::PostLayoutHook>>,
 }
 
 impl Linker {
     pub fn from_env() -> crate::error::Result<Self> {
         Ok(Linker {
             action: crate::args::from_env()?,
+            section_transforms: Vec::new(),
+            post_layout_hooks: Vec::new(),
         })
     }
 
+    /// Creates a linker from the supplied arguments, which shouldn't include the program name.
+    pub fn from_args<S: AsRef<str>, I: Iterator<Item = S>>(args: I) -> crate::error::Result<Self> {
+        Ok(Linker {
+            action: crate::args::parse(args)?,
+            section_transforms: Vec::new(),
+            post_layout_hooks: Vec::new(),
+        })
+    }
+
+    /// Adds a callback that can observe or rewrite the contents of input sections before layout.
+    /// See `section_transform` for details.
+    pub fn add_section_transform(
+        &mut self,
+        transform: impl section_transform::SectionTransform + 'static,
+    ) {
+        self.section_transforms.push(Box::new(transform));
+    }
+
+    /// Adds a callback that can patch the output after layout, before it's finalised. See
+    /// `post_layout` for details.
+    pub fn add_post_layout_hook(&mut self, hook: impl post_layout::PostLayoutHook + 'static) {
+        self.post_layout_hooks.push(Box::new(hook));
+    }
+
     pub fn run(&self) -> crate::error::Result {
         match &self.action {
             args::Action::Link(args) => {
@@ -82,12 +144,13 @@ impl Linker {
                 } else if args.print_allocations.is_some() {
                     debug_trace::init();
                 } else {
-                    tracing_subscriber::registry()
+                    // If we've already been run in this process, tracing is already set up.
+                    let _ = tracing_subscriber::registry()
                         .with(fmt::layer())
                         .with(EnvFilter::from_default_env())
-                        .init();
+                        .try_init();
                 }
-                link(args)
+                link(args, &self.section_transforms, &self.post_layout_hooks)
             }
             args::Action::Version => {
                 println!(
@@ -96,31 +159,122 @@ impl Linker {
                 );
                 Ok(())
             }
+            args::Action::QueryDefines(query) => query.run(),
+        }
+    }
+
+    /// Prints `error`, which should have come from `run`, in the way that our arguments asked for
+    /// diagnostics to be presented.
+    pub fn report_error(&self, error: &error::Error) {
+        match &self.action {
+            args::Action::Link(args) => args.diagnostics.report(error),
+            _ => error::report(error),
         }
     }
 }
 
 #[tracing::instrument(skip_all, name = "Link")]
-fn link(args: &Args) -> crate::error::Result {
-    args.setup_thread_pool()?;
-    let mut output = elf_writer::Output::new(args);
-    let input_data = input_data::InputData::from_args(args)?;
-    let inputs = archive_splitter::split_archives(&input_data)?;
+fn link(
+    args: &Args,
+    section_transforms: &[Box<dyn section_transform::SectionTransform>],
+    post_layout_hooks: &[Box<dyn post_layout::PostLayoutHook>],
+) -> crate::error::Result {
+    args.diagnostics.reset_warnings();
+    args.setup_thread_pool();
+    file_kind::check_emulation(args.emulation)?;
+    let mut stats = stats::Stats::new(args);
+    // If we might reuse the existing output, then we can't start deleting it until we've checked
+    // whether anything changed.
+    let output = args
+        .skip_if_unchanged
+        .is_none()
+        .then(|| elf_writer::Output::new(args));
+    let mut input_data = input_data::InputData::from_args(args)?;
+    stats.phase_done("Open input files");
+    if let Some(reproduce) = args.reproduce.as_deref() {
+        reproduce::write(reproduce, args, &input_data)?;
+    }
+    let stamp = if args.skip_if_unchanged.is_some() {
+        let stamp = stamp::Stamp::compute(args, &input_data)?;
+        if stamp.is_up_to_date(args) {
+            // The dependency file isn't covered by the stamp, so that it's always rewritten.
+            if let Some(dependency_file) = args.dependency_file.as_deref() {
+                dependency_file::write(dependency_file, args, &input_data)?;
+            }
+            return Ok(());
+        }
+        Some(stamp)
+    } else {
+        None
+    };
+    let mut output = output.unwrap_or_else(|| elf_writer::Output::new(args));
+    // Holds copies of any input objects whose sections get edited.
+    let transformed_inputs = bumpalo_herd::Herd::new();
+    let mut inputs = archive_splitter::split_archives(&input_data)?;
+    stats.phase_done("Split archives");
+    let lto_outputs = linker_plugin::run(args, &input_data, &inputs)?;
+    if let Some(lto_outputs) = &lto_outputs {
+        drop(inputs);
+        lto_outputs.add_to(&mut input_data)?;
+        inputs = archive_splitter::split_archives(&input_data)?;
+        lto_outputs.remove_claimed(&mut inputs);
+        stats.phase_done("Link-time optimisation");
+    }
+    if !section_transforms.is_empty() {
+        section_transform::apply(section_transforms, &mut inputs, &transformed_inputs)?;
+        stats.phase_done("Transform sections");
+    }
     let files = parsing::parse_input_files(&inputs, args)?;
+    stats.phase_done("Parse input files");
     let groups = grouping::group_files(files, args);
     let mut symbol_db =
         symbol_db::SymbolDb::build(&groups, input_data.version_script_data.as_ref(), args)?;
+    stats.phase_done("Build symbol DB");
     let herd = bumpalo_herd::Herd::new();
     let resolved = resolution::resolve_symbols_and_sections(&groups, &mut symbol_db, &herd)?;
+    stats.phase_done("Symbol resolution");
     let layout = layout::compute(&symbol_db, resolved, &mut output)?;
-    let output_file = output.write(&layout)?;
+    stats.phase_done("Layout");
+    let num_warnings = args.diagnostics.num_warnings();
+    if args.fatal_warnings && num_warnings > 0 {
+        output.discard();
+        anyhow::bail!("{num_warnings} warning(s) issued and --fatal-warnings was given");
+    }
+    if let Some(map_path) = args.map_file.as_deref() {
+        map_file::write(&layout, map_path)?;
+    }
+    if let Some(why_extract_path) = args.why_extract.as_deref() {
+        why_extract::write(&layout, why_extract_path)?;
+    }
+    let output_file = if args.emit_symbols_only {
+        symbols_only::write(&layout)?;
+        None
+    } else {
+        Some(output.write(&layout, post_layout_hooks)?)
+    };
+    stats.phase_done("Write output");
     diff::maybe_diff()?;
+    if let Some(dependency_file) = args.dependency_file.as_deref() {
+        dependency_file::write(dependency_file, args, &input_data)?;
+    }
+    let output_size = output_file
+        .is_some()
+        .then(|| std::fs::metadata(&args.output).map(|metadata| metadata.len()))
+        .transpose()
+        .with_context(|| format!("Failed to stat `{}`", args.output.display()))?;
+    stats.report(&layout, &input_data, output_size)?;
+    if let Some(stamp) = stamp {
+        stamp.write(args)?;
+    }
 
     let scope = tracing::info_span!("Shutdown");
     let _scope = scope.enter();
-    shutdown::free_output(output_file);
+    if let Some(output_file) = output_file {
+        shutdown::free_output(output_file);
+    }
     shutdown::free_layout(layout);
     shutdown::free_symbol_db(symbol_db);
+    drop(lto_outputs);
     shutdown::free_input_data(input_data);
     Ok(())
 }
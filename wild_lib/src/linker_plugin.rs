@@ -0,0 +1,1284 @@
+//! Support for the linker plugin interface that GNU ld and gold provide, which is how GCC and LLVM
+//! do link-time optimisation. See https://gcc.gnu.org/wiki/whopr/driver.
+//!
+//! A plugin claims the input files that contain compiler IR and tells us what symbols each of them
+//! defines and references. We stand in a symbol-only object for each claimed file and resolve
+//! symbols as usual, which tells us which archive members get loaded and which definitions prevail.
+//! Once we've passed those resolutions back, the plugin compiles the IR and gives us replacement
+//! objects, which we then link instead of the files that it claimed.
+//!
+//! The plugin's callbacks don't get passed any context, so the state that they need is global.
+
+use crate::archive_splitter::InputBytes;
+use crate::args::Args;
+use crate::args::Input;
+use crate::args::InputSpec;
+use crate::args::OutputKind;
+use crate::args::RelocationModel;
+use crate::error::Diagnostics;
+use crate::error::Result;
+use crate::file_kind::FileKind;
+use crate::file_kind::IrKind;
+use crate::input_data::InputData;
+use crate::parsing::ParsedInput;
+use crate::resolution::ResolvedFile;
+use crate::symbol_db::SymbolDb;
+use crate::threading::prelude::*;
+use crate::warning;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context as _;
+use libc::c_char;
+use libc::c_int;
+use libc::c_void;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::fd::AsRawFd as _;
+use std::os::unix::ffi::OsStrExt as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+// Tags for the entries of the transfer vector that we pass to the plugin's `onload`.
+const LDPT_NULL: c_int = 0;
+const LDPT_API_VERSION: c_int = 1;
+const LDPT_LINKER_OUTPUT: c_int = 3;
+const LDPT_OPTION: c_int = 4;
+const LDPT_REGISTER_CLAIM_FILE_HOOK: c_int = 5;
+const LDPT_REGISTER_ALL_SYMBOLS_READ_HOOK: c_int = 6;
+const LDPT_REGISTER_CLEANUP_HOOK: c_int = 7;
+const LDPT_ADD_SYMBOLS: c_int = 8;
+const LDPT_GET_SYMBOLS: c_int = 9;
+const LDPT_ADD_INPUT_FILE: c_int = 10;
+const LDPT_MESSAGE: c_int = 11;
+const LDPT_GET_INPUT_FILE: c_int = 12;
+const LDPT_RELEASE_INPUT_FILE: c_int = 13;
+const LDPT_ADD_INPUT_LIBRARY: c_int = 14;
+const LDPT_OUTPUT_NAME: c_int = 15;
+const LDPT_SET_EXTRA_LIBRARY_PATH: c_int = 16;
+const LDPT_GET_VIEW: c_int = 18;
+const LDPT_GET_SYMBOLS_V2: c_int = 25;
+const LDPT_GET_SYMBOLS_V3: c_int = 28;
+
+// Statuses returned by both the plugin and us.
+const LDPS_OK: c_int = 0;
+const LDPS_NO_SYMS: c_int = 1;
+const LDPS_BAD_HANDLE: c_int = 2;
+const LDPS_ERR: c_int = 3;
+
+// What the plugin says about each symbol of a file that it claims. We use the same terms for the
+// symbols of bitcode that we compile ourselves.
+pub(crate) const LDPK_DEF: u8 = 0;
+pub(crate) const LDPK_WEAKDEF: u8 = 1;
+pub(crate) const LDPK_UNDEF: u8 = 2;
+pub(crate) const LDPK_WEAKUNDEF: u8 = 3;
+pub(crate) const LDPK_COMMON: u8 = 4;
+
+pub(crate) const LDPV_DEFAULT: c_int = 0;
+pub(crate) const LDPV_PROTECTED: c_int = 1;
+pub(crate) const LDPV_INTERNAL: c_int = 2;
+pub(crate) const LDPV_HIDDEN: c_int = 3;
+
+// What we tell the plugin about how each symbol of a claimed file was resolved.
+const LDPR_UNDEF: c_int = 1;
+pub(crate) const LDPR_PREVAILING_DEF: c_int = 2;
+const LDPR_PREVAILING_DEF_IRONLY: c_int = 3;
+const LDPR_PREEMPTED_REG: c_int = 4;
+const LDPR_PREEMPTED_IR: c_int = 5;
+const LDPR_RESOLVED_IR: c_int = 6;
+const LDPR_RESOLVED_EXEC: c_int = 7;
+const LDPR_RESOLVED_DYN: c_int = 8;
+pub(crate) const LDPR_PREVAILING_DEF_IRONLY_EXP: c_int = 9;
+
+const LDPL_INFO: c_int = 0;
+const LDPL_WARNING: c_int = 1;
+
+const LDPO_EXEC: usize = 1;
+const LDPO_DYN: usize = 2;
+const LDPO_PIE: usize = 3;
+
+#[repr(C)]
+struct TransferVectorEntry {
+    tag: c_int,
+    value: usize,
+}
+
+#[repr(C)]
+struct PluginInputFile {
+    name: *const c_char,
+    fd: c_int,
+    offset: libc::off_t,
+    filesize: libc::off_t,
+    handle: *mut c_void,
+}
+
+/// A symbol of a claimed file. Older versions of the API declared `def` as an int, which on a
+/// little-endian machine has the same layout as `def` followed by the three fields after it.
+#[repr(C)]
+struct PluginSymbol {
+    name: *mut c_char,
+    version: *mut c_char,
+    def: u8,
+    symbol_type: u8,
+    section_kind: u8,
+    unused: u8,
+    visibility: c_int,
+    size: u64,
+    comdat_key: *mut c_char,
+    resolution: c_int,
+}
+
+type OnloadFn = unsafe extern "C" fn(*const TransferVectorEntry) -> c_int;
+type ClaimFileHandler = unsafe extern "C" fn(*const PluginInputFile, *mut c_int) -> c_int;
+type AllSymbolsReadHandler = unsafe extern "C" fn() -> c_int;
+type CleanupHandler = unsafe extern "C" fn() -> c_int;
+
+static STATE: Mutex<State> = Mutex::new(State::new());
+
+/// Everything that the plugin can reach through its callbacks.
+struct State {
+    claim_file: Option<ClaimFileHandler>,
+    all_symbols_read: Option<AllSymbolsReadHandler>,
+    cleanup: Option<CleanupHandler>,
+
+    /// The files that we've offered to the plugin. A file's handle is its index plus one.
+    files: Vec<OfferedFile>,
+
+    /// Files that the plugin asked us to add to the link, generally the objects that it produced.
+    added_inputs: Vec<Input>,
+
+    /// Directories from `set_extra_library_path` in which to look for libraries that the plugin
+    /// adds.
+    extra_library_paths: Vec<PathBuf>,
+
+    /// Errors that the plugin reported via `message`.
+    errors: Vec<String>,
+
+    /// Warnings that the plugin reported via `message`. The callback doesn't know which link it's
+    /// part of, so we issue them once control returns to us.
+    warnings: Vec<String>,
+}
+
+#[derive(Default)]
+struct OfferedFile {
+    /// Where the plugin can read the file from. The plugin may ask for this again after claiming
+    /// the file, so we keep the file open until cleanup.
+    source: Option<FileSource>,
+
+    symbols: Vec<Symbol>,
+
+    /// How each of `symbols` was resolved. `None` if the file wasn't loaded.
+    resolutions: Option<Vec<c_int>>,
+}
+
+struct FileSource {
+    name: CString,
+    file: std::fs::File,
+    offset: libc::off_t,
+    size: libc::off_t,
+
+    /// The address of the file's bytes, which stay mapped for as long as the plugin is loaded.
+    view: usize,
+}
+
+impl FileSource {
+    fn to_plugin(&self, handle: *mut c_void) -> PluginInputFile {
+        PluginInputFile {
+            name: self.name.as_ptr(),
+            fd: self.file.as_raw_fd(),
+            offset: self.offset,
+            filesize: self.size,
+            handle,
+        }
+    }
+}
+
+pub(crate) struct Symbol {
+    pub(crate) name: Vec<u8>,
+    pub(crate) def: u8,
+    pub(crate) visibility: c_int,
+    pub(crate) size: u64,
+    pub(crate) in_comdat: bool,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            claim_file: None,
+            all_symbols_read: None,
+            cleanup: None,
+            files: Vec::new(),
+            added_inputs: Vec::new(),
+            extra_library_paths: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Issues any warnings that the plugin reported, then returns any errors.
+    fn take_messages(&mut self, diagnostics: &Diagnostics) -> Result {
+        for warning in std::mem::take(&mut self.warnings) {
+            warning!(diagnostics, "{warning}");
+        }
+        self.take_errors()
+    }
+
+    fn take_errors(&mut self) -> Result {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(std::mem::take(&mut self.errors).join("\n")))
+    }
+}
+
+fn state() -> MutexGuard<'static, State> {
+    // If a callback panicked, then we're already failing, so we might as well continue with what
+    // was there.
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The objects that we got by compiling the IR files that were claimed, either by a plugin or by
+/// us. Dropping this runs the plugin's cleanup hook, which may delete the plugin's objects, so it
+/// should be kept until we're done with our inputs.
+pub(crate) struct LtoOutputs {
+    _plugin: Option<LoadedPlugin>,
+
+    /// The files and archive members that were claimed, identified by filename and offset within
+    /// the file. These get replaced by `inputs` and `objects`.
+    claimed: HashSet<(PathBuf, usize)>,
+
+    /// Files that the plugin asked us to add to the link.
+    inputs: Vec<Input>,
+
+    /// Objects that we compiled ourselves, together with names to show in diagnostics.
+    objects: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl LtoOutputs {
+    pub(crate) fn from_objects(
+        inputs: &[InputBytes],
+        claimed: impl Iterator<Item = usize>,
+        objects: Vec<(PathBuf, Vec<u8>)>,
+    ) -> Self {
+        Self {
+            _plugin: None,
+            claimed: claimed.map(|index| claim_key(&inputs[index])).collect(),
+            inputs: Vec::new(),
+            objects,
+        }
+    }
+
+    /// Adds our outputs to `input_data`.
+    pub(crate) fn add_to(&self, input_data: &mut InputData) -> Result {
+        input_data.add_plugin_inputs(&self.inputs)?;
+        for (name, bytes) in &self.objects {
+            input_data.add_in_memory_object(name, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the files that were claimed from `inputs`.
+    pub(crate) fn remove_claimed(&self, inputs: &mut Vec<InputBytes>) {
+        inputs.retain(|input| !self.claimed.contains(&claim_key(input)));
+    }
+}
+
+fn claim_key(input: &InputBytes) -> (PathBuf, usize) {
+    let file = &input.input.file;
+    (file.filename.clone(), member_offset(input))
+}
+
+/// Returns the offset of `input` within the file that contains it, which is non-zero for archive
+/// members.
+fn member_offset(input: &InputBytes) -> usize {
+    input.data.as_ptr() as usize - input.input.file.data().as_ptr() as usize
+}
+
+/// A plugin that we've loaded and whose `onload` succeeded.
+struct LoadedPlugin {
+    /// Strings that the plugin may hold pointers to until cleanup.
+    _options: Vec<CString>,
+    _output_name: CString,
+
+    /// Where to report problems during cleanup.
+    diagnostics: Arc<Diagnostics>,
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        let cleanup = state().cleanup.take();
+        if let Some(cleanup) = cleanup {
+            if unsafe { cleanup() } != LDPS_OK {
+                warning!(self.diagnostics, "Linker plugin cleanup failed");
+            }
+        }
+        if let Err(error) = state().take_messages(&self.diagnostics) {
+            warning!(self.diagnostics, "{error}");
+        }
+        *state() = State::new();
+    }
+}
+
+/// Offers any inputs that contain IR to our linker plugin. If the plugin claims any, we resolve
+/// symbols with the claimed files standing in for the IR, pass the resolutions back to the plugin,
+/// then return the objects that it produces. Without a plugin, we compile any LLVM bitcode
+/// ourselves if we can find `libLTO`. Compiler drivers pass their plugin whether or not it's
+/// installed or suits the inputs, so we do the same if the plugin doesn't exist or claims nothing.
+#[tracing::instrument(skip_all, name = "Link-time optimisation")]
+pub(crate) fn run(
+    args: &Args,
+    input_data: &InputData,
+    inputs: &[InputBytes],
+) -> Result<Option<LtoOutputs>> {
+    let candidates: Vec<(usize, IrKind)> = inputs
+        .par_iter()
+        .enumerate()
+        .filter_map(|(index, input)| {
+            let kind = match input.kind {
+                FileKind::LlvmIr => Some(IrKind::LlvmBitcode),
+                FileKind::ElfObject => IrKind::identify(input.data),
+                // Without a plugin, we'd only be looking for bitcode, so we can avoid reading the
+                // section tables of all the other members.
+                FileKind::Archive if args.plugin.is_none() && !args.fat_lto_objects => {
+                    IrKind::identify_bitcode(input.data)
+                }
+                FileKind::Archive => IrKind::identify(input.data),
+                _ => None,
+            }?;
+            Some((index, kind))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    if let Some(path) = &args.plugin {
+        if !path.exists() {
+            warning!(
+                args.diagnostics,
+                "Linker plugin `{}` doesn't exist, so it won't be used",
+                path.display()
+            );
+        } else if let Some(outputs) = run_plugin(path, args, input_data, inputs, &candidates)? {
+            return Ok(Some(outputs));
+        }
+    }
+    if args.thinlto_index_only.is_some() {
+        // libLTO can't write ThinLTO indexes, but LLVM's gold plugin can.
+        let gold_plugin = crate::llvm_lto::find_gold_plugin()
+            .context("--thinlto-index-only needs LLVMgold.so, which we couldn't find")?;
+        if args.plugin.as_ref() != Some(&gold_plugin) {
+            if let Some(outputs) = run_plugin(&gold_plugin, args, input_data, inputs, &candidates)?
+            {
+                return Ok(Some(outputs));
+            }
+        }
+    }
+    crate::llvm_lto::run(args, input_data, inputs, &candidates)
+}
+
+/// Loads the plugin at `path` and offers it `candidates`. Returns `None` if it claims none of them.
+fn run_plugin(
+    path: &Path,
+    args: &Args,
+    input_data: &InputData,
+    inputs: &[InputBytes],
+    candidates: &[(usize, IrKind)],
+) -> Result<Option<LtoOutputs>> {
+    let plugin = load(path, args)?;
+    let mut claimed = HashSet::new();
+    for &(index, _) in candidates {
+        if claim(&inputs[index], index)? {
+            claimed.insert(index);
+        }
+    }
+    if claimed.is_empty() {
+        return Ok(None);
+    }
+    check_unclaimed(inputs, candidates, &claimed)?;
+    if !is_llvm_plugin(path) {
+        check_thinlto_unused(path, args)?;
+    }
+
+    let resolutions = {
+        let state = state();
+        let symbols = claimed
+            .iter()
+            .map(|&index| (index, state.files[index].symbols.as_slice()))
+            .collect();
+        resolve(args, input_data, inputs, &symbols)?
+    };
+    let mut state_guard = state();
+    for (index, resolutions) in resolutions {
+        state_guard.files[index].resolutions = Some(resolutions);
+    }
+    drop(state_guard);
+
+    let all_symbols_read = state().all_symbols_read;
+    if let Some(all_symbols_read) = all_symbols_read {
+        let status = unsafe { all_symbols_read() };
+        state().take_messages(&args.diagnostics)?;
+        if status != LDPS_OK {
+            bail!("Linker plugin failed to process IR");
+        }
+    }
+
+    let inputs_to_add = std::mem::take(&mut state().added_inputs);
+    Ok(Some(LtoOutputs {
+        _plugin: Some(plugin),
+        claimed: claimed
+            .into_iter()
+            .map(|index| claim_key(&inputs[index]))
+            .collect(),
+        inputs: inputs_to_add,
+        objects: Vec::new(),
+    }))
+}
+
+/// Reports an error for the first input that contains IR that we can't link without a plugin
+/// claiming it.
+pub(crate) fn check_unclaimed(
+    inputs: &[InputBytes],
+    candidates: &[(usize, IrKind)],
+    claimed: &HashSet<usize>,
+) -> Result {
+    let unclaimed = candidates
+        .iter()
+        .find(|(index, kind)| kind.needs_plugin() && !claimed.contains(index));
+    if let Some(&(index, kind)) = unclaimed {
+        bail!(
+            "`{}` contains {kind}, which can only be linked using a linker plugin (-plugin)",
+            inputs[index]
+        );
+    }
+    Ok(())
+}
+
+/// Returns whether the plugin at `path` is LLVM's, which is the only one that understands our ThinLTO
+/// flags.
+fn is_llvm_plugin(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_bytes().starts_with(b"LLVMgold"))
+}
+
+/// Reports ThinLTO flags that we can't honour because a plugin other than LLVM's claimed the IR.
+/// This is only checked once the plugin has claimed something, since compiler drivers pass their
+/// plugin whether or not the inputs are for it.
+fn check_thinlto_unused(path: &Path, args: &Args) -> Result {
+    if args.thinlto_index_only.is_some() {
+        bail!(
+            "--thinlto-index-only isn't supported by linker plugin `{}`",
+            path.display()
+        );
+    }
+    if args.thinlto_cache_dir.is_some() || args.thinlto_jobs.is_some() {
+        warning!(
+            args.diagnostics,
+            "Ignoring ThinLTO options, which linker plugin `{}` doesn't support",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the plugin options that correspond to our ThinLTO flags, which we only pass to LLVM's
+/// plugin.
+fn thinlto_options(path: &Path, args: &Args) -> Vec<String> {
+    let mut options = Vec::new();
+    if !is_llvm_plugin(path) {
+        return options;
+    }
+    match &args.thinlto_index_only {
+        Some(Some(list)) => options.push(format!("thinlto-index-only={}", list.display())),
+        Some(None) => options.push("thinlto-index-only".to_owned()),
+        None => {}
+    }
+    if args.thinlto_index_only.is_some() {
+        options.push("thinlto-emit-imports-files".to_owned());
+    }
+    if let Some(dir) = &args.thinlto_cache_dir {
+        options.push(format!("cache-dir={}", dir.display()));
+    }
+    if let Some(jobs) = args.thinlto_jobs {
+        options.push(format!("jobs={jobs}"));
+    }
+    options
+}
+
+fn load(path: &Path, args: &Args) -> Result<LoadedPlugin> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let library = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+    if library.is_null() {
+        bail!(
+            "Failed to load linker plugin `{}`: {}",
+            path.display(),
+            dl_error()
+        );
+    }
+    let onload = unsafe { libc::dlsym(library, c"onload".as_ptr()) };
+    if onload.is_null() {
+        bail!("Linker plugin `{}` doesn't define `onload`", path.display());
+    }
+    let onload: OnloadFn = unsafe { std::mem::transmute(onload) };
+
+    let options = args
+        .plugin_opts
+        .iter()
+        .cloned()
+        .chain(thinlto_options(path, args))
+        .map(CString::new)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let output_name = CString::new(args.output.as_os_str().as_bytes())?;
+    let output_kind = match args.output_kind {
+        OutputKind::SharedObject => LDPO_DYN,
+        OutputKind::StaticExecutable(RelocationModel::Relocatable)
+        | OutputKind::DynamicExecutable(RelocationModel::Relocatable) => LDPO_PIE,
+        _ => LDPO_EXEC,
+    };
+
+    let entry = |tag, value| TransferVectorEntry { tag, value };
+    let mut transfer_vector = vec![
+        entry(LDPT_API_VERSION, 1),
+        entry(LDPT_LINKER_OUTPUT, output_kind),
+        entry(LDPT_OUTPUT_NAME, output_name.as_ptr() as usize),
+        entry(
+            LDPT_REGISTER_CLAIM_FILE_HOOK,
+            register_claim_file as *const () as usize,
+        ),
+        entry(
+            LDPT_REGISTER_ALL_SYMBOLS_READ_HOOK,
+            register_all_symbols_read as *const () as usize,
+        ),
+        entry(
+            LDPT_REGISTER_CLEANUP_HOOK,
+            register_cleanup as *const () as usize,
+        ),
+        entry(LDPT_ADD_SYMBOLS, add_symbols as *const () as usize),
+        entry(LDPT_GET_SYMBOLS, get_symbols_v1 as *const () as usize),
+        entry(LDPT_GET_SYMBOLS_V2, get_symbols_v2 as *const () as usize),
+        entry(LDPT_GET_SYMBOLS_V3, get_symbols_v3 as *const () as usize),
+        entry(LDPT_GET_INPUT_FILE, get_input_file as *const () as usize),
+        entry(
+            LDPT_RELEASE_INPUT_FILE,
+            release_input_file as *const () as usize,
+        ),
+        entry(LDPT_GET_VIEW, get_view as *const () as usize),
+        entry(LDPT_ADD_INPUT_FILE, add_input_file as *const () as usize),
+        entry(
+            LDPT_ADD_INPUT_LIBRARY,
+            add_input_library as *const () as usize,
+        ),
+        entry(
+            LDPT_SET_EXTRA_LIBRARY_PATH,
+            set_extra_library_path as *const () as usize,
+        ),
+        entry(LDPT_MESSAGE, message as *const () as usize),
+    ];
+    transfer_vector.extend(
+        options
+            .iter()
+            .map(|option| entry(LDPT_OPTION, option.as_ptr() as usize)),
+    );
+    transfer_vector.push(entry(LDPT_NULL, 0));
+
+    *state() = State::new();
+    let status = unsafe { onload(transfer_vector.as_ptr()) };
+    let plugin = LoadedPlugin {
+        _options: options,
+        _output_name: output_name,
+        diagnostics: args.diagnostics.clone(),
+    };
+    state().take_messages(&args.diagnostics)?;
+    if status != LDPS_OK {
+        bail!("Failed to initialise linker plugin `{}`", path.display());
+    }
+    Ok(plugin)
+}
+
+pub(crate) fn dl_error() -> String {
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        return "unknown error".to_owned();
+    }
+    unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Offers `input` to the plugin, returning whether it claimed it.
+fn claim(input: &InputBytes, index: usize) -> Result<bool> {
+    let Some(claim_file) = state().claim_file else {
+        return Ok(false);
+    };
+    let filename = &input.input.file.filename;
+    let source = FileSource {
+        name: CString::new(filename.as_os_str().as_bytes())?,
+        file: std::fs::File::open(filename)
+            .with_context(|| format!("Failed to open `{}`", filename.display()))?,
+        offset: member_offset(input) as libc::off_t,
+        size: input.data.len() as libc::off_t,
+        view: input.data.as_ptr() as usize,
+    };
+    let plugin_input = source.to_plugin((index + 1) as *mut c_void);
+    {
+        let mut state = state();
+        state.files.resize_with(index + 1, OfferedFile::default);
+        state.files[index].source = Some(source);
+    }
+    let mut claimed = 0;
+    let status = unsafe { claim_file(&plugin_input, &mut claimed) };
+    state()
+        .take_errors()
+        .with_context(|| format!("Linker plugin failed to read `{input}`"))?;
+    if status != LDPS_OK {
+        bail!("Linker plugin failed to read `{input}`");
+    }
+    Ok(claimed != 0)
+}
+
+/// Works out how each symbol of the claimed files is resolved, given the symbols of each claimed
+/// file, keyed by its index in `inputs`. We do this by doing symbol resolution with each claimed
+/// file replaced by an object that has the same symbols. Files that don't get loaded are left out
+/// of the result.
+#[tracing::instrument(skip_all, name = "Resolve symbols of IR")]
+pub(crate) fn resolve(
+    args: &Args,
+    input_data: &InputData,
+    inputs: &[InputBytes],
+    claimed: &HashMap<usize, &[Symbol]>,
+) -> Result<HashMap<usize, Vec<c_int>>> {
+    let stand_ins = claimed
+        .iter()
+        .map(|(&index, symbols)| (index, stand_in_object(symbols)))
+        .collect::<HashMap<_, _>>();
+    let inputs = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let Some(data) = stand_ins.get(&index) else {
+                return InputBytes {
+                    input: input.input.clone(),
+                    kind: input.kind,
+                    data: input.data,
+                    modifiers: input.modifiers,
+                };
+            };
+            InputBytes {
+                input: input.input.clone(),
+                kind: match input.kind {
+                    FileKind::LlvmIr => FileKind::ElfObject,
+                    kind => kind,
+                },
+                data,
+                modifiers: input.modifiers,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let files = crate::parsing::parse_input_files(&inputs, args)?;
+    let groups = crate::grouping::group_files(files, args);
+    let mut symbol_db = SymbolDb::build(&groups, input_data.version_script_data.as_ref(), args)?;
+    let herd = bumpalo_herd::Herd::new();
+    let resolved = crate::resolution::resolve_symbols_and_sections(&groups, &mut symbol_db, &herd)?;
+
+    let is_stand_in = |data: &[u8]| {
+        stand_ins
+            .values()
+            .any(|stand_in| std::ptr::eq(stand_in.as_slice(), data))
+    };
+
+    // Names that are referenced from outside the IR. A definition that prevails for one of these
+    // needs to be kept even if nothing in the IR references it.
+    let mut referenced = HashSet::new();
+    referenced.insert(args.entry.as_bytes());
+    referenced.extend(args.init.as_deref().map(str::as_bytes));
+    referenced.extend(args.fini.as_deref().map(str::as_bytes));
+    let loaded = resolved
+        .groups
+        .iter()
+        .flat_map(|group| &group.files)
+        .filter_map(|file| match file {
+            ResolvedFile::Object(object) => Some(object),
+            _ => None,
+        });
+    for object in loaded.clone() {
+        if is_stand_in(object.object.data) {
+            continue;
+        }
+        for symbol in object.object.symbols.iter() {
+            if symbol.is_undefined(LittleEndian) {
+                referenced.extend(object.object.symbol_name(symbol).ok());
+            }
+        }
+    }
+
+    let mut all_resolutions = HashMap::new();
+    for object in loaded {
+        let Some((&index, _)) = stand_ins
+            .iter()
+            .find(|(_, stand_in)| std::ptr::eq(stand_in.as_slice(), object.object.data))
+        else {
+            continue;
+        };
+        let resolutions = claimed[&index]
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| {
+                let symbol_id = object
+                    .symbol_id_range
+                    .input_to_id(object::SymbolIndex(i + 1));
+                let definition = symbol_db.definition(symbol_id);
+                let definer = definer(&symbol_db, definition);
+                let defined_in_ir =
+                    matches!(definer, Definer::Object(file) if is_stand_in(file.object.data));
+                if symbol.is_definition() {
+                    if definition != symbol_id {
+                        if defined_in_ir {
+                            LDPR_PREEMPTED_IR
+                        } else {
+                            LDPR_PREEMPTED_REG
+                        }
+                    } else if referenced.contains(symbol.name.as_slice()) {
+                        LDPR_PREVAILING_DEF
+                    } else if args.output_kind == OutputKind::SharedObject
+                        && symbol.visibility == LDPV_DEFAULT
+                    {
+                        LDPR_PREVAILING_DEF_IRONLY_EXP
+                    } else {
+                        LDPR_PREVAILING_DEF_IRONLY
+                    }
+                } else {
+                    match definer {
+                        Definer::Undefined => LDPR_UNDEF,
+                        _ if defined_in_ir => LDPR_RESOLVED_IR,
+                        Definer::Object(file) if file.is_dynamic() => LDPR_RESOLVED_DYN,
+                        Definer::Object(_) | Definer::Internal => LDPR_RESOLVED_EXEC,
+                    }
+                }
+            })
+            .collect();
+        all_resolutions.insert(index, resolutions);
+    }
+    Ok(all_resolutions)
+}
+
+/// What defines a symbol that a claimed file references or defines.
+enum Definer<'db, 'data> {
+    Undefined,
+    Object(&'db crate::parsing::ParsedInputObject<'data>),
+    /// A symbol that we define ourselves, e.g. `__ehdr_start`.
+    Internal,
+}
+
+fn definer<'db, 'data>(
+    symbol_db: &'db SymbolDb<'data>,
+    symbol_id: crate::symbol_db::SymbolId,
+) -> Definer<'db, 'data> {
+    if symbol_id.is_undefined() {
+        return Definer::Undefined;
+    }
+    match symbol_db.file(symbol_db.file_id_for_symbol(symbol_id)) {
+        ParsedInput::Object(object) => {
+            let index = symbol_id.to_input(object.symbol_id_range);
+            match object.object.symbol(index) {
+                Ok(symbol) if !symbol.is_undefined(LittleEndian) => Definer::Object(object),
+                _ => Definer::Undefined,
+            }
+        }
+        _ => Definer::Internal,
+    }
+}
+
+impl Symbol {
+    fn is_definition(&self) -> bool {
+        matches!(self.def, LDPK_DEF | LDPK_WEAKDEF | LDPK_COMMON)
+    }
+}
+
+/// Builds a relocatable ELF object that defines and references the same symbols as a claimed file.
+/// Its definitions are all in an empty `.text` section.
+pub(crate) fn stand_in_object(symbols: &[Symbol]) -> Vec<u8> {
+    use object::elf;
+    use object::U16;
+    use object::U32;
+    use object::U64;
+
+    const TEXT_INDEX: u16 = 1;
+    const SYMTAB_INDEX: u32 = 2;
+    const STRTAB_INDEX: u32 = 3;
+    const SHSTRTAB_INDEX: u16 = 4;
+    let e = LittleEndian;
+
+    let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+    let mut strtab = vec![0];
+    let mut symtab = vec![elf::Sym64::<LittleEndian>::default()];
+    for symbol in symbols {
+        let name_offset = strtab.len() as u32;
+        strtab.extend_from_slice(&symbol.name);
+        strtab.push(0);
+        let binding = if matches!(symbol.def, LDPK_WEAKDEF | LDPK_WEAKUNDEF) || symbol.in_comdat {
+            elf::STB_WEAK
+        } else {
+            elf::STB_GLOBAL
+        };
+        let (section_index, value, size) = match symbol.def {
+            LDPK_UNDEF | LDPK_WEAKUNDEF => (elf::SHN_UNDEF, 0, 0),
+            LDPK_COMMON => (elf::SHN_COMMON, 1, symbol.size),
+            _ => (TEXT_INDEX, 0, 0),
+        };
+        let visibility = match symbol.visibility {
+            LDPV_PROTECTED => elf::STV_PROTECTED,
+            LDPV_INTERNAL => elf::STV_INTERNAL,
+            LDPV_HIDDEN => elf::STV_HIDDEN,
+            _ => elf::STV_DEFAULT,
+        };
+        symtab.push(elf::Sym64 {
+            st_name: U32::new(e, name_offset),
+            st_info: (binding << 4) | elf::STT_NOTYPE,
+            st_other: visibility,
+            st_shndx: U16::new(e, section_index),
+            st_value: U64::new(e, value),
+            st_size: U64::new(e, size),
+        });
+    }
+
+    let header_size = size_of::<elf::FileHeader64<LittleEndian>>();
+    let shstrtab_offset = header_size;
+    let strtab_offset = shstrtab_offset + shstrtab.len();
+    let symtab_offset = (strtab_offset + strtab.len()).next_multiple_of(8);
+    let symtab_size = symtab.len() * size_of::<elf::Sym64<LittleEndian>>();
+    let section_headers_offset = symtab_offset + symtab_size;
+
+    let section =
+        |name: u32, sh_type: u32, flags: u32, offset: usize, size: usize| elf::SectionHeader64::<
+            LittleEndian,
+        > {
+            sh_name: U32::new(e, name),
+            sh_type: U32::new(e, sh_type),
+            sh_flags: U64::new(e, u64::from(flags)),
+            sh_addr: U64::new(e, 0),
+            sh_offset: U64::new(e, offset as u64),
+            sh_size: U64::new(e, size as u64),
+            sh_link: U32::new(e, 0),
+            sh_info: U32::new(e, 0),
+            sh_addralign: U64::new(e, 1),
+            sh_entsize: U64::new(e, 0),
+        };
+    let mut symtab_header = section(8, elf::SHT_SYMTAB, 0, symtab_offset, symtab_size);
+    symtab_header.sh_link = U32::new(e, STRTAB_INDEX);
+    // All symbols other than the null symbol are global.
+    symtab_header.sh_info = U32::new(e, 1);
+    symtab_header.sh_addralign = U64::new(e, 8);
+    symtab_header.sh_entsize = U64::new(e, size_of::<elf::Sym64<LittleEndian>>() as u64);
+    let section_headers = [
+        section(0, elf::SHT_NULL, 0, 0, 0),
+        section(
+            1,
+            elf::SHT_PROGBITS,
+            elf::SHF_ALLOC | elf::SHF_EXECINSTR,
+            shstrtab_offset,
+            0,
+        ),
+        symtab_header,
+        section(16, elf::SHT_STRTAB, 0, strtab_offset, strtab.len()),
+        section(24, elf::SHT_STRTAB, 0, shstrtab_offset, shstrtab.len()),
+    ];
+    debug_assert_eq!(section_headers.len(), usize::from(SHSTRTAB_INDEX) + 1);
+    debug_assert_eq!(SYMTAB_INDEX, 2);
+
+    let header = elf::FileHeader64::<LittleEndian> {
+        e_ident: elf::Ident {
+            magic: elf::ELFMAG,
+            class: elf::ELFCLASS64,
+            data: elf::ELFDATA2LSB,
+            version: elf::EV_CURRENT,
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            padding: [0; 7],
+        },
+        e_type: U16::new(e, elf::ET_REL),
+        e_machine: U16::new(e, elf::EM_X86_64),
+        e_version: U32::new(e, u32::from(elf::EV_CURRENT)),
+        e_entry: U64::new(e, 0),
+        e_phoff: U64::new(e, 0),
+        e_shoff: U64::new(e, section_headers_offset as u64),
+        e_flags: U32::new(e, 0),
+        e_ehsize: U16::new(e, header_size as u16),
+        e_phentsize: U16::new(e, 0),
+        e_phnum: U16::new(e, 0),
+        e_shentsize: U16::new(e, size_of::<elf::SectionHeader64<LittleEndian>>() as u16),
+        e_shnum: U16::new(e, section_headers.len() as u16),
+        e_shstrndx: U16::new(e, SHSTRTAB_INDEX),
+    };
+
+    let mut out = Vec::with_capacity(
+        section_headers_offset
+            + section_headers.len() * size_of::<elf::SectionHeader64<LittleEndian>>(),
+    );
+    out.extend_from_slice(object::bytes_of(&header));
+    out.extend_from_slice(shstrtab);
+    out.extend_from_slice(&strtab);
+    out.resize(symtab_offset, 0);
+    out.extend_from_slice(object::bytes_of_slice(&symtab));
+    out.extend_from_slice(object::bytes_of_slice(&section_headers));
+    out
+}
+
+/// Returns the file for a handle that we gave the plugin.
+fn file_index(state: &State, handle: *const c_void) -> Option<usize> {
+    let index = (handle as usize).checked_sub(1)?;
+    (index < state.files.len()).then_some(index)
+}
+
+unsafe fn c_str_bytes<'a>(s: *const c_char) -> &'a [u8] {
+    if s.is_null() {
+        return &[];
+    }
+    CStr::from_ptr(s).to_bytes()
+}
+
+unsafe extern "C" fn register_claim_file(handler: ClaimFileHandler) -> c_int {
+    state().claim_file = Some(handler);
+    LDPS_OK
+}
+
+unsafe extern "C" fn register_all_symbols_read(handler: AllSymbolsReadHandler) -> c_int {
+    state().all_symbols_read = Some(handler);
+    LDPS_OK
+}
+
+unsafe extern "C" fn register_cleanup(handler: CleanupHandler) -> c_int {
+    state().cleanup = Some(handler);
+    LDPS_OK
+}
+
+unsafe extern "C" fn add_symbols(
+    handle: *mut c_void,
+    num_symbols: c_int,
+    symbols: *const PluginSymbol,
+) -> c_int {
+    let mut state = state();
+    let Some(index) = file_index(&state, handle) else {
+        return LDPS_BAD_HANDLE;
+    };
+    let Ok(num_symbols) = usize::try_from(num_symbols) else {
+        return LDPS_ERR;
+    };
+    if num_symbols == 0 {
+        return LDPS_OK;
+    }
+    let symbols = std::slice::from_raw_parts(symbols, num_symbols);
+    state.files[index]
+        .symbols
+        .extend(symbols.iter().map(|symbol| Symbol {
+            name: c_str_bytes(symbol.name).to_vec(),
+            def: symbol.def,
+            visibility: symbol.visibility,
+            size: symbol.size,
+            in_comdat: !symbol.comdat_key.is_null() && *symbol.comdat_key != 0,
+        }));
+    LDPS_OK
+}
+
+/// Fills in the resolutions of a claimed file's symbols. Version 1 of the API predates
+/// `PREVAILING_DEF_IRONLY_EXP` and version 3 added a way to say that a file wasn't loaded.
+unsafe fn get_symbols(
+    version: u32,
+    handle: *const c_void,
+    num_symbols: c_int,
+    symbols: *mut PluginSymbol,
+) -> c_int {
+    let state = state();
+    let Some(index) = file_index(&state, handle) else {
+        return LDPS_BAD_HANDLE;
+    };
+    let file = &state.files[index];
+    let Ok(num_symbols) = usize::try_from(num_symbols) else {
+        return LDPS_ERR;
+    };
+    if num_symbols == 0 {
+        return LDPS_OK;
+    }
+    let symbols = std::slice::from_raw_parts_mut(symbols, num_symbols);
+    let Some(resolutions) = file.resolutions.as_ref() else {
+        // Nothing referenced this archive member, so we didn't load it.
+        for (symbol, ours) in symbols.iter_mut().zip(&file.symbols) {
+            symbol.resolution = if ours.is_definition() {
+                LDPR_PREEMPTED_REG
+            } else {
+                LDPR_UNDEF
+            };
+        }
+        return if version >= 3 { LDPS_NO_SYMS } else { LDPS_OK };
+    };
+    for (symbol, &resolution) in symbols.iter_mut().zip(resolutions) {
+        symbol.resolution = if version == 1 && resolution == LDPR_PREVAILING_DEF_IRONLY_EXP {
+            LDPR_PREVAILING_DEF
+        } else {
+            resolution
+        };
+    }
+    LDPS_OK
+}
+
+unsafe extern "C" fn get_symbols_v1(
+    handle: *const c_void,
+    num_symbols: c_int,
+    symbols: *mut PluginSymbol,
+) -> c_int {
+    get_symbols(1, handle, num_symbols, symbols)
+}
+
+unsafe extern "C" fn get_symbols_v2(
+    handle: *const c_void,
+    num_symbols: c_int,
+    symbols: *mut PluginSymbol,
+) -> c_int {
+    get_symbols(2, handle, num_symbols, symbols)
+}
+
+unsafe extern "C" fn get_symbols_v3(
+    handle: *const c_void,
+    num_symbols: c_int,
+    symbols: *mut PluginSymbol,
+) -> c_int {
+    get_symbols(3, handle, num_symbols, symbols)
+}
+
+unsafe extern "C" fn get_input_file(handle: *const c_void, file: *mut PluginInputFile) -> c_int {
+    let state = state();
+    let Some(source) =
+        file_index(&state, handle).and_then(|index| state.files[index].source.as_ref())
+    else {
+        return LDPS_BAD_HANDLE;
+    };
+    *file = source.to_plugin(handle.cast_mut());
+    LDPS_OK
+}
+
+unsafe extern "C" fn get_view(handle: *const c_void, view: *mut *const c_void) -> c_int {
+    let state = state();
+    let Some(source) =
+        file_index(&state, handle).and_then(|index| state.files[index].source.as_ref())
+    else {
+        return LDPS_BAD_HANDLE;
+    };
+    *view = source.view as *const c_void;
+    LDPS_OK
+}
+
+/// We keep files open until cleanup, so there's nothing to do when the plugin is done with one.
+unsafe extern "C" fn release_input_file(handle: *const c_void) -> c_int {
+    if file_index(&state(), handle).is_none() {
+        return LDPS_BAD_HANDLE;
+    }
+    LDPS_OK
+}
+
+unsafe extern "C" fn add_input_file(path: *const c_char) -> c_int {
+    let path = Path::new(std::ffi::OsStr::from_bytes(c_str_bytes(path)));
+    add_input(InputSpec::File(Box::from(path)));
+    LDPS_OK
+}
+
+unsafe extern "C" fn add_input_library(name: *const c_char) -> c_int {
+    let name = String::from_utf8_lossy(c_str_bytes(name)).into_owned();
+    let found = state()
+        .extra_library_paths
+        .iter()
+        .flat_map(|dir| {
+            [
+                dir.join(format!("lib{name}.so")),
+                dir.join(format!("lib{name}.a")),
+            ]
+        })
+        .find(|path| path.exists());
+    add_input(match found {
+        Some(path) => InputSpec::File(Box::from(path)),
+        None => InputSpec::Lib(Box::from(name)),
+    });
+    LDPS_OK
+}
+
+fn add_input(spec: InputSpec) {
+    state().added_inputs.push(Input {
+        spec,
+        search_first: None,
+        modifiers: Default::default(),
+    });
+}
+
+unsafe extern "C" fn set_extra_library_path(path: *const c_char) -> c_int {
+    let path = PathBuf::from(std::ffi::OsStr::from_bytes(c_str_bytes(path)));
+    state().extra_library_paths.push(path);
+    LDPS_OK
+}
+
+/// Handles the plugin's `message` callback, which in C is variadic. Rust can't define variadic
+/// functions, but on x86-64 the first few integer and pointer arguments of a variadic call are
+/// passed in the same registers as those of a regular call, so we declare enough of them for the
+/// messages that plugins send.
+unsafe extern "C" fn message(
+    level: c_int,
+    format: *const c_char,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+) -> c_int {
+    let text = format_message(c_str_bytes(format), &[arg0, arg1, arg2, arg3]);
+    match level {
+        LDPL_INFO => eprintln!("{text}"),
+        LDPL_WARNING => state().warnings.push(text),
+        _ => state().errors.push(text),
+    }
+    LDPS_OK
+}
+
+/// Expands a printf-style format string. We support the conversions that plugins use in their
+/// messages. Anything we don't understand, or that needs more arguments than we have, is copied
+/// verbatim.
+unsafe fn format_message(format: &[u8], args: &[usize]) -> String {
+    let mut out = Vec::new();
+    let mut args = args.iter().copied();
+    let mut rest = format;
+    while let Some(percent) = rest.iter().position(|b| *b == b'%') {
+        out.extend_from_slice(&rest[..percent]);
+        let spec = &rest[percent + 1..];
+        let modifiers = spec
+            .iter()
+            .take_while(|b| matches!(b, b'l' | b'z' | b'j' | b't' | b'h'))
+            .count();
+        let is_long = modifiers > 0 && spec[0] != b'h';
+        let Some(&conversion) = spec.get(modifiers) else {
+            out.extend_from_slice(&rest[percent..]);
+            rest = &[];
+            break;
+        };
+        let consumed = percent + 2 + modifiers;
+        if conversion == b'%' {
+            out.push(b'%');
+            rest = &rest[consumed..];
+            continue;
+        }
+        if !matches!(conversion, b's' | b'd' | b'i' | b'u' | b'x' | b'c' | b'p') {
+            out.extend_from_slice(&rest[percent..consumed]);
+            rest = &rest[consumed..];
+            continue;
+        }
+        let Some(arg) = args.next() else {
+            out.extend_from_slice(&rest[percent..]);
+            rest = &[];
+            break;
+        };
+        match conversion {
+            b's' => out.extend_from_slice(c_str_bytes(arg as *const c_char)),
+            b'd' | b'i' if is_long => out.extend_from_slice((arg as i64).to_string().as_bytes()),
+            b'd' | b'i' => out.extend_from_slice((arg as i32).to_string().as_bytes()),
+            b'u' if is_long => out.extend_from_slice(arg.to_string().as_bytes()),
+            b'u' => out.extend_from_slice((arg as u32).to_string().as_bytes()),
+            b'x' if is_long => out.extend_from_slice(format!("{arg:x}").as_bytes()),
+            b'x' => out.extend_from_slice(format!("{:x}", arg as u32).as_bytes()),
+            b'c' => out.push(arg as u8),
+            _ => out.extend_from_slice(format!("{arg:#x}").as_bytes()),
+        }
+        rest = &rest[consumed..];
+    }
+    out.extend_from_slice(rest);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[test]
+fn test_format_message() {
+    let name = c"foo.o";
+    let format = |format: &str, args: &[usize]| unsafe { format_message(format.as_bytes(), args) };
+    assert_eq!(
+        format(
+            "%s: %d symbols, %lu%%",
+            &[name.as_ptr() as usize, -2_i32 as u32 as usize, 7]
+        ),
+        "foo.o: -2 symbols, 7%"
+    );
+    assert_eq!(format("%x %q", &[255]), "ff %q");
+    assert_eq!(format("missing %s", &[]), "missing %s");
+}
+
+#[test]
+fn test_stand_in_object() {
+    let symbol = |name: &str, def, in_comdat| Symbol {
+        name: name.as_bytes().to_vec(),
+        def,
+        visibility: LDPV_DEFAULT,
+        size: 8,
+        in_comdat,
+    };
+    let data = stand_in_object(&[
+        symbol("main", LDPK_DEF, false),
+        symbol("inline_fn", LDPK_DEF, true),
+        symbol("printf", LDPK_UNDEF, false),
+        symbol("buffer", LDPK_COMMON, false),
+    ]);
+    let file = crate::elf::File::parse(&data, false).unwrap();
+    let names = file
+        .symbols
+        .iter()
+        .skip(1)
+        .map(|symbol| {
+            (
+                String::from_utf8(file.symbol_name(symbol).unwrap().to_vec()).unwrap(),
+                symbol.st_bind(),
+                symbol.st_shndx(LittleEndian),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            ("main".to_owned(), object::elf::STB_GLOBAL, 1),
+            ("inline_fn".to_owned(), object::elf::STB_WEAK, 1),
+            (
+                "printf".to_owned(),
+                object::elf::STB_GLOBAL,
+                object::elf::SHN_UNDEF
+            ),
+            (
+                "buffer".to_owned(),
+                object::elf::STB_GLOBAL,
+                object::elf::SHN_COMMON
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_thinlto_options() {
+    let parse = |input: &[&str]| {
+        let crate::args::Action::Link(args) = crate::args::parse(input.iter()).unwrap() else {
+            panic!("Unexpected action");
+        };
+        args
+    };
+    let gcc_plugin = Path::new("/usr/lib/gcc/x86_64-linux-gnu/12/liblto_plugin.so");
+    let llvm_plugin = Path::new("/usr/lib/llvm/lib/LLVMgold.so");
+
+    let args = parse(&["--thinlto-cache-dir=cache", "--thinlto-jobs=2", "a.o"]);
+    assert!(thinlto_options(gcc_plugin, &args).is_empty());
+    assert_eq!(
+        thinlto_options(llvm_plugin, &args),
+        ["cache-dir=cache", "jobs=2"]
+    );
+    assert!(check_thinlto_unused(gcc_plugin, &args).is_ok());
+
+    let args = parse(&["--thinlto-index-only", "a.o"]);
+    assert!(thinlto_options(gcc_plugin, &args).is_empty());
+    assert_eq!(
+        thinlto_options(llvm_plugin, &args),
+        ["thinlto-index-only", "thinlto-emit-imports-files"]
+    );
+    assert!(check_thinlto_unused(gcc_plugin, &args).is_err());
+}
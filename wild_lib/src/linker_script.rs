@@ -137,11 +137,24 @@ impl<'data> Version<'data> {
                         break;
                     }
                 }
-            } else if line == "global:" {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("global:") {
                 section = Some(VersionRuleSection::Global);
-            } else if line == "local:" {
+                line = rest.trim_start();
+            } else if let Some(rest) = line.strip_prefix("local:") {
                 section = Some(VersionRuleSection::Local);
-            } else if let Some(pattern) = line.strip_suffix(';') {
+                line = rest.trim_start();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            // Patterns may share a line with each other or with the section label, e.g.
+            // `local: *;`.
+            let Some(patterns) = line.strip_suffix(';') else {
+                bail!("Unsupported version script line `{line}`");
+            };
+            for pattern in patterns.split(';').map(str::trim) {
                 match section {
                     Some(VersionRuleSection::Global) => {
                         version.globals.push(SymbolMatcher::from_pattern(pattern)?)
@@ -151,8 +164,6 @@ impl<'data> Version<'data> {
                     }
                     None => bail!("Expected global/local, found `{line}`"),
                 }
-            } else if !line.is_empty() {
-                bail!("Unsupported version script line `{line}`");
             }
         }
         bail!("Missing close '}}' in version script");
@@ -454,4 +465,34 @@ mod tests {
         );
         assert!(version.locals.matches_all);
     }
+
+    #[test]
+    fn test_parse_version_script_same_line() {
+        let data = VersionScriptData {
+            raw: r#"{
+                    global: foo; bar*;
+                    local: *;
+                    };"#
+            .into(),
+        };
+        let script = VersionScript::parse(&data).unwrap();
+        let version = script.version.unwrap();
+        assert_equal(
+            version
+                .globals
+                .exact
+                .iter()
+                .map(|s| std::str::from_utf8(s.bytes()).unwrap()),
+            ["foo"],
+        );
+        assert_equal(
+            version
+                .globals
+                .prefixes
+                .iter()
+                .map(|s| std::str::from_utf8(s).unwrap()),
+            ["bar"],
+        );
+        assert!(version.locals.matches_all);
+    }
 }
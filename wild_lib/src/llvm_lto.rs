@@ -0,0 +1,736 @@
+//! Link-time optimisation of LLVM bitcode without a linker plugin, by loading LLVM's `libLTO` and
+//! using its C API, much as lld does with LLVM's LTO library. This means that `clang -flto` works
+//! without having to pass `LLVMgold.so`.
+//!
+//! We read the symbols of each bitcode module, then resolve symbols exactly as we do for files
+//! claimed by a plugin. Modules that end up loaded are compiled, preserving the symbols that are
+//! referenced from outside the IR. If all of them have ThinLTO summaries, we use ThinLTO, which
+//! compiles modules in parallel, otherwise we merge them and do regular LTO.
+
+use crate::archive_splitter::InputBytes;
+use crate::args::Args;
+use crate::args::OutputKind;
+use crate::args::RelocationModel;
+use crate::error::Result;
+use crate::file_kind::IrKind;
+use crate::input_data::InputData;
+use crate::linker_plugin;
+use crate::linker_plugin::LtoOutputs;
+use crate::linker_plugin::Symbol;
+use crate::linker_plugin::LDPK_COMMON;
+use crate::linker_plugin::LDPK_DEF;
+use crate::linker_plugin::LDPK_UNDEF;
+use crate::linker_plugin::LDPK_WEAKDEF;
+use crate::linker_plugin::LDPK_WEAKUNDEF;
+use crate::linker_plugin::LDPR_PREVAILING_DEF;
+use crate::linker_plugin::LDPR_PREVAILING_DEF_IRONLY_EXP;
+use crate::linker_plugin::LDPV_DEFAULT;
+use crate::linker_plugin::LDPV_HIDDEN;
+use crate::linker_plugin::LDPV_PROTECTED;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context as _;
+use libc::c_char;
+use libc::c_int;
+use libc::c_uint;
+use libc::c_void;
+use object::read::elf::FileHeader as _;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// If set, the path of the `libLTO` to use, rather than searching for one.
+const LIBLTO_ENV: &str = "WILD_LIBLTO";
+
+const LTO_SYMBOL_DEFINITION_MASK: c_uint = 0x700;
+const LTO_SYMBOL_DEFINITION_REGULAR: c_uint = 0x100;
+const LTO_SYMBOL_DEFINITION_TENTATIVE: c_uint = 0x200;
+const LTO_SYMBOL_DEFINITION_WEAK: c_uint = 0x300;
+const LTO_SYMBOL_DEFINITION_UNDEFINED: c_uint = 0x400;
+const LTO_SYMBOL_DEFINITION_WEAKUNDEF: c_uint = 0x500;
+const LTO_SYMBOL_SCOPE_MASK: c_uint = 0x3800;
+const LTO_SYMBOL_SCOPE_INTERNAL: c_uint = 0x800;
+const LTO_SYMBOL_SCOPE_HIDDEN: c_uint = 0x1000;
+const LTO_SYMBOL_SCOPE_PROTECTED: c_uint = 0x2000;
+const LTO_SYMBOL_COMDAT: c_uint = 0x4000;
+
+const LTO_DEBUG_MODEL_DWARF: c_int = 1;
+
+const LTO_CODEGEN_PIC_MODEL_STATIC: c_int = 0;
+const LTO_CODEGEN_PIC_MODEL_DYNAMIC: c_int = 1;
+
+type Module = *mut c_void;
+type CodeGen = *mut c_void;
+
+#[repr(C)]
+struct ObjectBuffer {
+    buffer: *const c_char,
+    size: usize,
+}
+
+/// Declares the functions that we use from `libLTO`, each as a field of `LibLto`.
+macro_rules! lto_functions {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        struct LibLto {
+            $($name: unsafe extern "C" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl LibLto {
+            fn from_handle(library: *mut c_void) -> Result<Self> {
+                Ok(Self {
+                    $($name: {
+                        let name = concat!(stringify!($name), "\0");
+                        let address = unsafe { libc::dlsym(library, name.as_ptr().cast()) };
+                        if address.is_null() {
+                            bail!("libLTO doesn't define `{}`", stringify!($name));
+                        }
+                        unsafe {
+                            std::mem::transmute::<
+                                *mut c_void,
+                                unsafe extern "C" fn($($arg),*) $(-> $ret)?,
+                            >(address)
+                        }
+                    },)*
+                })
+            }
+        }
+    };
+}
+
+lto_functions! {
+    lto_get_error_message: fn() -> *const c_char;
+    lto_module_create_in_codegen_context: fn(*const c_void, usize, *const c_char, CodeGen) -> Module;
+    lto_module_dispose: fn(Module);
+    lto_module_is_thinlto: fn(Module) -> bool;
+    lto_module_get_num_symbols: fn(Module) -> c_uint;
+    lto_module_get_symbol_name: fn(Module, c_uint) -> *const c_char;
+    lto_module_get_symbol_attribute: fn(Module, c_uint) -> c_uint;
+    lto_codegen_create: fn() -> CodeGen;
+    lto_codegen_dispose: fn(CodeGen);
+    lto_codegen_add_module: fn(CodeGen, Module) -> bool;
+    lto_codegen_set_debug_model: fn(CodeGen, c_int) -> bool;
+    lto_codegen_set_pic_model: fn(CodeGen, c_int) -> bool;
+    lto_codegen_add_must_preserve_symbol: fn(CodeGen, *const c_char);
+    lto_codegen_compile: fn(CodeGen, *mut usize) -> *const c_void;
+    thinlto_create_codegen: fn() -> CodeGen;
+    thinlto_codegen_dispose: fn(CodeGen);
+    thinlto_codegen_add_module: fn(CodeGen, *const c_char, *const c_char, c_int);
+    thinlto_codegen_add_must_preserve_symbol: fn(CodeGen, *const c_char, c_int);
+    thinlto_codegen_set_pic_model: fn(CodeGen, c_int) -> bool;
+    thinlto_codegen_set_cache_dir: fn(CodeGen, *const c_char);
+    thinlto_codegen_process: fn(CodeGen);
+    thinlto_module_get_num_objects: fn(CodeGen) -> c_uint;
+    thinlto_module_get_object: fn(CodeGen, c_uint) -> ObjectBuffer;
+    thinlto_debug_options: fn(*const *const c_char, c_int);
+    lto_codegen_set_cpu: fn(CodeGen, *const c_char);
+    thinlto_codegen_set_cpu: fn(CodeGen, *const c_char);
+}
+
+impl LibLto {
+    fn load() -> Result<Self> {
+        let mut errors = Vec::new();
+        for path in liblto_candidates() {
+            let c_path = CString::new(path.as_os_str().as_encoded_bytes())?;
+            let library = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+            if library.is_null() {
+                errors.push(linker_plugin::dl_error());
+                continue;
+            }
+            // We never unload the library. LLVM registers global state that doesn't expect it.
+            return LibLto::from_handle(library)
+                .with_context(|| format!("Failed to load `{}`", path.display()));
+        }
+        bail!("Couldn't find libLTO: {}", errors.join("; "))
+    }
+
+    fn error(&self, what: &str) -> anyhow::Error {
+        let message = unsafe { (self.lto_get_error_message)() };
+        if message.is_null() {
+            return anyhow!("{what}");
+        }
+        anyhow!(
+            "{what}: {}",
+            unsafe { CStr::from_ptr(message) }.to_string_lossy()
+        )
+    }
+}
+
+/// Returns where we look for `libLTO`, in order of preference: `WILD_LIBLTO` if that's set,
+/// otherwise the library search path, then the newest of the LLVM installations that
+/// Debian-style distributions use, then the usual library directories.
+fn liblto_candidates() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os(LIBLTO_ENV) {
+        return vec![PathBuf::from(path)];
+    }
+    let mut candidates = vec![PathBuf::from("libLTO.so")];
+    candidates.extend(versioned_installations(Path::new("/usr/lib")));
+    candidates.push(PathBuf::from("/usr/lib64/libLTO.so"));
+    candidates.push(PathBuf::from("/usr/local/lib/libLTO.so"));
+    candidates
+}
+
+/// Finds LLVM's gold plugin, which LLVM installs alongside `libLTO`.
+pub(crate) fn find_gold_plugin() -> Result<PathBuf> {
+    liblto_candidates()
+        .iter()
+        .filter_map(|path| Some(path.parent()?.join("LLVMgold.so")))
+        .find(|path| path.exists())
+        .context("LLVMgold.so isn't installed alongside libLTO")
+}
+
+/// Returns paths like `/usr/lib/llvm-17/lib/libLTO.so` within `dir`, newest first.
+fn versioned_installations(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut versions: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let version = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("llvm-")?
+                .parse()
+                .ok()?;
+            let path = entry.path().join("lib/libLTO.so");
+            path.exists().then_some((version, path))
+        })
+        .collect();
+    versions.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+    versions.into_iter().map(|(_, path)| path).collect()
+}
+
+/// A bitcode module that we've read with `libLTO`.
+struct LoadedModule<'data> {
+    handle: Module,
+    index: usize,
+    name: CString,
+    bitcode: &'data [u8],
+    symbols: Vec<Symbol>,
+    is_thin: bool,
+}
+
+/// Compiles the LLVM bitcode in `candidates`, returning objects to link in its place. Reports an
+/// error if any of the other candidates can't be linked without a plugin.
+pub(crate) fn run(
+    args: &Args,
+    input_data: &InputData,
+    inputs: &[InputBytes],
+    candidates: &[(usize, IrKind)],
+) -> Result<Option<LtoOutputs>> {
+    let bitcode_inputs = bitcode_inputs(args, candidates);
+    linker_plugin::check_unclaimed(
+        inputs,
+        candidates,
+        &bitcode_inputs.iter().map(|(index, _)| *index).collect(),
+    )?;
+    if bitcode_inputs.is_empty() {
+        return Ok(None);
+    }
+    let lib = LibLto::load().with_context(|| {
+        format!(
+            "`{}` contains LLVM IR, which needs either libLTO or a linker plugin (-plugin)",
+            inputs[bitcode_inputs[0].0]
+        )
+    })?;
+
+    set_options(&lib, args)?;
+    // Modules that we pass to regular LTO need to be read in its context, so we create it up-front
+    // even though we might end up using ThinLTO. It must outlive the modules.
+    let codegen = LtoCodeGen::new(&lib)?;
+    let mut modules = Modules {
+        lib: &lib,
+        modules: Vec::new(),
+    };
+    for &(index, kind) in &bitcode_inputs {
+        modules
+            .modules
+            .push(read_module(&lib, &codegen, &inputs[index], index, kind)?);
+    }
+
+    let symbols = modules
+        .modules
+        .iter()
+        .map(|module| (module.index, module.symbols.as_slice()))
+        .collect();
+    let resolutions = linker_plugin::resolve(args, input_data, inputs, &symbols)?;
+
+    let loaded: Vec<&LoadedModule> = modules
+        .modules
+        .iter()
+        .filter(|module| resolutions.contains_key(&module.index))
+        .collect();
+    let mut preserved = HashSet::new();
+    for module in &loaded {
+        for (symbol, resolution) in module.symbols.iter().zip(&resolutions[&module.index]) {
+            if *resolution == LDPR_PREVAILING_DEF || *resolution == LDPR_PREVAILING_DEF_IRONLY_EXP {
+                preserved.insert(CString::new(symbol.name.as_slice())?);
+            }
+        }
+    }
+    let pic_model = pic_model(args.output_kind);
+
+    let objects = if loaded.is_empty() {
+        Vec::new()
+    } else if loaded.iter().all(|module| module.is_thin) {
+        compile_thin(&lib, args, &loaded, &preserved, pic_model)?
+    } else {
+        compile_full(&lib, &codegen, args, &loaded, &preserved, pic_model)?
+    };
+
+    Ok(Some(LtoOutputs::from_objects(
+        inputs,
+        bitcode_inputs.iter().map(|(index, _)| *index),
+        objects,
+    )))
+}
+
+/// Returns the candidates that we compile ourselves. We leave the machine code in fat objects alone
+/// unless asked to use their bitcode, and we can't do anything with GCC's IR.
+fn bitcode_inputs(args: &Args, candidates: &[(usize, IrKind)]) -> Vec<(usize, IrKind)> {
+    candidates
+        .iter()
+        .copied()
+        .filter(|(_, kind)| match kind {
+            IrKind::LlvmBitcode => true,
+            IrKind::LlvmFat => args.fat_lto_objects,
+            IrKind::GccSlim | IrKind::GccFat => false,
+        })
+        .collect()
+}
+
+fn pic_model(output_kind: OutputKind) -> c_int {
+    match output_kind {
+        OutputKind::SharedObject
+        | OutputKind::StaticExecutable(RelocationModel::Relocatable)
+        | OutputKind::DynamicExecutable(RelocationModel::Relocatable) => {
+            LTO_CODEGEN_PIC_MODEL_DYNAMIC
+        }
+        _ => LTO_CODEGEN_PIC_MODEL_STATIC,
+    }
+}
+
+/// A code generator for regular LTO, disposed of when dropped.
+struct LtoCodeGen<'lib> {
+    lib: &'lib LibLto,
+    handle: CodeGen,
+}
+
+impl<'lib> LtoCodeGen<'lib> {
+    fn new(lib: &'lib LibLto) -> Result<Self> {
+        let handle = unsafe { (lib.lto_codegen_create)() };
+        if handle.is_null() {
+            return Err(lib.error("Failed to create LTO code generator"));
+        }
+        Ok(Self { lib, handle })
+    }
+}
+
+impl Drop for LtoCodeGen<'_> {
+    fn drop(&mut self) {
+        unsafe { (self.lib.lto_codegen_dispose)(self.handle) };
+    }
+}
+
+/// Owns the modules that we've read, disposing of them when dropped.
+struct Modules<'lib, 'data> {
+    lib: &'lib LibLto,
+    modules: Vec<LoadedModule<'data>>,
+}
+
+impl Drop for Modules<'_, '_> {
+    fn drop(&mut self) {
+        for module in &self.modules {
+            unsafe { (self.lib.lto_module_dispose)(module.handle) };
+        }
+    }
+}
+
+fn read_module<'data>(
+    lib: &LibLto,
+    codegen: &LtoCodeGen,
+    input: &InputBytes<'data>,
+    index: usize,
+    kind: IrKind,
+) -> Result<LoadedModule<'data>> {
+    let bitcode = match kind {
+        IrKind::LlvmFat => embedded_bitcode(input.data)
+            .with_context(|| format!("Failed to find bitcode in `{input}`"))?,
+        _ => input.data,
+    };
+    let name = CString::new(input.to_string())?;
+    let handle = unsafe {
+        (lib.lto_module_create_in_codegen_context)(
+            bitcode.as_ptr().cast(),
+            bitcode.len(),
+            name.as_ptr(),
+            codegen.handle,
+        )
+    };
+    if handle.is_null() {
+        return Err(lib.error(&format!("Failed to read bitcode from `{input}`")));
+    }
+    let mut module = LoadedModule {
+        handle,
+        index,
+        name,
+        bitcode,
+        symbols: Vec::new(),
+        is_thin: unsafe { (lib.lto_module_is_thinlto)(handle) },
+    };
+    let num_symbols = unsafe { (lib.lto_module_get_num_symbols)(handle) };
+    for i in 0..num_symbols {
+        let attributes = unsafe { (lib.lto_module_get_symbol_attribute)(handle, i) };
+        let Some((def, visibility)) = symbol_kind(attributes)
+            .with_context(|| format!("Failed to read symbols from `{input}`"))?
+        else {
+            continue;
+        };
+        let name = unsafe { CStr::from_ptr((lib.lto_module_get_symbol_name)(handle, i)) };
+        module.symbols.push(Symbol {
+            name: name.to_bytes().to_owned(),
+            def,
+            visibility,
+            size: 0,
+            in_comdat: attributes & LTO_SYMBOL_COMDAT != 0,
+        });
+    }
+    Ok(module)
+}
+
+/// Converts the attributes that `libLTO` reports for a symbol into the definition kind and
+/// visibility that a plugin would report. Returns None for symbols that are internal to the module.
+fn symbol_kind(attributes: c_uint) -> Result<Option<(u8, c_int)>> {
+    let scope = attributes & LTO_SYMBOL_SCOPE_MASK;
+    if scope == LTO_SYMBOL_SCOPE_INTERNAL {
+        return Ok(None);
+    }
+    let def = match attributes & LTO_SYMBOL_DEFINITION_MASK {
+        LTO_SYMBOL_DEFINITION_REGULAR => LDPK_DEF,
+        LTO_SYMBOL_DEFINITION_TENTATIVE => LDPK_COMMON,
+        LTO_SYMBOL_DEFINITION_WEAK => LDPK_WEAKDEF,
+        LTO_SYMBOL_DEFINITION_UNDEFINED => LDPK_UNDEF,
+        LTO_SYMBOL_DEFINITION_WEAKUNDEF => LDPK_WEAKUNDEF,
+        other => bail!("Unknown definition kind {other:#x}"),
+    };
+    let visibility = match scope {
+        LTO_SYMBOL_SCOPE_HIDDEN => LDPV_HIDDEN,
+        LTO_SYMBOL_SCOPE_PROTECTED => LDPV_PROTECTED,
+        _ => LDPV_DEFAULT,
+    };
+    Ok(Some((def, visibility)))
+}
+
+/// Returns the contents of the `.llvm.lto` section of a fat LTO object.
+fn embedded_bitcode(data: &[u8]) -> Result<&[u8]> {
+    let e = LittleEndian;
+    let header = object::elf::FileHeader64::<LittleEndian>::parse(data)?;
+    let sections = header.sections(e, data)?;
+    let section = sections
+        .iter()
+        .find(|section| sections.section_name(e, section) == Ok(b".llvm.lto"))
+        .context("Missing .llvm.lto section")?;
+    Ok(section.data(e, data)?)
+}
+
+/// Merges all the modules, then optimises and compiles the result to a single object.
+#[tracing::instrument(skip_all, name = "Full LTO")]
+fn compile_full(
+    lib: &LibLto,
+    codegen: &LtoCodeGen,
+    args: &Args,
+    modules: &[&LoadedModule],
+    preserved: &HashSet<CString>,
+    pic_model: c_int,
+) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let codegen = codegen.handle;
+    for module in modules {
+        if unsafe { (lib.lto_codegen_add_module)(codegen, module.handle) } {
+            return Err(lib.error(&format!(
+                "Failed to add `{}` to LTO",
+                module.name.to_string_lossy()
+            )));
+        }
+    }
+    unsafe {
+        (lib.lto_codegen_set_debug_model)(codegen, LTO_DEBUG_MODEL_DWARF);
+        (lib.lto_codegen_set_pic_model)(codegen, pic_model);
+    }
+    if let Some(cpu) = &args.lto_cpu {
+        let cpu = CString::new(cpu.as_str())?;
+        unsafe { (lib.lto_codegen_set_cpu)(codegen, cpu.as_ptr()) };
+    }
+    for symbol in preserved {
+        unsafe { (lib.lto_codegen_add_must_preserve_symbol)(codegen, symbol.as_ptr()) };
+    }
+    let mut length = 0;
+    let object = unsafe { (lib.lto_codegen_compile)(codegen, &mut length) };
+    if object.is_null() {
+        return Err(lib.error("LTO code generation failed"));
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(object.cast::<u8>(), length) };
+    Ok(vec![(output_name(args, None), bytes.to_owned())])
+}
+
+/// Compiles each module separately, using the ThinLTO summaries to import what it needs from the
+/// others. `libLTO` runs the backends in parallel, using as many threads as we do unless
+/// `--thinlto-jobs` says otherwise.
+#[tracing::instrument(skip_all, name = "ThinLTO")]
+fn compile_thin(
+    lib: &LibLto,
+    args: &Args,
+    modules: &[&LoadedModule],
+    preserved: &HashSet<CString>,
+    pic_model: c_int,
+) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let codegen = unsafe { (lib.thinlto_create_codegen)() };
+    if codegen.is_null() {
+        return Err(lib.error("Failed to create ThinLTO code generator"));
+    }
+    let result = (|| {
+        unsafe { (lib.thinlto_codegen_set_pic_model)(codegen, pic_model) };
+        if let Some(cpu) = &args.lto_cpu {
+            let cpu = CString::new(cpu.as_str())?;
+            unsafe { (lib.thinlto_codegen_set_cpu)(codegen, cpu.as_ptr()) };
+        }
+        if let Some(dir) = &args.thinlto_cache_dir {
+            // libLTO fails if the cache directory doesn't exist.
+            std::fs::create_dir_all(dir).with_context(|| {
+                format!(
+                    "Failed to create ThinLTO cache directory `{}`",
+                    dir.display()
+                )
+            })?;
+            let dir = CString::new(dir.as_os_str().as_encoded_bytes())?;
+            unsafe { (lib.thinlto_codegen_set_cache_dir)(codegen, dir.as_ptr()) };
+        }
+        for module in modules {
+            unsafe {
+                (lib.thinlto_codegen_add_module)(
+                    codegen,
+                    module.name.as_ptr(),
+                    module.bitcode.as_ptr().cast(),
+                    c_int::try_from(module.bitcode.len())?,
+                );
+            }
+        }
+        for symbol in preserved {
+            let length = c_int::try_from(symbol.as_bytes().len())?;
+            unsafe {
+                (lib.thinlto_codegen_add_must_preserve_symbol)(codegen, symbol.as_ptr(), length)
+            };
+        }
+        unsafe { (lib.thinlto_codegen_process)(codegen) };
+        let num_objects = unsafe { (lib.thinlto_module_get_num_objects)(codegen) };
+        if num_objects == 0 {
+            return Err(lib.error("ThinLTO code generation failed"));
+        }
+        (0..num_objects)
+            .map(|i| {
+                let object = unsafe { (lib.thinlto_module_get_object)(codegen, i) };
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(object.buffer.cast::<u8>(), object.size) };
+                Ok((output_name(args, Some(i)), bytes.to_owned()))
+            })
+            .collect()
+    })();
+    unsafe { (lib.thinlto_codegen_dispose)(codegen) };
+    result
+}
+
+/// Sets the options that `libLTO` only takes as command-line options: how many threads ThinLTO
+/// uses and the optimisation level. They're read when code generators are created, so this needs
+/// to happen first. LLVM's options can only be parsed once per process, so we don't try to change
+/// them later.
+fn set_options(lib: &LibLto, args: &Args) -> Result {
+    static SET: std::sync::Once = std::sync::Once::new();
+    let options = liblto_options(args)
+        .into_iter()
+        .map(CString::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let pointers: Vec<*const c_char> = options.iter().map(|option| option.as_ptr()).collect();
+    let count = c_int::try_from(pointers.len())?;
+    SET.call_once(|| unsafe { (lib.thinlto_debug_options)(pointers.as_ptr(), count) });
+    Ok(())
+}
+
+fn liblto_options(args: &Args) -> Vec<String> {
+    let num_threads = args.thinlto_jobs.unwrap_or(args.num_threads);
+    let mut options = vec![format!("-threads={num_threads}")];
+    if let Some(level) = args.lto_opt_level {
+        options.push(format!("-O{level}"));
+    }
+    options
+}
+
+/// Returns the name that we use in diagnostics for an object produced by LTO. The objects
+/// themselves are only ever in memory.
+fn output_name(args: &Args, index: Option<c_uint>) -> PathBuf {
+    let mut name = args.output.as_os_str().to_owned();
+    match index {
+        Some(index) => name.push(format!(".lto.{index}.o")),
+        None => name.push(".lto.o"),
+    }
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Action;
+
+    fn parse(input: &[&str]) -> Args {
+        let Action::Link(args) = crate::args::parse(input.iter()).unwrap() else {
+            panic!("Unexpected action");
+        };
+        args
+    }
+
+    #[test]
+    fn test_bitcode_inputs() {
+        let candidates = [
+            (0, IrKind::LlvmBitcode),
+            (1, IrKind::LlvmFat),
+            (2, IrKind::GccSlim),
+            (3, IrKind::GccFat),
+        ];
+        assert_eq!(
+            bitcode_inputs(&parse(&["a.o"]), &candidates),
+            [(0, IrKind::LlvmBitcode)]
+        );
+        assert_eq!(
+            bitcode_inputs(&parse(&["--fat-lto-objects", "a.o"]), &candidates),
+            [(0, IrKind::LlvmBitcode), (1, IrKind::LlvmFat)]
+        );
+    }
+
+    #[test]
+    fn test_pic_model() {
+        let model = |input: &[&str]| pic_model(parse(input).output_kind);
+        assert_eq!(model(&["-shared", "a.o"]), LTO_CODEGEN_PIC_MODEL_DYNAMIC);
+        assert_eq!(model(&["-pie", "a.o"]), LTO_CODEGEN_PIC_MODEL_DYNAMIC);
+        assert_eq!(
+            model(&["-static", "-pie", "--no-dynamic-linker", "a.o"]),
+            LTO_CODEGEN_PIC_MODEL_DYNAMIC
+        );
+        assert_eq!(model(&["a.o"]), LTO_CODEGEN_PIC_MODEL_STATIC);
+        assert_eq!(model(&["-static", "a.o"]), LTO_CODEGEN_PIC_MODEL_STATIC);
+    }
+
+    #[test]
+    fn test_liblto_options() {
+        assert_eq!(
+            liblto_options(&parse(&["--threads=8", "a.o"])),
+            ["-threads=8"]
+        );
+        assert_eq!(
+            liblto_options(&parse(&[
+                "--threads=8",
+                "--thinlto-jobs=2",
+                "--lto-O2",
+                "a.o"
+            ])),
+            ["-threads=2", "-O2"]
+        );
+        // What clang passes when it expects to be talking to lld, plus a flag that we ignore.
+        assert_eq!(
+            liblto_options(&parse(&[
+                "--threads=8",
+                "-plugin-opt=O1",
+                "-plugin-opt=jobs=3",
+                "-plugin-opt=thinlto",
+                "-flto=thin",
+                "a.o"
+            ])),
+            ["-threads=3", "-O1"]
+        );
+    }
+
+    #[test]
+    fn test_symbol_kind() {
+        assert_eq!(
+            symbol_kind(LTO_SYMBOL_DEFINITION_REGULAR).unwrap(),
+            Some((LDPK_DEF, LDPV_DEFAULT))
+        );
+        assert_eq!(
+            symbol_kind(LTO_SYMBOL_DEFINITION_WEAK | LTO_SYMBOL_SCOPE_HIDDEN).unwrap(),
+            Some((LDPK_WEAKDEF, LDPV_HIDDEN))
+        );
+        assert_eq!(
+            symbol_kind(
+                LTO_SYMBOL_DEFINITION_TENTATIVE | LTO_SYMBOL_SCOPE_PROTECTED | LTO_SYMBOL_COMDAT
+            )
+            .unwrap(),
+            Some((LDPK_COMMON, LDPV_PROTECTED))
+        );
+        assert_eq!(
+            symbol_kind(LTO_SYMBOL_DEFINITION_UNDEFINED).unwrap(),
+            Some((LDPK_UNDEF, LDPV_DEFAULT))
+        );
+        assert_eq!(
+            symbol_kind(LTO_SYMBOL_DEFINITION_WEAKUNDEF).unwrap(),
+            Some((LDPK_WEAKUNDEF, LDPV_DEFAULT))
+        );
+        assert_eq!(
+            symbol_kind(LTO_SYMBOL_DEFINITION_REGULAR | LTO_SYMBOL_SCOPE_INTERNAL).unwrap(),
+            None
+        );
+        assert!(symbol_kind(0x600).is_err());
+    }
+
+    #[test]
+    fn test_output_name() {
+        let args = parse(&["-o", "out/app", "a.o"]);
+        assert_eq!(output_name(&args, None), Path::new("out/app.lto.o"));
+        assert_eq!(output_name(&args, Some(2)), Path::new("out/app.lto.2.o"));
+    }
+
+    #[test]
+    fn test_embedded_bitcode() {
+        use object::write;
+
+        let mut obj = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::X86_64,
+            object::Endianness::Little,
+        );
+        let text = obj.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+        obj.append_section_data(text, &[0xc3], 1);
+        let data = obj.write().unwrap();
+        assert!(embedded_bitcode(&data).is_err());
+
+        let lto = obj.add_section(
+            Vec::new(),
+            b".llvm.lto".to_vec(),
+            object::SectionKind::Metadata,
+        );
+        obj.append_section_data(lto, b"BC\xc0\xde", 1);
+        let data = obj.write().unwrap();
+        assert_eq!(embedded_bitcode(&data).unwrap(), b"BC\xc0\xde");
+    }
+
+    #[test]
+    fn test_versioned_installations() {
+        let dir = std::env::temp_dir().join(format!("wild-liblto-test-{}", std::process::id()));
+        for name in ["llvm-9", "llvm-14", "llvm-15", "llvm-tools"] {
+            std::fs::create_dir_all(dir.join(name).join("lib")).unwrap();
+        }
+        // llvm-15 is only partially installed, so has no libLTO.
+        for name in ["llvm-9", "llvm-14", "llvm-tools"] {
+            std::fs::write(dir.join(name).join("lib/libLTO.so"), b"").unwrap();
+        }
+        assert_eq!(
+            versioned_installations(&dir),
+            [
+                dir.join("llvm-14/lib/libLTO.so"),
+                dir.join("llvm-9/lib/libLTO.so")
+            ]
+        );
+        assert!(versioned_installations(&dir.join("missing")).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,494 @@
+//! Writes a map file (`-Map`) describing the layout of the output. By default, this uses the text
+//! format of GNU ld. The file lists which archive members were loaded and why, which input sections
+//! were discarded, then for each output section, its address and size followed by the input
+//! sections placed in it and the global symbols that they define. With `--map-format=json`, we
+//! write the same information, plus the program segments, as JSON for consumption by tools.
+
+use crate::args::MapFormat;
+use crate::error::Result;
+use crate::input_data::InputRef;
+use crate::json::Json;
+use crate::layout::FileLayout;
+use crate::layout::Layout;
+use crate::layout::ObjectLayout;
+use crate::output_section_id::OrderEvent;
+use crate::output_section_id::OutputSectionId;
+use crate::output_section_map::OutputSectionMap;
+use crate::resolution::SectionSlot;
+use anyhow::Context as _;
+use linker_utils::elf::segment_type_to_string;
+use linker_utils::elf::shf;
+use linker_utils::elf::SectionFlags;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::io::Write as _;
+use std::path::Path;
+
+/// The column at which the requesting file is written in the archive member section.
+const REQUESTER_COLUMN: usize = 30;
+
+/// The column at which addresses are written in the memory map.
+const ADDRESS_COLUMN: usize = 16;
+
+/// An archive member that we loaded, together with the first reference to it that we found.
+pub(crate) struct ArchiveMember {
+    pub(crate) member: String,
+    pub(crate) requester: String,
+    pub(crate) symbol: String,
+}
+
+struct DiscardedSection {
+    name: String,
+    size: u64,
+    input: String,
+}
+
+struct OutputSection {
+    id: OutputSectionId,
+    inputs: Vec<InputSection>,
+}
+
+struct InputSection {
+    address: u64,
+    size: u64,
+    name: String,
+    input: String,
+
+    /// Global symbols defined by the section, with their addresses.
+    symbols: Vec<(u64, String)>,
+}
+
+#[tracing::instrument(skip_all, name = "Write map file")]
+pub(crate) fn write(layout: &Layout, path: &Path) -> Result {
+    write_to(layout, path).with_context(|| format!("Failed to write map file `{}`", path.display()))
+}
+
+fn write_to(layout: &Layout, path: &Path) -> Result {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let archive_members = archive_members(layout)?;
+    let discarded = discarded_sections(layout)?;
+    let memory_map = memory_map(layout)?;
+    match layout.args().map_format {
+        MapFormat::Text => {
+            write_archive_members(&archive_members, &mut out)?;
+            write_discarded_sections(&discarded, &mut out)?;
+            write_memory_map(layout, &memory_map, &mut out)?;
+        }
+        MapFormat::Json => {
+            json_map(layout, archive_members, discarded, memory_map).write(&mut out, 0)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn objects<'a, 'data>(layout: &'a Layout<'data>) -> impl Iterator<Item = &'a ObjectLayout<'data>> {
+    layout
+        .group_layouts
+        .iter()
+        .flat_map(|group| &group.files)
+        .filter_map(|file| match file {
+            FileLayout::Object(object) => Some(object),
+            _ => None,
+        })
+}
+
+pub(crate) fn archive_members(layout: &Layout) -> Result<Vec<ArchiveMember>> {
+    // The reasons are stored in a hash map, so sort them to keep our output in input order.
+    let mut reasons = layout.load_reasons.iter().collect::<Vec<_>>();
+    reasons.sort_by_key(|(file_id, _)| **file_id);
+    let mut members = Vec::with_capacity(reasons.len());
+    for (&file_id, reason) in reasons {
+        let (FileLayout::Object(member), FileLayout::Object(requester)) = (
+            layout.file_layout(file_id),
+            layout.file_layout(reason.requester),
+        ) else {
+            continue;
+        };
+        members.push(ArchiveMember {
+            member: display_input(&member.input),
+            requester: display_input(&requester.input),
+            symbol: crate::demangle::display_name(
+                layout.symbol_db.symbol_name(reason.symbol_id)?.bytes(),
+                layout.args(),
+            ),
+        });
+    }
+    Ok(members)
+}
+
+fn discarded_sections(layout: &Layout) -> Result<Vec<DiscardedSection>> {
+    let mut discarded_sections = Vec::new();
+    for object in objects(layout) {
+        for (slot, section) in object.sections.iter().zip(object.object.sections.iter()) {
+            let discarded = match slot {
+                SectionSlot::Unloaded(_)
+                | SectionSlot::MustLoad(_)
+                | SectionSlot::UnloadedDebugInfo(_)
+                | SectionSlot::Folded(_) => true,
+                SectionSlot::Discard => SectionFlags::from_header(section).contains(shf::ALLOC),
+                _ => false,
+            };
+            if !discarded {
+                continue;
+            }
+            // Like GNU ld, don't clutter the list with sections that had nothing in them.
+            let size = object.object.section_size(section)?;
+            if size == 0 {
+                continue;
+            }
+            discarded_sections.push(DiscardedSection {
+                name: String::from_utf8_lossy(object.object.section_name(section)?).into_owned(),
+                size,
+                input: display_input(&object.input),
+            });
+        }
+    }
+    Ok(discarded_sections)
+}
+
+/// Returns the output sections that we're writing, in output order, together with the input
+/// sections placed in them, sorted by address.
+fn memory_map(layout: &Layout) -> Result<Vec<OutputSection>> {
+    let mut inputs_by_section: OutputSectionMap<Vec<InputSection>> =
+        layout.output_sections.new_section_map();
+    for object in objects(layout) {
+        collect_input_sections(layout, object, &mut inputs_by_section)?;
+    }
+
+    let mut output_sections = Vec::new();
+    for event in layout.output_sections.sections_and_segments_events() {
+        let OrderEvent::Section(id) = event else {
+            continue;
+        };
+        // The file header has no name and nothing can be placed in it, so there's nothing useful to
+        // say about it.
+        if layout.output_sections.output_index_of_section(id).is_none()
+            || layout.output_sections.name(id).0.is_empty()
+        {
+            continue;
+        }
+        let mut inputs = core::mem::take(inputs_by_section.get_mut(id));
+        // Sections ordered using call-graph profiles may have been placed ahead of the sections of
+        // earlier files, so we sort by address. The sort is stable, which keeps empty sections in
+        // input order.
+        inputs.sort_by_key(|input| input.address);
+        for input in &mut inputs {
+            input.symbols.sort();
+        }
+        output_sections.push(OutputSection { id, inputs });
+    }
+    Ok(output_sections)
+}
+
+fn collect_input_sections(
+    layout: &Layout,
+    object: &ObjectLayout,
+    inputs_by_section: &mut OutputSectionMap<Vec<InputSection>>,
+) -> Result {
+    let mut input_indexes = vec![None; object.sections.len()];
+    let mut sections = Vec::new();
+    for (index, slot) in object.sections.iter().enumerate() {
+        let (SectionSlot::Loaded(section) | SectionSlot::LoadedDebugInfo(section)) = slot else {
+            continue;
+        };
+        let Some(address) = object.section_resolutions[index].address() else {
+            continue;
+        };
+        input_indexes[index] = Some(sections.len());
+        sections.push((
+            section.part_id.output_section_id(),
+            InputSection {
+                address,
+                size: section.size,
+                name: object
+                    .object
+                    .section_display_name(section.index)
+                    .into_owned(),
+                input: display_input(&object.input),
+                symbols: Vec::new(),
+            },
+        ));
+    }
+
+    for (index, symbol) in object.object.symbols.iter().enumerate() {
+        if symbol.is_local() || symbol.is_undefined(LittleEndian) {
+            continue;
+        }
+        let symbol_index = object::SymbolIndex(index);
+        let Some(section_index) = object.object.symbol_section(symbol, symbol_index)? else {
+            continue;
+        };
+        let Some(input_index) = input_indexes[section_index.0] else {
+            continue;
+        };
+        let symbol_id = object.symbol_id_range.input_to_id(symbol_index);
+        if layout.symbol_db.definition(symbol_id) != symbol_id {
+            continue;
+        }
+        let Some(resolution) = layout.local_symbol_resolution(symbol_id) else {
+            continue;
+        };
+        sections[input_index].1.symbols.push((
+            resolution.raw_value,
+            crate::demangle::display_name(
+                layout.symbol_db.symbol_name(symbol_id)?.bytes(),
+                layout.args(),
+            ),
+        ));
+    }
+
+    for (section_id, input) in sections {
+        inputs_by_section.get_mut(section_id).push(input);
+    }
+    Ok(())
+}
+
+fn write_archive_members(members: &[ArchiveMember], out: &mut impl std::io::Write) -> Result {
+    if members.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "Archive member included to satisfy reference by file (symbol)\n"
+    )?;
+    for member in members {
+        write!(out, "{}", member.member)?;
+        pad_to_column(out, member.member.len(), REQUESTER_COLUMN)?;
+        writeln!(out, "{} ({})", member.requester, member.symbol)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn write_discarded_sections(
+    discarded: &[DiscardedSection],
+    out: &mut impl std::io::Write,
+) -> Result {
+    writeln!(out, "Discarded input sections\n")?;
+    for section in discarded {
+        write_input_section_line(out, &section.name, 0, section.size, &section.input)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn write_memory_map(
+    layout: &Layout,
+    output_sections: &[OutputSection],
+    out: &mut impl std::io::Write,
+) -> Result {
+    writeln!(out, "Memory map\n")?;
+    for section in output_sections {
+        let name = layout.output_sections.display_name(section.id);
+        let section_layout = layout.section_layouts.get(section.id);
+        writeln!(out)?;
+        write!(out, "{name}")?;
+        pad_to_column(out, name.len(), ADDRESS_COLUMN - 1)?;
+        writeln!(
+            out,
+            " 0x{:016x} {:>#10x}",
+            section_layout.mem_offset, section_layout.mem_size
+        )?;
+
+        for input in &section.inputs {
+            write_input_section_line(out, &input.name, input.address, input.size, &input.input)?;
+            for (address, symbol_name) in &input.symbols {
+                pad_to_column(out, 0, ADDRESS_COLUMN)?;
+                writeln!(out, "0x{address:016x}                {symbol_name}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_input_section_line(
+    out: &mut impl std::io::Write,
+    name: &str,
+    address: u64,
+    size: u64,
+    input: &str,
+) -> Result {
+    write!(out, " {name}")?;
+    pad_to_column(out, name.len() + 1, ADDRESS_COLUMN - 1)?;
+    writeln!(out, " 0x{address:016x} {size:>#10x} {input}")?;
+    Ok(())
+}
+
+/// Writes spaces up to `column`. If we're already past it, we start a new line first.
+fn pad_to_column(out: &mut impl std::io::Write, current: usize, column: usize) -> Result {
+    if current >= column {
+        writeln!(out)?;
+        write!(out, "{:column$}", "")?;
+    } else {
+        write!(out, "{:1$}", "", column - current)?;
+    }
+    Ok(())
+}
+
+/// Formats an input the way GNU ld does, e.g. `libfoo.a(foo.o)` for archive members.
+pub(crate) fn display_input(input: &InputRef) -> String {
+    let filename = input.file.filename.display();
+    match &input.entry {
+        Some(entry) => format!(
+            "{filename}({})",
+            String::from_utf8_lossy(entry.identifier.as_slice())
+        ),
+        None => filename.to_string(),
+    }
+}
+
+fn json_map(
+    layout: &Layout,
+    archive_members: Vec<ArchiveMember>,
+    discarded: Vec<DiscardedSection>,
+    output_sections: Vec<OutputSection>,
+) -> Json {
+    // Work out which output sections go in which segments.
+    let mut segment_sections: Vec<Vec<String>> =
+        vec![Vec::new(); crate::program_segments::MAX_SEGMENTS];
+    let mut active_segments = Vec::new();
+    for event in layout.output_sections.sections_and_segments_events() {
+        match event {
+            OrderEvent::SegmentStart(id) => active_segments.push(id),
+            OrderEvent::SegmentEnd(id) => active_segments.retain(|active| *active != id),
+            OrderEvent::Section(section_id) => {
+                if layout
+                    .output_sections
+                    .output_index_of_section(section_id)
+                    .is_some()
+                {
+                    for segment_id in &active_segments {
+                        segment_sections[segment_id.as_usize()]
+                            .push(layout.output_sections.display_name(section_id).into_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    let segments = layout
+        .segment_layouts
+        .segments
+        .iter()
+        .map(|segment| {
+            Json::Object(vec![
+                (
+                    "type",
+                    Json::String(segment_type_to_string(segment.id.segment_type()).into_owned()),
+                ),
+                (
+                    "flags",
+                    Json::String(segment_flags(segment.id.segment_flags())),
+                ),
+                ("address", Json::Number(segment.sizes.mem_offset)),
+                ("size", Json::Number(segment.sizes.mem_size)),
+                (
+                    "file_offset",
+                    Json::Number(segment.sizes.file_offset as u64),
+                ),
+                ("file_size", Json::Number(segment.sizes.file_size as u64)),
+                (
+                    "sections",
+                    Json::Array(
+                        core::mem::take(&mut segment_sections[segment.id.as_usize()])
+                            .into_iter()
+                            .map(Json::String)
+                            .collect(),
+                    ),
+                ),
+            ])
+        })
+        .collect();
+
+    let sections = output_sections
+        .into_iter()
+        .map(|section| {
+            let section_layout = layout.section_layouts.get(section.id);
+            let inputs = section
+                .inputs
+                .into_iter()
+                .map(|input| {
+                    let symbols = input
+                        .symbols
+                        .into_iter()
+                        .map(|(address, name)| {
+                            Json::Object(vec![
+                                ("name", Json::String(name)),
+                                ("address", Json::Number(address)),
+                            ])
+                        })
+                        .collect();
+                    Json::Object(vec![
+                        ("file", Json::String(input.input)),
+                        ("section", Json::String(input.name)),
+                        ("address", Json::Number(input.address)),
+                        ("size", Json::Number(input.size)),
+                        ("symbols", Json::Array(symbols)),
+                    ])
+                })
+                .collect();
+            Json::Object(vec![
+                (
+                    "name",
+                    Json::String(layout.output_sections.display_name(section.id).into_owned()),
+                ),
+                ("address", Json::Number(section_layout.mem_offset)),
+                ("size", Json::Number(section_layout.mem_size)),
+                (
+                    "file_offset",
+                    Json::Number(section_layout.file_offset as u64),
+                ),
+                ("file_size", Json::Number(section_layout.file_size as u64)),
+                ("alignment", Json::Number(section_layout.alignment.value())),
+                ("inputs", Json::Array(inputs)),
+            ])
+        })
+        .collect();
+
+    let archive_members = archive_members
+        .into_iter()
+        .map(|member| {
+            Json::Object(vec![
+                ("member", Json::String(member.member)),
+                ("referenced_by", Json::String(member.requester)),
+                ("symbol", Json::String(member.symbol)),
+            ])
+        })
+        .collect();
+
+    let discarded = discarded
+        .into_iter()
+        .map(|section| {
+            Json::Object(vec![
+                ("file", Json::String(section.input)),
+                ("section", Json::String(section.name)),
+                ("size", Json::Number(section.size)),
+            ])
+        })
+        .collect();
+
+    Json::Object(vec![
+        (
+            "output",
+            Json::String(layout.args().output.display().to_string()),
+        ),
+        ("segments", Json::Array(segments)),
+        ("sections", Json::Array(sections)),
+        ("archive_members", Json::Array(archive_members)),
+        ("discarded_sections", Json::Array(discarded)),
+    ])
+}
+
+fn segment_flags(flags: u32) -> String {
+    [
+        (object::elf::PF_R, 'R'),
+        (object::elf::PF_W, 'W'),
+        (object::elf::PF_X, 'X'),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags & flag != 0)
+    .map(|(_, c)| c)
+    .collect()
+}
@@ -0,0 +1,222 @@
+//! Handling of the DT_NEEDED entries of the shared objects that we link against. With
+//! `--copy-dt-needed-entries`, those dependencies are added to the link as if they'd been given on
+//! the command line with `--as-needed`. Otherwise, like GNU ld, we don't resolve symbols against
+//! them. We do however look in them when a symbol is left undefined, so that we can tell the user
+//! which library they forgot to link against.
+
+use crate::args::Args;
+use crate::diagnostics::should_warn;
+use crate::diagnostics::WarningCategory;
+use crate::elf::DynamicTagValues;
+use crate::error::Result;
+use crate::file_kind::FileKind;
+use crate::parsing::ParsedInput;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolId;
+use crate::warning;
+use memmap2::Mmap;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Read as _;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Directories that the dynamic loader searches by default. We search these, after the directories
+/// that the user gave us, when looking for the dependencies of shared objects.
+const DEFAULT_LIBRARY_DIRS: &[&str] = &["/lib", "/usr/lib"];
+
+/// Returns the name by which a shared object would be referred to in the DT_NEEDED entries of other
+/// shared objects. That's its DT_SONAME if it has one, otherwise its filename.
+pub(crate) fn name_for_needed<'data>(
+    dt_info: &DynamicTagValues<'data>,
+    filename: &'data [u8],
+) -> &'data [u8] {
+    dt_info
+        .soname
+        .unwrap_or_else(|| filename.rsplit(|b| *b == b'/').next().unwrap_or(filename))
+}
+
+/// Searches for a shared object named in a DT_NEEDED entry of `needed_by`. Like GNU ld, we look in
+/// the `-rpath-link` directories, then the `-rpath` directories, then the library search path and
+/// finally the default directories of the dynamic loader. `$ORIGIN` in the `-rpath-link` and
+/// `-rpath` directories refers to the directory containing `needed_by`.
+pub(crate) fn find_needed(args: &Args, name: &[u8], needed_by: &Path) -> Option<PathBuf> {
+    let name = Path::new(std::str::from_utf8(name).ok()?);
+    if name.components().count() != 1 {
+        return name.exists().then(|| name.to_owned());
+    }
+    let default_dirs = DEFAULT_LIBRARY_DIRS.iter().map(|dir| {
+        args.sysroot
+            .as_deref()
+            .map_or(PathBuf::from(dir), |s| s.join(dir.trim_start_matches('/')))
+    });
+    args.rpath_link
+        .iter()
+        .map(|dir| expand_origin(&dir.to_string_lossy(), needed_by))
+        .chain(
+            args.rpaths
+                .iter()
+                .flat_map(|r| r.split(':'))
+                .map(|dir| expand_origin(dir, needed_by)),
+        )
+        .chain(args.lib_search_path.iter().map(|dir| dir.to_path_buf()))
+        .chain(default_dirs)
+        .map(|dir| dir.join(name))
+        .find(|path| {
+            let exists = path.exists();
+            if args.trace {
+                let outcome = if exists { "succeeded" } else { "failed" };
+                println!("attempt to open {} {outcome}", path.display());
+            }
+            exists
+        })
+}
+
+/// Replaces `$ORIGIN` or `${ORIGIN}` in `dir` with the directory containing `needed_by`.
+fn expand_origin(dir: &str, needed_by: &Path) -> PathBuf {
+    if !dir.contains("$ORIGIN") && !dir.contains("${ORIGIN}") {
+        return PathBuf::from(dir);
+    }
+    let origin = needed_by
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    PathBuf::from(
+        dir.replace("${ORIGIN}", "$ORIGIN")
+            .replace("$ORIGIN", &origin.to_string_lossy()),
+    )
+}
+
+/// Maps the shared object at `path` if it's one, reading just the ELF header first so that we don't
+/// map linker scripts or other files that happen to have the name that we're looking for.
+fn map_shared_object(path: &Path) -> Option<Mmap> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut header = [0; size_of::<crate::elf::FileHeader>()];
+    file.read_exact(&mut header).ok()?;
+    if !matches!(FileKind::identify_bytes(&header), Ok(FileKind::ElfDynamic)) {
+        return None;
+    }
+    // Safety: See `open_input`. We only read the symbol table, and only to produce a warning.
+    unsafe { Mmap::map(&file) }.ok()
+}
+
+/// Warns about undefined symbols that are defined by a dependency of one of our shared objects, but
+/// where that dependency wasn't given to us. `undefined` should contain symbols referenced by
+/// regular objects for which we found no definition.
+pub(crate) fn report_indirect_definitions(symbol_db: &SymbolDb, undefined: &[SymbolId]) -> Result {
+    let mut wanted: HashMap<&[u8], Vec<SymbolId>> = HashMap::new();
+    for &symbol_id in undefined {
+        let ParsedInput::Object(obj) = symbol_db.file(symbol_db.file_id_for_symbol(symbol_id))
+        else {
+            continue;
+        };
+        if obj.is_dynamic() {
+            continue;
+        }
+        let sym = obj.object.symbol(symbol_id.to_input(obj.symbol_id_range))?;
+        if sym.is_weak() {
+            continue;
+        }
+        wanted
+            .entry(obj.object.symbol_name(sym)?)
+            .or_default()
+            .push(symbol_id);
+    }
+    if wanted.is_empty() {
+        return Ok(());
+    }
+
+    // Find the DT_NEEDED entries of our shared objects that don't refer to any of our inputs.
+    let mut input_names = HashSet::new();
+    let mut dependencies = Vec::new();
+    for group in symbol_db.groups {
+        for file in &group.files {
+            let ParsedInput::Object(obj) = file else {
+                continue;
+            };
+            if !obj.is_dynamic() {
+                continue;
+            }
+            let dt_info = DynamicTagValues::read(&obj.object)?;
+            input_names.insert(name_for_needed(&dt_info, obj.input.lib_name()));
+            for needed in dt_info.needed {
+                dependencies.push((needed, obj.input.clone()));
+            }
+        }
+    }
+
+    let mut searched = HashSet::new();
+    for (needed, needed_by) in dependencies {
+        if input_names.contains(needed) || !searched.insert(needed) {
+            continue;
+        }
+        let Some(path) = find_needed(symbol_db.args, needed, &needed_by.file.filename) else {
+            continue;
+        };
+        let Some(data) = map_shared_object(&path) else {
+            continue;
+        };
+        let Ok(object) = crate::elf::File::parse(&data, true) else {
+            continue;
+        };
+        for sym in object.symbols.iter() {
+            if sym.is_undefined(LittleEndian) || sym.is_local() {
+                continue;
+            }
+            let Ok(name) = object.symbol_name(sym) else {
+                continue;
+            };
+            let Some(symbol_ids) = wanted.remove(name) else {
+                continue;
+            };
+            for symbol_id in symbol_ids {
+                let file_id = symbol_db.file_id_for_symbol(symbol_id);
+                let ParsedInput::Object(obj) = symbol_db.file(file_id) else {
+                    continue;
+                };
+                if !should_warn(
+                    symbol_db.args,
+                    WarningCategory::IndirectUndefined,
+                    &obj.input.file.filename,
+                ) {
+                    continue;
+                }
+                warning!(
+                    symbol_db.args.diagnostics,
+                    "{}: undefined reference to `{}`. It's defined in {}, which is needed by {}, \
+                     but {} wasn't given on the command line. Add it to the command line or use \
+                     --copy-dt-needed-entries",
+                    symbol_db.file(file_id),
+                    String::from_utf8_lossy(name),
+                    path.display(),
+                    needed_by,
+                    String::from_utf8_lossy(needed),
+                );
+            }
+        }
+        if wanted.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_expand_origin() {
+    let needed_by = Path::new("/opt/foo/lib/libfoo.so");
+    assert_eq!(
+        expand_origin("$ORIGIN/../deps", needed_by),
+        Path::new("/opt/foo/lib/../deps")
+    );
+    assert_eq!(
+        expand_origin("${ORIGIN}", needed_by),
+        Path::new("/opt/foo/lib")
+    );
+    assert_eq!(
+        expand_origin("$ORIGIN", Path::new("libfoo.so")),
+        Path::new(".")
+    );
+    assert_eq!(expand_origin("/usr/lib", needed_by), Path::new("/usr/lib"));
+}
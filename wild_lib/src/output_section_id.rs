@@ -72,7 +72,6 @@ pub(crate) const SECTION_HEADERS: OutputSectionId = part_id::SECTION_HEADERS.out
 pub(crate) const SHSTRTAB: OutputSectionId = part_id::SHSTRTAB.output_section_id();
 pub(crate) const STRTAB: OutputSectionId = part_id::STRTAB.output_section_id();
 pub(crate) const GOT: OutputSectionId = part_id::GOT.output_section_id();
-pub(crate) const RELA_PLT: OutputSectionId = part_id::RELA_PLT.output_section_id();
 pub(crate) const EH_FRAME: OutputSectionId = part_id::EH_FRAME.output_section_id();
 pub(crate) const EH_FRAME_HDR: OutputSectionId = part_id::EH_FRAME_HDR.output_section_id();
 pub(crate) const DYNAMIC: OutputSectionId = part_id::DYNAMIC.output_section_id();
@@ -83,11 +82,14 @@ pub(crate) const INTERP: OutputSectionId = part_id::INTERP.output_section_id();
 pub(crate) const GNU_VERSION: OutputSectionId = part_id::GNU_VERSION.output_section_id();
 pub(crate) const GNU_VERSION_R: OutputSectionId = part_id::GNU_VERSION_R.output_section_id();
 pub(crate) const PLT_GOT: OutputSectionId = part_id::PLT_GOT.output_section_id();
+pub(crate) const PLT: OutputSectionId = part_id::PLT.output_section_id();
+pub(crate) const GOT_PLT: OutputSectionId = part_id::GOT_PLT.output_section_id();
 
-// These two are multi-part sections, but we can pick any part we wish in order to get the section
-// ID.
+// These are multi-part sections, but we can pick any part we wish in order to get the section ID.
 pub(crate) const SYMTAB: OutputSectionId = part_id::SYMTAB_LOCAL.output_section_id();
 pub(crate) const RELA_DYN: OutputSectionId = part_id::RELA_DYN_RELATIVE.output_section_id();
+pub(crate) const RELA_PLT: OutputSectionId = part_id::RELA_PLT_GENERAL.output_section_id();
+pub(crate) const RELR_DYN: OutputSectionId = part_id::RELR_DYN.output_section_id();
 
 pub(crate) const RODATA: OutputSectionId = OutputSectionId::regular(0);
 pub(crate) const INIT_ARRAY: OutputSectionId = OutputSectionId::regular(1);
@@ -103,14 +105,30 @@ pub(crate) const BSS: OutputSectionId = OutputSectionId::regular(10);
 pub(crate) const COMMENT: OutputSectionId = OutputSectionId::regular(11);
 pub(crate) const GCC_EXCEPT_TABLE: OutputSectionId = OutputSectionId::regular(12);
 pub(crate) const NOTE_ABI_TAG: OutputSectionId = OutputSectionId::regular(13);
+pub(crate) const NOTE_WILD_CMDLINE: OutputSectionId = OutputSectionId::regular(14);
+pub(crate) const DATA_REL_RO: OutputSectionId = OutputSectionId::regular(15);
+pub(crate) const NOTE_GNU_BUILD_ID: OutputSectionId = OutputSectionId::regular(16);
+pub(crate) const NOTE_GNU_PROPERTY: OutputSectionId = OutputSectionId::regular(17);
+pub(crate) const SFRAME: OutputSectionId = OutputSectionId::regular(18);
+pub(crate) const CTF: OutputSectionId = OutputSectionId::regular(19);
 
-pub(crate) const NUM_BUILT_IN_REGULAR_SECTIONS: usize = 14;
+pub(crate) const NUM_BUILT_IN_REGULAR_SECTIONS: usize = 20;
 
 pub(crate) struct OutputSections<'data> {
     /// The base address for our output binary.
     pub(crate) base_address: u64,
+
+    /// The alignment of our loadable segments. See `-z max-page-size`.
+    pub(crate) page_size: Alignment,
+
+    /// Whether executable sections get pages that they don't share with other sections. See `-z
+    /// separate-code`.
+    pub(crate) separate_code: bool,
     pub(crate) section_infos: Vec<SectionOutputInfo<'data>>,
 
+    /// Addresses at which the user asked for particular sections to start. See `--section-start`.
+    start_addresses: AHashMap<OutputSectionId, u64>,
+
     // TODO: Consider moving this to Layout. We can't populate this until we know which output
     // sections have content, which we don't know until half way through the layout phase.
     /// Mapping from internal section IDs to output section indexes. None, if the section isn't
@@ -124,10 +142,23 @@ pub(crate) struct OutputSections<'data> {
 #[derive(Default)]
 struct CustomSectionIds {
     ro: Vec<OutputSectionId>,
+
+    /// Sections created by `-z keep-text-section-prefix`, in the order of `TEXT_SECTION_PREFIXES`.
+    text_prefixed: Vec<OutputSectionId>,
     exec: Vec<OutputSectionId>,
     data: Vec<OutputSectionId>,
     bss: Vec<OutputSectionId>,
     nonalloc: Vec<OutputSectionId>,
+
+    /// Note sections, which we cover with PT_NOTE segments. Those with 8 byte alignment need a
+    /// separate segment from those with 4 byte alignment.
+    notes: Vec<OutputSectionId>,
+    wide_notes: Vec<OutputSectionId>,
+
+    /// Sections with `SHF_X86_64_LARGE`, which we keep away from everything else.
+    large_ro: Vec<OutputSectionId>,
+    large_data: Vec<OutputSectionId>,
+    large_bss: Vec<OutputSectionId>,
 }
 
 impl<'data> OutputSections<'data> {
@@ -209,6 +240,10 @@ pub(crate) struct SectionOutputInfo<'data> {
     pub(crate) name: SectionName<'data>,
     pub(crate) section_flags: SectionFlags,
     pub(crate) ty: SectionType,
+
+    /// For SHF_LINK_ORDER sections, the output section containing the section that our first input
+    /// section is linked to. This becomes our `sh_link`.
+    pub(crate) link_order_link: Option<OutputSectionId>,
 }
 
 pub(crate) struct BuiltInSectionDetails {
@@ -288,17 +323,6 @@ const SECTION_DEFINITIONS: [BuiltInSectionDetails; NUM_BUILT_IN_SECTIONS] = [
         min_alignment: alignment::PLT,
         ..DEFAULT_DEFS
     },
-    BuiltInSectionDetails {
-        name: SectionName(b".rela.plt"),
-        ty: sht::RELA,
-        section_flags: shf::ALLOC.with(shf::INFO_LINK),
-        element_size: elf::RELA_ENTRY_SIZE,
-        link: &[DYNSYM, SYMTAB],
-        min_alignment: alignment::RELA_ENTRY,
-        start_symbol_name: Some("__rela_iplt_start"),
-        end_symbol_name: Some("__rela_iplt_end"),
-        ..DEFAULT_DEFS
-    },
     BuiltInSectionDetails {
         name: SectionName(b".eh_frame"),
         ty: sht::PROGBITS,
@@ -372,6 +396,22 @@ const SECTION_DEFINITIONS: [BuiltInSectionDetails; NUM_BUILT_IN_SECTIONS] = [
         link: &[DYNSTR],
         ..DEFAULT_DEFS
     },
+    BuiltInSectionDetails {
+        name: SectionName(b".plt"),
+        ty: sht::PROGBITS,
+        section_flags: shf::ALLOC.with(shf::EXECINSTR),
+        element_size: crate::elf::PLT_ENTRY_SIZE,
+        min_alignment: alignment::PLT,
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".got.plt"),
+        ty: sht::PROGBITS,
+        section_flags: shf::WRITE.with(shf::ALLOC),
+        element_size: crate::elf::GOT_ENTRY_SIZE,
+        min_alignment: alignment::GOT_ENTRY,
+        ..DEFAULT_DEFS
+    },
     // Multi-part generated sections
     BuiltInSectionDetails {
         name: SectionName(b".symtab"),
@@ -391,6 +431,26 @@ const SECTION_DEFINITIONS: [BuiltInSectionDetails; NUM_BUILT_IN_SECTIONS] = [
         link: &[DYNSYM],
         ..DEFAULT_DEFS
     },
+    BuiltInSectionDetails {
+        name: SectionName(b".rela.plt"),
+        ty: sht::RELA,
+        section_flags: shf::ALLOC.with(shf::INFO_LINK),
+        element_size: elf::RELA_ENTRY_SIZE,
+        link: &[DYNSYM, SYMTAB],
+        min_alignment: alignment::RELA_ENTRY,
+        start_symbol_name: Some("__rela_iplt_start"),
+        end_symbol_name: Some("__rela_iplt_end"),
+        info_fn: Some(rela_plt_info),
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".relr.dyn"),
+        ty: sht::RELR,
+        section_flags: shf::ALLOC,
+        element_size: crate::relr::ENTRY_SIZE,
+        min_alignment: alignment::USIZE,
+        ..DEFAULT_DEFS
+    },
     // Start of regular sections
     BuiltInSectionDetails {
         name: SectionName(b".rodata"),
@@ -486,6 +546,44 @@ const SECTION_DEFINITIONS: [BuiltInSectionDetails; NUM_BUILT_IN_SECTIONS] = [
         section_flags: shf::ALLOC.with(shf::GNU_RETAIN),
         ..DEFAULT_DEFS
     },
+    BuiltInSectionDetails {
+        name: SectionName(b".note.wild.cmdline"),
+        ty: sht::NOTE,
+        min_alignment: alignment::NOTE,
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".data.rel.ro"),
+        ty: sht::PROGBITS,
+        section_flags: shf::ALLOC.with(shf::WRITE),
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".note.gnu.build-id"),
+        ty: sht::NOTE,
+        section_flags: shf::ALLOC,
+        min_alignment: alignment::NOTE,
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".note.gnu.property"),
+        ty: sht::NOTE,
+        section_flags: shf::ALLOC,
+        min_alignment: alignment::USIZE,
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".sframe"),
+        ty: sht::PROGBITS,
+        section_flags: shf::ALLOC,
+        min_alignment: alignment::USIZE,
+        ..DEFAULT_DEFS
+    },
+    BuiltInSectionDetails {
+        name: SectionName(b".ctf"),
+        ty: sht::PROGBITS,
+        ..DEFAULT_DEFS
+    },
 ];
 
 pub(crate) fn built_in_section_ids(
@@ -589,6 +687,39 @@ pub(crate) enum OrderEvent {
     Section(OutputSectionId),
 }
 
+/// Prefixes of input section names that `-z keep-text-section-prefix` places in output sections
+/// of their own rather than in `.text`. The output sections are placed in this order, ahead of
+/// `.text`.
+pub(crate) const TEXT_SECTION_PREFIXES: [&[u8]; 4] = [
+    b".text.hot",
+    b".text.unlikely",
+    b".text.startup",
+    b".text.exit",
+];
+
+/// Returns the prefix from `TEXT_SECTION_PREFIXES` that `section_name` starts with, if any. A
+/// prefix only matches whole components of the name, so `.text.hotter` isn't `.text.hot`.
+pub(crate) fn text_section_prefix(section_name: &[u8]) -> Option<&'static [u8]> {
+    TEXT_SECTION_PREFIXES.into_iter().find(|prefix| {
+        section_name
+            .strip_prefix(*prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"."))
+    })
+}
+
+/// Names of the output sections for data that `-mcmodel=medium` and `-mcmodel=large` mark as large.
+const LARGE_SECTION_NAMES: [&[u8]; 3] = [b".lrodata", b".ldata", b".lbss"];
+
+/// Returns the output section for the large data section `section_name`, e.g. `.ldata` for
+/// `.ldata.foo`. As for `text_section_prefix`, only whole components of the name match.
+pub(crate) fn large_section_name(section_name: &[u8]) -> Option<&'static [u8]> {
+    LARGE_SECTION_NAMES.into_iter().find(|name| {
+        section_name
+            .strip_prefix(*name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"."))
+    })
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct SectionName<'data>(pub(crate) &'data [u8]);
 
@@ -610,9 +741,18 @@ impl<'data> Debug for SectionName<'data> {
 
 pub(crate) struct OutputSectionsBuilder<'data> {
     base_address: u64,
+    page_size: Alignment,
+    separate_code: bool,
+    relro: bool,
+    rosegment: bool,
+    writable_text: bool,
     custom_by_name: AHashMap<SectionName<'data>, OutputSectionId>,
     // TODO: Change this to be an OutputSectionMap.
     section_infos: Vec<SectionOutputInfo<'data>>,
+
+    /// The maximum alignment of the input sections of each custom note section.
+    note_alignments: AHashMap<OutputSectionId, Alignment>,
+    section_starts: Vec<(String, u64)>,
 }
 
 impl<'data> OutputSectionsBuilder<'data> {
@@ -624,11 +764,31 @@ impl<'data> OutputSectionsBuilder<'data> {
             .enumerate()
         {
             let id = OutputSectionId::from_usize(NUM_BUILT_IN_SECTIONS + offset);
-            if info.section_flags.contains(shf::EXECINSTR) {
-                custom.exec.push(id);
+            let is_large = info.section_flags.contains(shf::X86_64_LARGE)
+                && info.section_flags.contains(shf::ALLOC);
+            if is_large && !info.section_flags.contains(shf::EXECINSTR) {
+                if !info.section_flags.contains(shf::WRITE) {
+                    custom.large_ro.push(id);
+                } else if info.ty == sht::NOBITS {
+                    custom.large_bss.push(id);
+                } else {
+                    custom.large_data.push(id);
+                }
+            } else if info.section_flags.contains(shf::EXECINSTR) {
+                if TEXT_SECTION_PREFIXES.contains(&info.name.bytes()) {
+                    custom.text_prefixed.push(id);
+                } else {
+                    custom.exec.push(id);
+                }
             } else if !info.section_flags.contains(shf::WRITE) {
                 if !info.section_flags.contains(shf::ALLOC) {
                     custom.nonalloc.push(id);
+                } else if let Some(&alignment) = self.note_alignments.get(&id) {
+                    if alignment >= alignment::USIZE {
+                        custom.wide_notes.push(id);
+                    } else {
+                        custom.notes.push(id);
+                    }
                 } else {
                     custom.ro.push(id);
                 }
@@ -639,12 +799,41 @@ impl<'data> OutputSectionsBuilder<'data> {
             }
         }
 
+        custom.text_prefixed.sort_by_key(|id| {
+            let name = self.section_infos[id.as_usize()].name.bytes();
+            TEXT_SECTION_PREFIXES
+                .iter()
+                .position(|prefix| *prefix == name)
+        });
+
+        let start_addresses = self
+            .section_starts
+            .iter()
+            .filter_map(|(name, address)| {
+                let name = SectionName(name.as_bytes());
+                let id = self.custom_by_name.get(&name).copied().or_else(|| {
+                    self.section_infos[..NUM_BUILT_IN_SECTIONS]
+                        .iter()
+                        .position(|info| info.name == name)
+                        .map(OutputSectionId::from_usize)
+                })?;
+                Some((id, *address))
+            })
+            .collect();
+
         let mut output_sections = OutputSections {
             base_address: self.base_address,
+            start_addresses,
+            page_size: self.page_size,
+            separate_code: self.separate_code,
             section_infos: self.section_infos,
             custom_by_name: self.custom_by_name,
             output_section_indexes: Default::default(),
-            sections_and_segments_events: custom.sections_and_segments_events(),
+            sections_and_segments_events: custom.sections_and_segments_events(
+                self.relro,
+                self.rosegment,
+                self.writable_text,
+            ),
         };
 
         output_sections.determine_loadable_segment_ids()?;
@@ -662,6 +851,13 @@ impl<'data> OutputSectionsBuilder<'data> {
             // Section flags are sometimes different, take the union of everything we're
             // given.
             self.section_infos[section_id.as_usize()].section_flags |= custom.section_flags;
+            if custom.ty == sht::NOTE {
+                let alignment = self
+                    .note_alignments
+                    .entry(section_id)
+                    .or_insert(custom.alignment);
+                *alignment = (*alignment).max(custom.alignment);
+            }
 
             if let Some(slot) = sections.get_mut(custom.index.0) {
                 slot.set_part_id(section_id.part_id_with_alignment(custom.alignment));
@@ -683,6 +879,7 @@ impl<'data> OutputSectionsBuilder<'data> {
                 // We'll fill this in properly in `determine_loadable_segment_ids`.
                 loadable_segment_id: None,
                 ty: section_type,
+                link_order_link: None,
             });
             id
         })
@@ -696,20 +893,74 @@ impl<'data> OutputSectionsBuilder<'data> {
                 name: d.name,
                 loadable_segment_id: Some(crate::program_segments::LOAD_RO),
                 ty: d.ty,
+                link_order_link: None,
             })
             .collect();
         Self {
             section_infos,
             base_address,
+            page_size: alignment::PAGE,
+            separate_code: true,
+            relro: true,
+            rosegment: true,
+            writable_text: false,
             custom_by_name: AHashMap::new(),
+            note_alignments: AHashMap::new(),
+            section_starts: Vec::new(),
         }
     }
+
+    /// Sets whether to group sections that are only written during relocation into a segment that
+    /// the dynamic loader can make read-only afterwards.
+    pub(crate) fn with_relro(mut self, relro: bool) -> Self {
+        self.relro = relro;
+        self
+    }
+
+    /// Sets the alignment of loadable segments.
+    pub(crate) fn with_page_size(mut self, page_size: Alignment) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sets whether read-only data gets a LOAD segment of its own, rather than sharing one with
+    /// code.
+    pub(crate) fn with_rosegment(mut self, rosegment: bool) -> Self {
+        self.rosegment = rosegment;
+        self
+    }
+
+    /// Sets whether to put code and data in a single LOAD segment that's both writable and
+    /// executable, as `-n` and `-N` do.
+    pub(crate) fn with_writable_text(mut self, writable_text: bool) -> Self {
+        self.writable_text = writable_text;
+        self
+    }
+
+    /// Sets the addresses at which named sections should start. Names that don't match any output
+    /// section are ignored.
+    pub(crate) fn with_section_starts(mut self, section_starts: &[(String, u64)]) -> Self {
+        self.section_starts = section_starts.to_vec();
+        self
+    }
+
+    /// Sets whether executable sections should be padded so that they don't share pages with
+    /// non-executable sections.
+    pub(crate) fn with_separate_code(mut self, separate_code: bool) -> Self {
+        self.separate_code = separate_code;
+        self
+    }
 }
 
 impl CustomSectionIds {
     /// Returns vector of events for each section and segment in output order.
     /// Segments span multiple sections and can overlap, so are represented as start and end events.
-    fn sections_and_segments_events(&self) -> Vec<OrderEvent> {
+    fn sections_and_segments_events(
+        &self,
+        relro: bool,
+        rosegment: bool,
+        writable_text: bool,
+    ) -> Vec<OrderEvent> {
         fn build_section_events(
             sections: &[OutputSectionId],
         ) -> impl Iterator<Item = OrderEvent> + '_ {
@@ -718,6 +969,9 @@ impl CustomSectionIds {
 
         let mut events = Vec::with_capacity(64);
 
+        // The segment that replaces all the others with `-n` or `-N`. Otherwise, it stays empty.
+        events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_RWX));
+        events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_RWX));
         events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_RO));
         events.push(FILE_HEADER.event());
         events.push(OrderEvent::SegmentStart(crate::program_segments::PHDR));
@@ -727,8 +981,27 @@ impl CustomSectionIds {
         events.push(OrderEvent::SegmentStart(crate::program_segments::INTERP));
         events.push(INTERP.event());
         events.push(OrderEvent::SegmentEnd(crate::program_segments::INTERP));
+        // Property notes are 8 byte aligned, unlike most other notes. Readers of a PT_NOTE expect
+        // all of its notes to be aligned the same as it is, so 8 byte aligned notes get a PT_NOTE of
+        // their own.
+        events.push(OrderEvent::SegmentStart(
+            crate::program_segments::PROPERTY_NOTE,
+        ));
+        events.push(OrderEvent::SegmentStart(
+            crate::program_segments::GNU_PROPERTY,
+        ));
+        events.push(NOTE_GNU_PROPERTY.event());
+        events.push(OrderEvent::SegmentEnd(
+            crate::program_segments::GNU_PROPERTY,
+        ));
+        events.extend(build_section_events(&self.wide_notes));
+        events.push(OrderEvent::SegmentEnd(
+            crate::program_segments::PROPERTY_NOTE,
+        ));
         events.push(OrderEvent::SegmentStart(crate::program_segments::NOTE));
         events.push(NOTE_ABI_TAG.event());
+        events.push(NOTE_GNU_BUILD_ID.event());
+        events.extend(build_section_events(&self.notes));
         events.push(OrderEvent::SegmentEnd(crate::program_segments::NOTE));
         events.push(GNU_HASH.event());
         events.push(DYNSYM.event());
@@ -736,33 +1009,62 @@ impl CustomSectionIds {
         events.push(GNU_VERSION.event());
         events.push(GNU_VERSION_R.event());
         events.push(RELA_DYN.event());
+        events.push(RELR_DYN.event());
+        // Large read-only data goes before everything that code might reference with a 32 bit
+        // offset.
+        events.extend(build_section_events(&self.large_ro));
         events.push(RODATA.event());
         events.push(OrderEvent::SegmentStart(crate::program_segments::EH_FRAME));
         events.push(EH_FRAME_HDR.event());
         events.push(OrderEvent::SegmentEnd(crate::program_segments::EH_FRAME));
         events.push(EH_FRAME.event());
+        events.push(OrderEvent::SegmentStart(crate::program_segments::SFRAME));
+        events.push(SFRAME.event());
+        events.push(OrderEvent::SegmentEnd(crate::program_segments::SFRAME));
         events.push(PREINIT_ARRAY.event());
         events.push(GCC_EXCEPT_TABLE.event());
         events.extend(build_section_events(&self.ro));
         events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_RO));
 
         events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_EXEC));
+        events.push(PLT.event());
         events.push(PLT_GOT.event());
+        events.extend(build_section_events(&self.text_prefixed));
         events.push(TEXT.event());
         events.push(INIT.event());
         events.push(FINI.event());
         events.extend(build_section_events(&self.exec));
         events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_EXEC));
 
-        events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_RW));
+        // Sections that are only written while applying relocations go first, so that they can be
+        // made read-only once that's done. With `-z norelro`, the RELRO segments are left empty and
+        // everything goes in the regular writable segment.
+        events.push(OrderEvent::SegmentStart(
+            crate::program_segments::LOAD_RELRO,
+        ));
+        events.push(OrderEvent::SegmentStart(crate::program_segments::RELRO));
+        if !relro {
+            events.push(OrderEvent::SegmentEnd(crate::program_segments::RELRO));
+            events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_RELRO));
+            events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_RW));
+        }
         events.push(GOT.event());
         events.push(RELA_PLT.event());
         events.push(INIT_ARRAY.event());
         events.push(FINI_ARRAY.event());
-        events.push(DATA.event());
+        events.push(DATA_REL_RO.event());
         events.push(OrderEvent::SegmentStart(crate::program_segments::DYNAMIC));
         events.push(DYNAMIC.event());
         events.push(OrderEvent::SegmentEnd(crate::program_segments::DYNAMIC));
+        if relro {
+            events.push(OrderEvent::SegmentEnd(crate::program_segments::RELRO));
+            events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_RELRO));
+            events.push(OrderEvent::SegmentStart(crate::program_segments::LOAD_RW));
+        }
+        // With lazy binding, the dynamic loader writes to .got.plt each time it binds a function,
+        // so it can't be made read-only.
+        events.push(GOT_PLT.event());
+        events.push(DATA.event());
         events.extend(build_section_events(&self.data));
         events.push(OrderEvent::SegmentStart(crate::program_segments::TLS));
         events.push(TDATA.event());
@@ -772,19 +1074,89 @@ impl CustomSectionIds {
         events.extend(build_section_events(&self.bss));
         events.push(OrderEvent::SegmentEnd(crate::program_segments::LOAD_RW));
 
+        events.push(OrderEvent::SegmentStart(
+            crate::program_segments::LOAD_RW_LARGE,
+        ));
+        events.extend(build_section_events(&self.large_data));
+        events.extend(build_section_events(&self.large_bss));
+        events.push(OrderEvent::SegmentEnd(
+            crate::program_segments::LOAD_RW_LARGE,
+        ));
+
         events.push(OrderEvent::SegmentStart(crate::program_segments::STACK));
         events.push(OrderEvent::SegmentEnd(crate::program_segments::STACK));
 
         events.extend(build_section_events(&self.nonalloc));
+        events.push(CTF.event());
         events.push(COMMENT.event());
+        events.push(NOTE_WILD_CMDLINE.event());
         events.push(SHSTRTAB.event());
         events.push(SYMTAB.event());
         events.push(STRTAB.event());
 
+        if writable_text {
+            merge_segments(
+                &mut events,
+                crate::program_segments::LOAD_RWX,
+                &[
+                    crate::program_segments::LOAD_RO,
+                    crate::program_segments::LOAD_EXEC,
+                    crate::program_segments::LOAD_RELRO,
+                    crate::program_segments::LOAD_RW,
+                ],
+            );
+        } else if !rosegment {
+            merge_segments(
+                &mut events,
+                crate::program_segments::LOAD_EXEC,
+                &[
+                    crate::program_segments::LOAD_RO,
+                    crate::program_segments::LOAD_EXEC,
+                ],
+            );
+        }
+
         events
     }
 }
 
+/// Replaces the LOAD segments `segments` with the single segment `merged`, which covers everything
+/// from the start of the first of them to the end of the last. The others are left empty.
+fn merge_segments(
+    events: &mut Vec<OrderEvent>,
+    merged: ProgramSegmentId,
+    segments: &[ProgramSegmentId],
+) {
+    let is_merged = |event: &OrderEvent| {
+        matches!(
+            event,
+            OrderEvent::SegmentStart(id) | OrderEvent::SegmentEnd(id)
+                if *id == merged || segments.contains(id)
+        )
+    };
+    let (Some(first), Some(last)) = (
+        events.iter().position(is_merged),
+        events.iter().rposition(is_merged),
+    ) else {
+        return;
+    };
+    let mut merged_events = Vec::with_capacity(events.len() + segments.len() * 2);
+    for (index, event) in events.drain(..).enumerate() {
+        if index == first {
+            for &id in segments.iter().filter(|id| **id != merged) {
+                merged_events.push(OrderEvent::SegmentStart(id));
+                merged_events.push(OrderEvent::SegmentEnd(id));
+            }
+            merged_events.push(OrderEvent::SegmentStart(merged));
+        } else if index == last {
+            merged_events.push(OrderEvent::SegmentEnd(merged));
+        } else if !is_merged(&event) {
+            merged_events.push(event);
+        }
+    }
+    *events = merged_events;
+}
+
 impl<'data> OutputSections<'data> {
     /// Returns an iterator of events for each section and segment in output order. Segments span
     /// multiple sections and can overlap, so are represented as start and end events.
@@ -838,6 +1210,11 @@ impl<'data> OutputSections<'data> {
         String::from_utf8_lossy(self.name(section_id).0)
     }
 
+    /// Returns the address at which the user asked `section_id` to start, if any.
+    pub(crate) fn start_address(&self, section_id: OutputSectionId) -> Option<u64> {
+        self.start_addresses.get(&section_id).copied()
+    }
+
     pub(crate) fn custom_name_to_id(&self, name: SectionName) -> Option<OutputSectionId> {
         self.custom_by_name.get(&name).cloned()
     }
@@ -872,6 +1249,20 @@ fn version_r_info(layout: &Layout) -> u32 {
     layout.non_addressable_counts.verneed_count as u32
 }
 
+/// The section that SHF_INFO_LINK refers to. With lazy binding, that's .got.plt, which holds the
+/// entries for the JUMP_SLOT relocations. Otherwise the relocations in .rela.plt are all IRELATIVE
+/// for ifuncs, which apply to GOT entries.
+fn rela_plt_info(layout: &Layout) -> u32 {
+    let got_plt = layout
+        .args()
+        .lazy_binding()
+        .then(|| layout.output_sections.output_index_of_section(GOT_PLT))
+        .flatten();
+    got_plt
+        .or_else(|| layout.output_sections.output_index_of_section(GOT))
+        .map_or(0, u32::from)
+}
+
 fn dynsym_info(_layout: &Layout) -> u32 {
     // For now, we're not putting anything in dynstr, so the only "local" is the null symbol.
     1
@@ -912,6 +1303,7 @@ fn test_constant_ids() {
         (DYNSYM, ".dynsym"),
         (DYNSTR, ".dynstr"),
         (RELA_DYN, ".rela.dyn"),
+        (RELR_DYN, ".relr.dyn"),
         (GCC_EXCEPT_TABLE, ".gcc_except_table"),
         (INTERP, ".interp"),
         (GNU_VERSION, ".gnu.version"),
@@ -920,7 +1312,15 @@ fn test_constant_ids() {
         (SECTION_HEADERS, ".shdr"),
         (GNU_HASH, ".gnu.hash"),
         (PLT_GOT, ".plt.got"),
+        (PLT, ".plt"),
+        (GOT_PLT, ".got.plt"),
         (NOTE_ABI_TAG, ".note.ABI-tag"),
+        (NOTE_WILD_CMDLINE, ".note.wild.cmdline"),
+        (DATA_REL_RO, ".data.rel.ro"),
+        (NOTE_GNU_BUILD_ID, ".note.gnu.build-id"),
+        (NOTE_GNU_PROPERTY, ".note.gnu.property"),
+        (SFRAME, ".sframe"),
+        (CTF, ".ctf"),
     ];
     for (id, name) in check {
         assert_eq!(
@@ -930,3 +1330,88 @@ fn test_constant_ids() {
     }
     assert_eq!(NUM_BUILT_IN_SECTIONS, check.len());
 }
+
+#[test]
+fn test_relro_sections() {
+    use crate::program_segments::RELRO;
+
+    let sections_in_relro = |relro: bool| {
+        let mut in_relro = false;
+        let mut sections = Vec::new();
+        for event in CustomSectionIds::default().sections_and_segments_events(relro, true, false) {
+            match event {
+                OrderEvent::SegmentStart(RELRO) => in_relro = true,
+                OrderEvent::SegmentEnd(RELRO) => in_relro = false,
+                OrderEvent::Section(id) if in_relro => sections.push(id),
+                _ => {}
+            }
+        }
+        sections
+    };
+    let relro = sections_in_relro(true);
+    for id in [GOT, INIT_ARRAY, FINI_ARRAY, DATA_REL_RO, DYNAMIC] {
+        assert!(relro.contains(&id), "{id:?} should be in RELRO");
+    }
+    for id in [GOT_PLT, DATA, TDATA, BSS] {
+        assert!(!relro.contains(&id), "{id:?} shouldn't be in RELRO");
+    }
+    assert!(sections_in_relro(false).is_empty());
+}
+
+#[test]
+fn test_merged_load_segments() {
+    use crate::program_segments::LOAD_EXEC;
+    use crate::program_segments::LOAD_RO;
+    use crate::program_segments::LOAD_RW;
+    use crate::program_segments::LOAD_RWX;
+
+    let load_segments = |mut builder: OutputSectionsBuilder| {
+        builder.add_section(SectionName(b"exec"), shf::EXECINSTR, sht::PROGBITS);
+        builder.add_section(SectionName(b"data"), shf::WRITE, sht::PROGBITS);
+        let output_sections = builder.build().unwrap();
+        [RODATA, TEXT, DATA, BSS].map(|id| output_sections.output_info(id).loadable_segment_id)
+    };
+    let builder = || OutputSectionsBuilder::with_base_address(0x1000);
+    assert_eq!(
+        load_segments(builder().with_rosegment(false)),
+        [
+            Some(LOAD_EXEC),
+            Some(LOAD_EXEC),
+            Some(LOAD_RW),
+            Some(LOAD_RW)
+        ]
+    );
+    assert_eq!(
+        load_segments(builder().with_writable_text(true)),
+        [Some(LOAD_RWX); 4]
+    );
+    assert_eq!(
+        load_segments(builder())[..2],
+        [Some(LOAD_RO), Some(LOAD_EXEC)]
+    );
+}
+
+#[test]
+fn test_large_section_name() {
+    assert_eq!(large_section_name(b".ldata"), Some(&b".ldata"[..]));
+    assert_eq!(large_section_name(b".ldata.big"), Some(&b".ldata"[..]));
+    assert_eq!(large_section_name(b".lbss.x"), Some(&b".lbss"[..]));
+    assert_eq!(
+        large_section_name(b".lrodata.str1.1"),
+        Some(&b".lrodata"[..])
+    );
+    assert_eq!(large_section_name(b".ldatax"), None);
+    assert_eq!(large_section_name(b".data"), None);
+}
+
+#[test]
+fn test_text_section_prefix() {
+    assert_eq!(text_section_prefix(b".text.hot"), Some(&b".text.hot"[..]));
+    assert_eq!(
+        text_section_prefix(b".text.unlikely.foo"),
+        Some(&b".text.unlikely"[..])
+    );
+    assert_eq!(text_section_prefix(b".text.hotter"), None);
+    assert_eq!(text_section_prefix(b".text.main"), None);
+    assert_eq!(text_section_prefix(b".text"), None);
+}
@@ -0,0 +1,107 @@
+//! Prints a short human-readable summary of the output file once it has been written. This is
+//! intended for checking that a link did what was asked without needing to reach for readelf.
+
+use crate::elf::FileHeader;
+use crate::error::Result;
+use anyhow::Context as _;
+use linker_utils::elf::dynamic_tag_to_string;
+use linker_utils::elf::segment_type_to_string;
+use object::read::elf::Dyn as _;
+use object::read::elf::FileHeader as _;
+use object::read::elf::ProgramHeader as _;
+use object::LittleEndian;
+use std::path::Path;
+
+#[tracing::instrument(skip_all, name = "Print output summary")]
+pub(crate) fn print(path: &Path, data: &[u8]) -> Result {
+    let e = LittleEndian;
+    let header = FileHeader::parse(data)?;
+    let kind = match header.e_type(e) {
+        object::elf::ET_EXEC => "executable",
+        object::elf::ET_DYN
+            if header
+                .program_headers(e, data)?
+                .iter()
+                .any(|h| h.p_type(e) == object::elf::PT_INTERP) =>
+        {
+            "position-independent executable"
+        }
+        object::elf::ET_DYN => "shared object or static-PIE",
+        _ => "unknown",
+    };
+    println!("{}: {kind}", path.display());
+    println!("  Entry point: 0x{:x}", header.e_entry(e));
+
+    println!("  Segments:");
+    for segment in header.program_headers(e, data)? {
+        println!(
+            "    {:<16} {} offset=0x{:x} vaddr=0x{:x} filesz=0x{:x} memsz=0x{:x} align=0x{:x}",
+            segment_type_to_string(segment.p_type(e)),
+            FlagsDisplay(segment.p_flags(e)),
+            segment.p_offset(e),
+            segment.p_vaddr(e),
+            segment.p_filesz(e),
+            segment.p_memsz(e),
+            segment.p_align(e),
+        );
+        if segment.p_type(e) == object::elf::PT_INTERP {
+            let interp = segment
+                .data(e, data)
+                .map_err(|()| anyhow::anyhow!("Invalid PT_INTERP"))?;
+            let interp = interp.strip_suffix(&[0]).unwrap_or(interp);
+            println!("      Interpreter: {}", String::from_utf8_lossy(interp));
+        }
+    }
+
+    let sections = header.sections(e, data)?;
+    let Some((entries, strings_index)) = sections.dynamic(e, data)? else {
+        return Ok(());
+    };
+    let strings = sections
+        .strings(e, data, strings_index)
+        .context("Invalid .dynamic string table")?;
+    println!("  Dynamic tags:");
+    for entry in entries {
+        let tag = entry.d_tag(e);
+        if tag == u64::from(object::elf::DT_NULL) {
+            break;
+        }
+        let value = entry.d_val(e);
+        let tag_name = dynamic_tag_to_string(tag);
+        let tag = u32::try_from(tag)
+            .with_context(|| format!("Dynamic tag 0x{tag:x} doesn't fit in 32 bits"))?;
+        let is_string = matches!(
+            tag,
+            object::elf::DT_NEEDED
+                | object::elf::DT_SONAME
+                | object::elf::DT_RPATH
+                | object::elf::DT_RUNPATH
+                | object::elf::DT_AUXILIARY
+                | object::elf::DT_FILTER
+        );
+        let string = u32::try_from(value)
+            .ok()
+            .filter(|_| is_string)
+            .and_then(|offset| strings.get(offset).ok());
+        match string {
+            Some(string) => println!("    {tag_name:<16} {}", String::from_utf8_lossy(string)),
+            None => println!("    {tag_name:<16} 0x{value:x}"),
+        }
+    }
+    Ok(())
+}
+
+struct FlagsDisplay(u32);
+
+impl std::fmt::Display for FlagsDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (flag, c) in [
+            (object::elf::PF_R, 'R'),
+            (object::elf::PF_W, 'W'),
+            (object::elf::PF_X, 'X'),
+        ] {
+            std::fmt::Write::write_char(f, if self.0 & flag != 0 { c } else { '-' })?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::archive_splitter::InputBytes;
 use crate::args::Args;
+use crate::args::Defsym;
 use crate::args::Modifiers;
 use crate::args::OutputKind;
 use crate::args::RelocationModel;
@@ -59,13 +60,14 @@ fn set_start_symbol_ids(objects: &mut [ParsedInput]) {
 // the two smaller variants, so it doesn't matter.
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum ParsedInput<'data> {
-    Prelude(Prelude),
+    Prelude(Prelude<'data>),
     Object(ParsedInputObject<'data>),
     Epilogue(Epilogue),
 }
 
-pub(crate) struct Prelude {
+pub(crate) struct Prelude<'data> {
     pub(crate) symbol_definitions: Vec<InternalSymDefInfo>,
+    pub(crate) defsyms: &'data [Defsym],
 }
 
 pub(crate) struct ParsedInputObject<'data> {
@@ -103,6 +105,9 @@ pub(crate) enum InternalSymDefInfo {
     /// Defines a symbol that points at the non-inclusive end of the section. i.e. 1 byte past the
     /// last byte of the section.
     SectionEnd(OutputSectionId),
+
+    /// Defines the symbol given by the `--defsym` option at the specified index.
+    Defsym(usize),
 }
 
 impl<'data> ParsedInputObject<'data> {
@@ -158,6 +163,9 @@ impl<'data> ParsedInput<'data> {
             FileKind::Prelude => Self::Prelude(Prelude::new(args)?),
             FileKind::ElfDynamic => Self::Object(ParsedInputObject::new(input, true)?),
             FileKind::Text => unreachable!("Should have been handled earlier"),
+            FileKind::LlvmIr => {
+                unreachable!("IR should have been claimed by a plugin or reported earlier")
+            }
             FileKind::Epilogue => Self::Epilogue(Epilogue::new()),
         })
     }
@@ -223,8 +231,8 @@ impl<'data> ParsedInput<'data> {
     }
 }
 
-impl Prelude {
-    fn new(args: &Args) -> Result<Self> {
+impl<'data> Prelude<'data> {
+    fn new(args: &'data Args) -> Result<Self> {
         // The undefined symbol must always be symbol 0.
         let mut symbol_definitions = vec![InternalSymDefInfo::Undefined];
         for section_id in output_section_id::built_in_section_ids() {
@@ -249,10 +257,14 @@ impl Prelude {
                 symbol_definitions.push(InternalSymDefInfo::SectionEnd(section_id));
             }
         }
-        Ok(Self { symbol_definitions })
+        symbol_definitions.extend((0..args.defsyms.len()).map(InternalSymDefInfo::Defsym));
+        Ok(Self {
+            symbol_definitions,
+            defsyms: &args.defsyms,
+        })
     }
 
-    pub(crate) fn symbol_name(&self, symbol_id: SymbolId) -> SymbolName<'static> {
+    pub(crate) fn symbol_name(&self, symbol_id: SymbolId) -> SymbolName<'data> {
         let def = &self.symbol_definitions[symbol_id.as_usize()];
         let name = match def {
             InternalSymDefInfo::Undefined => Some(""),
@@ -262,6 +274,9 @@ impl Prelude {
             InternalSymDefInfo::SectionEnd(section_id) => {
                 section_id.built_in_details().end_symbol_name
             }
+            InternalSymDefInfo::Defsym(index) => {
+                return SymbolName::new(self.defsyms[*index].name.as_bytes());
+            }
         }
         .unwrap();
         SymbolName::new(name.as_bytes())
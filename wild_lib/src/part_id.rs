@@ -48,27 +48,35 @@ pub(crate) const SHSTRTAB: PartId = PartId(3);
 pub(crate) const STRTAB: PartId = PartId(4);
 pub(crate) const GOT: PartId = PartId(5);
 pub(crate) const PLT_GOT: PartId = PartId(6);
-pub(crate) const RELA_PLT: PartId = PartId(7);
-pub(crate) const EH_FRAME: PartId = PartId(8);
-pub(crate) const EH_FRAME_HDR: PartId = PartId(9);
-pub(crate) const DYNAMIC: PartId = PartId(10);
-pub(crate) const GNU_HASH: PartId = PartId(11);
-pub(crate) const DYNSYM: PartId = PartId(12);
-pub(crate) const DYNSTR: PartId = PartId(13);
-pub(crate) const INTERP: PartId = PartId(14);
-pub(crate) const GNU_VERSION: PartId = PartId(15);
-pub(crate) const GNU_VERSION_R: PartId = PartId(16);
+pub(crate) const EH_FRAME: PartId = PartId(7);
+pub(crate) const EH_FRAME_HDR: PartId = PartId(8);
+pub(crate) const DYNAMIC: PartId = PartId(9);
+pub(crate) const GNU_HASH: PartId = PartId(10);
+pub(crate) const DYNSYM: PartId = PartId(11);
+pub(crate) const DYNSTR: PartId = PartId(12);
+pub(crate) const INTERP: PartId = PartId(13);
+pub(crate) const GNU_VERSION: PartId = PartId(14);
+pub(crate) const GNU_VERSION_R: PartId = PartId(15);
+pub(crate) const PLT: PartId = PartId(16);
+pub(crate) const GOT_PLT: PartId = PartId(17);
 
-pub(crate) const NUM_SINGLE_PART_SECTIONS: u32 = 17;
+pub(crate) const NUM_SINGLE_PART_SECTIONS: u32 = 18;
 
 // Generated sections that have more than one part. Fortunately they all have exactly 2 parts.
 pub(crate) const SYMTAB_LOCAL: PartId = PartId::multi(0);
 pub(crate) const SYMTAB_GLOBAL: PartId = PartId::multi(1);
 pub(crate) const RELA_DYN_RELATIVE: PartId = PartId::multi(2);
 pub(crate) const RELA_DYN_GENERAL: PartId = PartId::multi(3);
+pub(crate) const RELA_PLT_GENERAL: PartId = PartId::multi(4);
+/// IRELATIVE relocations, which glibc needs to come after all other relocations.
+pub(crate) const RELA_PLT_IRELATIVE: PartId = PartId::multi(5);
+/// Packed relative relocations for places in input sections.
+pub(crate) const RELR_DYN: PartId = PartId::multi(6);
+/// Packed relative relocations for GOT entries.
+pub(crate) const RELR_DYN_GOT: PartId = PartId::multi(7);
 
 pub(crate) const MULTI_PART_BASE: u32 = NUM_SINGLE_PART_SECTIONS;
-pub(crate) const NUM_TWO_PART_SECTIONS: u32 = 2;
+pub(crate) const NUM_TWO_PART_SECTIONS: u32 = 4;
 pub(crate) const NUM_PARTS_PER_TWO_PART_SECTION: u32 = 2;
 
 /// The offset at which we start splitting sections by alignment.
@@ -89,6 +97,7 @@ impl<'data> UnresolvedSection<'data> {
     #[allow(clippy::if_same_then_else)]
     pub(crate) fn from_section(
         object: &crate::elf::File<'data>,
+        section_index: object::SectionIndex,
         section: &SectionHeader,
         args: &Args,
     ) -> Result<Option<Self>> {
@@ -97,17 +106,56 @@ impl<'data> UnresolvedSection<'data> {
         let section_name = object.section_name(section).unwrap_or_default();
         let section_flags = SectionFlags::from_header(section);
         let alignment = Alignment::new(object.section_alignment(section)?.max(1))?;
-        let built_in_section_id = if section_name.starts_with(b".rodata") {
+        let built_in_section_id = if section_flags.contains(shf::EXCLUDE) {
+            // Like GNU ld, we leave these out of executables and shared objects.
+            None
+        } else if section_name.starts_with(b".rodata") {
             Some(output_section_id::RODATA)
+        } else if let Some(prefix) = args
+            .keep_text_section_prefix
+            .then(|| output_section_id::text_section_prefix(section_name))
+            .flatten()
+        {
+            return Ok(Some(UnresolvedSection {
+                part_id: TemporaryPartId::Custom(
+                    CustomSectionId {
+                        name: SectionName(prefix),
+                    },
+                    alignment,
+                ),
+                is_string_merge: false,
+            }));
+        } else if let Some(name) = output_section_id::large_section_name(section_name) {
+            return Ok(Some(UnresolvedSection {
+                part_id: TemporaryPartId::Custom(
+                    CustomSectionId {
+                        name: SectionName(name),
+                    },
+                    alignment,
+                ),
+                is_string_merge: should_merge_strings(
+                    section,
+                    object.section_alignment(section)?,
+                    args,
+                ),
+            }));
         } else if section_name.starts_with(b".text") {
             Some(output_section_id::TEXT)
+        } else if section_name.starts_with(b".data.rel.ro") {
+            Some(output_section_id::DATA_REL_RO)
         } else if section_name.starts_with(b".data") {
             Some(output_section_id::DATA)
         } else if section_name.starts_with(b".bss") {
             Some(output_section_id::BSS)
-        } else if section_name.starts_with(b".init_array") || section_name.starts_with(b".ctors.") {
+        } else if section_name.starts_with(b".init_array")
+            || section_name.starts_with(b".ctors")
+                && crate::ctors::should_convert(object, section_index, section)?
+        {
             Some(output_section_id::INIT_ARRAY)
-        } else if section_name.starts_with(b".fini_array") || section_name.starts_with(b".dtors.") {
+        } else if section_name.starts_with(b".fini_array")
+            || section_name.starts_with(b".dtors")
+                && crate::ctors::should_convert(object, section_index, section)?
+        {
             Some(output_section_id::FINI_ARRAY)
         } else if section_name == b".init" {
             Some(output_section_id::INIT)
@@ -130,6 +178,18 @@ impl<'data> UnresolvedSection<'data> {
             Some(output_section_id::GCC_EXCEPT_TABLE)
         } else if section_name == b".note.ABI-tag" {
             Some(output_section_id::NOTE_ABI_TAG)
+        } else if section_name == b".note.wild.cmdline" || section_name == b".note.gnu.build-id" {
+            // These notes in an input describe how that input was linked, not how we're linking it.
+            None
+        } else if section_name == b".note.gnu.property" {
+            // We write a single property note, merged from those of all our inputs.
+            None
+        } else if section_name == b".sframe" {
+            // We write a single .sframe section, merged from those of all our inputs.
+            None
+        } else if section_name == b".ctf" {
+            // Likewise for CTF type information.
+            None
         } else if section_name.starts_with(b".rela")
             || b".strtab" == section_name
             || b".symtab" == section_name
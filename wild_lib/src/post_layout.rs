@@ -0,0 +1,228 @@
+//! An API for programs that embed the linker to patch the output after layout, but before it's
+//! finalised, e.g. to fill in a content hash or redirect a jump through a trampoline without a
+//! second pass with objcopy.
+//!
+//! Hooks can't change the size of anything. Space for whatever a hook wants to write needs to be
+//! reserved up front, e.g. by a zero-filled section in one of the input objects, which the hook
+//! can then find by section or symbol name. Hooks run after everything else has been written,
+//! except the build ID, which is computed last so that it covers any patches.
+
+use crate::error::Result;
+use crate::layout::Layout;
+use crate::output_section_id::OutputSectionId;
+use crate::resolution::ValueFlags;
+use crate::symbol::SymbolName;
+use linker_utils::elf::sht;
+
+/// The output file as seen by a `PostLayoutHook`.
+pub struct OutputImage<'a, 'data> {
+    layout: &'a Layout<'data>,
+    data: &'a mut [u8],
+}
+
+/// Where an output section ended up.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSection {
+    pub address: u64,
+
+    /// The offset of the section in the output file.
+    pub file_offset: usize,
+
+    /// The number of bytes the section occupies in the file. Zero for sections without contents,
+    /// such as `.bss`.
+    pub file_size: usize,
+
+    /// The number of bytes the section occupies in memory.
+    pub mem_size: u64,
+}
+
+/// A callback that gets to patch the output file after layout. When there are several, they're
+/// called in the order in which they were added.
+pub trait PostLayoutHook: Send + Sync {
+    fn apply(&self, output: &mut OutputImage) -> Result;
+}
+
+impl<F> PostLayoutHook for F
+where
+    F: Fn(&mut OutputImage) -> Result + Send + Sync,
+{
+    fn apply(&self, output: &mut OutputImage) -> Result {
+        self(output)
+    }
+}
+
+impl<'a, 'data> OutputImage<'a, 'data> {
+    /// Returns the address of the global symbol `name`, if it was defined and has an address.
+    pub fn symbol_address(&self, name: &str) -> Option<u64> {
+        let symbol_db = self.layout.symbol_db;
+        let symbol_id = *symbol_db
+            .global_names
+            .get(&SymbolName::prehashed(name.as_bytes()))?;
+        let resolution = self.layout.merged_symbol_resolution(symbol_id)?;
+        let flags = resolution.value_flags();
+        (flags.contains(ValueFlags::ADDRESS) && !flags.contains(ValueFlags::DYNAMIC))
+            .then(|| resolution.value())
+    }
+
+    /// Returns the output section called `name`, if there is one.
+    pub fn section(&self, name: &[u8]) -> Option<OutputSection> {
+        let sections = &self.layout.output_sections;
+        sections
+            .ids_with_info()
+            .find(|(id, info)| {
+                info.name.0 == name && sections.output_index_of_section(*id).is_some()
+            })
+            .map(|(id, _)| self.section_by_id(id))
+    }
+
+    /// Returns the offset in the file at which the byte at `address` was written, or None if
+    /// `address` isn't within the file contents of any section.
+    pub fn address_to_offset(&self, address: u64) -> Option<usize> {
+        let sections = &self.layout.output_sections;
+        sections
+            .ids_with_info()
+            .filter(|(id, info)| {
+                info.ty != sht::NOBITS
+                    && info.loadable_segment_id.is_some()
+                    && sections.output_index_of_section(*id).is_some()
+            })
+            .map(|(id, _)| self.section_by_id(id))
+            .find(|section| {
+                address
+                    .checked_sub(section.address)
+                    .is_some_and(|delta| delta < section.file_size as u64)
+            })
+            .map(|section| section.file_offset + (address - section.address) as usize)
+    }
+
+    /// The whole output file, including anything written by earlier hooks.
+    pub fn data(&self) -> &[u8] {
+        self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+
+    /// Returns the file contents of the output section called `name`.
+    pub fn section_data_mut(&mut self, name: &[u8]) -> Option<&mut [u8]> {
+        let section = self.section(name)?;
+        self.data
+            .get_mut(section.file_offset..section.file_offset + section.file_size)
+    }
+
+    fn section_by_id(&self, id: OutputSectionId) -> OutputSection {
+        let layout = self.layout.section_layouts.get(id);
+        OutputSection {
+            address: layout.mem_offset,
+            file_offset: layout.file_offset,
+            file_size: layout.file_size,
+            mem_size: layout.mem_size,
+        }
+    }
+}
+
+/// Runs each of `hooks` over `data`, which holds the output that was written for `layout`.
+#[tracing::instrument(skip_all, name = "Run post-layout hooks")]
+pub(crate) fn apply(hooks: &[Box<dyn PostLayoutHook>], layout: &Layout, data: &mut [u8]) -> Result {
+    let mut image = OutputImage { layout, data };
+    for hook in hooks {
+        hook.apply(&mut image)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_hook_patches_output_before_build_id() {
+    use crate::args::BuildId;
+    use anyhow::Context as _;
+    use object::write;
+    use object::LittleEndian;
+    use object::Object as _;
+    use object::ObjectSection as _;
+
+    let mut obj = write::Object::new(
+        object::BinaryFormat::Elf,
+        object::Architecture::X86_64,
+        object::Endianness::Little,
+    );
+    let text = obj.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+    // mov $60, %eax; xor %edi, %edi; syscall
+    obj.append_section_data(text, &[0xb8, 0x3c, 0, 0, 0, 0x31, 0xff, 0x0f, 0x05], 1);
+    obj.add_symbol(write::Symbol {
+        name: b"_start".to_vec(),
+        value: 0,
+        size: 9,
+        kind: object::SymbolKind::Text,
+        scope: object::SymbolScope::Linkage,
+        weak: false,
+        section: write::SymbolSection::Section(text),
+        flags: object::SymbolFlags::None,
+    });
+    // Space for the hook to fill in. Nothing references it, so it needs SHF_GNU_RETAIN.
+    let reserved = obj.add_section(Vec::new(), b"reserved".to_vec(), object::SectionKind::Data);
+    obj.section_mut(reserved).flags = object::SectionFlags::Elf {
+        sh_flags: u64::from(
+            object::elf::SHF_ALLOC | object::elf::SHF_WRITE | object::elf::SHF_GNU_RETAIN,
+        ),
+    };
+    obj.append_section_data(reserved, &[0; 8], 1);
+    obj.add_symbol(write::Symbol {
+        name: b"patch_here".to_vec(),
+        value: 4,
+        size: 4,
+        kind: object::SymbolKind::Data,
+        scope: object::SymbolScope::Linkage,
+        weak: false,
+        section: write::SymbolSection::Section(reserved),
+        flags: object::SymbolFlags::None,
+    });
+
+    let dir = std::env::temp_dir().join(format!("wild-post-layout-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("a.o");
+    let output = dir.join("a.out");
+    std::fs::write(&input, obj.write().unwrap()).unwrap();
+
+    let mut linker = crate::Linker::from_args(
+        [
+            "-static",
+            "--build-id=sha1",
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ]
+        .into_iter(),
+    )
+    .unwrap();
+    linker.add_post_layout_hook(|output: &mut OutputImage| {
+        output
+            .section_data_mut(b"reserved")
+            .context("Missing reserved section")?[..4]
+            .copy_from_slice(b"wild");
+        let address = output
+            .symbol_address("patch_here")
+            .context("Missing patch_here")?;
+        let offset = output
+            .address_to_offset(address)
+            .context("patch_here isn't in the file")?;
+        output.data_mut()[offset..offset + 4].copy_from_slice(&[1, 2, 3, 4]);
+        Ok(())
+    });
+    linker.run().unwrap();
+
+    let mut bytes = std::fs::read(&output).unwrap();
+    let linked = object::read::elf::ElfFile64::<LittleEndian>::parse(bytes.as_slice()).unwrap();
+    assert_eq!(
+        linked.section_by_name("reserved").unwrap().data().unwrap(),
+        b"wild\x01\x02\x03\x04"
+    );
+    // The build ID was computed over the file with the ID itself zeroed and the patches applied.
+    let note = linked.section_by_name(".note.gnu.build-id").unwrap();
+    let id_offset = note.file_range().unwrap().0 as usize + crate::build_id::NOTE_HEADER_SIZE;
+    let id = bytes[id_offset..id_offset + 20].to_vec();
+    bytes[id_offset..id_offset + 20].fill(0);
+    assert_eq!(crate::build_id::compute(&BuildId::Sha1, &bytes), id);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,233 @@
+//! Implements `--print-preemptibility`, which explains, for each symbol that we export, whether it
+//! can be preempted (interposed) by a definition in another module at runtime and why. Preemptible
+//! symbols must be accessed via the GOT or PLT, even from within the module that defines them, which
+//! is a common reason for code being slower than when it's statically linked.
+
+use crate::args::OutputKind;
+use crate::args::Symbolic;
+use crate::error::Result;
+use crate::layout::ResolutionFlags;
+use crate::parsing::ParsedInput;
+use crate::resolution::ValueFlags;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolId;
+use object::read::elf::Sym as _;
+
+/// Why a symbol is or isn't preemptible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    /// The version script made the symbol local, so it isn't exported at all.
+    VersionScriptLocal,
+    /// The symbol has protected visibility.
+    Protected,
+    /// The symbol is defined by the linker, which exports such symbols as protected.
+    LinkerDefined,
+    /// The symbol is defined in an executable, which the dynamic loader always searches first.
+    DefinedInExecutable,
+    /// `-Bsymbolic` or `-Bsymbolic-functions` bind references to the symbol to its definition.
+    Symbolic,
+    /// The symbol is a TLS variable in a shared object.
+    SharedObjectTls,
+    /// The symbol has default visibility and is defined in a shared object.
+    SharedObjectDefault,
+}
+
+impl Reason {
+    fn is_preemptible(self) -> bool {
+        matches!(self, Reason::SharedObjectTls | Reason::SharedObjectDefault)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Reason::VersionScriptLocal => "made local by the version script",
+            Reason::Protected => "has protected visibility",
+            Reason::LinkerDefined => "defined by the linker and exported as protected",
+            Reason::DefinedInExecutable => {
+                "defined in the executable, which the dynamic loader searches first"
+            }
+            Reason::Symbolic => "bound to its definition by -Bsymbolic or -Bsymbolic-functions",
+            Reason::SharedObjectTls => {
+                "TLS variable with default visibility in a shared object, accessed via the GOT"
+            }
+            Reason::SharedObjectDefault => {
+                "default visibility in a shared object, so an earlier loaded module may define it"
+            }
+        }
+    }
+}
+
+fn classify(
+    output_kind: OutputKind,
+    symbolic: Symbolic,
+    value_flags: ValueFlags,
+    visibility: Option<u8>,
+    st_type: u8,
+) -> Reason {
+    if value_flags.contains(ValueFlags::DOWNGRADE_TO_LOCAL) {
+        return Reason::VersionScriptLocal;
+    }
+    let Some(visibility) = visibility else {
+        return Reason::LinkerDefined;
+    };
+    if visibility != object::elf::STV_DEFAULT {
+        Reason::Protected
+    } else if output_kind.is_executable() {
+        Reason::DefinedInExecutable
+    } else if symbolic.applies_to(st_type) {
+        Reason::Symbolic
+    } else if st_type == object::elf::STT_TLS {
+        Reason::SharedObjectTls
+    } else {
+        Reason::SharedObjectDefault
+    }
+}
+
+/// Prints the report. Symbols made local by the version script are included if they're referenced,
+/// since that's the one case where a global symbol we define isn't exported.
+pub(crate) fn print(symbol_db: &SymbolDb, resolution_flags: &[ResolutionFlags]) -> Result {
+    let output_kind = symbol_db.args.output_kind;
+    let mut entries = Vec::new();
+    for (index, flags) in resolution_flags.iter().enumerate() {
+        let symbol_id = SymbolId::from_usize(index);
+        if !symbol_db.is_canonical(symbol_id) {
+            continue;
+        }
+        let value_flags = symbol_db.local_symbol_value_flags(symbol_id);
+        let is_exported = flags.contains(ResolutionFlags::EXPORT_DYNAMIC);
+        let is_localised =
+            value_flags.contains(ValueFlags::DOWNGRADE_TO_LOCAL) && !flags.is_empty();
+        if !is_exported && !is_localised {
+            continue;
+        }
+        let (visibility, st_type) = match symbol_db.file(symbol_db.file_id_for_symbol(symbol_id)) {
+            ParsedInput::Object(object) if !object.is_dynamic => {
+                let sym = object
+                    .object
+                    .symbol(symbol_id.to_input(object.symbol_id_range))?;
+                if sym.is_local() || sym.is_undefined(object::LittleEndian) {
+                    continue;
+                }
+                (Some(sym.st_visibility()), sym.st_type())
+            }
+            ParsedInput::Epilogue(_) => (None, object::elf::STT_NOTYPE),
+            _ => continue,
+        };
+        let reason = classify(
+            output_kind,
+            symbol_db.args.symbolic,
+            value_flags,
+            visibility,
+            st_type,
+        );
+        entries.push((symbol_db.symbol_name(symbol_id)?, reason));
+    }
+    entries.sort_by_key(|(name, _)| name.bytes());
+
+    println!("Preemptibility of global symbols:");
+    for (name, reason) in entries {
+        let status = if reason.is_preemptible() {
+            "preemptible"
+        } else {
+            "not preemptible"
+        };
+        println!("  {name}: {status} ({})", reason.description());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_classify() {
+    use crate::args::RelocationModel;
+    use object::elf::STT_OBJECT;
+    use object::elf::STT_TLS;
+    use object::elf::STV_DEFAULT;
+    use object::elf::STV_PROTECTED;
+
+    let exe = OutputKind::DynamicExecutable(RelocationModel::Relocatable);
+    let shared = OutputKind::SharedObject;
+    let address = ValueFlags::ADDRESS;
+    let localised = ValueFlags::ADDRESS | ValueFlags::DOWNGRADE_TO_LOCAL;
+    let none = Symbolic::None;
+
+    assert_eq!(
+        classify(exe, none, address, Some(STV_DEFAULT), STT_OBJECT),
+        Reason::DefinedInExecutable
+    );
+    assert_eq!(
+        classify(shared, none, address, Some(STV_DEFAULT), STT_OBJECT),
+        Reason::SharedObjectDefault
+    );
+    assert_eq!(
+        classify(shared, none, address, Some(STV_DEFAULT), STT_TLS),
+        Reason::SharedObjectTls
+    );
+    assert_eq!(
+        classify(shared, none, address, Some(STV_PROTECTED), STT_OBJECT),
+        Reason::Protected
+    );
+    assert_eq!(
+        classify(shared, none, localised, Some(STV_DEFAULT), STT_OBJECT),
+        Reason::VersionScriptLocal
+    );
+    assert_eq!(
+        classify(shared, none, address, None, object::elf::STT_NOTYPE),
+        Reason::LinkerDefined
+    );
+    assert!(classify(shared, none, address, Some(STV_DEFAULT), STT_OBJECT).is_preemptible());
+    assert!(!classify(exe, none, address, Some(STV_DEFAULT), STT_OBJECT).is_preemptible());
+}
+
+#[test]
+fn test_classify_symbolic() {
+    use object::elf::STT_FUNC;
+    use object::elf::STT_OBJECT;
+    use object::elf::STV_DEFAULT;
+
+    let shared = OutputKind::SharedObject;
+    let address = ValueFlags::ADDRESS;
+
+    for st_type in [STT_FUNC, STT_OBJECT] {
+        assert_eq!(
+            classify(shared, Symbolic::All, address, Some(STV_DEFAULT), st_type),
+            Reason::Symbolic
+        );
+    }
+    assert_eq!(
+        classify(
+            shared,
+            Symbolic::Functions,
+            address,
+            Some(STV_DEFAULT),
+            STT_FUNC
+        ),
+        Reason::Symbolic
+    );
+    assert_eq!(
+        classify(
+            shared,
+            Symbolic::Functions,
+            address,
+            Some(STV_DEFAULT),
+            object::elf::STT_GNU_IFUNC
+        ),
+        Reason::Symbolic
+    );
+    assert_eq!(
+        classify(
+            shared,
+            Symbolic::Functions,
+            address,
+            Some(STV_DEFAULT),
+            STT_OBJECT
+        ),
+        Reason::SharedObjectDefault
+    );
+    assert!(!classify(
+        shared,
+        Symbolic::All,
+        address,
+        Some(STV_DEFAULT),
+        STT_OBJECT
+    )
+    .is_preemptible());
+}
@@ -8,11 +8,18 @@ pub(crate) const INTERP: ProgramSegmentId = ProgramSegmentId(1);
 pub(crate) const NOTE: ProgramSegmentId = ProgramSegmentId(2);
 pub(crate) const LOAD_RO: ProgramSegmentId = ProgramSegmentId(3);
 pub(crate) const LOAD_EXEC: ProgramSegmentId = ProgramSegmentId(4);
-pub(crate) const LOAD_RW: ProgramSegmentId = ProgramSegmentId(5);
-pub(crate) const TLS: ProgramSegmentId = ProgramSegmentId(6);
-pub(crate) const EH_FRAME: ProgramSegmentId = ProgramSegmentId(7);
-pub(crate) const DYNAMIC: ProgramSegmentId = ProgramSegmentId(8);
-pub(crate) const STACK: ProgramSegmentId = ProgramSegmentId(9);
+pub(crate) const LOAD_RELRO: ProgramSegmentId = ProgramSegmentId(5);
+pub(crate) const LOAD_RW: ProgramSegmentId = ProgramSegmentId(6);
+pub(crate) const TLS: ProgramSegmentId = ProgramSegmentId(7);
+pub(crate) const EH_FRAME: ProgramSegmentId = ProgramSegmentId(8);
+pub(crate) const DYNAMIC: ProgramSegmentId = ProgramSegmentId(9);
+pub(crate) const STACK: ProgramSegmentId = ProgramSegmentId(10);
+pub(crate) const RELRO: ProgramSegmentId = ProgramSegmentId(11);
+pub(crate) const LOAD_RW_LARGE: ProgramSegmentId = ProgramSegmentId(12);
+pub(crate) const PROPERTY_NOTE: ProgramSegmentId = ProgramSegmentId(13);
+pub(crate) const GNU_PROPERTY: ProgramSegmentId = ProgramSegmentId(14);
+pub(crate) const SFRAME: ProgramSegmentId = ProgramSegmentId(15);
+pub(crate) const LOAD_RWX: ProgramSegmentId = ProgramSegmentId(16);
 
 pub(crate) struct ProgramSegmentDef {
     pub(crate) segment_type: u32,
@@ -40,6 +47,12 @@ const PROGRAM_SEGMENT_DEFS: &[ProgramSegmentDef] = &[
         segment_type: object::elf::PT_LOAD,
         segment_flags: object::elf::PF_R | object::elf::PF_X,
     },
+    // Writable data that is only written by the dynamic loader while applying relocations. Kept
+    // in a separate LOAD segment so that it starts and ends on a page boundary.
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_LOAD,
+        segment_flags: object::elf::PF_R | object::elf::PF_W,
+    },
     ProgramSegmentDef {
         segment_type: object::elf::PT_LOAD,
         segment_flags: object::elf::PF_R | object::elf::PF_W,
@@ -60,6 +73,33 @@ const PROGRAM_SEGMENT_DEFS: &[ProgramSegmentDef] = &[
         segment_type: object::elf::PT_GNU_STACK,
         segment_flags: object::elf::PF_R | object::elf::PF_W,
     },
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_GNU_RELRO,
+        segment_flags: object::elf::PF_R,
+    },
+    // Writable data that `-mcmodel=medium` or `-mcmodel=large` marked as large. Placed after
+    // everything else so that it doesn't push other data out of range of 32 bit offsets.
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_LOAD,
+        segment_flags: object::elf::PF_R | object::elf::PF_W,
+    },
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_NOTE,
+        segment_flags: object::elf::PF_R,
+    },
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_GNU_PROPERTY,
+        segment_flags: object::elf::PF_R,
+    },
+    ProgramSegmentDef {
+        segment_type: crate::sframe::PT_GNU_SFRAME,
+        segment_flags: object::elf::PF_R,
+    },
+    // With `-n` or `-N`, this replaces all the other LOAD segments except the one for large data.
+    ProgramSegmentDef {
+        segment_type: object::elf::PT_LOAD,
+        segment_flags: object::elf::PF_R | object::elf::PF_W | object::elf::PF_X,
+    },
 ];
 
 impl ProgramSegmentId {
@@ -83,9 +123,12 @@ impl ProgramSegmentId {
         )
     }
 
-    pub(crate) fn alignment(&self) -> crate::alignment::Alignment {
+    pub(crate) fn alignment(
+        &self,
+        page_size: crate::alignment::Alignment,
+    ) -> crate::alignment::Alignment {
         if self.segment_type() == object::elf::PT_LOAD {
-            crate::alignment::PAGE
+            page_size
         } else {
             crate::alignment::MIN
         }
@@ -165,4 +208,28 @@ fn test_constant_segment_ids() {
         PROGRAM_SEGMENT_DEFS[NOTE.as_usize()].segment_type,
         object::elf::PT_NOTE
     );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[LOAD_RELRO.as_usize()].segment_flags,
+        object::elf::PF_R | object::elf::PF_W
+    );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[RELRO.as_usize()].segment_type,
+        object::elf::PT_GNU_RELRO
+    );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[PROPERTY_NOTE.as_usize()].segment_type,
+        object::elf::PT_NOTE
+    );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[GNU_PROPERTY.as_usize()].segment_type,
+        object::elf::PT_GNU_PROPERTY
+    );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[SFRAME.as_usize()].segment_type,
+        crate::sframe::PT_GNU_SFRAME
+    );
+    assert_eq!(
+        PROGRAM_SEGMENT_DEFS[LOAD_RWX.as_usize()].segment_flags,
+        object::elf::PF_R | object::elf::PF_W | object::elf::PF_X
+    );
 }
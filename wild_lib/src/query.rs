@@ -0,0 +1,155 @@
+//! Implements `--query-defines`, which reports which inputs define particular symbols without doing
+//! a link. Archives are queried via their symbol table and shared objects via their dynamic symbol
+//! table, so we never need to look at archive members. This lets build systems cheaply decide which
+//! inputs a link needs.
+
+use crate::archive::ArchiveEntry;
+use crate::archive::ArchiveIterator;
+use crate::error::Result;
+use crate::file_kind::FileKind;
+use anyhow::bail;
+use anyhow::Context as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct QueryDefines {
+    pub(crate) inputs: Vec<PathBuf>,
+    pub(crate) symbols: Vec<String>,
+}
+
+impl QueryDefines {
+    /// Builds a query from the arguments following `--query-defines`, which are the inputs to
+    /// search, then `--`, then the symbols to look for. We don't guess which arguments are files,
+    /// since a symbol could easily have the same name as a file in the current directory.
+    pub(crate) fn from_args<S: AsRef<str>>(args: impl Iterator<Item = S>) -> Result<Self> {
+        let mut query = QueryDefines {
+            inputs: Vec::new(),
+            symbols: Vec::new(),
+        };
+        let mut seen_separator = false;
+        for arg in args {
+            let arg = arg.as_ref();
+            if seen_separator {
+                query.symbols.push(arg.to_owned());
+            } else if arg == "--" {
+                seen_separator = true;
+            } else {
+                query.inputs.push(PathBuf::from(arg));
+            }
+        }
+        if !seen_separator {
+            bail!("Usage: --query-defines INPUT... -- SYMBOL...");
+        }
+        if query.inputs.is_empty() {
+            bail!("--query-defines requires at least one input file");
+        }
+        Ok(query)
+    }
+
+    /// Prints each queried symbol followed by the inputs that define it.
+    pub(crate) fn run(&self) -> Result {
+        let mut definitions: HashMap<&[u8], Vec<String>> = self
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.as_bytes(), Vec::new()))
+            .collect();
+        for path in &self.inputs {
+            find_definitions(path, &mut definitions)
+                .with_context(|| format!("Failed to query `{}`", path.display()))?;
+        }
+        for symbol in &self.symbols {
+            let definers = &definitions[symbol.as_bytes()];
+            if definers.is_empty() {
+                println!("{symbol}: not defined");
+            } else {
+                println!("{symbol}: {}", definers.join(" "));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn find_definitions(path: &Path, definitions: &mut HashMap<&[u8], Vec<String>>) -> Result {
+    let bytes = std::fs::read(path)?;
+    match FileKind::identify_bytes(&bytes)? {
+        FileKind::Archive => archive_definitions(&bytes, path, definitions),
+        FileKind::ElfDynamic => elf_definitions(&bytes, true, path, definitions),
+        FileKind::ElfObject => elf_definitions(&bytes, false, path, definitions),
+        _ => bail!("Not an archive, object or shared object"),
+    }
+}
+
+fn archive_definitions(
+    bytes: &[u8],
+    path: &Path,
+    definitions: &mut HashMap<&[u8], Vec<String>>,
+) -> Result {
+    let mut symbol_table = None;
+    let mut extended_filenames = None;
+    let mut member_names = HashMap::new();
+    for entry in ArchiveIterator::from_archive_bytes(bytes)? {
+        match entry? {
+            ArchiveEntry::Symbols(table) => symbol_table = Some(table),
+            ArchiveEntry::Filenames(filenames) => extended_filenames = Some(filenames),
+            ArchiveEntry::Regular(content) => {
+                member_names.insert(
+                    content.header_offset(),
+                    content.identifier(extended_filenames),
+                );
+            }
+        }
+    }
+    let symbol_table =
+        symbol_table.context("Archive has no symbol table. Try running `ranlib` on it")?;
+    for (name, member_offset) in symbol_table.entries()? {
+        let Some(definers) = definitions.get_mut(name) else {
+            continue;
+        };
+        let member = member_names
+            .get(&member_offset)
+            .with_context(|| format!("Symbol table refers to invalid offset {member_offset}"))?;
+        definers.push(format!(
+            "{}({})",
+            path.display(),
+            String::from_utf8_lossy(member.as_slice())
+        ));
+    }
+    Ok(())
+}
+
+fn elf_definitions(
+    bytes: &[u8],
+    is_dynamic: bool,
+    path: &Path,
+    definitions: &mut HashMap<&[u8], Vec<String>>,
+) -> Result {
+    let e = LittleEndian;
+    let object = crate::elf::File::parse(bytes, is_dynamic)?;
+    for symbol in object.symbols.iter() {
+        if symbol.is_undefined(e) || symbol.is_local() {
+            continue;
+        }
+        if let Some(definers) = definitions.get_mut(object.symbol_name(symbol)?) {
+            definers.push(path.display().to_string());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_query_from_args() {
+    let parse = |args: &[&str]| QueryDefines::from_args(args.iter());
+    assert_eq!(
+        parse(&["libfoo.a", "libbar.so", "--", "foo", "Cargo.toml"]).unwrap(),
+        QueryDefines {
+            inputs: vec![PathBuf::from("libfoo.a"), PathBuf::from("libbar.so")],
+            symbols: vec!["foo".to_owned(), "Cargo.toml".to_owned()],
+        }
+    );
+    assert!(parse(&["libfoo.a", "foo"]).is_err());
+    assert!(parse(&["--", "foo"]).is_err());
+}
@@ -4,6 +4,7 @@
 //! binary because dynamic relocations haven't yet been applied to the GOT yet.
 
 use crate::args::OutputKind;
+use crate::args::Relax;
 use crate::elf::RelocationKindInfo;
 use crate::resolution::ValueFlags;
 use linker_utils::elf::shf;
@@ -29,15 +30,26 @@ pub(crate) enum RelaxationKind {
     /// The transformation will look like `mov *x(%rip), reg` ->  `mov x, reg`.
     RexMovIndirectToAbsolute,
 
-    // Transforms an indirect sub to an absolute sub.
-    RexSubIndirectToAbsolute,
+    /// Transforms an arithmetic instruction (add, or, adc, sbb, and, sub, xor or cmp) that reads
+    /// its second operand via the GOT into the form that takes an immediate. e.g. `sub
+    /// *x(%rip), reg` -> `sub x, reg`.
+    ArithIndirectToAbsolute,
 
-    // Transforms an indirect cmp to an absolute cmp.
-    RexCmpIndirectToAbsolute,
+    /// As above, but for instructions with a REX prefix.
+    RexArithIndirectToAbsolute,
+
+    /// Transforms `test reg, *x(%rip)` -> `test x, reg`.
+    TestIndirectToAbsolute,
+
+    /// As above, but for instructions with a REX prefix.
+    RexTestIndirectToAbsolute,
 
     /// Transform a call instruction like `call *x(%rip)` -> `call x(%rip)`.
     CallIndirectToRelative,
 
+    /// Transform a jump instruction like `jmp *x(%rip)` -> `jmp x(%rip); nop`.
+    JmpIndirectToRelative,
+
     /// Leave the instruction alone. Used when we only want to change the kind of relocation used.
     NoOp,
 
@@ -52,6 +64,9 @@ pub(crate) enum RelaxationKind {
 
     /// Transform general dynamic (GD) into initial exec
     TlsGdToInitialExec,
+
+    /// As above, but for the large-model form of the instruction.
+    TlsGdToInitialExecLarge,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -70,6 +85,7 @@ impl Relaxation {
         value_flags: ValueFlags,
         output_kind: OutputKind,
         section_flags: SectionFlags,
+        relax: Relax,
     ) -> Option<Self> {
         fn create(kind: RelaxationKind, new_r_type: u32) -> Option<Relaxation> {
             // This only fails for relocation types that we don't support and if we relax to a type
@@ -104,6 +120,22 @@ impl Relaxation {
             return None;
         }
 
+        let is_allowed = match relocation_kind {
+            object::elf::R_X86_64_REX_GOTPCRELX
+            | object::elf::R_X86_64_GOTPCRELX
+            | object::elf::R_X86_64_GOTPCREL => relax.got,
+            object::elf::R_X86_64_PLT32 | object::elf::R_X86_64_PLTOFF64 => relax.plt,
+            object::elf::R_X86_64_GOTTPOFF
+            | object::elf::R_X86_64_TLSGD
+            | object::elf::R_X86_64_TLSLD => relax.tls,
+            _ => true,
+        };
+        // libc relies on relaxation in static executables, e.g. to reach the GOT before it has been
+        // relocated, so there we relax regardless.
+        if !is_allowed && !output_kind.is_static_executable() {
+            return None;
+        }
+
         let offset = offset_in_section as usize;
         // TODO: Try fetching the symbol kind lazily. For most relocation, we don't need it, but
         // because fetching it contains potential error paths, the optimiser probably can't optimise
@@ -113,67 +145,49 @@ impl Relaxation {
                 if offset < 3 {
                     return None;
                 }
-                let b1 = section_bytes[offset - 2];
+                let opcode = section_bytes[offset - 2];
                 let rex = section_bytes[offset - 3];
-                if rex != 0x48 && rex != 0x4c {
+                if rex & 0xf0 != 0x40 {
                     return None;
                 }
                 if is_absolute || is_absolute_address {
-                    match b1 {
-                        0x8b => {
-                            return create(
-                                RelaxationKind::RexMovIndirectToAbsolute,
-                                object::elf::R_X86_64_32,
-                            );
-                        }
-                        0x2b => {
-                            return create(
-                                RelaxationKind::RexSubIndirectToAbsolute,
-                                object::elf::R_X86_64_32,
-                            );
-                        }
-                        0x3b => {
-                            return create(
-                                RelaxationKind::RexCmpIndirectToAbsolute,
-                                object::elf::R_X86_64_32,
-                            );
-                        }
+                    let kind = match opcode {
+                        0x8b => RelaxationKind::RexMovIndirectToAbsolute,
+                        0x85 => RelaxationKind::RexTestIndirectToAbsolute,
+                        _ if is_arith_opcode(opcode) => RelaxationKind::RexArithIndirectToAbsolute,
                         _ => return None,
-                    }
-                } else if can_bypass_got {
-                    match b1 {
-                        0x8b => {
-                            return create(
-                                RelaxationKind::MovIndirectToLea,
-                                object::elf::R_X86_64_PC32,
-                            );
-                        }
-                        _ => return None,
-                    }
+                    };
+                    return create(kind, object::elf::R_X86_64_32);
+                } else if can_bypass_got && opcode == 0x8b {
+                    return create(RelaxationKind::MovIndirectToLea, object::elf::R_X86_64_PC32);
                 }
             }
             object::elf::R_X86_64_GOTPCRELX => {
+                if offset < 2 {
+                    return None;
+                }
+                let opcode = section_bytes[offset - 2];
                 if is_absolute || is_absolute_address {
-                    match section_bytes.get(offset - 2)? {
-                        0x8b => {
-                            return create(
-                                RelaxationKind::MovIndirectToAbsolute,
-                                object::elf::R_X86_64_32,
-                            );
+                    let kind = match opcode {
+                        0x8b => Some(RelaxationKind::MovIndirectToAbsolute),
+                        0x85 => Some(RelaxationKind::TestIndirectToAbsolute),
+                        _ if is_arith_opcode(opcode) => {
+                            Some(RelaxationKind::ArithIndirectToAbsolute)
                         }
-                        _ => {}
+                        _ => None,
+                    };
+                    if let Some(kind) = kind {
+                        return create(kind, object::elf::R_X86_64_32);
                     }
                 }
                 if can_bypass_got {
-                    match section_bytes.get(offset - 2..offset)? {
-                        [0xff, 0x15] => {
-                            return create(
-                                RelaxationKind::CallIndirectToRelative,
-                                object::elf::R_X86_64_PC32,
-                            )
-                        }
+                    let kind = match section_bytes.get(offset - 2..offset)? {
+                        [0xff, 0x15] => RelaxationKind::CallIndirectToRelative,
+                        [0xff, 0x25] => RelaxationKind::JmpIndirectToRelative,
+                        [0x8b, _] => RelaxationKind::MovIndirectToLea,
                         _ => return None,
-                    }
+                    };
+                    return create(kind, object::elf::R_X86_64_PC32);
                 }
                 return None;
             }
@@ -216,10 +230,7 @@ impl Relaxation {
             object::elf::R_X86_64_TLSGD if output_kind.is_executable() => {
                 let kind = match TlsGdForm::identify(section_bytes, offset)? {
                     TlsGdForm::Regular => RelaxationKind::TlsGdToInitialExec,
-                    TlsGdForm::Large => {
-                        // TODO
-                        return None;
-                    }
+                    TlsGdForm::Large => RelaxationKind::TlsGdToInitialExecLarge,
                 };
                 return create(kind, object::elf::R_X86_64_GOTTPOFF);
             }
@@ -233,6 +244,20 @@ impl Relaxation {
         None
     }
 
+    /// Returns whether the relaxation replaces the instruction that the following relocation
+    /// applies to, which is the case for the TLS relaxations that remove the call to
+    /// `__tls_get_addr`. That relocation should then be ignored.
+    pub(crate) fn skips_next_relocation(&self) -> bool {
+        matches!(
+            self.kind,
+            RelaxationKind::TlsGdToLocalExec
+                | RelaxationKind::TlsGdToLocalExecLarge
+                | RelaxationKind::TlsGdToInitialExec
+                | RelaxationKind::TlsGdToInitialExecLarge
+                | RelaxationKind::TlsLdToLocalExec
+        )
+    }
+
     pub(crate) fn apply(
         &self,
         section_bytes: &mut [u8],
@@ -263,27 +288,50 @@ impl Relaxation {
                 *mod_rm = (*mod_rm >> 3) & 0x7 | 0xc0;
                 *addend = 0;
             }
-            RelaxationKind::RexSubIndirectToAbsolute => {
-                // Turn a PC-relative sub into an absolute sub.
+            RelaxationKind::ArithIndirectToAbsolute => {
+                // Turn e.g. a PC-relative sub into a sub with an immediate. The operation is
+                // encoded in bits 3-5 of the original opcode and moves into the ModR/M byte.
+                let opcode = section_bytes[offset - 2];
+                section_bytes[offset - 2] = 0x81;
+                let mod_rm = &mut section_bytes[offset - 1];
+                *mod_rm = (*mod_rm >> 3) & 0x7 | (opcode & 0x38) | 0xc0;
+                *addend = 0;
+            }
+            RelaxationKind::RexArithIndirectToAbsolute => {
                 let rex = section_bytes[offset - 3];
                 section_bytes[offset - 3] = (rex & !4) | ((rex & 4) >> 2);
+                let opcode = section_bytes[offset - 2];
                 section_bytes[offset - 2] = 0x81;
                 let mod_rm = &mut section_bytes[offset - 1];
-                *mod_rm = (*mod_rm >> 3) & 0x7 | 0xe8;
+                *mod_rm = (*mod_rm >> 3) & 0x7 | (opcode & 0x38) | 0xc0;
+                *addend = 0;
+            }
+            RelaxationKind::TestIndirectToAbsolute => {
+                section_bytes[offset - 2] = 0xf7;
+                let mod_rm = &mut section_bytes[offset - 1];
+                *mod_rm = (*mod_rm >> 3) & 0x7 | 0xc0;
                 *addend = 0;
             }
-            RelaxationKind::RexCmpIndirectToAbsolute => {
-                // Turn a PC-relative cmp into an absolute cmp.
+            RelaxationKind::RexTestIndirectToAbsolute => {
                 let rex = section_bytes[offset - 3];
                 section_bytes[offset - 3] = (rex & !4) | ((rex & 4) >> 2);
-                section_bytes[offset - 2] = 0x81;
+                section_bytes[offset - 2] = 0xf7;
                 let mod_rm = &mut section_bytes[offset - 1];
-                *mod_rm = (*mod_rm >> 3) & 0x7 | 0xf8;
+                *mod_rm = (*mod_rm >> 3) & 0x7 | 0xc0;
                 *addend = 0;
             }
             RelaxationKind::CallIndirectToRelative => {
                 section_bytes[offset - 2..offset].copy_from_slice(&[0x67, 0xe8]);
             }
+            RelaxationKind::JmpIndirectToRelative => {
+                // The jump is a byte shorter than the indirect jump, so the displacement moves
+                // back a byte and we fill the gap at the end with a nop. The place that the
+                // relocation is relative to doesn't move with it, so adjust the addend to match.
+                section_bytes[offset - 2] = 0xe9;
+                section_bytes[offset + 3] = 0x90;
+                *offset_in_section -= 1;
+                *addend = addend.wrapping_add(1);
+            }
             RelaxationKind::TlsGdToLocalExec => {
                 section_bytes[offset - 4..offset + 8].copy_from_slice(&[
                     0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0, // mov %fs:0,%rax
@@ -310,22 +358,43 @@ impl Relaxation {
                 *addend = -12_i64 as u64;
                 *next_modifier = RelocationModifier::SkipNextRelocation;
             }
+            RelaxationKind::TlsGdToInitialExecLarge => {
+                section_bytes[offset - 3..offset + 19].copy_from_slice(&[
+                    0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0, // mov %fs:0,%rax
+                    0x48, 0x03, 0x05, 0, 0, 0, 0, // add {offset}(%rip),%rax
+                    0x66, 0x0f, 0x1f, 0x44, 0, 0, // nopw (%rax,%rax)
+                ]);
+                *offset_in_section += 9;
+                *addend = -13_i64 as u64;
+                *next_modifier = RelocationModifier::SkipNextRelocation;
+            }
             RelaxationKind::TlsLdToLocalExec => {
                 // Transforms to: `mov %fs:0x0,%rax` with some amount of padding depending on
-                // whether the subsequent instruction is 64 bit (first) or 32 bit (second).
-                if section_bytes.get(offset + 4..offset + 6) == Some(&[0x48, 0xb8]) {
-                    section_bytes[offset - 3..offset + 19].copy_from_slice(&[
-                        // nopw (%rax,%rax)
-                        0x66, 0x66, 0x66, 0x66, 0x2e, 0x0f, 0x1f, 0x84, 0, 0, 0, 0, 0,
-                        // mov %fs:0,%rax
-                        0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0,
-                    ]);
-                    *offset_in_section += 15;
-                } else {
-                    section_bytes[offset - 3..offset + 9].copy_from_slice(&[
-                        0x66, 0x66, 0x66, 0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0,
-                    ]);
-                    *offset_in_section += 5;
+                // what follows the `lea`. That's either a 64 bit move of the address of
+                // `__tls_get_addr` (large model), an indirect call via the GOT (-fno-plt) or a
+                // direct call.
+                match section_bytes.get(offset + 4..offset + 6) {
+                    Some([0x48, 0xb8]) => {
+                        section_bytes[offset - 3..offset + 19].copy_from_slice(&[
+                            // nopw (%rax,%rax)
+                            0x66, 0x66, 0x66, 0x66, 0x2e, 0x0f, 0x1f, 0x84, 0, 0, 0, 0, 0,
+                            // mov %fs:0,%rax
+                            0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0,
+                        ]);
+                        *offset_in_section += 15;
+                    }
+                    Some([0xff, 0x15]) => {
+                        section_bytes[offset - 3..offset + 10].copy_from_slice(&[
+                            0x66, 0x66, 0x66, 0x66, 0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0,
+                        ]);
+                        *offset_in_section += 6;
+                    }
+                    _ => {
+                        section_bytes[offset - 3..offset + 9].copy_from_slice(&[
+                            0x66, 0x66, 0x66, 0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0,
+                        ]);
+                        *offset_in_section += 5;
+                    }
                 }
                 *next_modifier = RelocationModifier::SkipNextRelocation;
             }
@@ -334,6 +403,15 @@ impl Relaxation {
     }
 }
 
+/// Returns whether `opcode` is one of the arithmetic instructions that reads its second operand
+/// from memory (`op r32/64, r/m32/64`) and that has a form taking a 32 bit immediate (`81 /digit`).
+fn is_arith_opcode(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x03 | 0x0b | 0x13 | 0x1b | 0x23 | 0x2b | 0x33 | 0x3b
+    )
+}
+
 enum TlsGdForm {
     Regular,
     Large,
@@ -341,8 +419,13 @@ enum TlsGdForm {
 
 impl TlsGdForm {
     fn identify(bytes: &[u8], offset: usize) -> Option<Self> {
+        // The call to `__tls_get_addr` is either direct or, with -fno-plt, via the GOT. Both forms
+        // are the same length.
         if bytes.get(offset - 4..offset) == Some(&[0x66, 0x48, 0x8d, 0x3d])
-            && bytes.get(offset + 4..offset + 8) == Some(&[0x66, 0x66, 0x48, 0xe8])
+            && matches!(
+                bytes.get(offset + 4..offset + 8),
+                Some([0x66, 0x66, 0x48, 0xe8] | [0x66, 0x48, 0xff, 0x15])
+            )
         {
             Some(Self::Regular)
         } else if bytes.get(offset - 3..offset) == Some(&[0x48, 0x8d, 0x3d])
@@ -372,6 +455,7 @@ fn test_relaxation() {
             ValueFlags::ADDRESS,
             OutputKind::StaticExecutable(RelocationModel::Relocatable),
             shf::EXECINSTR,
+            Relax::ALL,
         ) {
             r.apply(&mut out, &mut offset, &mut 0, &mut modifier);
 
@@ -387,6 +471,7 @@ fn test_relaxation() {
             ValueFlags::ABSOLUTE,
             OutputKind::StaticExecutable(RelocationModel::Relocatable),
             shf::EXECINSTR,
+            Relax::ALL,
         ) {
             out.copy_from_slice(bytes_in);
             r.apply(&mut out, &mut offset, &mut 0, &mut modifier);
@@ -403,4 +488,98 @@ fn test_relaxation() {
         &[0x48, 0x8d, 0xae],
         &[0x48, 0xc7, 0xc5],
     );
+    // sub x@GOTPCREL(%rip), %rax
+    check(
+        object::elf::R_X86_64_REX_GOTPCRELX,
+        &[0x48, 0x2b, 0x05],
+        &[0x48, 0x2b, 0x05],
+        &[0x48, 0x81, 0xe8],
+    );
+    // add x@GOTPCREL(%rip), %r8
+    check(
+        object::elf::R_X86_64_REX_GOTPCRELX,
+        &[0x4c, 0x03, 0x05],
+        &[0x4c, 0x03, 0x05],
+        &[0x49, 0x81, 0xc0],
+    );
+    // test %rbx, x@GOTPCREL(%rip)
+    check(
+        object::elf::R_X86_64_REX_GOTPCRELX,
+        &[0x48, 0x85, 0x1d],
+        &[0x48, 0x85, 0x1d],
+        &[0x48, 0xf7, 0xc3],
+    );
+    // xor x@GOTPCREL(%rip), %ecx
+    check(
+        object::elf::R_X86_64_GOTPCRELX,
+        &[0x33, 0x0d],
+        &[0x33, 0x0d],
+        &[0x81, 0xf1],
+    );
+
+    // jmp *x@GOTPCREL(%rip)
+    let mut bytes = [0xff, 0x25, 0, 0, 0, 0];
+    let mut offset = 2;
+    let mut addend = -4_i64 as u64;
+    let relaxation = Relaxation::new(
+        object::elf::R_X86_64_GOTPCRELX,
+        &bytes,
+        offset,
+        ValueFlags::ADDRESS | ValueFlags::CAN_BYPASS_GOT,
+        OutputKind::StaticExecutable(RelocationModel::Relocatable),
+        shf::EXECINSTR,
+        Relax::ALL,
+    )
+    .unwrap();
+    relaxation.apply(
+        &mut bytes,
+        &mut offset,
+        &mut addend,
+        &mut RelocationModifier::Normal,
+    );
+    // The displacement, which now starts at offset 1, gets written when the relocation is applied.
+    assert_eq!(bytes, [0xe9, 0x25, 0, 0, 0, 0x90]);
+    assert_eq!(offset, 1);
+    assert_eq!(addend, -3_i64 as u64);
+
+    // lea x@tlsld(%rip),%rdi; call *__tls_get_addr@GOTPCREL(%rip)
+    let mut bytes = [0x48, 0x8d, 0x3d, 0, 0, 0, 0, 0xff, 0x15, 0, 0, 0, 0];
+    let mut offset = 3;
+    let mut modifier = RelocationModifier::Normal;
+    let relaxation = Relaxation::new(
+        object::elf::R_X86_64_TLSLD,
+        &bytes,
+        offset,
+        ValueFlags::ADDRESS,
+        OutputKind::StaticExecutable(RelocationModel::NonRelocatable),
+        shf::EXECINSTR,
+        Relax::ALL,
+    )
+    .unwrap();
+    assert!(relaxation.skips_next_relocation());
+    relaxation.apply(&mut bytes, &mut offset, &mut 0, &mut modifier);
+    assert_eq!(
+        bytes,
+        [0x66, 0x66, 0x66, 0x66, 0x64, 0x48, 0x8b, 0x04, 0x25, 0, 0, 0, 0]
+    );
+    assert!(modifier == RelocationModifier::SkipNextRelocation);
+
+    // Disabling a class of relaxation leaves its relocations alone.
+    let relax_call = |relax| {
+        Relaxation::new(
+            object::elf::R_X86_64_PLT32,
+            &[0xe8, 0, 0, 0, 0],
+            1,
+            ValueFlags::ADDRESS | ValueFlags::CAN_BYPASS_GOT,
+            OutputKind::DynamicExecutable(RelocationModel::NonRelocatable),
+            shf::EXECINSTR,
+            relax,
+        )
+    };
+    assert!(relax_call(Relax::ALL).is_some());
+    assert!(relax_call(Relax {
+        plt: false,
+        ..Relax::ALL
+    })
+    .is_none());
 }
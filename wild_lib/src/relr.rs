@@ -0,0 +1,200 @@
+//! Support for packing relative relocations into `.relr.dyn`. See `-z pack-relative-relocs`. Each
+//! entry is either an even address of a word to which the dynamic loader should add the load base,
+//! or an odd bitmap whose bits 1 to 63 say which of the 63 words following the previous entry's
+//! words also need it.
+//!
+//! We encode the relocations of each input section separately, and likewise the GOT entries of each
+//! group. Since the number of entries needed for a set of places only depends on the distances
+//! between them, we can work out how much space each input section needs before we assign
+//! addresses.
+
+use crate::elf::File;
+use crate::error::Result;
+use anyhow::Context as _;
+use object::LittleEndian;
+
+pub(crate) const DT_RELRSZ: u32 = 35;
+pub(crate) const DT_RELR: u32 = 36;
+pub(crate) const DT_RELRENT: u32 = 37;
+
+pub(crate) const ENTRY_SIZE: u64 = size_of::<u64>() as u64;
+
+/// The version that glibc defines to indicate that its dynamic loader supports DT_RELR.
+const GLIBC_ABI_DT_RELR: &[u8] = b"GLIBC_ABI_DT_RELR";
+
+/// The number of words that a bitmap entry covers.
+const BITMAP_WORDS: u64 = u64::BITS as u64 - 1;
+
+/// A bitmap with no bits set. We use these to fill any space that we allocated, but didn't need.
+const PADDING: u64 = 1;
+
+/// Calls `emit` with each entry needed to relocate `places`, which must be sorted, unique and even.
+fn encode(places: &[u64], mut emit: impl FnMut(u64) -> Result) -> Result {
+    let mut places = places.iter().copied().peekable();
+    while let Some(start) = places.next() {
+        emit(start)?;
+        let mut base = start + ENTRY_SIZE;
+        loop {
+            let mut bitmap = 0;
+            while let Some(&place) = places.peek() {
+                let offset = place.wrapping_sub(base);
+                if offset % ENTRY_SIZE != 0 || offset / ENTRY_SIZE >= BITMAP_WORDS {
+                    break;
+                }
+                bitmap |= 1 << (offset / ENTRY_SIZE + 1);
+                places.next();
+            }
+            if bitmap == 0 {
+                break;
+            }
+            emit(bitmap | 1)?;
+            base += BITMAP_WORDS * ENTRY_SIZE;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of entries needed to relocate `places`, which are offsets within a section.
+/// Note, `places` gets sorted.
+pub(crate) fn num_entries(places: &mut Vec<u64>) -> u64 {
+    places.sort_unstable();
+    places.dedup();
+    let mut count = 0;
+    let _ = encode(places, |_| {
+        count += 1;
+        Ok(())
+    });
+    count
+}
+
+/// Returns an upper bound on the number of entries needed for `num_relocations` of a group's
+/// `num_got_entries` GOT entries. We don't know which GOT entries they'll be until we write them.
+///
+/// Each run of entries starts with an address. If the run has no bitmaps, then nothing in the
+/// following 63 words needs relocating, so the run accounts for at least 64 words. Otherwise, a run
+/// with `k` bitmaps covers `1 + 63 * k` words. Either way, all runs but the last use at most one
+/// entry per 32 words. The last run can use one or two more than that.
+pub(crate) fn got_entries_bound(num_relocations: u64, num_got_entries: u64) -> u64 {
+    num_relocations.min(num_got_entries / 32 + 2)
+}
+
+/// Returns whether we can pack relative relocations. glibc before 2.36 ignores DT_RELR, so if we're
+/// linking against a glibc that doesn't define GLIBC_ABI_DT_RELR, we use RELA instead, as GNU ld
+/// does.
+pub(crate) fn can_pack<'data>(
+    mut shared_objects: impl Iterator<Item = &'data File<'data>>,
+) -> bool {
+    shared_objects.all(|object| {
+        let is_glibc = crate::elf::DynamicTagValues::read(object)
+            .is_ok_and(|tags| tags.soname == Some(b"libc.so.6".as_slice()));
+        !is_glibc || glibc_abi_version(object).is_ok_and(|version| version.is_some())
+    })
+}
+
+/// Returns the index of the GLIBC_ABI_DT_RELR version if `object` defines it. We reference this
+/// version so that older versions of glibc, which would ignore our relocations, refuse to load us.
+pub(crate) fn glibc_abi_version(object: &File) -> Result<Option<u16>> {
+    let e = LittleEndian;
+    let Some((mut verdefs, link)) = object.verdef.clone() else {
+        return Ok(None);
+    };
+    let strings = object.sections.strings(e, object.data, link)?;
+    while let Some((verdef, mut auxes)) = verdefs.next()? {
+        let aux = auxes.next()?.context("VERDEF with no AUX entry")?;
+        if aux.name(e, strings)? == GLIBC_ABI_DT_RELR {
+            return Ok(Some(verdef.vd_ndx.get(e)));
+        }
+    }
+    Ok(None)
+}
+
+/// Encodes the packed relative relocations of a group into the part of `.relr.dyn` that layout
+/// allocated for them.
+pub(crate) struct RelrWriter<'out> {
+    out: &'out mut [u64],
+
+    /// Places that we've been asked to relocate, but haven't yet encoded.
+    places: Vec<u64>,
+}
+
+impl<'out> RelrWriter<'out> {
+    pub(crate) fn new(out: &'out mut [u64]) -> Self {
+        Self {
+            out,
+            places: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, place: u64) {
+        self.places.push(place);
+    }
+
+    /// Encodes the places added since the last flush.
+    pub(crate) fn flush(&mut self) -> Result {
+        if self.places.is_empty() {
+            return Ok(());
+        }
+        self.places.sort_unstable();
+        self.places.dedup();
+        let out = &mut self.out;
+        encode(&self.places, |entry| {
+            *crate::slice::take_first_mut(out).context("Insufficient allocation to .relr.dyn")? =
+                entry;
+            Ok(())
+        })?;
+        self.places.clear();
+        Ok(())
+    }
+
+    /// Encodes any remaining places, then pads out whatever space we didn't use.
+    pub(crate) fn finish(&mut self) -> Result {
+        self.flush()?;
+        core::mem::take(&mut self.out).fill(PADDING);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_encode() {
+    let mut places = vec![
+        0x1010,
+        0x1000,
+        0x1008,
+        0x1008,
+        0x1400,
+        0x1400 + 63 * 8,
+        0x2004,
+    ];
+    assert_eq!(num_entries(&mut places), 5);
+    let mut entries = Vec::new();
+    encode(&places, |entry| {
+        entries.push(entry);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        entries,
+        [0x1000, 0b111, 0x1400, (1 << 63) | 1, 0x2004].as_slice()
+    );
+}
+
+#[test]
+fn test_got_entries_bound() {
+    // Runs without bitmaps alternate with runs that have a single bitmap.
+    for num_got_entries in 1..1000_u64 {
+        let places = (0..num_got_entries)
+            .filter(|i| [0, 64, 65].contains(&(i % 191)))
+            .map(|i| i * ENTRY_SIZE)
+            .collect::<Vec<_>>();
+        let num_relocations = places.len() as u64;
+        assert!(
+            num_entries(&mut places.clone()) <= got_entries_bound(num_relocations, num_got_entries)
+        );
+        let all = (0..num_got_entries)
+            .map(|i| i * ENTRY_SIZE)
+            .collect::<Vec<_>>();
+        assert!(
+            num_entries(&mut all.clone()) <= got_entries_bound(num_got_entries, num_got_entries)
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Support for `--reproduce`. We write a tar archive containing every file that we read, together
+//! with a response file that repeats the link using those copies. Someone investigating a bug can
+//! then extract the archive anywhere and run `wild @response.txt` from the top-level directory of
+//! the archive.
+//!
+//! Files are stored under their absolute paths, relative to the top-level directory, so that
+//! library search directories can be rewritten the same way and still find the same libraries.
+//! The output is written to the current directory and options that would write additional files,
+//! such as `-Map` and `--dependency-file`, are dropped from the response file.
+
+use crate::args::Args;
+use crate::error::Result;
+use crate::input_data::InputData;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Options that we drop from the response file because they'd write extra files, together with
+/// whether each takes a separate argument as its value when it isn't given with `=`.
+const DROPPED_OPTIONS: &[(&str, bool)] = &[
+    ("reproduce", true),
+    ("Map", true),
+    ("dependency-file", true),
+    ("separate-debug-file", false),
+    ("stats", false),
+    ("why-extract", false),
+];
+
+/// Options whose value is a directory that we should rewrite to point into the archive.
+const DIRECTORY_OPTIONS: &[&str] = &["L", "library-path", "sysroot"];
+
+/// Options with a separate value that must be passed through unchanged, even if it happens to name
+/// a file that we packed. For example, the dynamic linker is usually also an input.
+const VERBATIM_VALUE_OPTIONS: &[&str] = &[
+    "dynamic-linker",
+    "I",
+    "rpath",
+    "R",
+    "rpath-link",
+    "soname",
+    "h",
+    "plugin",
+    "plugin-opt",
+];
+
+#[tracing::instrument(skip_all, name = "Write reproduce archive")]
+pub(crate) fn write(path: &Path, args: &Args, input_data: &InputData) -> Result {
+    write_archive(path, args, input_data)
+        .with_context(|| format!("Failed to write reproduce archive `{}`", path.display()))
+}
+
+fn write_archive(path: &Path, args: &Args, input_data: &InputData) -> Result {
+    let root = path
+        .file_stem()
+        .context("Invalid --reproduce path")?
+        .to_string_lossy()
+        .into_owned();
+    let cwd = std::env::current_dir()?;
+
+    let mut packed = HashMap::new();
+    for input_path in input_data.dependency_paths() {
+        let absolute = normalise(&cwd.join(input_path));
+        let relative = relative_path(&absolute);
+        packed.insert(absolute, relative);
+    }
+
+    let mut tar = TarWriter::new(std::io::BufWriter::new(std::fs::File::create(path)?));
+    tar.append(
+        &format!("{root}/response.txt"),
+        response_file(&args.command_line, &cwd, &packed).as_bytes(),
+    )?;
+    tar.append(
+        &format!("{root}/version.txt"),
+        format!("wild {}\n", env!("CARGO_PKG_VERSION")).as_bytes(),
+    )?;
+    let mut files = packed.iter().collect::<Vec<_>>();
+    files.sort();
+    for (absolute, relative) in files {
+        let data = std::fs::read(absolute)
+            .with_context(|| format!("Failed to read `{}`", absolute.display()))?;
+        tar.append(&format!("{root}/{relative}"), &data)?;
+    }
+    tar.finish()?;
+    Ok(())
+}
+
+/// Returns the contents of a response file that repeats the link with the arguments `args`,
+/// rewriting paths of files that we packed to point at the packed copies.
+fn response_file(args: &[String], cwd: &Path, packed: &HashMap<PathBuf, String>) -> String {
+    let rewrite_file = |value: &str| {
+        packed
+            .get(&normalise(&cwd.join(value)))
+            .cloned()
+            .unwrap_or_else(|| value.to_owned())
+    };
+    let rewrite_directory = |value: &str| relative_path(&normalise(&cwd.join(value)));
+
+    let mut out = String::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let option = arg.strip_prefix("--").or(arg.strip_prefix('-'));
+        let (name, value) = match option.and_then(|option| option.split_once('=')) {
+            Some((name, value)) => (name, Some(value)),
+            None => (option.unwrap_or_default(), None),
+        };
+        let rewritten = if let Some(&(_, separate_value)) =
+            DROPPED_OPTIONS.iter().find(|(dropped, _)| *dropped == name)
+        {
+            if value.is_none() && separate_value {
+                args.next();
+            }
+            continue;
+        } else if let Some(output) = output_value(arg, name, value, &mut args) {
+            // The output goes in the current directory, so that replaying the link doesn't
+            // overwrite the original output.
+            let output = Path::new(output)
+                .file_name()
+                .map(|output| output.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            push_arg(&mut out, "-o");
+            output
+        } else if DIRECTORY_OPTIONS.contains(&name) {
+            match value {
+                Some(value) => format!("--{name}={}", rewrite_directory(value)),
+                None => {
+                    push_arg(&mut out, arg);
+                    args.next()
+                        .map(|dir| rewrite_directory(dir))
+                        .unwrap_or_default()
+                }
+            }
+        } else if value.is_none() && VERBATIM_VALUE_OPTIONS.contains(&name) {
+            push_arg(&mut out, arg);
+            args.next().cloned().unwrap_or_default()
+        } else if let Some(dir) = arg.strip_prefix("-L") {
+            format!("-L{}", rewrite_directory(dir))
+        } else if let Some(value) = value {
+            format!(
+                "{}={}",
+                &arg[..arg.len() - value.len() - 1],
+                rewrite_file(value)
+            )
+        } else if option.is_none() {
+            rewrite_file(arg)
+        } else {
+            arg.clone()
+        };
+        push_arg(&mut out, &rewritten);
+    }
+    out
+}
+
+/// If `arg` specifies the output file, returns the output, taking it from `args` if it's given
+/// separately. Like the argument parser, we accept `-o foo`, `--output=foo` and the joined `-ofoo`,
+/// but not `-omagic`, which is a different option.
+fn output_value<'a>(
+    arg: &'a str,
+    name: &str,
+    value: Option<&'a str>,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Option<&'a str> {
+    if name == "o" || name == "output" {
+        return Some(
+            value
+                .or_else(|| args.next().map(|a| a.as_str()))
+                .unwrap_or_default(),
+        );
+    }
+    if arg.starts_with("--") || name == "omagic" {
+        return None;
+    }
+    arg.strip_prefix("-o").filter(|output| !output.is_empty())
+}
+
+/// Appends `arg` to a response file, quoting it if necessary.
+fn push_arg(out: &mut String, arg: &str) {
+    if arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') || arg.is_empty() {
+        let quote = if arg.contains('"') { '\'' } else { '"' };
+        out.push(quote);
+        out.push_str(arg);
+        out.push(quote);
+    } else {
+        out.push_str(arg);
+    }
+    out.push('\n');
+}
+
+/// Removes `.` and `..` components from `path` without consulting the filesystem.
+fn normalise(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Returns where in the archive, relative to its top-level directory, we put `absolute`.
+fn relative_path(absolute: &Path) -> String {
+    absolute
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes a POSIX tar archive. Paths too long for the ustar header are stored in PAX extended
+/// headers.
+struct TarWriter<W: std::io::Write> {
+    out: W,
+}
+
+const BLOCK_SIZE: usize = 512;
+
+impl<W: std::io::Write> TarWriter<W> {
+    fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    fn append(&mut self, path: &str, data: &[u8]) -> Result {
+        if path.len() >= 100 {
+            let record = pax_record("path", path);
+            self.write_entry(
+                &ustar_header("PaxHeader", record.len(), b'x'),
+                record.as_bytes(),
+            )?;
+        }
+        let header = ustar_header(path, data.len(), b'0');
+        self.write_entry(&header, data)
+    }
+
+    fn write_entry(&mut self, header: &[u8; BLOCK_SIZE], data: &[u8]) -> Result {
+        self.out.write_all(header)?;
+        self.out.write_all(data)?;
+        let padding = data.len().next_multiple_of(BLOCK_SIZE) - data.len();
+        self.out.write_all(&[0; BLOCK_SIZE][..padding])?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result {
+        // An archive ends with two empty blocks.
+        self.out.write_all(&[0; BLOCK_SIZE * 2])?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Returns a ustar header. If `path` is too long, it gets truncated, so callers need to have
+/// written a PAX header with the full path.
+fn ustar_header(path: &str, size: usize, type_flag: u8) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+    let name = &path.as_bytes()[..path.len().min(99)];
+    header[..name.len()].copy_from_slice(name);
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size as u64);
+    write_octal(&mut header[136..148], 0);
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with the checksum field itself set to spaces.
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| u64::from(b)).sum::<u64>();
+    write_octal(&mut header[148..155], checksum);
+    header
+}
+
+/// Writes `value` as zero-padded octal, followed by a null terminator.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    field[..digits].copy_from_slice(format!("{value:0digits$o}").as_bytes());
+    field[digits] = 0;
+}
+
+/// Returns a PAX extended header record. Each record starts with its own length in decimal,
+/// including the digits of the length.
+fn pax_record(key: &str, value: &str) -> String {
+    let without_length = key.len() + value.len() + 3;
+    let mut length = without_length + 1;
+    while length != without_length + length.to_string().len() {
+        length = without_length + length.to_string().len();
+    }
+    format!("{length} {key}={value}\n")
+}
+
+#[test]
+fn test_pax_record() {
+    let record = pax_record("path", &"a".repeat(100));
+    assert_eq!(record.len(), 110);
+    assert!(record.starts_with("110 path="));
+    let record = pax_record("path", "abcd");
+    assert_eq!(record, "13 path=abcd\n");
+}
+
+#[test]
+fn test_response_file() {
+    let cwd = Path::new("/work");
+    let packed = HashMap::from([
+        (PathBuf::from("/work/a.o"), "work/a.o".to_owned()),
+        (
+            PathBuf::from("/usr/lib/crt1.o"),
+            "usr/lib/crt1.o".to_owned(),
+        ),
+        (PathBuf::from("/work/v.map"), "work/v.map".to_owned()),
+    ]);
+    let args = [
+        "a.o",
+        "/usr/lib/../lib/crt1.o",
+        "-o",
+        "out/prog",
+        "-L/usr/lib",
+        "-L",
+        "libs",
+        "--version-script=v.map",
+        "-Map=prog.map",
+        "--reproduce",
+        "r.tar",
+        "-lc",
+        "--rpath=/opt/my lib",
+        "-dynamic-linker",
+        "/usr/lib/crt1.o",
+        "-omagic",
+    ]
+    .map(String::from);
+    assert_eq!(
+        response_file(&args, cwd, &packed),
+        "work/a.o\nusr/lib/crt1.o\n-o\nprog\n-Lusr/lib\n-L\nwork/libs\n\
+         --version-script=work/v.map\n-lc\n\"--rpath=/opt/my lib\"\n-dynamic-linker\n/usr/lib/crt1.o\n\
+         -omagic\n"
+    );
+}
+
+#[test]
+fn test_response_file_drops_extra_outputs() {
+    let cwd = Path::new("/work");
+    let packed = HashMap::from([(PathBuf::from("/work/a.o"), "work/a.o".to_owned())]);
+    let args = [
+        "--stats",
+        "a.o",
+        "--stats=stats.txt",
+        "--why-extract=why.txt",
+        "--separate-debug-file",
+        "--separate-debug-file=prog.debug",
+        "--dependency-file",
+        "prog.d",
+        "-o",
+        "prog",
+    ]
+    .map(String::from);
+    assert_eq!(response_file(&args, cwd, &packed), "work/a.o\n-o\nprog\n");
+}
+
+#[test]
+fn test_response_file_joined_output() {
+    let cwd = Path::new("/work");
+    let packed = HashMap::from([(PathBuf::from("/work/a.o"), "work/a.o".to_owned())]);
+    let args = ["a.o", "-oout/prog"].map(String::from);
+    assert_eq!(response_file(&args, cwd, &packed), "work/a.o\n-o\nprog\n");
+    let args = ["a.o", "--output=out/prog"].map(String::from);
+    assert_eq!(response_file(&args, cwd, &packed), "work/a.o\n-o\nprog\n");
+}
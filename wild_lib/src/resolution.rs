@@ -3,6 +3,8 @@
 //! assigned to.
 
 use crate::args::Args;
+use crate::args::DefsymValue;
+use crate::args::Magic;
 use crate::debug_assert_bail;
 use crate::elf::File;
 use crate::error::Error;
@@ -39,12 +41,17 @@ use bitflags::bitflags;
 use crossbeam_queue::ArrayQueue;
 use crossbeam_queue::SegQueue;
 use crossbeam_utils::atomic::AtomicCell;
+use fxhash::FxHashMap;
 use itertools::Itertools;
+use linker_utils::elf::shf;
+use linker_utils::elf::sht;
 use linker_utils::elf::SectionFlags;
 use linker_utils::elf::SectionType;
+use object::read::elf::SectionHeader as _;
 use object::read::elf::Sym as _;
 use object::LittleEndian;
-use rayon::iter::ParallelBridge;
+use rayon::iter::IndexedParallelIterator;
+use rayon::iter::IntoParallelRefMutIterator;
 use rayon::iter::ParallelIterator;
 use std::collections::HashMap;
 use std::fmt::Display;
@@ -60,6 +67,29 @@ pub(crate) struct ResolutionOutputs<'data> {
     pub(crate) output_sections: OutputSections<'data>,
     pub(crate) merged_strings: OutputSectionMap<MergeStringsSection<'data>>,
     pub(crate) custom_start_stop_defs: Vec<InternalSymDefInfo>,
+
+    /// Why each archive member that we loaded was loaded.
+    pub(crate) load_reasons: LoadReasons,
+
+    /// Symbols that were referenced, but which nothing defined. Each name appears once, as the
+    /// symbol that other references to that name were redirected to.
+    pub(crate) undefined_symbols: Vec<SymbolId>,
+
+    /// Errors, such as duplicate symbols, that don't stop us from continuing. They're reported once
+    /// we've found any undefined symbols, so that they can all be reported together.
+    pub(crate) errors: Vec<Error>,
+}
+
+pub(crate) type LoadReasons = FxHashMap<FileId, LoadReason>;
+
+/// The first reference that caused an archive member to be loaded.
+#[derive(Clone, Copy)]
+pub(crate) struct LoadReason {
+    /// The file containing the reference.
+    pub(crate) requester: FileId,
+
+    /// The symbol in `requester` that was undefined.
+    pub(crate) symbol_id: SymbolId,
 }
 
 #[tracing::instrument(skip_all, name = "Symbol resolution")]
@@ -68,17 +98,21 @@ pub fn resolve_symbols_and_sections<'data>(
     symbol_db: &mut SymbolDb<'data>,
     herd: &'data bumpalo_herd::Herd,
 ) -> Result<ResolutionOutputs<'data>> {
-    let (mut groups, undefined_symbols, internal) =
+    let (mut groups, undefined_symbols, internal, load_reasons) =
         resolve_symbols_in_files(groups, symbol_db, herd)?;
 
+    crate::comdat::discard_duplicate_groups(&mut groups)?;
+
     let output_sections = assign_section_ids(&mut groups, symbol_db.args)?;
 
     let merged_strings = merge_strings(&mut groups, &output_sections)?;
 
-    let custom_start_stop_defs =
+    let (custom_start_stop_defs, undefined_symbols) =
         canonicalise_undefined_symbols(undefined_symbols, &output_sections, &groups, symbol_db)?;
 
-    resolve_alternative_symbol_definitions(symbol_db, &groups)?;
+    let errors = resolve_alternative_symbol_definitions(symbol_db, &groups, internal)?;
+
+    resolve_defsym_aliases(symbol_db, internal, &groups)?;
 
     groups[PRELUDE_FILE_ID.group()].files[PRELUDE_FILE_ID.file()] =
         ResolvedFile::Prelude(ResolvedPrelude {
@@ -89,6 +123,9 @@ pub fn resolve_symbols_and_sections<'data>(
         output_sections,
         merged_strings,
         custom_start_stop_defs,
+        load_reasons,
+        undefined_symbols,
+        errors,
     })
 }
 
@@ -104,7 +141,8 @@ pub(crate) fn resolve_symbols_in_files<'data>(
 ) -> Result<(
     Vec<ResolvedGroup<'data>>,
     SegQueue<UndefinedSymbol<'data>>,
-    &'data Prelude,
+    &'data Prelude<'data>,
+    LoadReasons,
 )> {
     let mut num_objects = 0;
     let mut objects = Vec::new();
@@ -183,16 +221,41 @@ pub(crate) fn resolve_symbols_in_files<'data>(
 
     let num_threads = symbol_db.args.num_threads.get();
 
+    let names_for_needed = groups
+        .iter()
+        .map(|group| {
+            group
+                .files
+                .iter()
+                .map(|file| match file {
+                    ParsedInput::Object(obj) if obj.is_dynamic() && obj.is_optional() => {
+                        crate::elf::DynamicTagValues::read(&obj.object)
+                            .ok()
+                            .map(|dt_info| {
+                                crate::needed_libs::name_for_needed(&dt_info, obj.input.lib_name())
+                            })
+                    }
+                    _ => None,
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
     let resources = ResolutionResources {
         groups,
         definitions_per_file: &definitions_per_group_and_file,
         idle_threads: (num_threads > 1).then(|| ArrayQueue::new(num_threads - 1)),
         symbol_db,
+        names_for_needed,
         outputs: &outputs,
         work_queue,
         loaded_metrics: Default::default(),
     };
 
+    if let Some(prelude) = prelude {
+        request_defsym_targets(prelude, &resources);
+    }
+
     let done = AtomicBool::new(false);
 
     crate::threading::scope(|s| {
@@ -209,8 +272,7 @@ pub(crate) fn resolve_symbols_in_files<'data>(
                             &allocator,
                         );
                         if let Err(e) = r {
-                            // We currently only store the first error.
-                            let _ = resources.outputs.errors.push(e);
+                            resources.outputs.errors.push(e);
                         }
                     }
                     if idle {
@@ -259,16 +321,53 @@ pub(crate) fn resolve_symbols_in_files<'data>(
     drop(resources);
     drop(definitions_per_group_and_file);
     symbol_db.restore_definitions(symbol_definitions);
-    if let Some(e) = outputs.errors.pop() {
-        return Err(e);
-    }
+    let mut errors = Vec::from_iter(outputs.errors);
+    // Errors arrive in whatever order our threads found them, so sort them to make our output
+    // deterministic.
+    errors.sort_by_cached_key(|e| format!("{e:#}"));
+    crate::error::combine(errors, symbol_db.args.error_limit)?;
 
     for obj in outputs.loaded {
         let file_id = obj.file_id;
         resolved[file_id.group()].files[file_id.file()] = ResolvedFile::Object(obj);
     }
 
-    Ok((resolved, outputs.undefined_symbols, prelude.unwrap()))
+    let load_reasons = outputs.load_reasons.into_iter().collect();
+
+    Ok((
+        resolved,
+        outputs.undefined_symbols,
+        prelude.unwrap(),
+        load_reasons,
+    ))
+}
+
+/// Requests loading of the files that define the symbols that `--defsym` options refer to, since
+/// nothing else might reference them.
+fn request_defsym_targets(prelude: &Prelude, resources: &ResolutionResources) {
+    for (index, def_info) in prelude.symbol_definitions.iter().enumerate() {
+        let InternalSymDefInfo::Defsym(defsym_index) = def_info else {
+            continue;
+        };
+        let DefsymValue::Symbol(target) = &prelude.defsyms[*defsym_index].value else {
+            continue;
+        };
+        let Some(&target_id) = resources
+            .symbol_db
+            .global_names
+            .get(&SymbolName::prehashed(target.as_bytes()))
+        else {
+            // We report this once we've finished resolution.
+            continue;
+        };
+        resources.request_file_id(
+            resources.symbol_db.file_id_for_symbol(target_id),
+            LoadReason {
+                requester: PRELUDE_FILE_ID,
+                symbol_id: SymbolId::from_usize(index),
+            },
+        );
+    }
 }
 
 struct WorkItem<'definitions> {
@@ -288,15 +387,21 @@ struct ResolutionResources<'data, 'definitions, 'outer_scope> {
     definitions_per_file: &'outer_scope Vec<Vec<DefinitionsCell<'definitions>>>,
     idle_threads: Option<ArrayQueue<Thread>>,
     symbol_db: &'outer_scope SymbolDb<'data>,
+
+    /// For each --as-needed shared object, the name by which other shared objects would refer to
+    /// it in their DT_NEEDED entries. Indexed by group then file.
+    names_for_needed: Vec<Vec<Option<&'data [u8]>>>,
+
     outputs: &'outer_scope Outputs<'data>,
     work_queue: SegQueue<WorkItem<'definitions>>,
     loaded_metrics: LoadedMetrics,
 }
 
 impl<'data, 'definitions, 'outer_scope> ResolutionResources<'data, 'definitions, 'outer_scope> {
-    fn request_file_id(&self, file_id: FileId) {
+    fn request_file_id(&self, file_id: FileId, reason: LoadReason) {
         if let Some(definitions) = self.definitions_per_file[file_id.group()][file_id.file()].take()
         {
+            self.outputs.load_reasons.push((file_id, reason));
             self.work_queue.push(WorkItem {
                 definitions: *definitions,
                 file_id,
@@ -322,13 +427,15 @@ impl<'data, 'definitions, 'outer_scope> ResolutionResources<'data, 'definitions,
 fn resolve_alternative_symbol_definitions<'data>(
     symbol_db: &mut SymbolDb<'data>,
     resolved: &[ResolvedGroup],
-) -> Result {
+    prelude: &Prelude,
+) -> Result<Vec<Error>> {
     // For now, we do this from a single thread since we don't expect a lot of symbols will have
     // multiple definitions. If it turns out that there are cases where it's actually taking
     // significant time, then we could parallelise this without too much work.
     let previous_definitions = core::mem::take(&mut symbol_db.alternative_definitions);
     let symbols_with_alternatives = core::mem::take(&mut symbol_db.symbols_with_alternatives);
     let mut alternatives = Vec::new();
+    let mut duplicates = Vec::new();
     for first in symbols_with_alternatives {
         alternatives.clear();
         let mut symbol_id = first;
@@ -339,11 +446,85 @@ fn resolve_alternative_symbol_definitions<'data>(
             }
             alternatives.push(symbol_id);
         }
-        let selected = select_symbol(symbol_db, first, &alternatives, resolved);
+        // As with GNU ld, symbols defined by --defsym override definitions from our inputs. The
+        // prelude is our first file, so if it defines a symbol, it'll always be `first`.
+        let is_defsym = symbol_db.file_id_for_symbol(first) == PRELUDE_FILE_ID
+            && matches!(
+                prelude.symbol_definitions[first.as_usize()],
+                InternalSymDefInfo::Defsym(_)
+            );
+        let selected = if is_defsym {
+            first
+        } else {
+            select_symbol(symbol_db, first, &alternatives, resolved)
+        };
         symbol_db.replace_definition(first, selected);
         for &alt in &alternatives {
             symbol_db.replace_definition(alt, selected);
         }
+        if is_defsym {
+            continue;
+        }
+        let strong = std::iter::once(first)
+            .chain(alternatives.iter().rev().copied())
+            .filter(|&symbol_id| {
+                !symbol_db
+                    .symbol_value_flags(symbol_id)
+                    .contains(ValueFlags::DYNAMIC)
+                    && symbol_db.symbol_strength(symbol_id, resolved) == SymbolStrength::Strong
+            })
+            .collect::<Vec<_>>();
+        if strong.len() > 1 {
+            duplicates.push(strong);
+        }
+    }
+    crate::duplicate_symbols::check(symbol_db, resolved, &duplicates)
+}
+
+/// Gives each symbol that `--defsym` defines as an alias of another symbol the same kind of value
+/// as that symbol. Its actual value gets copied once the other symbol's address is known.
+fn resolve_defsym_aliases(
+    symbol_db: &mut SymbolDb,
+    prelude: &Prelude,
+    resolved: &[ResolvedGroup],
+) -> Result {
+    for (index, def_info) in prelude.symbol_definitions.iter().enumerate() {
+        let InternalSymDefInfo::Defsym(defsym_index) = def_info else {
+            continue;
+        };
+        let defsym = &prelude.defsyms[*defsym_index];
+        let DefsymValue::Symbol(target) = &defsym.value else {
+            continue;
+        };
+        let symbol_id = SymbolId::from_usize(index);
+        let target_id = symbol_db
+            .defsym_target(target)
+            .filter(|&target_id| {
+                let file_id = symbol_db.file_id_for_symbol(target_id);
+                !matches!(
+                    resolved[file_id.group()].files[file_id.file()],
+                    ResolvedFile::NotLoaded(_)
+                )
+            })
+            .with_context(|| format!("--defsym {}: undefined symbol `{target}`", defsym.name))?;
+        let target_flags = symbol_db.symbol_value_flags(target_id);
+        if target_flags.contains(ValueFlags::DYNAMIC) {
+            bail!(
+                "--defsym {}: `{target}` is defined by a shared object, which isn't supported",
+                defsym.name
+            );
+        }
+        if target_flags.contains(ValueFlags::IFUNC) {
+            bail!(
+                "--defsym {}: `{target}` is an ifunc, which isn't supported",
+                defsym.name
+            );
+        }
+        let mut value_flags = (target_flags & (ValueFlags::ADDRESS | ValueFlags::ABSOLUTE))
+            | ValueFlags::CAN_BYPASS_GOT;
+        value_flags |=
+            symbol_db.local_symbol_value_flags(symbol_id) & ValueFlags::DOWNGRADE_TO_LOCAL;
+        symbol_db.set_local_symbol_value_flags(symbol_id, value_flags);
     }
     Ok(())
 }
@@ -454,6 +635,41 @@ pub(crate) enum SectionSlot<'data> {
 
     // Loaded section with debug info content.
     LoadedDebugInfo(crate::layout::Section),
+
+    /// The section was loaded, but then found to be identical to another section, so references
+    /// to it now go to that section instead. See the `icf` module.
+    Folded(FoldedSection),
+
+    /// The section is a member of a COMDAT group that we discarded in favour of a copy in another
+    /// object. See the `comdat` module.
+    Duplicate(DuplicateSection<'data>),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct FoldedSection {
+    /// The section as it was before we folded it.
+    pub(crate) section: crate::layout::Section,
+
+    /// The file that contains the section that we folded into.
+    pub(crate) into_file: FileId,
+
+    /// The section that we folded into.
+    pub(crate) into_section: object::SectionIndex,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct DuplicateSection<'data> {
+    pub(crate) part_id: PartId,
+
+    /// The signature of the group that the section was in.
+    pub(crate) signature: &'data [u8],
+
+    /// The file containing the copy of the group that we kept.
+    pub(crate) kept_file: FileId,
+
+    /// The member of the kept group with the same name and size, if there is one. References to
+    /// our section are redirected to it.
+    pub(crate) kept_section: Option<object::SectionIndex>,
 }
 
 #[derive(Clone, Copy)]
@@ -462,6 +678,25 @@ pub(crate) struct UnloadedSection {
 
     /// The index of the last FDE for this section. Previous FDEs will be linked from this.
     pub(crate) last_frame_index: Option<FrameIndex>,
+
+    /// The last of the SHF_LINK_ORDER sections that are linked to this section. Previous ones are
+    /// linked from it.
+    pub(crate) last_dependent: Option<object::SectionIndex>,
+
+    /// Set if this is an SHF_LINK_ORDER section.
+    pub(crate) link_order: Option<LinkOrder>,
+}
+
+/// The link from an SHF_LINK_ORDER section, e.g. `__patchable_function_entries`, to the section
+/// that it has metadata for. Such sections are loaded when the section that they're linked to is
+/// loaded, and are placed in the same order as those sections.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LinkOrder {
+    /// The section that we're linked to.
+    pub(crate) target: object::SectionIndex,
+
+    /// The previous section that's linked to the same section.
+    pub(crate) previous_dependent: Option<object::SectionIndex>,
 }
 
 impl UnloadedSection {
@@ -469,12 +704,14 @@ impl UnloadedSection {
         Self {
             part_id,
             last_frame_index: None,
+            last_dependent: None,
+            link_order: None,
         }
     }
 }
 
 /// An index into the exception frames for an object.
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub(crate) struct FrameIndex(NonZeroU32);
 
 pub(crate) struct ResolvedPrelude<'data> {
@@ -490,6 +727,30 @@ pub(crate) struct ResolvedObject<'data> {
     pub(crate) non_dynamic: Option<NonDynamicResolved<'data>>,
 }
 
+impl ResolvedObject<'_> {
+    /// Returns whether `symbol` is defined by a section that we discarded along with the rest of
+    /// its COMDAT group. Such definitions shouldn't be selected if there's an alternative.
+    fn is_in_duplicate_section(
+        &self,
+        symbol: &crate::elf::Symbol,
+        symbol_index: object::SymbolIndex,
+    ) -> bool {
+        let Some(non_dynamic) = self.non_dynamic.as_ref() else {
+            return false;
+        };
+        self.object
+            .symbol_section(symbol, symbol_index)
+            .ok()
+            .flatten()
+            .is_some_and(|section_index| {
+                matches!(
+                    non_dynamic.sections.get(section_index.0),
+                    Some(SectionSlot::Duplicate(..))
+                )
+            })
+    }
+}
+
 /// Parts of a resolved object that are only applicable to non-dynamic objects.
 pub(crate) struct NonDynamicResolved<'data> {
     pub(crate) sections: Vec<SectionSlot<'data>>,
@@ -510,12 +771,30 @@ pub(crate) struct MergeStringsFileSection<'data> {
     pub(crate) section_data: &'data [u8],
 }
 
-const MERGE_STRING_BUCKETS: usize = 32;
+/// The maximum number of buckets into which we split the strings of an output section. Strings are
+/// merged in parallel, one bucket per task. Must be a power of two.
+const MAX_MERGE_STRING_BUCKETS: usize = 256;
+
+/// The number of bytes of strings that we aim to have in each bucket. Output sections with fewer
+/// bytes than this are merged as a single bucket.
+const MERGE_STRING_BUCKET_TARGET_BYTES: usize = 64 * 1024;
+
+/// Input sections smaller than this keep their strings in a single list rather than splitting them
+/// by hash, since for the many tiny .rodata.str1.1 sections in a typical link, setting up the
+/// buckets costs more than it saves.
+const MIN_BUCKETED_SECTION_BYTES: usize = 4 * 1024;
 
 /// Information about a string-merge section prior to merging.
 pub(crate) struct UnresolvedMergeStringsFileSection<'data> {
     section_index: object::SectionIndex,
-    buckets: [Vec<PreHashed<StringToMerge<'data>>>; MERGE_STRING_BUCKETS],
+
+    /// The total size of the strings in the section, including null terminators.
+    num_bytes: usize,
+
+    /// The strings in the section. Small sections have a single bucket containing all strings in
+    /// order. Larger sections have `MAX_MERGE_STRING_BUCKETS` buckets, with each string in the
+    /// bucket given by its hash.
+    buckets: Vec<Vec<PreHashed<StringToMerge<'data>>>>,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -567,24 +846,37 @@ impl<'data> MergeStringsSectionBucket<'data> {
 
 #[derive(Default)]
 pub(crate) struct MergeStringsSection<'data> {
-    /// The buckets based on the hash value of the input string.
-    pub(crate) buckets: [MergeStringsSectionBucket<'data>; MERGE_STRING_BUCKETS],
+    /// The buckets based on the hash value of the input string. The number of buckets is a power of
+    /// two that depends on the total size of the input strings.
+    pub(crate) buckets: Vec<MergeStringsSectionBucket<'data>>,
 
     /// The byte offset of each bucket in the final section.
-    pub(crate) bucket_offsets: [u64; MERGE_STRING_BUCKETS],
+    pub(crate) bucket_offsets: Vec<u64>,
 }
 
 impl<'data> MergeStringsSection<'data> {
+    fn with_buckets(num_buckets: usize) -> Self {
+        Self {
+            buckets: (0..num_buckets).map(|_| Default::default()).collect(),
+            bucket_offsets: vec![0; num_buckets],
+        }
+    }
+
     pub(crate) fn get(&self, string: &PreHashed<StringToMerge<'data>>) -> Option<u64> {
-        let bucket_index = (string.hash() as usize) % MERGE_STRING_BUCKETS;
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket_index = (string.hash() as usize) % self.buckets.len();
         self.buckets[bucket_index]
             .get(string)
             .map(|offset| self.bucket_offsets[bucket_index] + offset)
     }
 
     pub(crate) fn len(&self) -> u64 {
-        self.bucket_offsets[MERGE_STRING_BUCKETS - 1]
-            + self.buckets[MERGE_STRING_BUCKETS - 1].next_offset
+        self.bucket_offsets
+            .last()
+            .zip(self.buckets.last())
+            .map_or(0, |(offset, bucket)| offset + bucket.next_offset)
     }
 
     pub(crate) fn totally_added(&self) -> usize {
@@ -600,6 +892,16 @@ impl<'data> MergeStringsSection<'data> {
     }
 }
 
+/// Returns how many buckets we should split an output section's strings into, given the total size
+/// of its input strings. The result is a power of two, so that it divides
+/// `MAX_MERGE_STRING_BUCKETS` and input sections that were split into that many buckets can be
+/// mapped onto our buckets.
+fn num_merge_string_buckets(num_bytes: usize) -> usize {
+    (num_bytes / MERGE_STRING_BUCKET_TARGET_BYTES)
+        .next_power_of_two()
+        .min(MAX_MERGE_STRING_BUCKETS)
+}
+
 /// Merges identical strings from all loaded objects where those strings are from input sections
 /// that are marked with both the SHF_MERGE and SHF_STRINGS flags.
 #[tracing::instrument(skip_all, name = "Merge strings")]
@@ -607,8 +909,7 @@ fn merge_strings<'data>(
     resolved: &mut [ResolvedGroup<'data>],
     output_sections: &OutputSections,
 ) -> Result<OutputSectionMap<MergeStringsSection<'data>>> {
-    let mut worklist_per_section: HashMap<OutputSectionId, [Vec<_>; MERGE_STRING_BUCKETS]> =
-        HashMap::new();
+    let mut worklist_per_section: HashMap<OutputSectionId, Vec<_>> = HashMap::new();
 
     for group in resolved {
         for file in &mut group.files {
@@ -625,38 +926,69 @@ fn merge_strings<'data>(
                     bail!("Internal error: expected SectionSlot::MergeStrings");
                 };
 
-                let id = sec.part_id.output_section_id();
-                worklist_per_section.entry(id).or_default();
-                for (i, bucket) in worklist_per_section
-                    .get_mut(&id)
-                    .unwrap()
-                    .iter_mut()
-                    .enumerate()
-                {
-                    bucket.push(&merge_info.buckets[i]);
-                }
+                worklist_per_section
+                    .entry(sec.part_id.output_section_id())
+                    .or_default()
+                    .push(merge_info);
             }
         }
     }
 
     let mut strings_by_section = output_sections.new_section_map::<MergeStringsSection>();
 
-    for (section_id, buckets) in worklist_per_section.iter() {
+    for (section_id, input_sections) in worklist_per_section.iter() {
+        let num_bytes = input_sections.iter().map(|s| s.num_bytes).sum();
+        let num_buckets = num_merge_string_buckets(num_bytes);
         let merged_strings = strings_by_section.get_mut(*section_id);
+        *merged_strings = MergeStringsSection::with_buckets(num_buckets);
 
-        buckets
-            .iter()
-            .zip(merged_strings.buckets.iter_mut())
-            .par_bridge()
-            .for_each(|(string_lists, merged_strings)| {
-                for strings in string_lists {
-                    for string in strings.iter() {
-                        merged_strings.add_string(*string);
+        if num_buckets == 1 {
+            let bucket = &mut merged_strings.buckets[0];
+            for input_section in input_sections {
+                for string in input_section.buckets.iter().flatten() {
+                    bucket.add_string(*string);
+                }
+            }
+            continue;
+        }
+
+        // Strings from small input sections haven't been split by hash, so do that now. Strings
+        // from large input sections are already split into `MAX_MERGE_STRING_BUCKETS` buckets,
+        // each of which maps to exactly one of our buckets.
+        let mut unsplit: Vec<Vec<&PreHashed<StringToMerge>>> = vec![Vec::new(); num_buckets];
+        for input_section in input_sections {
+            if let [strings] = input_section.buckets.as_slice() {
+                for string in strings {
+                    unsplit[(string.hash() as usize) % num_buckets].push(string);
+                }
+            }
+        }
+
+        merged_strings
+            .buckets
+            .par_iter_mut()
+            .zip(unsplit)
+            .enumerate()
+            .for_each(|(bucket_index, (merged_strings, unsplit))| {
+                for string in unsplit {
+                    merged_strings.add_string(*string);
+                }
+                for input_section in input_sections {
+                    if input_section.buckets.len() != MAX_MERGE_STRING_BUCKETS {
+                        continue;
+                    }
+                    for strings in input_section.buckets[bucket_index..]
+                        .iter()
+                        .step_by(num_buckets)
+                    {
+                        for string in strings {
+                            merged_strings.add_string(*string);
+                        }
                     }
                 }
             });
 
-        for i in 1..MERGE_STRING_BUCKETS {
+        for i in 1..num_buckets {
             merged_strings.bucket_offsets[i] =
                 merged_strings.bucket_offsets[i - 1] + merged_strings.buckets[i - 1].len();
         }
@@ -664,10 +996,10 @@ fn merge_strings<'data>(
 
     strings_by_section.for_each(|section_id, sec| {
         if sec.len() > 0 {
-            let input_sections = worklist_per_section.get(&section_id).unwrap()[0].len();
+            let input_sections = worklist_per_section.get(&section_id).unwrap().len();
             tracing::debug!(target: "metrics", section = ?output_sections.name(section_id), size = sec.len(),
                 totally_added = sec.totally_added(), strings = sec.string_count(), totally_added_strings = sec.totally_added_strings(),
-                input_sections, "merge_strings");
+                input_sections, buckets = sec.buckets.len(), "merge_strings");
         }
     });
 
@@ -679,7 +1011,13 @@ fn assign_section_ids<'data>(
     resolved: &mut [ResolvedGroup<'data>],
     args: &Args,
 ) -> Result<OutputSections<'data>> {
-    let mut output_sections_builder = OutputSectionsBuilder::with_base_address(args.base_address());
+    let mut output_sections_builder = OutputSectionsBuilder::with_base_address(args.base_address())
+        .with_relro(args.relro)
+        .with_page_size(args.max_page_size)
+        .with_separate_code(args.separate_code)
+        .with_rosegment(args.rosegment)
+        .with_writable_text(args.magic != Magic::Zmagic)
+        .with_section_starts(&args.section_starts);
     for group in resolved {
         for file in &mut group.files {
             if let ResolvedFile::Object(s) = file {
@@ -700,17 +1038,21 @@ struct Outputs<'data> {
     loaded: ArrayQueue<ResolvedObject<'data>>,
 
     /// Any errors that we encountered.
-    errors: ArrayQueue<Error>,
+    errors: SegQueue<Error>,
 
     undefined_symbols: SegQueue<UndefinedSymbol<'data>>,
+
+    /// The files that were loaded because another file referenced them, with the reference.
+    load_reasons: SegQueue<(FileId, LoadReason)>,
 }
 
 impl<'data> Outputs<'data> {
     fn new(num_objects: usize) -> Self {
         Self {
             loaded: ArrayQueue::new(num_objects),
-            errors: ArrayQueue::new(1),
+            errors: SegQueue::new(),
             undefined_symbols: SegQueue::new(),
+            load_reasons: SegQueue::new(),
         }
     }
 }
@@ -750,13 +1092,14 @@ fn canonicalise_undefined_symbols<'data>(
     output_sections: &OutputSections,
     groups: &[ResolvedGroup],
     symbol_db: &mut SymbolDb<'data>,
-) -> Result<Vec<InternalSymDefInfo>> {
+) -> Result<(Vec<InternalSymDefInfo>, Vec<SymbolId>)> {
     let mut custom_start_stop_defs = Vec::new();
     let mut name_to_id: PassThroughHashMap<SymbolName<'data>, SymbolId> = Default::default();
     let mut undefined_symbols = Vec::from_iter(undefined_symbols);
     // Sort by symbol ID to ensure deterministic behaviour. This means that the canonical symbol ID
     // for any given name will be the one for the earliest file that refers to that symbol.
     undefined_symbols.sort_by_key(|u| u.symbol_id);
+    let mut unresolved = Vec::new();
     for undefined in undefined_symbols {
         let is_defined = undefined.ignore_if_loaded.is_some_and(|file_id| {
             !matches!(
@@ -777,6 +1120,9 @@ fn canonicalise_undefined_symbols<'data>(
                     &mut custom_start_stop_defs,
                     output_sections,
                 );
+                if symbol_id.is_none() && undefined.ignore_if_loaded.is_none() {
+                    unresolved.push(undefined.symbol_id);
+                }
                 // If the symbol isn't a start/stop symbol, then assign responsibility for the
                 // symbol to the first object that referenced it. This lets us have PLT/GOT entries
                 // for the symbol if they're needed.
@@ -789,7 +1135,10 @@ fn canonicalise_undefined_symbols<'data>(
             }
         }
     }
-    Ok(custom_start_stop_defs)
+    if symbol_db.args.output_kind.is_executable() {
+        crate::needed_libs::report_indirect_definitions(symbol_db, &unresolved)?;
+    }
+    Ok((custom_start_stop_defs, unresolved))
 }
 
 fn allocate_start_stop_symbol_id<'data>(
@@ -872,14 +1221,17 @@ fn resolve_sections<'data>(
     allocator: &bumpalo_herd::Member<'data>,
     loaded_metrics: &LoadedMetrics,
 ) -> Result<Vec<SectionSlot<'data>>> {
-    let sections = obj
+    let mut sections = obj
         .object
         .sections
         .enumerate()
         .map(|(input_section_index, input_section)| {
-            if let Some(unloaded) =
-                UnresolvedSection::from_section(&obj.object, input_section, args)?
-            {
+            if let Some(unloaded) = UnresolvedSection::from_section(
+                &obj.object,
+                input_section_index,
+                input_section,
+                args,
+            )? {
                 let section_flags = SectionFlags::from_header(input_section);
                 let mut part_id = part_id::CUSTOM_PLACEHOLDER;
                 let mut custom_section = None;
@@ -930,7 +1282,7 @@ fn resolve_sections<'data>(
                                 } else {
                                     SectionSlot::UnloadedDebugInfo(part_id::CUSTOM_PLACEHOLDER)
                                 }
-                            } else if section_flags.should_retain() {
+                            } else if section_flags.should_retain() || is_note(input_section) {
                                 SectionSlot::MustLoad(UnloadedSection::new(
                                     part_id::CUSTOM_PLACEHOLDER,
                                 ))
@@ -952,9 +1304,43 @@ fn resolve_sections<'data>(
             }
         })
         .collect::<Result<Vec<_>>>()?;
+    link_dependent_sections(&obj.object, &mut sections);
     Ok(sections)
 }
 
+/// Returns whether `section` is a note. Nothing references notes, but they're there for the benefit
+/// of tools like debuggers, so like GNU ld, we keep them.
+fn is_note(section: &object::elf::SectionHeader64<LittleEndian>) -> bool {
+    SectionType::from_header(section) == sht::NOTE
+}
+
+/// Links each SHF_LINK_ORDER section to the section that it's linked to, so that it gets loaded and
+/// discarded together with that section.
+fn link_dependent_sections(object: &File, sections: &mut [SectionSlot]) {
+    let e = LittleEndian;
+    for (index, section) in object.sections.iter().enumerate() {
+        if !SectionFlags::from_header(section).contains(shf::LINK_ORDER) {
+            continue;
+        }
+        let target = section.sh_link(e) as usize;
+        if target == 0 || target == index || sections[index].unloaded_mut().is_none() {
+            continue;
+        }
+        let Some(target_section) = sections.get_mut(target).and_then(|s| s.unloaded_mut()) else {
+            continue;
+        };
+        let previous_dependent = target_section
+            .last_dependent
+            .replace(object::SectionIndex(index));
+        if let Some(unloaded) = sections[index].unloaded_mut() {
+            unloaded.link_order = Some(LinkOrder {
+                target: object::SectionIndex(target),
+                previous_dependent,
+            });
+        }
+    }
+}
+
 fn resolve_symbols<'data>(
     obj: &ParsedInputObject<'data>,
     resources: &ResolutionResources<'data, '_, '_>,
@@ -973,6 +1359,7 @@ fn resolve_symbols<'data>(
                     definition,
                     resources,
                     obj,
+                    &[],
                     undefined_symbols_out,
                 )
             },
@@ -986,6 +1373,7 @@ fn resolve_dynamic_symbols<'data>(
     undefined_symbols_out: &SegQueue<UndefinedSymbol<'data>>,
     definitions_out: &mut [SymbolId],
 ) -> Result {
+    let needed_libs = crate::elf::DynamicTagValues::read(&obj.object)?.needed;
     obj.object
         .symbols
         .enumerate()
@@ -998,6 +1386,7 @@ fn resolve_dynamic_symbols<'data>(
                     definition,
                     resources,
                     obj,
+                    &needed_libs,
                     undefined_symbols_out,
                 )
             },
@@ -1005,12 +1394,25 @@ fn resolve_dynamic_symbols<'data>(
     Ok(())
 }
 
+/// Returns whether the file that defines a symbol is an --as-needed shared object that's already
+/// listed in `needed_libs`. References from a shared object to its own dependencies don't make
+/// those dependencies needed by our output, since the runtime loader will load them anyway.
+fn is_already_needed(
+    file_id: FileId,
+    needed_libs: &[&[u8]],
+    resources: &ResolutionResources,
+) -> bool {
+    resources.names_for_needed[file_id.group()][file_id.file()]
+        .is_some_and(|name| needed_libs.contains(&name))
+}
+
 fn resolve_symbol<'data>(
     local_symbol_index: object::SymbolIndex,
     local_symbol: &crate::elf::SymtabEntry,
     definition_out: &mut SymbolId,
     resources: &ResolutionResources<'data, '_, '_>,
     obj: &ParsedInputObject<'data>,
+    needed_libs: &[&[u8]],
     undefined_symbols_out: &SegQueue<UndefinedSymbol<'data>>,
 ) -> Result {
     // Don't try to resolve symbols that are already defined, e.g. locals and globals that we
@@ -1025,15 +1427,31 @@ fn resolve_symbol<'data>(
         String::from_utf8_lossy(name_bytes)
     );
     assert!(!local_symbol.is_definition(LittleEndian));
+    // Like GNU ld, we only apply --wrap to references from regular objects.
+    let name_bytes = if obj.is_dynamic() {
+        name_bytes
+    } else {
+        resources.symbol_db.args.wrapped_name(name_bytes)
+    };
     let prehashed_name = SymbolName::prehashed(name_bytes);
     match resources.symbol_db.global_names.get(&prehashed_name) {
         Some(&symbol_id) => {
             *definition_out = symbol_id;
             let symbol_file_id = resources.symbol_db.file_id_for_symbol(symbol_id);
-            if symbol_file_id != obj.file_id && !local_symbol.is_weak() {
-                resources.request_file_id(symbol_file_id);
+            if symbol_file_id != obj.file_id
+                && !local_symbol.is_weak()
+                && !is_already_needed(symbol_file_id, needed_libs, resources)
+            {
+                resources.request_file_id(
+                    symbol_file_id,
+                    LoadReason {
+                        requester: obj.file_id,
+                        symbol_id: obj.symbol_id_range.input_to_id(local_symbol_index),
+                    },
+                );
             } else if symbol_file_id != PRELUDE_FILE_ID {
-                // The symbol is weak and we can't be sure that the file that defined it will end up
+                // The symbol is weak (or only referenced by a shared object that depends on the
+                // defining file) and we can't be sure that the file that defined it will end up
                 // being loaded, so the symbol might actually be undefined. Register it as an
                 // undefined symbol then later when we handle undefined symbols, we'll check if the
                 // file got loaded. TODO: If the file is a non-archived object, or possibly even if
@@ -1076,7 +1494,10 @@ impl<'data> std::fmt::Display for ResolvedFile<'data> {
 
 impl<'data> SectionSlot<'data> {
     pub(crate) fn is_loaded(&self) -> bool {
-        !matches!(self, SectionSlot::Discard | SectionSlot::Unloaded(..))
+        !matches!(
+            self,
+            SectionSlot::Discard | SectionSlot::Unloaded(..) | SectionSlot::Duplicate(..)
+        )
     }
 
     pub(crate) fn set_part_id(&mut self, part_id: PartId) {
@@ -1089,6 +1510,20 @@ impl<'data> SectionSlot<'data> {
             SectionSlot::MergeStrings(section) => section.part_id = part_id,
             SectionSlot::UnloadedDebugInfo(out) => *out = part_id,
             SectionSlot::LoadedDebugInfo(section) => section.part_id = part_id,
+            SectionSlot::Folded(folded) => folded.section.part_id = part_id,
+            SectionSlot::Duplicate(duplicate) => duplicate.part_id = part_id,
+        }
+    }
+
+    /// Returns the section's link to the section that it has metadata for, if it's an
+    /// SHF_LINK_ORDER section that we might load.
+    pub(crate) fn link_order(&self) -> Option<LinkOrder> {
+        match self {
+            SectionSlot::Unloaded(unloaded) | SectionSlot::MustLoad(unloaded) => {
+                unloaded.link_order
+            }
+            SectionSlot::Loaded(section) => section.link_order,
+            _ => None,
         }
     }
 
@@ -1106,13 +1541,23 @@ impl<'data> UnresolvedMergeStringsFileSection<'data> {
         section_index: object::SectionIndex,
     ) -> Result<UnresolvedMergeStringsFileSection<'data>> {
         let mut remaining = section_data;
-        let mut buckets: [Vec<PreHashed<StringToMerge>>; MERGE_STRING_BUCKETS] = Default::default();
-        while !remaining.is_empty() {
-            let string = StringToMerge::take_hashed(&mut remaining)?;
-            buckets[(string.hash() as usize) % MERGE_STRING_BUCKETS].push(string);
-        }
+        let buckets = if section_data.len() < MIN_BUCKETED_SECTION_BYTES {
+            let mut strings = Vec::new();
+            while !remaining.is_empty() {
+                strings.push(StringToMerge::take_hashed(&mut remaining)?);
+            }
+            vec![strings]
+        } else {
+            let mut buckets = vec![Vec::new(); MAX_MERGE_STRING_BUCKETS];
+            while !remaining.is_empty() {
+                let string = StringToMerge::take_hashed(&mut remaining)?;
+                buckets[(string.hash() as usize) % MAX_MERGE_STRING_BUCKETS].push(string);
+            }
+            buckets
+        };
         Ok(UnresolvedMergeStringsFileSection {
             section_index,
+            num_bytes: section_data.len(),
             buckets,
         })
     }
@@ -1175,6 +1620,15 @@ bitflags! {
 }
 
 impl ValueFlags {
+    /// Returns whether the value is an address in our output that could be interposed (overridden
+    /// at runtime) by a definition in another module. This is the case for default-visibility
+    /// symbols, including ifuncs, that we export from a shared object. Absolute references to such
+    /// values need a dynamic relocation against the symbol rather than a relative relocation.
+    pub(crate) fn is_interposable(self) -> bool {
+        self.intersects(ValueFlags::ADDRESS | ValueFlags::IFUNC)
+            && !self.contains(ValueFlags::CAN_BYPASS_GOT)
+    }
+
     /// Returns self merged with `other` which should be the flags for the local (possibly
     /// non-canonical symbol definition). Sometimes an object will reference a symbol that it
     /// doesn't define and will mark that symbol as hidden however the object that defines the
@@ -1203,7 +1657,9 @@ impl<'data> SymbolDb<'data> {
                 return SymbolStrength::Undefined;
             };
             let e = LittleEndian;
-            if obj_symbol.is_weak() {
+            if obj.is_in_duplicate_section(obj_symbol, local_index) {
+                SymbolStrength::Undefined
+            } else if obj_symbol.is_weak() {
                 SymbolStrength::Weak
             } else if obj_symbol.is_common(e) {
                 SymbolStrength::Common(obj_symbol.st_size(e))
@@ -1235,3 +1691,21 @@ fn section_slot_is_copy() {
 
     assert_copy(SectionSlot::Discard);
 }
+
+#[test]
+fn test_num_merge_string_buckets() {
+    assert_eq!(num_merge_string_buckets(0), 1);
+    assert_eq!(
+        num_merge_string_buckets(MERGE_STRING_BUCKET_TARGET_BYTES - 1),
+        1
+    );
+    assert_eq!(
+        num_merge_string_buckets(MERGE_STRING_BUCKET_TARGET_BYTES * 3),
+        4
+    );
+    assert_eq!(
+        num_merge_string_buckets(usize::MAX),
+        MAX_MERGE_STRING_BUCKETS
+    );
+    assert!(MAX_MERGE_STRING_BUCKETS.is_power_of_two());
+}
@@ -0,0 +1,123 @@
+//! Warnings about input sections that need permissions that the segment they end up in doesn't
+//! grant. We never emit segments that are both writable and executable, so an input section that's
+//! both writable and executable, or a writable section with a name that puts it in read-only data,
+//! will be placed somewhere that doesn't match its flags. That usually means that the program will
+//! crash when it writes to or executes the section, so we list the offending input sections to help
+//! track down where they came from.
+
+use crate::args::Args;
+use crate::diagnostics::should_warn;
+use crate::diagnostics::WarningCategory;
+use crate::layout::FileLayout;
+use crate::layout::GroupLayout;
+use crate::output_section_id::OutputSections;
+use crate::resolution::SectionSlot;
+use crate::warning;
+use linker_utils::elf::shf;
+use linker_utils::elf::SectionFlags;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// The number of input sections that we list for each output section.
+const MAX_LISTED: usize = 5;
+
+/// Issues a warning for each output section that contains input sections that need to be writable
+/// or executable, but which are in a segment that isn't.
+pub(crate) fn check(group_layouts: &[GroupLayout], output_sections: &OutputSections, args: &Args) {
+    if !args.warn_rwx_segments {
+        return;
+    }
+    // Conflicting input sections, keyed by the name of the output section that they're in.
+    let mut conflicts: BTreeMap<String, (u32, Vec<String>)> = BTreeMap::new();
+    for file in group_layouts.iter().flat_map(|group| &group.files) {
+        let FileLayout::Object(obj) = file else {
+            continue;
+        };
+        if !should_warn(
+            args,
+            WarningCategory::SectionFlags,
+            &obj.input.file.filename,
+        ) {
+            continue;
+        }
+        for slot in &obj.sections {
+            let SectionSlot::Loaded(section) = slot else {
+                continue;
+            };
+            let Ok(header) = obj.object.section(section.index) else {
+                continue;
+            };
+            let flags = SectionFlags::from_header(header);
+            let section_id = section.part_id.output_section_id();
+            let Some(segment_id) = output_sections.loadable_segment_id_for(section_id) else {
+                continue;
+            };
+            let segment_flags = segment_id.segment_flags();
+            if !flags.contains(shf::ALLOC) || !missing_permissions(flags, segment_flags) {
+                continue;
+            }
+            let entry = conflicts
+                .entry(output_sections.display_name(section_id).into_owned())
+                .or_insert_with(|| (segment_flags, Vec::new()));
+            entry.1.push(format!(
+                "{}:({}) has flags {flags}",
+                args.diagnostics
+                    .styled_file(&crate::map_file::display_input(&obj.input)),
+                obj.object.section_display_name(section.index),
+            ));
+        }
+    }
+    for (output_name, (segment_flags, inputs)) in conflicts {
+        let mut message = format!(
+            "input sections in `{output_name}` need permissions that its segment ({}) doesn't \
+             have:",
+            segment_permissions(segment_flags)
+        );
+        for input in inputs.iter().take(MAX_LISTED) {
+            let _ = write!(message, "\n>>> {input}");
+        }
+        if inputs.len() > MAX_LISTED {
+            let _ = write!(message, "\n>>> and {} more", inputs.len() - MAX_LISTED);
+        }
+        warning!(args.diagnostics, "{message}");
+    }
+}
+
+/// Returns whether a section with `flags` needs to be writable or executable, but the segment with
+/// `segment_flags` isn't.
+fn missing_permissions(flags: SectionFlags, segment_flags: u32) -> bool {
+    (flags.contains(shf::WRITE) && segment_flags & object::elf::PF_W == 0)
+        || (flags.contains(shf::EXECINSTR) && segment_flags & object::elf::PF_X == 0)
+}
+
+/// Formats segment flags as the permissions that they grant, e.g. `RX`.
+fn segment_permissions(segment_flags: u32) -> String {
+    [
+        (object::elf::PF_R, 'R'),
+        (object::elf::PF_W, 'W'),
+        (object::elf::PF_X, 'X'),
+    ]
+    .iter()
+    .filter(|&&(flag, _)| segment_flags & flag != 0)
+    .map(|&(_, c)| c)
+    .collect()
+}
+
+#[test]
+fn test_missing_permissions() {
+    use object::elf::PF_R;
+    use object::elf::PF_W;
+    use object::elf::PF_X;
+
+    let wa = shf::WRITE.with(shf::ALLOC);
+    let ax = shf::ALLOC.with(shf::EXECINSTR);
+    let wax = wa.with(shf::EXECINSTR);
+    assert!(!missing_permissions(wa, PF_R | PF_W));
+    assert!(missing_permissions(wa, PF_R));
+    assert!(!missing_permissions(ax, PF_R | PF_X));
+    assert!(missing_permissions(ax, PF_R | PF_W));
+    assert!(missing_permissions(wax, PF_R | PF_X));
+    assert!(missing_permissions(wax, PF_R | PF_W));
+    assert!(!missing_permissions(shf::ALLOC, PF_R));
+    assert_eq!(segment_permissions(PF_R | PF_X), "RX");
+}
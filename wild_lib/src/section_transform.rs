@@ -0,0 +1,307 @@
+//! An API for programs that embed the linker to observe or rewrite the contents of input sections
+//! before layout, e.g. to strip or instrument particular sections.
+//!
+//! Edits must preserve the size of the section, which means that nothing about layout changes and
+//! the section's relocations still apply at the same offsets. Relocations are applied on top of
+//! the new contents when we write the output, so bytes covered by a relocation get overwritten as
+//! usual. Sections can also be discarded entirely, which has the same effect as if they had the
+//! `SHF_EXCLUDE` flag.
+
+use crate::archive_splitter::InputBytes;
+use crate::error::Result;
+use crate::file_kind::FileKind;
+use crate::threading::prelude::*;
+use anyhow::bail;
+use anyhow::Context as _;
+use object::read::elf::FileHeader as _;
+use object::read::elf::SectionHeader as _;
+use object::LittleEndian;
+use std::borrow::Cow;
+
+/// A section of one of our input objects that's offered to each `SectionTransform`.
+#[non_exhaustive]
+pub struct InputSection<'a> {
+    /// The object that contains the section, e.g. `foo.o` or `libfoo.a @ foo.o`.
+    pub file: &'a str,
+
+    pub name: &'a [u8],
+
+    /// The section type, e.g. `SHT_PROGBITS`.
+    pub section_type: u32,
+
+    /// The section flags, e.g. `SHF_ALLOC`.
+    pub flags: u64,
+
+    /// The contents of the section, including any edits made by earlier transforms.
+    pub data: &'a [u8],
+}
+
+/// What a `SectionTransform` wants done with a section.
+pub enum SectionEdit {
+    /// Leave the section as it is.
+    Keep,
+
+    /// Replace the contents of the section. The new contents must be the same size as the old.
+    Replace(Vec<u8>),
+
+    /// Leave the section out of the output, along with anything that only it references.
+    Discard,
+}
+
+/// A callback that gets to look at each input section before layout. Transforms are called from
+/// multiple threads at once. When there are several, they're called in the order in which they
+/// were added, each seeing the edits of those before it.
+///
+/// Sections that describe the object rather than containing data, i.e. symbol tables, string
+/// tables, relocations and groups, aren't offered. Nor are compressed sections or those without
+/// contents, such as `.bss`.
+pub trait SectionTransform: Send + Sync {
+    fn transform(&self, section: &InputSection) -> Result<SectionEdit>;
+}
+
+impl<F> SectionTransform for F
+where
+    F: Fn(&InputSection) -> Result<SectionEdit> + Send + Sync,
+{
+    fn transform(&self, section: &InputSection) -> Result<SectionEdit> {
+        self(section)
+    }
+}
+
+/// Offers the sections of each object in `inputs` to `transforms`. Objects that get edited are
+/// copied into `herd` and the copies used in place of the originals.
+#[tracing::instrument(skip_all, name = "Transform sections")]
+pub(crate) fn apply<'data>(
+    transforms: &[Box<dyn SectionTransform>],
+    inputs: &mut [InputBytes<'data>],
+    herd: &'data bumpalo_herd::Herd,
+) -> Result {
+    inputs
+        .par_iter_mut()
+        .filter(|input| input.kind == FileKind::ElfObject)
+        .try_for_each(|input| {
+            let file = input.to_string();
+            if let Some(edited) = transform_object(transforms, &file, input.data, herd)
+                .with_context(|| format!("Failed to transform sections of `{file}`"))?
+            {
+                input.data = edited;
+            }
+            Ok(())
+        })
+}
+
+/// Returns a copy of the object `data` with the edits requested by `transforms`, or None if there
+/// weren't any.
+fn transform_object<'data>(
+    transforms: &[Box<dyn SectionTransform>],
+    file: &str,
+    data: &[u8],
+    herd: &'data bumpalo_herd::Herd,
+) -> Result<Option<&'data [u8]>> {
+    let e = LittleEndian;
+    let header = object::elf::FileHeader64::<LittleEndian>::parse(data)?;
+    let sections = header.sections(e, data)?;
+    let mut edited: Option<&mut [u8]> = None;
+
+    for (index, section) in sections.enumerate() {
+        let section_type = section.sh_type(e);
+        let flags = section.sh_flags(e);
+        let offered = !matches!(
+            section_type,
+            object::elf::SHT_NULL
+                | object::elf::SHT_NOBITS
+                | object::elf::SHT_SYMTAB
+                | object::elf::SHT_DYNSYM
+                | object::elf::SHT_STRTAB
+                | object::elf::SHT_REL
+                | object::elf::SHT_RELA
+                | object::elf::SHT_GROUP
+                | object::elf::SHT_SYMTAB_SHNDX
+        ) && flags & u64::from(object::elf::SHF_COMPRESSED) == 0;
+        if !offered {
+            continue;
+        }
+        let name = sections.section_name(e, section)?;
+        let Some((offset, size)) = section.file_range(e) else {
+            continue;
+        };
+        let range = offset as usize..(offset + size) as usize;
+        let mut contents = Cow::Borrowed(
+            data.get(range.clone())
+                .context("Section extends past the end of the file")?,
+        );
+        let mut discard = false;
+        for transform in transforms {
+            let edit = transform.transform(&InputSection {
+                file,
+                name,
+                section_type,
+                flags,
+                data: &contents,
+            })?;
+            match edit {
+                SectionEdit::Keep => {}
+                SectionEdit::Replace(new_contents) => {
+                    if new_contents.len() != contents.len() {
+                        bail!(
+                            "Transform changed the size of section `{}` from {} to {} bytes, \
+                             but only edits that preserve the size are supported",
+                            String::from_utf8_lossy(name),
+                            contents.len(),
+                            new_contents.len()
+                        );
+                    }
+                    contents = Cow::Owned(new_contents);
+                }
+                SectionEdit::Discard => {
+                    discard = true;
+                    break;
+                }
+            }
+        }
+        if !discard && matches!(contents, Cow::Borrowed(_)) {
+            continue;
+        }
+
+        let copy = edited.get_or_insert_with(|| herd.get().alloc_slice_copy(data));
+        if discard {
+            let header_offset =
+                header.e_shoff(e) as usize + index.0 * header.e_shentsize(e) as usize;
+            let header: &mut object::elf::SectionHeader64<LittleEndian> =
+                object::from_bytes_mut(&mut copy[header_offset..])
+                    .map_err(|()| anyhow::anyhow!("Misaligned section header"))?
+                    .0;
+            header
+                .sh_flags
+                .set(e, flags | u64::from(object::elf::SHF_EXCLUDE));
+        } else {
+            copy[range].copy_from_slice(&contents);
+        }
+    }
+    Ok(edited.map(|copy| &*copy))
+}
+
+#[test]
+fn test_transform_object() {
+    let object = crate::linker_plugin::stand_in_object(&[]);
+    let herd = bumpalo_herd::Herd::new();
+    let transform = |transform: Box<dyn SectionTransform>| {
+        transform_object(&[transform], "a.o", &object, &herd)
+    };
+
+    let keep = transform(Box::new(|_: &InputSection| Ok(SectionEdit::Keep))).unwrap();
+    assert!(keep.is_none());
+
+    let grow = |section: &InputSection| Ok(SectionEdit::Replace(vec![0; section.data.len() + 1]));
+    assert!(transform(Box::new(grow)).is_err());
+
+    let discard_text = |section: &InputSection| {
+        Ok(if section.name == b".text" {
+            SectionEdit::Discard
+        } else {
+            SectionEdit::Keep
+        })
+    };
+    let edited = transform(Box::new(discard_text)).unwrap().unwrap();
+    let header = object::elf::FileHeader64::<LittleEndian>::parse(edited).unwrap();
+    let sections = header.sections(LittleEndian, edited).unwrap();
+    let (_, text) = sections.section_by_name(LittleEndian, b".text").unwrap();
+    assert_ne!(
+        text.sh_flags(LittleEndian) & u64::from(object::elf::SHF_EXCLUDE),
+        0
+    );
+}
+
+#[test]
+fn test_transform_during_link() {
+    use object::write;
+    use object::Object as _;
+    use object::ObjectSection as _;
+
+    let mut obj = write::Object::new(
+        object::BinaryFormat::Elf,
+        object::Architecture::X86_64,
+        object::Endianness::Little,
+    );
+    let data = obj.add_section(Vec::new(), b".data".to_vec(), object::SectionKind::Data);
+    obj.append_section_data(data, b"original", 1);
+    let data_symbol = obj.section_symbol(data);
+    let text = obj.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+    // lea .data(%rip), %rsi; mov $60, %eax; xor %edi, %edi; syscall
+    obj.append_section_data(
+        text,
+        &[
+            0x48, 0x8d, 0x35, 0, 0, 0, 0, 0xb8, 0x3c, 0, 0, 0, 0x31, 0xff, 0x0f, 0x05,
+        ],
+        1,
+    );
+    obj.add_relocation(
+        text,
+        write::Relocation {
+            offset: 3,
+            symbol: data_symbol,
+            addend: -4,
+            flags: object::RelocationFlags::Elf {
+                r_type: object::elf::R_X86_64_PC32,
+            },
+        },
+    )
+    .unwrap();
+    obj.add_symbol(write::Symbol {
+        name: b"_start".to_vec(),
+        value: 0,
+        size: 16,
+        kind: object::SymbolKind::Text,
+        scope: object::SymbolScope::Linkage,
+        weak: false,
+        section: write::SymbolSection::Section(text),
+        flags: object::SymbolFlags::None,
+    });
+    // Nothing references this section, so it's only kept because of SHF_GNU_RETAIN.
+    let extra = obj.add_section(Vec::new(), b"extra".to_vec(), object::SectionKind::Data);
+    obj.section_mut(extra).flags = object::SectionFlags::Elf {
+        sh_flags: u64::from(
+            object::elf::SHF_ALLOC | object::elf::SHF_WRITE | object::elf::SHF_GNU_RETAIN,
+        ),
+    };
+    obj.append_section_data(extra, b"extra", 1);
+
+    let dir = std::env::temp_dir().join(format!(
+        "wild-section-transform-test-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("a.o");
+    let output = dir.join("a.out");
+    std::fs::write(&input, obj.write().unwrap()).unwrap();
+
+    let mut linker = crate::Linker::from_args(
+        [
+            "-static",
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ]
+        .into_iter(),
+    )
+    .unwrap();
+    linker.add_section_transform(|section: &InputSection| {
+        Ok(match section.name {
+            b".data" => SectionEdit::Replace(b"replaced".to_vec()),
+            b"extra" => SectionEdit::Discard,
+            _ => SectionEdit::Keep,
+        })
+    });
+
+    // Embedders may link more than once in the same process.
+    for _ in 0..2 {
+        std::fs::remove_file(&output).ok();
+        linker.run().unwrap();
+        let bytes = std::fs::read(&output).unwrap();
+        let linked = object::read::elf::ElfFile64::<LittleEndian>::parse(bytes.as_slice()).unwrap();
+        let data = linked.section_by_name(".data").unwrap();
+        assert_eq!(data.data().unwrap(), b"replaced");
+        assert!(linked.section_by_name("extra").is_none());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,440 @@
+//! Support for `--separate-debug-file`. Once the output has been written in full, we split it in
+//! two, giving the same result as `objcopy --only-keep-debug` followed by `objcopy --strip-debug
+//! --add-gnu-debuglink`. The debug file keeps all the section headers, but only the contents of
+//! notes and non-alloc sections. The main output loses its debug sections and gains a
+//! `.gnu.debuglink` section that names the debug file. Both files keep `.note.gnu.build-id`, so
+//! debuggers can match them up by either mechanism.
+
+use crate::elf::FileHeader;
+use crate::elf::SectionHeader;
+use crate::elf::SymtabEntry;
+use crate::error::Result;
+use anyhow::bail;
+use anyhow::Context as _;
+use object::read::elf::FileHeader as _;
+use object::read::elf::SectionHeader as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::borrow::Cow;
+use std::path::Path;
+
+const DEBUGLINK_SECTION_NAME: &[u8] = b".gnu.debuglink";
+
+pub(crate) struct SplitOutput {
+    pub(crate) main: Vec<u8>,
+    pub(crate) debug: Vec<u8>,
+}
+
+/// A section in a file that we're assembling.
+struct NewSection<'data> {
+    header: SectionHeader,
+
+    /// Contents to be placed after the part of the original file that we keep as-is. None if the
+    /// section occupies no space in the file, or if it's within the part that we keep.
+    data: Option<Cow<'data, [u8]>>,
+}
+
+#[tracing::instrument(skip_all, name = "Split debug info")]
+pub(crate) fn split(data: &[u8], debug_path: &Path) -> Result<SplitOutput> {
+    let debug = debug_file(data)?;
+    let main = stripped_file(data, debug_path, &debug)?;
+    Ok(SplitOutput { main, debug })
+}
+
+fn is_debug_section(header: &SectionHeader, name: &[u8]) -> bool {
+    header.sh_flags(LittleEndian) & u64::from(object::elf::SHF_ALLOC) == 0
+        && name.starts_with(b".debug_")
+}
+
+/// Builds the debug file. Allocated sections other than notes are changed to SHT_NOBITS, so that
+/// section indices and addresses stay the same as in the main output.
+fn debug_file(data: &[u8]) -> Result<Vec<u8>> {
+    let e = LittleEndian;
+    let header = FileHeader::parse(data)?;
+    let mut sections = Vec::new();
+    for section in header.section_headers(e, data)? {
+        let mut new_header = *section;
+        let section_type = section.sh_type(e);
+        let is_alloc = section.sh_flags(e) & u64::from(object::elf::SHF_ALLOC) != 0;
+        let keep_data = section_type != object::elf::SHT_NULL
+            && section_type != object::elf::SHT_NOBITS
+            && (!is_alloc || section_type == object::elf::SHT_NOTE);
+        if is_alloc && !keep_data {
+            new_header.sh_type.set(e, object::elf::SHT_NOBITS);
+        }
+        sections.push(NewSection {
+            header: new_header,
+            data: keep_data
+                .then(|| section.data(e, data).map(Cow::Borrowed))
+                .transpose()
+                .map_err(|_| anyhow::anyhow!("Section data out of range"))?,
+        });
+    }
+    let shstrndx = header.shstrndx(e, data)?;
+    assemble(data, program_headers_end(header, data)?, sections, shstrndx)
+}
+
+/// Builds the main output, which is the original output minus its debug sections, plus a
+/// `.gnu.debuglink` section that refers to `debug`.
+fn stripped_file(data: &[u8], debug_path: &Path, debug: &[u8]) -> Result<Vec<u8>> {
+    let e = LittleEndian;
+    let header = FileHeader::parse(data)?;
+    let section_table = header.sections(e, data)?;
+    let section_headers = section_table.iter().as_slice();
+    let shstrndx = header.shstrndx(e, data)? as usize;
+
+    // Everything up to the end of the last allocated section stays where it is, since it's mapped
+    // at runtime.
+    let mut prefix_len = program_headers_end(header, data)?;
+    for section in section_headers {
+        if section.sh_flags(e) & u64::from(object::elf::SHF_ALLOC) != 0
+            && section.sh_type(e) != object::elf::SHT_NOBITS
+        {
+            prefix_len = prefix_len.max((section.sh_offset(e) + section.sh_size(e)) as usize);
+        }
+    }
+
+    let mut index_map = Vec::with_capacity(section_headers.len());
+    let mut next_index = 0_u32;
+    for section in section_headers {
+        if is_debug_section(section, section_table.section_name(e, section)?) {
+            index_map.push(None);
+        } else {
+            index_map.push(Some(next_index));
+            next_index += 1;
+        }
+    }
+    let map_index = |index: u32| -> u32 {
+        index_map
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(index)
+    };
+
+    let mut sections = Vec::new();
+    let mut found_shstrtab = false;
+    let mut debuglink_name_offset = 0;
+    for (index, section) in section_headers.iter().enumerate() {
+        if index_map[index].is_none() {
+            continue;
+        }
+        let mut new_header = *section;
+        new_header.sh_link.set(e, map_index(section.sh_link(e)));
+        if section.sh_flags(e) & u64::from(object::elf::SHF_INFO_LINK) != 0 {
+            new_header.sh_info.set(e, map_index(section.sh_info(e)));
+        }
+        let is_alloc = section.sh_flags(e) & u64::from(object::elf::SHF_ALLOC) != 0;
+        let section_data = if index == 0
+            || section.sh_type(e) == object::elf::SHT_NOBITS
+            || (is_alloc
+                && section.sh_offset(e) as usize + section.sh_size(e) as usize <= prefix_len)
+        {
+            None
+        } else {
+            let bytes = section
+                .data(e, data)
+                .map_err(|_| anyhow::anyhow!("Section data out of range"))?;
+            Some(if index == shstrndx {
+                found_shstrtab = true;
+                debuglink_name_offset = bytes.len() as u32;
+                let mut names = bytes.to_vec();
+                names.extend_from_slice(DEBUGLINK_SECTION_NAME);
+                names.push(0);
+                new_header.sh_size.set(e, names.len() as u64);
+                Cow::Owned(names)
+            } else if section.sh_type(e) == object::elf::SHT_SYMTAB {
+                Cow::Owned(remap_symbols(bytes, &map_index)?)
+            } else {
+                Cow::Borrowed(bytes)
+            })
+        };
+        sections.push(NewSection {
+            header: new_header,
+            data: section_data,
+        });
+    }
+    if !found_shstrtab {
+        bail!("Output has no section-name string table");
+    }
+
+    let debuglink = debuglink_contents(debug_path, debug)?;
+    let mut debuglink_header = SectionHeader {
+        sh_name: Default::default(),
+        sh_type: Default::default(),
+        sh_flags: Default::default(),
+        sh_addr: Default::default(),
+        sh_offset: Default::default(),
+        sh_size: Default::default(),
+        sh_link: Default::default(),
+        sh_info: Default::default(),
+        sh_addralign: Default::default(),
+        sh_entsize: Default::default(),
+    };
+    debuglink_header.sh_name.set(e, debuglink_name_offset);
+    debuglink_header.sh_type.set(e, object::elf::SHT_PROGBITS);
+    debuglink_header.sh_size.set(e, debuglink.len() as u64);
+    debuglink_header.sh_addralign.set(e, 4);
+    sections.push(NewSection {
+        header: debuglink_header,
+        data: Some(Cow::Owned(debuglink)),
+    });
+
+    assemble(data, prefix_len, sections, map_index(shstrndx as u32))
+}
+
+/// Returns a copy of the symbol table `bytes` with section indices updated.
+fn remap_symbols(bytes: &[u8], map_index: &impl Fn(u32) -> u32) -> Result<Vec<u8>> {
+    let e = LittleEndian;
+    let symbols: &[SymtabEntry] = object::slice_from_all_bytes(bytes)
+        .map_err(|()| anyhow::anyhow!("Invalid symbol table size"))?;
+    let mut out = Vec::with_capacity(bytes.len());
+    for symbol in symbols {
+        let mut symbol = *symbol;
+        let index = symbol.st_shndx(e);
+        if index != object::elf::SHN_UNDEF && index < object::elf::SHN_LORESERVE {
+            symbol.st_shndx.set(e, map_index(u32::from(index)) as u16);
+        }
+        out.extend_from_slice(object::bytes_of(&symbol));
+    }
+    Ok(out)
+}
+
+/// Returns the contents of `.gnu.debuglink`. This is the file name of the debug file, padded to a
+/// multiple of 4 bytes, followed by the CRC-32 of the debug file.
+fn debuglink_contents(debug_path: &Path, debug: &[u8]) -> Result<Vec<u8>> {
+    let name = debug_path
+        .file_name()
+        .with_context(|| format!("Invalid debug file name `{}`", debug_path.display()))?;
+    let mut contents = name.as_encoded_bytes().to_vec();
+    contents.push(0);
+    contents.resize(contents.len().next_multiple_of(4), 0);
+    let mut crc = flate2::Crc::new();
+    crc.update(debug);
+    contents.extend_from_slice(&crc.sum().to_le_bytes());
+    Ok(contents)
+}
+
+fn program_headers_end(header: &FileHeader, data: &[u8]) -> Result<usize> {
+    let e = LittleEndian;
+    let program_headers = header.program_headers(e, data)?;
+    Ok((header.e_phoff(e) as usize + size_of_val(program_headers)).max(size_of::<FileHeader>()))
+}
+
+/// Builds a file that starts with the first `prefix_len` bytes of `data`, followed by the contents
+/// of `sections` and then the section headers.
+fn assemble(
+    data: &[u8],
+    prefix_len: usize,
+    mut sections: Vec<NewSection>,
+    shstrndx: u32,
+) -> Result<Vec<u8>> {
+    let e = LittleEndian;
+    let Ok(num_sections) = u16::try_from(sections.len()) else {
+        bail!("Too many sections when splitting debug info");
+    };
+    let mut out = data[..prefix_len].to_vec();
+    for section in &mut sections {
+        if let Some(contents) = section.data.as_ref() {
+            let alignment = section.header.sh_addralign(e).max(1) as usize;
+            out.resize(out.len().next_multiple_of(alignment), 0);
+            section.header.sh_offset.set(e, out.len() as u64);
+            out.extend_from_slice(contents);
+        }
+    }
+    out.resize(out.len().next_multiple_of(8), 0);
+    let section_headers_offset = out.len();
+    for section in &sections {
+        out.extend_from_slice(object::bytes_of(&section.header));
+    }
+
+    let mut file_header = *FileHeader::parse(data)?;
+    file_header.e_shoff.set(e, section_headers_offset as u64);
+    file_header.e_shnum.set(e, num_sections);
+    file_header.e_shstrndx.set(e, shstrndx as u16);
+    out[..size_of::<FileHeader>()].copy_from_slice(object::bytes_of(&file_header));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::read::elf::SectionTable;
+
+    const TEXT: &[u8] = &[0x90, 0x90, 0x90, 0xc3];
+    const DEBUG_INFO: &[u8] = b"debuginf";
+
+    fn zeroed<T: object::Pod>() -> T {
+        *object::from_bytes::<T>(&vec![0; size_of::<T>()]).unwrap().0
+    }
+
+    fn section_header(
+        name: u32,
+        ty: u32,
+        flags: u32,
+        offset: usize,
+        size: usize,
+        link: u32,
+    ) -> SectionHeader {
+        let e = LittleEndian;
+        let mut header: SectionHeader = zeroed();
+        header.sh_name.set(e, name);
+        header.sh_type.set(e, ty);
+        header.sh_flags.set(e, u64::from(flags));
+        header.sh_offset.set(e, offset as u64);
+        header.sh_size.set(e, size as u64);
+        header.sh_link.set(e, link);
+        header.sh_addralign.set(e, 1);
+        header
+    }
+
+    /// Builds an executable with sections `.debug_info`, `.text`, `.symtab`, `.strtab` and
+    /// `.shstrtab`, in that order. The debug section comes first so that removing it changes the
+    /// indices of all the others.
+    fn build_input() -> Vec<u8> {
+        let e = LittleEndian;
+        let mut data = vec![0; size_of::<FileHeader>()];
+        let text_offset = data.len();
+        data.extend_from_slice(TEXT);
+        let debug_info_offset = data.len();
+        data.extend_from_slice(DEBUG_INFO);
+
+        data.resize(data.len().next_multiple_of(8), 0);
+        let symtab_offset = data.len();
+        data.extend_from_slice(object::bytes_of(&zeroed::<SymtabEntry>()));
+        let mut main: SymtabEntry = zeroed();
+        main.st_name.set(e, 1);
+        main.st_info = (object::elf::STB_GLOBAL << 4) | object::elf::STT_FUNC;
+        main.st_shndx.set(e, 2);
+        main.st_value.set(e, 0x1000);
+        data.extend_from_slice(object::bytes_of(&main));
+        let symtab_size = data.len() - symtab_offset;
+
+        let strtab_offset = data.len();
+        let strtab = b"\0main\0";
+        data.extend_from_slice(strtab);
+
+        let shstrtab_offset = data.len();
+        let shstrtab = b"\0.debug_info\0.text\0.symtab\0.strtab\0.shstrtab\0";
+        data.extend_from_slice(shstrtab);
+
+        let mut text = section_header(
+            13,
+            object::elf::SHT_PROGBITS,
+            object::elf::SHF_ALLOC | object::elf::SHF_EXECINSTR,
+            text_offset,
+            TEXT.len(),
+            0,
+        );
+        text.sh_addr.set(e, 0x1000);
+        let mut symtab = section_header(
+            19,
+            object::elf::SHT_SYMTAB,
+            0,
+            symtab_offset,
+            symtab_size,
+            4,
+        );
+        symtab.sh_info.set(e, 1);
+        symtab.sh_entsize.set(e, size_of::<SymtabEntry>() as u64);
+        symtab.sh_addralign.set(e, 8);
+        let headers = [
+            zeroed(),
+            section_header(
+                1,
+                object::elf::SHT_PROGBITS,
+                0,
+                debug_info_offset,
+                DEBUG_INFO.len(),
+                0,
+            ),
+            text,
+            symtab,
+            section_header(
+                27,
+                object::elf::SHT_STRTAB,
+                0,
+                strtab_offset,
+                strtab.len(),
+                0,
+            ),
+            section_header(
+                35,
+                object::elf::SHT_STRTAB,
+                0,
+                shstrtab_offset,
+                shstrtab.len(),
+                0,
+            ),
+        ];
+        data.resize(data.len().next_multiple_of(8), 0);
+        let section_headers_offset = data.len();
+        for header in &headers {
+            data.extend_from_slice(object::bytes_of(header));
+        }
+
+        let mut header: FileHeader = zeroed();
+        header.e_ident.magic = object::elf::ELFMAG;
+        header.e_ident.class = object::elf::ELFCLASS64;
+        header.e_ident.data = object::elf::ELFDATA2LSB;
+        header.e_ident.version = object::elf::EV_CURRENT;
+        header.e_type.set(e, object::elf::ET_EXEC);
+        header.e_machine.set(e, object::elf::EM_X86_64);
+        header.e_version.set(e, u32::from(object::elf::EV_CURRENT));
+        header.e_ehsize.set(e, size_of::<FileHeader>() as u16);
+        header.e_shoff.set(e, section_headers_offset as u64);
+        header.e_shentsize.set(e, size_of::<SectionHeader>() as u16);
+        header.e_shnum.set(e, headers.len() as u16);
+        header.e_shstrndx.set(e, 5);
+        data[..size_of::<FileHeader>()].copy_from_slice(object::bytes_of(&header));
+        data
+    }
+
+    fn sections(data: &[u8]) -> SectionTable<'_, FileHeader> {
+        FileHeader::parse(data)
+            .unwrap()
+            .sections(LittleEndian, data)
+            .unwrap()
+    }
+
+    fn section_data<'data>(data: &'data [u8], name: &[u8]) -> Option<&'data [u8]> {
+        let (_, section) = sections(data).section_by_name(LittleEndian, name)?;
+        Some(section.data(LittleEndian, data).unwrap())
+    }
+
+    #[test]
+    fn test_split() {
+        let e = LittleEndian;
+        let input = build_input();
+        let split = split(&input, Path::new("/some/dir/app.debug")).unwrap();
+
+        // The main output keeps its code and symbols, but loses its debug info.
+        let main_sections = sections(&split.main);
+        assert!(main_sections.section_by_name(e, b".debug_info").is_none());
+        assert_eq!(section_data(&split.main, b".text"), Some(TEXT));
+        let (text_index, _) = main_sections.section_by_name(e, b".text").unwrap();
+        let symbols = main_sections
+            .symbols(e, &split.main, object::elf::SHT_SYMTAB)
+            .unwrap();
+        let main = symbols.symbol(object::SymbolIndex(1)).unwrap();
+        assert_eq!(symbols.symbol_name(e, main).unwrap(), b"main");
+        assert_eq!(usize::from(main.st_shndx(e)), text_index.0);
+        assert_eq!(
+            symbols.string_section(),
+            main_sections.section_by_name(e, b".strtab").unwrap().0
+        );
+
+        // `.gnu_debuglink` holds the padded file name of the debug file, then its CRC.
+        let debuglink = section_data(&split.main, DEBUGLINK_SECTION_NAME).unwrap();
+        assert_eq!(&debuglink[..12], b"app.debug\0\0\0");
+        let mut crc = flate2::Crc::new();
+        crc.update(&split.debug);
+        assert_eq!(debuglink[12..], crc.sum().to_le_bytes());
+
+        // The debug file has the debug info, but not the code.
+        assert_eq!(section_data(&split.debug, b".debug_info"), Some(DEBUG_INFO));
+        let (_, text) = sections(&split.debug).section_by_name(e, b".text").unwrap();
+        assert_eq!(text.sh_type(e), object::elf::SHT_NOBITS);
+        assert_eq!(text.sh_addr(e), 0x1000);
+    }
+}
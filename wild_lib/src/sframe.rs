@@ -0,0 +1,343 @@
+//! Merging of the SFrame stack trace sections that GNU as emits with `--gsframe`. Each input
+//! `.sframe` section has a header, an array of function descriptor entries (FDEs) and then the
+//! frame row entries (FREs) that those FDEs refer to. We write a single `.sframe` section with one
+//! header, followed by the FDEs of the functions that we kept, sorted by address so that unwinders
+//! can binary search them, and then their FREs. The section is covered by a PT_GNU_SFRAME segment so
+//! that profilers can find it at runtime.
+//!
+//! If our inputs disagree about the version or ABI of their SFrame data, we warn and don't write
+//! `.sframe` at all, as GNU ld does.
+
+use crate::elf::File;
+use crate::error::Diagnostics;
+use crate::error::Result;
+use crate::input_data::FileId;
+use crate::input_data::InputRef;
+use crate::layout::FileLayout;
+use crate::layout::Layout;
+use crate::resolution::SectionSlot;
+use crate::warning;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context as _;
+use object::read::elf::Rela as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use object::SectionIndex;
+use std::ops::Range;
+
+pub(crate) const PT_GNU_SFRAME: u32 = 0x6474_e554;
+
+const MAGIC: u16 = 0xdee2;
+const HEADER_SIZE: usize = 28;
+
+/// The FDEs are sorted by function start address.
+const F_FDE_SORTED: u8 = 0x1;
+
+/// Function start addresses are relative to the FDE field that holds them, rather than to the start
+/// of the section.
+const F_FDE_FUNC_START_PCREL: u8 = 0x4;
+
+/// The parts of an SFrame header that all of our inputs need to agree on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Abi {
+    version: u8,
+    pc_relative: bool,
+    arch: u8,
+    cfa_fixed_fp_offset: u8,
+    cfa_fixed_ra_offset: u8,
+}
+
+/// The `.sframe` section of an input object, reduced to the FDEs of functions that we're keeping.
+pub(crate) struct InputSFrame {
+    file_id: FileId,
+    section_index: SectionIndex,
+    abi: Abi,
+    flags: u8,
+    fdes: Vec<Fde>,
+}
+
+struct Fde {
+    /// The section containing the function and the function's offset within it.
+    target_section: SectionIndex,
+    target_offset: u64,
+    func_size: u32,
+    num_fres: u32,
+    info: u8,
+    rep_size: u8,
+
+    /// Where the FREs of this function are in the input section data.
+    fres: Range<usize>,
+}
+
+/// The content of our output `.sframe` section, other than the function addresses, which we don't
+/// know until we write it.
+pub(crate) struct MergedSFrame {
+    abi: Abi,
+    flags: u8,
+    inputs: Vec<InputSFrame>,
+    num_fdes: usize,
+    num_fres: u32,
+    fre_len: usize,
+}
+
+/// Reads the `.sframe` section of `object`, if it has one, dropping FDEs for functions in sections
+/// that we didn't load.
+pub(crate) fn read_input(
+    file_id: FileId,
+    object: &File,
+    sections: &[SectionSlot],
+) -> Result<Option<InputSFrame>> {
+    let e = LittleEndian;
+    let Some((section_index, section)) = object.section_by_name(".sframe") else {
+        return Ok(None);
+    };
+    let data = object.raw_section_data(section)?;
+    ensure!(data.len() >= HEADER_SIZE, "SFrame header is truncated");
+    ensure!(
+        u16::from_le_bytes([data[0], data[1]]) == MAGIC,
+        "Bad SFrame magic"
+    );
+    let abi = Abi {
+        version: data[2],
+        pc_relative: data[3] & F_FDE_FUNC_START_PCREL != 0,
+        arch: data[4],
+        cfa_fixed_fp_offset: data[5],
+        cfa_fixed_ra_offset: data[6],
+    };
+    let fde_size = fde_size(abi.version)?;
+    // FDE and FRE offsets are relative to the end of the header, which includes an auxiliary part.
+    let header_end = HEADER_SIZE + usize::from(data[7]);
+    let num_fdes = read_u32(data, 8)? as usize;
+    let fde_offset = header_end + read_u32(data, 20)? as usize;
+    let fre_offset = header_end + read_u32(data, 24)? as usize;
+
+    // The function that each FDE describes is identified by a relocation of its start address.
+    let mut targets = vec![None; num_fdes];
+    for rel in object.relocations(section_index)? {
+        let Some(fde_index) = (rel.r_offset(e) as usize)
+            .checked_sub(fde_offset)
+            .filter(|offset| offset % fde_size == 0)
+            .map(|offset| offset / fde_size)
+            .filter(|index| *index < num_fdes)
+        else {
+            continue;
+        };
+        let r_type = rel.r_type(e, false);
+        if r_type != object::elf::R_X86_64_PC32 {
+            bail!(
+                "Unsupported relocation type {} in .sframe",
+                linker_utils::elf::rel_type_to_string(r_type)
+            );
+        }
+        let symbol_index = rel
+            .symbol(e, false)
+            .context("Missing .sframe relocation symbol")?;
+        let symbol = object.symbol(symbol_index)?;
+        let Some(target_section) = object.symbol_section(symbol, symbol_index)? else {
+            continue;
+        };
+        targets[fde_index] = Some((
+            target_section,
+            symbol.st_value(e).wrapping_add(rel.r_addend(e) as u64),
+        ));
+    }
+
+    let mut fdes = Vec::new();
+    for (index, target) in targets.into_iter().enumerate() {
+        let Some((target_section, target_offset)) = target else {
+            continue;
+        };
+        if !matches!(sections.get(target_section.0), Some(SectionSlot::Loaded(_))) {
+            continue;
+        }
+        let fde = fde_offset + index * fde_size;
+        let num_fres = read_u32(data, fde + 12)?;
+        let info = *data.get(fde + 16).context("SFrame FDE is truncated")?;
+        let fres_start = fre_offset + read_u32(data, fde + 8)? as usize;
+        let fres_len = fres_size(data.get(fres_start..).unwrap_or_default(), info, num_fres)?;
+        fdes.push(Fde {
+            target_section,
+            target_offset,
+            func_size: read_u32(data, fde + 4)?,
+            num_fres,
+            info,
+            rep_size: if abi.version >= 2 { data[fde + 17] } else { 0 },
+            fres: fres_start..fres_start + fres_len,
+        });
+    }
+    Ok(Some(InputSFrame {
+        file_id,
+        section_index,
+        abi,
+        flags: data[3],
+        fdes,
+    }))
+}
+
+/// Merges the SFrame data of our inputs. Returns `None` if there's nothing to write or if the
+/// inputs are incompatible with each other.
+pub(crate) fn merge(
+    inputs: Vec<(InputSFrame, &InputRef)>,
+    diagnostics: &Diagnostics,
+) -> Option<MergedSFrame> {
+    let (first, first_input) = inputs.first()?;
+    let abi = first.abi;
+    let mut flags = !0;
+    for (sframe, input) in &inputs {
+        if sframe.abi != abi {
+            warning!(
+                diagnostics,
+                "Not writing .sframe, since the SFrame data in {input} ({:?}) is incompatible with \
+                 that in {first_input} ({abi:?})",
+                sframe.abi
+            );
+            return None;
+        }
+        flags &= sframe.flags;
+    }
+    let inputs: Vec<InputSFrame> = inputs.into_iter().map(|(sframe, _)| sframe).collect();
+    let fdes = || inputs.iter().flat_map(|sframe| &sframe.fdes);
+    let num_fdes = fdes().count();
+    if num_fdes == 0 {
+        return None;
+    }
+    let num_fres = fdes().map(|fde| fde.num_fres).sum();
+    let fre_len = fdes().map(|fde| fde.fres.len()).sum();
+    Some(MergedSFrame {
+        abi,
+        flags: flags | F_FDE_SORTED,
+        inputs,
+        num_fdes,
+        num_fres,
+        fre_len,
+    })
+}
+
+impl MergedSFrame {
+    pub(crate) fn size(&self) -> u64 {
+        (HEADER_SIZE + self.num_fdes * self.fde_size() + self.fre_len) as u64
+    }
+
+    fn fde_size(&self) -> usize {
+        fde_size(self.abi.version).unwrap()
+    }
+
+    /// Writes our `.sframe` section, which is at `section_address`, to `out`.
+    pub(crate) fn write(&self, out: &mut [u8], section_address: u64, layout: &Layout) -> Result {
+        ensure!(
+            out.len() as u64 == self.size(),
+            "Allocated {} bytes for .sframe, but needed {}",
+            out.len(),
+            self.size()
+        );
+        let mut fdes = Vec::with_capacity(self.num_fdes);
+        for sframe in &self.inputs {
+            let FileLayout::Object(object) = layout.file_layout(sframe.file_id) else {
+                bail!("SFrame data from a file that isn't an object");
+            };
+            let data = object
+                .object
+                .raw_section_data(object.object.section(sframe.section_index)?)?;
+            for fde in &sframe.fdes {
+                let address = object.section_resolutions[fde.target_section.0]
+                    .address()
+                    .context("SFrame FDE for a function without an address")?
+                    + fde.target_offset;
+                fdes.push((address, fde, data));
+            }
+        }
+        fdes.sort_by_key(|(address, ..)| *address);
+
+        let fde_size = self.fde_size();
+        let (header, rest) = out.split_at_mut(HEADER_SIZE);
+        header[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        header[2] = self.abi.version;
+        header[3] = self.flags;
+        header[4] = self.abi.arch;
+        header[5] = self.abi.cfa_fixed_fp_offset;
+        header[6] = self.abi.cfa_fixed_ra_offset;
+        header[7] = 0;
+        header[8..12].copy_from_slice(&(self.num_fdes as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&self.num_fres.to_le_bytes());
+        header[16..20].copy_from_slice(&(self.fre_len as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&0_u32.to_le_bytes());
+        header[24..28].copy_from_slice(&((self.num_fdes * fde_size) as u32).to_le_bytes());
+
+        let (fde_out, fre_out) = rest.split_at_mut(self.num_fdes * fde_size);
+        let mut fre_offset = 0;
+        for (index, ((address, fde, data), out)) in fdes
+            .iter()
+            .zip(fde_out.chunks_exact_mut(fde_size))
+            .enumerate()
+        {
+            let base = if self.abi.pc_relative {
+                section_address + (HEADER_SIZE + index * fde_size) as u64
+            } else {
+                section_address
+            };
+            let start = i32::try_from(address.wrapping_sub(base) as i64)
+                .context("Function is more than 2GiB away from .sframe")?;
+            out[0..4].copy_from_slice(&start.to_le_bytes());
+            out[4..8].copy_from_slice(&fde.func_size.to_le_bytes());
+            out[8..12].copy_from_slice(&(fre_offset as u32).to_le_bytes());
+            out[12..16].copy_from_slice(&fde.num_fres.to_le_bytes());
+            out[16] = fde.info;
+            if self.abi.version >= 2 {
+                out[17] = fde.rep_size;
+                out[18..20].fill(0);
+            }
+            fre_out[fre_offset..fre_offset + fde.fres.len()]
+                .copy_from_slice(&data[fde.fres.clone()]);
+            fre_offset += fde.fres.len();
+        }
+        Ok(())
+    }
+}
+
+/// Returns the size of an FDE in the supplied SFrame version.
+fn fde_size(version: u8) -> Result<usize> {
+    match version {
+        1 => Ok(17),
+        2 => Ok(20),
+        _ => bail!("Unsupported SFrame version {version}"),
+    }
+}
+
+/// Returns the size of the `num_fres` FREs at the start of `data`, which belong to an FDE with
+/// `fde_info`.
+fn fres_size(data: &[u8], fde_info: u8, num_fres: u32) -> Result<usize> {
+    let address_size = match fde_info & 0xf {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        other => bail!("Unsupported SFrame FRE type {other}"),
+    };
+    let mut offset = 0;
+    for _ in 0..num_fres {
+        let fre_info = *data
+            .get(offset + address_size)
+            .context("SFrame FRE is truncated")?;
+        let num_offsets = usize::from((fre_info >> 1) & 0xf);
+        let offset_size = 1 << ((fre_info >> 5) & 0x3);
+        offset += address_size + 1 + num_offsets * offset_size;
+    }
+    ensure!(offset <= data.len(), "SFrame FRE is truncated");
+    Ok(offset)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("SFrame data is truncated")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+#[test]
+fn test_fres_size() {
+    // An FDE with 1 byte start addresses. The first FRE has just a CFA offset. The second has CFA
+    // and FP offsets of 2 bytes each.
+    let data = [0, 0b0000_0010, 8, 4, 0b0010_0100, 16, 0, 0xf0, 0xff, 0xaa];
+    assert_eq!(fres_size(&data, 0, 2).unwrap(), 9);
+    assert!(fres_size(&data, 0, 3).is_err());
+}
@@ -0,0 +1,216 @@
+//! Finds the source file and line for a location in an input object by reading the object's DWARF
+//! line tables. This is only used when reporting errors, so we read the debug info of just the
+//! objects that we're reporting about and don't try to make lookups fast.
+//!
+//! Addresses in an object's line table are relative to sections and are filled in by relocations.
+//! We apply those relocations as we read, mapping each address to the section index in the top 32
+//! bits and the offset within that section in the bottom 32 bits.
+
+use crate::args::Args;
+use crate::elf::File;
+use crate::error::DiagnosticLocation;
+use crate::error::Result;
+use gimli::Reader as _;
+use linker_utils::elf::shf;
+use linker_utils::elf::SectionFlags;
+use object::read::elf::Rela as _;
+use object::read::elf::SectionHeader as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use object::SectionIndex;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub(crate) struct SourceLocation {
+    pub(crate) file: String,
+    pub(crate) line: u64,
+}
+
+/// A location within a section of an input object, for use in diagnostics.
+pub(crate) struct ObjectLocation<'data> {
+    /// The input, formatted like `libfoo.a(foo.o)`.
+    pub(crate) input: String,
+    pub(crate) object: &'data File<'data>,
+    pub(crate) section: SectionIndex,
+    pub(crate) offset: u64,
+}
+
+impl ObjectLocation<'_> {
+    /// Returns a description of the location for use in a diagnostic. This includes the source
+    /// location, so reads the object's debug info.
+    pub(crate) fn describe(&self, args: &Args) -> Result<DiagnosticLocation> {
+        let section = self.object.section(self.section)?;
+        let flags = SectionFlags::from_header(section);
+        let file_offset = (section.sh_type(LittleEndian) != object::elf::SHT_NOBITS
+            && !flags.contains(shf::COMPRESSED))
+        .then(|| section.sh_offset(LittleEndian) + self.offset);
+        Ok(DiagnosticLocation {
+            file: self.input.clone(),
+            section: Some(self.object.section_display_name(self.section).into_owned()),
+            section_offset: self.offset,
+            file_offset,
+            function: containing_symbol(self.object, self.section, self.offset)
+                .map(|name| crate::demangle::display_name(name, args)),
+            source: find(self.object, self.section, self.offset),
+        })
+    }
+}
+
+/// Returns the source location of `offset` within the section `section_index` of `object`, or
+/// `None` if the object has no line information for it. Malformed debug info is ignored.
+pub(crate) fn find(
+    object: &File,
+    section_index: SectionIndex,
+    offset: u64,
+) -> Option<SourceLocation> {
+    object.section_by_name(".debug_line")?;
+    find_in_line_tables(object, section_index, offset)
+        .ok()
+        .flatten()
+}
+
+/// How the value at each offset within a debug section is relocated.
+#[derive(Debug, Clone, Default)]
+struct Relocations(Rc<HashMap<usize, Relocation>>);
+
+#[derive(Debug)]
+struct Relocation {
+    /// The index of the section that the relocation's symbol is in, if any.
+    section: Option<usize>,
+    value: u64,
+}
+
+impl gimli::read::Relocate for Relocations {
+    fn relocate_address(&self, offset: usize, value: u64) -> gimli::Result<u64> {
+        Ok(match self.0.get(&offset) {
+            Some(relocation) => match relocation.section {
+                Some(section) => ((section as u64) << 32) | relocation.value,
+                None => relocation.value,
+            },
+            None => value,
+        })
+    }
+
+    fn relocate_offset(&self, offset: usize, value: usize) -> gimli::Result<usize> {
+        Ok(self
+            .0
+            .get(&offset)
+            .map_or(value, |relocation| relocation.value as usize))
+    }
+}
+
+fn find_in_line_tables(
+    object: &File,
+    section_index: SectionIndex,
+    offset: u64,
+) -> Result<Option<SourceLocation>> {
+    let sections = gimli::DwarfSections::load(|id| load_section(object, id.name()))?;
+    let dwarf = sections.borrow(|(data, relocations)| {
+        gimli::RelocateReader::new(
+            gimli::EndianSlice::new(data, gimli::LittleEndian),
+            relocations.clone(),
+        )
+    });
+    let target = ((section_index.0 as u64) << 32) | offset;
+
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(program) = unit.line_program.clone() else {
+            continue;
+        };
+        let mut rows = program.rows();
+        let mut previous: Option<gimli::LineRow> = None;
+        while let Some((_, row)) = rows.next_row()? {
+            if let Some(previous) =
+                previous.filter(|previous| (previous.address()..row.address()).contains(&target))
+            {
+                let header = rows.header();
+                let file = previous
+                    .file(header)
+                    .map(|file| dwarf.attr_string(&unit, file.path_name()))
+                    .transpose()?;
+                return Ok(Some(SourceLocation {
+                    file: match file {
+                        Some(file) => file.to_string_lossy()?.into_owned(),
+                        None => "??".to_owned(),
+                    },
+                    line: previous.line().map_or(0, |line| line.get()),
+                }));
+            }
+            previous = (!row.end_sequence()).then_some(*row);
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the data for the debug section `name` together with its relocations. Missing sections
+/// are treated as empty.
+fn load_section<'data>(
+    object: &File<'data>,
+    name: &str,
+) -> Result<(Cow<'data, [u8]>, Relocations)> {
+    let Some((index, section)) = object.section_by_name(name) else {
+        return Ok((Cow::Borrowed(&[]), Relocations::default()));
+    };
+    let raw = object.raw_section_data(section)?;
+    let size = object.section_size(section)? as usize;
+    let data = if size == raw.len() {
+        Cow::Borrowed(raw)
+    } else {
+        let mut data = vec![0; size];
+        object.copy_section_data(section, &mut data)?;
+        Cow::Owned(data)
+    };
+
+    let mut relocations = HashMap::new();
+    for rela in object.relocations(index)? {
+        let Some(symbol_index) = rela.symbol(LittleEndian, false) else {
+            continue;
+        };
+        let symbol = object.symbol(symbol_index)?;
+        let section = object
+            .symbol_section(symbol, symbol_index)?
+            .map(|section| section.0);
+        relocations.insert(
+            rela.r_offset(LittleEndian) as usize,
+            Relocation {
+                section,
+                value: symbol
+                    .st_value(LittleEndian)
+                    .wrapping_add(rela.r_addend(LittleEndian) as u64),
+            },
+        );
+    }
+    Ok((data, Relocations(Rc::new(relocations))))
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Returns the name of the function or object that contains `offset` within `section`.
+fn containing_symbol<'data>(
+    object: &File<'data>,
+    section: SectionIndex,
+    offset: u64,
+) -> Option<&'data [u8]> {
+    object.symbols.iter().find_map(|symbol| {
+        let value = symbol.st_value(LittleEndian);
+        let is_match = usize::from(symbol.st_shndx(LittleEndian)) == section.0
+            && matches!(
+                symbol.st_type(),
+                object::elf::STT_FUNC | object::elf::STT_OBJECT
+            )
+            && (value..value + symbol.st_size(LittleEndian)).contains(&offset);
+        is_match
+            .then(|| object.symbol_name(symbol).ok())
+            .flatten()
+            .filter(|name| !name.is_empty())
+    })
+}
@@ -0,0 +1,265 @@
+//! Support for `--skip-if-unchanged`. After a successful link, we write a stamp file alongside the
+//! output recording our command line and the identity of every file that we read and wrote. On the
+//! next link, if the stamp still matches and none of the files that we wrote, i.e. the output and
+//! any map or similar file, have been touched since, we leave them in place and exit without doing
+//! any further work.
+//!
+//! By default, we identify inputs by their metadata: device, inode, size and modification and
+//! change times. That's cheap, but it can't see changes made by something that preserves all of
+//! those, and it treats an input that was rewritten with identical contents as changed. With
+//! `--skip-if-unchanged=contents`, we instead identify inputs by their size and a hash of their
+//! contents, at the cost of reading every input in full. Our outputs are always identified by
+//! metadata, since all we need to know is whether something other than us has written them.
+
+use crate::args::Args;
+use crate::args::ChangeDetection;
+use crate::error::Result;
+use crate::input_data::InputData;
+use anyhow::Context;
+use sha1::Digest as _;
+use sha1::Sha1;
+use std::fmt::Write as _;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+const OUTPUT_PREFIX: &str = "output ";
+
+pub(crate) struct Stamp {
+    /// Everything except the lines describing the files that we write, which we can only add once
+    /// they've been written.
+    inputs: String,
+}
+
+impl Stamp {
+    pub(crate) fn compute(args: &Args, input_data: &InputData) -> Result<Self> {
+        let mut inputs = String::new();
+        writeln!(&mut inputs, "wild {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(&mut inputs, "cwd {:?}", std::env::current_dir()?)?;
+        // GCC passes the linker plugin a temporary file with a different name on every run.
+        for arg in args
+            .command_line
+            .iter()
+            .filter(|arg| !arg.contains("-fresolution="))
+        {
+            writeln!(&mut inputs, "arg {arg:?}")?;
+        }
+        // The order in which we link files matters, but linker scripts don't show up in `files`, so
+        // we record both the link order and the full set of files that we opened.
+        for file in &input_data.files {
+            writeln!(&mut inputs, "link {:?}", file.filename)?;
+        }
+        let mut paths: Vec<&Path> = input_data.filenames.iter().map(|p| p.as_path()).collect();
+        paths.extend(args.version_script_path.as_deref());
+        paths.sort();
+        for path in paths {
+            let description = match args.skip_if_unchanged {
+                Some(ChangeDetection::Contents) => hash_file(path)
+                    .with_context(|| format!("Failed to read `{}`", path.display()))?,
+                _ => describe_file(
+                    &std::fs::metadata(path)
+                        .with_context(|| format!("Failed to stat `{}`", path.display()))?,
+                ),
+            };
+            writeln!(&mut inputs, "input {path:?} {description}")?;
+        }
+        Ok(Self { inputs })
+    }
+
+    /// Returns whether the previous link had identical inputs and its outputs are still intact.
+    pub(crate) fn is_up_to_date(&self, args: &Args) -> bool {
+        let Ok(previous) = std::fs::read_to_string(stamp_path(&args.output)) else {
+            return false;
+        };
+        let Some(outputs_start) = previous.find(&format!("\n{OUTPUT_PREFIX}")) else {
+            return false;
+        };
+        let (inputs, outputs) = previous.split_at(outputs_start + 1);
+        inputs == self.inputs && outputs == describe_outputs(args).unwrap_or_default()
+    }
+
+    /// Records the inputs of the link that just completed. Must be called after all of our outputs
+    /// have been fully written.
+    pub(crate) fn write(mut self, args: &Args) -> Result {
+        self.inputs.push_str(&describe_outputs(args)?);
+        let path = stamp_path(&args.output);
+        std::fs::write(&path, self.inputs)
+            .with_context(|| format!("Failed to write `{}`", path.display()))
+    }
+}
+
+/// Describes each of the files that a link with `args` writes, other than the dependency file,
+/// which is cheap enough that we always rewrite it. Fails if any of them doesn't exist.
+fn describe_outputs(args: &Args) -> Result<String> {
+    let outputs = std::iter::once(args.output.as_ref())
+        .chain(args.map_file.as_deref())
+        .chain(args.why_extract.as_deref())
+        .chain(args.separate_debug_file.as_deref())
+        .chain(args.stats.as_ref().and_then(|path| path.as_deref()));
+    let mut out = String::new();
+    for path in outputs {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("Failed to stat `{}`", path.display()))?;
+        writeln!(
+            &mut out,
+            "{OUTPUT_PREFIX}{path:?} {}",
+            describe_file(&metadata)
+        )?;
+    }
+    Ok(out)
+}
+
+/// Returns a description of a file that changes whenever the file's contents do. We include the
+/// change time as well as the modification time, since tools that preserve mtime can't preserve it.
+fn describe_file(metadata: &std::fs::Metadata) -> String {
+    format!(
+        "{} {} {} {}.{} {}.{}",
+        metadata.dev(),
+        metadata.ino(),
+        metadata.size(),
+        metadata.mtime(),
+        metadata.mtime_nsec(),
+        metadata.ctime(),
+        metadata.ctime_nsec()
+    )
+}
+
+/// Returns the size of the file at `path` and a hash of its contents.
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha1::new();
+    let size = std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let mut description = format!("{size} ");
+    for byte in hasher.finalize() {
+        write!(&mut description, "{byte:02x}")?;
+    }
+    Ok(description)
+}
+
+fn stamp_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".wild-stamp");
+    PathBuf::from(path)
+}
+
+#[test]
+fn test_stamp_detects_changes() {
+    let dir = std::env::temp_dir().join(format!("wild-stamp-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("a.o");
+    let output = dir.join("out");
+    std::fs::write(&input, b"a").unwrap();
+    let crate::args::Action::Link(args) = crate::args::parse(
+        [
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+            "--skip-if-unchanged",
+        ]
+        .into_iter(),
+    )
+    .unwrap() else {
+        panic!("Unexpected action");
+    };
+    let compute = || {
+        let input_data = InputData {
+            config: &args,
+            filenames: [input.clone()].into_iter().collect(),
+            files: Vec::new(),
+            version_script_data: None,
+        };
+        Stamp::compute(&args, &input_data).unwrap()
+    };
+
+    assert!(!compute().is_up_to_date(&args));
+    std::fs::write(&output, b"out").unwrap();
+    compute().write(&args).unwrap();
+    assert!(compute().is_up_to_date(&args));
+
+    std::fs::write(&input, b"ab").unwrap();
+    assert!(!compute().is_up_to_date(&args));
+    compute().write(&args).unwrap();
+    assert!(compute().is_up_to_date(&args));
+
+    std::fs::write(&output, b"changed").unwrap();
+    assert!(!compute().is_up_to_date(&args));
+
+    let map = dir.join("out.map");
+    let crate::args::Action::Link(args) = crate::args::parse(
+        [
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+            "--skip-if-unchanged",
+            &format!("-Map={}", map.display()),
+        ]
+        .into_iter(),
+    )
+    .unwrap() else {
+        panic!("Unexpected action");
+    };
+    let input_data = InputData {
+        config: &args,
+        filenames: [input.clone()].into_iter().collect(),
+        files: Vec::new(),
+        version_script_data: None,
+    };
+    std::fs::write(&map, b"map").unwrap();
+    Stamp::compute(&args, &input_data)
+        .unwrap()
+        .write(&args)
+        .unwrap();
+    assert!(Stamp::compute(&args, &input_data)
+        .unwrap()
+        .is_up_to_date(&args));
+    std::fs::remove_file(&map).unwrap();
+    assert!(!Stamp::compute(&args, &input_data)
+        .unwrap()
+        .is_up_to_date(&args));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stamp_hashes_contents() {
+    let dir = std::env::temp_dir().join(format!("wild-stamp-hash-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("a.o");
+    let output = dir.join("out");
+    std::fs::write(&input, b"a").unwrap();
+    std::fs::write(&output, b"out").unwrap();
+    let crate::args::Action::Link(args) = crate::args::parse(
+        [
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+            "--skip-if-unchanged=contents",
+        ]
+        .into_iter(),
+    )
+    .unwrap() else {
+        panic!("Unexpected action");
+    };
+    let compute = || {
+        let input_data = InputData {
+            config: &args,
+            filenames: [input.clone()].into_iter().collect(),
+            files: Vec::new(),
+            version_script_data: None,
+        };
+        Stamp::compute(&args, &input_data).unwrap()
+    };
+
+    compute().write(&args).unwrap();
+    assert!(compute().is_up_to_date(&args));
+
+    // Rewriting an input with the same contents changes its metadata, but not its hash.
+    std::fs::remove_file(&input).unwrap();
+    std::fs::write(&input, b"a").unwrap();
+    assert!(compute().is_up_to_date(&args));
+
+    // A change that keeps the size the same is still detected.
+    std::fs::write(&input, b"b").unwrap();
+    assert!(!compute().is_up_to_date(&args));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
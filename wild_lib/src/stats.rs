@@ -0,0 +1,218 @@
+//! Support for `--stats`. At the end of the link we report how long each phase took, how much we
+//! read and wrote and various counts that are useful when investigating why an output is large or a
+//! link is slow. The report goes to stdout, or with `--stats=FILE`, to a file.
+//!
+//! Unlike `--time`, which reports every span, phase times here are only for the top-level phases,
+//! so the report stays short and doesn't require a special tracing subscriber.
+
+use crate::args::Args;
+use crate::error::Result;
+use crate::input_data::InputData;
+use crate::layout::FileLayout;
+use crate::layout::Layout;
+use crate::output_section_id;
+use crate::output_section_id::OutputSectionId;
+use crate::resolution::SectionSlot;
+use anyhow::Context as _;
+use linker_utils::elf::shf;
+use linker_utils::elf::SectionFlags;
+use std::fmt::Write as _;
+use std::time::Duration;
+use std::time::Instant;
+
+pub(crate) struct Stats {
+    enabled: bool,
+    start: Instant,
+    phase_start: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+/// How much of an input file ended up in the output.
+struct Contribution {
+    input: String,
+    kept: u64,
+    discarded: u64,
+}
+
+impl Stats {
+    pub(crate) fn new(args: &Args) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled: args.stats.is_some(),
+            start: now,
+            phase_start: now,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Records that the phase `name` has finished. It's taken to have started when the previous
+    /// phase finished.
+    pub(crate) fn phase_done(&mut self, name: &'static str) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        self.phases.push((name, now - self.phase_start));
+        self.phase_start = now;
+    }
+
+    /// Writes the report. `output_size` should be `None` if we didn't write an output file.
+    pub(crate) fn report(
+        &self,
+        layout: &Layout,
+        input_data: &InputData,
+        output_size: Option<u64>,
+    ) -> Result {
+        let Some(destination) = layout.args().stats.as_ref() else {
+            return Ok(());
+        };
+        let report = self.format(layout, input_data, output_size)?;
+        match destination {
+            Some(path) => std::fs::write(path, report)
+                .with_context(|| format!("Failed to write stats to `{}`", path.display()))?,
+            None => print!("{report}"),
+        }
+        Ok(())
+    }
+
+    fn format(
+        &self,
+        layout: &Layout,
+        input_data: &InputData,
+        output_size: Option<u64>,
+    ) -> Result<String> {
+        let mut out = String::new();
+
+        writeln!(out, "Phases:")?;
+        for (name, duration) in &self.phases {
+            writeln!(
+                out,
+                "  {name:<32} {:>10.2} ms",
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+        writeln!(
+            out,
+            "  {:<32} {:>10.2} ms",
+            "Total",
+            self.start.elapsed().as_secs_f64() * 1000.0
+        )?;
+
+        let bytes_read = input_data
+            .files
+            .iter()
+            .map(|file| file.data().len() as u64)
+            .sum::<u64>();
+        writeln!(out, "I/O:")?;
+        writeln!(out, "  Input files: {}", input_data.files.len() - 2)?;
+        writeln!(out, "  Bytes read: {bytes_read}")?;
+        if let Some(output_size) = output_size {
+            writeln!(out, "  Bytes written: {output_size}")?;
+        }
+
+        writeln!(out, "Symbols:")?;
+        writeln!(out, "  Total: {}", layout.symbol_db.num_symbols())?;
+        writeln!(
+            out,
+            "  Global names: {}",
+            layout.symbol_db.global_names.len()
+        )?;
+        writeln!(
+            out,
+            "  Dynamic symbols: {}",
+            entries(layout, output_section_id::DYNSYM)
+        )?;
+        writeln!(
+            out,
+            "  Symbol table entries: {}",
+            entries(layout, output_section_id::SYMTAB)
+        )?;
+
+        writeln!(out, "GOT and PLT:")?;
+        for id in [
+            output_section_id::GOT,
+            output_section_id::GOT_PLT,
+            output_section_id::PLT_GOT,
+            output_section_id::PLT,
+        ] {
+            writeln!(
+                out,
+                "  {} entries: {}",
+                layout.output_sections.display_name(id),
+                entries(layout, id)
+            )?;
+        }
+
+        writeln!(out, "String merging:")?;
+        let mut merge_result = Ok(());
+        layout.merged_strings.for_each(|id, section| {
+            if section.len() > 0 && merge_result.is_ok() {
+                let input_bytes = section.totally_added() as u64;
+                merge_result = writeln!(
+                    out,
+                    "  {}: {input_bytes} bytes in, {} bytes out, {} bytes saved",
+                    layout.output_sections.display_name(id),
+                    section.len(),
+                    input_bytes.saturating_sub(section.len())
+                );
+            }
+        });
+        merge_result?;
+
+        let mut contributions = contributions(layout)?;
+        let kept = contributions.iter().map(|c| c.kept).sum::<u64>();
+        let discarded = contributions.iter().map(|c| c.discarded).sum::<u64>();
+        writeln!(out, "Garbage collection:")?;
+        writeln!(out, "  Bytes kept: {kept}")?;
+        writeln!(out, "  Bytes discarded: {discarded}")?;
+
+        writeln!(out, "Contributions by input file (kept / discarded bytes):")?;
+        contributions.sort_by(|a, b| b.kept.cmp(&a.kept).then_with(|| a.input.cmp(&b.input)));
+        for contribution in &contributions {
+            writeln!(
+                out,
+                "  {:>10} {:>10} {}",
+                contribution.kept, contribution.discarded, contribution.input
+            )?;
+        }
+        Ok(out)
+    }
+}
+
+/// Returns the number of entries in the output section `id`.
+fn entries(layout: &Layout, id: OutputSectionId) -> u64 {
+    let element_size = id.element_size();
+    if element_size == 0 {
+        return 0;
+    }
+    layout.section_layouts.get(id).mem_size / element_size
+}
+
+/// Returns how many bytes of allocated sections each input object contributed or had discarded.
+fn contributions(layout: &Layout) -> Result<Vec<Contribution>> {
+    let mut contributions = Vec::new();
+    for file in layout.group_layouts.iter().flat_map(|group| &group.files) {
+        let FileLayout::Object(object) = file else {
+            continue;
+        };
+        let mut contribution = Contribution {
+            input: object.input.to_string(),
+            kept: 0,
+            discarded: 0,
+        };
+        for (slot, section) in object.sections.iter().zip(object.object.sections.iter()) {
+            if !SectionFlags::from_header(section).contains(shf::ALLOC) {
+                continue;
+            }
+            match slot {
+                SectionSlot::Loaded(section) => contribution.kept += section.size,
+                SectionSlot::Unloaded(_) | SectionSlot::Folded(_) => {
+                    contribution.discarded += object.object.section_size(section)?;
+                }
+                _ => {}
+            }
+        }
+        contributions.push(contribution);
+    }
+    Ok(contributions)
+}
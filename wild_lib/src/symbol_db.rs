@@ -2,6 +2,7 @@
 //! information about where each symbol can be obtained.
 
 use crate::args::Args;
+use crate::args::DefsymValue;
 use crate::args::OutputKind;
 use crate::error::Result;
 use crate::grouping::Group;
@@ -69,6 +70,10 @@ pub struct SymbolDb<'data> {
     /// The names of symbols that mark the start / stop of sections. These are indexed by the offset
     /// into the epilogue's symbol IDs.
     start_stop_symbol_names: Vec<SymbolName<'data>>,
+
+    /// The parsed version script, or an empty script if none was supplied. Kept so that symbols we
+    /// create after initial symbol loading, such as start/stop symbols, can be checked against it.
+    version_script: VersionScript<'data>,
 }
 
 /// A global symbol that hasn't been put into our database yet.
@@ -206,7 +211,7 @@ impl<'data> SymbolDb<'data> {
     #[tracing::instrument(skip_all, name = "Build symbol DB")]
     pub fn build(
         groups: &'data [Group],
-        version_script_data: Option<&VersionScriptData>,
+        version_script_data: Option<&'data VersionScriptData>,
         args: &'data Args,
     ) -> Result<Self> {
         let version_script = version_script_data
@@ -265,6 +270,7 @@ impl<'data> SymbolDb<'data> {
             num_symbols_per_group,
             start_stop_symbol_names: Default::default(),
             symbol_value_flags,
+            version_script,
         };
         index.populate_symbol_db(symbol_per_file)?;
         Ok(index)
@@ -329,8 +335,11 @@ impl<'data> SymbolDb<'data> {
         self.symbol_definitions.push(symbol_id);
         self.start_stop_symbol_names.push(*symbol_name);
         self.num_symbols_per_group[self.epilogue_file_id.group()] += 1;
-        self.symbol_value_flags
-            .push(ValueFlags::ADDRESS | ValueFlags::CAN_BYPASS_GOT);
+        let mut value_flags = ValueFlags::ADDRESS | ValueFlags::CAN_BYPASS_GOT;
+        if self.version_script.is_local(&symbol_name) {
+            value_flags |= ValueFlags::DOWNGRADE_TO_LOCAL;
+        }
+        self.symbol_value_flags.push(value_flags);
         symbol_id
     }
 
@@ -352,7 +361,19 @@ impl<'data> SymbolDb<'data> {
         let input_object = self.file(file_id);
         match input_object {
             ParsedInput::Prelude(o) => Ok(o.symbol_name(symbol_id)),
-            ParsedInput::Object(o) => o.symbol_name(symbol_id),
+            ParsedInput::Object(o) => {
+                let name = o.symbol_name(symbol_id)?;
+                if self.args.wrapped_symbols.is_empty() || o.is_dynamic() {
+                    return Ok(name);
+                }
+                // Undefined references that --wrap redirected take the name of the symbol that
+                // they now refer to, so that that's what we import if it's dynamic.
+                let symbol = o.object.symbol(symbol_id.to_input(o.symbol_id_range))?;
+                if !symbol.is_undefined(LittleEndian) {
+                    return Ok(name);
+                }
+                Ok(SymbolName::new(self.args.wrapped_name(name.bytes())))
+            }
             ParsedInput::Epilogue(o) => {
                 Ok(self.start_stop_symbol_names[symbol_id.offset_from(o.start_symbol_id)])
             }
@@ -371,6 +392,19 @@ impl<'data> SymbolDb<'data> {
         flags
     }
 
+    /// Sets the value flags of a symbol that we define ourselves once we know more about its value.
+    pub(crate) fn set_local_symbol_value_flags(&mut self, symbol_id: SymbolId, flags: ValueFlags) {
+        self.symbol_value_flags[symbol_id.as_usize()] = flags;
+    }
+
+    /// Returns the definition of the symbol named `name`, if any input defines it. Used to find the
+    /// symbol that a `--defsym` alias refers to.
+    pub(crate) fn defsym_target(&self, name: &str) -> Option<SymbolId> {
+        self.global_names
+            .get(&SymbolName::prehashed(name.as_bytes()))
+            .map(|&symbol_id| self.definition(symbol_id))
+    }
+
     pub(crate) fn num_symbols(&self) -> usize {
         self.symbol_definitions.len()
     }
@@ -469,7 +503,7 @@ fn load_symbols_from_file<'data>(
                 .load_symbols(s.file_id, &s.object, symbols_out, outputs)?
             }
         }
-        ParsedInput::Prelude(s) => s.load_symbols(symbols_out, outputs)?,
+        ParsedInput::Prelude(s) => s.load_symbols(symbols_out, outputs, version_script)?,
         ParsedInput::Epilogue(_) => {
             // Custom section start/stop symbols are generated after archive handling.
         }
@@ -484,7 +518,10 @@ fn value_flags_from_elf_symbol(sym: &crate::elf::Symbol, args: &Args) -> ValueFl
         || args.output_kind.is_static_executable()
         // Symbols defined in an executable cannot be interposed since the executable is always the
         // first place checked for a symbol by the dynamic loader.
-        || (args.output_kind.is_executable() && !is_undefined);
+        || (args.output_kind.is_executable() && !is_undefined)
+        // -Bsymbolic binds references within a shared object to its own definitions, so calls to
+        // them needn't go via the PLT.
+        || (!is_undefined && is_bound_symbolically(sym, args));
     // When writing a shared object, TLS variables should never bypass the GOT, even if they're
     // local variables.
     if args.output_kind == OutputKind::SharedObject && sym.st_type() == object::elf::STT_TLS {
@@ -511,6 +548,14 @@ fn value_flags_from_elf_symbol(sym: &crate::elf::Symbol, args: &Args) -> ValueFl
     flags
 }
 
+/// Returns whether `-Bsymbolic` or `-Bsymbolic-functions` make `sym` non-interposable.
+fn is_bound_symbolically(sym: &crate::elf::Symbol, args: &Args) -> bool {
+    if args.output_kind != OutputKind::SharedObject {
+        return false;
+    }
+    args.symbolic.applies_to(sym.st_type())
+}
+
 struct SymbolInfoWriter<'out> {
     resolutions: sharded_vec_writer::Shard<'out, SymbolId>,
     value_kinds: sharded_vec_writer::Shard<'out, ValueFlags>,
@@ -733,11 +778,12 @@ impl TryFrom<usize> for SymbolId {
     }
 }
 
-impl Prelude {
+impl<'data> Prelude<'data> {
     fn load_symbols(
         &self,
         symbols_out: &mut SymbolInfoWriter,
-        outputs: &mut SymbolLoadOutputs,
+        outputs: &mut SymbolLoadOutputs<'data>,
+        version_script: &VersionScript,
     ) -> Result {
         outputs
             .pending_symbols
@@ -762,6 +808,23 @@ impl Prelude {
                         .push(PendingSymbol::new(symbol_id, name));
                     ValueFlags::ADDRESS | ValueFlags::CAN_BYPASS_GOT
                 }
+                InternalSymDefInfo::Defsym(index) => {
+                    let defsym = &self.defsyms[*index];
+                    let name = SymbolName::prehashed(defsym.name.as_bytes());
+                    outputs
+                        .pending_symbols
+                        .push(PendingSymbol::from_prehashed(symbol_id, name));
+                    // If the value is another symbol, then these flags get replaced once we know
+                    // what that symbol is.
+                    let mut value_flags = match defsym.value {
+                        DefsymValue::Absolute(_) => ValueFlags::ABSOLUTE,
+                        DefsymValue::Symbol(_) => ValueFlags::ADDRESS,
+                    } | ValueFlags::CAN_BYPASS_GOT;
+                    if version_script.is_local(&name) {
+                        value_flags |= ValueFlags::DOWNGRADE_TO_LOCAL;
+                    }
+                    value_flags
+                }
             };
             symbols_out.set_next(value_flags, symbol_id, PRELUDE_FILE_ID);
         }
@@ -781,6 +844,7 @@ impl InternalSymDefInfo {
             InternalSymDefInfo::Undefined => None,
             InternalSymDefInfo::SectionStart(i) => Some(i),
             InternalSymDefInfo::SectionEnd(i) => Some(i),
+            InternalSymDefInfo::Defsym(_) => None,
         }
     }
 }
@@ -0,0 +1,159 @@
+//! Implements `--emit-symbols-only`. Rather than writing an ELF file, we write a text listing of the
+//! global symbols that would end up in the output, together with their final addresses and whether
+//! they're exported or imported. Resolution and layout still run in full, but we skip writing
+//! section contents and applying relocations, which is most of the cost of a link. This is intended
+//! for tools such as ABI checkers that only care about the export set or final addresses.
+
+use crate::error::Result;
+use crate::layout::Layout;
+use crate::layout::ResolutionFlags;
+use crate::parsing::ParsedInput;
+use crate::symbol_db::SymbolId;
+use anyhow::Context as _;
+use object::read::elf::Sym as _;
+use std::io::Write as _;
+
+/// How a symbol relates to the dynamic symbol table of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Defined by us and visible to other modules at runtime.
+    Exported,
+    /// Defined by us, but not exported.
+    Global,
+    /// Defined by a shared object that we link against.
+    Imported,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Exported => "export",
+            Scope::Global => "global",
+            Scope::Imported => "import",
+        }
+    }
+}
+
+struct Entry<'data> {
+    value: u64,
+    size: u64,
+    binding: u8,
+    symbol_type: u8,
+    scope: Scope,
+    name: &'data [u8],
+}
+
+#[tracing::instrument(skip_all, name = "Write symbols")]
+pub(crate) fn write(layout: &Layout) -> Result {
+    let path = &layout.args().output;
+    write_to(layout, path).with_context(|| format!("Failed to write `{}`", path.display()))
+}
+
+fn write_to(layout: &Layout, path: &std::path::Path) -> Result {
+    let symbol_db = layout.symbol_db;
+    let mut entries = Vec::new();
+    for index in 0..symbol_db.num_symbols() {
+        let symbol_id = SymbolId::from_usize(index);
+        if !symbol_db.is_canonical(symbol_id) {
+            continue;
+        }
+        let Some(resolution) = layout.local_symbol_resolution(symbol_id) else {
+            continue;
+        };
+        let is_exported = resolution
+            .resolution_flags
+            .contains(ResolutionFlags::EXPORT_DYNAMIC);
+        let (size, binding, symbol_type, scope) = match symbol_db
+            .file(symbol_db.file_id_for_symbol(symbol_id))
+        {
+            ParsedInput::Object(object) => {
+                let sym = object
+                    .object
+                    .symbol(symbol_id.to_input(object.symbol_id_range))?;
+                if sym.is_local() || (!object.is_dynamic && sym.is_undefined(object::LittleEndian))
+                {
+                    continue;
+                }
+                let scope = if object.is_dynamic {
+                    Scope::Imported
+                } else if is_exported {
+                    Scope::Exported
+                } else {
+                    Scope::Global
+                };
+                (
+                    sym.st_size(object::LittleEndian),
+                    sym.st_bind(),
+                    sym.st_type(),
+                    scope,
+                )
+            }
+            ParsedInput::Prelude(_) | ParsedInput::Epilogue(_) => {
+                let scope = if is_exported {
+                    Scope::Exported
+                } else {
+                    Scope::Global
+                };
+                (0, object::elf::STB_GLOBAL, object::elf::STT_NOTYPE, scope)
+            }
+        };
+        entries.push(Entry {
+            value: resolution.value_for_symbol_table(),
+            size,
+            binding,
+            symbol_type,
+            scope,
+            name: symbol_db.symbol_name(symbol_id)?.bytes(),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(b.name));
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    if layout.args().output_kind.is_executable() {
+        if let Ok(entry) = layout.entry_symbol_address() {
+            writeln!(out, "entry 0x{entry:x}")?;
+        }
+    }
+    for entry in entries {
+        writeln!(
+            out,
+            "{:016x} {:>8} {:<6} {:<7} {:<6} {}",
+            entry.value,
+            entry.size,
+            binding_name(entry.binding),
+            type_name(entry.symbol_type),
+            entry.scope.name(),
+            String::from_utf8_lossy(entry.name),
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn binding_name(binding: u8) -> &'static str {
+    match binding {
+        object::elf::STB_GLOBAL => "GLOBAL",
+        object::elf::STB_WEAK => "WEAK",
+        object::elf::STB_GNU_UNIQUE => "UNIQUE",
+        _ => "OTHER",
+    }
+}
+
+fn type_name(symbol_type: u8) -> &'static str {
+    match symbol_type {
+        object::elf::STT_NOTYPE => "NOTYPE",
+        object::elf::STT_OBJECT => "OBJECT",
+        object::elf::STT_FUNC => "FUNC",
+        object::elf::STT_TLS => "TLS",
+        object::elf::STT_GNU_IFUNC => "IFUNC",
+        object::elf::STT_COMMON => "COMMON",
+        _ => "OTHER",
+    }
+}
+
+#[test]
+fn test_names() {
+    assert_eq!(binding_name(object::elf::STB_WEAK), "WEAK");
+    assert_eq!(type_name(object::elf::STT_GNU_IFUNC), "IFUNC");
+    assert_eq!(Scope::Imported.name(), "import");
+}
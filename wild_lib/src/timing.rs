@@ -114,7 +114,9 @@ pub(crate) fn init_tracing() {
     use tracing_subscriber::prelude::*;
     let layer = TimingLayer::default();
     let subscriber = tracing_subscriber::Registry::default().with(layer);
-    tracing::subscriber::set_global_default(subscriber).unwrap();
+    // Fails if an earlier link in the same process already set up tracing, in which case we keep
+    // what it set up.
+    let _ = tracing::subscriber::set_global_default(subscriber);
 }
 
 struct Indent {
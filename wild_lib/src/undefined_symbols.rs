@@ -0,0 +1,116 @@
+//! Reporting of symbols that are referenced, but that nothing defines. For each such symbol, we list
+//! where it was referenced from in the same format as lld and mold: the source file and line if the
+//! referencing object has debug info, then the object and the function or section containing the
+//! reference. Only references from sections that survived garbage collection are reported and weak
+//! references are ignored.
+
+use crate::error::Diagnostic;
+use crate::error::Error;
+use crate::error::Result;
+use crate::layout::FileLayout;
+use crate::layout::GroupLayout;
+use crate::relaxation::Relaxation;
+use crate::resolution::SectionSlot;
+use crate::source_location::ObjectLocation;
+use crate::symbol_db::SymbolDb;
+use crate::symbol_db::SymbolId;
+use linker_utils::elf::SectionFlags;
+use object::read::elf::Rela as _;
+use object::read::elf::Sym as _;
+use object::LittleEndian;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+/// The number of references that we list for each undefined symbol.
+const MAX_REFERENCES: usize = 3;
+
+/// Returns an error, or with `--warn-unresolved-symbols` prints a warning, for each of `undefined`
+/// that is referenced from an executable. `undefined` is the list of symbols that resolution
+/// couldn't find a definition for.
+pub(crate) fn check(
+    symbol_db: &SymbolDb,
+    group_layouts: &[GroupLayout],
+    undefined: &[SymbolId],
+) -> Result<Vec<Error>> {
+    if undefined.is_empty() || !symbol_db.args.output_kind.is_executable() {
+        return Ok(Vec::new());
+    }
+    let undefined = undefined.iter().copied().collect::<HashSet<_>>();
+    let mut references: BTreeMap<SymbolId, Vec<ObjectLocation>> = BTreeMap::new();
+    for file in group_layouts.iter().flat_map(|group| &group.files) {
+        let FileLayout::Object(obj) = file else {
+            continue;
+        };
+        for slot in &obj.sections {
+            let SectionSlot::Loaded(section) = slot else {
+                continue;
+            };
+            let header = obj.object.section(section.index)?;
+            let section_data = obj.object.raw_section_data(header)?;
+            let mut skip_next = false;
+            for rela in obj.object.relocations(section.index)? {
+                // As in layout, ignore the call to `__tls_get_addr` that a TLS relaxation removed.
+                if std::mem::take(&mut skip_next) {
+                    continue;
+                }
+                let Some(symbol_index) = rela.symbol(LittleEndian, false) else {
+                    continue;
+                };
+                let symbol_id = symbol_db.definition(obj.symbol_id_range.input_to_id(symbol_index));
+                skip_next = Relaxation::new(
+                    rela.r_type(LittleEndian, false),
+                    section_data,
+                    rela.r_offset(LittleEndian),
+                    symbol_db.local_symbol_value_flags(symbol_id),
+                    symbol_db.args.output_kind,
+                    SectionFlags::from_header(header),
+                    symbol_db.args.relax,
+                )
+                .is_some_and(|relaxation| relaxation.skips_next_relocation());
+                if !undefined.contains(&symbol_id) || obj.object.symbol(symbol_index)?.is_weak() {
+                    continue;
+                }
+                references
+                    .entry(symbol_id)
+                    .or_default()
+                    .push(ObjectLocation {
+                        input: crate::map_file::display_input(&obj.input),
+                        object: obj.object,
+                        section: section.index,
+                        offset: rela.r_offset(LittleEndian),
+                    });
+            }
+        }
+    }
+    let mut errors = Vec::new();
+    for (symbol_id, references) in references {
+        let name = symbol_db.symbol_name(symbol_id)?;
+        let symbol = crate::demangle::display_name(name.bytes(), symbol_db.args);
+        let locations = references
+            .iter()
+            .take(MAX_REFERENCES)
+            .map(|reference| reference.describe(symbol_db.args))
+            .collect::<Result<Vec<_>>>()?;
+        let note = (references.len() > MAX_REFERENCES).then(|| {
+            format!(
+                ">>> referenced {} more times",
+                references.len() - MAX_REFERENCES
+            )
+        });
+        let diagnostic = Diagnostic {
+            kind: "undefined-symbol",
+            summary: "undefined symbol",
+            symbol,
+            locations,
+            location_label: "referenced by",
+            prefer_function: true,
+            note,
+        };
+        if symbol_db.args.warn_unresolved_symbols {
+            symbol_db.args.diagnostics.warn(&diagnostic);
+        } else {
+            errors.push(Error::new(diagnostic));
+        }
+    }
+    Ok(errors)
+}
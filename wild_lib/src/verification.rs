@@ -71,10 +71,13 @@ pub(crate) fn clear_ignored(expected: &mut OutputSectionPartMap<u64>) {
     const IGNORED_OFFSET: u64 = 0x98760000;
 
     const IGNORED: &[PartId] = &[
-        part_id::RELA_PLT,
+        part_id::RELA_PLT_GENERAL,
+        part_id::RELA_PLT_IRELATIVE,
         part_id::EH_FRAME_HDR,
         part_id::RELA_DYN_GENERAL,
         part_id::RELA_DYN_RELATIVE,
+        part_id::RELR_DYN,
+        part_id::RELR_DYN_GOT,
         part_id::GNU_VERSION,
         part_id::GNU_HASH,
         part_id::DYNAMIC,
@@ -0,0 +1,24 @@
+//! Support for `--why-extract=FILE`. For each archive member, or object between `--start-lib` and
+//! `--end-lib`, that we loaded, we write the object that referenced it and the symbol that it
+//! referenced. The format matches LLD: a header line followed by one tab-separated line per loaded
+//! member. Only the first reference that caused each member to be loaded is reported.
+
+use crate::error::Result;
+use crate::layout::Layout;
+use anyhow::Context as _;
+use std::fmt::Write as _;
+use std::path::Path;
+
+#[tracing::instrument(skip_all, name = "Write why-extract")]
+pub(crate) fn write(layout: &Layout, path: &Path) -> Result {
+    let mut out = String::from("reference\textracted\tsymbol\n");
+    for member in crate::map_file::archive_members(layout)? {
+        writeln!(
+            out,
+            "{}\t{}\t{}",
+            member.requester, member.member, member.symbol
+        )?;
+    }
+    std::fs::write(path, out)
+        .with_context(|| format!("Failed to write why-extract file `{}`", path.display()))
+}